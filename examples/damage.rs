@@ -0,0 +1,192 @@
+//! Shows the cost of repainting only the damaged parts of a window.
+//!
+//! A cursor-sized square follows the pointer over an expensive, software-rendered background.
+//! Moving the pointer only invalidates the square's old and new positions, so only those
+//! pixels are rendered again. Press space to toggle invalidating the whole window instead, and
+//! compare the paint times which are printed to the console.
+
+use glazier::kurbo::{Point, Rect, Size};
+use glazier::{
+    Application, IdleToken, KbKey, KeyEvent, PixelFormat, PointerEvent, Region, Scalable, Scale,
+    WinHandler, WindowHandle,
+};
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+/// The size of the square following the pointer, in display points.
+const CURSOR_SIZE: f64 = 24.0;
+/// How many paints to average over before reporting.
+const REPORT_INTERVAL: u32 = 60;
+
+fn main() {
+    let app = Application::new().unwrap();
+    let window = glazier::WindowBuilder::new(app.clone())
+        .title("Damage")
+        .size(Size::new(800.0, 600.0))
+        .handler(Box::new(WindowState::default()))
+        .build()
+        .unwrap();
+    window.show();
+    app.run(None);
+}
+
+#[derive(Default)]
+struct WindowState {
+    handle: WindowHandle,
+    scale: Scale,
+    /// The physical size of `pixels`.
+    width: usize,
+    height: usize,
+    /// The window contents as BGRA, kept between paints so only damage needs rendering.
+    pixels: Vec<u8>,
+    cursor: Option<Point>,
+    full_repaint: bool,
+    paints: u32,
+    painted_pixels: usize,
+    paint_time: Duration,
+}
+
+impl WindowState {
+    fn cursor_rect(&self) -> Option<Rect> {
+        self.cursor
+            .map(|pos| Rect::from_center_size(pos, (CURSOR_SIZE, CURSOR_SIZE)))
+    }
+
+    /// Render the pixels inside `rect`, which is in display points.
+    fn render_rect(&mut self, rect: Rect) {
+        let cursor = self.cursor_rect().map(|r| r.to_px(self.scale));
+        let rect = rect.to_px(self.scale).expand();
+        let x0 = rect.x0.max(0.0) as usize;
+        let y0 = rect.y0.max(0.0) as usize;
+        let x1 = (rect.x1.max(0.0) as usize).min(self.width);
+        let y1 = (rect.y1.max(0.0) as usize).min(self.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let in_cursor = cursor.map_or(false, |c| {
+                    c.contains(Point::new(x as f64 + 0.5, y as f64 + 0.5))
+                });
+                let [b, g, r] = if in_cursor {
+                    [0x20, 0x20, 0xe0]
+                } else {
+                    background(x as f64, y as f64)
+                };
+                let ix = (y * self.width + x) * 4;
+                self.pixels[ix..ix + 4].copy_from_slice(&[b, g, r, 0xff]);
+            }
+        }
+        self.painted_pixels += x1.saturating_sub(x0) * y1.saturating_sub(y0);
+    }
+
+    fn report(&mut self) {
+        self.paints += 1;
+        if self.paints < REPORT_INTERVAL {
+            return;
+        }
+        let total = (self.width * self.height * self.paints as usize).max(1);
+        println!(
+            "{}: {:.2}ms per paint, repainting {:.1}% of the window",
+            if self.full_repaint {
+                "full repaint"
+            } else {
+                "damage only"
+            },
+            self.paint_time.as_secs_f64() * 1000.0 / self.paints as f64,
+            self.painted_pixels as f64 * 100.0 / total as f64,
+        );
+        self.paints = 0;
+        self.painted_pixels = 0;
+        self.paint_time = Duration::ZERO;
+    }
+}
+
+/// A deliberately expensive background, so that the cost of painting is easy to see.
+fn background(x: f64, y: f64) -> [u8; 3] {
+    let mut v = 0.0;
+    for i in 1..=8 {
+        let f = i as f64 * 0.01;
+        v += (x * f).sin() * (y * f).cos() / i as f64;
+    }
+    let v = ((v + 1.0) * 0.5).clamp(0.0, 1.0);
+    [(v * 255.0) as u8, (v * 180.0) as u8, (v * 90.0) as u8]
+}
+
+impl WinHandler for WindowState {
+    fn connect(&mut self, handle: &WindowHandle) {
+        self.handle = handle.clone();
+        self.scale = handle.get_scale().unwrap_or_default();
+    }
+
+    fn scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    fn surface_resized(&mut self, size: Size) {
+        self.width = size.width as usize;
+        self.height = size.height as usize;
+        self.pixels = vec![0; self.width * self.height * 4];
+        self.handle.invalidate();
+    }
+
+    fn prepare_paint(&mut self) {}
+
+    fn paint(&mut self, invalid: &Region) {
+        if self.pixels.is_empty() {
+            return;
+        }
+        let start = Instant::now();
+        for rect in invalid.rects() {
+            self.render_rect(*rect);
+        }
+        self.paint_time += start.elapsed();
+        if let Err(e) = self.handle.present_pixels(
+            &self.pixels,
+            self.width as u32,
+            self.height as u32,
+            PixelFormat::Bgra8,
+        ) {
+            eprintln!("failed to present: {e}");
+        }
+        self.report();
+    }
+
+    fn pointer_move(&mut self, event: &PointerEvent) {
+        let old = self.cursor_rect();
+        self.cursor = Some(event.pos);
+        if self.full_repaint {
+            self.handle.invalidate();
+            return;
+        }
+        let mut damage = Region::EMPTY;
+        damage.add_rect(self.cursor_rect().unwrap());
+        if let Some(old) = old {
+            damage.add_rect(old);
+        }
+        self.handle.invalidate_region(&damage);
+    }
+
+    fn key_down(&mut self, event: KeyEvent) -> bool {
+        if event.key == KbKey::Character(" ".into()) {
+            self.full_repaint = !self.full_repaint;
+            // Don't mix the two modes in one report.
+            self.paints = 0;
+            self.painted_pixels = 0;
+            self.paint_time = Duration::ZERO;
+            return true;
+        }
+        false
+    }
+
+    fn idle(&mut self, _: IdleToken) {}
+
+    fn request_close(&mut self) {
+        self.handle.close();
+    }
+
+    fn destroy(&mut self) {
+        Application::global().quit()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
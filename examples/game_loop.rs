@@ -0,0 +1,211 @@
+//! Drives the application from a loop of its own, as a game would, instead of handing control
+//! to `Application::run`.
+//!
+//! Each turn of the loop handles the pending events with `Application::run_loop_iteration`, steps a
+//! bouncing square's simulation at a fixed rate, and asks for a frame. While the square moves,
+//! the loop only polls for events; press space to pause it, after which the loop waits for the
+//! next event instead of spinning.
+//!
+//! Every frame fills the whole window, and the number presented each second is printed, so
+//! maximizing the window shows how fast `WindowHandle::present_pixels` is.
+
+use glazier::kurbo::{Point, Rect, Size, Vec2};
+use glazier::{
+    Application, KbKey, KeyEvent, LoopMode, LoopStatus, PixelFormat, Region, Scalable, Scale,
+    WinHandler, WindowHandle,
+};
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// How often the simulation is stepped, whatever the frame rate.
+const TICK: Duration = Duration::from_micros(16_667);
+/// The size of the square, in display points.
+const SQUARE_SIZE: f64 = 48.0;
+/// How fast the square moves, in display points a second.
+const SPEED: f64 = 240.0;
+
+fn main() {
+    let app = Application::new().unwrap();
+    let world = Rc::new(RefCell::new(World::default()));
+    let window = glazier::WindowBuilder::new(app.clone())
+        .title("Game loop")
+        .size(Size::new(640.0, 480.0))
+        .handler(Box::new(WindowState {
+            world: world.clone(),
+            ..Default::default()
+        }))
+        .build()
+        .unwrap();
+    window.show();
+
+    let mut next_tick = Instant::now();
+    loop {
+        let paused = world.borrow().paused;
+        // Poll while animating, so the loop keeps up with the simulation, and sleep otherwise.
+        let mode = if paused {
+            LoopMode::Wait
+        } else {
+            LoopMode::WaitTimeout(next_tick.saturating_duration_since(Instant::now()))
+        };
+        if app.run_loop_iteration(mode) == LoopStatus::Exit {
+            break;
+        }
+        if paused {
+            next_tick = Instant::now();
+            continue;
+        }
+        let now = Instant::now();
+        while next_tick <= now {
+            world.borrow_mut().step(TICK);
+            next_tick += TICK;
+        }
+        window.request_anim_frame();
+    }
+}
+
+/// The simulation, which is shared by the loop and the window's handler.
+struct World {
+    bounds: Size,
+    pos: Point,
+    velocity: Vec2,
+    paused: bool,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World {
+            bounds: Size::ZERO,
+            pos: Point::ZERO,
+            velocity: Vec2::new(SPEED, SPEED * 0.6),
+            paused: false,
+        }
+    }
+}
+
+impl World {
+    fn step(&mut self, dt: Duration) {
+        let max = Point::new(
+            (self.bounds.width - SQUARE_SIZE).max(0.0),
+            (self.bounds.height - SQUARE_SIZE).max(0.0),
+        );
+        self.pos += self.velocity * dt.as_secs_f64();
+        if self.pos.x < 0.0 || self.pos.x > max.x {
+            self.velocity.x = -self.velocity.x;
+            self.pos.x = self.pos.x.clamp(0.0, max.x);
+        }
+        if self.pos.y < 0.0 || self.pos.y > max.y {
+            self.velocity.y = -self.velocity.y;
+            self.pos.y = self.pos.y.clamp(0.0, max.y);
+        }
+    }
+
+    fn square(&self) -> Rect {
+        Rect::from_origin_size(self.pos, (SQUARE_SIZE, SQUARE_SIZE))
+    }
+}
+
+#[derive(Default)]
+struct WindowState {
+    handle: WindowHandle,
+    world: Rc<RefCell<World>>,
+    scale: Scale,
+    /// The physical size of `pixels`.
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    /// The frames presented since `counting_since`, for printing the frame rate.
+    frames: u32,
+    counting_since: Option<Instant>,
+}
+
+impl WindowState {
+    fn count_frame(&mut self) {
+        self.frames += 1;
+        let since = *self.counting_since.get_or_insert_with(Instant::now);
+        let elapsed = since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let rate = f64::from(self.frames) / elapsed.as_secs_f64();
+            println!("{}x{}: {rate:.1} frames/s", self.width, self.height);
+            self.frames = 0;
+            self.counting_since = Some(Instant::now());
+        }
+    }
+}
+
+impl WinHandler for WindowState {
+    fn connect(&mut self, handle: &WindowHandle) {
+        self.handle = handle.clone();
+        self.scale = handle.get_scale().unwrap_or_default();
+    }
+
+    fn scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    fn size(&mut self, size: Size) {
+        self.world.borrow_mut().bounds = size;
+    }
+
+    fn surface_resized(&mut self, size: Size) {
+        self.width = size.width as usize;
+        self.height = size.height as usize;
+        self.pixels = vec![0; self.width * self.height * 4];
+        self.handle.invalidate();
+    }
+
+    fn prepare_paint(&mut self) {}
+
+    fn paint(&mut self, _: &Region) {
+        if self.pixels.is_empty() {
+            return;
+        }
+        let square = self.world.borrow().square().to_px(self.scale);
+        for (i, pixel) in self.pixels.chunks_exact_mut(4).enumerate() {
+            let center = Point::new((i % self.width) as f64 + 0.5, (i / self.width) as f64 + 0.5);
+            let color = if square.contains(center) {
+                [0x20, 0x90, 0xf0, 0xff]
+            } else {
+                [0x30, 0x20, 0x20, 0xff]
+            };
+            pixel.copy_from_slice(&color);
+        }
+        if let Err(e) = self.handle.present_pixels(
+            &self.pixels,
+            self.width as u32,
+            self.height as u32,
+            PixelFormat::Bgra8,
+        ) {
+            eprintln!("failed to present: {e}");
+            return;
+        }
+        if !self.world.borrow().paused {
+            self.count_frame();
+        }
+    }
+
+    fn key_down(&mut self, event: KeyEvent) -> bool {
+        if event.key == KbKey::Character(" ".into()) {
+            let mut world = self.world.borrow_mut();
+            world.paused = !world.paused;
+            // The time spent paused isn't part of the frame rate
+            self.frames = 0;
+            self.counting_since = None;
+            return true;
+        }
+        false
+    }
+
+    fn request_close(&mut self) {
+        self.handle.close();
+    }
+
+    fn destroy(&mut self) {
+        Application::global().quit()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,111 @@
+//! Docks a bar to the top edge of the screen, with a wlr-layer-shell surface.
+//!
+//! The bar reserves its height as an exclusive zone, so maximized windows are placed below it.
+//! This needs a Wayland compositor which supports layer shell, such as sway.
+
+#[cfg(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd"))]
+fn main() {
+    use glazier::kurbo::{Insets, Size};
+    use glazier::platform::linux::{
+        Anchor, KeyboardInteractivity, Layer, LayerShellConfig, WindowBuilderExt,
+    };
+    use glazier::Application;
+
+    let app = Application::new().unwrap();
+    let window = glazier::WindowBuilder::new(app.clone())
+        .title("Bar")
+        // The bar stretches across the screen, so only its height is used.
+        .size(Size::new(0.0, BAR_HEIGHT))
+        .handler(Box::new(bar::Bar::default()))
+        .set_layer_shell(LayerShellConfig {
+            layer: Layer::Top,
+            anchor: Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            margin: Insets::ZERO,
+            exclusive_zone: BAR_HEIGHT as i32,
+            keyboard_interactivity: KeyboardInteractivity::None,
+            namespace: "panel".into(),
+        })
+        .build()
+        .unwrap();
+    window.show();
+    app.run(None);
+}
+
+#[cfg(not(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")))]
+fn main() {
+    eprintln!("This example needs layer shell, which is only available on Wayland");
+}
+
+/// The height of the bar, in display points.
+#[allow(dead_code)]
+const BAR_HEIGHT: f64 = 32.0;
+
+#[allow(dead_code)]
+mod bar {
+    use glazier::kurbo::Size;
+    use glazier::{Application, IdleToken, PixelFormat, Region, WinHandler, WindowHandle};
+    use std::any::Any;
+
+    #[derive(Default)]
+    pub struct Bar {
+        handle: WindowHandle,
+        /// The physical size of the bar.
+        width: usize,
+        height: usize,
+    }
+
+    impl WinHandler for Bar {
+        fn connect(&mut self, handle: &WindowHandle) {
+            self.handle = handle.clone();
+        }
+
+        fn size(&mut self, size: Size) {
+            println!("the compositor made the bar {}x{}", size.width, size.height);
+        }
+
+        fn surface_resized(&mut self, size: Size) {
+            self.width = size.width as usize;
+            self.height = size.height as usize;
+            self.handle.invalidate();
+        }
+
+        fn prepare_paint(&mut self) {}
+
+        fn paint(&mut self, _: &Region) {
+            if self.width == 0 || self.height == 0 {
+                return;
+            }
+            // A horizontal gradient, from dark blue to purple.
+            let mut pixels = Vec::with_capacity(self.width * self.height * 4);
+            for _ in 0..self.height {
+                for x in 0..self.width {
+                    let t = x as f64 / self.width as f64;
+                    let r = (0x20 as f64 + t * 0x60 as f64) as u8;
+                    pixels.extend_from_slice(&[r, 0x20, 0x60, 0xff]);
+                }
+            }
+            if let Err(e) = self.handle.present_pixels(
+                &pixels,
+                self.width as u32,
+                self.height as u32,
+                PixelFormat::Rgba8,
+            ) {
+                eprintln!("failed to present: {e}");
+            }
+        }
+
+        fn idle(&mut self, _: IdleToken) {}
+
+        fn request_close(&mut self) {
+            self.handle.close();
+        }
+
+        fn destroy(&mut self) {
+            Application::global().quit()
+        }
+
+        fn as_any(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! A window with a sidebar and an inline titlebar, in the style of modern macOS apps.
+//!
+//! The content covers the whole window, under a transparent titlebar with no title, and the
+//! traffic lights are moved down to sit in the middle of the header. The window can still be
+//! dragged by the header, which calls `handle_titlebar` when it is pressed.
+
+#[cfg(target_os = "macos")]
+fn main() {
+    use glazier::kurbo::{Point, Size};
+    use glazier::platform::mac::WindowExt;
+    use glazier::Application;
+
+    let app = Application::new().unwrap();
+    let window = glazier::WindowBuilder::new(app.clone())
+        .title("Inline titlebar")
+        .size(Size::new(800.0, 500.0))
+        .handler(Box::new(window::WindowState::default()))
+        .build()
+        .unwrap();
+    window.set_titlebar_transparent(true);
+    window.set_fullsize_content_view(true);
+    window.set_title_visibility(false);
+    // The buttons are about 14 points tall, so this centers them in the header.
+    window.set_traffic_light_offset(Some(Point::new(20.0, (HEADER_HEIGHT - 14.0) / 2.0)));
+    window.show();
+    app.run(None);
+}
+
+#[cfg(not(target_os = "macos"))]
+fn main() {
+    eprintln!("This example uses titlebar customizations which are only available on macOS");
+}
+
+/// The height of the header, which the titlebar is drawn over, in display points.
+#[allow(dead_code)]
+const HEADER_HEIGHT: f64 = 52.0;
+/// The width of the sidebar, in display points.
+#[allow(dead_code)]
+const SIDEBAR_WIDTH: f64 = 220.0;
+
+#[allow(dead_code)]
+mod window {
+    use glazier::kurbo::Size;
+    use glazier::{
+        Application, IdleToken, PixelFormat, PointerEvent, Region, Scale, WinHandler, WindowHandle,
+    };
+    use std::any::Any;
+
+    use super::{HEADER_HEIGHT, SIDEBAR_WIDTH};
+
+    #[derive(Default)]
+    pub struct WindowState {
+        handle: WindowHandle,
+        scale: Scale,
+        size: Size,
+    }
+
+    impl WinHandler for WindowState {
+        fn connect(&mut self, handle: &WindowHandle) {
+            self.handle = handle.clone();
+            self.scale = handle.get_scale().unwrap_or_default();
+        }
+
+        fn scale(&mut self, scale: Scale) {
+            self.scale = scale;
+        }
+
+        fn surface_resized(&mut self, size: Size) {
+            self.size = size;
+            self.handle.invalidate();
+        }
+
+        fn prepare_paint(&mut self) {}
+
+        fn paint(&mut self, _: &Region) {
+            let width = self.size.width as usize;
+            let height = self.size.height as usize;
+            if width == 0 || height == 0 {
+                return;
+            }
+            let sidebar = (SIDEBAR_WIDTH * self.scale.x()) as usize;
+            let header = (HEADER_HEIGHT * self.scale.y()) as usize;
+            let mut pixels = Vec::with_capacity(width * height * 4);
+            for y in 0..height {
+                for x in 0..width {
+                    let [r, g, b] = match (x < sidebar, y < header) {
+                        (true, _) => [0xe8, 0xe6, 0xe3],
+                        (false, true) => [0xf6, 0xf5, 0xf4],
+                        (false, false) => [0xff, 0xff, 0xff],
+                    };
+                    pixels.extend_from_slice(&[b, g, r, 0xff]);
+                }
+            }
+            if let Err(e) =
+                self.handle
+                    .present_pixels(&pixels, width as u32, height as u32, PixelFormat::Bgra8)
+            {
+                eprintln!("failed to present: {e}");
+            }
+        }
+
+        fn pointer_down(&mut self, event: &PointerEvent) {
+            // The whole header drags the window, since it has no controls in it.
+            if event.button.is_primary() && event.pos.y < HEADER_HEIGHT {
+                self.handle.handle_titlebar(true);
+            }
+        }
+
+        fn idle(&mut self, _: IdleToken) {}
+
+        fn request_close(&mut self) {
+            self.handle.close();
+        }
+
+        fn destroy(&mut self) {
+            Application::global().quit()
+        }
+
+        fn as_any(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+}
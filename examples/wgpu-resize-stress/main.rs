@@ -0,0 +1,242 @@
+//! Continuously resizes a window while rendering to it with wgpu.
+//!
+//! The swapchain is only ever reconfigured in `WinHandler::surface_resized`, so if glazier's
+//! resize ordering is correct, acquiring a frame never fails. Any `Outdated` or `Lost` error
+//! panics, reporting how many frames were presented before the failure.
+//!
+//! Dragging the window's frame pauses the stress, so that the window can be resized by hand.
+//! Once the drag ends, the example reports how many sizes and paints the handler saw, which
+//! glazier coalesces to at most one of each per refresh of the display.
+
+use glazier::kurbo::Size;
+use glazier::{Application, IdleToken, Region, Scalable, WinHandler, WindowHandle};
+use std::any::Any;
+use std::time::Instant;
+
+const MIN_SIZE: f64 = 200.0;
+const MAX_SIZE: f64 = 800.0;
+/// How far the window grows or shrinks on every frame, in display points.
+const STEP: f64 = 7.0;
+
+struct InnerWindowState {
+    window: WindowHandle,
+    device: wgpu::Device,
+    config: wgpu::SurfaceConfiguration,
+    surface: wgpu::Surface,
+    queue: wgpu::Queue,
+    frames: u64,
+    started: Instant,
+}
+
+impl InnerWindowState {
+    fn create(window: WindowHandle) -> Self {
+        let scale = window.get_scale().unwrap_or_default();
+        let size = window.get_size().to_px(scale);
+        let instance = wgpu::Instance::default();
+        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            compatible_surface: Some(&surface),
+        }))
+        .expect("Failed to find an appropriate adapter");
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits:
+                    wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+            },
+            None,
+        ))
+        .expect("Failed to create device");
+
+        let caps = surface.get_capabilities(&adapter);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: caps.formats[0],
+            width: size.width as u32,
+            height: size.height as u32,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(&device, &config);
+
+        Self {
+            window,
+            device,
+            config,
+            surface,
+            queue,
+            frames: 0,
+            started: Instant::now(),
+        }
+    }
+
+    fn draw(&mut self) {
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(err) => panic!(
+                "Failed to acquire a frame after {} frames ({:.1}s): {err}",
+                self.frames,
+                self.started.elapsed().as_secs_f64()
+            ),
+        };
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            // Cycle the clear color, so stale frames are easy to spot.
+            let t = (self.frames % 120) as f64 / 120.0;
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: t,
+                            g: 1.0 - t,
+                            b: 0.5,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+        }
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        self.frames += 1;
+        if self.frames % 1000 == 0 {
+            println!(
+                "{} frames in {:.1}s without a surface error",
+                self.frames,
+                self.started.elapsed().as_secs_f64()
+            );
+        }
+    }
+}
+
+fn main() {
+    let app = Application::new().unwrap();
+    let window = glazier::WindowBuilder::new(app.clone())
+        .resizable(true)
+        .size(Size::new(MIN_SIZE, MIN_SIZE))
+        .handler(Box::new(WindowState::new()))
+        .build()
+        .unwrap();
+    window.show();
+    app.run(None);
+}
+
+struct WindowState {
+    inner: Option<InnerWindowState>,
+    growing: bool,
+    /// Set while the user is resizing the window.
+    interactive_resize: Option<ResizeStats>,
+}
+
+struct ResizeStats {
+    started: Instant,
+    sizes: u64,
+    paints: u64,
+}
+
+impl WindowState {
+    fn new() -> Self {
+        Self {
+            inner: None,
+            growing: true,
+            interactive_resize: None,
+        }
+    }
+
+    fn resize_step(&mut self) {
+        let window = &self.inner.as_ref().unwrap().window;
+        let size = window.get_size();
+        if size.width >= MAX_SIZE {
+            self.growing = false;
+        } else if size.width <= MIN_SIZE {
+            self.growing = true;
+        }
+        let step = if self.growing { STEP } else { -STEP };
+        let side = (size.width + step).clamp(MIN_SIZE, MAX_SIZE);
+        window.set_size(Size::new(side, side * 0.75));
+    }
+}
+
+impl WinHandler for WindowState {
+    fn connect(&mut self, handle: &WindowHandle) {
+        let inner = InnerWindowState::create(handle.clone());
+        inner.window.invalidate();
+        self.inner = Some(inner);
+    }
+
+    fn prepare_paint(&mut self) {}
+
+    fn paint(&mut self, _: &Region) {
+        self.inner.as_mut().unwrap().draw();
+        if let Some(stats) = &mut self.interactive_resize {
+            stats.paints += 1;
+            return;
+        }
+        self.resize_step();
+        self.inner.as_ref().unwrap().window.invalidate();
+    }
+
+    fn size(&mut self, _: Size) {
+        if let Some(stats) = &mut self.interactive_resize {
+            stats.sizes += 1;
+        }
+    }
+
+    fn interactive_resize_began(&mut self) {
+        self.interactive_resize = Some(ResizeStats {
+            started: Instant::now(),
+            sizes: 0,
+            paints: 0,
+        });
+    }
+
+    fn interactive_resize_ended(&mut self) {
+        if let Some(stats) = self.interactive_resize.take() {
+            let secs = stats.started.elapsed().as_secs_f64();
+            println!(
+                "Resized by hand with {} sizes and {} paints in {secs:.1}s ({:.0} paints a second)",
+                stats.sizes,
+                stats.paints,
+                stats.paints as f64 / secs
+            );
+        }
+        self.inner.as_ref().unwrap().window.invalidate();
+    }
+
+    fn idle(&mut self, _: IdleToken) {}
+
+    fn surface_resized(&mut self, size: Size) {
+        // The surface can be resized while the window is still being built.
+        if let Some(inner) = self.inner.as_mut() {
+            inner.config.width = size.width as u32;
+            inner.config.height = size.height as u32;
+            inner.surface.configure(&inner.device, &inner.config);
+        }
+    }
+
+    fn request_close(&mut self) {
+        self.inner.as_ref().unwrap().window.close();
+    }
+
+    fn destroy(&mut self) {
+        Application::global().quit()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -183,11 +183,10 @@ impl WinHandler for WindowState {
 
     fn idle(&mut self, _: IdleToken) {}
 
-    fn size(&mut self, _: Size) {
+    fn surface_resized(&mut self, size: Size) {
         let inner = self.inner.as_mut().unwrap();
-        let size = surface_size(&inner.window);
-        inner.config.width = size.0;
-        inner.config.height = size.1;
+        inner.config.width = size.width as u32;
+        inner.config.height = size.height as u32;
         inner.surface.configure(&inner.device, &inner.config);
     }
 
@@ -14,14 +14,24 @@
 
 //! The top-level application type.
 
+use std::any::Any;
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use crate::backend::application as backend;
 use crate::clipboard::Clipboard;
+use crate::common_util::{self, Counter};
 use crate::error::Error;
+use crate::notification::{Notification, NotificationHandle, NotificationId};
+#[cfg(feature = "testing")]
+use crate::testing::InjectedEvent;
 use crate::util;
+use crate::window::WindowHandle;
 
 /// A top-level handler that is not associated with any window.
 ///
@@ -40,6 +50,390 @@ pub trait AppHandler {
     /// Called when a menu item is selected.
     #[allow(unused_variables)]
     fn command(&mut self, id: u32) {}
+
+    /// Called when the system's [`PowerState`] changes, such as when it is unplugged, the
+    /// battery level changes or low power mode is toggled.
+    ///
+    /// Changes to the battery level alone are passed on at most once a minute, so a battery
+    /// which is charging or draining doesn't keep waking the application. A change which
+    /// comes sooner is held, and the latest level is passed on once the minute is up. Changes
+    /// to the power source or low power mode are passed on straight away.
+    #[allow(unused_variables)]
+    fn power_state_changed(&mut self, state: PowerState) {}
+
+    /// Called when the user's [`A11yPrefs`] change, such as when reduced motion or high
+    /// contrast is turned on.
+    #[allow(unused_variables)]
+    fn accessibility_preferences_changed(&mut self, prefs: A11yPrefs) {}
+
+    /// Called when the [`SystemFonts`] change, such as when the user changes the text size.
+    ///
+    /// macOS has no setting for the system fonts, so this is never called there.
+    #[allow(unused_variables)]
+    fn system_fonts_changed(&mut self, fonts: SystemFonts) {}
+
+    /// Called when the [text scale factor](Application::text_scale_factor) changes, so that
+    /// text can be laid out again at its new size.
+    ///
+    /// `factor` is the new text scale factor. The system fonts change with it, so
+    /// [`system_fonts_changed`](AppHandler::system_fonts_changed) is called too.
+    #[allow(unused_variables)]
+    fn text_scale_factor_changed(&mut self, factor: f64) {}
+
+    /// Called when the [accent color](Application::accent_color) or any of the
+    /// [system colors](Application::system_color) change, such as when the user picks another
+    /// accent color or switches between light and dark mode.
+    ///
+    /// `color` is the new accent color.
+    #[allow(unused_variables)]
+    fn accent_color_changed(&mut self, color: Option<Color>) {}
+
+    /// Called when the user changes their language or region settings, after which
+    /// [`Application::get_locale`], [`Application::get_locales`] and
+    /// [`Application::ui_direction`] return the new values.
+    ///
+    /// The locale comes from the environment on Linux, so this is never called there.
+    fn locale_changed(&mut self) {}
+
+    /// Called when the system is about to sleep, such as when the lid is closed.
+    ///
+    /// This is the place to pause network activity and save anything which shouldn't be lost.
+    /// On Linux, logind waits for this to return before sleeping, for up to its
+    /// `InhibitDelayMaxSec`, which is five seconds by default. Other systems only wait briefly,
+    /// if at all.
+    ///
+    /// Like the other callbacks, this is called on the main thread, even when no windows are
+    /// open.
+    fn system_will_sleep(&mut self) {}
+
+    /// Called when the system has woken up after [`system_will_sleep`].
+    ///
+    /// Network connections may still be coming back up by then.
+    ///
+    /// [`system_will_sleep`]: AppHandler::system_will_sleep
+    fn system_did_wake(&mut self) {}
+
+    /// Called when the user's session is locked, such as by the screen locker.
+    fn session_locked(&mut self) {}
+
+    /// Called when the user's session is unlocked after [`session_locked`].
+    ///
+    /// [`session_locked`]: AppHandler::session_locked
+    fn session_unlocked(&mut self) {}
+
+    /// Called when the user clicks a notification sent with
+    /// [`Application::send_notification`].
+    ///
+    /// The `action` is the index of the action the user chose, or `None` if they clicked the
+    /// notification itself.
+    #[allow(unused_variables)]
+    fn notification_activated(&mut self, id: NotificationId, action: Option<usize>) {}
+
+    /// Called when a notification couldn't be shown, after
+    /// [`Application::send_notification`] had returned.
+    ///
+    /// On macOS, the user is asked whether the application may send notifications the first
+    /// time it sends one. If they refuse, this gets [`Error::PermissionDenied`].
+    #[allow(unused_variables)]
+    fn notification_failed(&mut self, id: NotificationId, error: Error) {}
+
+    /// Called when the user asks to open `paths` with the application, such as by opening
+    /// them in the Finder, dropping them on the application's Dock icon, or choosing one from
+    /// the [Open Recent] menu.
+    ///
+    /// This is only called on macOS. Elsewhere, files are passed on the command line.
+    ///
+    /// [Open Recent]: crate::platform::mac::MenuExt::add_recent_documents
+    #[allow(unused_variables)]
+    fn open_files(&mut self, paths: Vec<PathBuf>) {}
+
+    /// Called when the platform fails outside of any call the application made, such as when
+    /// the connection to the display server is lost, or the display server reports that a
+    /// request sent earlier failed.
+    ///
+    /// Errors which the run loop can't recover from are passed here just before it exits.
+    #[allow(unused_variables)]
+    fn backend_error(&mut self, error: Error) {}
+
+    /// Called on the main thread after [`AppHandle::wake`].
+    ///
+    /// Any number of wakes made before this is called are coalesced into a single call, so
+    /// this is a good place to poll an async executor whose wakers call [`AppHandle::wake`].
+    fn woken(&mut self) {}
+
+    /// Called when the run loop is about to wait, because there are no events left to handle.
+    ///
+    /// This is a good place for deferred work which doesn't need a timer, such as flushing
+    /// caches or logs. The loop only waits once this returns, so heavy work here delays the
+    /// events which arrive in the meantime, including input. Work which should run while the
+    /// loop waits belongs on another thread, which can call [`AppHandle::wake`] when it is done.
+    ///
+    /// Outside of macOS, this is not called by nested loops, such as [`WindowHandle::run_modal`]
+    /// or a live resize on Windows. It is not called by an
+    /// [`Application::run_loop_iteration`] which polls, nor on web.
+    ///
+    /// [`WindowHandle::run_modal`]: crate::WindowHandle::run_modal
+    fn idle(&mut self) {}
+
+    /// Called when the run loop wakes up after [`idle`](AppHandler::idle), before it handles
+    /// the events which woke it.
+    ///
+    /// On Wayland, this is called after those events have been handled.
+    fn idle_ended(&mut self) {}
+
+    /// Returns how the application would like glazier to trade performance for power use.
+    ///
+    /// This is queried when the application starts running, or when the handler is
+    /// [set](Application::set_handler) for driving the loop, and again after each call to
+    /// [`power_state_changed`](AppHandler::power_state_changed), so returning a different
+    /// preference from there takes effect immediately.
+    fn power_preference(&mut self) -> PowerPreference {
+        PowerPreference::Default
+    }
+
+    /// Called after the [`WinHandler`] of `window` panicked, with the panic's payload, when
+    /// [closing windows on panic](Application::set_close_windows_on_panic) is turned on.
+    ///
+    /// By then the window is being closed, and its handler is only given
+    /// [`destroy`](WinHandler::destroy).
+    ///
+    /// [`WinHandler`]: crate::WinHandler
+    /// [`WinHandler::destroy`]: crate::WinHandler::destroy
+    #[allow(unused_variables)]
+    fn handler_panicked(&mut self, window: &WindowHandle, payload: Box<dyn Any + Send>) {}
+}
+
+/// How glazier should trade performance for power use.
+///
+/// While throttled, animation frames are capped to 30 frames per second. Frames caused by the
+/// system, such as after a resize, are never delayed.
+///
+/// See [`AppHandler::power_preference`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerPreference {
+    /// Throttle while the system is in [low power mode](PowerState::low_power_mode).
+    #[default]
+    Default,
+    /// Always throttle, even while the system isn't in low power mode.
+    LowPower,
+    /// Never throttle, even while the system is in low power mode.
+    HighPerformance,
+}
+
+impl PowerPreference {
+    /// Whether animation frames should be throttled.
+    pub(crate) fn throttles(self, low_power_mode: bool) -> bool {
+        match self {
+            PowerPreference::Default => low_power_mode,
+            PowerPreference::LowPower => true,
+            PowerPreference::HighPerformance => false,
+        }
+    }
+}
+
+/// The power source and battery charge of the system.
+///
+/// See [`Application::power_state`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PowerState {
+    /// Whether the system is running on battery power.
+    pub on_battery: bool,
+    /// The remaining battery charge, from `0.0` to `1.0`.
+    ///
+    /// This is `None` if the system has no battery, or its level can't be determined.
+    pub battery_level: Option<f64>,
+    /// Whether the user has asked the system to save power, such as with Low Power Mode on
+    /// macOS, battery saver on Windows, or the power saver profile on Linux.
+    pub low_power_mode: bool,
+}
+
+/// The user's accessibility preferences, which applications should respect when drawing.
+///
+/// See [`Application::accessibility_preferences`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct A11yPrefs {
+    /// Whether the user has asked for animations to be reduced or removed.
+    pub reduce_motion: bool,
+    /// Whether the user has asked for a high contrast appearance.
+    pub high_contrast: bool,
+    /// Whether the user has asked for translucent and blurred backgrounds to be made opaque.
+    ///
+    /// Linux desktops have no such setting, so this is always `false` there.
+    pub prefer_reduced_transparency: bool,
+}
+
+/// A font family and size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontDescription {
+    /// The name of the font family, such as `Segoe UI`.
+    pub family: String,
+    /// The font size, in [display points](crate::Scale).
+    pub size: f64,
+}
+
+/// The fonts the platform uses for its own user interface, for text which should match it.
+///
+/// The sizes already include any text scaling the user has asked for.
+///
+/// See [`Application::system_fonts`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemFonts {
+    /// The font for most text, such as labels and buttons.
+    pub ui: FontDescription,
+    /// A fixed width font, for code and terminals.
+    pub monospace: FontDescription,
+    /// The font for menu items.
+    pub menu: FontDescription,
+    /// The font for window titles.
+    pub title: FontDescription,
+    /// The font for small text, such as tooltips and status bars.
+    pub small: FontDescription,
+}
+
+/// An 8-bit sRGB color, with alpha.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// The opacity, where `255` is opaque.
+    pub a: u8,
+}
+
+impl Color {
+    /// An opaque color.
+    pub const fn rgb8(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    /// A color with the given opacity, where `255` is opaque.
+    pub const fn rgba8(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    /// Converts components from `0.0` to `1.0`, as most platforms report them.
+    pub(crate) fn from_f64(r: f64, g: f64, b: f64, a: f64) -> Color {
+        let channel = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color::rgba8(channel(r), channel(g), channel(b), channel(a))
+    }
+}
+
+/// A color the platform uses for its own user interface, for [`Application::system_color`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemColor {
+    /// The background of selected text and items.
+    Highlight,
+    /// The background of windows.
+    Window,
+    /// Text drawn on the window background.
+    Text,
+}
+
+/// How the platform rasterizes text, so text drawn by the application can look like its own.
+///
+/// See [`Application::text_rendering_prefs`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextRenderingPrefs {
+    /// How the edges of glyphs are smoothed.
+    pub antialiasing: TextAntialiasing,
+    /// Whether glyph outlines are fitted to the pixel grid.
+    pub hinting: bool,
+    /// The gamma glyph coverage is blended with, where `1.0` blends linearly.
+    pub gamma: f64,
+}
+
+/// How the edges of glyphs are smoothed, for [`TextRenderingPrefs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextAntialiasing {
+    /// Glyphs have hard edges.
+    None,
+    /// Each pixel is partly covered.
+    Grayscale,
+    /// Each of the red, green and blue parts of a pixel is covered separately, in the given
+    /// order across the screen.
+    Subpixel(SubpixelOrder),
+}
+
+/// The layout of the red, green and blue parts of each pixel on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubpixelOrder {
+    /// Red, green and blue from left to right, which most screens use.
+    Rgb,
+    /// Blue, green and red from left to right.
+    Bgr,
+    /// Red, green and blue from top to bottom.
+    VerticalRgb,
+    /// Blue, green and red from top to bottom.
+    VerticalBgr,
+}
+
+/// The direction the user interface is laid out in, from [`Application::ui_direction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UiDirection {
+    /// Left to right, as for English.
+    #[default]
+    Ltr,
+    /// Right to left, as for Arabic and Hebrew, where layouts are mirrored.
+    Rtl,
+}
+
+/// The sizes and timings the platform uses for its standard controls.
+///
+/// See [`Application::ui_metrics`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiMetrics {
+    /// The width of a vertical scrollbar, in [display points](crate::Scale).
+    pub scrollbar_width: f64,
+    /// The width of the text caret, in [display points](crate::Scale).
+    pub caret_width: f64,
+    /// How long the text caret stays on, and then off, while blinking.
+    ///
+    /// This is `None` if the user has turned off caret blinking.
+    pub caret_blink: Option<Duration>,
+    /// How far the pointer has to move with a button held, in [display points](crate::Scale),
+    /// before it starts a drag.
+    pub drag_threshold: f64,
+    /// How long the pointer has to rest on a menu item before its submenu opens.
+    pub menu_show_delay: Duration,
+}
+
+/// How long [`Application::run_loop_iteration`] waits for events to arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Wait for as long as it takes.
+    Wait,
+    /// Wait for no longer than the duration.
+    WaitTimeout(Duration),
+    /// Don't wait, only handle the events which are already queued.
+    Poll,
+}
+
+/// Whether the application is still running, returned by
+/// [`Application::run_loop_iteration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopStatus {
+    /// The application is still running, so the loop should be run again.
+    Continue,
+    /// The application has quit, and the loop shouldn't be run again.
+    Exit,
+}
+
+/// A windowing system which an [`Application`] can run on, for
+/// [`Application::new_with_backend`].
+///
+/// Only Linux and the BSDs have more than one backend, and each is only available when its
+/// feature is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Wayland, with the `wayland` feature.
+    Wayland,
+    /// X11, with the `x11` feature.
+    X11,
+    /// Running in memory without a display server, with the `headless` feature.
+    ///
+    /// See [`platform::headless`](crate::platform::headless).
+    Headless,
 }
 
 /// The top level application object.
@@ -55,11 +449,22 @@ pub struct Application {
 /// Platform-independent `Application` state.
 struct State {
     running: bool,
+    /// The platform's wake locks, by the id of their token.
+    ///
+    /// These are owned here rather than by the tokens, so that they can all be released
+    /// when the application quits.
+    wake_locks: HashMap<u64, backend::SleepGuard>,
 }
 
 /// Used to ensure only one Application instance is ever created.
 static APPLICATION_CREATED: AtomicBool = AtomicBool::new(false);
 
+/// Whether a call to [`AppHandler::woken`] has been queued, used to coalesce wakes.
+static WAKE_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// See [`Application::set_close_windows_on_panic`].
+pub(crate) static CLOSE_WINDOWS_ON_PANIC: AtomicBool = AtomicBool::new(false);
+
 thread_local! {
     /// A reference object to the current `Application`, if any.
     static GLOBAL_APP: RefCell<Option<Application>> = RefCell::new(None);
@@ -76,12 +481,53 @@ impl Application {
     ///
     /// [druid#771]: https://github.com/linebender/druid/issues/771
     pub fn new() -> Result<Application, Error> {
+        Application::new_inner(None)
+    }
+
+    /// Create a new `Application`, which runs on `backend`.
+    ///
+    /// [`Application::new`] picks the backend itself. On Linux and the BSDs, it uses the one
+    /// named by the `GLAZIER_BACKEND` environment variable, which is `wayland`, `x11` or
+    /// `headless`. Otherwise it prefers Wayland when `WAYLAND_DISPLAY` is set, and falls back
+    /// to X11. This overrides both.
+    ///
+    /// # Errors
+    ///
+    /// Errors if an `Application` has already been created, or if `backend` isn't available
+    /// on this platform or couldn't connect to its display server.
+    pub fn new_with_backend(backend: Backend) -> Result<Application, Error> {
+        Application::new_inner(Some(backend))
+    }
+
+    fn new_inner(backend: Option<Backend>) -> Result<Application, Error> {
+        #[cfg(not(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")))]
+        if backend.is_some() {
+            return Err(Error::Unsupported("choosing a backend"));
+        }
         APPLICATION_CREATED
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
             .map_err(|_| Error::ApplicationAlreadyExists)?;
         util::claim_main_thread();
-        let backend_app = backend::Application::new()?;
-        let state = Rc::new(RefCell::new(State { running: false }));
+        #[cfg(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd"))]
+        let backend_app = match backend {
+            Some(backend) => backend::Application::new_with_backend(backend),
+            None => backend::Application::new(),
+        };
+        #[cfg(not(any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")))]
+        let backend_app = backend::Application::new();
+        let backend_app = match backend_app {
+            Ok(backend_app) => backend_app,
+            Err(err) => {
+                // Nothing was created, so allow another attempt, such as with another backend
+                util::release_main_thread();
+                APPLICATION_CREATED.store(false, Ordering::Release);
+                return Err(err.into());
+            }
+        };
+        let state = Rc::new(RefCell::new(State {
+            running: false,
+            wake_locks: HashMap::new(),
+        }));
         let app = Application { backend_app, state };
         GLOBAL_APP.with(|global_app| {
             *global_app.borrow_mut() = Some(app.clone());
@@ -89,6 +535,28 @@ impl Application {
         Ok(app)
     }
 
+    /// Create a new `Application`, identified to the desktop by `app_id`.
+    ///
+    /// The `app_id` should match the name of the application's `.desktop` file, without the
+    /// extension, such as `org.example.Editor`. Desktop environments use it to group the
+    /// application's windows in the taskbar or dock, and to find their icon. On X11, it
+    /// becomes the `WM_CLASS` of every window, and on Wayland, their `app_id`. On Windows and
+    /// X11, the frames saved by [`WindowBuilder::with_frame_autosave_name`] are kept under it.
+    ///
+    /// This is ignored on other platforms.
+    ///
+    /// [`WindowBuilder::with_frame_autosave_name`]: crate::WindowBuilder::with_frame_autosave_name
+    ///
+    /// # Errors
+    ///
+    /// Errors if an `Application` has already been created.
+    pub fn new_with_name(app_id: &str) -> Result<Application, Error> {
+        let app = Application::new()?;
+        app.backend_app.set_app_id(app_id);
+        crate::autosave::set_app_id(app_id);
+        Ok(app)
+    }
+
     /// Get the current globally active `Application`.
     ///
     /// A globally active `Application` exists after [`Application::new()`]
@@ -142,12 +610,16 @@ impl Application {
         }
 
         // Run the platform application
+        let handler = self.guard_handler(handler);
         self.backend_app.run(handler);
+        self.release_wake_locks();
 
         // This application is no longer active, so clear the global reference
         GLOBAL_APP.with(|global_app| {
             *global_app.borrow_mut() = None;
         });
+        // .. and forget any wake which won't be delivered now
+        WAKE_PENDING.store(false, Ordering::Release);
         // .. and release the main thread
         util::release_main_thread();
         // .. and mark as done so a new sequence can start
@@ -156,13 +628,111 @@ impl Application {
             .expect("Application marked as not created while still running.");
     }
 
+    /// Run one iteration of the event loop, instead of running it until the application quits.
+    ///
+    /// This waits for events to arrive as `mode` says, then handles them along with any which
+    /// are already queued, the timers which are due, and the callbacks passed to
+    /// [`AppHandle::run_on_main`], and then returns. This lets the application be driven from
+    /// an external loop, such as another toolkit's or a test harness's. [`run`](Application::run)
+    /// is this in a loop with [`LoopMode::Wait`].
+    ///
+    /// An application which owns its loop, like a game, can poll with [`LoopMode::Poll`] while
+    /// it's animating, then step its simulation and request a frame, and wait with
+    /// [`LoopMode::Wait`] once it has nothing left to do. The `game_loop` example does this.
+    ///
+    /// Events are passed to the [`AppHandler`] given to
+    /// [`set_handler`](Application::set_handler), just as they are to the one given to
+    /// [`run`](Application::run). Without one, callbacks passed to [`AppHandle::run_on_main`]
+    /// are given `None`.
+    ///
+    /// Returns [`LoopStatus::Exit`] once [`quit`](Application::quit) has been called, or the
+    /// last window has closed on platforms which quit then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Application` is running.
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        if self.state.borrow().running {
+            panic!("Can't run a loop iteration while the Application is running");
+        }
+        self.backend_app.run_loop_iteration(mode)
+    }
+
+    /// Handle pending events and return, waiting up to `timeout` for them to arrive.
+    ///
+    /// This is [`run_loop_iteration`](Application::run_loop_iteration), where a timeout of
+    /// zero is [`LoopMode::Poll`] and `None` is [`LoopMode::Wait`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Application` is running.
+    pub fn pump_events(&self, timeout: Option<Duration>) -> LoopStatus {
+        self.run_loop_iteration(match timeout {
+            None => LoopMode::Wait,
+            Some(timeout) if timeout.is_zero() => LoopMode::Poll,
+            Some(timeout) => LoopMode::WaitTimeout(timeout),
+        })
+    }
+
+    /// Set the [`AppHandler`] which is told about events while
+    /// [running loop iterations](Application::run_loop_iteration), replacing any earlier one.
+    ///
+    /// [`run`](Application::run) sets its own handler in the same way, so this is only needed
+    /// by applications which drive the loop themselves. Setting `None` removes the handler,
+    /// which is also dropped once an iteration returns [`LoopStatus::Exit`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Application` is running, or if this is called from a callback which
+    /// was given the handler.
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        if self.state.borrow().running {
+            panic!("Can't set the handler while the Application is running");
+        }
+        let handler = self.guard_handler(handler);
+        self.backend_app.set_handler(handler);
+    }
+
+    /// Prepare `handler` to be given to the backend, which keeps its panics from unwinding
+    /// into the platform.
+    fn guard_handler(
+        &self,
+        mut handler: Option<Box<dyn AppHandler>>,
+    ) -> Option<Box<dyn AppHandler>> {
+        // Decide whether to throttle before any windows start animating
+        let preference = handler
+            .as_mut()
+            .map_or(PowerPreference::Default, |h| h.power_preference());
+        // Querying the power state can be slow on some platforms, so avoid it if we can
+        let low_power_mode =
+            preference == PowerPreference::Default && self.power_state().low_power_mode;
+        common_util::set_frame_throttling(preference.throttles(low_power_mode));
+
+        handler.map(|inner| Box::new(GuardedAppHandler(inner)) as Box<dyn AppHandler>)
+    }
+
     /// Quit the `Application`.
     ///
     /// This will cause [`Application::run`] to return control back to the calling function.
+    /// Any wake locks which are still held are released.
     pub fn quit(&self) {
+        self.release_wake_locks();
         self.backend_app.quit()
     }
 
+    /// Set whether a window whose [`WinHandler`] panics is closed, instead of the process
+    /// being aborted.
+    ///
+    /// Panics in handlers are never allowed to unwind into the platform, which calls them
+    /// from code that can't be unwound through. By default, the panic is logged and the
+    /// process aborts. When this is turned on, the window is closed instead, and the panic is
+    /// passed to [`AppHandler::handler_panicked`]. Panics in the [`AppHandler`] always abort.
+    ///
+    /// [`WinHandler`]: crate::WinHandler
+    pub fn set_close_windows_on_panic(&self, close: bool) {
+        CLOSE_WINDOWS_ON_PANIC.store(close, Ordering::Relaxed);
+    }
+
     /// Returns a handle to the system clipboard.
     pub fn clipboard(&self) -> Clipboard {
         self.backend_app.clipboard().into()
@@ -170,17 +740,289 @@ impl Application {
 
     /// Returns the current locale string.
     ///
-    /// This should be a [Unicode language identifier].
+    /// This is a [Unicode language identifier] in its BCP 47 form, such as `en-US`, on every
+    /// platform.
     ///
     /// [Unicode language identifier]: https://unicode.org/reports/tr35/#Unicode_language_identifier
     pub fn get_locale() -> String {
         backend::Application::get_locale()
     }
 
+    /// Returns the user's preferred languages, most preferred first, for applications which
+    /// fall back through them when a translation is missing.
+    ///
+    /// These are in the same form as [`get_locale`](Application::get_locale), and there is
+    /// always at least one. This is `LANGUAGE` followed by the other locale variables on
+    /// Linux, where they can't change while the application runs. Elsewhere,
+    /// [`AppHandler::locale_changed`] is called when they change.
+    pub fn get_locales() -> Vec<String> {
+        backend::Application::get_locales()
+    }
+
+    /// Returns the direction the user interface should be laid out in, for the language
+    /// the system's own interface is shown in.
+    ///
+    /// Applications should mirror their layouts when this is [`UiDirection::Rtl`]. It can
+    /// change along with the locale, which [`AppHandler::locale_changed`] reports. On Linux
+    /// and web, this is guessed from the [preferred language](Application::get_locales).
+    ///
+    /// Menus follow it on Windows and macOS. The fallback decorations on Wayland are not
+    /// mirrored yet.
+    pub fn ui_direction() -> UiDirection {
+        backend::Application::ui_direction()
+    }
+
     /// Get a handle that can be used to schedule tasks on the application loop.
     pub fn get_handle(&self) -> Option<AppHandle> {
         self.backend_app.get_handle().map(AppHandle)
     }
+
+    /// Returns the current power source and battery charge of the system.
+    ///
+    /// This is best effort. If the power state can't be determined, such as on a desktop
+    /// without a battery, this reports that the system is not on battery, with no level.
+    ///
+    /// [`AppHandler::power_state_changed`] is called when this changes.
+    pub fn power_state(&self) -> PowerState {
+        self.backend_app.power_state()
+    }
+
+    /// Returns the user's accessibility preferences.
+    ///
+    /// These are cached, and only read again when the system reports that they have changed,
+    /// so this is cheap enough to call every frame.
+    /// [`AppHandler::accessibility_preferences_changed`] is called when they change.
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        self.backend_app.accessibility_preferences()
+    }
+
+    /// Returns the fonts the platform uses for its own user interface.
+    ///
+    /// Text which should match the platform's own controls, such as labels and menus, should
+    /// use these. [`AppHandler::system_fonts_changed`] is called when they change.
+    pub fn system_fonts(&self) -> SystemFonts {
+        self.backend_app.system_fonts()
+    }
+
+    /// Returns how much the user has asked for text to be enlarged, such as with "Make text
+    /// bigger" on Windows or GNOME's large text setting, where `1.0` is the normal size.
+    ///
+    /// This is independent of the window's [`Scale`](crate::Scale): it should only be applied
+    /// to the sizes of fonts, leaving the rest of the layout as it is. The
+    /// [system fonts](Application::system_fonts) already include it. macOS has no such
+    /// setting, so it is always `1.0` there, as it is on web.
+    /// [`AppHandler::text_scale_factor_changed`] is called when it changes.
+    pub fn text_scale_factor(&self) -> f64 {
+        self.backend_app.text_scale_factor()
+    }
+
+    /// Returns the color the user picked for highlighting controls, such as the default button
+    /// or a focused text field.
+    ///
+    /// This follows the light or dark mode the system is in. It is `None` where the platform
+    /// has no accent color, or the desktop doesn't report it, such as on web.
+    /// [`AppHandler::accent_color_changed`] is called when it changes.
+    pub fn accent_color(&self) -> Option<Color> {
+        self.backend_app.accent_color()
+    }
+
+    /// Returns a color the platform uses for its own user interface, for custom drawn
+    /// controls which should match it.
+    ///
+    /// Like the [accent color](Application::accent_color), this follows light or dark mode,
+    /// and [`AppHandler::accent_color_changed`] is called when it changes. Linux has no
+    /// standard for these, so they are the colors of GNOME's own theme there. This is `None`
+    /// on web.
+    pub fn system_color(&self, color: SystemColor) -> Option<Color> {
+        self.backend_app.system_color(color)
+    }
+
+    /// Returns how the platform rasterizes text.
+    ///
+    /// Text renderers should rasterize glyphs the same way, so the application's text looks
+    /// like the rest of the system's. This follows changes to the settings, but there is no
+    /// callback for them, since they rarely change while an application runs.
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        self.backend_app.text_rendering_prefs()
+    }
+
+    /// Returns the sizes and timings the platform uses for its standard controls.
+    ///
+    /// Where the platform has no setting for a metric, this reports its usual default.
+    pub fn ui_metrics(&self) -> UiMetrics {
+        self.backend_app.ui_metrics()
+    }
+
+    /// Returns how far the pointer has to move with a button held, in
+    /// [display points](crate::Scale), before it starts a drag.
+    ///
+    /// This is the [`drag_threshold`](UiMetrics::drag_threshold) of [`ui_metrics`]. Moves which
+    /// stay within it should be treated as part of a click, so a shaky hand doesn't start a
+    /// drag. See [`AutoScroll`](crate::AutoScroll) for scrolling while the drag goes on.
+    ///
+    /// [`ui_metrics`]: Application::ui_metrics
+    pub fn drag_threshold(&self) -> f64 {
+        self.ui_metrics().drag_threshold
+    }
+
+    /// Deliver a synthetic input event to the [`WinHandler`] of `target`.
+    ///
+    /// This bypasses the native layer completely, so nothing else sees the event: the system
+    /// doesn't move the cursor, focus the window or track the pressed keys and buttons. It is
+    /// only intended for testing how an application handles input, without the flakiness of
+    /// simulating input at the OS level.
+    ///
+    /// The event is queued like an [idle callback](crate::IdleHandle::add_idle), and delivered
+    /// in order with the other injected events the next time the event loop runs, such as
+    /// during [`run_loop_iteration`](Application::run_loop_iteration). This means that events can also be
+    /// injected from inside the handler.
+    ///
+    /// A key press which the handler doesn't handle is typed into the window's focused text
+    /// field, as it would be without an input method.
+    ///
+    /// This requires the `testing` feature.
+    ///
+    /// [`WinHandler`]: crate::WinHandler
+    #[cfg(feature = "testing")]
+    pub fn inject_event(&self, target: &WindowHandle, event: InjectedEvent) -> Result<(), Error> {
+        event.inject(target)
+    }
+
+    /// Prevent the display from dimming or sleeping because the user is idle.
+    ///
+    /// This also keeps the system awake. Sleep is inhibited until the returned [`SleepGuard`]
+    /// is dropped. This is best effort; if the platform refuses the request, a warning is
+    /// logged and the guard does nothing. Use [`Application::acquire_wake_lock`] to give a
+    /// reason, or to handle the failure.
+    pub fn inhibit_display_sleep(&self) -> SleepGuard {
+        self.inhibit_sleep(WakeLockKind::Display)
+    }
+
+    /// Prevent the system from sleeping because the user is idle, while still allowing the
+    /// display to turn off.
+    ///
+    /// Sleep is inhibited until the returned [`SleepGuard`] is dropped. This is best effort;
+    /// if the platform refuses the request, a warning is logged and the guard does nothing.
+    pub fn inhibit_system_sleep(&self) -> SleepGuard {
+        self.inhibit_sleep(WakeLockKind::System)
+    }
+
+    fn inhibit_sleep(&self, kind: WakeLockKind) -> SleepGuard {
+        match self.acquire_wake_lock(kind, "Requested by the application") {
+            Ok(token) => SleepGuard(Some(token)),
+            Err(e) => {
+                tracing::warn!("{e}");
+                SleepGuard(None)
+            }
+        }
+    }
+
+    /// Keep the display or system awake, such as while a video plays or an export runs.
+    ///
+    /// The lock is held until the returned token is dropped, or the application quits,
+    /// whichever comes first, so a token which is never dropped can't keep the system awake
+    /// after the application is done with it. Each token holds its own lock, so the display
+    /// or system stays awake until the last of the overlapping tokens is released.
+    ///
+    /// The `reason`, such as `Playing a video`, may be shown to the user by the system's
+    /// power settings.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the platform refused the lock. On web, this is always
+    /// [`Error::Unsupported`].
+    pub fn acquire_wake_lock(
+        &self,
+        kind: WakeLockKind,
+        reason: &str,
+    ) -> Result<WakeLockToken, Error> {
+        static WAKE_LOCK_COUNTER: Counter = Counter::new();
+        let lock = self.backend_app.acquire_wake_lock(kind, reason)?;
+        let id = WAKE_LOCK_COUNTER.next();
+        self.state.borrow_mut().wake_locks.insert(id, lock);
+        Ok(WakeLockToken {
+            state: Rc::downgrade(&self.state),
+            id,
+        })
+    }
+
+    fn release_wake_locks(&self) {
+        // The locks are released outside of the borrow
+        let wake_locks = std::mem::take(&mut self.state.borrow_mut().wake_locks);
+        drop(wake_locks);
+    }
+
+    /// Show a notification on the desktop, even while the application isn't in front.
+    ///
+    /// When the user clicks it, or one of its actions,
+    /// [`AppHandler::notification_activated`] is called with the id of the returned handle.
+    /// On macOS, the application has to be in a bundle, and the user is asked whether it may
+    /// send notifications the first time. On Windows, it is shown with a temporary icon in
+    /// the notification area.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the platform has no notifications, or couldn't be reached. Failures which
+    /// are only known later, such as the user not allowing notifications, are passed to
+    /// [`AppHandler::notification_failed`]. On Linux, the notification server is only
+    /// talked to from a background thread, so failing to reach it is reported there too.
+    pub fn send_notification(
+        &self,
+        notification: Notification,
+    ) -> Result<NotificationHandle, Error> {
+        let id = NotificationId::next();
+        self.backend_app.send_notification(&notification, id)?;
+        Ok(NotificationHandle::new(id))
+    }
+
+    /// Add `path` to the system's list of documents which were recently opened with the
+    /// application.
+    ///
+    /// On Windows, this is the list which a [jump list] can show, and on macOS it is the list
+    /// in the Dock menu and the "Open Recent" menu. This is not supported on Linux or web.
+    ///
+    /// [jump list]: crate::platform::windows::ApplicationExt::set_jump_list
+    pub fn add_recent_document(&self, path: impl AsRef<Path>) {
+        self.backend_app.add_recent_document(path.as_ref())
+    }
+}
+
+/// Keeps the display or system awake for as long as it is alive.
+///
+/// Returned by [`Application::inhibit_display_sleep`] and
+/// [`Application::inhibit_system_sleep`]. Dropping the guard, including while unwinding from a
+/// panic, allows the display or system to sleep again, as does the application quitting.
+#[must_use = "sleep is only inhibited while the guard is alive"]
+pub struct SleepGuard(Option<WakeLockToken>);
+
+/// What a wake lock keeps awake, for [`Application::acquire_wake_lock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WakeLockKind {
+    /// Keep the display from dimming or sleeping because the user is idle, which also keeps
+    /// the system awake.
+    Display,
+    /// Keep the system from sleeping because the user is idle, while still allowing the
+    /// display to turn off.
+    System,
+}
+
+/// Holds a wake lock until it is dropped, or the application quits.
+///
+/// Returned by [`Application::acquire_wake_lock`].
+#[must_use = "the wake lock is released when the token is dropped"]
+pub struct WakeLockToken {
+    state: Weak<RefCell<State>>,
+    id: u64,
+}
+
+impl Drop for WakeLockToken {
+    fn drop(&mut self) {
+        // Once the application has quit, the lock has already been released
+        if let Some(state) = self.state.upgrade() {
+            let lock = state.borrow_mut().wake_locks.remove(&self.id);
+            drop(lock);
+        }
+    }
 }
 
 /// A handle that can enqueue tasks on the application loop.
@@ -194,16 +1036,198 @@ impl AppHandle {
     {
         self.0.run_on_main(callback);
     }
+
+    /// Wake the application's main loop, which then calls [`AppHandler::woken`].
+    ///
+    /// This can be called from any thread, and is cheap to call often: if a wake is already
+    /// pending, this does nothing.
+    pub fn wake(&self) {
+        if !WAKE_PENDING.swap(true, Ordering::AcqRel) {
+            self.0.run_on_main(|handler| {
+                // Clear this first, so a wake made from `woken` isn't lost
+                WAKE_PENDING.store(false, Ordering::Release);
+                if let Some(handler) = handler {
+                    handler.woken();
+                }
+            });
+        }
+    }
+}
+
+/// Identifies an event source added to the application's run loop, so that it can be removed.
+///
+/// Event sources are platform specific; see `add_event_source` on the `ApplicationExt` traits
+/// in [`platform`](crate::platform).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
+pub struct EventSourceToken(u64);
+
+pub(crate) fn closes_windows_on_panic() -> bool {
+    CLOSE_WINDOWS_ON_PANIC.load(Ordering::Relaxed)
+}
+
+/// Keeps panics in the [`AppHandler`] from unwinding into the platform's callbacks, by
+/// aborting once they are logged.
+struct GuardedAppHandler(Box<dyn AppHandler>);
+
+impl GuardedAppHandler {
+    fn call<R>(&mut self, callback: &str, f: impl FnOnce(&mut dyn AppHandler) -> R) -> R {
+        let _span = common_util::callback_span("AppHandler", callback, None);
+        let inner = &mut *self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(inner))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = common_util::panic_message(&*payload);
+                tracing::error!("AppHandler::{callback} panicked, aborting: {message}");
+                std::process::abort();
+            }
+        }
+    }
+}
+
+impl AppHandler for GuardedAppHandler {
+    fn command(&mut self, id: u32) {
+        self.call("command", |h| h.command(id))
+    }
+
+    fn power_state_changed(&mut self, state: PowerState) {
+        self.call("power_state_changed", |h| h.power_state_changed(state))
+    }
+
+    fn accessibility_preferences_changed(&mut self, prefs: A11yPrefs) {
+        self.call("accessibility_preferences_changed", |h| {
+            h.accessibility_preferences_changed(prefs)
+        })
+    }
+
+    fn system_fonts_changed(&mut self, fonts: SystemFonts) {
+        self.call("system_fonts_changed", |h| h.system_fonts_changed(fonts))
+    }
+
+    fn text_scale_factor_changed(&mut self, factor: f64) {
+        self.call("text_scale_factor_changed", |h| {
+            h.text_scale_factor_changed(factor)
+        })
+    }
+
+    fn accent_color_changed(&mut self, color: Option<Color>) {
+        self.call("accent_color_changed", |h| h.accent_color_changed(color))
+    }
+
+    fn locale_changed(&mut self) {
+        self.call("locale_changed", |h| h.locale_changed())
+    }
+
+    fn system_will_sleep(&mut self) {
+        self.call("system_will_sleep", |h| h.system_will_sleep())
+    }
+
+    fn system_did_wake(&mut self) {
+        self.call("system_did_wake", |h| h.system_did_wake())
+    }
+
+    fn session_locked(&mut self) {
+        self.call("session_locked", |h| h.session_locked())
+    }
+
+    fn session_unlocked(&mut self) {
+        self.call("session_unlocked", |h| h.session_unlocked())
+    }
+
+    fn notification_activated(&mut self, id: NotificationId, action: Option<usize>) {
+        self.call("notification_activated", |h| {
+            h.notification_activated(id, action)
+        })
+    }
+
+    fn notification_failed(&mut self, id: NotificationId, error: Error) {
+        self.call("notification_failed", |h| h.notification_failed(id, error))
+    }
+
+    fn open_files(&mut self, paths: Vec<PathBuf>) {
+        self.call("open_files", |h| h.open_files(paths))
+    }
+
+    fn backend_error(&mut self, error: Error) {
+        self.call("backend_error", |h| h.backend_error(error))
+    }
+
+    fn woken(&mut self) {
+        self.call("woken", |h| h.woken())
+    }
+
+    fn idle(&mut self) {
+        self.call("idle", |h| h.idle())
+    }
+
+    fn idle_ended(&mut self) {
+        self.call("idle_ended", |h| h.idle_ended())
+    }
+
+    fn power_preference(&mut self) -> PowerPreference {
+        self.call("power_preference", |h| h.power_preference())
+    }
+
+    fn handler_panicked(&mut self, window: &WindowHandle, payload: Box<dyn Any + Send>) {
+        self.call("handler_panicked", |h| h.handler_panicked(window, payload))
+    }
+}
+
+impl EventSourceToken {
+    /// Create a new token.
+    pub fn next() -> EventSourceToken {
+        static EVENT_SOURCE_COUNTER: Counter = Counter::new();
+        EventSourceToken(EVENT_SOURCE_COUNTER.next())
+    }
+
+    /// Create a new token from a raw value.
+    pub const fn from_raw(id: u64) -> EventSourceToken {
+        EventSourceToken(id)
+    }
+
+    /// Get the raw value for a token.
+    pub const fn into_raw(self) -> u64 {
+        self.0
+    }
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
     use super::*;
 
     use static_assertions as sa;
 
     sa::assert_not_impl_any!(Application: Send, Sync);
     sa::assert_impl_all!(AppHandle: Send);
+    // Wake locks must be released on the main thread
+    sa::assert_not_impl_any!(WakeLockToken: Send, Sync);
     // TODO: sa::assert_not_impl_all!(AppHandle: Sync);
     // and same for IdleHandle
+
+    /// Only one `Application` can exist at a time, and it claims the thread it was created
+    /// on, so the tests which create one, or which use the main thread, take turns.
+    static APP_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Wait until no other test has an `Application`.
+    pub(crate) fn lock_app() -> MutexGuard<'static, ()> {
+        // A test which panicked while holding the lock has already torn its application down
+        APP_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Without the wayland feature, its backend always fails to be created
+    #[cfg(all(
+        feature = "headless",
+        not(feature = "wayland"),
+        any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")
+    ))]
+    #[test]
+    fn application_can_be_created_after_failing() {
+        let _lock = lock_app();
+        assert!(Application::new_with_backend(Backend::Wayland).is_err());
+        assert!(Application::try_global().is_none());
+        let app = Application::new_with_backend(Backend::Headless).unwrap();
+        app.quit();
+        app.run(None);
+    }
 }
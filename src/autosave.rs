@@ -0,0 +1,247 @@
+//! Saving where windows are, for [`WindowBuilder::with_frame_autosave_name`].
+//!
+//! AppKit saves and restores frames itself. Elsewhere, each window's [`WindowPlacement`] is
+//! written to a small file in the user's state directory, named after the window and kept
+//! under the application's id.
+//!
+//! [`WindowBuilder::with_frame_autosave_name`]: crate::WindowBuilder::with_frame_autosave_name
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::kurbo::Rect;
+use crate::window::{WindowHandle, WindowPlacement, WindowState};
+
+/// Turns autosaving off while it's set, so that tests always start from the same place.
+const DISABLE_VAR: &str = "GLAZIER_DISABLE_FRAME_AUTOSAVE";
+
+pub(crate) fn enabled() -> bool {
+    std::env::var_os(DISABLE_VAR).is_none()
+}
+
+/// The id given to [`Application::new_with_name`], which frames are saved under.
+///
+/// [`Application::new_with_name`]: crate::Application::new_with_name
+static APP_ID: Mutex<Option<String>> = Mutex::new(None);
+
+pub(crate) fn set_app_id(app_id: &str) {
+    *APP_ID.lock().unwrap() = Some(app_id.to_owned());
+}
+
+/// What is saved of a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SavedFrame {
+    pub(crate) placement: WindowPlacement,
+    /// Whether the window was fullscreen, in which case the placement is where it goes when it
+    /// leaves fullscreen.
+    pub(crate) fullscreen: bool,
+}
+
+/// The frame saved under `name`, if there is one.
+pub(crate) fn load(name: &str) -> Option<SavedFrame> {
+    if !enabled() {
+        return None;
+    }
+    let text = std::fs::read_to_string(path(name)?).ok()?;
+    let frame = decode(&text);
+    if frame.is_none() {
+        tracing::warn!("the saved frame of window {name:?} can't be read, so it isn't restored");
+    }
+    frame
+}
+
+/// Save where `handle` is under `name`, and whether it is `fullscreen`.
+#[cfg_attr(not(any(target_os = "windows", feature = "x11")), allow(unused))]
+pub(crate) fn save(name: &str, handle: &WindowHandle, fullscreen: bool) {
+    if !enabled() {
+        return;
+    }
+    let Some(path) = path(name) else {
+        return;
+    };
+    let frame = SavedFrame {
+        placement: handle.placement(),
+        fullscreen,
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&path, encode(&merge(frame, load(name)))));
+    if let Err(e) = result {
+        tracing::warn!("failed to save the frame of window {name:?}: {e}");
+    }
+}
+
+/// The frame to save, given where the window is now and what was saved before.
+///
+/// A minimized or fullscreen window isn't where it goes back to, so it keeps the rect which
+/// was saved before, if there is one. A minimized window is saved in the state it had before,
+/// or as restored, so that it comes back as a normal window.
+fn merge(mut frame: SavedFrame, previous: Option<SavedFrame>) -> SavedFrame {
+    let minimized = frame.placement.state == WindowState::Minimized;
+    if minimized || frame.fullscreen {
+        if let Some(previous) = previous {
+            frame.placement.monitor = previous.placement.monitor;
+            frame.placement.rect = previous.placement.rect;
+            if minimized {
+                frame.placement.state = previous.placement.state;
+                frame.fullscreen = previous.fullscreen;
+            }
+        }
+    }
+    if frame.placement.state == WindowState::Minimized {
+        frame.placement.state = WindowState::Restored;
+    }
+    frame
+}
+
+/// Where the placement saved under `name` is kept, with anything which isn't allowed in a
+/// file name replaced.
+fn path(name: &str) -> Option<PathBuf> {
+    let file_name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    Some(state_dir()?.join(app_name()).join("frames").join(file_name))
+}
+
+#[cfg(target_os = "windows")]
+fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+fn state_dir() -> Option<PathBuf> {
+    // https://specifications.freedesktop.org/basedir-spec/latest/
+    match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")),
+    }
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd"
+)))]
+fn state_dir() -> Option<PathBuf> {
+    None
+}
+
+/// The name the application's files are kept under: its id if it has one, or else the
+/// executable's name.
+fn app_name() -> String {
+    if let Some(app_id) = APP_ID.lock().unwrap().clone() {
+        return app_id;
+    }
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "glazier".into())
+}
+
+/// A line of the monitor's and the window's rects, followed by the state, and `fullscreen` if
+/// the window was.
+fn encode(frame: &SavedFrame) -> String {
+    let placement = &frame.placement;
+    let state = match placement.state {
+        WindowState::Maximized => "maximized",
+        WindowState::Minimized => "minimized",
+        WindowState::Restored => "restored",
+    };
+    let fullscreen = if frame.fullscreen { " fullscreen" } else { "" };
+    let (m, r) = (placement.monitor, placement.rect);
+    format!(
+        "{} {} {} {} {} {} {} {} {state}{fullscreen}\n",
+        m.x0, m.y0, m.x1, m.y1, r.x0, r.y0, r.x1, r.y1
+    )
+}
+
+fn decode(text: &str) -> Option<SavedFrame> {
+    let mut fields = text.split_whitespace();
+    let mut coords = [0.0; 8];
+    for coord in &mut coords {
+        *coord = fields
+            .next()?
+            .parse()
+            .ok()
+            .filter(|it: &f64| it.is_finite())?;
+    }
+    let state = match fields.next()? {
+        "maximized" => WindowState::Maximized,
+        "minimized" => WindowState::Minimized,
+        "restored" => WindowState::Restored,
+        _ => return None,
+    };
+    // Frames saved by earlier versions end with the state.
+    let fullscreen = match fields.next() {
+        None => false,
+        Some("fullscreen") => true,
+        Some(_) => return None,
+    };
+    let [mx0, my0, mx1, my1, rx0, ry0, rx1, ry1] = coords;
+    let placement = WindowPlacement {
+        monitor: Rect::new(mx0, my0, mx1, my1),
+        rect: Rect::new(rx0, ry0, rx1, ry1),
+        state,
+    };
+    Some(SavedFrame {
+        placement,
+        fullscreen,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(rect: Rect, state: WindowState, fullscreen: bool) -> SavedFrame {
+        let placement = WindowPlacement {
+            monitor: Rect::new(-1920.0, 0.0, 0.0, 1080.0),
+            rect,
+            state,
+        };
+        SavedFrame {
+            placement,
+            fullscreen,
+        }
+    }
+
+    #[test]
+    fn placements_round_trip() {
+        let rect = Rect::new(10.5, 20.0, 810.5, 620.0);
+        for fullscreen in [false, true] {
+            let frame = frame(rect, WindowState::Maximized, fullscreen);
+            assert_eq!(decode(&encode(&frame)), Some(frame));
+        }
+        let old = decode("-1920 0 0 1080 10.5 20 810.5 620 maximized");
+        assert_eq!(old, Some(frame(rect, WindowState::Maximized, false)));
+        assert_eq!(decode("0 0 1920 1080 0 0 800 600"), None);
+        assert_eq!(decode("0 0 1920 1080 0 0 800 NaN restored"), None);
+        assert_eq!(decode("0 0 1920 1080 0 0 800 600 restored tiled"), None);
+    }
+
+    #[test]
+    fn minimized_and_fullscreen_windows_keep_their_rect() {
+        let before = Rect::new(10.0, 20.0, 810.0, 620.0);
+        let now = Rect::new(-32000.0, -32000.0, -31840.0, -31972.0);
+        let previous = Some(frame(before, WindowState::Maximized, false));
+
+        let minimized = frame(now, WindowState::Minimized, false);
+        let expected = frame(before, WindowState::Maximized, false);
+        assert_eq!(merge(minimized, previous), expected);
+        let expected = frame(now, WindowState::Restored, false);
+        assert_eq!(merge(minimized, None), expected);
+
+        let fullscreen = frame(now, WindowState::Restored, true);
+        let expected = frame(before, WindowState::Restored, true);
+        assert_eq!(merge(fullscreen, previous), expected);
+
+        let restored = frame(now, WindowState::Restored, false);
+        assert_eq!(merge(restored, previous), restored);
+    }
+}
@@ -0,0 +1,167 @@
+//! Scrolling a view while something is dragged near its edges.
+
+use std::time::Duration;
+
+use instant::Instant;
+
+use crate::kurbo::{Point, Rect, Vec2};
+use crate::window::{TimerToken, WindowHandle};
+
+/// How often the view scrolls while the pointer rests near its edge.
+const INTERVAL: Duration = Duration::from_millis(16);
+
+/// Scrolls a view while the pointer is dragged near its edges, such as when selecting text or
+/// dragging an item in a list.
+///
+/// The view scrolls faster the closer the pointer gets to the edge, and keeps scrolling at the
+/// fastest speed once it leaves the view. It is driven by the window's timers, so it keeps
+/// going while the pointer rests:
+///
+/// - call [`pointer_moved`] from [`WinHandler::pointer_move`] while the drag goes on,
+/// - call [`timer`] from [`WinHandler::timer`], and scroll by the offset it returns,
+/// - call [`stop`] when the drag ends.
+///
+/// All positions and speeds are in [display points](crate::Scale).
+///
+/// [`pointer_moved`]: AutoScroll::pointer_moved
+/// [`timer`]: AutoScroll::timer
+/// [`stop`]: AutoScroll::stop
+/// [`WinHandler::pointer_move`]: crate::WinHandler::pointer_move
+/// [`WinHandler::timer`]: crate::WinHandler::timer
+#[derive(Debug, Clone)]
+pub struct AutoScroll {
+    region: Rect,
+    margin: f64,
+    max_speed: f64,
+    pos: Option<Point>,
+    timer: Option<TimerToken>,
+    last_tick: Option<Instant>,
+}
+
+impl AutoScroll {
+    /// Create a helper for the view which covers `region` of the window.
+    pub fn new(region: Rect) -> AutoScroll {
+        AutoScroll {
+            region,
+            margin: 24.0,
+            max_speed: 1200.0,
+            pos: None,
+            timer: None,
+            last_tick: None,
+        }
+    }
+
+    /// Set how close to the edge the pointer has to be for the view to scroll.
+    ///
+    /// The default is 24 display points.
+    pub fn with_margin(mut self, margin: f64) -> AutoScroll {
+        self.margin = margin.max(f64::EPSILON);
+        self
+    }
+
+    /// Set how fast the view scrolls at the edge and beyond it, per second.
+    ///
+    /// The default is 1200 display points per second.
+    pub fn with_max_speed(mut self, max_speed: f64) -> AutoScroll {
+        self.max_speed = max_speed;
+        self
+    }
+
+    /// Set the region the view covers, such as after the window is resized.
+    pub fn set_region(&mut self, region: Rect) {
+        self.region = region;
+    }
+
+    /// Returns `true` while the view is being scrolled.
+    pub fn is_active(&self) -> bool {
+        self.timer.is_some()
+    }
+
+    /// Tell the helper where the pointer is being dragged to.
+    ///
+    /// This starts scrolling once the pointer gets near an edge.
+    pub fn pointer_moved(&mut self, handle: &WindowHandle, pos: Point) {
+        self.pos = Some(pos);
+        if self.timer.is_none() && self.velocity(pos) != Vec2::ZERO {
+            self.last_tick = Some(Instant::now());
+            self.timer = Some(handle.request_timer(INTERVAL));
+        }
+    }
+
+    /// Handle a timer, returning how far to scroll the view if it was this helper's.
+    ///
+    /// A positive offset scrolls towards the bottom right, like [`WinHandler::wheel`]. The
+    /// next timer is requested as long as the pointer stays near an edge.
+    ///
+    /// [`WinHandler::wheel`]: crate::WinHandler::wheel
+    pub fn timer(&mut self, handle: &WindowHandle, token: TimerToken) -> Option<Vec2> {
+        if self.timer != Some(token) {
+            return None;
+        }
+        self.timer = None;
+        let velocity = self.velocity(self.pos?);
+        if velocity == Vec2::ZERO {
+            return None;
+        }
+        let now = Instant::now();
+        let elapsed = self
+            .last_tick
+            .replace(now)
+            .map_or(INTERVAL, |last| now - last);
+        self.timer = Some(handle.request_timer(INTERVAL));
+        Some(velocity * elapsed.as_secs_f64())
+    }
+
+    /// Stop scrolling, such as when the drag ends.
+    pub fn stop(&mut self) {
+        self.pos = None;
+        self.timer = None;
+        self.last_tick = None;
+    }
+
+    /// Returns the speed, per second, at which the view scrolls while the pointer is at `pos`.
+    pub fn velocity(&self, pos: Point) -> Vec2 {
+        let axis = |pos: f64, start: f64, end: f64| {
+            // Views smaller than both margins scroll from their middle.
+            let margin = self.margin.min((end - start) / 2.0);
+            if pos < start + margin {
+                -self.max_speed * ((start + margin - pos) / margin).min(1.0)
+            } else if pos > end - margin {
+                self.max_speed * ((pos - (end - margin)) / margin).min(1.0)
+            } else {
+                0.0
+            }
+        };
+        let region = self.region.abs();
+        Vec2::new(
+            axis(pos.x, region.x0, region.x1),
+            axis(pos.y, region.y0, region.y1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn velocity_grows_towards_the_edges() {
+        let scroll = AutoScroll::new(Rect::new(0.0, 0.0, 400.0, 300.0))
+            .with_margin(20.0)
+            .with_max_speed(100.0);
+        assert_eq!(scroll.velocity(Point::new(200.0, 150.0)), Vec2::ZERO);
+        assert_eq!(
+            scroll.velocity(Point::new(10.0, 150.0)),
+            Vec2::new(-50.0, 0.0)
+        );
+        assert_eq!(
+            scroll.velocity(Point::new(200.0, 300.0)),
+            Vec2::new(0.0, 100.0)
+        );
+        // Beyond the view, it doesn't get any faster.
+        assert_eq!(
+            scroll.velocity(Point::new(-50.0, -50.0)),
+            Vec2::new(-100.0, -100.0)
+        );
+    }
+}
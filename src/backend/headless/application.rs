@@ -0,0 +1,544 @@
+//! The headless application, and its event loop.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::clipboard::Clipboard;
+use super::window::Window;
+use crate::backend::application::SleepGuard;
+use crate::{
+    A11yPrefs, AppHandler, Color, Error, EventSourceToken, FontDescription, IdleToken, LoopMode,
+    LoopStatus, Notification, NotificationId, PowerState, SystemColor, SystemFonts,
+    TextAntialiasing, TextRenderingPrefs, UiDirection, UiMetrics, WakeLockKind, WinHandler,
+};
+
+type MainCallback = Box<dyn FnOnce(Option<&mut dyn AppHandler>) + Send>;
+
+pub(crate) enum IdleAction {
+    Callback(Box<dyn FnOnce(&mut dyn WinHandler) + Send>),
+    Token(IdleToken),
+}
+
+/// Work queued for the main thread, which can come from any thread.
+#[derive(Default)]
+pub(crate) struct Queues {
+    main: VecDeque<MainCallback>,
+    /// Idle work, with the id of the window it is for.
+    pub(super) idle: VecDeque<(u64, IdleAction)>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Application {
+    pub(super) state: Rc<AppState>,
+}
+
+pub(crate) struct AppState {
+    pub(super) queues: Arc<Mutex<Queues>>,
+    /// The virtual time, which only moves when the event loop skips ahead to the next thing
+    /// it has to do, or when the application advances it.
+    clock: Cell<Instant>,
+    pub(super) windows: RefCell<Vec<Rc<Window>>>,
+    clipboard: Clipboard,
+    quitting: Cell<bool>,
+    handler: RefCell<Option<Box<dyn AppHandler>>>,
+}
+
+impl AppState {
+    pub(super) fn now(&self) -> Instant {
+        self.clock.get()
+    }
+}
+
+impl Application {
+    pub fn new() -> Application {
+        let state = AppState {
+            queues: Default::default(),
+            clock: Cell::new(Instant::now()),
+            windows: Default::default(),
+            clipboard: Clipboard::default(),
+            quitting: Cell::new(false),
+            handler: RefCell::new(None),
+        };
+        Application {
+            state: Rc::new(state),
+        }
+    }
+
+    pub fn quit(&self) {
+        self.state.quitting.set(true);
+    }
+
+    pub fn set_app_id(&self, _app_id: &str) {}
+
+    pub fn clipboard(&self) -> Clipboard {
+        self.state.clipboard.clone()
+    }
+
+    pub fn primary_clipboard(&self) -> Clipboard {
+        self.state.clipboard.primary()
+    }
+
+    pub fn get_locale() -> String {
+        // Tests shouldn't depend on the machine they run on
+        "en-US".into()
+    }
+
+    pub fn get_locales() -> Vec<String> {
+        vec![Self::get_locale()]
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        UiDirection::Ltr
+    }
+
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        *self.state.handler.borrow_mut() = handler;
+    }
+
+    pub fn run(self, handler: Option<Box<dyn AppHandler>>) {
+        self.set_handler(handler);
+        while self.run_loop_iteration(LoopMode::Wait) == LoopStatus::Continue {}
+    }
+
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        if !self.state.quitting.get() && !self.has_work() && mode != LoopMode::Poll {
+            self.with_handler(|handler| handler.idle());
+            if !self.has_work() {
+                // Waiting only moves the virtual clock, up to the timeout
+                let timeout = match mode {
+                    LoopMode::WaitTimeout(timeout) => Some(self.state.now() + timeout),
+                    _ => None,
+                };
+                let deadline = match (self.next_deadline(), timeout) {
+                    (Some(deadline), Some(timeout)) => Some(deadline.min(timeout)),
+                    (deadline, timeout) => deadline.or(timeout),
+                };
+                match deadline {
+                    Some(deadline) => self.state.clock.set(deadline),
+                    None => {
+                        tracing::warn!(
+                            "the headless application has nothing left to wait for, so it stops \
+                             running without having been quit"
+                        );
+                        self.quit();
+                    }
+                }
+            }
+            self.with_handler(|handler| handler.idle_ended());
+        }
+        while !self.state.quitting.get() && self.has_work() {
+            self.dispatch();
+        }
+        if self.state.quitting.get() {
+            self.shutdown();
+            self.state.handler.borrow_mut().take();
+            LoopStatus::Exit
+        } else {
+            LoopStatus::Continue
+        }
+    }
+
+    pub fn get_handle(&self) -> Option<AppHandle> {
+        Some(AppHandle {
+            queues: self.state.queues.clone(),
+        })
+    }
+
+    pub fn add_event_source(
+        &self,
+        _fd: RawFd,
+        _callback: Box<dyn FnMut(Option<&mut dyn AppHandler>)>,
+    ) -> EventSourceToken {
+        tracing::warn!("event sources are never polled by the headless backend");
+        EventSourceToken::next()
+    }
+
+    pub fn remove_event_source(&self, _token: EventSourceToken) {}
+
+    // The desktop's settings are fixed, so tests don't depend on the machine they run on.
+
+    pub fn power_state(&self) -> PowerState {
+        PowerState::default()
+    }
+
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        A11yPrefs::default()
+    }
+
+    pub fn system_fonts(&self) -> SystemFonts {
+        let font = |family: &str| FontDescription {
+            family: family.into(),
+            size: 11.0,
+        };
+        SystemFonts {
+            ui: font("sans-serif"),
+            monospace: font("monospace"),
+            menu: font("sans-serif"),
+            title: font("sans-serif"),
+            small: font("sans-serif"),
+        }
+    }
+
+    pub fn text_scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    pub fn accent_color(&self) -> Option<Color> {
+        None
+    }
+
+    pub fn system_color(&self, _color: SystemColor) -> Option<Color> {
+        None
+    }
+
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        TextRenderingPrefs {
+            antialiasing: TextAntialiasing::Grayscale,
+            hinting: false,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn ui_metrics(&self) -> UiMetrics {
+        UiMetrics {
+            scrollbar_width: 15.0,
+            caret_width: 1.0,
+            // A blinking caret would keep a timer pending forever, so `run` would never stop
+            caret_blink: None,
+            drag_threshold: 4.0,
+            menu_show_delay: Duration::from_millis(400),
+        }
+    }
+
+    /// There is no display or system to keep awake, so this always succeeds.
+    pub fn acquire_wake_lock(&self, _kind: WakeLockKind, _reason: &str) -> SleepGuard {
+        SleepGuard::default()
+    }
+
+    /// Notifications are never shown, and so never activated.
+    pub fn send_notification(
+        &self,
+        _notification: &Notification,
+        _id: NotificationId,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub fn close_notification(&self, _id: NotificationId) {}
+
+    /// The time on the virtual clock.
+    pub fn now(&self) -> Instant {
+        self.state.now()
+    }
+
+    pub fn advance_clock(&self, duration: Duration) {
+        self.state.clock.set(self.state.now() + duration);
+    }
+
+    fn windows(&self) -> Vec<Rc<Window>> {
+        self.state.windows.borrow().clone()
+    }
+
+    fn with_handler(&self, f: impl FnOnce(&mut dyn AppHandler)) {
+        if let Some(handler) = self.state.handler.borrow_mut().as_mut() {
+            f(&mut **handler);
+        }
+    }
+
+    /// Returns `true` if there is anything to do at the current time.
+    fn has_work(&self) -> bool {
+        let now = self.state.now();
+        {
+            let queues = self.state.queues.lock().unwrap();
+            if !queues.main.is_empty() || !queues.idle.is_empty() {
+                return true;
+            }
+        }
+        self.windows().iter().any(|window| window.has_work(now))
+    }
+
+    /// The next time at which there will be something to do.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.windows()
+            .iter()
+            .filter_map(|window| window.next_deadline())
+            .min()
+    }
+
+    /// Do everything which is ready at the current time, in a fixed order.
+    fn dispatch(&self) {
+        // The queues are taken first, so that work which is queued from the callbacks waits
+        // for the next round
+        let (main, idle) = {
+            let mut queues = self.state.queues.lock().unwrap();
+            (
+                std::mem::take(&mut queues.main),
+                std::mem::take(&mut queues.idle),
+            )
+        };
+        for callback in main {
+            let mut handler = self.state.handler.borrow_mut();
+            callback(match handler.as_mut() {
+                Some(handler) => Some(&mut **handler),
+                None => None,
+            });
+        }
+        let windows = self.windows();
+        for (id, action) in idle {
+            let Some(window) = windows.iter().find(|window| window.id == id) else {
+                tracing::debug!("ignoring idle work for a window which has been closed");
+                continue;
+            };
+            window.with_handler(|handler| match action {
+                IdleAction::Callback(callback) => callback(handler),
+                IdleAction::Token(token) => handler.idle(token),
+            });
+        }
+
+        // Timers which are due at the same time fire in the order they were requested
+        let now = self.state.now();
+        let mut timers = windows
+            .iter()
+            .flat_map(|window| {
+                window
+                    .take_due_timers(now)
+                    .into_iter()
+                    .map(move |(deadline, token)| (deadline, token, window))
+            })
+            .collect::<Vec<_>>();
+        timers.sort_by_key(|(deadline, token, _)| (*deadline, token.into_raw()));
+        for (_, token, window) in timers {
+            window.with_handler(|handler| handler.timer(token));
+        }
+
+        for window in &windows {
+            window.update(now);
+        }
+        for window in windows.iter().filter(|window| window.is_closing()) {
+            self.destroy(window);
+        }
+    }
+
+    fn destroy(&self, window: &Rc<Window>) {
+        // Like the other backends, a sheet of the window is ended and closed before it
+        if let Some(sheet) = window.shown_sheet() {
+            self.destroy(&sheet);
+        }
+        self.state
+            .windows
+            .borrow_mut()
+            .retain(|it| !Rc::ptr_eq(it, window));
+        window.destroy();
+    }
+
+    /// Destroy the windows which are left when the application quits.
+    fn shutdown(&self) {
+        for window in self.windows() {
+            self.destroy(&window);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AppHandle {
+    queues: Arc<Mutex<Queues>>,
+}
+
+impl AppHandle {
+    pub fn run_on_main<F>(&self, callback: F)
+    where
+        F: FnOnce(Option<&mut dyn AppHandler>) + Send + 'static,
+    {
+        self.queues
+            .lock()
+            .unwrap()
+            .main
+            .push_back(Box::new(callback));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::super::test_util::{Recorder, TestApp, TextBox};
+    use crate::kurbo::Point;
+    use crate::platform::headless::ApplicationExt;
+    use crate::{
+        AppHandler, Application, InjectedEvent, KbKey, KeyEvent, LoopMode, LoopStatus, Modifiers,
+        PointerEvent,
+    };
+
+    #[test]
+    fn run_returns_once_quit() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::reacting(&log, |_, entry| {
+            if entry.starts_with("timer") {
+                Application::global().quit();
+            }
+        }));
+        let quit = window.request_timer(Duration::from_secs(1));
+        // This would fire if run kept going
+        window.request_timer(Duration::from_secs(2));
+        app.run(None);
+
+        let log = log.borrow();
+        let timers = log
+            .iter()
+            .filter(|entry| entry.starts_with("timer"))
+            .collect::<Vec<_>>();
+        assert_eq!(timers, [&format!("timer {}", quit.into_raw())]);
+        assert_eq!(log.last().map(String::as_str), Some("destroy"));
+    }
+
+    #[test]
+    fn timers_fire_in_virtual_clock_order() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::new(&log));
+        app.pump_events(Some(Duration::ZERO));
+        log.borrow_mut().clear();
+
+        let start = app.now();
+        let late = window.request_timer(Duration::from_millis(30));
+        let early = window.request_timer(Duration::from_millis(10));
+        let tied = window.request_timer(Duration::from_millis(30));
+        // Pumping with nothing due skips the clock ahead to the next timer
+        app.pump_events(None);
+        assert_eq!(app.now() - start, Duration::from_millis(10));
+        app.advance_clock(Duration::from_secs(1));
+        app.pump_events(Some(Duration::ZERO));
+
+        let expected = [early, late, tied].map(|token| format!("timer {}", token.into_raw()));
+        assert_eq!(*log.borrow(), expected);
+    }
+
+    #[test]
+    fn loop_modes_wait_as_long_as_they_say() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::new(&log));
+        app.run_loop_iteration(LoopMode::Poll);
+        log.borrow_mut().clear();
+
+        let start = app.now();
+        let timer = window.request_timer(Duration::from_millis(30));
+        // Polling never moves the clock, and a timeout stops short of the timer
+        assert_eq!(app.run_loop_iteration(LoopMode::Poll), LoopStatus::Continue);
+        assert_eq!(app.now(), start);
+        app.run_loop_iteration(LoopMode::WaitTimeout(Duration::from_millis(10)));
+        assert_eq!(app.now() - start, Duration::from_millis(10));
+        assert!(log.borrow().is_empty());
+        app.run_loop_iteration(LoopMode::Wait);
+        assert_eq!(app.now() - start, Duration::from_millis(30));
+        assert_eq!(*log.borrow(), [format!("timer {}", timer.into_raw())]);
+
+        Application::global().quit();
+        assert_eq!(app.run_loop_iteration(LoopMode::Poll), LoopStatus::Exit);
+    }
+
+    #[test]
+    fn injected_events_arrive_in_order() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::new(&log));
+        app.pump_events(Some(Duration::ZERO));
+        log.borrow_mut().clear();
+
+        let pointer = |x, y| PointerEvent {
+            pos: Point::new(x, y),
+            ..Default::default()
+        };
+        let key = || KeyEvent::for_test(Modifiers::default(), "a");
+        let events = [
+            InjectedEvent::PointerDown(pointer(1.0, 2.0)),
+            InjectedEvent::KeyDown(key()),
+            InjectedEvent::PointerMove(pointer(3.0, 4.0)),
+            InjectedEvent::KeyUp(key()),
+            InjectedEvent::PointerUp(pointer(3.0, 4.0)),
+        ];
+        for event in events {
+            app.inject_event(&window, event).unwrap();
+        }
+        // Nothing is delivered until the event loop runs
+        assert!(log.borrow().is_empty());
+        app.pump_events(Some(Duration::ZERO));
+
+        assert_eq!(
+            *log.borrow(),
+            [
+                "pointer_down 1 2",
+                "key_down a",
+                "pointer_move 3 4",
+                "key_up a",
+                "pointer_up 3 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn injected_keys_type_into_the_focused_field() {
+        let app = TestApp::new();
+        let text_box = TextBox::default();
+        let (text, submitted) = (text_box.text.clone(), text_box.submitted.clone());
+        let window = app.window(text_box);
+        app.pump_events(Some(Duration::ZERO));
+
+        let key = |key| InjectedEvent::KeyDown(KeyEvent::for_test(Modifiers::default(), key));
+        let events = [
+            // Nothing is focused yet, so this goes nowhere
+            key(KbKey::Character("x".into())),
+            // The field is focused by the click, before the keys after it arrive
+            InjectedEvent::PointerDown(PointerEvent::default()),
+            key(KbKey::Character("h".into())),
+            key(KbKey::Character("i".into())),
+            // The handler handles this one itself
+            key(KbKey::Enter),
+        ];
+        for event in events {
+            app.inject_event(&window, event).unwrap();
+        }
+        app.pump_events(Some(Duration::ZERO));
+
+        assert_eq!(*text.borrow(), "hi");
+        assert_eq!(*submitted.borrow(), ["hi"]);
+    }
+
+    struct Commands(Rc<RefCell<Vec<u32>>>);
+
+    impl AppHandler for Commands {
+        fn command(&mut self, id: u32) {
+            self.0.borrow_mut().push(id);
+        }
+    }
+
+    #[test]
+    fn pumped_callbacks_reach_the_handler() {
+        let app = TestApp::new();
+        let commands = Rc::new(RefCell::new(Vec::new()));
+        app.set_handler(Some(Box::new(Commands(commands.clone()))));
+        let handle = app.get_handle().unwrap();
+        let send_command = |id| {
+            handle.run_on_main(move |handler| {
+                if let Some(handler) = handler {
+                    handler.command(id);
+                }
+            })
+        };
+        send_command(1);
+        app.pump_events(Some(Duration::ZERO));
+        assert_eq!(*commands.borrow(), [1]);
+
+        app.set_handler(None);
+        send_command(2);
+        app.pump_events(Some(Duration::ZERO));
+        assert_eq!(*commands.borrow(), [1]);
+    }
+}
@@ -0,0 +1,105 @@
+//! An in-memory clipboard.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::clipboard::{ClipboardFormat, FormatId};
+
+/// A clipboard which is only shared by the handles to it.
+///
+/// Each [`Application`](super::application::Application) has one, along with the primary
+/// selection.
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    contents: Rc<RefCell<Vec<ClipboardFormat>>>,
+    primary: Rc<RefCell<Vec<ClipboardFormat>>>,
+}
+
+impl Clipboard {
+    /// Returns a handle to the primary selection of this clipboard.
+    pub(crate) fn primary(&self) -> Clipboard {
+        Clipboard {
+            contents: self.primary.clone(),
+            primary: self.primary.clone(),
+        }
+    }
+
+    pub fn put_string(&mut self, s: impl AsRef<str>) {
+        self.put_formats(&[ClipboardFormat::from(s.as_ref())]);
+    }
+
+    pub fn put_formats(&mut self, formats: &[ClipboardFormat]) {
+        *self.contents.borrow_mut() = formats.to_vec();
+    }
+
+    pub fn get_string(&self) -> Option<String> {
+        self.get_format(ClipboardFormat::TEXT)
+            .and_then(|data| String::from_utf8(data).ok())
+    }
+
+    pub fn put_primary(&mut self, s: impl AsRef<str>) {
+        *self.primary.borrow_mut() = vec![ClipboardFormat::from(s.as_ref())];
+    }
+
+    pub fn get_primary(&self) -> Option<String> {
+        self.primary().get_string()
+    }
+
+    pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
+        let contents = self.contents.borrow();
+        formats
+            .iter()
+            .find(|format| contents.iter().any(|it| it.identifier == **format))
+            .copied()
+    }
+
+    pub fn get_format(&self, format: FormatId) -> Option<Vec<u8>> {
+        self.contents
+            .borrow()
+            .iter()
+            .find(|it| it.identifier == format)
+            .map(|it| it.data.clone())
+    }
+
+    pub fn available_type_names(&self) -> Vec<String> {
+        self.contents
+            .borrow()
+            .iter()
+            .map(|it| it.identifier.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clipboard_is_shared_by_its_handles() {
+        let mut clipboard = Clipboard::default();
+        let other = clipboard.clone();
+        assert_eq!(other.get_string(), None);
+
+        clipboard.put_string("hello");
+        assert_eq!(other.get_string().as_deref(), Some("hello"));
+
+        let png = ClipboardFormat::new(ClipboardFormat::PNG, vec![1, 2, 3]);
+        clipboard.put_formats(&[ClipboardFormat::from("text"), png]);
+        assert_eq!(
+            other.preferred_format(&[ClipboardFormat::PNG, ClipboardFormat::TEXT]),
+            Some(ClipboardFormat::PNG)
+        );
+        assert_eq!(other.get_format(ClipboardFormat::PNG), Some(vec![1, 2, 3]));
+        assert_eq!(other.get_string().as_deref(), Some("text"));
+    }
+
+    #[test]
+    fn primary_selection_is_separate() {
+        let mut clipboard = Clipboard::default();
+        clipboard.put_string("clipboard");
+        clipboard.put_primary("primary");
+        assert_eq!(clipboard.get_string().as_deref(), Some("clipboard"));
+        assert_eq!(clipboard.get_primary().as_deref(), Some("primary"));
+        assert_eq!(clipboard.primary().get_string().as_deref(), Some("primary"));
+    }
+}
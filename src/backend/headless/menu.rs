@@ -0,0 +1,31 @@
+//! Menus, which are never shown.
+
+use crate::hotkey::HotKey;
+
+/// A menu, which is accepted wherever other backends take one, but never shown.
+#[derive(Default, Debug)]
+pub struct Menu;
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu
+    }
+
+    pub fn new_for_popup() -> Menu {
+        Menu
+    }
+
+    pub fn add_dropdown(&mut self, _menu: Menu, _text: &str, _enabled: bool) {}
+
+    pub fn add_item(
+        &mut self,
+        _id: u32,
+        _text: &str,
+        _key: Option<&HotKey>,
+        _selected: Option<bool>,
+        _enabled: bool,
+    ) {
+    }
+
+    pub fn add_separator(&mut self) {}
+}
@@ -0,0 +1,14 @@
+//! A backend which runs entirely in memory, without a display server.
+//!
+//! See [`crate::platform::headless`] for how it differs from the other backends. It is only
+//! ever used through the Linux dispatch layer, so it implements the same methods as the
+//! Wayland and X11 backends.
+
+pub mod application;
+pub mod clipboard;
+pub mod menu;
+pub mod screen;
+pub mod window;
+
+#[cfg(test)]
+pub(crate) mod test_util;
@@ -0,0 +1,11 @@
+//! The virtual screen.
+
+use crate::kurbo::Rect;
+use crate::screen::Monitor;
+
+/// The bounds of the only monitor, at a common resolution.
+pub(crate) const MONITOR_RECT: Rect = Rect::new(0.0, 0.0, 1920.0, 1080.0);
+
+pub(crate) fn get_monitors() -> Vec<Monitor> {
+    vec![Monitor::new(true, MONITOR_RECT, MONITOR_RECT)]
+}
@@ -0,0 +1,255 @@
+//! Helpers for the tests which run on the headless backend.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::MutexGuard;
+
+use crate::application::test::lock_app;
+use crate::kurbo::{Point, Rect};
+use crate::text::{Action, Affinity, HitTestPoint, InputHandler, Selection};
+use crate::{
+    AppHandler, Application, Backend, KbKey, KeyEvent, PointerEvent, Region, TextFieldToken,
+    TimerToken, WinHandler, WindowBuilder, WindowHandle,
+};
+
+/// A headless application, which is quit when it is dropped, so that the next test can
+/// create its own.
+pub(crate) struct TestApp {
+    app: Option<Application>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestApp {
+    pub(crate) fn new() -> TestApp {
+        let lock = lock_app();
+        let app = Application::new_with_backend(Backend::Headless).unwrap();
+        TestApp {
+            app: Some(app),
+            _lock: lock,
+        }
+    }
+
+    /// Build a visible window, which is handled by `handler`.
+    pub(crate) fn window(&self, handler: impl WinHandler + 'static) -> WindowHandle {
+        WindowBuilder::new((**self).clone())
+            .handler(Box::new(handler))
+            .with_visible(true)
+            .build()
+            .unwrap()
+    }
+
+    /// Run the application until it quits, or has nothing left to do.
+    pub(crate) fn run(mut self, handler: Option<Box<dyn AppHandler>>) {
+        self.app.take().unwrap().run(handler);
+    }
+}
+
+impl std::ops::Deref for TestApp {
+    type Target = Application;
+
+    fn deref(&self) -> &Application {
+        self.app.as_ref().unwrap()
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        // Running an application which has quit only closes its windows, and releases
+        // the globals which keep another one from being created
+        if let Some(app) = self.app.take() {
+            app.quit();
+            app.run(None);
+        }
+    }
+}
+
+/// A window handler which logs the callbacks it gets, for tests to check their order.
+pub(crate) struct Recorder {
+    handle: WindowHandle,
+    log: Rc<RefCell<Vec<String>>>,
+    /// Called with each entry as it is logged, for tests which act on the callbacks.
+    react: Box<dyn FnMut(&WindowHandle, &str)>,
+}
+
+impl Recorder {
+    pub(crate) fn new(log: &Rc<RefCell<Vec<String>>>) -> Recorder {
+        Recorder::reacting(log, |_, _| {})
+    }
+
+    pub(crate) fn reacting(
+        log: &Rc<RefCell<Vec<String>>>,
+        react: impl FnMut(&WindowHandle, &str) + 'static,
+    ) -> Recorder {
+        Recorder {
+            handle: WindowHandle::default(),
+            log: log.clone(),
+            react: Box::new(react),
+        }
+    }
+
+    fn record(&mut self, entry: String) {
+        self.log.borrow_mut().push(entry.clone());
+        (self.react)(&self.handle, &entry);
+    }
+}
+
+impl WinHandler for Recorder {
+    fn connect(&mut self, handle: &WindowHandle) {
+        self.handle = handle.clone();
+        self.record("connect".into());
+    }
+
+    fn prepare_paint(&mut self) {
+        self.record("prepare_paint".into());
+    }
+
+    fn paint(&mut self, _invalid: &Region) {
+        self.record("paint".into());
+    }
+
+    fn key_down(&mut self, event: KeyEvent) -> bool {
+        self.record(format!("key_down {}", event.key));
+        true
+    }
+
+    fn key_up(&mut self, event: KeyEvent) {
+        self.record(format!("key_up {}", event.key));
+    }
+
+    fn pointer_move(&mut self, event: &PointerEvent) {
+        self.record(format!("pointer_move {} {}", event.pos.x, event.pos.y));
+    }
+
+    fn pointer_down(&mut self, event: &PointerEvent) {
+        self.record(format!("pointer_down {} {}", event.pos.x, event.pos.y));
+    }
+
+    fn pointer_up(&mut self, event: &PointerEvent) {
+        self.record(format!("pointer_up {} {}", event.pos.x, event.pos.y));
+    }
+
+    fn timer(&mut self, token: TimerToken) {
+        self.record(format!("timer {}", token.into_raw()));
+    }
+
+    fn destroy(&mut self) {
+        self.record("destroy".into());
+    }
+
+    #[cfg(feature = "accesskit")]
+    fn accesskit_tree(&mut self) -> accesskit::TreeUpdate {
+        unreachable!()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A window handler with one text field, which is focused when the window is clicked.
+///
+/// It leaves every key but Enter to the field, and Enter submits the text.
+#[derive(Default)]
+pub(crate) struct TextBox {
+    handle: WindowHandle,
+    field: Option<TextFieldToken>,
+    pub(crate) text: Rc<RefCell<String>>,
+    pub(crate) submitted: Rc<RefCell<Vec<String>>>,
+}
+
+impl WinHandler for TextBox {
+    fn connect(&mut self, handle: &WindowHandle) {
+        self.handle = handle.clone();
+        self.field = Some(handle.add_text_field());
+    }
+
+    fn prepare_paint(&mut self) {}
+
+    fn paint(&mut self, _invalid: &Region) {}
+
+    fn key_down(&mut self, event: KeyEvent) -> bool {
+        if event.key != KbKey::Enter {
+            return false;
+        }
+        let text = self.text.borrow().clone();
+        self.submitted.borrow_mut().push(text);
+        true
+    }
+
+    fn pointer_down(&mut self, _event: &PointerEvent) {
+        self.handle.set_focused_text_field(self.field);
+    }
+
+    fn acquire_input_lock(
+        &mut self,
+        _token: TextFieldToken,
+        _mutable: bool,
+    ) -> Box<dyn InputHandler> {
+        Box::new(Field(self.text.clone()))
+    }
+
+    fn release_input_lock(&mut self, _token: TextFieldToken) {}
+
+    #[cfg(feature = "accesskit")]
+    fn accesskit_tree(&mut self) -> accesskit::TreeUpdate {
+        unreachable!()
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The text of a [`TextBox`], with the caret always at its end.
+struct Field(Rc<RefCell<String>>);
+
+impl InputHandler for Field {
+    fn selection(&self) -> Selection {
+        Selection::caret(self.len())
+    }
+
+    fn set_selection(&mut self, _: Selection) {}
+
+    fn composition_range(&self) -> Option<Range<usize>> {
+        None
+    }
+
+    fn set_composition_range(&mut self, _: Option<Range<usize>>) {}
+
+    fn is_char_boundary(&self, i: usize) -> bool {
+        self.0.borrow().is_char_boundary(i)
+    }
+
+    fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    fn slice(&self, range: Range<usize>) -> Cow<str> {
+        Cow::Owned(self.0.borrow()[range].to_owned())
+    }
+
+    fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        self.0.borrow_mut().replace_range(range, text);
+    }
+
+    fn hit_test_point(&self, _: Point) -> HitTestPoint {
+        HitTestPoint::default()
+    }
+
+    fn line_range(&self, _: usize, _: Affinity) -> Range<usize> {
+        0..self.len()
+    }
+
+    fn bounding_box(&self) -> Option<Rect> {
+        None
+    }
+
+    fn slice_bounding_box(&self, _: Range<usize>) -> Option<Rect> {
+        None
+    }
+
+    fn handle_action(&mut self, _: Action) {}
+}
@@ -0,0 +1,993 @@
+//! Windows which are never shown, but get the same callbacks as on other backends.
+
+use std::cell::{Cell, RefCell};
+use std::panic::Location;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle};
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
+
+use super::application::{AppState, Application, IdleAction, Queues};
+use super::menu::Menu;
+use crate::common_util::Counter;
+use crate::dialog::FileDialogOptions;
+use crate::error::Error;
+use crate::kurbo::{Insets, Point, Rect, Size};
+use crate::mouse::{Cursor, CursorDesc};
+use crate::platform::linux::LayerShellConfig;
+use crate::text::Event;
+use crate::window::{
+    FileDialogToken, FrameTimings, ImageBuf, MaximizeMode, ModalResponse, PixelFormat, PopupConfig,
+    StackingLevel, Theme, TimerToken, Visibility, WinHandler, WindowLevel, WindowState, WindowType,
+};
+use crate::{IdleToken, Region, Scalable, Scale, TextFieldToken};
+
+/// How often a window which keeps requesting animation frames is painted, on the virtual
+/// clock.
+const FRAME_INTERVAL: Duration = Duration::from_nanos(16_666_667);
+
+/// The size of windows which weren't given one.
+const DEFAULT_SIZE: Size = Size::new(800.0, 600.0);
+
+/// Returns `size`, made at least as large as `min_size`.
+fn clamp_size(size: Size, min_size: Size) -> Size {
+    size.clamp(min_size, Size::new(f64::INFINITY, f64::INFINITY))
+}
+
+pub(crate) struct Window {
+    pub(super) id: u64,
+    app: Weak<AppState>,
+    /// Taken when the window is destroyed.
+    handler: RefCell<Option<Box<dyn WinHandler>>>,
+    position: Cell<Point>,
+    size: Cell<Size>,
+    min_size: Cell<Size>,
+    scale: Cell<Scale>,
+    state: Cell<WindowState>,
+    visible: Cell<bool>,
+    /// Whether the handler was last told the window is visible.
+    reported_visible: Cell<bool>,
+    resized: Cell<bool>,
+    rescaled: Cell<bool>,
+    invalid: RefCell<Region>,
+    next_frame: Cell<Option<Instant>>,
+    last_frame: Cell<Option<Instant>>,
+    timers: RefCell<Vec<(Instant, TimerToken)>>,
+    /// The last pixels which were presented, in the RGBA layout.
+    presented: RefCell<Option<ImageBuf>>,
+    focused_text_field: Cell<Option<TextFieldToken>>,
+    vsync: Cell<bool>,
+    closing: Cell<bool>,
+    /// Set when the handler is given `destroy`, after which the handles do nothing.
+    destroyed: Cell<bool>,
+    /// Set while this window is shown as a sheet.
+    sheet_completion: RefCell<Option<Box<dyn FnOnce(ModalResponse)>>>,
+    /// The last window shown as a sheet of this one, which is ended before this one closes if
+    /// it is still shown.
+    sheet: RefCell<Weak<Window>>,
+}
+
+impl Window {
+    #[track_caller]
+    pub(super) fn with_handler<R>(&self, f: impl FnOnce(&mut dyn WinHandler) -> R) -> Option<R> {
+        match self.handler.try_borrow_mut() {
+            Ok(mut handler) => handler.as_mut().map(|handler| f(&mut **handler)),
+            Err(_) => {
+                tracing::error!("failed to borrow WinHandler at {}", Location::caller());
+                None
+            }
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.app
+            .upgrade()
+            .map_or_else(Instant::now, |app| app.now())
+    }
+
+    /// The size of the surface in pixels, rounded like the buffers of the other backends.
+    fn size_px(&self) -> Size {
+        self.size.get().to_px(self.scale.get()).round()
+    }
+
+    pub(super) fn is_closing(&self) -> bool {
+        self.closing.get()
+    }
+
+    /// The window which is shown as a sheet of this one.
+    pub(super) fn shown_sheet(&self) -> Option<Rc<Window>> {
+        self.sheet
+            .borrow()
+            .upgrade()
+            .filter(|sheet| sheet.sheet_completion.borrow().is_some())
+    }
+
+    /// Returns `true` if there is anything to do for this window at `now`.
+    pub(super) fn has_work(&self, now: Instant) -> bool {
+        self.closing.get()
+            || self.resized.get()
+            || self.rescaled.get()
+            || self.visible.get() != self.reported_visible.get()
+            || self
+                .next_deadline()
+                .map_or(false, |deadline| deadline <= now)
+    }
+
+    /// The next time at which a timer fires or a frame is painted.
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        let timers = self.timers.borrow();
+        let timer = timers.iter().map(|(deadline, _)| *deadline).min();
+        match (timer, self.next_frame.get()) {
+            (Some(timer), Some(frame)) => Some(timer.min(frame)),
+            (timer, frame) => timer.or(frame),
+        }
+    }
+
+    pub(super) fn take_due_timers(&self, now: Instant) -> Vec<(Instant, TimerToken)> {
+        let mut timers = self.timers.borrow_mut();
+        let (due, pending) = timers.drain(..).partition(|(deadline, _)| *deadline <= now);
+        *timers = pending;
+        due
+    }
+
+    /// Tell the handler what changed, and paint if a frame is due.
+    pub(super) fn update(&self, now: Instant) {
+        let visible = self.visible.get();
+        if visible != self.reported_visible.replace(visible) {
+            let visibility = if visible {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+            self.with_handler(|handler| handler.visibility_changed(visibility));
+        }
+        if self.rescaled.take() {
+            let scale = self.scale.get();
+            self.with_handler(|handler| handler.scale(scale));
+            self.resized.set(true);
+        }
+        if self.resized.take() {
+            let size = self.size.get();
+            let size_px = self.size_px();
+            self.with_handler(|handler| {
+                handler.size(size);
+                handler.surface_resized(size_px);
+            });
+        }
+        if self.next_frame.get().map_or(false, |frame| frame <= now) {
+            self.next_frame.set(None);
+            self.last_frame.set(Some(now));
+            self.with_handler(|handler| handler.prepare_paint());
+            let invalid = std::mem::replace(&mut *self.invalid.borrow_mut(), Region::EMPTY);
+            if !invalid.is_empty() {
+                self.with_handler(|handler| handler.paint(&invalid));
+            }
+        }
+    }
+
+    /// Paint at the next frame, which is at least [`FRAME_INTERVAL`] after the last one.
+    fn request_frame(&self) {
+        if !self.visible.get() || self.next_frame.get().is_some() {
+            return;
+        }
+        let now = self.now();
+        let frame = self
+            .last_frame
+            .get()
+            .map_or(now, |last| now.max(last + FRAME_INTERVAL));
+        self.next_frame.set(Some(frame));
+    }
+
+    pub(super) fn destroy(&self) {
+        self.closing.set(true);
+        // A sheet which is closed is ended first
+        let completion = self.sheet_completion.borrow_mut().take();
+        if let Some(completion) = completion {
+            completion(ModalResponse::Cancel);
+        }
+        self.destroyed.set(true);
+        let handler = self.handler.borrow_mut().take();
+        if let Some(mut handler) = handler {
+            handler.destroy();
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct WindowHandle {
+    window: Weak<Window>,
+}
+
+impl WindowHandle {
+    /// The window, unless it has been destroyed, in which case calls do nothing.
+    fn window(&self) -> Option<Rc<Window>> {
+        let window = self
+            .window
+            .upgrade()
+            .filter(|window| !window.destroyed.get());
+        if window.is_none() {
+            tracing::debug!("the window has already been closed");
+        }
+        window
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.window
+            .upgrade()
+            .map_or(false, |window| !window.destroyed.get())
+    }
+
+    pub fn show(&self) {
+        if let Some(window) = self.window() {
+            window.visible.set(true);
+            self.invalidate();
+        }
+    }
+
+    pub fn hide(&self) {
+        if let Some(window) = self.window() {
+            window.visible.set(false);
+            window.next_frame.set(None);
+        }
+    }
+
+    pub fn close(&self) {
+        if let Some(window) = self.window() {
+            window.closing.set(true);
+        }
+    }
+
+    pub fn set_skip_taskbar(&self, _skip: bool) {}
+
+    pub fn set_window_level(&self, _level: StackingLevel) {}
+
+    pub fn set_titlebar_theme(&self, _theme: Option<Theme>) {}
+
+    pub fn begin_sheet(&self, sheet: &WindowHandle, completion: Box<dyn FnOnce(ModalResponse)>) {
+        let (Some(parent), Some(window)) = (self.window(), sheet.window()) else {
+            return;
+        };
+        *parent.sheet.borrow_mut() = Rc::downgrade(&window);
+        *window.sheet_completion.borrow_mut() = Some(completion);
+        sheet.show();
+    }
+
+    pub fn end_sheet(&self, response: ModalResponse) {
+        let Some(window) = self.window() else {
+            return;
+        };
+        let completion = window.sheet_completion.borrow_mut().take();
+        if let Some(completion) = completion {
+            self.hide();
+            completion(response);
+        }
+    }
+
+    pub fn run_modal(&self) -> ModalResponse {
+        tracing::warn!("run_modal is unimplemented on the headless backend");
+        ModalResponse::Cancel
+    }
+
+    pub fn end_modal(&self, _response: ModalResponse) {}
+
+    pub fn focus_next_window(&self) {}
+
+    pub fn resizable(&self, _resizable: bool) {}
+
+    pub fn set_aspect_ratio(&self, _ratio: Option<f64>) {}
+
+    pub fn set_resize_increments(&self, _increments: Option<Size>) {}
+
+    pub fn show_titlebar(&self, _show_titlebar: bool) {}
+
+    pub fn set_position(&self, position: Point) {
+        if let Some(window) = self.window() {
+            window.position.set(position);
+        }
+    }
+
+    pub fn get_position(&self) -> Point {
+        self.window()
+            .map_or(Point::ZERO, |window| window.position.get())
+    }
+
+    pub fn content_insets(&self) -> Insets {
+        Insets::ZERO
+    }
+
+    pub fn set_size(&self, size: Size) {
+        let Some(window) = self.window() else {
+            return;
+        };
+        let size = clamp_size(size, window.min_size.get());
+        if window.size.replace(size) != size {
+            window.resized.set(true);
+            self.invalidate();
+        }
+    }
+
+    pub fn get_size(&self) -> Size {
+        self.window().map_or(Size::ZERO, |window| window.size.get())
+    }
+
+    pub fn set_window_state(&mut self, state: WindowState) {
+        if let Some(window) = self.window() {
+            window.state.set(state);
+        }
+    }
+
+    pub fn get_window_state(&self) -> WindowState {
+        self.window()
+            .map_or(WindowState::Restored, |window| window.state.get())
+    }
+
+    pub fn set_maximize_mode(&self, _mode: MaximizeMode) {}
+
+    pub fn handle_titlebar(&self, _val: bool) {}
+
+    pub fn bring_to_front_and_focus(&self) {}
+
+    pub fn request_anim_frame(&self) {
+        if let Some(window) = self.window() {
+            window.request_frame();
+        }
+    }
+
+    pub fn invalidate(&self) {
+        if let Some(window) = self.window() {
+            let rect = window.size.get().to_rect();
+            window.invalid.borrow_mut().set_rect(rect);
+            window.request_frame();
+        }
+    }
+
+    pub fn invalidate_rect(&self, rect: Rect) {
+        if let Some(window) = self.window() {
+            window.invalid.borrow_mut().add_rect(rect);
+            window.request_frame();
+        }
+    }
+
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), Error> {
+        let window = self.window().ok_or(Error::WindowDropped)?;
+        let size_px = window.size_px();
+        if (width as f64, height as f64) != (size_px.width, size_px.height) {
+            return Err(anyhow::anyhow!(
+                "pixel buffer is {width}x{height}, but the window is {}x{}",
+                size_px.width,
+                size_px.height
+            )
+            .into());
+        }
+        if buf.len() != width as usize * height as usize * PixelFormat::BYTES_PER_PIXEL {
+            return Err(
+                anyhow::anyhow!("wrong number of pixels for a {width}x{height} buffer").into(),
+            );
+        }
+        let mut pixels = PixelFormat::Rgba8.from_bgra(format.to_bgra(buf).into_owned());
+        // Presented pixels are always opaque
+        for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+            px[3] = 0xff;
+        }
+        let image = ImageBuf::new(width, height, pixels).with_scale(window.scale.get());
+        *window.presented.borrow_mut() = Some(image);
+        Ok(())
+    }
+
+    pub fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, Error> {
+        let image = self
+            .capture()
+            .ok_or_else(|| anyhow::anyhow!("nothing has been presented to the window"))?;
+        let bgra = PixelFormat::Rgba8.to_bgra(image.pixels()).into_owned();
+        Ok(format.from_bgra(bgra))
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        self.window()?.presented.borrow().clone()
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        if let Some(window) = self.window() {
+            window.vsync.set(enabled);
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.window().map_or(true, |window| window.vsync.get())
+    }
+
+    pub fn set_title(&self, _title: &str) {}
+
+    pub fn set_menu(&self, _menu: Menu) {}
+
+    pub fn show_context_menu(&self, _menu: Menu, _pos: Point) {}
+
+    pub fn add_text_field(&self) -> TextFieldToken {
+        TextFieldToken::next()
+    }
+
+    pub fn remove_text_field(&self, token: TextFieldToken) {
+        if let Some(window) = self.window() {
+            if window.focused_text_field.get() == Some(token) {
+                window.focused_text_field.set(None);
+            }
+        }
+    }
+
+    pub fn set_focused_text_field(&self, active_field: Option<TextFieldToken>) {
+        if let Some(window) = self.window() {
+            window.focused_text_field.set(active_field);
+        }
+    }
+
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.window()?.focused_text_field.get()
+    }
+
+    pub fn update_text_field(&self, _token: TextFieldToken, _update: Event) {
+        // There is no input method to tell
+    }
+
+    pub fn show_character_palette(&self) {}
+
+    pub fn request_timer(&self, deadline: Instant) -> TimerToken {
+        let token = TimerToken::next();
+        if let Some(window) = self.window() {
+            // The deadline is on the real clock, so it is moved to the virtual one. Rounding
+            // up to whole milliseconds leaves out the time it took to get here, so that timers
+            // requested with the same delay are due at the same time.
+            let nanos = deadline
+                .saturating_duration_since(Instant::now())
+                .as_nanos();
+            let delay = Duration::from_millis(((nanos + 999_999) / 1_000_000) as u64);
+            let deadline = window.now() + delay;
+            window.timers.borrow_mut().push((deadline, token));
+        }
+        token
+    }
+
+    pub fn set_cursor(&mut self, _cursor: &Cursor) {}
+
+    pub fn make_cursor(&self, _desc: &CursorDesc) -> Option<Cursor> {
+        None
+    }
+
+    pub fn open_file(&mut self, _options: FileDialogOptions) -> Option<FileDialogToken> {
+        tracing::warn!("file dialogs can't be shown on the headless backend");
+        None
+    }
+
+    pub fn save_as(&mut self, _options: FileDialogOptions) -> Option<FileDialogToken> {
+        tracing::warn!("file dialogs can't be shown on the headless backend");
+        None
+    }
+
+    pub fn get_idle_handle(&self) -> Option<IdleHandle> {
+        let window = self.window()?;
+        Some(IdleHandle {
+            queues: window.app.upgrade()?.queues.clone(),
+            window: window.id,
+        })
+    }
+
+    pub fn get_scale(&self) -> Result<Scale, Error> {
+        let window = self.window().ok_or(Error::WindowDropped)?;
+        Ok(window.scale.get())
+    }
+
+    /// Set the scale of the window, which is 1 unless it is overridden.
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        if let Some(window) = self.window() {
+            let scale = scale.unwrap_or(1.0);
+            if window.scale.replace(Scale::new(scale, scale)) != Scale::new(scale, scale) {
+                window.rescaled.set(true);
+                self.invalidate();
+            }
+        }
+    }
+
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        FrameTimings::default()
+    }
+
+    pub fn set_present_feedback(&self, _enabled: bool) {}
+
+    #[cfg(feature = "accesskit")]
+    pub fn update_accesskit_if_active(
+        &self,
+        _update_factory: impl FnOnce() -> accesskit::TreeUpdate,
+    ) {
+    }
+}
+
+impl PartialEq for WindowHandle {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.window.ptr_eq(&rhs.window)
+    }
+}
+
+impl Eq for WindowHandle {}
+
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
+
+// raw-window-handle 0.5 has no way to report that there is no native window, so these are
+// empty Xlib handles, which renderers fail to create a surface for.
+#[cfg(feature = "rwh_05")]
+unsafe impl HasRawWindowHandle for WindowHandle {
+    fn raw_window_handle(&self) -> rwh_05::RawWindowHandle {
+        rwh_05::RawWindowHandle::Xlib(rwh_05::XlibWindowHandle::empty())
+    }
+}
+
+#[cfg(feature = "rwh_05")]
+unsafe impl HasRawDisplayHandle for WindowHandle {
+    fn raw_display_handle(&self) -> rwh_05::RawDisplayHandle {
+        rwh_05::RawDisplayHandle::Xlib(rwh_05::XlibDisplayHandle::empty())
+    }
+}
+
+#[derive(Clone)]
+pub struct IdleHandle {
+    queues: Arc<Mutex<Queues>>,
+    window: u64,
+}
+
+impl IdleHandle {
+    pub fn add_idle_callback<F>(&self, callback: F)
+    where
+        F: FnOnce(&mut dyn WinHandler) + Send + 'static,
+    {
+        let action = IdleAction::Callback(Box::new(callback));
+        self.queues
+            .lock()
+            .unwrap()
+            .idle
+            .push_back((self.window, action));
+    }
+
+    pub fn add_idle_token(&self, token: IdleToken) {
+        let action = IdleAction::Token(token);
+        self.queues
+            .lock()
+            .unwrap()
+            .idle
+            .push_back((self.window, action));
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct CustomCursor;
+
+pub(crate) struct WindowBuilder {
+    app: Application,
+    handler: Option<Box<dyn WinHandler>>,
+    position: Option<Point>,
+    size: Option<Size>,
+    min_size: Option<Size>,
+    state: Option<WindowState>,
+    visible: bool,
+}
+
+impl WindowBuilder {
+    pub fn new(app: Application) -> WindowBuilder {
+        WindowBuilder {
+            app,
+            handler: None,
+            position: None,
+            size: None,
+            min_size: None,
+            state: None,
+            visible: false,
+        }
+    }
+
+    pub fn handler(mut self, handler: Box<dyn WinHandler>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    pub fn size(mut self, size: Size) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn min_size(mut self, size: Size) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    pub fn resizable(self, _resizable: bool) -> Self {
+        self
+    }
+
+    pub fn show_titlebar(self, _show_titlebar: bool) -> Self {
+        self
+    }
+
+    pub fn layer_shell(self, _config: LayerShellConfig) -> Self {
+        self
+    }
+
+    pub fn prefer_client_side_decorations(self, _prefer: bool) -> Self {
+        self
+    }
+
+    pub fn transparent(self, _transparent: bool) -> Self {
+        self
+    }
+
+    pub fn position(mut self, position: Point) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn level(self, _level: WindowLevel) -> Self {
+        self
+    }
+
+    pub fn with_window_type(self, _window_type: WindowType) -> Self {
+        self
+    }
+
+    pub fn with_frame_autosave_name(self, _name: &str, _fullscreen: bool) -> Self {
+        self
+    }
+
+    pub fn title(self, _title: impl Into<String>) -> Self {
+        self
+    }
+
+    pub fn menu(self, _menu: Menu) -> Self {
+        self
+    }
+
+    pub fn window_state(mut self, state: WindowState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn maximize_mode(self, _mode: MaximizeMode) -> Self {
+        self
+    }
+
+    pub fn kiosk(self, _kiosk: bool) -> Self {
+        self
+    }
+
+    pub fn popup(self, _popup: PopupConfig) -> Self {
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_skip_taskbar(self, _skip: bool) -> Self {
+        self
+    }
+
+    pub fn with_managed_surface(self, _managed: bool) -> Self {
+        self
+    }
+
+    pub fn with_accepts_focus(self, _accepts_focus: bool) -> Self {
+        self
+    }
+
+    pub fn with_parent(self, _parent: crate::WindowHandle) -> Self {
+        self
+    }
+
+    pub fn offscreen(self, _offscreen: bool) -> Self {
+        self
+    }
+
+    pub fn build_as_child(
+        self,
+        _parent: raw_window_handle::RawWindowHandle,
+    ) -> Result<WindowHandle, Error> {
+        Err(Error::Unsupported("child windows"))
+    }
+
+    pub fn build(self) -> Result<WindowHandle, Error> {
+        static WINDOW_COUNTER: Counter = Counter::new();
+
+        let handler = self
+            .handler
+            .ok_or_else(|| anyhow::anyhow!("a window can't be built without a handler"))?;
+        let min_size = self.min_size.unwrap_or(Size::ZERO);
+        let size = clamp_size(self.size.unwrap_or(DEFAULT_SIZE), min_size);
+        let scale = Scale::new(1.0, 1.0);
+        let window = Rc::new(Window {
+            id: WINDOW_COUNTER.next(),
+            app: Rc::downgrade(&self.app.state),
+            handler: RefCell::new(Some(handler)),
+            position: Cell::new(self.position.unwrap_or(Point::ZERO)),
+            size: Cell::new(size),
+            min_size: Cell::new(min_size),
+            scale: Cell::new(scale),
+            state: Cell::new(self.state.unwrap_or(WindowState::Restored)),
+            visible: Cell::new(false),
+            reported_visible: Cell::new(false),
+            resized: Cell::new(false),
+            rescaled: Cell::new(false),
+            invalid: RefCell::new(Region::EMPTY),
+            next_frame: Cell::new(None),
+            last_frame: Cell::new(None),
+            timers: RefCell::new(Vec::new()),
+            presented: RefCell::new(None),
+            focused_text_field: Cell::new(None),
+            vsync: Cell::new(true),
+            closing: Cell::new(false),
+            destroyed: Cell::new(false),
+            sheet_completion: RefCell::new(None),
+            sheet: RefCell::new(Weak::new()),
+        });
+        self.app.state.windows.borrow_mut().push(window.clone());
+
+        let handle = WindowHandle {
+            window: Rc::downgrade(&window),
+        };
+        let size_px = window.size_px();
+        window.with_handler(|handler| {
+            handler.connect(&handle.clone().into());
+            handler.scale(scale);
+            handler.size(size);
+            handler.surface_resized(size_px);
+            handler.visibility_changed(Visibility::Hidden);
+        });
+        if self.visible {
+            handle.show();
+        }
+        Ok(handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::super::test_util::{Recorder, TestApp};
+    use super::FRAME_INTERVAL;
+    use crate::kurbo::Size;
+    use crate::platform::headless::ApplicationExt;
+    use crate::{Application, ModalResponse, PixelFormat, Scale, WindowBuilder, WindowState};
+
+    #[test]
+    fn animation_frames_are_paced() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::reacting(&log, {
+            let frames = frames.clone();
+            move |handle, entry| {
+                if entry == "prepare_paint" {
+                    let mut frames = frames.borrow_mut();
+                    frames.push(Application::global().now());
+                    if frames.len() < 4 {
+                        handle.request_anim_frame();
+                    }
+                }
+            }
+        }));
+        for _ in 0..4 {
+            app.pump_events(None);
+        }
+        let intervals = frames
+            .borrow()
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+        assert_eq!(intervals, [FRAME_INTERVAL; 3]);
+
+        // A frame which is requested long after the last one isn't delayed
+        app.advance_clock(Duration::from_secs(1));
+        window.request_anim_frame();
+        app.pump_events(Some(Duration::ZERO));
+        assert_eq!(frames.borrow().last(), Some(&app.now()));
+    }
+
+    #[test]
+    fn presented_pixels_are_captured() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::new(&log));
+        window.set_size(Size::new(2.0, 1.0));
+        assert_eq!(window.capture(), None);
+        assert!(window.read_back_pixels(PixelFormat::Rgba8).is_err());
+
+        window
+            .present_pixels(&[1, 2, 3, 4, 5, 6, 7, 8], 2, 1, PixelFormat::Rgba8)
+            .unwrap();
+        let image = window.capture().unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        // Presented pixels are opaque
+        assert_eq!(image.pixels(), [1, 2, 3, 255, 5, 6, 7, 255]);
+        assert_eq!(
+            window.read_back_pixels(PixelFormat::Rgba8).unwrap(),
+            image.pixels()
+        );
+        assert_eq!(
+            window.read_back_pixels(PixelFormat::Bgra8).unwrap(),
+            [3, 2, 1, 255, 7, 6, 5, 255]
+        );
+
+        // Buffers of another size are rejected
+        assert!(window
+            .present_pixels(&[0; 4], 1, 1, PixelFormat::Rgba8)
+            .is_err());
+        assert_eq!(window.capture(), Some(image));
+    }
+
+    #[test]
+    fn fractional_scales_take_rounded_buffers() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::new(&log));
+        window.set_size(Size::new(3.0, 1.0));
+        window.set_backing_scale_override(Some(1.5));
+        // 4.5 by 1.5 pixels
+        let pixels = vec![0; 5 * 2 * PixelFormat::BYTES_PER_PIXEL];
+        window
+            .present_pixels(&pixels, 5, 2, PixelFormat::Rgba8)
+            .unwrap();
+        let image = window.capture().unwrap();
+        assert_eq!((image.width(), image.height()), (5, 2));
+        assert_eq!(image.scale(), Scale::new(1.5, 1.5));
+    }
+
+    #[test]
+    fn windows_are_destroyed_once() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::reacting(&log, |handle, entry| {
+            if entry == "destroy" {
+                handle.close();
+            }
+        }));
+        let other_log = Rc::new(RefCell::new(Vec::new()));
+        let other = app.window(Recorder::new(&other_log));
+
+        window.close();
+        window.close();
+        app.pump_events(Some(Duration::ZERO));
+        assert!(!window.is_alive());
+        assert!(other.is_alive());
+        // Closing as the application quits, which destroys the windows which are left
+        other.close();
+        drop(app);
+        assert!(!other.is_alive());
+
+        for log in [log, other_log] {
+            let destroyed = log.borrow().iter().filter(|it| *it == "destroy").count();
+            assert_eq!(destroyed, 1);
+        }
+    }
+
+    #[test]
+    fn handles_do_nothing_from_destroy() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let checks = Rc::new(RefCell::new(Vec::new()));
+        let window = app.window(Recorder::reacting(&log, {
+            let checks = checks.clone();
+            move |handle, entry| {
+                if entry != "destroy" {
+                    return;
+                }
+                // Failing here would abort, so the results are checked after the test
+                let weak = handle.downgrade();
+                let mut handle = handle.clone();
+                handle.set_title("closed");
+                handle.set_size(Size::new(10.0, 10.0));
+                handle.set_window_state(WindowState::Maximized);
+                handle.invalidate();
+                handle.request_anim_frame();
+                handle.request_timer(Duration::ZERO);
+                handle.show();
+                handle.close();
+                let present = handle.present_pixels(&[0; 4], 1, 1, PixelFormat::Rgba8);
+                checks.borrow_mut().extend([
+                    ("is_alive", !handle.is_alive()),
+                    ("upgrade", weak.upgrade().is_none()),
+                    ("get_size", handle.get_size() == Size::ZERO),
+                    ("get_idle_handle", handle.get_idle_handle().is_none()),
+                    ("present_pixels", present.is_err()),
+                    ("capture", handle.capture().is_none()),
+                ]);
+            }
+        }));
+        let weak = window.downgrade();
+        assert!(weak.upgrade() == Some(window.clone()));
+
+        window.close();
+        app.pump_events(Some(Duration::ZERO));
+        assert!(weak.upgrade().is_none());
+        drop(app);
+
+        // Nothing which was called from destroy reached the handler again
+        assert_eq!(log.borrow().last().map(String::as_str), Some("destroy"));
+        let destroyed = log.borrow().iter().filter(|it| *it == "destroy").count();
+        assert_eq!(destroyed, 1);
+        let checks = checks.borrow();
+        assert_eq!(checks.len(), 6);
+        for &(check, passed) in checks.iter() {
+            assert!(passed, "{check} after destroy");
+        }
+    }
+
+    #[test]
+    fn closing_the_parent_ends_its_sheet_first() {
+        let app = TestApp::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorder = |name: &'static str| {
+            let events = events.clone();
+            Recorder::reacting(&Rc::new(RefCell::new(Vec::new())), move |_, entry| {
+                if entry == "destroy" {
+                    events.borrow_mut().push(format!("{name} destroyed"));
+                }
+            })
+        };
+        let parent = app.window(recorder("parent"));
+        let builder = WindowBuilder::new((*app).clone()).handler(Box::new(recorder("sheet")));
+        let completion_events = events.clone();
+        let sheet = parent
+            .present_modal_child(builder, move |response| {
+                completion_events
+                    .borrow_mut()
+                    .push(format!("completed {response:?}"));
+            })
+            .unwrap();
+        app.pump_events(Some(Duration::ZERO));
+        assert!(events.borrow().is_empty());
+
+        parent.close();
+        app.pump_events(Some(Duration::ZERO));
+        assert_eq!(
+            *events.borrow(),
+            ["completed Cancel", "sheet destroyed", "parent destroyed"]
+        );
+        assert!(!sheet.is_alive());
+    }
+
+    #[test]
+    fn ended_sheets_stay_open() {
+        let app = TestApp::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let parent = app.window(Recorder::new(&log));
+        let sheet_log = Rc::new(RefCell::new(Vec::new()));
+        let sheet = app.window(Recorder::new(&sheet_log));
+        let response = Rc::new(Cell::new(None));
+        let completed = response.clone();
+        parent.begin_sheet(sheet.clone(), move |response| completed.set(Some(response)));
+        sheet.end_sheet(ModalResponse::Ok);
+        assert_eq!(response.get(), Some(ModalResponse::Ok));
+
+        parent.close();
+        app.pump_events(Some(Duration::ZERO));
+        assert!(sheet.is_alive());
+        assert!(!sheet_log.borrow().contains(&"destroy".to_string()));
+    }
+}
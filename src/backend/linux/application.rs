@@ -1,32 +1,82 @@
+#[cfg(feature = "headless")]
+use crate::backend::headless;
+#[cfg(any(feature = "x11", feature = "wayland"))]
+use crate::backend::shared::linux;
 #[cfg(feature = "wayland")]
 use crate::backend::wayland;
 #[cfg(feature = "x11")]
 use crate::backend::x11;
-use crate::AppHandler;
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "headless")]
+use std::time::Instant;
+
+use crate::{
+    A11yPrefs, AppHandler, Backend, Color, Error, LoopMode, LoopStatus, Notification,
+    NotificationId, PowerState, SystemColor, SystemFonts, TextRenderingPrefs, UiDirection,
+    UiMetrics, WakeLockKind,
+};
 
 use super::clipboard::Clipboard;
 
+#[cfg(any(feature = "x11", feature = "wayland"))]
+pub(crate) use crate::backend::shared::linux::sleep::SleepGuard;
+#[cfg(not(any(feature = "x11", feature = "wayland")))]
+#[derive(Default)]
+pub(crate) struct SleepGuard;
+
 #[derive(Clone)]
 pub(crate) enum Application {
     #[cfg(feature = "x11")]
     X11(x11::application::Application),
     #[cfg(feature = "wayland")]
     Wayland(wayland::application::Application),
+    #[cfg(feature = "headless")]
+    Headless(headless::application::Application),
 }
 
 impl Application {
     pub fn new() -> Result<Self, anyhow::Error> {
-        #[cfg(feature = "wayland")]
-        if let Ok(app) = wayland::application::Application::new() {
-            return Ok(Application::Wayland(app));
+        if let Some(backend) = backend_from_env() {
+            return Application::new_with_backend(backend);
         }
-
-        #[cfg(feature = "x11")]
-        if let Ok(app) = x11::application::Application::new() {
-            return Ok(Application::X11(app));
+        // The headless backend is only used when it's asked for, unless there is no other
+        if cfg!(not(any(feature = "x11", feature = "wayland"))) {
+            return Application::new_with_backend(Backend::Headless);
+        }
+        // Prefer Wayland in a Wayland session, but XWayland might still work if it fails
+        if cfg!(feature = "wayland")
+            && (std::env::var_os("WAYLAND_DISPLAY").is_some() || cfg!(not(feature = "x11")))
+        {
+            match Application::new_with_backend(Backend::Wayland) {
+                Ok(app) => return Ok(app),
+                Err(e) if cfg!(feature = "x11") => {
+                    tracing::warn!("failed to connect to wayland, falling back to x11: {e}");
+                }
+                Err(e) => return Err(e),
+            }
         }
+        Application::new_with_backend(Backend::X11)
+    }
 
-        Err(anyhow::anyhow!("can't create application"))
+    pub fn new_with_backend(backend: Backend) -> Result<Self, anyhow::Error> {
+        match backend {
+            #[cfg(feature = "x11")]
+            Backend::X11 => Ok(Application::X11(x11::application::Application::new()?)),
+            #[cfg(feature = "wayland")]
+            Backend::Wayland => Ok(Application::Wayland(
+                wayland::application::Application::new()?,
+            )),
+            #[cfg(feature = "headless")]
+            Backend::Headless => Ok(Application::Headless(
+                headless::application::Application::new(),
+            )),
+            // Only reachable when some backends are disabled
+            #[allow(unreachable_patterns)]
+            backend => Err(anyhow::anyhow!(
+                "the {backend:?} backend isn't available, as its feature isn't enabled"
+            )),
+        }
     }
 
     pub fn quit(&self) {
@@ -39,6 +89,21 @@ impl Application {
             Application::Wayland(app) => {
                 app.quit();
             }
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => {
+                app.quit();
+            }
+        }
+    }
+
+    pub fn set_app_id(&self, app_id: &str) {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(app) => app.set_app_id(app_id),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(app) => app.set_app_id(app_id),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.set_app_id(app_id),
         }
     }
 
@@ -48,6 +113,8 @@ impl Application {
             Application::X11(app) => Clipboard::X11(app.clipboard()),
             #[cfg(feature = "wayland")]
             Application::Wayland(app) => Clipboard::Wayland(app.clipboard()),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => Clipboard::Headless(app.clipboard()),
         }
     }
 
@@ -58,6 +125,32 @@ impl Application {
             Application::X11(_app) => x11::application::Application::get_locale(),
             #[cfg(feature = "wayland")]
             Application::Wayland(_app) => wayland::application::Application::get_locale(),
+            #[cfg(feature = "headless")]
+            Application::Headless(_app) => headless::application::Application::get_locale(),
+        }
+    }
+
+    pub fn get_locales() -> Vec<String> {
+        let app = crate::Application::try_global().unwrap();
+        match &app.backend_app {
+            #[cfg(feature = "x11")]
+            Application::X11(_app) => x11::application::Application::get_locales(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_app) => wayland::application::Application::get_locales(),
+            #[cfg(feature = "headless")]
+            Application::Headless(_app) => headless::application::Application::get_locales(),
+        }
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        let app = crate::Application::try_global().unwrap();
+        match &app.backend_app {
+            #[cfg(feature = "x11")]
+            Application::X11(_app) => x11::application::Application::ui_direction(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_app) => wayland::application::Application::ui_direction(),
+            #[cfg(feature = "headless")]
+            Application::Headless(_app) => headless::application::Application::ui_direction(),
         }
     }
 
@@ -71,14 +164,200 @@ impl Application {
             Application::Wayland(app) => {
                 app.run(handler);
             }
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => {
+                app.run(handler);
+            }
+        }
+    }
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(app) => app.set_handler(handler),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(app) => app.set_handler(handler),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.set_handler(handler),
         }
     }
+
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(app) => app.run_loop_iteration(mode),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(app) => app.run_loop_iteration(mode),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.run_loop_iteration(mode),
+        }
+    }
+
     pub fn get_handle(&self) -> Option<AppHandle> {
         match self {
             #[cfg(feature = "x11")]
             Application::X11(app) => app.get_handle().map(AppHandle::X11),
             #[cfg(feature = "wayland")]
             Application::Wayland(app) => app.get_handle().map(AppHandle::Wayland),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.get_handle().map(AppHandle::Headless),
+        }
+    }
+
+    pub fn power_state(&self) -> PowerState {
+        match self {
+            // This goes over DBus, so is the same for both windowing backends.
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::power::power_state(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::power::power_state(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.power_state(),
+        }
+    }
+
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        match self {
+            // As are the accessibility preferences, which come from the settings portal.
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::accessibility_preferences(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::accessibility_preferences(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.accessibility_preferences(),
+        }
+    }
+
+    pub fn system_fonts(&self) -> SystemFonts {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::system_fonts(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::system_fonts(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.system_fonts(),
+        }
+    }
+
+    pub fn text_scale_factor(&self) -> f64 {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::text_scale_factor(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::text_scale_factor(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.text_scale_factor(),
+        }
+    }
+
+    pub fn accent_color(&self) -> Option<Color> {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::accent_color(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::accent_color(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.accent_color(),
+        }
+    }
+
+    pub fn system_color(&self, color: SystemColor) -> Option<Color> {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => Some(linux::settings::system_color(color)),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => Some(linux::settings::system_color(color)),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.system_color(color),
+        }
+    }
+
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::text_rendering_prefs(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::text_rendering_prefs(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.text_rendering_prefs(),
+        }
+    }
+
+    pub fn ui_metrics(&self) -> UiMetrics {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::settings::ui_metrics(),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::settings::ui_metrics(),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.ui_metrics(),
+        }
+    }
+
+    pub fn acquire_wake_lock(&self, kind: WakeLockKind, reason: &str) -> Result<SleepGuard, Error> {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::sleep::acquire_wake_lock(kind, reason),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::sleep::acquire_wake_lock(kind, reason),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => Ok(app.acquire_wake_lock(kind, reason)),
+        }
+    }
+
+    pub fn add_recent_document(&self, _path: &Path) {
+        tracing::warn!("Application::add_recent_document is unimplemented on linux");
+    }
+
+    pub fn send_notification(
+        &self,
+        notification: &Notification,
+        id: NotificationId,
+    ) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => self.send_dbus_notification(notification, id),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => self.send_dbus_notification(notification, id),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.send_notification(notification, id),
+        }
+    }
+
+    /// X11 and Wayland both use the desktop's notification server.
+    #[cfg(any(feature = "x11", feature = "wayland"))]
+    fn send_dbus_notification(
+        &self,
+        notification: &Notification,
+        id: NotificationId,
+    ) -> Result<(), Error> {
+        use linux::notification::NotificationEvent;
+
+        let handle = self.get_handle().expect("the application has a handle");
+        linux::notification::send_notification(notification, id, move |event| {
+            handle.run_on_main(move |handler| {
+                let Some(handler) = handler else {
+                    return;
+                };
+                match event {
+                    NotificationEvent::Activated(id, action) => {
+                        handler.notification_activated(id, action)
+                    }
+                    NotificationEvent::Failed(id, error) => {
+                        handler.notification_failed(id, error.into())
+                    }
+                }
+            })
+        })
+    }
+
+    pub fn close_notification(&self, id: NotificationId) {
+        match self {
+            #[cfg(feature = "x11")]
+            Application::X11(_) => linux::notification::close_notification(id),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(_) => linux::notification::close_notification(id),
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => app.close_notification(id),
         }
     }
 }
@@ -89,6 +368,8 @@ pub(crate) enum AppHandle {
     X11(x11::application::AppHandle),
     #[cfg(feature = "wayland")]
     Wayland(wayland::application::AppHandle),
+    #[cfg(feature = "headless")]
+    Headless(headless::application::AppHandle),
 }
 
 impl AppHandle {
@@ -101,6 +382,8 @@ impl AppHandle {
             AppHandle::X11(app) => app.run_on_main(callback),
             #[cfg(feature = "wayland")]
             AppHandle::Wayland(app) => app.run_on_main(callback),
+            #[cfg(feature = "headless")]
+            AppHandle::Headless(app) => app.run_on_main(callback),
         }
     }
 }
@@ -112,6 +395,75 @@ impl crate::platform::linux::ApplicationExt for crate::Application {
             Application::X11(it) => crate::Clipboard(Clipboard::X11(it.primary.clone())),
             #[cfg(feature = "wayland")]
             Application::Wayland(_) => unimplemented!(),
+            #[cfg(feature = "headless")]
+            Application::Headless(it) => {
+                crate::Clipboard(Clipboard::Headless(it.primary_clipboard()))
+            }
+        }
+    }
+
+    fn add_event_source(
+        &self,
+        fd: std::os::unix::io::RawFd,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> crate::EventSourceToken {
+        match &self.backend_app {
+            #[cfg(feature = "x11")]
+            Application::X11(it) => it.add_event_source(fd, Box::new(callback)),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(it) => it.add_event_source(fd, Box::new(callback)),
+            #[cfg(feature = "headless")]
+            Application::Headless(it) => it.add_event_source(fd, Box::new(callback)),
+        }
+    }
+
+    fn remove_event_source(&self, token: crate::EventSourceToken) {
+        match &self.backend_app {
+            #[cfg(feature = "x11")]
+            Application::X11(it) => it.remove_event_source(token),
+            #[cfg(feature = "wayland")]
+            Application::Wayland(it) => it.remove_event_source(token),
+            #[cfg(feature = "headless")]
+            Application::Headless(it) => it.remove_event_source(token),
+        }
+    }
+}
+
+#[cfg(feature = "headless")]
+impl crate::platform::headless::ApplicationExt for crate::Application {
+    fn is_headless(&self) -> bool {
+        matches!(self.backend_app, Application::Headless(_))
+    }
+
+    fn now(&self) -> Instant {
+        match &self.backend_app {
+            Application::Headless(it) => it.now(),
+            #[cfg(any(feature = "x11", feature = "wayland"))]
+            _ => Instant::now(),
+        }
+    }
+
+    fn advance_clock(&self, duration: Duration) {
+        match &self.backend_app {
+            Application::Headless(it) => it.advance_clock(duration),
+            #[cfg(any(feature = "x11", feature = "wayland"))]
+            _ => tracing::warn!("only the headless backend has a virtual clock"),
+        }
+    }
+}
+
+/// The backend named by `GLAZIER_BACKEND`, if it's set.
+fn backend_from_env() -> Option<Backend> {
+    let name = std::env::var_os("GLAZIER_BACKEND")?;
+    match name.to_str()? {
+        "wayland" => Some(Backend::Wayland),
+        "x11" => Some(Backend::X11),
+        "headless" => Some(Backend::Headless),
+        _ => {
+            tracing::warn!(
+                "ignoring GLAZIER_BACKEND={name:?}, which isn't wayland, x11 or headless"
+            );
+            None
         }
     }
 }
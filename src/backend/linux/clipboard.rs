@@ -1,3 +1,5 @@
+#[cfg(feature = "headless")]
+use crate::backend::headless;
 #[cfg(feature = "wayland")]
 use crate::backend::wayland;
 #[cfg(feature = "x11")]
@@ -10,6 +12,8 @@ pub enum Clipboard {
     X11(x11::clipboard::Clipboard),
     #[cfg(feature = "wayland")]
     Wayland(wayland::clipboard::Clipboard),
+    #[cfg(feature = "headless")]
+    Headless(headless::clipboard::Clipboard),
 }
 
 impl Clipboard {
@@ -23,6 +27,10 @@ impl Clipboard {
             Clipboard::Wayland(clipboard) => {
                 clipboard.put_string(s);
             }
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => {
+                clipboard.put_string(s);
+            }
         }
     }
 
@@ -36,6 +44,10 @@ impl Clipboard {
             Clipboard::Wayland(clipboard) => {
                 clipboard.put_formats(formats);
             }
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => {
+                clipboard.put_formats(formats);
+            }
         }
     }
 
@@ -45,6 +57,30 @@ impl Clipboard {
             Clipboard::X11(clipboard) => clipboard.get_string(),
             #[cfg(feature = "wayland")]
             Clipboard::Wayland(clipboard) => clipboard.get_string(),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.get_string(),
+        }
+    }
+
+    pub fn put_primary(&mut self, s: impl AsRef<str>) {
+        match self {
+            #[cfg(feature = "x11")]
+            Clipboard::X11(clipboard) => clipboard.put_primary(s),
+            #[cfg(feature = "wayland")]
+            Clipboard::Wayland(clipboard) => clipboard.put_primary(s),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.put_primary(s),
+        }
+    }
+
+    pub fn get_primary(&self) -> Option<String> {
+        match self {
+            #[cfg(feature = "x11")]
+            Clipboard::X11(clipboard) => clipboard.get_primary(),
+            #[cfg(feature = "wayland")]
+            Clipboard::Wayland(clipboard) => clipboard.get_primary(),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.get_primary(),
         }
     }
 
@@ -54,6 +90,8 @@ impl Clipboard {
             Clipboard::X11(clipboard) => clipboard.preferred_format(formats),
             #[cfg(feature = "wayland")]
             Clipboard::Wayland(clipboard) => clipboard.preferred_format(formats),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.preferred_format(formats),
         }
     }
 
@@ -63,6 +101,8 @@ impl Clipboard {
             Clipboard::X11(clipboard) => clipboard.get_format(format),
             #[cfg(feature = "wayland")]
             Clipboard::Wayland(clipboard) => clipboard.get_format(format),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.get_format(format),
         }
     }
 
@@ -72,6 +112,8 @@ impl Clipboard {
             Clipboard::X11(clipboard) => clipboard.available_type_names(),
             #[cfg(feature = "wayland")]
             Clipboard::Wayland(clipboard) => clipboard.available_type_names(),
+            #[cfg(feature = "headless")]
+            Clipboard::Headless(clipboard) => clipboard.available_type_names(),
         }
     }
 }
@@ -1,3 +1,5 @@
+#[cfg(feature = "headless")]
+use crate::backend::headless;
 #[cfg(feature = "wayland")]
 use crate::backend::wayland;
 #[cfg(feature = "x11")]
@@ -9,6 +11,8 @@ pub enum Menu {
     X11(x11::menu::Menu),
     #[cfg(feature = "wayland")]
     Wayland(wayland::menu::Menu),
+    #[cfg(feature = "headless")]
+    Headless(headless::menu::Menu),
 }
 
 impl Menu {
@@ -21,6 +25,10 @@ impl Menu {
             super::application::Application::Wayland(_) => {
                 Self::Wayland(wayland::menu::Menu::new())
             }
+            #[cfg(feature = "headless")]
+            super::application::Application::Headless(_) => {
+                Self::Headless(headless::menu::Menu::new())
+            }
         }
     }
 
@@ -33,6 +41,10 @@ impl Menu {
             super::application::Application::Wayland(_) => {
                 Self::Wayland(wayland::menu::Menu::new_for_popup())
             }
+            #[cfg(feature = "headless")]
+            super::application::Application::Headless(_) => {
+                Self::Headless(headless::menu::Menu::new_for_popup())
+            }
         }
     }
 
@@ -46,6 +58,8 @@ impl Menu {
                     }
                     #[cfg(feature = "wayland")]
                     Menu::Wayland(_) => {}
+                    #[cfg(feature = "headless")]
+                    Menu::Headless(_) => {}
                 };
             }
             #[cfg(feature = "wayland")]
@@ -56,6 +70,20 @@ impl Menu {
                     Menu::Wayland(menu) => {
                         m.add_dropdown(menu, text, enabled);
                     }
+                    #[cfg(feature = "headless")]
+                    Menu::Headless(_) => {}
+                };
+            }
+            #[cfg(feature = "headless")]
+            Menu::Headless(m) => {
+                match menu {
+                    #[cfg(feature = "x11")]
+                    Menu::X11(_) => {}
+                    #[cfg(feature = "wayland")]
+                    Menu::Wayland(_) => {}
+                    Menu::Headless(menu) => {
+                        m.add_dropdown(menu, text, enabled);
+                    }
                 };
             }
         }
@@ -78,6 +106,10 @@ impl Menu {
             Menu::Wayland(menu) => {
                 menu.add_item(id, text, key, selected, enabled);
             }
+            #[cfg(feature = "headless")]
+            Menu::Headless(menu) => {
+                menu.add_item(id, text, key, selected, enabled);
+            }
         }
     }
 
@@ -91,6 +123,10 @@ impl Menu {
             Menu::Wayland(menu) => {
                 menu.add_separator();
             }
+            #[cfg(feature = "headless")]
+            Menu::Headless(menu) => {
+                menu.add_separator();
+            }
         }
     }
 }
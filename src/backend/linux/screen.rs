@@ -1,3 +1,5 @@
+#[cfg(feature = "headless")]
+use crate::backend::headless;
 #[cfg(feature = "wayland")]
 use crate::backend::wayland;
 #[cfg(feature = "x11")]
@@ -12,5 +14,7 @@ pub fn get_monitors() -> Vec<Monitor> {
         super::application::Application::X11(app) => x11::screen::get_monitors(app),
         #[cfg(feature = "wayland")]
         super::application::Application::Wayland(_) => wayland::screen::get_monitors(),
+        #[cfg(feature = "headless")]
+        super::application::Application::Headless(_) => headless::screen::get_monitors(),
     }
 }
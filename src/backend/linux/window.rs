@@ -1,18 +1,24 @@
 use kurbo::{Insets, Point, Rect, Size};
-use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
-};
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle};
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
 use std::time::Instant;
 
+#[cfg(feature = "headless")]
+use crate::backend::headless;
 #[cfg(feature = "wayland")]
 use crate::backend::wayland;
 #[cfg(feature = "x11")]
 use crate::backend::x11;
 use crate::{
-    text::Event, Cursor, CursorDesc, Error, FileDialogOptions, FileDialogToken, IdleToken, Scale,
-    TextFieldToken, TimerToken, WinHandler, WindowLevel, WindowState,
+    text::Event, Cursor, CursorDesc, Error, FileDialogOptions, FileDialogToken, FrameTimings,
+    IdleToken, ImageBuf, MaximizeMode, ModalResponse, PixelFormat, Scale, StackingLevel,
+    TextFieldToken, Theme, TimerToken, WinHandler, WindowLevel, WindowState, WindowType,
 };
 
+use crate::platform::linux::LayerShellConfig;
+use crate::window::PopupConfig;
+
 use super::{application::Application, menu::Menu};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -21,6 +27,8 @@ pub enum CustomCursor {
     X11(x11::window::CustomCursor),
     #[cfg(feature = "wayland")]
     Wayland(wayland::window::CustomCursor),
+    #[cfg(feature = "headless")]
+    Headless(headless::window::CustomCursor),
 }
 
 impl CustomCursor {
@@ -30,6 +38,8 @@ impl CustomCursor {
             CustomCursor::X11(it) => it,
             #[cfg(feature = "wayland")]
             CustomCursor::Wayland(_) => panic!("Must use an X11 custom cursor here"),
+            #[cfg(feature = "headless")]
+            CustomCursor::Headless(_) => panic!("Must use an X11 custom cursor here"),
         }
     }
 }
@@ -39,6 +49,8 @@ pub(crate) enum WindowBuilder {
     X11(x11::window::WindowBuilder),
     #[cfg(feature = "wayland")]
     Wayland(wayland::window::WindowBuilder),
+    #[cfg(feature = "headless")]
+    Headless(headless::window::WindowBuilder),
 }
 
 impl WindowBuilder {
@@ -50,6 +62,10 @@ impl WindowBuilder {
             Application::Wayland(app) => {
                 WindowBuilder::Wayland(wayland::window::WindowBuilder::new(app))
             }
+            #[cfg(feature = "headless")]
+            Application::Headless(app) => {
+                WindowBuilder::Headless(headless::window::WindowBuilder::new(app))
+            }
         }
     }
 
@@ -59,6 +75,8 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.handler(handler)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.handler(handler)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.handler(handler)),
         };
         self
     }
@@ -69,6 +87,8 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.size(size)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.size(size)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.size(size)),
         };
         self
     }
@@ -79,6 +99,8 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.min_size(size)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.min_size(size)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.min_size(size)),
         };
         self
     }
@@ -89,6 +111,10 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.resizable(resizable)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.resizable(resizable)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.resizable(resizable))
+            }
         };
         self
     }
@@ -101,6 +127,42 @@ impl WindowBuilder {
             WindowBuilder::Wayland(builder) => {
                 WindowBuilder::Wayland(builder.show_titlebar(show_titlebar))
             }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.show_titlebar(show_titlebar))
+            }
+        };
+        self
+    }
+
+    pub fn layer_shell(mut self, config: LayerShellConfig) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.layer_shell(config)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.layer_shell(config)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.layer_shell(config))
+            }
+        };
+        self
+    }
+
+    pub fn prefer_client_side_decorations(mut self, prefer: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.prefer_client_side_decorations(prefer))
+            }
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.prefer_client_side_decorations(prefer))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.prefer_client_side_decorations(prefer))
+            }
         };
         self
     }
@@ -113,6 +175,10 @@ impl WindowBuilder {
             WindowBuilder::Wayland(builder) => {
                 WindowBuilder::Wayland(builder.transparent(transparent))
             }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.transparent(transparent))
+            }
         };
         self
     }
@@ -123,6 +189,8 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.position(position)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.position(position)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.position(position)),
         };
         self
     }
@@ -133,6 +201,44 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.level(level)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.level(level)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.level(level)),
+        };
+        self
+    }
+
+    pub fn with_frame_autosave_name(mut self, name: &str, fullscreen: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.with_frame_autosave_name(name, fullscreen))
+            }
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_frame_autosave_name(name, fullscreen))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_frame_autosave_name(name, fullscreen))
+            }
+        };
+        self
+    }
+
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.with_window_type(window_type))
+            }
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_window_type(window_type))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_window_type(window_type))
+            }
         };
         self
     }
@@ -143,6 +249,8 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.title(title)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.title(title)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.title(title)),
         };
         self
     }
@@ -154,12 +262,24 @@ impl WindowBuilder {
                 super::menu::Menu::X11(menu) => WindowBuilder::X11(builder.menu(menu)),
                 #[cfg(feature = "wayland")]
                 super::menu::Menu::Wayland(_) => WindowBuilder::X11(builder),
+                #[cfg(feature = "headless")]
+                super::menu::Menu::Headless(_) => WindowBuilder::X11(builder),
             },
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => match menu {
                 #[cfg(feature = "x11")]
                 super::menu::Menu::X11(_) => WindowBuilder::Wayland(builder),
                 super::menu::Menu::Wayland(menu) => WindowBuilder::Wayland(builder.menu(menu)),
+                #[cfg(feature = "headless")]
+                super::menu::Menu::Headless(_) => WindowBuilder::Wayland(builder),
+            },
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => match menu {
+                #[cfg(feature = "x11")]
+                super::menu::Menu::X11(_) => WindowBuilder::Headless(builder),
+                #[cfg(feature = "wayland")]
+                super::menu::Menu::Wayland(_) => WindowBuilder::Headless(builder),
+                super::menu::Menu::Headless(menu) => WindowBuilder::Headless(builder.menu(menu)),
             },
         };
         self
@@ -171,10 +291,191 @@ impl WindowBuilder {
             WindowBuilder::X11(builder) => WindowBuilder::X11(builder.window_state(state)),
             #[cfg(feature = "wayland")]
             WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.window_state(state)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.window_state(state))
+            }
+        };
+        self
+    }
+
+    pub fn maximize_mode(mut self, mode: MaximizeMode) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.maximize_mode(mode)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.maximize_mode(mode)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.maximize_mode(mode))
+            }
+        };
+        self
+    }
+
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.kiosk(kiosk)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.kiosk(kiosk)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.kiosk(kiosk)),
+        };
+        self
+    }
+
+    pub fn popup(mut self, popup: PopupConfig) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.popup(popup)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.popup(popup)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder.popup(popup)),
+        };
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.with_visible(visible)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_visible(visible))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_visible(visible))
+            }
+        };
+        self
+    }
+
+    pub fn with_skip_taskbar(mut self, skip: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.with_skip_taskbar(skip)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_skip_taskbar(skip))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_skip_taskbar(skip))
+            }
+        };
+        self
+    }
+
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.with_managed_surface(managed))
+            }
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_managed_surface(managed))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_managed_surface(managed))
+            }
+        };
+        self
+    }
+
+    pub fn use_pointer_api(self, _enable: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_coalesced_pointer_events(mut self, coalesce: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.with_coalesced_pointer_events(coalesce))
+            }
+            // Ignored
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => WindowBuilder::Headless(builder),
+        };
+        self
+    }
+
+    pub fn with_accepts_focus(mut self, accepts_focus: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => {
+                WindowBuilder::X11(builder.with_accepts_focus(accepts_focus))
+            }
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => {
+                WindowBuilder::Wayland(builder.with_accepts_focus(accepts_focus))
+            }
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_accepts_focus(accepts_focus))
+            }
+        };
+        self
+    }
+
+    pub fn with_parent(mut self, parent: crate::WindowHandle) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.with_parent(parent)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.with_parent(parent)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.with_parent(parent))
+            }
+        };
+        self
+    }
+
+    pub fn offscreen(mut self, offscreen: bool) -> Self {
+        self = match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => WindowBuilder::X11(builder.offscreen(offscreen)),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => WindowBuilder::Wayland(builder.offscreen(offscreen)),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => {
+                WindowBuilder::Headless(builder.offscreen(offscreen))
+            }
         };
         self
     }
 
+    pub fn build_as_child(
+        self,
+        parent: raw_window_handle::RawWindowHandle,
+    ) -> Result<WindowHandle, Error> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowBuilder::X11(builder) => builder
+                .build_as_child(parent)
+                .map(WindowHandle::X11)
+                .map_err(Into::into),
+            #[cfg(feature = "wayland")]
+            WindowBuilder::Wayland(builder) => builder
+                .build_as_child(parent)
+                .map(WindowHandle::Wayland)
+                .map_err(Into::into),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => builder
+                .build_as_child(parent)
+                .map(WindowHandle::Headless)
+                .map_err(Into::into),
+        }
+    }
+
     pub fn build(self) -> Result<WindowHandle, Error> {
         match self {
             #[cfg(feature = "x11")]
@@ -186,6 +487,11 @@ impl WindowBuilder {
                 .build()
                 .map(WindowHandle::Wayland)
                 .map_err(Into::into),
+            #[cfg(feature = "headless")]
+            WindowBuilder::Headless(builder) => builder
+                .build()
+                .map(WindowHandle::Headless)
+                .map_err(Into::into),
         }
     }
 }
@@ -196,6 +502,8 @@ pub enum IdleHandle {
     X11(x11::window::IdleHandle),
     #[cfg(feature = "wayland")]
     Wayland(wayland::window::IdleHandle),
+    #[cfg(feature = "headless")]
+    Headless(headless::window::IdleHandle),
 }
 
 impl IdleHandle {
@@ -212,6 +520,10 @@ impl IdleHandle {
             IdleHandle::Wayland(idle) => {
                 idle.add_idle_callback(callback);
             }
+            #[cfg(feature = "headless")]
+            IdleHandle::Headless(idle) => {
+                idle.add_idle_callback(callback);
+            }
         }
     }
 
@@ -225,6 +537,10 @@ impl IdleHandle {
             IdleHandle::Wayland(idle) => {
                 idle.add_idle_token(token);
             }
+            #[cfg(feature = "headless")]
+            IdleHandle::Headless(idle) => {
+                idle.add_idle_token(token);
+            }
         }
     }
 }
@@ -235,6 +551,8 @@ pub(crate) enum WindowHandle {
     X11(x11::window::WindowHandle),
     #[cfg(feature = "wayland")]
     Wayland(wayland::window::WindowHandle),
+    #[cfg(feature = "headless")]
+    Headless(headless::window::WindowHandle),
     None,
 }
 
@@ -249,6 +567,12 @@ impl From<wayland::window::WindowHandle> for crate::WindowHandle {
         Self(WindowHandle::Wayland(value))
     }
 }
+#[cfg(feature = "headless")]
+impl From<headless::window::WindowHandle> for crate::WindowHandle {
+    fn from(value: headless::window::WindowHandle) -> Self {
+        Self(WindowHandle::Headless(value))
+    }
+}
 
 #[cfg(feature = "x11")]
 impl From<x11::window::WindowHandle> for crate::WindowHandle {
@@ -260,12 +584,23 @@ impl From<x11::window::WindowHandle> for crate::WindowHandle {
 impl WindowHandle {
     // #[cfg(feature = "wayland")]
     // /// Assume that this WindowHandle is from Wayland
-    // pub(crate) fn unwrap_wayland(&self) -> &wayland::window::WindowHandle {
-    //     match self {
-    //         WindowHandle::Wayland(it) => it,
-    //         _ => unreachable!("Must use a wayland window handle"),
-    //     }
-    // }
+    #[cfg(feature = "wayland")]
+    /// Assume that this WindowHandle is from Wayland
+    pub(crate) fn unwrap_wayland(&self) -> &wayland::window::WindowHandle {
+        match self {
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(it) => it,
+            _ => unreachable!("Must use a wayland window handle"),
+        }
+    }
+    #[cfg(feature = "headless")]
+    /// Assume that this WindowHandle is from the headless backend
+    pub(crate) fn unwrap_headless(&self) -> &headless::window::WindowHandle {
+        match self {
+            WindowHandle::Headless(it) => it,
+            _ => unreachable!("Must use a headless window handle"),
+        }
+    }
     #[cfg(feature = "x11")]
     /// Assume that this WindowHandle is from X11
     pub(crate) fn unwrap_x11(&self) -> &x11::window::WindowHandle {
@@ -278,6 +613,18 @@ impl WindowHandle {
 }
 
 impl WindowHandle {
+    pub fn hide(&self) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.hide(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.hide(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.hide(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
     pub fn show(&self) {
         match self {
             #[cfg(feature = "x11")]
@@ -288,6 +635,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.show();
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.show();
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -302,10 +653,26 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.close();
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.close();
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
 
+    pub fn is_alive(&self) -> bool {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.is_alive(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.is_alive(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.is_alive(),
+            WindowHandle::None => false,
+        }
+    }
+
     pub fn resizable(&self, resizable: bool) {
         match self {
             #[cfg(feature = "x11")]
@@ -316,6 +683,34 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.resizable(resizable);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.resizable(resizable);
+            }
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_aspect_ratio(ratio),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_aspect_ratio(ratio),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_aspect_ratio(ratio),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_resize_increments(&self, increments: Option<Size>) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_resize_increments(increments),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_resize_increments(increments),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_resize_increments(increments),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -330,6 +725,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_window_state(state);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_window_state(state);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -340,6 +739,124 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.get_window_state(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.get_window_state(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.get_window_state(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_skip_taskbar(&self, skip: bool) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_skip_taskbar(skip),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_skip_taskbar(skip),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_skip_taskbar(skip),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn begin_sheet(&self, sheet: &WindowHandle, completion: Box<dyn FnOnce(ModalResponse)>) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.begin_sheet(sheet.unwrap_x11(), completion),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.begin_sheet(sheet.unwrap_wayland(), completion),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.begin_sheet(sheet.unwrap_headless(), completion)
+            }
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn end_sheet(&self, response: ModalResponse) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.end_sheet(response),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.end_sheet(response),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.end_sheet(response),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn run_modal(&self) -> ModalResponse {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.run_modal(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.run_modal(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.run_modal(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn end_modal(&self, response: ModalResponse) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.end_modal(response),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.end_modal(response),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.end_modal(response),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn focus_next_window(&self) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.focus_next_window(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.focus_next_window(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.focus_next_window(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_window_level(&self, level: StackingLevel) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_window_level(level),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_window_level(level),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_window_level(level),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_titlebar_theme(theme),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_titlebar_theme(theme),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_titlebar_theme(theme),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_maximize_mode(&self, mode: MaximizeMode) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => {
+                handle.set_maximize_mode(mode);
+            }
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => {
+                handle.set_maximize_mode(mode);
+            }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_maximize_mode(mode);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -354,6 +871,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.handle_titlebar(val);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.handle_titlebar(val);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -368,6 +889,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.show_titlebar(show_titlebar);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.show_titlebar(show_titlebar);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -382,6 +907,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_position(position);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_position(position);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -392,6 +921,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.get_position(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.get_position(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.get_position(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -402,6 +933,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.content_insets(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.content_insets(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.content_insets(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -416,6 +949,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_size(size);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_size(size);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -426,6 +963,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.get_size(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.get_size(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.get_size(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -440,6 +979,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.bring_to_front_and_focus();
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.bring_to_front_and_focus();
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -454,6 +997,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.request_anim_frame();
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.request_anim_frame();
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -468,6 +1015,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.invalidate();
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.invalidate();
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -482,6 +1033,76 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.invalidate_rect(rect);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.invalidate_rect(rect);
+            }
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.present_pixels(buf, width, height, format),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.present_pixels(buf, width, height, format),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.present_pixels(buf, width, height, format),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, Error> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.read_back_pixels(format),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.read_back_pixels(format),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.read_back_pixels(format),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.capture(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.capture(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.capture(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_vsync(enabled),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_vsync(enabled),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_vsync(enabled),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.vsync(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.vsync(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.vsync(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -496,6 +1117,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_title(title);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_title(title);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -510,6 +1135,8 @@ impl WindowHandle {
                     }
                     #[cfg(feature = "wayland")]
                     super::menu::Menu::Wayland(_) => {}
+                    #[cfg(feature = "headless")]
+                    super::menu::Menu::Headless(_) => {}
                 };
             }
             #[cfg(feature = "wayland")]
@@ -520,6 +1147,20 @@ impl WindowHandle {
                     super::menu::Menu::Wayland(menu) => {
                         handle.set_menu(menu);
                     }
+                    #[cfg(feature = "headless")]
+                    super::menu::Menu::Headless(_) => {}
+                };
+            }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                match menu {
+                    #[cfg(feature = "x11")]
+                    super::menu::Menu::X11(_) => {}
+                    #[cfg(feature = "wayland")]
+                    super::menu::Menu::Wayland(_) => {}
+                    super::menu::Menu::Headless(menu) => {
+                        handle.set_menu(menu);
+                    }
                 };
             }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
@@ -532,6 +1173,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.add_text_field(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.add_text_field(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.add_text_field(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -546,6 +1189,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.remove_text_field(token);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.remove_text_field(token);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -560,6 +1207,23 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_focused_text_field(active_field);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_focused_text_field(active_field);
+            }
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.focused_text_field(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.focused_text_field(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.focused_text_field(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -574,6 +1238,22 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.update_text_field(token, update);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.update_text_field(token, update);
+            }
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn show_character_palette(&self) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.show_character_palette(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.show_character_palette(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.show_character_palette(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -584,6 +1264,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.request_timer(deadline),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.request_timer(deadline),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.request_timer(deadline),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -598,6 +1280,10 @@ impl WindowHandle {
             WindowHandle::Wayland(handle) => {
                 handle.set_cursor(cursor);
             }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                handle.set_cursor(cursor);
+            }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -608,6 +1294,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.make_cursor(desc),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.make_cursor(desc),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.make_cursor(desc),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -618,6 +1306,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.open_file(options),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.open_file(options),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.open_file(options),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -628,6 +1318,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.save_as(options),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.save_as(options),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.save_as(options),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -642,6 +1334,8 @@ impl WindowHandle {
                     }
                     #[cfg(feature = "wayland")]
                     super::menu::Menu::Wayland(_) => {}
+                    #[cfg(feature = "headless")]
+                    super::menu::Menu::Headless(_) => {}
                 };
             }
             #[cfg(feature = "wayland")]
@@ -652,6 +1346,20 @@ impl WindowHandle {
                     super::menu::Menu::Wayland(menu) => {
                         handle.show_context_menu(menu, pos);
                     }
+                    #[cfg(feature = "headless")]
+                    super::menu::Menu::Headless(_) => {}
+                };
+            }
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => {
+                match menu {
+                    #[cfg(feature = "x11")]
+                    super::menu::Menu::X11(_) => {}
+                    #[cfg(feature = "wayland")]
+                    super::menu::Menu::Wayland(_) => {}
+                    super::menu::Menu::Headless(menu) => {
+                        handle.show_context_menu(menu, pos);
+                    }
                 };
             }
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
@@ -664,6 +1372,8 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.get_idle_handle().map(IdleHandle::X11),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.get_idle_handle().map(IdleHandle::Wayland),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.get_idle_handle().map(IdleHandle::Headless),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -674,6 +1384,44 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.get_scale().map_err(Into::into),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.get_scale().map_err(Into::into),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.get_scale().map_err(Into::into),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_backing_scale_override(scale),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_backing_scale_override(scale),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_backing_scale_override(scale),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.last_frame_timings(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.last_frame_timings(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.last_frame_timings(),
+            WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
+        }
+    }
+
+    pub fn set_present_feedback(&self, enabled: bool) {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.set_present_feedback(enabled),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.set_present_feedback(enabled),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.set_present_feedback(enabled),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
@@ -688,11 +1436,42 @@ impl WindowHandle {
             WindowHandle::X11(handle) => handle.update_accesskit_if_active(update_factory),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.update_accesskit_if_active(update_factory),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.update_accesskit_if_active(update_factory),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
 }
 
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.window_handle(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.window_handle(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.window_handle(),
+            WindowHandle::None => Err(HandleError::Unavailable),
+        }
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        match self {
+            #[cfg(feature = "x11")]
+            WindowHandle::X11(handle) => handle.display_handle(),
+            #[cfg(feature = "wayland")]
+            WindowHandle::Wayland(handle) => handle.display_handle(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.display_handle(),
+            WindowHandle::None => Err(HandleError::Unavailable),
+        }
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
     fn raw_window_handle(&self) -> RawWindowHandle {
         match self {
@@ -700,11 +1479,14 @@ unsafe impl HasRawWindowHandle for WindowHandle {
             WindowHandle::X11(handle) => handle.raw_window_handle(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.raw_window_handle(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.raw_window_handle(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
     fn raw_display_handle(&self) -> RawDisplayHandle {
         match self {
@@ -712,7 +1494,16 @@ unsafe impl HasRawDisplayHandle for WindowHandle {
             WindowHandle::X11(handle) => handle.raw_display_handle(),
             #[cfg(feature = "wayland")]
             WindowHandle::Wayland(handle) => handle.raw_display_handle(),
+            #[cfg(feature = "headless")]
+            WindowHandle::Headless(handle) => handle.raw_display_handle(),
             WindowHandle::None => panic!("Used an uninitialised WindowHandle"),
         }
     }
 }
+
+impl crate::platform::linux::WindowBuilderExt for crate::WindowBuilder {
+    fn set_layer_shell(mut self, config: LayerShellConfig) -> Self {
+        self.0 = self.0.layer_shell(config);
+        self
+    }
+}
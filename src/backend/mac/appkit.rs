@@ -24,6 +24,8 @@ use objc::{class, msg_send, sel, sel_impl};
 #[link(name = "AppKit", kind = "framework")]
 extern "C" {
     pub static NSRunLoopCommonModes: id;
+    pub static NSAppearanceNameAqua: id;
+    pub static NSAppearanceNameDarkAqua: id;
 }
 
 bitflags! {
@@ -17,22 +17,43 @@
 #![allow(non_upper_case_globals)]
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::c_void;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 
-use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyRegular};
-use cocoa::base::{id, nil, NO, YES};
-use cocoa::foundation::{NSArray, NSAutoreleasePool};
+use anyhow::anyhow;
+use block::{Block, ConcreteBlock};
+use cocoa::appkit::{
+    CGFloat, NSApp, NSApplication, NSApplicationActivationPolicyRegular,
+    NSApplicationPresentationOptions, NSEventMask, NSEventModifierFlags, NSEventType,
+};
+use cocoa::base::{id, nil, BOOL, NO, YES};
+use cocoa::foundation::{
+    NSArray, NSAutoreleasePool, NSDefaultRunLoopMode, NSInteger, NSPoint, NSUInteger,
+};
 use lazy_static::lazy_static;
 use objc::declare::ClassDecl;
 use objc::runtime::{Class, Object, Sel};
 use objc::{class, msg_send, sel, sel_impl};
 
-use crate::application::AppHandler;
-use crate::common_util::{shared_queue, SharedDequeuer, SharedEnqueuer};
+use crate::application::{
+    A11yPrefs, AppHandler, Color, EventSourceToken, FontDescription, LoopMode, LoopStatus,
+    PowerState, SubpixelOrder, SystemColor, SystemFonts, TextAntialiasing, TextRenderingPrefs,
+    UiDirection, UiMetrics, WakeLockKind,
+};
+use crate::common_util::{self, shared_queue, SharedDequeuer, SharedEnqueuer};
+use crate::error::Error as ShellError;
+use crate::notification::{Notification, NotificationId};
+use crate::platform::mac::PresentationOptions;
 
 use super::clipboard::Clipboard;
 use super::error::Error;
+use super::event_source::FileDescriptorSource;
+use super::menu;
+use super::notification;
 use super::util;
 
 static APP_DELEGATE_STATE_IVAR: &str = "glazierDelegateState";
@@ -46,6 +67,24 @@ pub(crate) struct Application {
 
 struct State {
     quitting: bool,
+    /// Set by the first loop iteration, which has to finish launching the application itself.
+    observers: Option<Observers>,
+    /// Whether a loop iteration has seen that we are quitting, after which there is nothing left
+    /// to run.
+    exited: bool,
+}
+
+/// Listens for system changes on behalf of the app delegate, until dropped.
+struct Observers {
+    delegate: id,
+    power_source: CFRunLoopSourceRef,
+    /// Calls `AppHandler::idle` and `idle_ended` around the run loop's waits
+    idle_observer: CFRunLoopObserverRef,
+    notif_center: id,
+    workspace_center: id,
+    distributed_center: id,
+    /// Passes on the key ups which `NSApplication` drops while Command is held
+    key_up_monitor: id,
 }
 
 impl Application {
@@ -56,7 +95,11 @@ impl Application {
         unsafe {
             let _pool = NSAutoreleasePool::new(nil);
             let ns_app = NSApp();
-            let state = Rc::new(RefCell::new(State { quitting: false }));
+            let state = Rc::new(RefCell::new(State {
+                quitting: false,
+                observers: None,
+                exited: false,
+            }));
 
             // Initialize the application delegate
             let delegate: id = msg_send![APP_DELEGATE.0, alloc];
@@ -64,10 +107,13 @@ impl Application {
             let delegate_state = DelegateState {
                 handler: None,
                 run_on_main_queue: shared_queue(),
+                a11y_prefs: accessibility_preferences(),
+                event_sources: HashMap::new(),
             };
             let delegate_state_ptr = Box::into_raw(Box::new(delegate_state));
             (*delegate).set_ivar(APP_DELEGATE_STATE_IVAR, delegate_state_ptr as *mut c_void);
             let () = msg_send![ns_app, setDelegate: delegate];
+            notification::set_delegate(delegate);
 
             Ok(Application {
                 ns_app,
@@ -77,21 +123,77 @@ impl Application {
         }
     }
 
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        unsafe { DelegateState::from_delegate(&mut *self.delegate).handler = handler }
+    }
+
     pub fn run(self, handler: Option<Box<dyn AppHandler>>) {
+        self.set_handler(handler);
+        while self.run_loop_iteration(LoopMode::Wait) == LoopStatus::Continue {}
         unsafe {
+            // Clean up the delegate
             let state_ptr = *(*self.delegate).get_ivar::<*mut c_void>(APP_DELEGATE_STATE_IVAR)
                 as *mut DelegateState;
-            state_ptr.as_mut().expect("delegate state").handler = handler;
-
-            // Run the main app loop
-            self.ns_app.run();
-
-            // Clean up the delegate
             let () = msg_send![self.ns_app, setDelegate: nil];
             drop(Box::from_raw(state_ptr)); // Causes it to drop & dealloc automatically
         }
     }
 
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        unsafe {
+            let _pool = NSAutoreleasePool::new(nil);
+            {
+                let mut state = self.state.borrow_mut();
+                if state.exited {
+                    return LoopStatus::Exit;
+                }
+                if state.observers.is_none() {
+                    // This is done by `-[NSApplication run]` otherwise, and sends
+                    // `applicationDidFinishLaunching:` to our delegate.
+                    let () = msg_send![self.ns_app, finishLaunching];
+                    state.observers = Some(Observers::start(self.delegate));
+                }
+            }
+            // Waiting also runs the run loop's other sources, such as `runOnMainQueue`
+            let mut until: id = match mode {
+                LoopMode::Wait => msg_send![class!(NSDate), distantFuture],
+                LoopMode::WaitTimeout(timeout) => {
+                    msg_send![class!(NSDate), dateWithTimeIntervalSinceNow: timeout.as_secs_f64()]
+                }
+                LoopMode::Poll => msg_send![class!(NSDate), distantPast],
+            };
+            loop {
+                let event: id = msg_send![self.ns_app,
+                    nextEventMatchingMask: NSEventMask::NSAnyEventMask.bits()
+                    untilDate: until
+                    inMode: NSDefaultRunLoopMode
+                    dequeue: YES];
+                if event == nil {
+                    break;
+                }
+                let () = msg_send![self.ns_app, sendEvent: event];
+                // Once we have an event, only handle the ones which are already queued.
+                until = msg_send![class!(NSDate), distantPast];
+            }
+            let () = msg_send![self.ns_app, updateWindows];
+        }
+        if self.state.borrow().quitting {
+            // The windows which `quit` queued to be closed may not have been yet, so they are
+            // closed now, which is when their handlers are destroyed.
+            self.close_windows(false);
+            let observers = {
+                let mut state = self.state.borrow_mut();
+                state.exited = true;
+                state.observers.take()
+            };
+            drop(observers);
+            self.set_handler(None);
+            LoopStatus::Exit
+        } else {
+            LoopStatus::Continue
+        }
+    }
+
     pub fn quit(&self) {
         if let Ok(mut state) = self.state.try_borrow_mut() {
             if !state.quitting {
@@ -99,14 +201,11 @@ impl Application {
                 unsafe {
                     // We want to queue up the destruction of all our windows.
                     // Failure to do so will lead to resource leaks.
-                    let windows: id = msg_send![self.ns_app, windows];
-                    for i in 0..windows.count() {
-                        let window: id = windows.objectAtIndex(i);
-                        let () = msg_send![window, performSelectorOnMainThread: sel!(close) withObject: nil waitUntilDone: NO];
-                    }
-                    // Stop sets a stop request flag in the OS.
-                    // The run loop is stopped after dealing with events.
+                    self.close_windows(true);
+                    // Stop breaks out of a modal loop, if one is running. Ours stops once it
+                    // has handled an event, so it is sent one.
                     let () = msg_send![self.ns_app, stop: nil];
+                    post_wake_event();
                 }
             }
         } else {
@@ -114,6 +213,35 @@ impl Application {
         }
     }
 
+    /// Close all of the application's windows, or queue them to be closed if `deferred`, as
+    /// the caller may be one of their handlers.
+    fn close_windows(&self, deferred: bool) {
+        unsafe {
+            let windows: id = msg_send![self.ns_app, windows];
+            // Closing a window removes it from the list, so this goes over a copy.
+            let windows: id = msg_send![windows, copy];
+            for i in 0..windows.count() {
+                let window: id = windows.objectAtIndex(i);
+                // Child windows are closed along with their parent. Tabs are windows of
+                // their own, which are each in the list once, whether or not selected.
+                let parent: id = msg_send![window, parentWindow];
+                if parent != nil {
+                    continue;
+                }
+                if deferred {
+                    let () = msg_send![window, performSelectorOnMainThread: sel!(close) withObject: nil waitUntilDone: NO];
+                } else {
+                    let () = msg_send![window, close];
+                }
+            }
+            let () = msg_send![windows, release];
+        }
+    }
+
+    pub fn set_app_id(&self, _app_id: &str) {
+        // Ignored, the bundle identifier comes from Info.plist
+    }
+
     pub fn clipboard(&self) -> Clipboard {
         Clipboard
     }
@@ -123,12 +251,37 @@ impl Application {
             let nslocale_class = class!(NSLocale);
             let locale: id = msg_send![nslocale_class, currentLocale];
             let ident: id = msg_send![locale, localeIdentifier];
-            let mut locale = util::from_nsstring(ident);
-            // This is done because the locale parsing library we use expects an unicode locale, but these vars have an ISO locale
-            if let Some(idx) = locale.chars().position(|c| c == '@') {
-                locale.truncate(idx);
+            // This is an ISO locale, such as `en_US@rg=gbzzzz`, rather than a unicode one
+            common_util::normalize_locale(&util::from_nsstring(ident))
+                .unwrap_or_else(|| "en-US".into())
+        }
+    }
+
+    pub fn get_locales() -> Vec<String> {
+        unsafe {
+            let languages: id = msg_send![class!(NSLocale), preferredLanguages];
+            let count: NSUInteger = msg_send![languages, count];
+            let mut locales: Vec<String> = (0..count)
+                .filter_map(|i| {
+                    let language: id = msg_send![languages, objectAtIndex: i];
+                    common_util::normalize_locale(&util::from_nsstring(language))
+                })
+                .collect();
+            if locales.is_empty() {
+                locales.push(Self::get_locale());
             }
-            locale
+            locales
+        }
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        // NSUserInterfaceLayoutDirectionRightToLeft
+        const RIGHT_TO_LEFT: NSInteger = 1;
+        let direction: NSInteger = unsafe { msg_send![NSApp(), userInterfaceLayoutDirection] };
+        if direction == RIGHT_TO_LEFT {
+            UiDirection::Rtl
+        } else {
+            UiDirection::Ltr
         }
     }
 
@@ -139,6 +292,671 @@ impl Application {
             enqueuer: delegate.run_on_main_queue.0.clone(),
         })
     }
+
+    pub fn power_state(&self) -> PowerState {
+        power_state()
+    }
+
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        unsafe { DelegateState::from_delegate(&mut *self.delegate).a11y_prefs }
+    }
+
+    pub fn system_fonts(&self) -> SystemFonts {
+        unsafe {
+            let nsfont = class!(NSFont);
+            let system_size: CGFloat = msg_send![nsfont, systemFontSize];
+            let small_size: CGFloat = msg_send![nsfont, smallSystemFontSize];
+            let ui: id = msg_send![nsfont, systemFontOfSize: system_size];
+            let small: id = msg_send![nsfont, systemFontOfSize: small_size];
+            // A size of zero asks for the default size of each kind of font.
+            let menu: id = msg_send![nsfont, menuFontOfSize: 0.0 as CGFloat];
+            let title: id = msg_send![nsfont, titleBarFontOfSize: 0.0 as CGFloat];
+            // The monospaced system font was added in macOS 10.15.
+            let responds: BOOL =
+                msg_send![nsfont, respondsToSelector: sel!(monospacedSystemFontOfSize:weight:)];
+            let monospace: id = if responds == YES {
+                // This is `NSFontWeightRegular`.
+                msg_send![nsfont, monospacedSystemFontOfSize: system_size weight: 0.0 as CGFloat]
+            } else {
+                msg_send![nsfont, userFixedPitchFontOfSize: system_size]
+            };
+            SystemFonts {
+                ui: font_description(ui),
+                monospace: font_description(monospace),
+                menu: font_description(menu),
+                title: font_description(title),
+                small: font_description(small),
+            }
+        }
+    }
+
+    /// The text size is only a setting on iOS.
+    pub fn text_scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        unsafe {
+            let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+            // This is only in the defaults if it has been changed, and zero turns smoothing off.
+            let smoothing: id =
+                msg_send![defaults, objectForKey: util::make_nsstring("AppleFontSmoothing")];
+            let smoothing: NSInteger = if smoothing == nil {
+                2
+            } else {
+                msg_send![smoothing, integerValue]
+            };
+            // Subpixel antialiasing was removed in macOS 10.14, along with the accent color
+            // being added, after which smoothing only makes glyphs bolder.
+            let responds: BOOL =
+                msg_send![class!(NSColor), respondsToSelector: sel!(controlAccentColor)];
+            let antialiasing = if responds == NO && smoothing > 0 {
+                TextAntialiasing::Subpixel(SubpixelOrder::Rgb)
+            } else {
+                TextAntialiasing::Grayscale
+            };
+            TextRenderingPrefs {
+                antialiasing,
+                // Core Text doesn't hint.
+                hinting: false,
+                // Coverage is blended with a gamma of about 1.8 while smoothing.
+                gamma: if smoothing > 0 { 1.8 } else { 1.0 },
+            }
+        }
+    }
+
+    pub fn ui_metrics(&self) -> UiMetrics {
+        unsafe {
+            let scroller_style: NSInteger = msg_send![class!(NSScroller), preferredScrollerStyle];
+            // This is `NSControlSizeRegular`.
+            let scrollbar_width: CGFloat = msg_send![class!(NSScroller),
+                scrollerWidthForControlSize: 0 as NSUInteger
+                scrollerStyle: scroller_style];
+            let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+            let blink_on: NSInteger = msg_send![defaults,
+                integerForKey: util::make_nsstring("NSTextInsertionPointBlinkPeriodOn")];
+            // The blink period is only in the defaults if it has been changed.
+            let blink_on = if blink_on > 0 { blink_on as u64 } else { 500 };
+            UiMetrics {
+                scrollbar_width,
+                caret_width: 1.0,
+                caret_blink: Some(Duration::from_millis(blink_on)),
+                // AppKit has no setting for these, so use what its own controls do.
+                drag_threshold: 3.0,
+                menu_show_delay: Duration::ZERO,
+            }
+        }
+    }
+
+    pub fn accent_color(&self) -> Option<Color> {
+        accent_color()
+    }
+
+    pub fn system_color(&self, color: SystemColor) -> Option<Color> {
+        unsafe {
+            let nscolor = class!(NSColor);
+            let color: id = match color {
+                SystemColor::Highlight => {
+                    // This replaced `alternateSelectedControlColor` in macOS 10.14.
+                    let responds: BOOL = msg_send![nscolor,
+                        respondsToSelector: sel!(selectedContentBackgroundColor)];
+                    if responds == YES {
+                        msg_send![nscolor, selectedContentBackgroundColor]
+                    } else {
+                        msg_send![nscolor, alternateSelectedControlColor]
+                    }
+                }
+                SystemColor::Window => msg_send![nscolor, windowBackgroundColor],
+                SystemColor::Text => msg_send![nscolor, textColor],
+            };
+            resolve_color(color)
+        }
+    }
+
+    pub fn acquire_wake_lock(
+        &self,
+        kind: WakeLockKind,
+        reason: &str,
+    ) -> Result<SleepGuard, ShellError> {
+        let assertion_type = match kind {
+            WakeLockKind::Display => "PreventUserIdleDisplaySleep",
+            WakeLockKind::System => "PreventUserIdleSystemSleep",
+        };
+        SleepGuard::new(assertion_type, reason)
+    }
+
+    pub fn send_notification(
+        &self,
+        notification: &Notification,
+        id: NotificationId,
+    ) -> Result<(), ShellError> {
+        notification::send(notification, id, self.get_handle().unwrap())
+    }
+
+    pub fn close_notification(&self, id: NotificationId) {
+        notification::close(id)
+    }
+
+    pub fn add_recent_document(&self, path: &Path) {
+        let Some(path) = path.to_str() else {
+            tracing::warn!("the recent document {} isn't valid UTF-8", path.display());
+            return;
+        };
+        unsafe {
+            let url: id = msg_send![class!(NSURL), fileURLWithPath: util::make_nsstring(path)];
+            let controller: id = msg_send![class!(NSDocumentController), sharedDocumentController];
+            let () = msg_send![controller, noteNewRecentDocumentURL: url];
+        }
+    }
+}
+
+type IOPMAssertionID = u32;
+type CFRunLoopSourceRef = *mut c_void;
+type CFRunLoopRef = *mut c_void;
+type CFRunLoopObserverRef = *mut c_void;
+type CFRunLoopActivity = usize;
+type CFRunLoopObserverCallBack =
+    extern "C" fn(observer: CFRunLoopObserverRef, activity: CFRunLoopActivity, info: *mut c_void);
+type IOPowerSourceCallbackType = extern "C" fn(context: *mut c_void);
+
+const kCFRunLoopBeforeWaiting: CFRunLoopActivity = 1 << 5;
+const kCFRunLoopAfterWaiting: CFRunLoopActivity = 1 << 6;
+
+const kIOPMAssertionLevelOn: u32 = 255;
+const kIOReturnSuccess: i32 = 0;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    // The strings are CFStringRefs, which NSStrings are toll-free bridged to.
+    fn IOPMAssertionCreateWithName(
+        assertion_type: id,
+        level: u32,
+        name: id,
+        assertion_id: *mut IOPMAssertionID,
+    ) -> i32;
+    fn IOPMAssertionRelease(assertion_id: IOPMAssertionID) -> i32;
+
+    // These return CoreFoundation types, which are toll-free bridged to their NS equivalents.
+    fn IOPSCopyPowerSourcesInfo() -> id;
+    fn IOPSCopyPowerSourcesList(blob: id) -> id;
+    fn IOPSGetPowerSourceDescription(blob: id, power_source: id) -> id;
+    fn IOPSGetProvidingPowerSourceType(blob: id) -> id;
+    fn IOPSNotificationCreateRunLoopSource(
+        callback: IOPowerSourceCallbackType,
+        context: *mut c_void,
+    ) -> CFRunLoopSourceRef;
+}
+
+#[repr(C)]
+struct CFRunLoopObserverContext {
+    version: isize,
+    info: *mut c_void,
+    retain: *const c_void,
+    release: *const c_void,
+    copy_description: *const c_void,
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopDefaultMode: id;
+    static kCFRunLoopCommonModes: id;
+    fn CFRunLoopGetMain() -> CFRunLoopRef;
+    fn CFRunLoopAddSource(run_loop: CFRunLoopRef, source: CFRunLoopSourceRef, mode: id);
+    fn CFRunLoopSourceInvalidate(source: CFRunLoopSourceRef);
+    fn CFRunLoopObserverCreate(
+        allocator: *const c_void,
+        activities: CFRunLoopActivity,
+        repeats: u8,
+        order: isize,
+        callout: CFRunLoopObserverCallBack,
+        context: *mut CFRunLoopObserverContext,
+    ) -> CFRunLoopObserverRef;
+    fn CFRunLoopAddObserver(run_loop: CFRunLoopRef, observer: CFRunLoopObserverRef, mode: id);
+    fn CFRunLoopObserverInvalidate(observer: CFRunLoopObserverRef);
+    fn CFRelease(cf: *const c_void);
+}
+
+fn power_state() -> PowerState {
+    unsafe {
+        let _pool = NSAutoreleasePool::new(nil);
+        let info = IOPSCopyPowerSourcesInfo();
+        if info == nil {
+            tracing::warn!("IOPSCopyPowerSourcesInfo failed");
+            return PowerState::default();
+        }
+        let providing = IOPSGetProvidingPowerSourceType(info);
+        let on_battery = providing != nil && util::from_nsstring(providing) == "Battery Power";
+
+        let mut battery_level = None;
+        let sources = IOPSCopyPowerSourcesList(info);
+        if sources != nil {
+            for i in 0..sources.count() {
+                let description = IOPSGetPowerSourceDescription(info, sources.objectAtIndex(i));
+                if description == nil {
+                    continue;
+                }
+                let kind: id = msg_send![description, objectForKey: util::make_nsstring("Type")];
+                if kind == nil || util::from_nsstring(kind) != "InternalBattery" {
+                    continue;
+                }
+                let current: id =
+                    msg_send![description, objectForKey: util::make_nsstring("Current Capacity")];
+                let max: id =
+                    msg_send![description, objectForKey: util::make_nsstring("Max Capacity")];
+                if current != nil && max != nil {
+                    let current: NSInteger = msg_send![current, integerValue];
+                    let max: NSInteger = msg_send![max, integerValue];
+                    if max > 0 {
+                        battery_level = Some(current as f64 / max as f64);
+                    }
+                }
+                break;
+            }
+            CFRelease(sources as *const c_void);
+        }
+        CFRelease(info as *const c_void);
+
+        // Low Power Mode was added in macOS 12.
+        let process_info: id = msg_send![class!(NSProcessInfo), processInfo];
+        let responds: BOOL =
+            msg_send![process_info, respondsToSelector: sel!(isLowPowerModeEnabled)];
+        let low_power_mode = responds == YES && {
+            let enabled: BOOL = msg_send![process_info, isLowPowerModeEnabled];
+            enabled == YES
+        };
+
+        PowerState {
+            on_battery,
+            battery_level,
+            low_power_mode,
+        }
+    }
+}
+
+fn font_description(font: id) -> FontDescription {
+    unsafe {
+        // The family of the system font is a private name, which only the platform's font APIs
+        // know how to look up.
+        let family: id = msg_send![font, familyName];
+        let size: CGFloat = msg_send![font, pointSize];
+        FontDescription {
+            family: util::from_nsstring(family),
+            size,
+        }
+    }
+}
+
+fn accent_color() -> Option<Color> {
+    unsafe {
+        // The accent color was added in macOS 10.14.
+        let nscolor = class!(NSColor);
+        let responds: BOOL = msg_send![nscolor, respondsToSelector: sel!(controlAccentColor)];
+        if responds == NO {
+            return None;
+        }
+        resolve_color(msg_send![nscolor, controlAccentColor])
+    }
+}
+
+/// Converts a system color to sRGB, in the application's light or dark appearance.
+///
+/// The system colors are dynamic, and are resolved in the current appearance, which is only
+/// the application's while it draws.
+unsafe fn resolve_color(color: id) -> Option<Color> {
+    if color == nil {
+        return None;
+    }
+    let nsappearance = class!(NSAppearance);
+    let previous: id = msg_send![nsappearance, currentAppearance];
+    // The application's appearance was added in macOS 10.14, with dark mode.
+    let responds: BOOL = msg_send![NSApp(), respondsToSelector: sel!(effectiveAppearance)];
+    if responds == YES {
+        let appearance: id = msg_send![NSApp(), effectiveAppearance];
+        let () = msg_send![nsappearance, setCurrentAppearance: appearance];
+    }
+    let srgb: id = msg_send![class!(NSColorSpace), sRGBColorSpace];
+    let converted: id = msg_send![color, colorUsingColorSpace: srgb];
+    if responds == YES {
+        let () = msg_send![nsappearance, setCurrentAppearance: previous];
+    }
+    if converted == nil {
+        return None;
+    }
+    let (mut r, mut g, mut b, mut a): (CGFloat, CGFloat, CGFloat, CGFloat) = (0.0, 0.0, 0.0, 0.0);
+    let () = msg_send![converted, getRed: &mut r green: &mut g blue: &mut b alpha: &mut a];
+    Some(Color::from_f64(r, g, b, a))
+}
+
+/// Called when the system colors, or the accent color, change, and when the user switches
+/// between light and dark mode.
+extern "C" fn system_colors_did_change(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.accent_color_changed(accent_color());
+    }
+}
+
+extern "C" fn locale_did_change(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.locale_changed();
+    }
+}
+
+/// Called by the workspace before the system sleeps. The system only waits briefly for us.
+extern "C" fn system_will_sleep(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.system_will_sleep();
+    }
+}
+
+extern "C" fn system_did_wake(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.system_did_wake();
+    }
+}
+
+extern "C" fn session_locked(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.session_locked();
+    }
+}
+
+extern "C" fn session_unlocked(this: &mut Object, _: Sel, _notification: id) {
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if let Some(handler) = state.handler.as_mut() {
+        handler.session_unlocked();
+    }
+}
+
+/// Called by the notification center when the user clicks one of our notifications.
+extern "C" fn did_receive_notification_response(
+    this: &mut Object,
+    _: Sel,
+    _center: id,
+    response: id,
+    completion: id,
+) {
+    unsafe {
+        let state = DelegateState::from_delegate(this);
+        if let (Some((id, action)), Some(handler)) =
+            (notification::activation(response), state.handler.as_mut())
+        {
+            handler.notification_activated(id, action);
+        }
+        let completion = &*(completion as *const Block<(), ()>);
+        completion.call(());
+    }
+}
+
+/// Called by the notification center when a notification arrives while we're in front.
+extern "C" fn will_present_notification(
+    _this: &mut Object,
+    _: Sel,
+    _center: id,
+    _notification: id,
+    completion: id,
+) {
+    unsafe {
+        let completion = &*(completion as *const Block<(NSUInteger,), ()>);
+        completion.call((notification::UN_NOTIFICATION_PRESENTATION_OPTIONS,));
+    }
+}
+
+fn accessibility_preferences() -> A11yPrefs {
+    unsafe {
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        // Reduce motion was added in macOS 10.12.
+        let responds: BOOL = msg_send![workspace,
+            respondsToSelector: sel!(accessibilityDisplayShouldReduceMotion)];
+        let reduce_motion = responds == YES && {
+            let reduce: BOOL = msg_send![workspace, accessibilityDisplayShouldReduceMotion];
+            reduce == YES
+        };
+        let high_contrast: BOOL = msg_send![workspace, accessibilityDisplayShouldIncreaseContrast];
+        let reduce_transparency: BOOL =
+            msg_send![workspace, accessibilityDisplayShouldReduceTransparency];
+        A11yPrefs {
+            reduce_motion,
+            high_contrast: high_contrast == YES,
+            prefer_reduced_transparency: reduce_transparency == YES,
+        }
+    }
+}
+
+/// Called by the workspace when any accessibility display option changes.
+extern "C" fn accessibility_display_options_did_change(
+    this: &mut Object,
+    _: Sel,
+    _notification: id,
+) {
+    let prefs = accessibility_preferences();
+    let state = unsafe { DelegateState::from_delegate(this) };
+    if state.a11y_prefs != prefs {
+        state.a11y_prefs = prefs;
+        if let Some(handler) = state.handler.as_mut() {
+            handler.accessibility_preferences_changed(prefs);
+        }
+    }
+}
+
+impl Observers {
+    unsafe fn start(delegate: id) -> Observers {
+        // Listen for changes to the power state
+        let power_source =
+            IOPSNotificationCreateRunLoopSource(power_source_changed, delegate.cast());
+        if !power_source.is_null() {
+            CFRunLoopAddSource(CFRunLoopGetMain(), power_source, kCFRunLoopDefaultMode);
+        }
+        // .. and to the run loop waiting. This goes before Core Animation's commit, which is
+        // also done before waiting, so windows invalidated from `idle` are drawn straight away.
+        let mut context = CFRunLoopObserverContext {
+            version: 0,
+            info: delegate.cast(),
+            retain: std::ptr::null(),
+            release: std::ptr::null(),
+            copy_description: std::ptr::null(),
+        };
+        let idle_observer = CFRunLoopObserverCreate(
+            std::ptr::null(),
+            kCFRunLoopBeforeWaiting | kCFRunLoopAfterWaiting,
+            1,
+            0,
+            run_loop_activity,
+            &mut context,
+        );
+        if !idle_observer.is_null() {
+            // The common modes include the one used while tracking a resize or a menu.
+            CFRunLoopAddObserver(CFRunLoopGetMain(), idle_observer, kCFRunLoopCommonModes);
+        }
+        // .. and to Low Power Mode being toggled
+        let notif_center: id = msg_send![class!(NSNotificationCenter), defaultCenter];
+        let () = msg_send![notif_center,
+            addObserver: delegate
+            selector: sel!(powerStateDidChange:)
+            name: util::make_nsstring("NSProcessInfoPowerStateDidChangeNotification")
+            object: nil];
+        // .. and to the system colors
+        let () = msg_send![notif_center,
+            addObserver: delegate
+            selector: sel!(systemColorsDidChange:)
+            name: util::make_nsstring("NSSystemColorsDidChangeNotification")
+            object: nil];
+        // .. and to the language and region settings
+        let () = msg_send![notif_center,
+            addObserver: delegate
+            selector: sel!(localeDidChange:)
+            name: util::make_nsstring("NSCurrentLocaleDidChangeNotification")
+            object: nil];
+        // .. and to dark mode, which is only posted to the distributed center
+        let distributed_center: id =
+            msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+        let () = msg_send![distributed_center,
+            addObserver: delegate
+            selector: sel!(systemColorsDidChange:)
+            name: util::make_nsstring("AppleInterfaceThemeChangedNotification")
+            object: nil];
+        // .. and to the accessibility display options, which are posted by the workspace
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let workspace_center: id = msg_send![workspace, notificationCenter];
+        let () = msg_send![workspace_center,
+            addObserver: delegate
+            selector: sel!(accessibilityDisplayOptionsDidChange:)
+            name: util::make_nsstring("NSWorkspaceAccessibilityDisplayOptionsDidChangeNotification")
+            object: nil];
+        // .. and to the system sleeping and waking
+        let () = msg_send![workspace_center,
+            addObserver: delegate
+            selector: sel!(systemWillSleep:)
+            name: util::make_nsstring("NSWorkspaceWillSleepNotification")
+            object: nil];
+        let () = msg_send![workspace_center,
+            addObserver: delegate
+            selector: sel!(systemDidWake:)
+            name: util::make_nsstring("NSWorkspaceDidWakeNotification")
+            object: nil];
+        // .. and to the screen being locked, which is only posted to the distributed center
+        let () = msg_send![distributed_center,
+            addObserver: delegate
+            selector: sel!(sessionLocked:)
+            name: util::make_nsstring("com.apple.screenIsLocked")
+            object: nil];
+        let () = msg_send![distributed_center,
+            addObserver: delegate
+            selector: sel!(sessionUnlocked:)
+            name: util::make_nsstring("com.apple.screenIsUnlocked")
+            object: nil];
+        // .. and to the keys released while Command is held, which `-[NSApplication sendEvent:]`
+        // doesn't send on to the key window, so handlers would see them pressed and never
+        // released. The window is sent them itself instead.
+        let block = ConcreteBlock::new(|event: id| -> id {
+            let mods: NSEventModifierFlags = msg_send![event, modifierFlags];
+            let window: id = msg_send![event, window];
+            if !mods.contains(NSEventModifierFlags::NSCommandKeyMask) || window == nil {
+                return event;
+            }
+            let () = msg_send![window, sendEvent: event];
+            nil
+        });
+        let block = block.copy();
+        let key_up_monitor: id = msg_send![class!(NSEvent),
+            addLocalMonitorForEventsMatchingMask: NSEventMask::NSKeyUpMask.bits()
+            handler: &*block];
+        Observers {
+            delegate,
+            power_source,
+            idle_observer,
+            notif_center,
+            workspace_center,
+            distributed_center,
+            key_up_monitor,
+        }
+    }
+}
+
+impl Drop for Observers {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.power_source.is_null() {
+                CFRunLoopSourceInvalidate(self.power_source);
+                CFRelease(self.power_source);
+            }
+            if !self.idle_observer.is_null() {
+                CFRunLoopObserverInvalidate(self.idle_observer);
+                CFRelease(self.idle_observer);
+            }
+            let () = msg_send![self.notif_center, removeObserver: self.delegate];
+            let () = msg_send![self.workspace_center, removeObserver: self.delegate];
+            let () = msg_send![self.distributed_center, removeObserver: self.delegate];
+            if self.key_up_monitor != nil {
+                let () = msg_send![class!(NSEvent), removeMonitor: self.key_up_monitor];
+            }
+        }
+    }
+}
+
+/// Called by the main run loop before and after it waits. The info is our app delegate.
+extern "C" fn run_loop_activity(
+    _observer: CFRunLoopObserverRef,
+    activity: CFRunLoopActivity,
+    info: *mut c_void,
+) {
+    unsafe {
+        let state = DelegateState::from_delegate(&mut *(info as *mut Object));
+        if let Some(handler) = state.handler.as_mut() {
+            if activity == kCFRunLoopBeforeWaiting {
+                handler.idle();
+            } else {
+                handler.idle_ended();
+            }
+        }
+    }
+}
+
+/// Called by IOKit whenever any power source changes. The context is our app delegate.
+extern "C" fn power_source_changed(context: *mut c_void) {
+    unsafe {
+        let state = DelegateState::from_delegate(&mut *(context as *mut Object));
+        // Held battery level changes are passed on from another thread, so all of them go
+        // through the main queue
+        let handle = AppHandle {
+            enqueuer: state.run_on_main_queue.0.clone(),
+        };
+        common_util::debounce_power_state(power_state(), move |power_state| {
+            handle
+                .run_on_main(move |handler| common_util::power_state_changed(handler, power_state))
+        });
+    }
+}
+
+/// Called when Low Power Mode is toggled. This may be called from any thread.
+extern "C" fn power_state_did_change(this: &mut Object, _: Sel, _notification: id) {
+    unsafe {
+        let () = msg_send![this,
+            performSelectorOnMainThread: sel!(updatePowerState)
+            withObject: nil
+            waitUntilDone: NO];
+    }
+}
+
+extern "C" fn update_power_state(this: &mut Object, _: Sel) {
+    power_source_changed(this as *mut Object as *mut c_void);
+}
+
+/// Holds an IOKit power management assertion, which is released on drop.
+pub(crate) struct SleepGuard(IOPMAssertionID);
+
+impl SleepGuard {
+    fn new(assertion_type: &str, reason: &str) -> Result<SleepGuard, ShellError> {
+        let mut assertion_id = 0;
+        let result = unsafe {
+            let _pool = NSAutoreleasePool::new(nil);
+            // The name is shown to the user by tools such as `pmset -g assertions`.
+            IOPMAssertionCreateWithName(
+                util::make_nsstring(assertion_type),
+                kIOPMAssertionLevelOn,
+                util::make_nsstring(reason),
+                &mut assertion_id,
+            )
+        };
+        if result == kIOReturnSuccess {
+            Ok(SleepGuard(assertion_id))
+        } else {
+            Err(anyhow!("IOPMAssertionCreateWithName failed: {result:#x}").into())
+        }
+    }
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        let result = unsafe { IOPMAssertionRelease(self.0) };
+        if result != kIOReturnSuccess {
+            tracing::warn!("IOPMAssertionRelease failed: {result:#x}");
+        }
+    }
 }
 
 impl crate::platform::mac::ApplicationExt for crate::Application {
@@ -161,6 +979,50 @@ impl crate::platform::mac::ApplicationExt for crate::Application {
             NSApp().setMainMenu_(menu.0.menu);
         }
     }
+
+    fn add_event_source(
+        &self,
+        fd: RawFd,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> EventSourceToken {
+        let token = EventSourceToken::next();
+        if let Some(source) = FileDescriptorSource::new(fd, token, event_source_ready) {
+            let state = unsafe { DelegateState::from_delegate(&mut *self.backend_app.delegate) };
+            state.event_sources.insert(
+                token,
+                EventSource {
+                    source,
+                    callback: Some(Box::new(callback)),
+                },
+            );
+        }
+        token
+    }
+
+    fn remove_event_source(&self, token: EventSourceToken) {
+        let state = unsafe { DelegateState::from_delegate(&mut *self.backend_app.delegate) };
+        state.event_sources.remove(&token);
+    }
+
+    fn set_automatic_window_tabbing(&self, allowed: bool) {
+        let allowed = if allowed { YES } else { NO };
+        unsafe {
+            let () = msg_send![class!(NSWindow), setAllowsAutomaticWindowTabbing: allowed];
+        }
+    }
+
+    fn set_presentation_options(&self, options: PresentationOptions) {
+        // AppKit raises an exception for invalid combinations, which we can't unwind through.
+        if !options.is_valid() {
+            tracing::warn!("Ignoring invalid presentation options {:?}", options);
+            return;
+        }
+        unsafe {
+            let options =
+                NSApplicationPresentationOptions::from_bits_truncate(options.bits() as NSUInteger);
+            self.backend_app.ns_app.setPresentationOptions_(options);
+        }
+    }
 }
 
 type MainThreadCb = Box<dyn FnOnce(Option<&mut dyn AppHandler>) + Send>;
@@ -193,6 +1055,15 @@ impl AppHandle {
 struct DelegateState {
     handler: Option<Box<dyn AppHandler>>,
     run_on_main_queue: (SharedEnqueuer<MainThreadCb>, SharedDequeuer<MainThreadCb>),
+    /// Read again whenever the workspace says the accessibility display options have changed.
+    a11y_prefs: A11yPrefs,
+    event_sources: HashMap<EventSourceToken, EventSource>,
+}
+
+struct EventSource {
+    source: FileDescriptorSource,
+    /// This is taken while the callback runs, so that it can add and remove event sources.
+    callback: Option<Box<dyn FnMut(Option<&mut dyn AppHandler>)>>,
 }
 
 impl DelegateState {
@@ -228,11 +1099,82 @@ lazy_static! {
             handle_menu_item as extern "C" fn(&mut Object, Sel, id),
         );
 
+        decl.add_method(
+            sel!(application:openURLs:),
+            application_open_urls as extern "C" fn(&mut Object, Sel, id, id),
+        );
+
+        decl.add_method(
+            sel!(openRecentDocument:),
+            open_recent_document as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        // We are the delegate of the "Open Recent" menu.
+        decl.add_method(
+            sel!(menuNeedsUpdate:),
+            menu_needs_update as extern "C" fn(&mut Object, Sel, id),
+        );
+
         decl.add_method(
             sel!(runOnMainQueue),
             run_on_main_queue as extern "C" fn(&mut Object, Sel),
         );
 
+        decl.add_method(
+            sel!(powerStateDidChange:),
+            power_state_did_change as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(updatePowerState),
+            update_power_state as extern "C" fn(&mut Object, Sel),
+        );
+
+        decl.add_method(
+            sel!(accessibilityDisplayOptionsDidChange:),
+            accessibility_display_options_did_change as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(systemColorsDidChange:),
+            system_colors_did_change as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(localeDidChange:),
+            locale_did_change as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(systemWillSleep:),
+            system_will_sleep as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(systemDidWake:),
+            system_did_wake as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(sessionLocked:),
+            session_locked as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(sessionUnlocked:),
+            session_unlocked as extern "C" fn(&mut Object, Sel, id),
+        );
+
+        decl.add_method(
+            sel!(userNotificationCenter:didReceiveNotificationResponse:withCompletionHandler:),
+            did_receive_notification_response as extern "C" fn(&mut Object, Sel, id, id, id),
+        );
+
+        decl.add_method(
+            sel!(userNotificationCenter:willPresentNotification:withCompletionHandler:),
+            will_present_notification as extern "C" fn(&mut Object, Sel, id, id, id),
+        );
+
         AppDelegate(decl.register())
     };
 }
@@ -256,6 +1198,81 @@ extern "C" fn handle_menu_item(this: &mut Object, _: Sel, item: id) {
     }
 }
 
+/// Called when files are opened with the application from the Finder or the Dock.
+extern "C" fn application_open_urls(this: &mut Object, _: Sel, _app: id, urls: id) {
+    let _span = common_util::event_span("application:openURLs:");
+    unsafe {
+        let count: NSUInteger = msg_send![urls, count];
+        let paths: Vec<PathBuf> = (0..count)
+            .filter_map(|i| {
+                let url: id = msg_send![urls, objectAtIndex: i];
+                let is_file: BOOL = msg_send![url, isFileURL];
+                if is_file == NO {
+                    return None;
+                }
+                let path: id = msg_send![url, path];
+                Some(PathBuf::from(util::from_nsstring(path)))
+            })
+            .collect();
+        open_files(this, paths);
+    }
+}
+
+/// The action of the documents in the "Open Recent" menu, which hold their URL.
+extern "C" fn open_recent_document(this: &mut Object, _: Sel, item: id) {
+    let _span = common_util::event_span("openRecentDocument:");
+    unsafe {
+        let url: id = msg_send![item, representedObject];
+        if url == nil {
+            return;
+        }
+        // It moves back to the top of the list, as it would have if it had been opened by AppKit.
+        let controller: id = msg_send![class!(NSDocumentController), sharedDocumentController];
+        let () = msg_send![controller, noteNewRecentDocumentURL: url];
+        let path: id = msg_send![url, path];
+        open_files(this, vec![PathBuf::from(util::from_nsstring(path))]);
+    }
+}
+
+unsafe fn open_files(delegate: &mut Object, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    let state = DelegateState::from_delegate(delegate);
+    if let Some(handler) = state.handler.as_mut() {
+        handler.open_files(paths);
+    }
+}
+
+extern "C" fn menu_needs_update(this: &mut Object, _: Sel, menu: id) {
+    menu::update_recent_documents(menu, this);
+}
+
+extern "C" fn event_source_ready(_: *mut c_void, _: usize, info: *mut c_void) {
+    let token = FileDescriptorSource::token(info);
+    unsafe {
+        let delegate: id = msg_send![NSApp(), delegate];
+        let state = DelegateState::from_delegate(&mut *delegate);
+        // The source may have been removed by another callback.
+        let Some(mut callback) = state
+            .event_sources
+            .get_mut(&token)
+            .and_then(|source| source.callback.take())
+        else {
+            return;
+        };
+        callback(match state.handler.as_mut() {
+            Some(handler) => Some(handler.as_mut()),
+            None => None,
+        });
+        let state = DelegateState::from_delegate(&mut *delegate);
+        if let Some(source) = state.event_sources.get_mut(&token) {
+            source.callback = Some(callback);
+            source.source.enable();
+        }
+    }
+}
+
 extern "C" fn run_on_main_queue(this: &mut Object, _: Sel) {
     unsafe {
         let state = DelegateState::from_delegate(this);
@@ -265,5 +1282,22 @@ extern "C" fn run_on_main_queue(this: &mut Object, _: Sel) {
                 None => None,
             });
         }
+        // A loop iteration which is waiting only returns once it gets an event
+        post_wake_event();
     }
 }
+
+/// Post an event which does nothing, so that a loop waiting for the next event wakes up.
+pub(super) unsafe fn post_wake_event() {
+    let event: id = msg_send![class!(NSEvent),
+        otherEventWithType: NSEventType::NSApplicationDefined
+        location: NSPoint::new(0., 0.)
+        modifierFlags: NSEventModifierFlags::empty()
+        timestamp: 0.0f64
+        windowNumber: 0 as NSInteger
+        context: nil
+        subtype: 0i16
+        data1: 0 as NSInteger
+        data2: 0 as NSInteger];
+    let () = msg_send![NSApp(), postEvent: event atStart: YES];
+}
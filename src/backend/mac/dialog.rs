@@ -28,8 +28,8 @@ use crate::dialog::{FileDialogOptions, FileDialogType};
 use crate::{FileInfo, FileSpec};
 
 pub(crate) type NSModalResponse = NSInteger;
-const NSModalResponseOK: NSInteger = 1;
-const NSModalResponseCancel: NSInteger = 0;
+pub(crate) const NSModalResponseOK: NSInteger = 1;
+pub(crate) const NSModalResponseCancel: NSInteger = 0;
 
 pub(crate) unsafe fn get_file_info(
     panel: id,
@@ -0,0 +1,256 @@
+//! Measuring when frames reach the screen with a `CVDisplayLink`, and pacing animation frames
+//! with it.
+//!
+//! The link's callback runs on a thread owned by CoreVideo once per refresh of the display the
+//! window is on, at whatever rate the display is running, which changes on ProMotion displays.
+//! Requested frames are painted on the main thread after the next callback.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use cocoa::base::{id, nil, NO};
+use cocoa::foundation::NSAutoreleasePool;
+use objc::rc::WeakPtr;
+use objc::{msg_send, sel, sel_impl};
+
+use crate::common_util::FrameTimingsCache;
+
+type CVDisplayLinkRef = *mut c_void;
+type CVReturn = i32;
+
+const K_CV_RETURN_SUCCESS: CVReturn = 0;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct CVSMPTETime {
+    subframes: i16,
+    subframeDivisor: i16,
+    counter: u32,
+    type_: u32,
+    flags: u32,
+    hours: i16,
+    minutes: i16,
+    seconds: i16,
+    frames: i16,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct CVTimeStamp {
+    version: u32,
+    videoTimeScale: i32,
+    videoTime: i64,
+    hostTime: u64,
+    rateScalar: f64,
+    videoRefreshPeriod: i64,
+    smpteTime: CVSMPTETime,
+    flags: u64,
+    reserved: u64,
+}
+
+type CVDisplayLinkOutputCallback = extern "C" fn(
+    display_link: CVDisplayLinkRef,
+    now: *const CVTimeStamp,
+    output_time: *const CVTimeStamp,
+    flags_in: u64,
+    flags_out: *mut u64,
+    context: *mut c_void,
+) -> CVReturn;
+
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVDisplayLinkCreateWithActiveCGDisplays(display_link: *mut CVDisplayLinkRef) -> CVReturn;
+    fn CVDisplayLinkSetCurrentCGDisplay(display_link: CVDisplayLinkRef, display: u32) -> CVReturn;
+    fn CVDisplayLinkSetOutputCallback(
+        display_link: CVDisplayLinkRef,
+        callback: CVDisplayLinkOutputCallback,
+        context: *mut c_void,
+    ) -> CVReturn;
+    fn CVDisplayLinkStart(display_link: CVDisplayLinkRef) -> CVReturn;
+    fn CVDisplayLinkStop(display_link: CVDisplayLinkRef) -> CVReturn;
+    fn CVDisplayLinkRelease(display_link: CVDisplayLinkRef);
+    fn CVGetCurrentHostTime() -> u64;
+    fn CVGetHostClockFrequency() -> f64;
+}
+
+/// A running display link, which reports every vsync to a [`FrameTimingsCache`], and has the
+/// view redraw after the vsync following a call to [`request_frame`](Self::request_frame).
+///
+/// The callback runs on a thread owned by CoreVideo, so the cache is shared with it.
+pub(crate) struct DisplayLink {
+    link: CVDisplayLinkRef,
+    /// Owned by the display link's callback, and freed once the link has stopped.
+    context: *mut Context,
+}
+
+struct Context {
+    frame_timings: FrameTimingsCache,
+    /// The view is sent `deliverPresentFeedback` on the main thread when there is feedback
+    /// for its handler. Weak references can be loaded from any thread, and won't resurrect a
+    /// view which is being deallocated.
+    view: WeakPtr,
+    /// Whether the view is sent `redraw` after the next vsync.
+    frame_requested: AtomicBool,
+    /// The shortest time between the vsyncs frames are painted for, in host clock units, or 0
+    /// to paint for every vsync.
+    frame_interval: AtomicU64,
+    /// The output time of the last vsync a frame was painted for.
+    last_frame: AtomicU64,
+}
+
+impl DisplayLink {
+    pub fn start(frame_timings: FrameTimingsCache, view: id) -> Option<DisplayLink> {
+        unsafe {
+            let mut link = std::ptr::null_mut();
+            if CVDisplayLinkCreateWithActiveCGDisplays(&mut link) != K_CV_RETURN_SUCCESS {
+                tracing::warn!("failed to create a display link");
+                return None;
+            }
+            let context = Box::into_raw(Box::new(Context {
+                frame_timings,
+                view: WeakPtr::new(view),
+                frame_requested: AtomicBool::new(false),
+                frame_interval: AtomicU64::new(0),
+                last_frame: AtomicU64::new(0),
+            }));
+            // The link is stopped and its context freed when this is dropped.
+            let display_link = DisplayLink { link, context };
+            if CVDisplayLinkSetOutputCallback(link, output_callback, context.cast())
+                != K_CV_RETURN_SUCCESS
+                || CVDisplayLinkStart(link) != K_CV_RETURN_SUCCESS
+            {
+                tracing::warn!("failed to start a display link");
+                return None;
+            }
+            Some(display_link)
+        }
+    }
+
+    /// Have the view sent `redraw` on the main thread after the next vsync. Any number of calls
+    /// before then redraw it once.
+    pub fn request_frame(&self) {
+        unsafe { &*self.context }
+            .frame_requested
+            .store(true, Ordering::Release);
+    }
+
+    /// Skip vsyncs, so that frames are painted at least `interval` apart. `None` paints a frame
+    /// for every vsync.
+    pub fn set_frame_interval(&self, interval: Option<Duration>) {
+        let interval = interval.map_or(0, |interval| unsafe {
+            (interval.as_secs_f64() * CVGetHostClockFrequency()) as u64
+        });
+        unsafe { &*self.context }
+            .frame_interval
+            .store(interval, Ordering::Relaxed);
+    }
+
+    /// Follow the refresh of `display`, which is the `CGDirectDisplayID` of the screen the
+    /// window moved to.
+    pub fn set_display(&self, display: u32) {
+        if unsafe { CVDisplayLinkSetCurrentCGDisplay(self.link, display) } != K_CV_RETURN_SUCCESS {
+            tracing::warn!("failed to move a display link to display {display}");
+        }
+    }
+}
+
+impl Drop for DisplayLink {
+    fn drop(&mut self) {
+        unsafe {
+            // Stopping waits for the callback to return, so the context is no longer used.
+            CVDisplayLinkStop(self.link);
+            CVDisplayLinkRelease(self.link);
+            drop(Box::from_raw(self.context));
+        }
+    }
+}
+
+extern "C" fn output_callback(
+    _: CVDisplayLinkRef,
+    _: *const CVTimeStamp,
+    output_time: *const CVTimeStamp,
+    _: u64,
+    _: *mut u64,
+    context: *mut c_void,
+) -> CVReturn {
+    unsafe {
+        let context = &*(context as *const Context);
+        let frame_timings = &context.frame_timings;
+        let output_time = &*output_time;
+        // The output time is when the frame being composited now will be shown, which is the
+        // first vsync a frame painted before this callback can make.
+        if let Some(vsync) = host_time_to_instant(output_time.hostTime) {
+            let refresh = (output_time.videoTimeScale > 0).then(|| {
+                Duration::from_secs_f64(
+                    output_time.videoRefreshPeriod as f64 / output_time.videoTimeScale as f64,
+                )
+            });
+            frame_timings.frame_presented(vsync, refresh);
+        }
+        let redraw = frame_due(context, output_time);
+        let feedback = frame_timings.has_feedback();
+        if redraw || feedback {
+            let pool = NSAutoreleasePool::new(nil);
+            let view = context.view.load();
+            if !view.is_null() {
+                if redraw {
+                    let () = msg_send![*view, performSelectorOnMainThread: sel!(redraw)
+                        withObject: nil waitUntilDone: NO];
+                }
+                if feedback {
+                    let () = msg_send![*view, performSelectorOnMainThread: sel!(deliverPresentFeedback)
+                        withObject: nil waitUntilDone: NO];
+                }
+            }
+            drop(view);
+            pool.drain();
+        }
+    }
+    K_CV_RETURN_SUCCESS
+}
+
+/// Whether a frame was requested, and enough time has passed since the last one, in which
+/// case the request is taken.
+fn frame_due(context: &Context, output_time: &CVTimeStamp) -> bool {
+    if !context.frame_requested.load(Ordering::Acquire) {
+        return false;
+    }
+    let interval = context.frame_interval.load(Ordering::Relaxed);
+    if interval > 0 {
+        // Allow for half a refresh of jitter, so that an interval which is a multiple of the
+        // refresh period isn't rounded up to the next one.
+        let half_refresh = (output_time.videoTimeScale > 0).then(|| unsafe {
+            output_time.videoRefreshPeriod as f64 / output_time.videoTimeScale as f64 / 2.0
+                * CVGetHostClockFrequency()
+        });
+        let since_last = output_time
+            .hostTime
+            .saturating_sub(context.last_frame.load(Ordering::Relaxed));
+        if since_last + (half_refresh.unwrap_or(0.0) as u64) < interval {
+            return false;
+        }
+    }
+    if !context.frame_requested.swap(false, Ordering::AcqRel) {
+        return false;
+    }
+    context
+        .last_frame
+        .store(output_time.hostTime, Ordering::Relaxed);
+    true
+}
+
+/// Converts a time in host clock units, which may be in the future, to an `Instant`.
+fn host_time_to_instant(host_time: u64) -> Option<Instant> {
+    let (now_host, frequency) = unsafe { (CVGetCurrentHostTime(), CVGetHostClockFrequency()) };
+    let now = Instant::now();
+    if host_time >= now_host {
+        now.checked_add(Duration::from_secs_f64(
+            (host_time - now_host) as f64 / frequency,
+        ))
+    } else {
+        now.checked_sub(Duration::from_secs_f64(
+            (now_host - host_time) as f64 / frequency,
+        ))
+    }
+}
@@ -0,0 +1,119 @@
+//! Polling file descriptors on the main run loop with `CFFileDescriptor`.
+
+#![allow(non_upper_case_globals)]
+
+use std::ffi::c_void;
+use std::os::raw::c_int;
+use std::os::unix::io::RawFd;
+
+use crate::application::EventSourceToken;
+
+type CFFileDescriptorRef = *mut c_void;
+type CFRunLoopSourceRef = *mut c_void;
+type CFRunLoopRef = *mut c_void;
+type CFStringRef = *const c_void;
+type CFAllocatorRef = *const c_void;
+type CFIndex = isize;
+type CFOptionFlags = usize;
+type Boolean = u8;
+
+const kCFFileDescriptorReadCallBack: CFOptionFlags = 1;
+
+/// Called on the main thread when a file descriptor can be read, with the source's token as
+/// `info`.
+pub(crate) type Callout =
+    extern "C" fn(file_descriptor: *mut c_void, types: usize, info: *mut c_void);
+
+#[repr(C)]
+struct CFFileDescriptorContext {
+    version: CFIndex,
+    info: *mut c_void,
+    retain: *const c_void,
+    release: *const c_void,
+    copy_description: *const c_void,
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopCommonModes: CFStringRef;
+    fn CFFileDescriptorCreate(
+        allocator: CFAllocatorRef,
+        fd: c_int,
+        close_on_invalidate: Boolean,
+        callout: Callout,
+        context: *const CFFileDescriptorContext,
+    ) -> CFFileDescriptorRef;
+    fn CFFileDescriptorEnableCallBacks(file_descriptor: CFFileDescriptorRef, types: CFOptionFlags);
+    fn CFFileDescriptorInvalidate(file_descriptor: CFFileDescriptorRef);
+    fn CFFileDescriptorCreateRunLoopSource(
+        allocator: CFAllocatorRef,
+        file_descriptor: CFFileDescriptorRef,
+        order: CFIndex,
+    ) -> CFRunLoopSourceRef;
+    fn CFRunLoopGetMain() -> CFRunLoopRef;
+    fn CFRunLoopAddSource(run_loop: CFRunLoopRef, source: CFRunLoopSourceRef, mode: CFStringRef);
+    fn CFRelease(cf: *const c_void);
+}
+
+/// A file descriptor watched by the main run loop, which is removed from it when dropped.
+pub(crate) struct FileDescriptorSource(CFFileDescriptorRef);
+
+impl FileDescriptorSource {
+    /// Watch `fd`, calling `callout` when it can be read.
+    ///
+    /// Like all `CFFileDescriptor` callbacks, this only fires once, until it is
+    /// [enabled](Self::enable) again.
+    pub fn new(
+        fd: RawFd,
+        token: EventSourceToken,
+        callout: Callout,
+    ) -> Option<FileDescriptorSource> {
+        let context = CFFileDescriptorContext {
+            version: 0,
+            info: token.into_raw() as *mut c_void,
+            retain: std::ptr::null(),
+            release: std::ptr::null(),
+            copy_description: std::ptr::null(),
+        };
+        unsafe {
+            // We don't own the file descriptor, so it isn't closed with the source.
+            let file_descriptor =
+                CFFileDescriptorCreate(std::ptr::null(), fd, 0, callout, &context);
+            if file_descriptor.is_null() {
+                tracing::warn!("failed to create a CFFileDescriptor for {}", fd);
+                return None;
+            }
+            let source = CFFileDescriptorCreateRunLoopSource(std::ptr::null(), file_descriptor, 0);
+            if source.is_null() {
+                tracing::warn!("failed to create a run loop source for {}", fd);
+                CFRelease(file_descriptor);
+                return None;
+            }
+            // Common modes include the ones used while tracking menus and resizing windows.
+            CFRunLoopAddSource(CFRunLoopGetMain(), source, kCFRunLoopCommonModes);
+            // The run loop keeps the source alive, and invalidating the file descriptor removes it.
+            CFRelease(source);
+            let source = FileDescriptorSource(file_descriptor);
+            source.enable();
+            Some(source)
+        }
+    }
+
+    pub fn enable(&self) {
+        unsafe { CFFileDescriptorEnableCallBacks(self.0, kCFFileDescriptorReadCallBack) }
+    }
+
+    /// Recovers the token passed to [`new`](Self::new) from a callout's `info`.
+    pub fn token(info: *mut c_void) -> EventSourceToken {
+        EventSourceToken::from_raw(info as u64)
+    }
+}
+
+impl Drop for FileDescriptorSource {
+    fn drop(&mut self) {
+        unsafe {
+            CFFileDescriptorInvalidate(self.0);
+            CFRelease(self.0);
+        }
+    }
+}
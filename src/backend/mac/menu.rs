@@ -14,15 +14,16 @@
 
 //! macOS implementation of menus.
 
-use cocoa::appkit::{NSEventModifierFlags, NSMenu, NSMenuItem};
-use cocoa::base::{id, nil, NO};
+use cocoa::appkit::{NSApp, NSEventModifierFlags, NSMenu, NSMenuItem};
+use cocoa::base::{id, nil, NO, YES};
 use cocoa::foundation::{NSAutoreleasePool, NSString};
-use objc::{msg_send, sel, sel_impl};
+use objc::{class, msg_send, sel, sel_impl};
 
 use super::util::make_nsstring;
 use crate::common_util::strip_access_key;
 use crate::hotkey::HotKey;
 use crate::keyboard::{KbKey, Modifiers};
+use crate::platform::mac::{MenuExt, SystemAction};
 
 pub struct Menu {
     pub menu: id,
@@ -113,6 +114,99 @@ impl Menu {
     }
 }
 
+impl MenuExt for crate::Menu {
+    fn add_system_item(&mut self, action: SystemAction, text: &str, key: Option<&HotKey>) {
+        let action = match action {
+            SystemAction::ToggleTabBar => sel!(toggleTabBar:),
+            SystemAction::ToggleTabOverview => sel!(toggleTabOverview:),
+            SystemAction::MergeAllWindows => sel!(mergeAllWindows:),
+            SystemAction::MoveTabToNewWindow => sel!(moveTabToNewWindow:),
+            SystemAction::SelectNextTab => sel!(selectNextTab:),
+            SystemAction::SelectPreviousTab => sel!(selectPreviousTab:),
+        };
+        let key_equivalent = key.map(HotKey::key_equivalent).unwrap_or("");
+        unsafe {
+            // Without a target, the action goes along the responder chain to the key window.
+            let item = NSMenuItem::alloc(nil)
+                .initWithTitle_action_keyEquivalent_(
+                    make_nsstring(&strip_access_key(text)),
+                    action,
+                    make_nsstring(key_equivalent),
+                )
+                .autorelease();
+            if let Some(mask) = key.map(HotKey::key_modifier_mask) {
+                let () = msg_send![item, setKeyEquivalentModifierMask: mask];
+            }
+            self.0.menu.addItem_(item);
+        }
+    }
+
+    fn add_recent_documents(&mut self, text: &str, clear_text: &str) {
+        let recent = Menu::new();
+        unsafe {
+            let controller: id = msg_send![class!(NSDocumentController), sharedDocumentController];
+            let clear = NSMenuItem::alloc(nil)
+                .initWithTitle_action_keyEquivalent_(
+                    make_nsstring(&strip_access_key(clear_text)),
+                    sel!(clearRecentDocuments:),
+                    make_nsstring(""),
+                )
+                .autorelease();
+            let () = msg_send![clear, setTarget: controller];
+            recent.menu.addItem_(clear);
+            // The app delegate puts the documents above the clear item whenever the menu opens.
+            let delegate: id = msg_send![NSApp(), delegate];
+            let () = msg_send![recent.menu, setDelegate: delegate];
+        }
+        self.0.add_dropdown(recent, text, true);
+    }
+
+    fn add_services(&mut self, text: &str) {
+        let services = Menu::new();
+        unsafe {
+            let () = msg_send![NSApp(), setServicesMenu: services.menu];
+        }
+        self.0.add_dropdown(services, text, true);
+    }
+}
+
+/// Fill the "Open Recent" menu with AppKit's list of recent documents, above its clear item.
+pub(crate) fn update_recent_documents(menu: id, delegate: id) {
+    unsafe {
+        let count: isize = msg_send![menu, numberOfItems];
+        for _ in 1..count {
+            let () = msg_send![menu, removeItemAtIndex: 0_isize];
+        }
+        let controller: id = msg_send![class!(NSDocumentController), sharedDocumentController];
+        let urls: id = msg_send![controller, recentDocumentURLs];
+        let url_count: usize = msg_send![urls, count];
+        let file_manager: id = msg_send![class!(NSFileManager), defaultManager];
+        for i in 0..url_count {
+            let url: id = msg_send![urls, objectAtIndex: i];
+            let path: id = msg_send![url, path];
+            let title: id = msg_send![file_manager, displayNameAtPath: path];
+            let item = NSMenuItem::alloc(nil)
+                .initWithTitle_action_keyEquivalent_(
+                    title,
+                    sel!(openRecentDocument:),
+                    make_nsstring(""),
+                )
+                .autorelease();
+            let () = msg_send![item, setTarget: delegate];
+            let () = msg_send![item, setRepresentedObject: url];
+            let () = msg_send![menu, insertItem: item atIndex: i as isize];
+        }
+        if url_count > 0 {
+            let sep = id::separatorItem(menu);
+            let () = msg_send![menu, insertItem: sep atIndex: url_count as isize];
+        }
+        let count: isize = msg_send![menu, numberOfItems];
+        let clear: id = msg_send![menu, itemAtIndex: count - 1];
+        let enabled = if url_count > 0 { YES } else { NO };
+        let () = msg_send![clear, setEnabled: enabled];
+    }
+}
+
 impl HotKey {
     /// Return the string value of this hotkey, for use with Cocoa `NSResponder`
     /// objects.
@@ -20,9 +20,12 @@ pub mod appkit;
 pub mod application;
 pub mod clipboard;
 pub mod dialog;
+mod display_link;
 pub mod error;
+mod event_source;
 mod keyboard;
 pub mod menu;
+mod notification;
 pub mod screen;
 pub mod text_input;
 pub mod util;
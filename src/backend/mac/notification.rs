@@ -0,0 +1,273 @@
+//! Notifications with the UserNotifications framework.
+//!
+//! The app delegate is also the notification center's delegate, which is how it hears about
+//! the user clicking a notification.
+
+use std::cell::RefCell;
+use std::path::Path;
+
+use anyhow::anyhow;
+use block::ConcreteBlock;
+use cocoa::base::{id, nil, BOOL, NO};
+use cocoa::foundation::{NSArray, NSUInteger};
+use objc::{class, msg_send, sel, sel_impl};
+
+use super::application::AppHandle;
+use super::util::{from_nsstring, make_nsstring};
+use crate::error::Error as ShellError;
+use crate::{Notification, NotificationId};
+
+#[link(name = "UserNotifications", kind = "framework")]
+extern "C" {}
+
+const UN_AUTHORIZATION_OPTION_SOUND: NSUInteger = 1 << 1;
+const UN_AUTHORIZATION_OPTION_ALERT: NSUInteger = 1 << 2;
+const UN_NOTIFICATION_ACTION_OPTION_FOREGROUND: NSUInteger = 1 << 2;
+/// Shows notifications as banners with a sound while we're in front, as they are while we
+/// aren't, instead of dropping them.
+pub(super) const UN_NOTIFICATION_PRESENTATION_OPTIONS: NSUInteger = (1 << 1) | (1 << 2);
+
+const DEFAULT_ACTION_IDENTIFIER: &str = "com.apple.UNNotificationDefaultActionIdentifier";
+const DISMISS_ACTION_IDENTIFIER: &str = "com.apple.UNNotificationDismissActionIdentifier";
+
+thread_local! {
+    /// The sets of actions we've registered categories for, where each category's identifier
+    /// is its index.
+    ///
+    /// The notification center only lets us replace all of the categories at once.
+    static CATEGORIES: RefCell<Vec<Vec<String>>> = RefCell::new(Vec::new());
+}
+
+/// The notification center, which throws an exception if we aren't in an application bundle.
+unsafe fn center() -> Option<id> {
+    let bundle: id = msg_send![class!(NSBundle), mainBundle];
+    let identifier: id = msg_send![bundle, bundleIdentifier];
+    if identifier == nil {
+        return None;
+    }
+    let center: id = msg_send![class!(UNUserNotificationCenter), currentNotificationCenter];
+    Some(center)
+}
+
+/// Make `delegate` hear about notifications being clicked, including the one which launched
+/// the application.
+pub(super) unsafe fn set_delegate(delegate: id) {
+    if let Some(center) = center() {
+        let () = msg_send![center, setDelegate: delegate];
+    }
+}
+
+pub(super) fn send(
+    notification: &Notification,
+    id: NotificationId,
+    handle: AppHandle,
+) -> Result<(), ShellError> {
+    unsafe {
+        let center = center().ok_or(ShellError::Unsupported(
+            "notifications outside of an application bundle",
+        ))?;
+        let content: id = msg_send![class!(UNMutableNotificationContent), new];
+        let () = msg_send![content, setTitle: make_nsstring(&notification.title)];
+        let () = msg_send![content, setBody: make_nsstring(&notification.body)];
+        if !notification.actions.is_empty() {
+            let category = category(center, &notification.actions);
+            let () = msg_send![content, setCategoryIdentifier: category];
+        }
+        if let Some(path) = &notification.image {
+            match attachment(path, id) {
+                Ok(attachment) => {
+                    let attachments = NSArray::arrayWithObject(nil, attachment);
+                    let () = msg_send![content, setAttachments: attachments];
+                }
+                Err(e) => tracing::warn!("the notification's image can't be shown: {e}"),
+            }
+        }
+        let request: id = msg_send![class!(UNNotificationRequest),
+            requestWithIdentifier: identifier(id)
+            content: content
+            trigger: nil];
+        let () = msg_send![content, release];
+
+        // The user is only asked the first time, after which this answers straight away.
+        // The request is released by the handler, which is called once.
+        let () = msg_send![request, retain];
+        let authorized = ConcreteBlock::new(move |granted: BOOL, _error: id| {
+            if granted == NO {
+                failed(&handle, id, ShellError::PermissionDenied("notifications"));
+            } else {
+                let handle = handle.clone();
+                let added = ConcreteBlock::new(move |error: id| {
+                    if error != nil {
+                        let description: id = msg_send![error, localizedDescription];
+                        let description = from_nsstring(description);
+                        let error = anyhow!("failed to show the notification: {description}");
+                        failed(&handle, id, error.into());
+                    }
+                })
+                .copy();
+                let () = msg_send![center,
+                    addNotificationRequest: request
+                    withCompletionHandler: &*added];
+            }
+            let () = msg_send![request, release];
+        })
+        .copy();
+        let () = msg_send![center,
+            requestAuthorizationWithOptions: UN_AUTHORIZATION_OPTION_ALERT
+                | UN_AUTHORIZATION_OPTION_SOUND
+            completionHandler: &*authorized];
+        Ok(())
+    }
+}
+
+pub(super) fn close(id: NotificationId) {
+    unsafe {
+        let Some(center) = center() else {
+            return;
+        };
+        let identifiers = NSArray::arrayWithObject(nil, identifier(id));
+        let () = msg_send![center, removePendingNotificationRequestsWithIdentifiers: identifiers];
+        let () = msg_send![center, removeDeliveredNotificationsWithIdentifiers: identifiers];
+    }
+}
+
+/// The notification and action the user chose, from a `UNNotificationResponse`.
+///
+/// The outer `None` is for responses which aren't activations, such as the user dismissing
+/// the notification.
+pub(super) unsafe fn activation(response: id) -> Option<(NotificationId, Option<usize>)> {
+    let notification: id = msg_send![response, notification];
+    let request: id = msg_send![notification, request];
+    let identifier: id = msg_send![request, identifier];
+    let id = parse_request_identifier(&from_nsstring(identifier))?;
+    let action: id = msg_send![response, actionIdentifier];
+    Some((id, parse_action_identifier(&from_nsstring(action))?))
+}
+
+/// The identifier of the request for the notification with `id`.
+fn request_identifier(id: NotificationId) -> String {
+    id.into_raw().to_string()
+}
+
+fn parse_request_identifier(identifier: &str) -> Option<NotificationId> {
+    identifier.parse().ok().map(NotificationId::from_raw)
+}
+
+/// The identifier of the action with `index`, in the notification's category.
+fn action_identifier(index: usize) -> String {
+    index.to_string()
+}
+
+/// The action the user chose, where `None` is the notification itself.
+///
+/// The outer `None` is for the user dismissing the notification, and for actions which we
+/// didn't add.
+fn parse_action_identifier(identifier: &str) -> Option<Option<usize>> {
+    match identifier {
+        DEFAULT_ACTION_IDENTIFIER => Some(None),
+        DISMISS_ACTION_IDENTIFIER => None,
+        action => Some(Some(action.parse().ok()?)),
+    }
+}
+
+fn failed(handle: &AppHandle, id: NotificationId, error: ShellError) {
+    handle.run_on_main(move |handler| {
+        if let Some(handler) = handler {
+            handler.notification_failed(id, error);
+        }
+    });
+}
+
+fn identifier(id: NotificationId) -> id {
+    make_nsstring(&request_identifier(id))
+}
+
+/// The identifier of the category for `actions`, registering it if it's new.
+unsafe fn category(center: id, actions: &[String]) -> id {
+    let index = CATEGORIES.with(|categories| {
+        let mut categories = categories.borrow_mut();
+        if let Some(index) = categories.iter().position(|it| it == actions) {
+            return index;
+        }
+        categories.push(actions.to_vec());
+        let all = categories
+            .iter()
+            .enumerate()
+            .map(|(index, actions)| {
+                let actions = actions
+                    .iter()
+                    .enumerate()
+                    .map(|(index, label)| {
+                        // Choosing an action brings the application to the front, like clicking
+                        // the notification itself does
+                        let action: id = msg_send![class!(UNNotificationAction),
+                            actionWithIdentifier: make_nsstring(&action_identifier(index))
+                            title: make_nsstring(label)
+                            options: UN_NOTIFICATION_ACTION_OPTION_FOREGROUND];
+                        action
+                    })
+                    .collect::<Vec<id>>();
+                let category: id = msg_send![class!(UNNotificationCategory),
+                    categoryWithIdentifier: make_nsstring(&index.to_string())
+                    actions: NSArray::arrayWithObjects(nil, &actions)
+                    intentIdentifiers: NSArray::array(nil)
+                    options: 0 as NSUInteger];
+                category
+            })
+            .collect::<Vec<id>>();
+        let set: id = msg_send![class!(NSSet), setWithArray: NSArray::arrayWithObjects(nil, &all)];
+        let () = msg_send![center, setNotificationCategories: set];
+        categories.len() - 1
+    });
+    make_nsstring(&index.to_string())
+}
+
+/// An attachment showing the image at `path`.
+///
+/// The notification center moves the file into its own storage, so it gets a copy.
+unsafe fn attachment(path: &Path, id: NotificationId) -> Result<id, anyhow::Error> {
+    let extension = path.extension().and_then(|it| it.to_str()).unwrap_or("png");
+    let copy = std::env::temp_dir().join(format!(
+        "glazier-notification-{}.{extension}",
+        id.into_raw()
+    ));
+    std::fs::copy(path, &copy)?;
+    let copy = copy
+        .to_str()
+        .ok_or_else(|| anyhow!("the path isn't valid UTF-8"))?;
+    let url: id = msg_send![class!(NSURL), fileURLWithPath: make_nsstring(copy)];
+    let mut error = nil;
+    let attachment: id = msg_send![class!(UNNotificationAttachment),
+        attachmentWithIdentifier: make_nsstring("image")
+        URL: url
+        options: nil
+        error: &mut error];
+    if attachment == nil {
+        let description: id = msg_send![error, localizedDescription];
+        return Err(anyhow!(from_nsstring(description)));
+    }
+    Ok(attachment)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identifiers_round_trip() {
+        let id = NotificationId::from_raw(42);
+        assert_eq!(parse_request_identifier(&request_identifier(id)), Some(id));
+        assert_eq!(parse_request_identifier("not an id"), None);
+
+        for index in 0..Notification::MAX_ACTIONS {
+            let identifier = action_identifier(index);
+            assert_eq!(parse_action_identifier(&identifier), Some(Some(index)));
+        }
+        assert_eq!(
+            parse_action_identifier(DEFAULT_ACTION_IDENTIFIER),
+            Some(None)
+        );
+        assert_eq!(parse_action_identifier(DISMISS_ACTION_IDENTIFIER), None);
+        assert_eq!(parse_action_identifier("com.example.reply"), None);
+    }
+}
@@ -16,25 +16,42 @@
 
 #![allow(non_snake_case)]
 
+use std::cell::Cell;
 use std::ffi::c_void;
 use std::mem;
+use std::ptr::NonNull;
 use std::sync::{Arc, Mutex, Weak};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "accesskit")]
 use accesskit_macos::Adapter as AccessKitAdapter;
 use block::ConcreteBlock;
 use cocoa::appkit::{
-    CGFloat, NSApp, NSApplication, NSAutoresizingMaskOptions, NSBackingStoreBuffered, NSColor,
-    NSEvent, NSView, NSViewHeightSizable, NSViewWidthSizable, NSWindow, NSWindowStyleMask,
+    CGFloat, NSApp, NSApplication, NSApplicationPresentationOptions, NSAutoresizingMaskOptions,
+    NSBackingStoreBuffered, NSColor, NSEvent, NSEventMask, NSEventType, NSView,
+    NSViewHeightSizable, NSViewNotSizable, NSViewWidthSizable, NSWindow,
+    NSWindowCollectionBehavior, NSWindowStyleMask,
 };
 use cocoa::base::{id, nil, BOOL, NO, YES};
 use cocoa::foundation::{
-    NSArray, NSAutoreleasePool, NSInteger, NSPoint, NSRect, NSSize, NSString, NSUInteger,
+    NSArray, NSAutoreleasePool, NSDefaultRunLoopMode, NSInteger, NSPoint, NSRect, NSSize, NSString,
+    NSUInteger,
+};
+use core_graphics::base::{
+    kCGBitmapByteOrder32Little, kCGImageAlphaNoneSkipFirst, kCGImageAlphaNoneSkipLast,
+    kCGImageAlphaPremultipliedLast, kCGRenderingIntentDefault,
+};
+use core_graphics::color_space::CGColorSpace;
+use core_graphics::context::CGContext;
+use core_graphics::data_provider::CGDataProvider;
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+use core_graphics::image::{CGImage, CGImageRef};
+use core_graphics::window::{
+    create_image, kCGWindowImageBestResolution, kCGWindowListOptionIncludingWindow, CGWindowID,
 };
 use lazy_static::lazy_static;
 use objc::declare::ClassDecl;
-use objc::rc::WeakPtr;
+use objc::rc::{StrongPtr, WeakPtr};
 use objc::runtime::{Class, Object, Protocol, Sel};
 use objc::{class, msg_send, sel, sel_impl};
 #[cfg(feature = "accesskit")]
@@ -42,40 +59,52 @@ use once_cell::unsync::OnceCell;
 use tracing::{debug, info};
 
 use raw_window_handle::{
-    AppKitDisplayHandle, AppKitWindowHandle, HasRawDisplayHandle, HasRawWindowHandle,
-    RawDisplayHandle, RawWindowHandle,
+    AppKitDisplayHandle, AppKitWindowHandle, DisplayHandle, HandleError, HasDisplayHandle,
+    HasWindowHandle, RawDisplayHandle, RawWindowHandle,
 };
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
 
 use crate::kurbo::{Insets, Point, Rect, Size, Vec2};
 
 use super::appkit::{
-    NSRunLoopCommonModes, NSTrackingArea, NSTrackingAreaOptions, NSView as NSViewExt,
+    NSAppearanceNameAqua, NSAppearanceNameDarkAqua, NSRunLoopCommonModes, NSTrackingArea,
+    NSTrackingAreaOptions, NSView as NSViewExt,
 };
-use super::application::Application;
+use super::application::{self, Application};
 use super::dialog;
+use super::display_link::DisplayLink;
 use super::keyboard::{make_modifiers, KeyboardState};
 use super::menu::Menu;
 use super::text_input::NSRange;
 use super::util::{assert_main_thread, make_nsstring};
-use crate::common_util::IdleCallback;
+use crate::common_util::{
+    self, FrameSchedule, FrameThrottle, FrameTimingsCache, IdleCallback, PaintRequests,
+};
 use crate::dialog::{FileDialogOptions, FileDialogType};
+use crate::keyboard::{KbKey, KeyEvent};
 use crate::keyboard_types::KeyState;
 use crate::mouse::{Cursor, CursorDesc, MouseButton, MouseButtons, MouseEvent};
+use crate::platform::mac::ColorSpace;
 use crate::region::Region;
 use crate::scale::Scale;
 use crate::text::{Event, InputHandler};
 use crate::window::{
-    FileDialogToken, IdleToken, TextFieldToken, TimerToken, WinHandler, WindowLevel, WindowState,
+    FileDialogToken, FocusDirection, FrameTimings, IdleToken, ImageBuf, MaximizeMode,
+    ModalResponse, PixelFormat, PopupConfig, StackingLevel, TextFieldToken, Theme, TimerToken,
+    Visibility, WinHandler, WindowLevel, WindowState, WindowType,
 };
 use crate::Error;
 
 #[allow(non_upper_case_globals)]
 const NSWindowDidBecomeKeyNotification: &str = "NSWindowDidBecomeKeyNotification";
+#[allow(non_upper_case_globals)]
+const NSWindowAbove: NSInteger = 1;
 
 #[allow(dead_code)]
 #[allow(non_upper_case_globals)]
 mod levels {
-    use crate::window::WindowLevel;
+    use crate::window::{StackingLevel, WindowLevel};
 
     // These are the levels that AppKit seems to have.
     pub const NSModalPanelLevel: i32 = 24;
@@ -97,6 +126,24 @@ mod levels {
             Modal(_) => NSModalPanelWindowLevel,
         }
     }
+
+    const kCGDesktopWindowLevelKey: i32 = 2;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGWindowLevelForKey(key: i32) -> i32;
+    }
+
+    pub fn as_raw_stacking_level(level: StackingLevel) -> i32 {
+        match level {
+            StackingLevel::Normal => NSNormalWindowLevel,
+            StackingLevel::AlwaysOnBottom => NSNormalWindowLevel - 1,
+            StackingLevel::AlwaysOnTop => NSFloatingWindowLevel,
+            StackingLevel::DesktopWidget => unsafe {
+                CGWindowLevelForKey(kCGDesktopWindowLevelKey)
+            },
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -141,14 +188,27 @@ pub(crate) struct WindowBuilder {
     handler: Option<Box<dyn WinHandler>>,
     title: String,
     menu: Option<Menu>,
-    size: Size,
+    /// When this isn't set, windows are 500 by 400 points, and child windows fill their parent
+    size: Option<Size>,
     min_size: Option<Size>,
     position: Option<Point>,
     level: Option<WindowLevel>,
+    window_type: Option<WindowType>,
     window_state: Option<WindowState>,
+    maximize_mode: MaximizeMode,
     resizable: bool,
     show_titlebar: bool,
     transparent: bool,
+    kiosk: bool,
+    visible: bool,
+    skip_taskbar: bool,
+    accepts_focus: bool,
+    managed_surface: bool,
+    coalesce_pointer_events: bool,
+    tabbing_identifier: Option<String>,
+    owner: Option<crate::WindowHandle>,
+    popup: Option<PopupConfig>,
+    frame_autosave_name: Option<String>,
 }
 
 #[derive(Clone)]
@@ -187,8 +247,55 @@ struct ViewState {
     /// Tracks whether we've installed a delegate on the sublayer
     installed_layer_delegate: bool,
     keyboard_state: KeyboardState,
+    /// The key which `performKeyEquivalent:` offered the handler, and which it didn't handle.
+    /// It isn't offered again when AppKit passes it on to `keyDown:`.
+    key_equivalent: Option<StrongPtr>,
     active_text_input: Option<TextFieldToken>,
     parent: Option<crate::WindowHandle>,
+    /// The window given to `WindowBuilder::with_parent`, which this is a child window of while
+    /// it is shown
+    owner: Option<crate::WindowHandle>,
+    /// The frame used when zooming the window
+    maximize_mode: MaximizeMode,
+    /// Kiosk windows ignore close requests, and own the application presentation options
+    kiosk: bool,
+    /// The visibility last reported to the handler
+    visibility: Visibility,
+    frame_throttle: FrameThrottle,
+    /// Whether `redraw` has already been queued, which the other requests wait for
+    paint_requests: PaintRequests,
+    frame_timings: FrameTimingsCache,
+    /// Started by the first call to `last_frame_timings` or `set_present_feedback`, or by the
+    /// first animation frame requested while `vsync` is set
+    display_link: Option<DisplayLink>,
+    /// The `CAMetalLayer` installed by `WindowExt::metal_layer`, or nil. We own a reference to it.
+    metal_layer: id,
+    /// Applied to `metal_layer` as `displaySyncEnabled`, and whether animation frames are paced
+    /// by `display_link`
+    vsync: bool,
+    /// Set by `WindowExt::set_preferred_frame_rate`, and applied to `display_link`
+    preferred_frame_rate: Option<f64>,
+    /// Whether we may install `metal_layer` or present to the view's layer, which is otherwise
+    /// left to the application
+    managed_surface: bool,
+    /// Whether the moves which are queued together are delivered as one event
+    coalesce_pointer_events: bool,
+    /// Whether the handler was last told that the window got focus. AppKit tells us about the
+    /// window becoming key both as its delegate and through the observer `show` registers.
+    has_focus: bool,
+    /// Whether `windowWillClose:` has been handled. A window can be closed more than once, such
+    /// as by `Application::quit` and by the window which owns it.
+    closed: bool,
+    /// Built inside a view we don't own, with `build_as_child`. The view's window belongs to the
+    /// host, so these have no title, menu or window state.
+    is_child: bool,
+    /// The scale `metal_layer` and the handler render at, instead of the backing scale factor.
+    backing_scale_override: Option<f64>,
+    /// Where `WindowExt::set_traffic_light_offset` has put the window buttons.
+    traffic_light_offset: Option<Point>,
+    /// The frames AppKit gave the titlebar container and the window buttons, before they were
+    /// first moved.
+    traffic_light_frames: Option<[NSRect; 4]>,
     #[cfg(feature = "accesskit")]
     accesskit_adapter: OnceCell<AccessKitAdapter>,
 }
@@ -203,14 +310,26 @@ impl WindowBuilder {
             handler: None,
             title: String::new(),
             menu: None,
-            size: Size::new(500., 400.),
+            size: None,
             min_size: None,
             position: None,
             level: None,
+            window_type: None,
             window_state: None,
+            maximize_mode: MaximizeMode::default(),
             resizable: true,
             show_titlebar: true,
             transparent: false,
+            kiosk: false,
+            visible: false,
+            skip_taskbar: false,
+            accepts_focus: true,
+            managed_surface: true,
+            coalesce_pointer_events: true,
+            tabbing_identifier: None,
+            owner: None,
+            popup: None,
+            frame_autosave_name: None,
         }
     }
 
@@ -220,7 +339,7 @@ impl WindowBuilder {
     }
 
     pub fn size(mut self, size: Size) -> Self {
-        self.size = size;
+        self.size = Some(size);
         self
     }
 
@@ -239,6 +358,11 @@ impl WindowBuilder {
         self
     }
 
+    pub fn prefer_client_side_decorations(self, _prefer: bool) -> Self {
+        // The system always draws the decorations
+        self
+    }
+
     pub fn transparent(mut self, transparent: bool) -> Self {
         self.transparent = transparent;
         self
@@ -249,6 +373,16 @@ impl WindowBuilder {
         self
     }
 
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = Some(window_type);
+        self
+    }
+
+    pub fn with_frame_autosave_name(mut self, name: &str, _fullscreen: bool) -> Self {
+        self.frame_autosave_name = Some(name.to_owned());
+        self
+    }
+
     pub fn position(mut self, position: Point) -> Self {
         self.position = Some(position);
         self
@@ -259,6 +393,66 @@ impl WindowBuilder {
         self
     }
 
+    pub fn maximize_mode(mut self, mode: MaximizeMode) -> Self {
+        self.maximize_mode = mode;
+        self
+    }
+
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self.kiosk = kiosk;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_skip_taskbar(mut self, skip: bool) -> Self {
+        self.skip_taskbar = skip;
+        self
+    }
+
+    pub fn with_accepts_focus(mut self, accepts_focus: bool) -> Self {
+        self.accepts_focus = accepts_focus;
+        self
+    }
+
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self.managed_surface = managed;
+        self
+    }
+
+    pub fn use_pointer_api(self, _enable: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_coalesced_pointer_events(mut self, coalesce: bool) -> Self {
+        self.coalesce_pointer_events = coalesce;
+        self
+    }
+
+    pub fn with_parent(mut self, parent: crate::WindowHandle) -> Self {
+        self.owner = Some(parent);
+        self
+    }
+
+    pub fn popup(mut self, popup: PopupConfig) -> Self {
+        if popup.grab {
+            tracing::warn!("grabbing popups is currently unimplemented for mac.");
+        }
+        self.popup = Some(popup);
+        self
+    }
+
+    pub fn offscreen(self, offscreen: bool) -> Self {
+        if offscreen {
+            tracing::warn!("WindowBuilder::offscreen is currently unimplemented for mac.");
+        }
+        self
+    }
+
     pub fn title(mut self, title: impl Into<String>) -> Self {
         self.title = title.into();
         self
@@ -269,8 +463,93 @@ impl WindowBuilder {
         self
     }
 
-    pub fn build(self) -> Result<WindowHandle, Error> {
+    pub fn build_as_child(self, parent: RawWindowHandle) -> Result<WindowHandle, Error> {
+        assert_main_thread();
+        let RawWindowHandle::AppKit(parent) = parent else {
+            return Err(Error::Unsupported(
+                "child windows of a window which isn't an AppKit view",
+            ));
+        };
+        if self.kiosk || self.popup.is_some() {
+            return Err(Error::IncompatibleOptions(
+                "a child window can't be a kiosk or popup window",
+            ));
+        }
+        unsafe {
+            let parent = parent.ns_view.as_ptr() as id;
+            let bounds: NSRect = msg_send![parent, bounds];
+            let size = self
+                .size
+                .unwrap_or(Size::new(bounds.size.width, bounds.size.height));
+            let position = self.position.unwrap_or(Point::ZERO);
+            let origin = child_origin(parent, position, size.height);
+            let frame = NSRect::new(origin, NSSize::new(size.width, size.height));
+
+            let (view, idle_queue) = make_view(self.handler.expect("view"));
+            view.initWithFrame_(frame);
+            // The host resizes the view with `set_size`, rather than AppKit along with its parent
+            view.setAutoresizingMask_(NSViewNotSizable);
+            let rect = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 0.));
+            let opts = NSTrackingAreaOptions::MouseEnteredAndExited
+                | NSTrackingAreaOptions::MouseMoved
+                | NSTrackingAreaOptions::ActiveAlways
+                | NSTrackingAreaOptions::InVisibleRect;
+            let tracking_area = NSTrackingArea::alloc(nil)
+                .initWithRect_options_owner_userInfo(rect, opts, view, nil)
+                .autorelease();
+            view.addTrackingArea(tracking_area);
+            // Until it is shown
+            let () = msg_send![view, setHidden: YES];
+            parent.addSubview_(view);
+
+            let view_state: *mut c_void = *(*view).get_ivar("viewState");
+            let view_state = &mut *(view_state as *mut ViewState);
+            view_state.is_child = true;
+            view_state.managed_surface = self.managed_surface;
+            view_state.coalesce_pointer_events = self.coalesce_pointer_events;
+            let () = msg_send![class!(NSEvent), setMouseCoalescingEnabled: NO];
+            let handle = WindowHandle {
+                nsview: view_state.nsview.clone(),
+                idle_queue,
+            };
+
+            view_state.handler.connect(&handle.clone().into());
+            view_state.handler.scale(Scale::default());
+            view_state.handler.size(size);
+            view_state.handler.visibility_changed(Visibility::Hidden);
+
+            check_if_layer_delegate_install_needed(view, view_state);
+
+            if self.visible {
+                handle.show();
+            }
+            Ok(handle)
+        }
+    }
+
+    pub fn build(mut self) -> Result<WindowHandle, Error> {
         assert_main_thread();
+        if self.kiosk && self.popup.is_some() {
+            return Err(Error::IncompatibleOptions(
+                "a popup can't be a kiosk window",
+            ));
+        }
+        let size = self.size.unwrap_or(Size::new(500., 400.));
+        if let Some(popup) = &self.popup {
+            // Our positions are relative to the screen, until the window has a parent
+            let origin = popup.origin(size);
+            self.position = Some(popup.parent.get_position() + origin.to_vec2());
+            self.level = Some(WindowLevel::DropDown(popup.parent.clone()));
+            self.show_titlebar = false;
+        }
+        if matches!(
+            self.window_type,
+            Some(
+                WindowType::Menu | WindowType::Tooltip | WindowType::Splash | WindowType::DropDown
+            )
+        ) {
+            self.show_titlebar = false;
+        }
         unsafe {
             let mut style_mask = NSWindowStyleMask::NSClosableWindowMask
                 | NSWindowStyleMask::NSMiniaturizableWindowMask;
@@ -285,17 +564,35 @@ impl WindowBuilder {
 
             let screen_height = crate::Screen::get_display_rect().height();
             let position = self.position.unwrap_or_else(|| Point::new(20., 20.));
-            let origin = NSPoint::new(position.x, screen_height - position.y - self.size.height); // Flip back
+            let origin = NSPoint::new(position.x, screen_height - position.y - size.height); // Flip back
 
-            let rect = NSRect::new(origin, NSSize::new(self.size.width, self.size.height));
+            let mut rect = NSRect::new(origin, NSSize::new(size.width, size.height));
+
+            if self.kiosk {
+                style_mask = NSWindowStyleMask::NSBorderlessWindowMask;
+                let screen: id = msg_send![class!(NSScreen), mainScreen];
+                rect = msg_send![screen, frame];
+            }
 
-            let window: id = msg_send![WINDOW_CLASS.0, alloc];
+            let class = if self.accepts_focus {
+                WINDOW_CLASS.0
+            } else {
+                // Clicking a non-activating panel doesn't activate the application, so the
+                // window which had focus keeps it.
+                style_mask |= NSWindowStyleMask::NSNonactivatingPanelMask;
+                PANEL_CLASS.0
+            };
+            let window: id = msg_send![class, alloc];
             let window = window.initWithContentRect_styleMask_backing_defer_(
                 rect,
                 style_mask,
                 NSBackingStoreBuffered,
                 NO,
             );
+            if !self.accepts_focus {
+                // Panels are hidden whenever the application is deactivated by default.
+                let () = msg_send![window, setHidesOnDeactivate: NO];
+            }
 
             if let Some(min_size) = self.min_size {
                 let size = NSSize::new(min_size.width, min_size.height);
@@ -308,6 +605,17 @@ impl WindowBuilder {
             }
 
             window.setTitle_(make_nsstring(&self.title));
+            if let Some(identifier) = &self.tabbing_identifier {
+                let () = msg_send![window, setTabbingIdentifier: make_nsstring(identifier)];
+            }
+            if let Some(name) = &self.frame_autosave_name {
+                if crate::autosave::enabled() {
+                    // AppKit keeps the frame in the user defaults, and saves it as it changes
+                    let name = make_nsstring(name);
+                    let _: BOOL = msg_send![window, setFrameUsingName: name];
+                    let _: BOOL = msg_send![window, setFrameAutosaveName: name];
+                }
+            }
 
             let (view, idle_queue) = make_view(self.handler.expect("view"));
             let content_view = window.contentView();
@@ -335,6 +643,14 @@ impl WindowBuilder {
             content_view.addSubview_(view);
             let view_state: *mut c_void = *(*view).get_ivar("viewState");
             let view_state = &mut *(view_state as *mut ViewState);
+            view_state.maximize_mode = self.maximize_mode;
+            view_state.kiosk = self.kiosk;
+            view_state.managed_surface = self.managed_surface;
+            view_state.coalesce_pointer_events = self.coalesce_pointer_events;
+            // AppKit would otherwise drop the moves which `mouse_move` merges itself, along with
+            // the ones windows which don't coalesce must see. This is for the whole application.
+            let () = msg_send![class!(NSEvent), setMouseCoalescingEnabled: NO];
+            view_state.owner = self.owner.take();
             let mut handle = WindowHandle {
                 nsview: view_state.nsview.clone(),
                 idle_queue,
@@ -353,6 +669,28 @@ impl WindowBuilder {
                 }
                 handle.set_level(level);
             }
+            // AppKit has no window types, so their levels are the closest equivalent
+            match self.window_type {
+                Some(WindowType::Menu | WindowType::Tooltip | WindowType::DropDown) => {
+                    let () = msg_send![window, setLevel: levels::NSPopUpMenuWindowLevel];
+                }
+                Some(WindowType::Utility) => {
+                    let () = msg_send![window, setLevel: levels::NSFloatingWindowLevel];
+                }
+                _ => (),
+            }
+
+            if self.kiosk {
+                let () = msg_send![window, setMovable: NO];
+                let () = msg_send![window, setLevel: levels::NSStatusWindowLevel];
+                let options = NSApplicationPresentationOptions::NSApplicationPresentationHideDock
+                    | NSApplicationPresentationOptions::NSApplicationPresentationHideMenuBar
+                    | NSApplicationPresentationOptions::NSApplicationPresentationDisableProcessSwitching
+                    | NSApplicationPresentationOptions::NSApplicationPresentationDisableForceQuit
+                    | NSApplicationPresentationOptions::NSApplicationPresentationDisableSessionTermination
+                    | NSApplicationPresentationOptions::NSApplicationPresentationDisableHideApplication;
+                NSApp().setPresentationOptions_(options);
+            }
 
             // set_window_state above could have invalidated the frame size
             let frame = NSView::frame(content_view);
@@ -362,9 +700,17 @@ impl WindowBuilder {
             view_state
                 .handler
                 .size(Size::new(frame.size.width, frame.size.height));
+            // We haven't been shown yet.
+            view_state.handler.visibility_changed(Visibility::Hidden);
 
             check_if_layer_delegate_install_needed(view, view_state);
 
+            if self.skip_taskbar {
+                handle.set_skip_taskbar(true);
+            }
+            if self.visible {
+                handle.show();
+            }
             Ok(handle)
         }
     }
@@ -394,6 +740,20 @@ lazy_static! {
         extern "C" fn acceptsFirstResponder(_this: &Object, _sel: Sel) -> BOOL {
             YES
         }
+        // The view takes keyboard input like a text field does, so it is in the window's key
+        // view loop whether or not Full Keyboard Access is turned on.
+        decl.add_method(
+            sel!(canBecomeKeyView),
+            canBecomeKeyView as extern "C" fn(&Object, Sel) -> BOOL,
+        );
+        extern "C" fn canBecomeKeyView(this: &Object, _sel: Sel) -> BOOL {
+            let hidden: BOOL = unsafe { msg_send![this, isHiddenOrHasHiddenAncestor] };
+            if hidden == YES {
+                NO
+            } else {
+                YES
+            }
+        }
         // acceptsFirstMouse is called when a left mouse click would focus the window
         decl.add_method(
             sel!(acceptsFirstMouse:),
@@ -412,10 +772,26 @@ lazy_static! {
             info!("view is dealloc'ed");
             unsafe {
                 let view_state: *mut c_void = *this.get_ivar("viewState");
-                drop(Box::from_raw(view_state as *mut ViewState));
+                let view_state = Box::from_raw(view_state as *mut ViewState);
+                if view_state.metal_layer != nil {
+                    let () = msg_send![view_state.metal_layer, release];
+                }
+                drop(view_state);
             }
         }
 
+        decl.add_method(
+            sel!(becomeFirstResponder),
+            become_first_responder as extern "C" fn(&mut Object, Sel) -> BOOL,
+        );
+        decl.add_method(
+            sel!(resignFirstResponder),
+            resign_first_responder as extern "C" fn(&mut Object, Sel) -> BOOL,
+        );
+        decl.add_method(
+            sel!(closeChild),
+            close_child as extern "C" fn(&mut Object, Sel),
+        );
         decl.add_method(
             sel!(windowDidBecomeKey:),
             window_did_become_key as extern "C" fn(&mut Object, Sel, id),
@@ -424,10 +800,42 @@ lazy_static! {
             sel!(windowDidResignKey:),
             window_did_resign_key as extern "C" fn(&mut Object, Sel, id),
         );
+        decl.add_method(
+            sel!(windowDidChangeOcclusionState:),
+            window_did_change_occlusion_state as extern "C" fn(&mut Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(windowDidChangeScreen:),
+            window_did_change_screen as extern "C" fn(&mut Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(windowDidResize:),
+            window_did_relayout_titlebar as extern "C" fn(&mut Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(windowDidExitFullScreen:),
+            window_did_relayout_titlebar as extern "C" fn(&mut Object, Sel, id),
+        );
         decl.add_method(
             sel!(setFrameSize:),
             set_frame_size as extern "C" fn(&mut Object, Sel, NSSize),
         );
+        decl.add_method(
+            sel!(viewWillStartLiveResize),
+            view_will_start_live_resize as extern "C" fn(&mut Object, Sel),
+        );
+        decl.add_method(
+            sel!(viewDidEndLiveResize),
+            view_did_end_live_resize as extern "C" fn(&mut Object, Sel),
+        );
+        decl.add_method(
+            sel!(deliverPresentFeedback),
+            deliver_present_feedback as extern "C" fn(&mut Object, Sel),
+        );
+        decl.add_method(
+            sel!(viewDidChangeBackingProperties),
+            view_did_change_backing_properties as extern "C" fn(&mut Object, Sel),
+        );
         decl.add_method(
             sel!(mouseDown:),
             mouse_down_left as extern "C" fn(&mut Object, Sel, id),
@@ -485,6 +893,10 @@ lazy_static! {
             key_down as extern "C" fn(&mut Object, Sel, id),
         );
         decl.add_method(sel!(keyUp:), key_up as extern "C" fn(&mut Object, Sel, id));
+        decl.add_method(
+            sel!(performKeyEquivalent:),
+            perform_key_equivalent as extern "C" fn(&mut Object, Sel, id) -> BOOL,
+        );
         decl.add_method(
             sel!(flagsChanged:),
             mods_changed as extern "C" fn(&mut Object, Sel, id),
@@ -496,6 +908,10 @@ lazy_static! {
         decl.add_method(sel!(runIdle), run_idle as extern "C" fn(&mut Object, Sel));
         decl.add_method(sel!(viewWillDraw), view_will_draw as extern "C" fn(&mut Object, Sel));
         decl.add_method(sel!(redraw), redraw as extern "C" fn(&mut Object, Sel));
+        decl.add_method(
+            sel!(redrawDelayedFrame),
+            redraw_delayed_frame as extern "C" fn(&mut Object, Sel),
+        );
         decl.add_method(
             sel!(handleTimer:),
             handle_timer as extern "C" fn(&mut Object, Sel, id),
@@ -516,6 +932,10 @@ lazy_static! {
             sel!(windowWillClose:),
             window_will_close as extern "C" fn(&mut Object, Sel, id),
         );
+        decl.add_method(
+            sel!(windowWillUseStandardFrame:defaultFrame:),
+            window_will_use_standard_frame as extern "C" fn(&mut Object, Sel, id, NSRect) -> NSRect,
+        );
 
         #[cfg(feature = "accesskit")]
         {
@@ -642,9 +1062,29 @@ fn make_view(handler: Box<dyn WinHandler>) -> (id, Weak<Mutex<Vec<IdleKind>>>) {
             mouse_left: true,
             installed_layer_delegate: false,
             keyboard_state,
+            key_equivalent: None,
             //text: PietText::new_with_unique_state(),
             active_text_input: None,
             parent: None,
+            owner: None,
+            maximize_mode: MaximizeMode::default(),
+            kiosk: false,
+            visibility: Visibility::Hidden,
+            frame_throttle: FrameThrottle::default(),
+            paint_requests: PaintRequests::default(),
+            frame_timings: FrameTimingsCache::default(),
+            display_link: None,
+            metal_layer: nil,
+            vsync: true,
+            preferred_frame_rate: None,
+            managed_surface: true,
+            coalesce_pointer_events: true,
+            has_focus: false,
+            closed: false,
+            is_child: false,
+            backing_scale_override: None,
+            traffic_light_offset: None,
+            traffic_light_frames: None,
             #[cfg(feature = "accesskit")]
             accesskit_adapter: OnceCell::new(),
         };
@@ -673,16 +1113,206 @@ lazy_static! {
         }
         WindowClass(decl.register())
     };
+    static ref PANEL_CLASS: WindowClass = unsafe {
+        let mut decl =
+            ClassDecl::new("GlazierPanel", class!(NSPanel)).expect("Panel class defined");
+        decl.add_method(
+            sel!(canBecomeKeyWindow),
+            refuse as extern "C" fn(&Object, Sel) -> BOOL,
+        );
+        decl.add_method(
+            sel!(canBecomeMainWindow),
+            refuse as extern "C" fn(&Object, Sel) -> BOOL,
+        );
+        extern "C" fn refuse(_this: &Object, _sel: Sel) -> BOOL {
+            NO
+        }
+        WindowClass(decl.register())
+    };
 }
 
 extern "C" fn set_frame_size(this: &mut Object, _: Sel, size: NSSize) {
+    let _span = common_util::event_span("setFrameSize:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
         view_state.handler.size(Size::new(size.width, size.height));
         let superclass = msg_send![this, superclass];
         let () = msg_send![super(this, superclass), setFrameSize: size];
+        // The view's layer is resized along with its frame, so the surface has now changed size.
+        update_metal_layer(this, view_state);
+        let scale = surface_scale(this, view_state);
+        view_state
+            .handler
+            .surface_resized(Size::new(size.width * scale, size.height * scale));
+    }
+}
+
+/// Called when the user starts dragging the window's frame.
+///
+/// AppKit already resizes and draws the view once per display refresh while it does.
+extern "C" fn view_will_start_live_resize(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("viewWillStartLiveResize");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        let superclass = msg_send![this, superclass];
+        let () = msg_send![super(this, superclass), viewWillStartLiveResize];
+        view_state.handler.interactive_resize_began();
+    }
+}
+
+extern "C" fn view_did_end_live_resize(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("viewDidEndLiveResize");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        let superclass = msg_send![this, superclass];
+        let () = msg_send![super(this, superclass), viewDidEndLiveResize];
+        view_state.handler.interactive_resize_ended();
+    }
+}
+
+/// Called when the view moves to a screen with a different backing scale factor.
+extern "C" fn view_did_change_backing_properties(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("viewDidChangeBackingProperties");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        let superclass = msg_send![this, superclass];
+        let () = msg_send![super(this, superclass), viewDidChangeBackingProperties];
+        update_metal_layer(this, view_state);
+        let scale = surface_scale(this, view_state);
+        let frame: NSRect = msg_send![this, frame];
+        view_state.handler.scale(Scale::new(scale, scale));
+        view_state.handler.surface_resized(Size::new(
+            frame.size.width * scale,
+            frame.size.height * scale,
+        ));
+    }
+}
+
+/// The scale the view's surface is rendered at, which is the backing scale override if there is
+/// one.
+unsafe fn surface_scale(view: *mut Object, view_state: &ViewState) -> CGFloat {
+    match view_state.backing_scale_override {
+        Some(scale) => scale,
+        None => msg_send![view, backingScaleFactor],
+    }
+}
+
+/// Keeps the drawable of our `CAMetalLayer`, if there is one, at the view's size in pixels.
+unsafe fn update_metal_layer(view: *mut Object, view_state: &ViewState) {
+    if view_state.metal_layer == nil {
+        return;
+    }
+    let scale = surface_scale(view, view_state);
+    let bounds: NSRect = msg_send![view, bounds];
+    let drawable_size = NSSize::new(bounds.size.width * scale, bounds.size.height * scale);
+    // Otherwise Core Animation animates the change, and stretches the old drawable while it does.
+    let () = msg_send![class!(CATransaction), begin];
+    let () = msg_send![class!(CATransaction), setDisableActions: YES];
+    let () = msg_send![view_state.metal_layer, setContentsScale: scale];
+    // `CGSize` has the same layout as `NSSize`.
+    let () = msg_send![view_state.metal_layer, setDrawableSize: drawable_size];
+    let () = msg_send![class!(CATransaction), commit];
+}
+
+/// Starts measuring when frames reach the screen, if we aren't already.
+unsafe fn start_display_link(view: id, view_state: &mut ViewState) {
+    if view_state.display_link.is_none() {
+        view_state.display_link = DisplayLink::start(view_state.frame_timings.clone(), view);
+        update_display_link(view, view_state);
+    }
+}
+
+/// Has the display link follow the screen the window is on, at the preferred frame rate.
+unsafe fn update_display_link(view: id, view_state: &ViewState) {
+    let Some(display_link) = &view_state.display_link else {
+        return;
+    };
+    let interval = view_state
+        .preferred_frame_rate
+        .filter(|rate| *rate > 0.0 && rate.is_finite())
+        .map(|rate| Duration::from_secs_f64(1.0 / rate));
+    display_link.set_frame_interval(interval);
+    let window: id = msg_send![view, window];
+    let screen: id = if window == nil {
+        nil
+    } else {
+        msg_send![window, screen]
+    };
+    if screen != nil {
+        let description: id = msg_send![screen, deviceDescription];
+        let number: id = msg_send![description, objectForKey: make_nsstring("NSScreenNumber")];
+        if number != nil {
+            let display: u32 = msg_send![number, unsignedIntValue];
+            display_link.set_display(display);
+        }
+    }
+}
+
+/// Moves the display link to the window's new screen, which may refresh at another rate.
+extern "C" fn window_did_change_screen(this: &mut Object, _: Sel, _notification: id) {
+    let _span = common_util::event_span("windowDidChangeScreen:");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        update_display_link(this, &*(view_state as *const ViewState));
+    }
+}
+
+/// Passes the feedback collected by the display link to the handler.
+extern "C" fn deliver_present_feedback(this: &mut Object, _: Sel) {
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        for info in view_state.frame_timings.take_feedback() {
+            view_state.handler.frame_presented(info);
+        }
+    }
+}
+
+/// Applies our vsync setting to the `CAMetalLayer`, if there is one.
+unsafe fn update_display_sync(view_state: &ViewState) {
+    let layer = view_state.metal_layer;
+    if layer == nil {
+        return;
     }
+    // `displaySyncEnabled` is only available from macOS 10.13.
+    let supported: BOOL = msg_send![layer, respondsToSelector: sel!(setDisplaySyncEnabled:)];
+    if supported == YES {
+        let enabled = if view_state.vsync { YES } else { NO };
+        let () = msg_send![layer, setDisplaySyncEnabled: enabled];
+    }
+}
+
+/// Whether glazier manages the view's layer, see `WindowBuilder::with_managed_surface`.
+unsafe fn managed_surface(view: *mut Object) -> bool {
+    let view_state: *mut c_void = *(*view).get_ivar("viewState");
+    (*(view_state as *mut ViewState)).managed_surface
+}
+
+/// Returns the view's `CAMetalLayer`, installing it first if needed.
+unsafe fn ensure_metal_layer(view: *mut Object) -> id {
+    let view_state: *mut c_void = *(*view).get_ivar("viewState");
+    let view_state = &mut *(view_state as *mut ViewState);
+    if view_state.metal_layer == nil {
+        let layer: id = msg_send![class!(CAMetalLayer), new];
+        // Setting the layer before `wantsLayer` makes the view layer-hosting, so that AppKit
+        // leaves the layer's contents alone.
+        let () = msg_send![view, setLayer: layer];
+        let () = msg_send![view, setWantsLayer: YES];
+        // Repaints in the same transaction as a resize, so that the window is never shown at its
+        // new size with a frame drawn at the old one.
+        let () = msg_send![layer, setNeedsDisplayOnBoundsChange: YES];
+        view_state.metal_layer = layer;
+        update_metal_layer(view, view_state);
+        update_display_sync(view_state);
+        // Our delegate may have been installed on a previous layer.
+        view_state.installed_layer_delegate = false;
+        check_if_layer_delegate_install_needed(view, view_state);
+    }
+    view_state.metal_layer
 }
 
 fn mouse_event(
@@ -707,6 +1337,7 @@ fn mouse_event(
             focus,
             button,
             wheel_delta,
+            coalesced: Vec::new(),
         }
     }
 }
@@ -766,21 +1397,24 @@ fn check_if_layer_delegate_install_needed(view: *mut Object, view_state: &mut Vi
             let () = unsafe { msg_send![layer, setDelegate: view] };
             // always invalidate the layer when first installed; otherwise the user has to think hard about
             // the order of installing the layer and calling invalidate() in their initialization code
-            unsafe { request_anim_frame(view) };
+            unsafe { request_anim_frame(view, view_state) };
             view_state.installed_layer_delegate = true;
         }
     }
 }
 
 extern "C" fn mouse_down_left(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("mouseDown:");
     mouse_down(this, nsevent, MouseButton::Primary);
 }
 
 extern "C" fn mouse_down_right(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("rightMouseDown:");
     mouse_down(this, nsevent, MouseButton::Secondary);
 }
 
 extern "C" fn mouse_down_other(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("otherMouseDown:");
     unsafe {
         if let Some(button) = get_mouse_button(nsevent.buttonNumber()) {
             mouse_down(this, nsevent, button);
@@ -792,6 +1426,11 @@ fn mouse_down(this: &mut Object, nsevent: id, button: MouseButton) {
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
+        if view_state.is_child {
+            // The host's window doesn't know to give us the keyboard focus
+            let window: id = msg_send![this, window];
+            let () = msg_send![window, makeFirstResponder: this as id];
+        }
         let count = nsevent.clickCount() as u8;
         let focus = view_state.focus_click && button == MouseButton::Primary;
         let event = mouse_event(nsevent, this as id, count, focus, button, Vec2::ZERO);
@@ -800,14 +1439,17 @@ fn mouse_down(this: &mut Object, nsevent: id, button: MouseButton) {
 }
 
 extern "C" fn mouse_up_left(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("mouseUp:");
     mouse_up(this, nsevent, MouseButton::Primary);
 }
 
 extern "C" fn mouse_up_right(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("rightMouseUp:");
     mouse_up(this, nsevent, MouseButton::Secondary);
 }
 
 extern "C" fn mouse_up_other(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("otherMouseUp:");
     unsafe {
         if let Some(button) = get_mouse_button(nsevent.buttonNumber()) {
             mouse_up(this, nsevent, button);
@@ -839,15 +1481,55 @@ fn mouse_up(this: &mut Object, nsevent: id, button: MouseButton) {
 }
 
 extern "C" fn mouse_move(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("mouseMoved:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
-        let event = mouse_event(nsevent, this as id, 0, false, MouseButton::None, Vec2::ZERO);
+        let mut event = mouse_event(nsevent, this as id, 0, false, MouseButton::None, Vec2::ZERO);
+        if view_state.coalesce_pointer_events {
+            let mut latest = nsevent;
+            while let Some(next) = take_queued_move(latest) {
+                let mut coalesced = mem::take(&mut event.coalesced);
+                coalesced.push(event.pos);
+                event = mouse_event(next, this as id, 0, false, MouseButton::None, Vec2::ZERO);
+                event.coalesced = coalesced;
+                latest = next;
+            }
+        }
         view_state.handler.mouse_move(&event);
     }
 }
 
+/// Take the next event from the queue if it is the same kind of move as `nsevent`, in the same
+/// window.
+///
+/// This only looks at the events which have already arrived, and stops at any other event, so
+/// that nothing is handled out of order.
+unsafe fn take_queued_move(nsevent: id) -> Option<id> {
+    let past: id = msg_send![class!(NSDate), distantPast];
+    let next: id = msg_send![NSApp(),
+        nextEventMatchingMask: NSEventMask::NSAnyEventMask.bits()
+        untilDate: past
+        inMode: NSDefaultRunLoopMode
+        dequeue: NO];
+    if next == nil || next.eventType() != nsevent.eventType() {
+        return None;
+    }
+    let window: id = msg_send![nsevent, window];
+    let next_window: id = msg_send![next, window];
+    if window != next_window {
+        return None;
+    }
+    let next: id = msg_send![NSApp(),
+        nextEventMatchingMask: NSEventMask::NSAnyEventMask.bits()
+        untilDate: past
+        inMode: NSDefaultRunLoopMode
+        dequeue: YES];
+    Some(next)
+}
+
 extern "C" fn mouse_enter(this: &mut Object, _sel: Sel, nsevent: id) {
+    let _span = common_util::event_span("mouseEntered:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
@@ -858,6 +1540,7 @@ extern "C" fn mouse_enter(this: &mut Object, _sel: Sel, nsevent: id) {
 }
 
 extern "C" fn mouse_leave(this: &mut Object, _: Sel, _nsevent: id) {
+    let _span = common_util::event_span("mouseExited:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
@@ -867,6 +1550,7 @@ extern "C" fn mouse_leave(this: &mut Object, _: Sel, _nsevent: id) {
 }
 
 extern "C" fn scroll_wheel(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("scrollWheel:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
@@ -893,6 +1577,7 @@ extern "C" fn scroll_wheel(this: &mut Object, _: Sel, nsevent: id) {
 }
 
 extern "C" fn pinch_event(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("magnifyWithEvent:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
@@ -903,12 +1588,24 @@ extern "C" fn pinch_event(this: &mut Object, _: Sel, nsevent: id) {
 }
 
 extern "C" fn key_down(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("keyDown:");
     let view_state = unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         &mut *(view_state as *mut ViewState)
     };
+    let offered = view_state
+        .key_equivalent
+        .take()
+        .map_or(false, |key| *key == nsevent);
     if let Some(event) = view_state.keyboard_state.process_native_event(nsevent) {
-        if !view_state.handler.key_down(event) {
+        let direction = focus_direction(&event);
+        if offered || !view_state.handler.key_down(event) {
+            if let Some(direction) = direction {
+                if view_state.handler.wants_focus_change(direction) {
+                    unsafe { select_next_key_view(*view_state.nsview.load(), direction) };
+                    return;
+                }
+            }
             // key down not handled; forward to text input system
             unsafe {
                 let events = NSArray::arrayWithObjects(nil, &[nsevent]);
@@ -918,7 +1615,94 @@ extern "C" fn key_down(this: &mut Object, _: Sel, nsevent: id) {
     }
 }
 
+/// AppKit offers the keys pressed with Command or Control to every view in the key window,
+/// before the menu bar and before sending `keyDown:`. The menu bar's shortcuts come first, as
+/// they would if the view didn't answer, and then the handler gets the key. This way it sees
+/// keys which the window would otherwise keep for itself, such as Control+Tab, and it only
+/// sees each key once.
+extern "C" fn perform_key_equivalent(this: &mut Object, _: Sel, nsevent: id) -> BOOL {
+    let _span = common_util::event_span("performKeyEquivalent:");
+    unsafe {
+        let window: id = msg_send![this, window];
+        let responder: id = if window == nil {
+            nil
+        } else {
+            msg_send![window, firstResponder]
+        };
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        let decision = key_equivalent_decision(
+            responder == this as *mut Object,
+            nsevent.eventType() == NSEventType::NSKeyDown,
+            || {
+                let menu: id = msg_send![NSApp(), mainMenu];
+                menu != nil && {
+                    let handled: BOOL = msg_send![menu, performKeyEquivalent: nsevent];
+                    handled == YES
+                }
+            },
+            || {
+                view_state.key_equivalent = None;
+                let event = view_state.keyboard_state.process_native_event(nsevent)?;
+                Some(view_state.handler.key_down(event))
+            },
+        );
+        match decision {
+            KeyEquivalent::NotOurs => {
+                let superclass = msg_send![this, superclass];
+                msg_send![super(this, superclass), performKeyEquivalent: nsevent]
+            }
+            KeyEquivalent::Handled => YES,
+            KeyEquivalent::Unhandled { stash } => {
+                if stash {
+                    // AppKit goes on to send it to `keyDown:`, unless the window uses it.
+                    view_state.key_equivalent = Some(StrongPtr::retain(nsevent));
+                }
+                NO
+            }
+        }
+    }
+}
+
+/// What `performKeyEquivalent:` does with a key.
+#[derive(Debug, PartialEq, Eq)]
+enum KeyEquivalent {
+    /// The key is passed on to the superclass.
+    NotOurs,
+    /// The menu bar or the handler used the key, so the view answers `YES`.
+    Handled,
+    /// The view answers `NO`, and if the handler was offered the key, it is stashed so that
+    /// `keyDown:` doesn't offer it again.
+    Unhandled { stash: bool },
+}
+
+/// Decide what `performKeyEquivalent:` does with a key, for a view which is or isn't the first
+/// responder.
+///
+/// The menu bar is only asked to handle the key once it's known to be ours, and the handler
+/// only if the menu bar didn't, which `menu_handled` and `handler_handled` do when called.
+/// `handler_handled` returns `None` if the key couldn't be offered to the handler.
+fn key_equivalent_decision(
+    first_responder: bool,
+    key_down: bool,
+    menu_handled: impl FnOnce() -> bool,
+    handler_handled: impl FnOnce() -> Option<bool>,
+) -> KeyEquivalent {
+    if !first_responder || !key_down {
+        return KeyEquivalent::NotOurs;
+    }
+    if menu_handled() {
+        return KeyEquivalent::Handled;
+    }
+    match handler_handled() {
+        Some(true) => KeyEquivalent::Handled,
+        Some(false) => KeyEquivalent::Unhandled { stash: true },
+        None => KeyEquivalent::Unhandled { stash: false },
+    }
+}
+
 extern "C" fn key_up(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("keyUp:");
     let view_state = unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         &mut *(view_state as *mut ViewState)
@@ -929,6 +1713,7 @@ extern "C" fn key_up(this: &mut Object, _: Sel, nsevent: id) {
 }
 
 extern "C" fn mods_changed(this: &mut Object, _: Sel, nsevent: id) {
+    let _span = common_util::event_span("flagsChanged:");
     let view_state = unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         &mut *(view_state as *mut ViewState)
@@ -943,6 +1728,7 @@ extern "C" fn mods_changed(this: &mut Object, _: Sel, nsevent: id) {
 }
 
 extern "C" fn view_will_draw(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("viewWillDraw");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
@@ -951,19 +1737,27 @@ extern "C" fn view_will_draw(this: &mut Object, _: Sel) {
 }
 
 extern "C" fn draw_rect(this: &mut Object, _: Sel, dirtyRect: NSRect) {
+    let _span = common_util::event_span("drawRect:");
     unsafe {
-        // FIXME: use the actual invalid region instead of just this bounding box.
-        // https://developer.apple.com/documentation/appkit/nsview/1483772-getrectsbeingdrawn?language=objc
-        let rect = Rect::from_origin_size(
-            (dirtyRect.origin.x, dirtyRect.origin.y),
-            (dirtyRect.size.width, dirtyRect.size.height),
-        );
-        let invalid = Region::from(rect);
+        // dirtyRect is just the bounding box of the rects AppKit has accumulated for us.
+        let mut rects: *const NSRect = std::ptr::null();
+        let mut count: NSInteger = 0;
+        let () = msg_send![this, getRectsBeingDrawn: &mut rects count: &mut count];
+        let mut invalid = Region::EMPTY;
+        if rects.is_null() || count <= 0 {
+            invalid.add_rect(ns_rect_to_rect(dirtyRect));
+        } else {
+            for rect in std::slice::from_raw_parts(rects, count as usize) {
+                invalid.add_rect(ns_rect_to_rect(*rect));
+            }
+        }
 
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
 
+        view_state.frame_timings.paint_started();
         view_state.handler.paint(&invalid);
+        view_state.frame_throttle.frame_painted();
 
         // sometimes layers are attached in the paint handler
         check_if_layer_delegate_install_needed(this, view_state);
@@ -973,7 +1767,15 @@ extern "C" fn draw_rect(this: &mut Object, _: Sel, dirtyRect: NSRect) {
     }
 }
 
+fn ns_rect_to_rect(rect: NSRect) -> Rect {
+    Rect::from_origin_size(
+        (rect.origin.x, rect.origin.y),
+        (rect.size.width, rect.size.height),
+    )
+}
+
 extern "C" fn display_layer(this: &mut Object, _: Sel, _: Sel) {
+    let _span = common_util::event_span("displayLayer:");
     unsafe {
         // FIXME: use the actual invalid region instead of just this bounding box.
         // https://developer.apple.com/documentation/appkit/nsview/1483772-getrectsbeingdrawn?language=objc
@@ -984,7 +1786,9 @@ extern "C" fn display_layer(this: &mut Object, _: Sel, _: Sel) {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
 
+        view_state.frame_timings.paint_started();
         view_state.handler.paint(&invalid);
+        view_state.frame_throttle.frame_painted();
 
         // sometimes layers are attached in the paint handler
         check_if_layer_delegate_install_needed(this, view_state);
@@ -998,8 +1802,21 @@ fn run_deferred(this: &mut Object, view_state: &mut ViewState, op: DeferredOp) {
     }
 }
 
-fn set_size_deferred(this: &mut Object, _view_state: &mut ViewState, size: Size) {
+fn set_size_deferred(this: &mut Object, view_state: &mut ViewState, size: Size) {
     unsafe {
+        if view_state.is_child {
+            // Keep the top left corner where it is
+            let superview: id = msg_send![this, superview];
+            let frame: NSRect = msg_send![this, frame];
+            let flipped: BOOL = msg_send![superview, isFlipped];
+            let mut origin = frame.origin;
+            if flipped == NO {
+                origin.y -= size.height - frame.size.height;
+            }
+            let () = msg_send![this, setFrameOrigin: origin];
+            let () = msg_send![this, setFrameSize: NSSize::new(size.width, size.height)];
+            return;
+        }
         let window: id = msg_send![this, window];
         let current_frame: NSRect = msg_send![window, frame];
         let mut new_frame = current_frame;
@@ -1012,8 +1829,15 @@ fn set_size_deferred(this: &mut Object, _view_state: &mut ViewState, size: Size)
     }
 }
 
-fn set_position_deferred(this: &mut Object, _view_state: &mut ViewState, position: Point) {
+fn set_position_deferred(this: &mut Object, view_state: &mut ViewState, position: Point) {
     unsafe {
+        if view_state.is_child {
+            let superview: id = msg_send![this, superview];
+            let frame: NSRect = msg_send![this, frame];
+            let origin = child_origin(superview, position, frame.size.height);
+            let () = msg_send![this, setFrameOrigin: origin];
+            return;
+        }
         let window: id = msg_send![this, window];
         let frame: NSRect = msg_send![window, frame];
 
@@ -1033,6 +1857,7 @@ fn set_position_deferred(this: &mut Object, _view_state: &mut ViewState, positio
 }
 
 extern "C" fn run_idle(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("runIdle");
     let view_state = unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         &mut *(view_state as *mut ViewState)
@@ -1050,7 +1875,13 @@ extern "C" fn run_idle(this: &mut Object, _: Sel) {
 }
 
 extern "C" fn redraw(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("redraw");
     unsafe {
+        // AppKit displays the view once, however often it is marked as needing it before then.
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        (*(view_state as *const ViewState))
+            .paint_requests
+            .paint_started();
         let () = msg_send![this as *const _, setNeedsDisplay: YES];
         let layer: id = msg_send![this, layer];
         if layer != nil {
@@ -1059,7 +1890,18 @@ extern "C" fn redraw(this: &mut Object, _: Sel) {
     }
 }
 
+extern "C" fn redraw_delayed_frame(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("redrawDelayedFrame");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        view_state.frame_throttle.delayed_frame_due();
+        redraw(this, sel!(redraw));
+    }
+}
+
 extern "C" fn handle_timer(this: &mut Object, _: Sel, timer: id) {
+    let _span = common_util::event_span("handleTimer:");
     let view_state = unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         &mut *(view_state as *mut ViewState)
@@ -1073,6 +1915,7 @@ extern "C" fn handle_timer(this: &mut Object, _: Sel, timer: id) {
 }
 
 extern "C" fn handle_menu_item(this: &mut Object, _: Sel, item: id) {
+    let _span = common_util::event_span("handleMenuItem:");
     unsafe {
         let tag: isize = msg_send![item, tag];
         let view_state: *mut c_void = *this.get_ivar("viewState");
@@ -1082,6 +1925,7 @@ extern "C" fn handle_menu_item(this: &mut Object, _: Sel, item: id) {
 }
 
 extern "C" fn show_context_menu(this: &mut Object, _: Sel, item: id) {
+    let _span = common_util::event_span("showContextMenu:");
     unsafe {
         let window: id = msg_send![this as *const _, window];
         let mut location: NSPoint = msg_send![window, mouseLocationOutsideOfEventStream];
@@ -1092,48 +1936,373 @@ extern "C" fn show_context_menu(this: &mut Object, _: Sel, item: id) {
 }
 
 extern "C" fn window_did_become_key(this: &mut Object, _: Sel, _notification: id) {
+    let _span = common_util::event_span("windowDidBecomeKey:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
-        view_state.handler.got_focus();
+        if !view_state.has_focus {
+            view_state.has_focus = true;
+            view_state.handler.got_focus();
+        }
     }
 }
 
-extern "C" fn window_did_resign_key(this: &mut Object, _: Sel, _notification: id) {
+/// Child windows don't own their window, so they have focus while they are its first responder.
+extern "C" fn become_first_responder(this: &mut Object, _: Sel) -> BOOL {
+    unsafe {
+        let superclass = msg_send![this, superclass];
+        let accepted: BOOL = msg_send![super(this, superclass), becomeFirstResponder];
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        if accepted == YES && view_state.is_child && !view_state.has_focus {
+            view_state.has_focus = true;
+            view_state.handler.got_focus();
+        }
+        accepted
+    }
+}
+
+extern "C" fn resign_first_responder(this: &mut Object, _: Sel) -> BOOL {
+    unsafe {
+        let superclass = msg_send![this, superclass];
+        let accepted: BOOL = msg_send![super(this, superclass), resignFirstResponder];
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        if accepted == YES && view_state.is_child && view_state.has_focus {
+            view_state.has_focus = false;
+            view_state.handler.lost_focus();
+        }
+        accepted
+    }
+}
+
+/// Closes a child window, which AppKit has no `windowWillClose:` for. The view is deallocated
+/// once its parent lets go of it, so this is run from the main queue rather than from a handler.
+extern "C" fn close_child(this: &mut Object, _: Sel) {
+    let _span = common_util::event_span("closeChild");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
-        view_state.handler.lost_focus();
+        if view_state.closed {
+            return;
+        }
+        view_state.closed = true;
+        view_state.handler.destroy();
+        let () = msg_send![this, removeFromSuperview];
+    }
+}
+
+/// Where a child window at `position` with the given height goes in `parent`'s coordinates,
+/// which start at the bottom left unless `parent` is flipped.
+unsafe fn child_origin(parent: id, position: Point, height: f64) -> NSPoint {
+    let flipped: BOOL = msg_send![parent, isFlipped];
+    if flipped == YES {
+        NSPoint::new(position.x, position.y)
+    } else {
+        let bounds: NSRect = msg_send![parent, bounds];
+        NSPoint::new(position.x, bounds.size.height - position.y - height)
+    }
+}
+
+extern "C" fn window_did_resign_key(this: &mut Object, _: Sel, _notification: id) {
+    let _span = common_util::event_span("windowDidResignKey:");
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        if view_state.has_focus {
+            view_state.has_focus = false;
+            view_state.handler.lost_focus();
+        }
+    }
+}
+
+/// Called after AppKit has laid out the titlebar again, which puts the window buttons back.
+extern "C" fn window_did_relayout_titlebar(this: &mut Object, _: Sel, _notification: id) {
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        if view_state.traffic_light_offset.is_some() {
+            let window: id = msg_send![this, window];
+            position_traffic_lights(window, view_state);
+        }
+    }
+}
+
+/// Moves the window buttons to `traffic_light_offset`, or back to where AppKit had them.
+///
+/// The buttons live in a titlebar container view, which is made tall enough to hold them at
+/// their new position.
+unsafe fn position_traffic_lights(window: id, view_state: &mut ViewState) {
+    // NSWindowCloseButton, NSWindowMiniaturizeButton and NSWindowZoomButton
+    let close: id = msg_send![window, standardWindowButton: 0 as NSUInteger];
+    let miniaturize: id = msg_send![window, standardWindowButton: 1 as NSUInteger];
+    let zoom: id = msg_send![window, standardWindowButton: 2 as NSUInteger];
+    if close == nil || miniaturize == nil || zoom == nil {
+        return;
+    }
+    let superview: id = msg_send![close, superview];
+    let container: id = msg_send![superview, superview];
+    if container == nil {
+        return;
+    }
+    if view_state.traffic_light_frames.is_none() {
+        view_state.traffic_light_frames = Some([
+            msg_send![container, frame],
+            msg_send![close, frame],
+            msg_send![miniaturize, frame],
+            msg_send![zoom, frame],
+        ]);
+    }
+    let Some(frames) = view_state.traffic_light_frames else {
+        return;
+    };
+    let buttons = [close, miniaturize, zoom];
+    let window_frame: NSRect = msg_send![window, frame];
+    // The container keeps its width, which follows the window's, and stays at the top of it.
+    let mut container_frame: NSRect = msg_send![container, frame];
+    let Some(offset) = view_state.traffic_light_offset else {
+        container_frame.size.height = frames[0].size.height;
+        container_frame.origin.y = window_frame.size.height - container_frame.size.height;
+        let () = msg_send![container, setFrame: container_frame];
+        for (button, frame) in buttons.iter().zip(&frames[1..]) {
+            let () = msg_send![*button, setFrameOrigin: frame.origin];
+        }
+        view_state.traffic_light_frames = None;
+        return;
+    };
+    // The titlebar only shows with the menu bar in fullscreen, where it stays as it is.
+    let style_mask: NSWindowStyleMask = window.styleMask();
+    if style_mask.contains(NSWindowStyleMask::NSFullScreenWindowMask) {
+        return;
+    }
+    // The buttons keep their place at the bottom of the container, so making it taller moves
+    // them down.
+    container_frame.size.height = frames[1].size.height + offset.y;
+    container_frame.origin.y = window_frame.size.height - container_frame.size.height;
+    let () = msg_send![container, setFrame: container_frame];
+    let spacing = frames[2].origin.x - frames[1].origin.x;
+    for (i, (button, frame)) in buttons.iter().zip(&frames[1..]).enumerate() {
+        let origin = NSPoint::new(offset.x + i as CGFloat * spacing, frame.origin.y);
+        let () = msg_send![*button, setFrameOrigin: origin];
+    }
+}
+
+/// Shows or hides a child window, which isn't told when its window changes visibility.
+fn set_child_visibility(view: id, visibility: Visibility) {
+    unsafe {
+        let hidden = if visibility == Visibility::Hidden {
+            YES
+        } else {
+            NO
+        };
+        let () = msg_send![view, setHidden: hidden];
+        let view_state: *mut c_void = *(*view).get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        if view_state.visibility != visibility {
+            view_state.visibility = visibility;
+            view_state.handler.visibility_changed(visibility);
+        }
+    }
+}
+
+extern "C" fn window_did_change_occlusion_state(this: &mut Object, _: Sel, notification: id) {
+    let _span = common_util::event_span("windowDidChangeOcclusionState:");
+    // NSWindowOcclusionStateVisible
+    const OCCLUSION_STATE_VISIBLE: NSUInteger = 1 << 1;
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        let window: id = msg_send![notification, object];
+        let miniaturized: BOOL = msg_send![window, isMiniaturized];
+        let visible: BOOL = msg_send![window, isVisible];
+        let occlusion: NSUInteger = msg_send![window, occlusionState];
+        let visibility = if miniaturized == YES || visible == NO {
+            Visibility::Hidden
+        } else if occlusion & OCCLUSION_STATE_VISIBLE != 0 {
+            Visibility::Visible
+        } else {
+            Visibility::Occluded
+        };
+        if view_state.visibility != visibility {
+            view_state.visibility = visibility;
+            view_state.handler.visibility_changed(visibility);
+        }
     }
 }
 
 extern "C" fn window_should_close(this: &mut Object, _: Sel, _window: id) -> BOOL {
+    let _span = common_util::event_span("windowShouldClose:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
-        view_state.handler.request_close();
+        // Kiosk windows can only be closed with `WindowHandle::close`.
+        if !view_state.kiosk {
+            view_state.handler.request_close();
+        }
         NO
     }
 }
 
 extern "C" fn window_will_close(this: &mut Object, _: Sel, _notification: id) {
+    let _span = common_util::event_span("windowWillClose:");
     unsafe {
         let view_state: *mut c_void = *this.get_ivar("viewState");
         let view_state = &mut *(view_state as *mut ViewState);
+        if view_state.closed {
+            return;
+        }
+        view_state.closed = true;
+        if view_state.kiosk {
+            NSApp().setPresentationOptions_(
+                NSApplicationPresentationOptions::NSApplicationPresentationDefault,
+            );
+        }
+        let window: id = msg_send![this, window];
+        let modal: id = msg_send![NSApp(), modalWindow];
+        if window == modal {
+            stop_modal(dialog::NSModalResponseCancel);
+        }
+        // A sheet of this window ends before it, and then closes along with it.
+        let sheet: id = msg_send![window, attachedSheet];
+        if sheet != nil {
+            let () = msg_send![window, endSheet: sheet returnCode: dialog::NSModalResponseCancel];
+            let () = msg_send![sheet, performSelectorOnMainThread: sel!(close) withObject: nil waitUntilDone: NO];
+        }
+        let sheet_parent: id = msg_send![window, sheetParent];
+        if sheet_parent != nil {
+            let () =
+                msg_send![sheet_parent, endSheet: window returnCode: dialog::NSModalResponseCancel];
+        }
+        // Windows built with this one as their parent close along with it.
+        let children: id = msg_send![window, childWindows];
+        if children != nil {
+            for i in 0..children.count() {
+                let child: id = children.objectAtIndex(i);
+                let () = msg_send![child, performSelectorOnMainThread: sel!(close) withObject: nil waitUntilDone: NO];
+            }
+        }
         view_state.handler.destroy();
     }
 }
 
-unsafe fn request_anim_frame(view: *mut Object) {
-    // TODO: synchronize with screen refresh rate using CVDisplayLink instead.
+fn modal_response_from_raw(response: dialog::NSModalResponse) -> ModalResponse {
+    match response {
+        dialog::NSModalResponseOK => ModalResponse::Ok,
+        dialog::NSModalResponseCancel => ModalResponse::Cancel,
+        other => ModalResponse::Other(other as i32),
+    }
+}
+
+fn modal_response_to_raw(response: ModalResponse) -> dialog::NSModalResponse {
+    match response {
+        ModalResponse::Ok => dialog::NSModalResponseOK,
+        ModalResponse::Cancel => dialog::NSModalResponseCancel,
+        ModalResponse::Other(other) => other as dialog::NSModalResponse,
+    }
+}
+
+/// The direction which focus moves in, if `event` is Tab or Shift+Tab.
+fn focus_direction(event: &KeyEvent) -> Option<FocusDirection> {
+    let mods = event.mods;
+    if event.key != KbKey::Tab || mods.ctrl() || mods.alt() || mods.meta() {
+        return None;
+    }
+    Some(if mods.shift() {
+        FocusDirection::Backward
+    } else {
+        FocusDirection::Forward
+    })
+}
+
+/// Move focus from `view` to the next view in its window's key view loop, or to another window
+/// if the loop has nowhere else to go.
+unsafe fn select_next_key_view(view: id, direction: FocusDirection) {
+    let window: id = msg_send![view, window];
+    if window == nil {
+        return;
+    }
+    let () = match direction {
+        FocusDirection::Forward => msg_send![window, selectNextKeyView: nil],
+        FocusDirection::Backward => msg_send![window, selectPreviousKeyView: nil],
+    };
+    let responder: id = msg_send![window, firstResponder];
+    if responder != view {
+        return;
+    }
+    // Going forward activates the window furthest back, so that doing it again moves on
+    // through all of the windows. Going back undoes that.
+    let windows: id = msg_send![NSApp(), orderedWindows];
+    let count: NSUInteger = msg_send![windows, count];
+    let mut indices: Vec<NSUInteger> = (0..count).collect();
+    if direction == FocusDirection::Forward {
+        indices.reverse();
+    }
+    for i in indices {
+        let other: id = msg_send![windows, objectAtIndex: i];
+        let visible: BOOL = msg_send![other, isVisible];
+        let can_become_key: BOOL = msg_send![other, canBecomeKeyWindow];
+        if other != window && visible == YES && can_become_key == YES {
+            let () = msg_send![other, makeKeyAndOrderFront: nil];
+            return;
+        }
+    }
+}
+
+/// End the loop of `runModalForWindow:`.
+unsafe fn stop_modal(response: dialog::NSModalResponse) {
+    let () = msg_send![NSApp(), stopModalWithCode: response];
+    // The loop only notices once it has handled an event, which it may not get for a while if
+    // this wasn't called from an event, such as from a timer.
+    application::post_wake_event();
+}
+
+/// The default frame passed in by `zoom:` is the visible frame of the screen,
+/// which excludes the dock and the menu bar.
+extern "C" fn window_will_use_standard_frame(
+    this: &mut Object,
+    _: Sel,
+    window: id,
+    default_frame: NSRect,
+) -> NSRect {
+    unsafe {
+        let view_state: *mut c_void = *this.get_ivar("viewState");
+        let view_state = &mut *(view_state as *mut ViewState);
+        match view_state.maximize_mode {
+            MaximizeMode::WorkArea => default_frame,
+            MaximizeMode::FullMonitor => {
+                let screen: id = msg_send![window, screen];
+                if screen == nil {
+                    default_frame
+                } else {
+                    msg_send![screen, frame]
+                }
+            }
+        }
+    }
+}
+
+/// Has `redraw` sent to the view, after the next vsync if frames are synchronized with the
+/// display.
+unsafe fn request_anim_frame(view: *mut Object, view_state: &mut ViewState) {
+    if view_state.vsync {
+        start_display_link(view, view_state);
+        if let Some(display_link) = &view_state.display_link {
+            display_link.request_frame();
+            return;
+        }
+    }
     let () = msg_send![view, performSelectorOnMainThread: sel!(redraw)
                 withObject: nil waitUntilDone: NO];
 }
 
 impl WindowHandle {
     pub fn show(&self) {
+        if let Some(view) = self.child_view() {
+            set_child_visibility(view, Visibility::Visible);
+            return;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             // register our view class to be alerted when it becomes the key view.
             let notif_center_class = class!(NSNotificationCenter);
             let notif_string = NSString::alloc(nil)
@@ -1141,28 +2310,104 @@ impl WindowHandle {
                 .autorelease();
             let notif_center: id = msg_send![notif_center_class, defaultCenter];
             let () = msg_send![notif_center, addObserver:*self.nsview.load() selector: sel!(windowDidBecomeKey:) name: notif_string object: window];
-            window.makeKeyAndOrderFront_(nil)
+            // Draw the first frame before the window is ordered in, so it isn't seen empty.
+            let visible: BOOL = msg_send![window, isVisible];
+            if visible == NO {
+                let () = msg_send![window, display];
+            }
+            window.makeKeyAndOrderFront_(nil);
+            if let Some(view) = self.nsview.load().as_ref() {
+                let state: *mut c_void = *view.get_ivar("viewState");
+                let state = &mut (*(state as *mut ViewState));
+                if let Some(owner) = &state.owner {
+                    // Child windows move with their parent, stay above it and are minimized
+                    // with it. Adding a child window orders it in, so this waits until it is shown.
+                    let owner = owner.0.nswindow();
+                    if owner != nil {
+                        let () = msg_send![owner, addChildWindow: window ordered: NSWindowAbove];
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn hide(&self) {
+        if let Some(view) = self.child_view() {
+            set_child_visibility(view, Visibility::Hidden);
+            return;
+        }
+        unsafe {
+            let window = self.nswindow();
+            // `show` registers the observer again.
+            let notif_center: id = msg_send![class!(NSNotificationCenter), defaultCenter];
+            let notif_string = NSString::alloc(nil)
+                .init_str(NSWindowDidBecomeKeyNotification)
+                .autorelease();
+            let () = msg_send![notif_center, removeObserver:*self.nsview.load() name: notif_string object: window];
+            // Otherwise, the window would be ordered in again along with its parent.
+            let parent: id = msg_send![window, parentWindow];
+            if parent != nil {
+                let () = msg_send![parent, removeChildWindow: window];
+            }
+            let () = msg_send![window, orderOut: nil];
         }
     }
 
     /// Close the window.
+    pub fn is_alive(&self) -> bool {
+        self.nswindow() != nil
+    }
+
     pub fn close(&self) {
+        if let Some(view) = self.child_view() {
+            unsafe {
+                let () = msg_send![view, performSelectorOnMainThread: sel!(closeChild) withObject: nil waitUntilDone: NO];
+            }
+            return;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let () = msg_send![window, performSelectorOnMainThread: sel!(close) withObject: nil waitUntilDone: NO];
         }
     }
 
     /// Bring this window to the front of the window stack and give it focus.
     pub fn bring_to_front_and_focus(&self) {
+        if let Some(view) = self.child_view() {
+            // The host decides where its own window goes
+            unsafe {
+                let window: id = msg_send![view, window];
+                let () = msg_send![window, makeFirstResponder: view];
+            }
+            return;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let () = msg_send![window, performSelectorOnMainThread: sel!(makeKeyAndOrderFront:) withObject: nil waitUntilDone: NO];
         }
     }
 
     pub fn request_anim_frame(&self) {
-        unsafe { request_anim_frame(*self.nsview.load()) }
+        unsafe {
+            let view = *self.nsview.load();
+            if view.is_null() {
+                return;
+            }
+            let view_state: *mut c_void = *(*view).get_ivar("viewState");
+            let view_state = &mut *(view_state as *mut ViewState);
+            match view_state.frame_throttle.schedule() {
+                FrameSchedule::Now => {
+                    if view_state.paint_requests.request() {
+                        request_anim_frame(view, view_state);
+                    }
+                }
+                FrameSchedule::After(delay) => {
+                    let () = msg_send![view, performSelector: sel!(redrawDelayedFrame)
+                        withObject: nil afterDelay: delay.as_secs_f64()];
+                }
+                FrameSchedule::Scheduled => (),
+            }
+        }
     }
 
     // Request invalidation of the entire window contents.
@@ -1182,6 +2427,137 @@ impl WindowHandle {
         }
     }
 
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), Error> {
+        let view = self.nsview.load();
+        if view.is_null() {
+            return Err(Error::WindowDropped);
+        }
+        unsafe {
+            if !managed_surface(*view) {
+                return Err(anyhow::anyhow!(
+                    "present_pixels is not supported on windows without a managed surface"
+                )
+                .into());
+            }
+            let bounds: NSRect = msg_send![*view, bounds];
+            let scale: CGFloat = msg_send![*view, backingScaleFactor];
+            let size_px = (
+                (bounds.size.width * scale).round(),
+                (bounds.size.height * scale).round(),
+            );
+            if (width as f64, height as f64) != size_px {
+                return Err(anyhow::anyhow!(
+                    "pixel buffer is {width}x{height}, but the window is {}x{}",
+                    size_px.0,
+                    size_px.1
+                )
+                .into());
+            }
+            // CoreGraphics can read both layouts directly, so no conversion is needed.
+            let bitmap_info = match format {
+                PixelFormat::Rgba8 => kCGImageAlphaNoneSkipLast,
+                PixelFormat::Bgra8 => kCGImageAlphaNoneSkipFirst | kCGBitmapByteOrder32Little,
+            };
+            let provider = CGDataProvider::from_buffer(Arc::new(buf.to_vec()));
+            let image = CGImage::new(
+                width as usize,
+                height as usize,
+                8,
+                32,
+                width as usize * PixelFormat::BYTES_PER_PIXEL,
+                &CGColorSpace::create_device_rgb(),
+                bitmap_info,
+                &provider,
+                false,
+                kCGRenderingIntentDefault,
+            );
+            let mut layer: id = msg_send![*view, layer];
+            if layer == nil {
+                let () = msg_send![*view, setWantsLayer: YES];
+                layer = msg_send![*view, layer];
+            }
+            // The layer retains its contents, so the image can be dropped afterwards.
+            let contents = &*image as *const CGImageRef as id;
+            let () = msg_send![layer, setContents: contents];
+            let () = msg_send![layer, setContentsScale: scale];
+        }
+        Ok(())
+    }
+
+    pub fn read_back_pixels(&self, _format: PixelFormat) -> Result<Vec<u8>, Error> {
+        Err(Error::Unsupported("read_back_pixels"))
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        const NS_WINDOW_SHARING_NONE: NSUInteger = 0;
+        let view = self.nsview.load();
+        let window = self.nswindow();
+        if window == nil {
+            return None;
+        }
+        unsafe {
+            let sharing_type: NSUInteger = msg_send![window, sharingType];
+            if sharing_type == NS_WINDOW_SHARING_NONE {
+                return None;
+            }
+            // Only capture the view, in CoreGraphics' global coordinates. These start at the
+            // top left of the primary screen, whereas AppKit's start at its bottom left.
+            let bounds: NSRect = msg_send![*view, bounds];
+            let in_window: NSRect = msg_send![*view, convertRect: bounds toView: nil];
+            let on_screen: NSRect = msg_send![window, convertRectToScreen: in_window];
+            let screens: id = msg_send![class!(NSScreen), screens];
+            let primary: id = msg_send![screens, firstObject];
+            let primary_frame: NSRect = msg_send![primary, frame];
+            let rect = CGRect::new(
+                &CGPoint::new(
+                    on_screen.origin.x,
+                    primary_frame.size.height - on_screen.origin.y - on_screen.size.height,
+                ),
+                &CGSize::new(on_screen.size.width, on_screen.size.height),
+            );
+            let window_number: NSInteger = msg_send![window, windowNumber];
+            let image = create_image(
+                rect,
+                kCGWindowListOptionIncludingWindow,
+                window_number as CGWindowID,
+                kCGWindowImageBestResolution,
+            )?;
+            // The window server picks the image's layout, so draw it into one we know. Bitmap
+            // contexts can only hold RGBA with premultiplied alpha, which we undo afterwards.
+            let (width, height) = (image.width(), image.height());
+            let mut context = CGContext::create_bitmap_context(
+                None,
+                width,
+                height,
+                8,
+                width * PixelFormat::BYTES_PER_PIXEL,
+                &CGColorSpace::create_device_rgb(),
+                kCGImageAlphaPremultipliedLast,
+            );
+            context.draw_image(
+                CGRect::new(
+                    &CGPoint::new(0., 0.),
+                    &CGSize::new(width as CGFloat, height as CGFloat),
+                ),
+                &image,
+            );
+            // The window server picks the image's resolution too, so its scale is taken from
+            // its size
+            let scale = Scale::new(
+                width as f64 / on_screen.size.width,
+                height as f64 / on_screen.size.height,
+            );
+            let pixels = unpremultiply(context.data().to_vec());
+            Some(ImageBuf::new(width as u32, height as u32, pixels).with_scale(scale))
+        }
+    }
+
     pub fn set_cursor(&mut self, cursor: &Cursor) {
         unsafe {
             let nscursor = class!(NSCursor);
@@ -1257,6 +2633,15 @@ impl WindowHandle {
         }
     }
 
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        unsafe {
+            let view = self.nsview.load().as_ref()?;
+            let state: *mut c_void = *view.get_ivar("viewState");
+            (*(state as *mut ViewState)).active_text_input
+        }
+    }
+
     pub fn update_text_field(&self, token: TextFieldToken, update: Event) {
         unsafe {
             if let Some(view) = self.nsview.load().as_ref() {
@@ -1283,6 +2668,13 @@ impl WindowHandle {
         }
     }
 
+    pub fn show_character_palette(&self) {
+        // The picked characters are sent to the first responder with `insertText:`.
+        unsafe {
+            let _: () = msg_send![NSApp(), orderFrontCharacterPalette: nil];
+        }
+    }
+
     pub fn open_file(&mut self, options: FileDialogOptions) -> Option<FileDialogToken> {
         Some(self.open_save_impl(FileDialogType::Open, options))
     }
@@ -1291,6 +2683,60 @@ impl WindowHandle {
         Some(self.open_save_impl(FileDialogType::Save, options))
     }
 
+    pub fn begin_sheet(&self, sheet: &WindowHandle, completion: Box<dyn FnOnce(ModalResponse)>) {
+        unsafe {
+            let window = self.nswindow();
+            let sheet = sheet.nswindow();
+            // Blocks may be called more than once, as far as the type system knows.
+            let completion = Cell::new(Some(completion));
+            let block = ConcreteBlock::new(move |response: dialog::NSModalResponse| {
+                if let Some(completion) = completion.take() {
+                    completion(modal_response_from_raw(response));
+                }
+            });
+            let block = block.copy();
+            let () = msg_send![window, beginSheet: sheet completionHandler: block];
+        }
+    }
+
+    pub fn end_sheet(&self, response: ModalResponse) {
+        let response = modal_response_to_raw(response);
+        unsafe {
+            let window = self.nswindow();
+            let parent: id = msg_send![window, sheetParent];
+            if parent != nil {
+                let () = msg_send![parent, endSheet: window returnCode: response];
+            }
+        }
+    }
+
+    pub fn run_modal(&self) -> ModalResponse {
+        unsafe {
+            let window = self.nswindow();
+            if window == nil {
+                return ModalResponse::Cancel;
+            }
+            // Our timers and `performSelectorOnMainThread:` calls are scheduled in the common
+            // modes, which include the modal panel mode this loop runs in.
+            let response: dialog::NSModalResponse = msg_send![NSApp(), runModalForWindow: window];
+            modal_response_from_raw(response)
+        }
+    }
+
+    pub fn end_modal(&self, response: ModalResponse) {
+        unsafe {
+            let window = self.nswindow();
+            let modal: id = msg_send![NSApp(), modalWindow];
+            if window != nil && window == modal {
+                stop_modal(modal_response_to_raw(response));
+            }
+        }
+    }
+
+    pub fn focus_next_window(&self) {
+        unsafe { select_next_key_view(*self.nsview.load(), FocusDirection::Forward) }
+    }
+
     fn open_save_impl(&mut self, ty: FileDialogType, opts: FileDialogOptions) -> FileDialogToken {
         let token = FileDialogToken::next();
         let self_clone = self.clone();
@@ -1315,10 +2761,47 @@ impl WindowHandle {
         token
     }
 
+    pub fn set_present_feedback(&self, enabled: bool) {
+        let view = self.nsview.load();
+        if let Some(view) = unsafe { (*view).as_ref() } {
+            let view_state: *mut c_void = unsafe { *view.get_ivar("viewState") };
+            let view_state = unsafe { &mut *(view_state as *mut ViewState) };
+            view_state.frame_timings.set_feedback(enabled);
+            if enabled {
+                unsafe { start_display_link(view as *const Object as id, view_state) };
+            }
+        }
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        let view = self.nsview.load();
+        if let Some(view) = unsafe { (*view).as_ref() } {
+            let view_state: *mut c_void = unsafe { *view.get_ivar("viewState") };
+            let view_state = unsafe { &mut *(view_state as *mut ViewState) };
+            view_state.vsync = enabled;
+            unsafe { update_display_sync(view_state) };
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        let view = self.nsview.load();
+        match unsafe { (*view).as_ref() } {
+            Some(view) => {
+                let view_state: *mut c_void = unsafe { *view.get_ivar("viewState") };
+                unsafe { (*(view_state as *const ViewState)).vsync }
+            }
+            None => true,
+        }
+    }
+
     /// Set the title for this menu.
     pub fn set_title(&self, title: &str) {
+        if self.child_view().is_some() {
+            debug!("child windows have no title");
+            return;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let title = make_nsstring(title);
             window.setTitle_(title);
         }
@@ -1347,11 +2830,26 @@ impl WindowHandle {
     }
 
     pub fn get_position(&self) -> Point {
+        if let Some(view) = self.child_view() {
+            unsafe {
+                let superview: id = msg_send![view, superview];
+                let frame: NSRect = msg_send![view, frame];
+                let flipped: BOOL = msg_send![superview, isFlipped];
+                if flipped == YES {
+                    return Point::new(frame.origin.x, frame.origin.y);
+                }
+                let bounds: NSRect = msg_send![superview, bounds];
+                return Point::new(
+                    frame.origin.x,
+                    bounds.size.height - frame.origin.y - frame.size.height,
+                );
+            }
+        }
         unsafe {
             // TODO this should be the max y in orig mac coords
             let screen_height = crate::Screen::get_display_rect().height();
 
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let current_frame: NSRect = msg_send![window, frame];
 
             let mut position = Point::new(
@@ -1371,10 +2869,13 @@ impl WindowHandle {
     }
 
     pub fn content_insets(&self) -> Insets {
+        if self.child_view().is_some() {
+            return Insets::ZERO;
+        }
         unsafe {
             let screen_height = crate::Screen::get_display_rect().height();
 
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let clr: NSRect = msg_send![window, contentLayoutRect];
 
             let window_frame_r: NSRect = NSWindow::frame(window);
@@ -1399,9 +2900,13 @@ impl WindowHandle {
     }
 
     fn set_level(&self, level: WindowLevel) {
+        if self.child_view().is_some() {
+            debug!("child windows have no level of their own");
+            return;
+        }
         unsafe {
             let level = levels::as_raw_window_level(level);
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let () = msg_send![window, setLevel: level];
         }
     }
@@ -1411,16 +2916,23 @@ impl WindowHandle {
     }
 
     pub fn get_size(&self) -> Size {
+        if let Some(view) = self.child_view() {
+            let frame: NSRect = unsafe { msg_send![view, frame] };
+            return Size::new(frame.size.width, frame.size.height);
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let current_frame: NSRect = msg_send![window, frame];
             Size::new(current_frame.size.width, current_frame.size.height)
         }
     }
 
     pub fn get_window_state(&self) -> WindowState {
+        if self.child_view().is_some() {
+            return WindowState::Restored;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let isMin: BOOL = msg_send![window, isMiniaturized];
             if isMin != NO {
                 return WindowState::Minimized;
@@ -1434,9 +2946,13 @@ impl WindowHandle {
     }
 
     pub fn set_window_state(&mut self, state: WindowState) {
+        if self.child_view().is_some() {
+            debug!("child windows can't be minimized or maximized");
+            return;
+        }
         let cur_state = self.get_window_state();
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             match (state, cur_state) {
                 (s1, s2) if s1 == s2 => (),
                 (WindowState::Minimized, _) => {
@@ -1456,13 +2972,113 @@ impl WindowHandle {
         }
     }
 
-    pub fn handle_titlebar(&self, _val: bool) {
-        tracing::warn!("WindowHandle::handle_titlebar is currently unimplemented for Mac.");
+    pub fn set_maximize_mode(&self, mode: MaximizeMode) {
+        unsafe {
+            if let Some(view) = self.nsview.load().as_ref() {
+                let state: *mut c_void = *view.get_ivar("viewState");
+                let state = &mut (*(state as *mut ViewState));
+                state.maximize_mode = mode;
+            }
+        }
+    }
+
+    pub fn handle_titlebar(&self, val: bool) {
+        if !val || self.child_view().is_some() {
+            return;
+        }
+        unsafe {
+            // Dragging starts from the mouse down which is being handled, and AppKit tracks the
+            // mouse from there.
+            let event: id = msg_send![NSApp(), currentEvent];
+            if event == nil || event.eventType() != NSEventType::NSLeftMouseDown {
+                return;
+            }
+            let window = self.nswindow();
+            // `performWindowDragWithEvent:` is only available from macOS 10.11.
+            let supported: BOOL =
+                msg_send![window, respondsToSelector: sel!(performWindowDragWithEvent:)];
+            if supported == YES {
+                let () = msg_send![window, performWindowDragWithEvent: event];
+            }
+        }
+    }
+
+    pub fn set_window_level(&self, level: StackingLevel) {
+        if self.child_view().is_some() {
+            debug!("child windows have no level of their own");
+            return;
+        }
+        unsafe {
+            let window = self.nswindow();
+            let () = msg_send![window, setLevel: levels::as_raw_stacking_level(level)];
+            // Widgets stay on the desktop of every space, even in Mission Control.
+            let widget = NSWindowCollectionBehavior::NSWindowCollectionBehaviorCanJoinAllSpaces
+                | NSWindowCollectionBehavior::NSWindowCollectionBehaviorStationary;
+            let mut behavior = window.collectionBehavior();
+            if level == StackingLevel::DesktopWidget {
+                // This can't be combined with `Stationary`.
+                behavior &= !NSWindowCollectionBehavior::NSWindowCollectionBehaviorTransient;
+                behavior |= widget;
+            } else {
+                behavior &= !widget;
+            }
+            window.setCollectionBehavior_(behavior);
+        }
+    }
+
+    pub fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        if self.child_view().is_some() {
+            debug!("child windows have no titlebar");
+            return;
+        }
+        unsafe {
+            let window = self.nswindow();
+            // A nil appearance is inherited from the application, which follows the system.
+            let appearance: id = match theme {
+                Some(Theme::Light) => {
+                    msg_send![class!(NSAppearance), appearanceNamed: NSAppearanceNameAqua]
+                }
+                Some(Theme::Dark) => {
+                    msg_send![class!(NSAppearance), appearanceNamed: NSAppearanceNameDarkAqua]
+                }
+                None => nil,
+            };
+            let () = msg_send![window, setAppearance: appearance];
+        }
+    }
+
+    pub fn set_skip_taskbar(&self, skip: bool) {
+        if self.child_view().is_some() {
+            return;
+        }
+        unsafe {
+            let window = self.nswindow();
+            let mut behavior = window.collectionBehavior();
+            // Desktop widgets are already left alone by Mission Control.
+            let stationary =
+                behavior.contains(NSWindowCollectionBehavior::NSWindowCollectionBehaviorStationary);
+            if skip {
+                behavior |= NSWindowCollectionBehavior::NSWindowCollectionBehaviorIgnoresCycle;
+                if !stationary {
+                    behavior |= NSWindowCollectionBehavior::NSWindowCollectionBehaviorTransient;
+                }
+            } else {
+                behavior &= !(NSWindowCollectionBehavior::NSWindowCollectionBehaviorIgnoresCycle
+                    | NSWindowCollectionBehavior::NSWindowCollectionBehaviorTransient);
+            }
+            window.setCollectionBehavior_(behavior);
+            let excluded = if skip { YES } else { NO };
+            let () = msg_send![window, setExcludedFromWindowsMenu: excluded];
+        }
     }
 
     pub fn resizable(&self, resizable: bool) {
+        if self.child_view().is_some() {
+            debug!("child windows are resized by their host");
+            return;
+        }
         unsafe {
-            let window: id = msg_send![*self.nsview.load(), window];
+            let window = self.nswindow();
             let mut style_mask: NSWindowStyleMask = window.styleMask();
 
             if resizable {
@@ -1475,7 +3091,46 @@ impl WindowHandle {
         }
     }
 
+    pub fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        if self.child_view().is_some() {
+            return;
+        }
+        unsafe {
+            let window = self.nswindow();
+            match ratio {
+                // AppKit grows the minimum size to fit the ratio itself.
+                Some(ratio) => {
+                    let () = msg_send![window, setContentAspectRatio: NSSize::new(ratio, 1.0)];
+                }
+                // The aspect ratio and the resize increments are exclusive, so setting the
+                // increments to a single point removes the ratio.
+                None => {
+                    let () = msg_send![window, setContentResizeIncrements: NSSize::new(1.0, 1.0)];
+                }
+            }
+        }
+    }
+
+    pub fn set_resize_increments(&self, increments: Option<Size>) {
+        // Single points are what AppKit resizes by when nothing was set.
+        if self.child_view().is_some() {
+            return;
+        }
+        let increments = increments.unwrap_or(Size::new(1.0, 1.0));
+        unsafe {
+            let window = self.nswindow();
+            let () = msg_send![
+                window,
+                setContentResizeIncrements: NSSize::new(increments.width, increments.height)
+            ];
+        }
+    }
+
     pub fn set_menu(&self, menu: Menu) {
+        if self.child_view().is_some() {
+            debug!("child windows have no menu bar");
+            return;
+        }
         unsafe {
             NSApp().setMainMenu_(menu.menu);
         }
@@ -1508,12 +3163,44 @@ impl WindowHandle {
         }
     }
 
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        let view = self.nsview.load();
+        if (*view).is_null() {
+            return;
+        }
+        unsafe {
+            let view_state: *mut c_void = *(**view).get_ivar("viewState");
+            let view_state = &mut *(view_state as *mut ViewState);
+            if view_state.backing_scale_override == scale {
+                return;
+            }
+            view_state.backing_scale_override = scale;
+            // This updates the layer and tells the handler, once it is no longer being called.
+            let () = msg_send![*view, performSelectorOnMainThread: sel!(viewDidChangeBackingProperties)
+                withObject: nil waitUntilDone: NO];
+        }
+    }
+
     /// Get the `Scale` of the window.
     pub fn get_scale(&self) -> Result<Scale, Error> {
         let scale_factor: CGFloat = unsafe { msg_send![*self.nsview.load(), backingScaleFactor] };
         Ok(Scale::new(scale_factor, scale_factor))
     }
 
+    /// Get the timings of the last frame which reached the screen.
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        let view = self.nsview.load();
+        match unsafe { (*view).as_ref() } {
+            Some(view) => {
+                let view_state: *mut c_void = unsafe { *view.get_ivar("viewState") };
+                let view_state = unsafe { &mut *(view_state as *mut ViewState) };
+                unsafe { start_display_link(view as *const Object as id, view_state) };
+                view_state.frame_timings.last()
+            }
+            None => FrameTimings::default(),
+        }
+    }
+
     #[cfg(feature = "accesskit")]
     pub fn update_accesskit_if_active(
         &self,
@@ -1531,20 +3218,248 @@ impl WindowHandle {
     }
 }
 
+#[link(name = "QuartzCore", kind = "framework")]
+extern "C" {}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    // These are CFStringRefs, which NSStrings are toll-free bridged to.
+    static kCGColorSpaceSRGB: id;
+    static kCGColorSpaceDisplayP3: id;
+    static kCGColorSpaceExtendedSRGB: id;
+    static kCGColorSpaceExtendedLinearSRGB: id;
+    static kCGColorSpaceExtendedLinearDisplayP3: id;
+    fn CGColorSpaceCreateWithName(name: id) -> *mut c_void;
+    fn CGColorSpaceRelease(space: *mut c_void);
+}
+
+impl WindowHandle {
+    /// Returns the view's window, or `nil` once it has closed.
+    ///
+    /// AppKit releases the window when it closes, while the view can outlive it, so the view's
+    /// `window` can't be trusted from then on. Messages to `nil` do nothing.
+    fn nswindow(&self) -> id {
+        let view = self.nsview.load();
+        if (*view).is_null() {
+            return nil;
+        }
+        unsafe {
+            let view_state: *mut c_void = *(**view).get_ivar("viewState");
+            if (*(view_state as *const ViewState)).closed {
+                return nil;
+            }
+            msg_send![*view, window]
+        }
+    }
+
+    /// The view, if it was built with `build_as_child` and hasn't closed.
+    fn child_view(&self) -> Option<id> {
+        let view = *self.nsview.load();
+        if view.is_null() {
+            return None;
+        }
+        unsafe {
+            let view_state: *mut c_void = *(*view).get_ivar("viewState");
+            let view_state = &*(view_state as *const ViewState);
+            (view_state.is_child && !view_state.closed).then_some(view)
+        }
+    }
+
+    /// Calls `f` with the view's window and state, if the window hasn't closed.
+    ///
+    /// Child windows don't own their window, so `f` isn't called for them.
+    fn with_window(&self, f: impl FnOnce(id, &mut ViewState)) {
+        let window = self.nswindow();
+        if window == nil || self.child_view().is_some() {
+            return;
+        }
+        let view = self.nsview.load();
+        unsafe {
+            let view_state: *mut c_void = *(**view).get_ivar("viewState");
+            f(window, &mut *(view_state as *mut ViewState))
+        }
+    }
+
+    /// Calls `f` with the view's `CAMetalLayer`, installing it first if needed.
+    fn with_metal_layer(&self, f: impl FnOnce(id)) {
+        let view = self.nsview.load();
+        if (*view).is_null() {
+            return;
+        }
+        unsafe {
+            if !managed_surface(*view) {
+                tracing::warn!(
+                    "the window's surface isn't managed by glazier, so it has no metal layer"
+                );
+                return;
+            }
+            f(ensure_metal_layer(*view))
+        }
+    }
+}
+
+impl crate::platform::mac::WindowBuilderExt for crate::WindowBuilder {
+    fn set_tabbing_identifier(mut self, identifier: &str) -> Self {
+        self.0.tabbing_identifier = Some(identifier.to_owned());
+        self
+    }
+}
+
+impl crate::platform::mac::WindowExt for crate::WindowHandle {
+    fn metal_layer(&self) -> *mut c_void {
+        let mut layer = std::ptr::null_mut();
+        self.0.with_metal_layer(|metal_layer| unsafe {
+            // This reference belongs to the caller, ours is released when the view is dealloc'ed.
+            let metal_layer: id = msg_send![metal_layer, retain];
+            layer = metal_layer as *mut c_void;
+        });
+        layer
+    }
+
+    fn set_wants_extended_dynamic_range(&self, wants: bool) {
+        let wants = if wants { YES } else { NO };
+        self.0.with_metal_layer(|layer| unsafe {
+            let () = msg_send![layer, setWantsExtendedDynamicRangeContent: wants];
+        });
+    }
+
+    fn set_colorspace(&self, colorspace: ColorSpace) {
+        self.0.with_metal_layer(|layer| unsafe {
+            let name = match colorspace {
+                ColorSpace::Srgb => kCGColorSpaceSRGB,
+                ColorSpace::DisplayP3 => kCGColorSpaceDisplayP3,
+                ColorSpace::ExtendedSrgb => kCGColorSpaceExtendedSRGB,
+                ColorSpace::ExtendedLinearSrgb => kCGColorSpaceExtendedLinearSRGB,
+                ColorSpace::ExtendedLinearDisplayP3 => kCGColorSpaceExtendedLinearDisplayP3,
+            };
+            let space = CGColorSpaceCreateWithName(name);
+            if space.is_null() {
+                tracing::warn!("{:?} is not supported on this version of macOS", colorspace);
+                return;
+            }
+            // The layer retains the color space.
+            let () = msg_send![layer, setColorspace: space];
+            CGColorSpaceRelease(space);
+        });
+    }
+
+    fn set_presents_with_transaction(&self, presents_with_transaction: bool) {
+        let presents_with_transaction = if presents_with_transaction { YES } else { NO };
+        self.0.with_metal_layer(|layer| unsafe {
+            let () = msg_send![layer, setPresentsWithTransaction: presents_with_transaction];
+        });
+    }
+
+    fn set_titlebar_transparent(&self, transparent: bool) {
+        let transparent = if transparent { YES } else { NO };
+        self.0.with_window(|window, _| unsafe {
+            let () = msg_send![window, setTitlebarAppearsTransparent: transparent];
+        });
+    }
+
+    fn set_fullsize_content_view(&self, fullsize: bool) {
+        self.0.with_window(|window, _| unsafe {
+            let mut style_mask: NSWindowStyleMask = window.styleMask();
+            style_mask.set(NSWindowStyleMask::NSFullSizeContentViewWindowMask, fullsize);
+            window.setStyleMask_(style_mask);
+        });
+    }
+
+    fn set_title_visibility(&self, visible: bool) {
+        // NSWindowTitleVisible and NSWindowTitleHidden
+        let visibility: NSInteger = if visible { 0 } else { 1 };
+        self.0.with_window(|window, _| unsafe {
+            let () = msg_send![window, setTitleVisibility: visibility];
+        });
+    }
+
+    fn set_traffic_light_offset(&self, offset: Option<Point>) {
+        self.0.with_window(|window, view_state| unsafe {
+            view_state.traffic_light_offset = offset;
+            position_traffic_lights(window, view_state);
+        });
+    }
+
+    fn set_traffic_lights_visible(&self, visible: bool) {
+        let hidden = if visible { NO } else { YES };
+        self.0.with_window(|window, _| unsafe {
+            for button in 0..3 as NSUInteger {
+                let button: id = msg_send![window, standardWindowButton: button];
+                if button != nil {
+                    let () = msg_send![button, setHidden: hidden];
+                }
+            }
+        });
+    }
+
+    fn set_preferred_frame_rate(&self, rate: Option<f64>) {
+        let view = self.nsview.load();
+        if let Some(view) = unsafe { (*view).as_ref() } {
+            let view_state: *mut c_void = unsafe { *view.get_ivar("viewState") };
+            let view_state = unsafe { &mut *(view_state as *mut ViewState) };
+            view_state.preferred_frame_rate = rate;
+            unsafe { update_display_link(view as *const Object as id, view_state) };
+        }
+    }
+
+    fn add_tabbed_window(
+        &self,
+        builder: crate::WindowBuilder,
+    ) -> Result<crate::WindowHandle, crate::Error> {
+        let handle = builder.build()?;
+        unsafe {
+            let window = self.0.nswindow();
+            let tab = handle.0.nswindow();
+            if window != nil && tab != nil {
+                let identifier: id = msg_send![window, tabbingIdentifier];
+                let () = msg_send![tab, setTabbingIdentifier: identifier];
+                let () = msg_send![window, addTabbedWindow: tab ordered: NSWindowAbove];
+            }
+        }
+        // This selects the new tab, and registers for its focus like any other window.
+        handle.show();
+        Ok(handle)
+    }
+}
+
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        // The weak pointer becomes nil once the view has been deallocated.
+        let nsv = self.nsview.load();
+        let ns_view = NonNull::new(*nsv as *mut c_void).ok_or(HandleError::Unavailable)?;
+        let handle = RawWindowHandle::AppKit(AppKitWindowHandle::new(ns_view));
+        // SAFETY: The view is still alive.
+        Ok(unsafe { raw_window_handle::WindowHandle::borrow_raw(handle) })
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        if self.nsview.load().is_null() {
+            return Err(HandleError::Unavailable);
+        }
+        let handle = RawDisplayHandle::AppKit(AppKitDisplayHandle::new());
+        // SAFETY: The AppKit display handle doesn't contain any pointers.
+        Ok(unsafe { DisplayHandle::borrow_raw(handle) })
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
+    fn raw_window_handle(&self) -> rwh_05::RawWindowHandle {
         let nsv = self.nsview.load();
         let window: id = unsafe { msg_send![*nsv, window] };
-        let mut handle = AppKitWindowHandle::empty();
+        let mut handle = rwh_05::AppKitWindowHandle::empty();
         handle.ns_view = *nsv as *mut _;
         handle.ns_window = window as *mut _;
-        RawWindowHandle::AppKit(handle)
+        rwh_05::RawWindowHandle::AppKit(handle)
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
-    fn raw_display_handle(&self) -> RawDisplayHandle {
-        RawDisplayHandle::AppKit(AppKitDisplayHandle::empty())
+    fn raw_display_handle(&self) -> rwh_05::RawDisplayHandle {
+        rwh_05::RawDisplayHandle::AppKit(rwh_05::AppKitDisplayHandle::empty())
     }
 }
 
@@ -1641,3 +3556,81 @@ fn time_interval_from_deadline(deadline: Instant) -> f64 {
         secs + subsecs
     }
 }
+
+/// Divide the color of each RGBA pixel in `pixels` by its alpha, as [`ImageBuf`]s don't
+/// premultiply.
+fn unpremultiply(mut pixels: Vec<u8>) -> Vec<u8> {
+    for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+        let alpha = px[3] as u32;
+        if alpha != 0 && alpha != 255 {
+            for channel in &mut px[..3] {
+                *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn unpremultiplied_captures() {
+        let pixels = vec![
+            10, 20, 30, 255, // opaque, so unchanged
+            0, 0, 0, 0, // transparent, so unchanged
+            64, 32, 0, 128, // half transparent
+            200, 100, 0, 100, // invalid, as the color exceeds the alpha
+        ];
+        assert_eq!(
+            unpremultiply(pixels),
+            [10, 20, 30, 255, 0, 0, 0, 0, 128, 64, 0, 128, 255, 255, 0, 100]
+        );
+    }
+
+    #[test]
+    fn key_equivalents() {
+        // Whether the menu bar and the handler were asked, and what they said
+        let decide = |first_responder, key_down, menu: bool, handler: Option<bool>| {
+            let asked = (Cell::new(false), Cell::new(false));
+            let decision = key_equivalent_decision(
+                first_responder,
+                key_down,
+                || {
+                    asked.0.set(true);
+                    menu
+                },
+                || {
+                    asked.1.set(true);
+                    handler
+                },
+            );
+            (decision, asked.0.get(), asked.1.get())
+        };
+
+        let not_ours = (KeyEquivalent::NotOurs, false, false);
+        assert_eq!(decide(false, true, true, Some(true)), not_ours);
+        assert_eq!(decide(true, false, true, Some(true)), not_ours);
+
+        // The menu bar comes first
+        assert_eq!(
+            decide(true, true, true, Some(true)),
+            (KeyEquivalent::Handled, true, false)
+        );
+        assert_eq!(
+            decide(true, true, false, Some(true)),
+            (KeyEquivalent::Handled, true, true)
+        );
+        assert_eq!(
+            decide(true, true, false, Some(false)),
+            (KeyEquivalent::Unhandled { stash: true }, true, true)
+        );
+        assert_eq!(
+            decide(true, true, false, None),
+            (KeyEquivalent::Unhandled { stash: false }, true, true)
+        );
+    }
+}
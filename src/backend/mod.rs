@@ -30,12 +30,12 @@ pub(crate) mod shared;
 
 #[cfg(all(
     any(target_os = "freebsd", target_os = "linux", target_os = "openbsd"),
-    any(feature = "x11", feature = "wayland")
+    any(feature = "x11", feature = "wayland", feature = "headless")
 ))]
 pub mod linux;
 #[cfg(all(
     any(target_os = "freebsd", target_os = "linux", target_os = "openbsd"),
-    any(feature = "x11", feature = "wayland")
+    any(feature = "x11", feature = "wayland", feature = "headless")
 ))]
 pub use linux::*;
 
@@ -51,6 +51,12 @@ pub(crate) mod x11;
 ))]
 pub(crate) mod wayland;
 
+#[cfg(all(
+    feature = "headless",
+    any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")
+))]
+pub(crate) mod headless;
+
 #[cfg(all(
     any(feature = "wayland", feature = "x11"),
     any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")
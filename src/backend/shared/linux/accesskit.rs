@@ -0,0 +1,89 @@
+//! Exposing the accessibility tree to assistive technology over AT-SPI.
+
+#![cfg_attr(not(any(feature = "x11", feature = "wayland")), allow(dead_code))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use accesskit::{ActionHandler, TreeUpdate};
+use accesskit_unix::Adapter;
+use zbus::blocking::{Connection, Proxy};
+use zbus::fdo::PropertiesProxyBlocking;
+
+use super::env::app_name;
+
+const A11Y_BUS: &str = "org.a11y.Bus";
+const A11Y_BUS_PATH: &str = "/org/a11y/bus";
+const STATUS_INTERFACE: &str = "org.a11y.Status";
+
+/// Whether a screen reader or other assistive technology is running, as AT-SPI last reported.
+///
+/// This starts out false, and is kept up to date from a background thread once
+/// [`watch`](AccessibilityStatus::watch) is called.
+#[derive(Clone, Default)]
+pub(crate) struct AccessibilityStatus {
+    enabled: Arc<AtomicBool>,
+}
+
+impl AccessibilityStatus {
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Watch AT-SPI's status, calling `on_enabled` from a background thread whenever
+    /// assistive technology starts running, including when it already is.
+    pub(crate) fn watch(&self, on_enabled: impl Fn() + Send + 'static) {
+        let enabled = self.enabled.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = watch(&enabled, on_enabled) {
+                tracing::debug!("stopped watching the AT-SPI status: {}", e);
+            }
+        });
+    }
+}
+
+/// Creates an AccessKit adapter, which should only be done once [`AccessibilityStatus`] says
+/// that assistive technology is running.
+///
+/// Until then we don't ask for `initial_tree`, so handlers don't build trees which nobody will
+/// read.
+pub(crate) fn new_adapter(
+    initial_tree: impl FnOnce() -> Option<TreeUpdate>,
+    action_handler: Box<dyn ActionHandler + Send + Sync>,
+) -> Option<Adapter> {
+    let initial_tree = initial_tree()?;
+    Adapter::new(
+        app_name(),
+        "glazier".into(),
+        env!("CARGO_PKG_VERSION").into(),
+        move || initial_tree,
+        action_handler,
+    )
+}
+
+fn watch(enabled: &AtomicBool, on_enabled: impl Fn()) -> zbus::Result<()> {
+    let connection = Connection::session()?;
+    let properties = PropertiesProxyBlocking::builder(&connection)
+        .destination(A11Y_BUS)?
+        .path(A11Y_BUS_PATH)?
+        .build()?;
+    let changes = properties.receive_properties_changed()?;
+    let update = || -> zbus::Result<()> {
+        let now = query(&connection)?;
+        if !enabled.swap(now, Ordering::AcqRel) && now {
+            on_enabled();
+        }
+        Ok(())
+    };
+    update()?;
+    for _ in changes {
+        update()?;
+    }
+    Ok(())
+}
+
+fn query(connection: &Connection) -> zbus::Result<bool> {
+    let status = Proxy::new(connection, A11Y_BUS, A11Y_BUS_PATH, STATUS_INTERFACE)?;
+    Ok(status.get_property::<bool>("IsEnabled")?
+        || status.get_property::<bool>("ScreenReaderEnabled")?)
+}
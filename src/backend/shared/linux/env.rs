@@ -1,4 +1,27 @@
+use crate::common_util::{normalize_locale, ui_direction_for_locale};
+use crate::UiDirection;
+
+/// The name we report the application under, taken from the executable's name.
+pub fn app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "glazier".into())
+}
+
 pub fn locale() -> String {
+    locales().swap_remove(0)
+}
+
+/// The direction which goes with the most preferred language, as neither the environment nor
+/// the desktop has a setting for it.
+pub fn ui_direction() -> UiDirection {
+    ui_direction_for_locale(&locale())
+}
+
+/// The languages in `LANGUAGE`, followed by the locale of the other variables, without
+/// duplicates. This has at least one entry.
+pub fn locales() -> Vec<String> {
     fn locale_env_var(var: &str) -> Option<String> {
         match std::env::var(var) {
             Ok(s) if s.is_empty() => {
@@ -22,18 +45,24 @@ pub fn locale() -> String {
 
     // from gettext manual
     // https://www.gnu.org/software/gettext/manual/html_node/Locale-Environment-Variables.html#Locale-Environment-Variables
-    let mut locale = locale_env_var("LANGUAGE")
-        // the LANGUAGE value is priority list separated by :
-        // See: https://www.gnu.org/software/gettext/manual/html_node/The-LANGUAGE-variable.html#The-LANGUAGE-variable
-        .and_then(|locale| locale.split(':').next().map(String::from))
-        .or_else(|| locale_env_var("LC_ALL"))
+    let locale = locale_env_var("LC_ALL")
         .or_else(|| locale_env_var("LC_MESSAGES"))
-        .or_else(|| locale_env_var("LANG"))
-        .unwrap_or_else(|| "en-US".to_string());
+        .or_else(|| locale_env_var("LANG"));
+    // the LANGUAGE value is priority list separated by :
+    // See: https://www.gnu.org/software/gettext/manual/html_node/The-LANGUAGE-variable.html#The-LANGUAGE-variable
+    let languages = locale_env_var("LANGUAGE").unwrap_or_default();
 
-    // This is done because the locale parsing library we use expects an unicode locale, but these vars have an ISO locale
-    if let Some(idx) = locale.chars().position(|c| c == '.' || c == '@') {
-        locale.truncate(idx);
+    // These vars have ISO locales, such as `en_US.UTF-8`, rather than unicode ones
+    let mut locales = Vec::new();
+    for locale in languages.split(':').chain(locale.as_deref()) {
+        if let Some(locale) = normalize_locale(locale) {
+            if !locales.contains(&locale) {
+                locales.push(locale);
+            }
+        }
+    }
+    if locales.is_empty() {
+        locales.push("en-US".to_string());
     }
-    locale
+    locales
 }
@@ -1,2 +1,15 @@
+// exposing accessibility trees over AT-SPI
+#[cfg(feature = "accesskit")]
+pub mod accesskit;
 // environment based utilities
 pub mod env;
+// desktop notifications over dbus
+pub mod notification;
+// power state over dbus
+pub mod power;
+// sleep and session lock notifications from logind
+pub mod session;
+// desktop settings from the settings portal
+pub mod settings;
+// inhibiting sleep over dbus
+pub mod sleep;
@@ -0,0 +1,328 @@
+//! Desktop notifications with the `org.freedesktop.Notifications` service over DBus.
+//!
+//! Only a thread of our own talks to the server, so the UI thread never waits for it.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+use super::env::app_name;
+use crate::error::Error as ShellError;
+use crate::{Notification, NotificationId};
+
+/// The action key which the server reports when the notification itself is clicked.
+const DEFAULT_ACTION: &str = "default";
+
+/// The notification thread's requests, which is started when the first notification is sent.
+static NOTIFIER: Mutex<Option<Sender<Request>>> = Mutex::new(None);
+
+enum Request {
+    Send(Notification, NotificationId),
+    Close(NotificationId),
+    /// Sent by the thread which receives the server's signals, so that only the notification
+    /// thread reports events.
+    Report(NotificationEvent),
+}
+
+/// What happened to a notification, which is reported from a background thread.
+pub(crate) enum NotificationEvent {
+    /// The user clicked the notification, or the action with this index.
+    Activated(NotificationId, Option<usize>),
+    /// The notification couldn't be shown.
+    Failed(NotificationId, anyhow::Error),
+}
+
+/// Show `notification`, calling `on_event` from a background thread when the user clicks it,
+/// or one of its actions, or when it couldn't be shown.
+///
+/// Only the `on_event` given with the first notification is used.
+pub(crate) fn send_notification(
+    notification: &Notification,
+    id: NotificationId,
+    on_event: impl Fn(NotificationEvent) + Send + 'static,
+) -> Result<(), ShellError> {
+    let mut notifier = NOTIFIER.lock().unwrap();
+    let requests = notifier.get_or_insert_with(|| {
+        let (requests, received) = mpsc::channel();
+        let reports = requests.clone();
+        std::thread::spawn(move || run(received, reports, on_event));
+        requests
+    });
+    requests
+        .send(Request::Send(notification.clone(), id))
+        .map_err(|_| anyhow!("the notification thread has stopped").into())
+}
+
+pub(crate) fn close_notification(id: NotificationId) {
+    if let Some(requests) = NOTIFIER.lock().unwrap().as_ref() {
+        // The thread only stops if it panicked, which it has already logged
+        let _ = requests.send(Request::Close(id));
+    }
+}
+
+/// Handle the requests until the application exits.
+fn run(
+    requests: Receiver<Request>,
+    reports: Sender<Request>,
+    on_event: impl Fn(NotificationEvent),
+) {
+    let notifier = Notifier::connect(reports);
+    for request in requests {
+        match (request, &notifier) {
+            (Request::Send(notification, id), Ok(notifier)) => {
+                // The ids are locked until this one is added, so that the signals about it
+                // wait for it to be known
+                let mut ids = notifier.ids.lock().unwrap();
+                match notifier.notify(&notification) {
+                    Ok(server_id) => {
+                        ids.insert(server_id, id);
+                    }
+                    Err(e) => {
+                        drop(ids);
+                        let error = anyhow!("failed to send the notification: {e}");
+                        on_event(NotificationEvent::Failed(id, error));
+                    }
+                }
+            }
+            (Request::Send(_, id), Err(e)) => {
+                let error = anyhow!("failed to connect to the notification server: {e}");
+                on_event(NotificationEvent::Failed(id, error));
+            }
+            (Request::Close(id), Ok(notifier)) => notifier.close(id),
+            (Request::Close(_), Err(_)) => (),
+            (Request::Report(event), _) => on_event(event),
+        }
+    }
+}
+
+struct Notifier {
+    proxy: Proxy<'static>,
+    /// Our ids for the notifications the server is showing, by the server's ids.
+    ids: Arc<Mutex<HashMap<u32, NotificationId>>>,
+}
+
+impl Notifier {
+    fn connect(reports: Sender<Request>) -> zbus::Result<Notifier> {
+        let connection = Connection::session()?;
+        let proxy = Proxy::new(
+            &connection,
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+        )?;
+        let ids = Arc::new(Mutex::new(HashMap::new()));
+        // Some servers only send the signals to the connection which sent the notification,
+        // so they are received on the same one
+        let signals = proxy.receive_all_signals()?;
+        let watched_ids = ids.clone();
+        std::thread::spawn(move || {
+            for signal in signals {
+                let signal = match parse_signal(&signal) {
+                    Ok(Some(signal)) => signal,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::warn!(
+                            "failed to read a signal from the notification server: {}",
+                            e
+                        );
+                        continue;
+                    }
+                };
+                let activated = handle_signal(signal, &mut watched_ids.lock().unwrap());
+                if let Some((id, action)) = activated {
+                    let event = NotificationEvent::Activated(id, action);
+                    if reports.send(Request::Report(event)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Notifier { proxy, ids })
+    }
+
+    fn notify(&self, notification: &Notification) -> zbus::Result<u32> {
+        let mut hints = HashMap::new();
+        let image = notification
+            .image
+            .as_ref()
+            .and_then(|path| path.canonicalize().ok())
+            .map(|path| format!("file://{}", path.display()));
+        if let Some(image) = &image {
+            hints.insert("image-path", Value::from(image.as_str()));
+        }
+        self.proxy.call(
+            "Notify",
+            &(
+                app_name(),
+                // This isn't replacing an earlier notification
+                0u32,
+                "",
+                notification.title.as_str(),
+                notification.body.as_str(),
+                action_keys(&notification.actions),
+                hints,
+                // Let the server decide when it expires
+                -1i32,
+            ),
+        )
+    }
+
+    fn close(&self, id: NotificationId) {
+        let server_id = self
+            .ids
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|(server_id, it)| (*it == id).then_some(*server_id));
+        if let Some(server_id) = server_id {
+            if let Err(e) = self.proxy.call_method("CloseNotification", &server_id) {
+                tracing::warn!("failed to close the notification: {}", e);
+            }
+        }
+    }
+}
+
+/// The actions to send to the server, as pairs of keys and labels.
+///
+/// Each action's key is its index, and the default action, which isn't shown, is first.
+fn action_keys(labels: &[String]) -> Vec<String> {
+    let mut actions = vec![DEFAULT_ACTION.to_string(), String::new()];
+    for (index, label) in labels.iter().enumerate() {
+        actions.push(index.to_string());
+        actions.push(label.clone());
+    }
+    actions
+}
+
+/// The action which was chosen with `key`, where `None` is the notification itself.
+///
+/// The outer `None` is for keys which we didn't send.
+fn action_index(key: &str) -> Option<Option<usize>> {
+    match key {
+        DEFAULT_ACTION => Some(None),
+        key => key.parse().ok().map(Some),
+    }
+}
+
+/// The signals from the server which we act on.
+#[derive(Debug, PartialEq, Eq)]
+enum Signal {
+    ActionInvoked { server_id: u32, key: String },
+    NotificationClosed { server_id: u32 },
+}
+
+fn parse_signal(signal: &zbus::Message) -> zbus::Result<Option<Signal>> {
+    let Some(member) = signal.member() else {
+        return Ok(None);
+    };
+    let signal = match member.as_str() {
+        "ActionInvoked" => {
+            let (server_id, key): (u32, String) = signal.body()?;
+            Signal::ActionInvoked { server_id, key }
+        }
+        "NotificationClosed" => {
+            let (server_id, _reason): (u32, u32) = signal.body()?;
+            Signal::NotificationClosed { server_id }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(signal))
+}
+
+/// Update `ids` for `signal`, returning the notification and action to report as activated.
+fn handle_signal(
+    signal: Signal,
+    ids: &mut HashMap<u32, NotificationId>,
+) -> Option<(NotificationId, Option<usize>)> {
+    match signal {
+        Signal::ActionInvoked { server_id, key } => {
+            let id = ids.get(&server_id).copied()?;
+            Some((id, action_index(&key)?))
+        }
+        Signal::NotificationClosed { server_id } => {
+            ids.remove(&server_id);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use zbus::export::serde::Serialize;
+    use zbus::zvariant::DynamicType;
+
+    fn signal(name: &str, body: &(impl Serialize + DynamicType)) -> Signal {
+        let message = zbus::MessageBuilder::signal(
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            name,
+        )
+        .unwrap()
+        .build(body)
+        .unwrap();
+        parse_signal(&message).unwrap().unwrap()
+    }
+
+    #[test]
+    fn action_keys_round_trip() {
+        let labels = ["Reply".to_string(), "Mark as read".to_string()];
+        let actions = action_keys(&labels);
+        assert_eq!(
+            actions,
+            ["default", "", "0", "Reply", "1", "Mark as read"].map(String::from)
+        );
+        let keys = actions.iter().step_by(2).map(|key| action_index(key));
+        assert_eq!(
+            keys.collect::<Vec<_>>(),
+            [Some(None), Some(Some(0)), Some(Some(1))]
+        );
+        assert_eq!(action_index("inline-reply"), None);
+    }
+
+    #[test]
+    fn signals_are_parsed() {
+        assert_eq!(
+            signal("ActionInvoked", &(7u32, "1")),
+            Signal::ActionInvoked {
+                server_id: 7,
+                key: "1".into()
+            }
+        );
+        assert_eq!(
+            signal("NotificationClosed", &(7u32, 2u32)),
+            Signal::NotificationClosed { server_id: 7 }
+        );
+    }
+
+    #[test]
+    fn signals_update_ids() {
+        let id = NotificationId::from_raw(1);
+        let mut ids = HashMap::from([(7, id)]);
+        let invoked = |key: &str| Signal::ActionInvoked {
+            server_id: 7,
+            key: key.into(),
+        };
+        assert_eq!(
+            handle_signal(invoked("default"), &mut ids),
+            Some((id, None))
+        );
+        assert_eq!(handle_signal(invoked("2"), &mut ids), Some((id, Some(2))));
+        assert_eq!(handle_signal(invoked("unknown"), &mut ids), None);
+        // Other applications' notifications are ignored
+        let other = Signal::ActionInvoked {
+            server_id: 8,
+            key: "default".into(),
+        };
+        assert_eq!(handle_signal(other, &mut ids), None);
+
+        let closed = Signal::NotificationClosed { server_id: 7 };
+        assert_eq!(handle_signal(closed, &mut ids), None);
+        assert!(ids.is_empty());
+        assert_eq!(handle_signal(invoked("default"), &mut ids), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! Reading the power state from UPower and power-profiles-daemon over DBus.
+
+use std::sync::{Arc, Mutex};
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::fdo::PropertiesProxyBlocking;
+
+use crate::application::PowerState;
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+/// A composite device which UPower maintains to summarise all batteries.
+const DISPLAY_DEVICE_PATH: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+const POWER_PROFILES: &str = "net.hadess.PowerProfiles";
+const POWER_PROFILES_PATH: &str = "/net/hadess/PowerProfiles";
+
+pub(crate) fn power_state() -> PowerState {
+    Connection::system()
+        .and_then(|connection| query(&connection))
+        .unwrap_or_else(|e| {
+            tracing::warn!("failed to get the power state from UPower: {}", e);
+            PowerState::default()
+        })
+}
+
+fn query(connection: &Connection) -> zbus::Result<PowerState> {
+    let upower = Proxy::new(connection, UPOWER, UPOWER_PATH, UPOWER)?;
+    let on_battery = upower.get_property("OnBattery")?;
+    let device = Proxy::new(connection, UPOWER, DISPLAY_DEVICE_PATH, DEVICE_INTERFACE)?;
+    let battery_level = if device.get_property("IsPresent")? {
+        let percentage: f64 = device.get_property("Percentage")?;
+        Some(percentage / 100.0)
+    } else {
+        None
+    };
+    Ok(PowerState {
+        on_battery,
+        battery_level,
+        low_power_mode: power_saver(connection),
+    })
+}
+
+/// Whether power-profiles-daemon is in its power saver profile. Many systems don't run it, so
+/// we don't treat failure as an error.
+fn power_saver(connection: &Connection) -> bool {
+    let profile = Proxy::new(
+        connection,
+        POWER_PROFILES,
+        POWER_PROFILES_PATH,
+        POWER_PROFILES,
+    )
+    .and_then(|proxy| proxy.get_property::<String>("ActiveProfile"));
+    matches!(profile.as_deref(), Ok("power-saver"))
+}
+
+/// The last power state we reported, and the callback to report changes to.
+type Watcher<F> = Arc<Mutex<(Option<PowerState>, F)>>;
+
+/// Calls `on_change` from a background thread whenever the power state changes.
+pub(crate) fn watch_power_state(on_change: impl Fn(PowerState) + Send + 'static) {
+    let watcher = Arc::new(Mutex::new((None, on_change)));
+    // Plugging in or unplugging also changes the display device's `State`, so watching it is
+    // enough to catch changes to `OnBattery` as well.
+    for (destination, path) in [
+        (UPOWER, DISPLAY_DEVICE_PATH),
+        (POWER_PROFILES, POWER_PROFILES_PATH),
+    ] {
+        let watcher = watcher.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = watch(destination, path, watcher) {
+                tracing::warn!("stopped watching the power state: {}", e);
+            }
+        });
+    }
+}
+
+fn watch<F: Fn(PowerState)>(
+    destination: &'static str,
+    path: &'static str,
+    watcher: Watcher<F>,
+) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let properties = PropertiesProxyBlocking::builder(&connection)
+        .destination(destination)?
+        .path(path)?
+        .build()?;
+    let changes = properties.receive_properties_changed()?;
+    {
+        let state = query(&connection)?;
+        watcher.lock().unwrap().0.get_or_insert(state);
+    }
+    for _ in changes {
+        let state = query(&connection)?;
+        let mut watcher = watcher.lock().unwrap();
+        if watcher.0 != Some(state) {
+            watcher.0 = Some(state);
+            (watcher.1)(state);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Sleep and session lock notifications from logind over DBus.
+
+use std::sync::{Arc, Mutex};
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::OwnedObjectPath;
+
+use super::sleep::{delay_system_sleep, SleepGuard};
+use crate::AppHandler;
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// A change to the system's sleep or the session's lock, for the [`AppHandler`].
+pub(crate) enum SessionEvent {
+    /// The system is about to sleep, which it delays until the guard is dropped.
+    WillSleep(SleepGuard),
+    DidWake,
+    Locked,
+    Unlocked,
+}
+
+impl SessionEvent {
+    /// Tell `handler` about the event. Sleep is delayed until this returns.
+    pub(crate) fn deliver(self, handler: Option<&mut dyn AppHandler>) {
+        let Some(handler) = handler else {
+            return;
+        };
+        match self {
+            SessionEvent::WillSleep(_delay) => handler.system_will_sleep(),
+            SessionEvent::DidWake => handler.system_did_wake(),
+            SessionEvent::Locked => handler.session_locked(),
+            SessionEvent::Unlocked => handler.session_unlocked(),
+        }
+    }
+}
+
+/// Calls `on_event` from a background thread whenever the system sleeps or wakes, or the
+/// session is locked or unlocked.
+pub(crate) fn watch_session(on_event: impl Fn(SessionEvent) + Send + 'static) {
+    let on_event = Arc::new(Mutex::new(on_event));
+    let sleep_event = on_event.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = watch_sleep(|event| (sleep_event.lock().unwrap())(event)) {
+            tracing::warn!("stopped watching for system sleep: {}", e);
+        }
+    });
+    std::thread::spawn(move || {
+        if let Err(e) = watch_lock(|event| (on_event.lock().unwrap())(event)) {
+            tracing::warn!("stopped watching the session's lock: {}", e);
+        }
+    });
+}
+
+fn watch_sleep(on_event: impl Fn(SessionEvent)) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let manager = Proxy::new(&connection, LOGIND, LOGIND_PATH, MANAGER_INTERFACE)?;
+    let signals = manager.receive_signal("PrepareForSleep")?;
+    // By the time logind tells us it's going to sleep, it's too late to delay it
+    let mut delay = Some(delay_system_sleep());
+    for signal in signals {
+        let sleeping: bool = signal.body()?;
+        if sleeping {
+            on_event(SessionEvent::WillSleep(delay.take().unwrap_or_default()));
+        } else {
+            delay = Some(delay_system_sleep());
+            on_event(SessionEvent::DidWake);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the session is locked comes from its `LockedHint`, which the screen locker sets
+/// however it was locked.
+fn watch_lock(on_event: impl Fn(SessionEvent)) -> zbus::Result<()> {
+    let connection = Connection::system()?;
+    let manager = Proxy::new(&connection, LOGIND, LOGIND_PATH, MANAGER_INTERFACE)?;
+    // The session of this process, or else the user's graphical session
+    let path: OwnedObjectPath = manager.call("GetSession", &("auto",))?;
+    let session = Proxy::new(&connection, LOGIND, path, SESSION_INTERFACE)?;
+    let changes = session.receive_property_changed::<bool>("LockedHint");
+    let mut locked: bool = session.get_property("LockedHint")?;
+    for change in changes {
+        let now = change.get()?;
+        if now != locked {
+            locked = now;
+            on_event(if locked {
+                SessionEvent::Locked
+            } else {
+                SessionEvent::Unlocked
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,375 @@
+//! Reading desktop settings from the XDG settings portal over DBus.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::application::{
+    A11yPrefs, Color, FontDescription, SubpixelOrder, SystemColor, SystemFonts, TextAntialiasing,
+    TextRenderingPrefs, UiMetrics,
+};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+/// Where GNOME keeps most of its appearance settings.
+const INTERFACE: &str = "org.gnome.desktop.interface";
+
+/// The last settings we read, which `watch_settings` keeps up to date.
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+/// The settings which come from the portal, read together so that one watcher can keep them
+/// all up to date.
+#[derive(Clone, PartialEq)]
+struct Settings {
+    a11y_prefs: A11yPrefs,
+    fonts: SystemFonts,
+    /// GNOME's `text-scaling-factor`, which the fonts already include.
+    text_scale: f64,
+    metrics: UiMetrics,
+    text_rendering: TextRenderingPrefs,
+    accent_color: Option<Color>,
+    /// Whether the desktop prefers a dark style.
+    dark: bool,
+}
+
+/// GNOME's accent colors, which it only reports by name.
+const GNOME_ACCENT_COLORS: &[(&str, Color)] = &[
+    ("blue", Color::rgb8(0x35, 0x84, 0xe4)),
+    ("teal", Color::rgb8(0x21, 0x90, 0xa4)),
+    ("green", Color::rgb8(0x3a, 0x94, 0x4a)),
+    ("yellow", Color::rgb8(0xc8, 0x88, 0x00)),
+    ("orange", Color::rgb8(0xed, 0x5b, 0x00)),
+    ("red", Color::rgb8(0xe6, 0x2d, 0x42)),
+    ("pink", Color::rgb8(0xd5, 0x61, 0x99)),
+    ("purple", Color::rgb8(0x91, 0x41, 0xac)),
+    ("slate", Color::rgb8(0x6f, 0x83, 0x96)),
+];
+
+/// The font size GTK uses when there's no setting, in points.
+const DEFAULT_FONT_SIZE: f64 = 10.0;
+
+pub(crate) fn accessibility_preferences() -> A11yPrefs {
+    with_settings(|settings| settings.a11y_prefs)
+}
+
+pub(crate) fn system_fonts() -> SystemFonts {
+    with_settings(|settings| settings.fonts.clone())
+}
+
+pub(crate) fn text_scale_factor() -> f64 {
+    with_settings(|settings| settings.text_scale)
+}
+
+pub(crate) fn ui_metrics() -> UiMetrics {
+    with_settings(|settings| settings.metrics)
+}
+
+pub(crate) fn text_rendering_prefs() -> TextRenderingPrefs {
+    with_settings(|settings| settings.text_rendering)
+}
+
+pub(crate) fn accent_color() -> Option<Color> {
+    with_settings(|settings| settings.accent_color)
+}
+
+/// The colors of libadwaita, GNOME's theme, in the desktop's light or dark style.
+pub(crate) fn system_color(color: SystemColor) -> Color {
+    let (accent_color, dark) = with_settings(|settings| (settings.accent_color, settings.dark));
+    match (color, dark) {
+        (SystemColor::Highlight, _) => accent_color.unwrap_or(GNOME_ACCENT_COLORS[0].1),
+        (SystemColor::Window, false) => Color::rgb8(0xfa, 0xfa, 0xfb),
+        (SystemColor::Window, true) => Color::rgb8(0x22, 0x22, 0x26),
+        (SystemColor::Text, false) => Color::rgba8(0x00, 0x00, 0x06, 0xcc),
+        (SystemColor::Text, true) => Color::rgb8(0xff, 0xff, 0xff),
+    }
+}
+
+fn with_settings<T>(f: impl FnOnce(&Settings) -> T) -> T {
+    f(SETTINGS.lock().unwrap().get_or_insert_with(read_settings))
+}
+
+fn read_settings() -> Settings {
+    Connection::session()
+        .and_then(|connection| query(&connection))
+        .unwrap_or_else(|e| {
+            tracing::warn!("failed to read settings from the settings portal: {}", e);
+            Settings {
+                a11y_prefs: A11yPrefs::default(),
+                fonts: fonts(None, None, None, 1.0),
+                text_scale: 1.0,
+                metrics: metrics(true, None, None),
+                text_rendering: text_rendering(None, None, None),
+                accent_color: None,
+                dark: false,
+            }
+        })
+}
+
+fn query(connection: &Connection) -> zbus::Result<Settings> {
+    let settings = Proxy::new(connection, PORTAL, PORTAL_PATH, SETTINGS_INTERFACE)?;
+    // `contrast` is the desktop neutral setting, but isn't supported everywhere yet.
+    let high_contrast = read::<u32>(&settings, "org.freedesktop.appearance", "contrast") == Some(1)
+        || read(
+            &settings,
+            "org.gnome.desktop.a11y.interface",
+            "high-contrast",
+        )
+        .unwrap_or(false);
+    let enable_animations = read(&settings, INTERFACE, "enable-animations").unwrap_or(true);
+    let a11y_prefs = A11yPrefs {
+        reduce_motion: !enable_animations,
+        high_contrast,
+        // There's no standard setting for this.
+        prefer_reduced_transparency: false,
+    };
+    let text_scale = read(&settings, INTERFACE, "text-scaling-factor")
+        .filter(|scale: &f64| *scale > 0.0)
+        .unwrap_or(1.0);
+    let fonts = fonts(
+        read(&settings, INTERFACE, "font-name"),
+        read(&settings, INTERFACE, "monospace-font-name"),
+        read(
+            &settings,
+            "org.gnome.desktop.wm.preferences",
+            "titlebar-font",
+        ),
+        text_scale,
+    );
+    let metrics = metrics(
+        read(&settings, INTERFACE, "cursor-blink").unwrap_or(true),
+        read(&settings, INTERFACE, "cursor-blink-time"),
+        read(
+            &settings,
+            "org.gnome.desktop.peripherals.mouse",
+            "drag-threshold",
+        ),
+    );
+    // The desktop neutral accent color is new, so fall back to GNOME's own setting.
+    let accent_color = read(&settings, "org.freedesktop.appearance", "accent-color")
+        .and_then(accent_color_from_rgb)
+        .or_else(|| {
+            let name: String = read(&settings, INTERFACE, "accent-color")?;
+            GNOME_ACCENT_COLORS
+                .iter()
+                .find(|(accent, _)| *accent == name)
+                .map(|(_, color)| *color)
+        });
+    let dark = match read::<u32>(&settings, "org.freedesktop.appearance", "color-scheme") {
+        Some(scheme) => scheme == 1,
+        None => {
+            read::<String>(&settings, INTERFACE, "color-scheme").as_deref() == Some("prefer-dark")
+        }
+    };
+    let text_rendering = text_rendering(
+        read(&settings, INTERFACE, "font-antialiasing"),
+        read(&settings, INTERFACE, "font-rgba-order"),
+        read(&settings, INTERFACE, "font-hinting"),
+    );
+    Ok(Settings {
+        a11y_prefs,
+        fonts,
+        text_scale,
+        metrics,
+        text_rendering,
+        accent_color,
+        dark,
+    })
+}
+
+/// Reads the `(ddd)` accent color of the settings portal, whose components are out of range
+/// when the user hasn't picked one.
+fn accent_color_from_rgb(value: OwnedValue) -> Option<Color> {
+    let Value::Structure(rgb) = Value::from(value) else {
+        return None;
+    };
+    let components = rgb
+        .fields()
+        .iter()
+        .map(|field| match field {
+            Value::F64(c) if (0.0..=1.0).contains(c) => Some(*c),
+            _ => None,
+        })
+        .collect::<Option<Vec<f64>>>()?;
+    match components[..] {
+        [r, g, b] => Some(Color::from_f64(r, g, b, 1.0)),
+        _ => None,
+    }
+}
+
+/// Builds the system fonts from GSettings font names, falling back to fontconfig's generic
+/// families where a setting is missing.
+fn fonts(
+    ui: Option<String>,
+    monospace: Option<String>,
+    title: Option<String>,
+    text_scale: f64,
+) -> SystemFonts {
+    let font = |name: Option<String>, fallback: &str| {
+        let (family, points) = name
+            .as_deref()
+            .and_then(parse_font_name)
+            .unwrap_or_else(|| (fallback.to_string(), DEFAULT_FONT_SIZE));
+        // Display points are 1/96 of an inch, rather than 1/72.
+        FontDescription {
+            family,
+            size: points * text_scale * 96.0 / 72.0,
+        }
+    };
+    // The title bar uses the UI font unless it has been set separately.
+    let title = font(title.or_else(|| ui.clone()), "Sans");
+    let ui = font(ui, "Sans");
+    let small = FontDescription {
+        family: ui.family.clone(),
+        size: ui.size * 0.9,
+    };
+    SystemFonts {
+        monospace: font(monospace, "Monospace"),
+        menu: ui.clone(),
+        title,
+        small,
+        ui,
+    }
+}
+
+/// Splits a Pango font description, such as `Cantarell Bold 11`, into its family and size in
+/// points. Style words are dropped from the family, since they aren't part of its name.
+fn parse_font_name(name: &str) -> Option<(String, f64)> {
+    const STYLES: &[&str] = &[
+        "Normal",
+        "Regular",
+        "Italic",
+        "Oblique",
+        "Thin",
+        "Light",
+        "Medium",
+        "Semi-Bold",
+        "Bold",
+        "Heavy",
+        "Condensed",
+    ];
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    let size = match words.pop()? {
+        // Absolute sizes are given in device pixels.
+        size if size.ends_with("px") => size.trim_end_matches("px").parse::<f64>().ok()? * 0.75,
+        size => size.parse().ok()?,
+    };
+    while words.last().map_or(false, |word| STYLES.contains(word)) {
+        words.pop();
+    }
+    let family = words.join(" ").trim_end_matches(',').to_string();
+    (!family.is_empty()).then_some((family, size))
+}
+
+/// Builds the UI metrics from GSettings, using GTK's defaults where a setting is missing.
+fn metrics(blink: bool, blink_time: Option<i32>, drag_threshold: Option<i32>) -> UiMetrics {
+    // The blink time is for a whole cycle, on and then off.
+    let blink_time = blink_time.filter(|ms| *ms > 0).unwrap_or(1200);
+    UiMetrics {
+        // Neither GTK nor Qt let the user set this, so use the width of an Adwaita scrollbar.
+        scrollbar_width: 14.0,
+        // Nor this, which is the width of GTK's caret.
+        caret_width: 1.0,
+        caret_blink: blink.then(|| Duration::from_millis(blink_time as u64 / 2)),
+        drag_threshold: drag_threshold.unwrap_or(8) as f64,
+        menu_show_delay: Duration::from_millis(225),
+    }
+}
+
+/// Builds the text rendering preferences from GSettings, using GNOME's defaults where a
+/// setting is missing.
+fn text_rendering(
+    antialiasing: Option<String>,
+    rgba_order: Option<String>,
+    hinting: Option<String>,
+) -> TextRenderingPrefs {
+    let order = match rgba_order.as_deref() {
+        Some("bgr") => SubpixelOrder::Bgr,
+        Some("vrgb") => SubpixelOrder::VerticalRgb,
+        Some("vbgr") => SubpixelOrder::VerticalBgr,
+        _ => SubpixelOrder::Rgb,
+    };
+    let antialiasing = match antialiasing.as_deref() {
+        Some("none") => TextAntialiasing::None,
+        Some("rgba") => TextAntialiasing::Subpixel(order),
+        _ => TextAntialiasing::Grayscale,
+    };
+    TextRenderingPrefs {
+        antialiasing,
+        // GNOME's default is slight hinting.
+        hinting: hinting.as_deref() != Some("none"),
+        // Fontconfig has no gamma setting, and FreeType blends linearly.
+        gamma: 1.0,
+    }
+}
+
+/// Reads a single setting. Desktops only provide some settings, so a missing or mistyped
+/// setting isn't treated as an error.
+fn read<T: TryFrom<OwnedValue>>(settings: &Proxy, namespace: &str, key: &str) -> Option<T> {
+    let value: OwnedValue = settings.call("Read", &(namespace, key)).ok()?;
+    // `Read` wraps the setting in a second variant.
+    let value = match Value::from(value) {
+        Value::Value(inner) => OwnedValue::from(*inner),
+        value => OwnedValue::from(value),
+    };
+    T::try_from(value).ok()
+}
+
+/// Calls `on_a11y_change`, `on_fonts_change`, `on_text_scale_change` and `on_colors_change`
+/// from a background thread whenever the accessibility preferences, the system fonts, the
+/// text scale factor or the colors change.
+pub(crate) fn watch_settings(
+    on_a11y_change: impl Fn(A11yPrefs) + Send + 'static,
+    on_fonts_change: impl Fn(SystemFonts) + Send + 'static,
+    on_text_scale_change: impl Fn(f64) + Send + 'static,
+    on_colors_change: impl Fn(Option<Color>) + Send + 'static,
+) {
+    std::thread::spawn(move || {
+        if let Err(e) = watch(
+            on_a11y_change,
+            on_fonts_change,
+            on_text_scale_change,
+            on_colors_change,
+        ) {
+            tracing::warn!("stopped watching the settings portal: {}", e);
+        }
+    });
+}
+
+fn watch(
+    on_a11y_change: impl Fn(A11yPrefs),
+    on_fonts_change: impl Fn(SystemFonts),
+    on_text_scale_change: impl Fn(f64),
+    on_colors_change: impl Fn(Option<Color>),
+) -> zbus::Result<()> {
+    let connection = Connection::session()?;
+    let settings = Proxy::new(&connection, PORTAL, PORTAL_PATH, SETTINGS_INTERFACE)?;
+    let changes = settings.receive_signal("SettingChanged")?;
+    {
+        let settings = query(&connection)?;
+        SETTINGS.lock().unwrap().get_or_insert(settings);
+    }
+    for _ in changes {
+        let settings = query(&connection)?;
+        let mut cached = SETTINGS.lock().unwrap();
+        let old = cached.replace(settings.clone());
+        drop(cached);
+        if old.as_ref().map(|old| old.a11y_prefs) != Some(settings.a11y_prefs) {
+            on_a11y_change(settings.a11y_prefs);
+        }
+        if old.as_ref().map(|old| &old.fonts) != Some(&settings.fonts) {
+            on_fonts_change(settings.fonts);
+        }
+        if old.as_ref().map(|old| old.text_scale) != Some(settings.text_scale) {
+            on_text_scale_change(settings.text_scale);
+        }
+        if old.as_ref().map(|old| (old.accent_color, old.dark))
+            != Some((settings.accent_color, settings.dark))
+        {
+            on_colors_change(settings.accent_color);
+        }
+    }
+    Ok(())
+}
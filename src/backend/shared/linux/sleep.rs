@@ -0,0 +1,144 @@
+//! Inhibiting display and system sleep over DBus.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedFd, OwnedObjectPath, Value};
+
+use super::env::app_name;
+use crate::error::Error as ShellError;
+use crate::WakeLockKind;
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+
+/// The portal's flags for inhibiting suspending, and the session being marked as idle.
+const PORTAL_SUSPEND: u32 = 4;
+const PORTAL_IDLE: u32 = 8;
+
+/// Releases its inhibition when dropped.
+///
+/// The default guard inhibits nothing.
+#[derive(Default)]
+pub(crate) struct SleepGuard(Option<Inhibitor>);
+
+enum Inhibitor {
+    /// `org.freedesktop.ScreenSaver` inhibitions are tied to the connection which made them,
+    /// so it must be kept open until we `UnInhibit`.
+    ScreenSaver { connection: Connection, cookie: u32 },
+    /// logind releases the inhibitor lock once its file descriptor is closed.
+    Logind(OwnedFd),
+    /// The inhibit portal, which sandboxed applications have to use, releases its inhibition
+    /// when the request is closed, or its connection is.
+    Portal {
+        connection: Connection,
+        request: OwnedObjectPath,
+    },
+}
+
+/// Inhibit `kind` of sleep, falling back to the inhibit portal when the desktop's own
+/// services can't be reached, as in a sandbox.
+pub(crate) fn acquire_wake_lock(
+    kind: WakeLockKind,
+    reason: &str,
+) -> Result<SleepGuard, ShellError> {
+    let (inhibitor, portal_flags) = match kind {
+        WakeLockKind::Display => (inhibit_screen_saver(reason), PORTAL_IDLE | PORTAL_SUSPEND),
+        WakeLockKind::System => (
+            inhibit_logind("sleep:idle", reason, "block"),
+            PORTAL_SUSPEND,
+        ),
+    };
+    match inhibitor.or_else(|e| {
+        tracing::debug!("falling back to the inhibit portal: {}", e);
+        inhibit_portal(portal_flags, reason)
+    }) {
+        Ok(inhibitor) => Ok(SleepGuard(Some(inhibitor))),
+        Err(e) => Err(anyhow!("failed to inhibit {kind:?} sleep: {e}").into()),
+    }
+}
+
+fn inhibit_screen_saver(reason: &str) -> zbus::Result<Inhibitor> {
+    let connection = Connection::session()?;
+    let reply = connection.call_method(
+        Some("org.freedesktop.ScreenSaver"),
+        "/org/freedesktop/ScreenSaver",
+        Some("org.freedesktop.ScreenSaver"),
+        "Inhibit",
+        &(app_name(), reason),
+    )?;
+    let cookie = reply.body()?;
+    Ok(Inhibitor::ScreenSaver { connection, cookie })
+}
+
+fn inhibit_portal(flags: u32, reason: &str) -> zbus::Result<Inhibitor> {
+    let connection = Connection::session()?;
+    let options = HashMap::from([("reason", Value::from(reason))]);
+    let reply = connection.call_method(
+        Some(PORTAL),
+        "/org/freedesktop/portal/desktop",
+        Some("org.freedesktop.portal.Inhibit"),
+        "Inhibit",
+        // We have no parent window to give
+        &("", flags, options),
+    )?;
+    let request = reply.body()?;
+    Ok(Inhibitor::Portal {
+        connection,
+        request,
+    })
+}
+
+/// Delays system sleep until the guard is dropped, for at most logind's `InhibitDelayMaxSec`.
+///
+/// This has to be taken before the system starts preparing to sleep.
+pub(crate) fn delay_system_sleep() -> SleepGuard {
+    match inhibit_logind("sleep", "Letting the application prepare to sleep", "delay") {
+        Ok(inhibitor) => SleepGuard(Some(inhibitor)),
+        Err(e) => {
+            tracing::warn!("failed to delay system sleep: {}", e);
+            SleepGuard(None)
+        }
+    }
+}
+
+fn inhibit_logind(what: &str, reason: &str, mode: &str) -> zbus::Result<Inhibitor> {
+    let connection = Connection::system()?;
+    let reply = connection.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        Some("org.freedesktop.login1.Manager"),
+        "Inhibit",
+        &(what, app_name(), reason, mode),
+    )?;
+    Ok(Inhibitor::Logind(reply.body()?))
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        let res = match &self.0 {
+            Some(Inhibitor::ScreenSaver { connection, cookie }) => connection.call_method(
+                Some("org.freedesktop.ScreenSaver"),
+                "/org/freedesktop/ScreenSaver",
+                Some("org.freedesktop.ScreenSaver"),
+                "UnInhibit",
+                cookie,
+            ),
+            Some(Inhibitor::Portal {
+                connection,
+                request,
+            }) => connection.call_method(
+                Some(PORTAL),
+                request.as_str(),
+                Some("org.freedesktop.portal.Request"),
+                "Close",
+                &(),
+            ),
+            // Dropping the logind file descriptor releases its lock.
+            Some(Inhibitor::Logind(_)) | None => return,
+        };
+        if let Err(e) = res {
+            tracing::warn!("failed to release sleep inhibition: {}", e);
+        }
+    }
+}
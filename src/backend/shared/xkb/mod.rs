@@ -253,6 +253,19 @@ impl State {
         // TODO not sure how to get this
         let is_composing = false;
 
+        KeyEvent {
+            state,
+            key,
+            code,
+            location,
+            mods: self.modifiers(),
+            repeat,
+            is_composing,
+        }
+    }
+
+    /// The modifiers which are active, such as for pointer events.
+    pub fn modifiers(&self) -> Modifiers {
         let mut mods = Modifiers::empty();
         // Update xkb's state (e.g. return capitals if we've pressed shift)
         unsafe {
@@ -270,15 +283,7 @@ impl State {
                 }
             }
         }
-        KeyEvent {
-            state,
-            key,
-            code,
-            location,
-            mods,
-            repeat,
-            is_composing,
-        }
+        mods
     }
 
     fn get_logical_key(&mut self, scancode: u32) -> Key {
@@ -15,36 +15,53 @@
 #![allow(clippy::single_match)]
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::HashMap,
     ffi::c_void,
+    os::unix::io::RawFd,
     rc::{Rc, Weak},
-    sync::mpsc::{Sender, TryRecvError},
+    sync::{
+        mpsc::{Sender, TryRecvError},
+        Arc,
+    },
+    time::Duration,
 };
 
 use smithay_client_toolkit::{
     compositor::CompositorState,
+    data_device_manager::DataDeviceManagerState,
     output::OutputState,
     reexports::{
-        calloop::{channel, EventLoop, LoopHandle, LoopSignal},
+        calloop::{
+            channel, generic::Generic, EventLoop, Interest, LoopHandle, LoopSignal, Mode,
+            PostAction, RegistrationToken,
+        },
         client::{
             globals::{registry_queue_init, BindError},
             protocol::wl_compositor,
             Connection, QueueHandle, WaylandSource,
         },
+        protocols::wp::presentation_time::client::wp_presentation::WpPresentation,
     },
     registry::RegistryState,
     seat::SeatState,
-    shell::xdg::XdgShell,
+    shell::{wlr_layer::LayerShell, xdg::XdgShell},
+    shm::Shm,
+    subcompositor::SubcompositorState,
 };
 
-use super::{clipboard, error::Error, ActiveAction, IdleAction, WaylandState};
+use super::{
+    clipboard::{self, ClipboardState},
+    error::Error,
+    fractional_scale::FractionalScaleManager,
+    ActiveAction, IdleAction, WaylandState,
+};
 use crate::{
     backend::{
         shared::{linux, xkb::Context},
         wayland::input::TextInputManagerData,
     },
-    AppHandler,
+    common_util, AppHandler, EventSourceToken, LoopMode, LoopStatus, UiDirection,
 };
 
 #[derive(Clone)]
@@ -54,22 +71,42 @@ pub struct Application {
     // The inner is taken in `run`
     state: Rc<RefCell<Option<WaylandState>>>,
     pub(super) compositor: wl_compositor::WlCompositor,
+    /// Used for `WindowHandle::last_frame_timings`, if the compositor supports it
+    pub(super) presentation: Option<WpPresentation>,
+    /// Used to render at fractional scales, if the compositor supports it
+    pub(super) fractional_scale: Option<FractionalScaleManager>,
     pub(super) wayland_queue: QueueHandle<WaylandState>,
     pub(super) xdg_shell: Weak<XdgShell>,
-    // Used for timers and keyboard repeating - not yet implemented
-    #[allow(unused)]
-    loop_handle: LoopHandle<'static, WaylandState>,
+    /// Dangling if the compositor doesn't support `wlr-layer-shell`
+    pub(super) layer_shell: Weak<LayerShell>,
+    // Used for detecting occlusion. Timers and keyboard repeating are not yet implemented
+    pub(super) loop_handle: LoopHandle<'static, WaylandState>,
     loop_signal: LoopSignal,
     pub(super) idle_sender: Sender<IdleAction>,
     pub(super) loop_sender: channel::Sender<ActiveAction>,
     pub(super) raw_display_handle: *mut c_void,
+    /// The calloop sources added by `add_event_source`
+    event_sources: Rc<RefCell<HashMap<EventSourceToken, RegistrationToken>>>,
+    /// Shared with `WaylandState::quitting`
+    quitting: Rc<Cell<bool>>,
+    /// Shared with `WaylandState::clipboard`
+    clipboard: Rc<RefCell<ClipboardState>>,
+    /// Whether the DBus watchers have been started, by the first loop iteration
+    watchers_started: Rc<Cell<bool>>,
+    /// Set by `Application::new_with_name`, and given to every toplevel
+    pub(super) app_id: Rc<RefCell<Option<String>>>,
+    /// Shared with `WaylandState::accessibility`
+    #[cfg(feature = "accesskit")]
+    accessibility: linux::accesskit::AccessibilityStatus,
 }
 
 impl Application {
     pub fn new() -> Result<Self, Error> {
         tracing::info!("wayland application initiated");
+        Self::with_connection(Connection::connect_to_env()?)
+    }
 
-        let conn = Connection::connect_to_env()?;
+    pub(super) fn with_connection(conn: Connection) -> Result<Self, Error> {
         let (globals, event_queue) = registry_queue_init::<WaylandState>(&conn).unwrap();
         let qh = event_queue.handle();
         let event_loop: EventLoop<WaylandState> = EventLoop::try_new()?;
@@ -100,10 +137,16 @@ impl Application {
 
         let compositor_state: CompositorState = CompositorState::bind(&globals, &qh)?;
         let compositor = compositor_state.wl_compositor().clone();
+        let subcompositor = SubcompositorState::bind(compositor.clone(), &globals, &qh)
+            .map(Arc::new)
+            .ok();
 
         let (idle_sender, idle_actions) = std::sync::mpsc::channel();
         let shell = Rc::new(XdgShell::bind(&globals, &qh)?);
         let shell_ref = Rc::downgrade(&shell);
+        let layer_shell = LayerShell::bind(&globals, &qh).ok().map(Rc::new);
+        let layer_shell_ref = layer_shell.as_ref().map_or_else(Weak::new, Rc::downgrade);
+        let shm = Shm::bind(&globals, &qh)?;
         let text_input_global = globals.bind(&qh, 1..=1, TextInputManagerData).map_or_else(
             |err| match err {
                 e @ BindError::UnsupportedVersion => Err(e),
@@ -111,12 +154,26 @@ impl Application {
             },
             |it| Ok(Some(it)),
         )?;
+        let presentation = globals.bind(&qh, 1..=1, ()).ok();
+        let fractional_scale = FractionalScaleManager::bind(&globals, &qh);
+        let clipboard = Rc::new(RefCell::new(ClipboardState::new(
+            conn.clone(),
+            qh.clone(),
+            DataDeviceManagerState::bind(&globals, &qh).ok(),
+            globals.bind(&qh, 1..=2, ()).ok(),
+        )));
 
+        let quitting = Rc::new(Cell::new(false));
+        #[cfg(feature = "accesskit")]
+        let accessibility = linux::accesskit::AccessibilityStatus::default();
         let mut state = WaylandState {
             registry_state: RegistryState::new(&globals),
             output_state: OutputState::new(&globals, &qh),
-            _compositor_state: compositor_state,
+            compositor_state,
+            subcompositor,
             _xdg_shell_state: shell,
+            _layer_shell_state: layer_shell,
+            shm,
             event_loop: Some(event_loop),
             handler: None,
             idle_actions,
@@ -125,72 +182,251 @@ impl Application {
             wayland_queue: qh.clone(),
             _loop_sender: loop_sender.clone(),
             loop_signal: loop_signal.clone(),
+            quitting: quitting.clone(),
             input_states: vec![],
             seats: SeatState::new(&globals, &qh),
             xkb_context: Context::new(),
             text_input: text_input_global,
+            clipboard: clipboard.clone(),
+            // Until the compositor tells us otherwise
+            presentation_clock: nix::libc::CLOCK_MONOTONIC as u32,
+            idling: false,
+            #[cfg(feature = "accesskit")]
+            accessibility: accessibility.clone(),
         };
         state.initial_seats();
         Ok(Application {
             state: Rc::new(RefCell::new(Some(state))),
             compositor,
+            presentation,
+            fractional_scale,
             wayland_queue: qh,
             loop_handle,
             loop_signal,
             idle_sender,
             loop_sender,
             xdg_shell: shell_ref,
+            layer_shell: layer_shell_ref,
             raw_display_handle: conn.backend().display_ptr().cast(),
+            event_sources: Default::default(),
+            quitting,
+            clipboard,
+            watchers_started: Default::default(),
+            app_id: Default::default(),
+            #[cfg(feature = "accesskit")]
+            accessibility,
         })
     }
 
+    pub fn set_app_id(&self, app_id: &str) {
+        *self.app_id.borrow_mut() = Some(app_id.to_owned());
+    }
+
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        // There is no state once we have run until we quit, so nothing to handle
+        if let Some(state) = self.state.borrow_mut().as_mut() {
+            state.handler = handler;
+        }
+    }
+
     pub fn run(self, handler: Option<Box<dyn AppHandler>>) {
         tracing::info!("wayland event loop initiated");
-        let mut state = self
-            .state
-            .borrow_mut()
-            .take()
-            .expect("Can only run an application once");
-        state.handler = handler;
-        let mut event_loop = state.event_loop.take().unwrap();
-        event_loop
-            .run(None, &mut state, |state| loop {
-                match state.idle_actions.try_recv() {
-                    Ok(IdleAction::Callback(cb)) => cb(state),
-                    Ok(IdleAction::Token(window, token)) => match state.windows.get_mut(&window) {
-                        Some(state) => state.handler.idle(token),
-                        None => {
-                            tracing::debug!("Tried to run an idle token on a non-existant window")
-                        }
-                    },
-                    Err(TryRecvError::Empty) => break,
-                    Err(TryRecvError::Disconnected) => {
-                        unreachable!("Backend has allowed the idle sender to be dropped")
-                    }
-                }
+        self.set_handler(handler);
+        while self.run_loop_iteration(LoopMode::Wait) == LoopStatus::Continue {}
+    }
+
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        let mut state = self.state.borrow_mut();
+        let Some(wl_state) = state.as_mut() else {
+            // We have already run until we quit
+            return LoopStatus::Exit;
+        };
+        self.start_watchers();
+        let timeout = match mode {
+            LoopMode::Wait => None,
+            LoopMode::WaitTimeout(timeout) => Some(timeout),
+            LoopMode::Poll => Some(Duration::ZERO),
+        };
+        let mut event_loop = wl_state.event_loop.take().unwrap();
+        if let Err(e) = event_loop.dispatch(timeout, wl_state) {
+            backend_error(wl_state, e.into());
+            self.quitting.set(true);
+        }
+        run_idle_actions(wl_state, mode == LoopMode::Poll);
+        wl_state.event_loop = Some(event_loop);
+        if self.quitting.get() {
+            // Drop our state, now that there is nothing left to run
+            destroy_windows(wl_state);
+            *state = None;
+            LoopStatus::Exit
+        } else {
+            LoopStatus::Continue
+        }
+    }
+
+    /// Dispatch the events which arrive within `timeout`, without the rest of an iteration
+    #[cfg(test)]
+    pub(super) fn dispatch_events(&self, timeout: Duration) {
+        let mut state = self.state.borrow_mut();
+        let wl_state = state.as_mut().unwrap();
+        let mut event_loop = wl_state.event_loop.take().unwrap();
+        event_loop.dispatch(Some(timeout), wl_state).unwrap();
+        wl_state.event_loop = Some(event_loop);
+    }
+
+    /// Start watching DBus for changes to the power state, the session and desktop settings.
+    fn start_watchers(&self) {
+        if self.watchers_started.replace(true) {
+            return;
+        }
+        let handle = self.get_handle().unwrap();
+        linux::power::watch_power_state(move |power_state| {
+            let handle = handle.clone();
+            common_util::debounce_power_state(power_state, move |power_state| {
+                handle.run_on_main(move |handler| {
+                    common_util::power_state_changed(handler, power_state)
+                })
             })
-            .expect("Shouldn't error in event loop");
+        });
+        let handle = self.get_handle().unwrap();
+        linux::session::watch_session(move |event| {
+            handle.run_on_main(move |handler| event.deliver(handler))
+        });
+        let handle = self.get_handle().unwrap();
+        let fonts_handle = handle.clone();
+        let text_scale_handle = handle.clone();
+        let colors_handle = handle.clone();
+        linux::settings::watch_settings(
+            move |prefs| {
+                handle.run_on_main(move |handler| {
+                    if let Some(handler) = handler {
+                        handler.accessibility_preferences_changed(prefs);
+                    }
+                })
+            },
+            move |fonts| {
+                fonts_handle.run_on_main(move |handler| {
+                    if let Some(handler) = handler {
+                        handler.system_fonts_changed(fonts);
+                    }
+                })
+            },
+            move |factor| {
+                text_scale_handle.run_on_main(move |handler| {
+                    if let Some(handler) = handler {
+                        handler.text_scale_factor_changed(factor);
+                    }
+                })
+            },
+            move |color| {
+                colors_handle.run_on_main(move |handler| {
+                    if let Some(handler) = handler {
+                        handler.accent_color_changed(color);
+                    }
+                })
+            },
+        );
+        #[cfg(feature = "accesskit")]
+        {
+            let handle = self.get_handle().unwrap();
+            self.accessibility.watch(move || {
+                handle.run_on_state(|state| {
+                    for window in state.windows.values_mut() {
+                        window.init_accesskit();
+                    }
+                })
+            });
+        }
     }
 
     pub fn quit(&self) {
-        // Stopping the event loop should be sufficient, as our state is dropped upon `run` finishing
+        // Our state is dropped by the loop iteration which sees that we are quitting
+        self.quitting.set(true);
         self.loop_signal.stop();
         self.loop_signal.wakeup();
     }
 
     pub fn clipboard(&self) -> clipboard::Clipboard {
-        // TODO: Wayland's clipboard is inherently asynchronous (as is the web)
-        clipboard::Clipboard {}
+        // TODO: Wayland's clipboard is inherently asynchronous (as is the web), so reads block
+        clipboard::Clipboard::new(self.clipboard.clone())
     }
 
     pub fn get_locale() -> String {
         linux::env::locale()
     }
 
+    pub fn get_locales() -> Vec<String> {
+        linux::env::locales()
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        linux::env::ui_direction()
+    }
+
     pub fn get_handle(&self) -> Option<AppHandle> {
-        Some(AppHandle {
-            loop_sender: self.loop_sender.clone(),
-        })
+        Some(AppHandle::new(self.loop_sender.clone()))
+    }
+
+    pub fn add_event_source(
+        &self,
+        fd: RawFd,
+        mut callback: Box<dyn FnMut(Option<&mut dyn AppHandler>)>,
+    ) -> EventSourceToken {
+        let token = EventSourceToken::next();
+        let source = Generic::new(fd, Interest::READ, Mode::Level);
+        match self.loop_handle.insert_source(source, move |_, _, state| {
+            callback(match state.handler.as_mut() {
+                Some(handler) => Some(&mut **handler),
+                None => None,
+            });
+            Ok(PostAction::Continue)
+        }) {
+            Ok(registration) => {
+                self.event_sources.borrow_mut().insert(token, registration);
+            }
+            Err(err) => tracing::warn!("Adding an event source failed: {err}"),
+        }
+        token
+    }
+
+    pub fn remove_event_source(&self, token: EventSourceToken) {
+        if let Some(registration) = self.event_sources.borrow_mut().remove(&token) {
+            self.loop_handle.remove(registration);
+        }
+    }
+}
+
+/// Run the idle callbacks, which is done after every dispatch of the event loop.
+///
+/// Unless the loop is `polling`, the `AppHandler` is then told that it is about to wait.
+fn run_idle_actions(state: &mut WaylandState, polling: bool) {
+    // This runs after each dispatch, so the events which woke the loop are already handled
+    if std::mem::take(&mut state.idling) {
+        if let Some(handler) = state.handler.as_mut() {
+            handler.idle_ended();
+        }
+    }
+    loop {
+        match state.idle_actions.try_recv() {
+            Ok(IdleAction::Callback(cb)) => cb(state),
+            Ok(IdleAction::Token(window, token)) => match state.windows.get_mut(&window) {
+                Some(state) => state.handler.idle(token),
+                None => {
+                    tracing::debug!("Tried to run an idle token on a non-existant window")
+                }
+            },
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                unreachable!("Backend has allowed the idle sender to be dropped")
+            }
+        }
+    }
+    if polling {
+        return;
+    }
+    if let Some(handler) = state.handler.as_mut() {
+        handler.idle();
+        state.idling = true;
     }
 }
 
@@ -200,6 +436,10 @@ pub struct AppHandle {
 }
 
 impl AppHandle {
+    pub(super) fn new(loop_sender: channel::Sender<ActiveAction>) -> Self {
+        AppHandle { loop_sender }
+    }
+
     pub fn run_on_main<F>(&self, callback: F)
     where
         F: FnOnce(Option<&mut dyn AppHandler>) + Send + 'static,
@@ -219,12 +459,20 @@ impl AppHandle {
     fn run_on_main_state<F>(&self, callback: F)
     where
         F: FnOnce(Option<&mut Box<dyn AppHandler>>) + Send + 'static,
+    {
+        self.run_on_state(|state| callback(state.handler.as_mut()))
+    }
+
+    #[track_caller]
+    /// Run a callback on the whole state of the event loop
+    pub(super) fn run_on_state<F>(&self, callback: F)
+    where
+        F: FnOnce(&mut WaylandState) + Send + 'static,
     {
         match self
             .loop_sender
-            .send(ActiveAction::Callback(Box::new(|state| {
-                callback(state.handler.as_mut())
-            }))) {
+            .send(ActiveAction::Callback(Box::new(callback)))
+        {
             Ok(()) => (),
             Err(err) => {
                 tracing::warn!("Sending idle event loop failed: {err:?}")
@@ -235,3 +483,19 @@ impl AppHandle {
 
 // SAFETY: We only send `Send` items through the channel
 unsafe impl Send for AppHandle {}
+
+/// Log an error which stops the event loop, and pass it to the `AppHandler`.
+/// Destroy the windows which are still open once the event loop has stopped, either because the
+/// application quit or because the connection to the compositor was lost
+fn destroy_windows(state: &mut WaylandState) {
+    for (_, window) in state.windows.drain() {
+        window.destroy();
+    }
+}
+
+fn backend_error(state: &mut WaylandState, error: Error) {
+    tracing::error!("Error dispatching the event loop: {}", error);
+    if let Some(handler) = state.handler.as_mut() {
+        handler.backend_error(crate::Error::Platform(error.into()));
+    }
+}
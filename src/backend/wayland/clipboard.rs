@@ -13,47 +13,1206 @@
 // limitations under the License.
 
 //! Interactions with the system pasteboard on wayland compositors.
+//!
+//! Setting the selection needs the serial of a recent input event, so a write made without one
+//! (for example, while the window isn't focused) is held until the next input event arrives.
+//!
+//! Reads use `wlr-data-control` if the compositor supports it, as that doesn't need focus either.
+//! Otherwise, they use the selection offered to us through `wl_data_device`.
 
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd},
+    rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    poll::{poll, PollFd, PollFlags},
+    unistd::pipe2,
+};
+use smithay_client_toolkit::{
+    data_device_manager::{
+        data_device::{DataDevice, DataDeviceHandler},
+        data_offer::{DataOfferHandler, DragOffer, SelectionOffer},
+        data_source::{CopyPasteSource, DataSourceHandler},
+        DataDeviceManagerState, WritePipe,
+    },
+    delegate_data_device, delegate_data_device_manager, delegate_data_offer, delegate_data_source,
+    reexports::{
+        client::{
+            event_created_child,
+            protocol::{
+                wl_data_device::WlDataDevice, wl_data_device_manager::DndAction,
+                wl_data_source::WlDataSource, wl_seat::WlSeat,
+            },
+            Connection, Dispatch, Proxy, QueueHandle,
+        },
+        protocols_wlr::data_control::v1::client::{
+            zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+            zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+            zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+        },
+    },
+};
+
+use super::WaylandState;
 use crate::{ClipboardFormat, FormatId};
 
+const STRING_TARGETS: [&str; 5] = [
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "TEXT",
+    "STRING",
+    "text/plain",
+];
+
+/// How long we wait for the selection owner to send us its data
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// The system clipboard.
-#[derive(Debug, Clone)]
-pub struct Clipboard {}
+#[derive(Clone)]
+pub struct Clipboard(Rc<RefCell<ClipboardState>>);
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clipboard").finish_non_exhaustive()
+    }
+}
 
 impl Clipboard {
+    pub(super) fn new(state: Rc<RefCell<ClipboardState>>) -> Self {
+        Self(state)
+    }
+
     /// Put a string onto the system clipboard.
-    pub fn put_string(&mut self, _str: impl AsRef<str>) {
-        todo!();
+    pub fn put_string(&mut self, s: impl AsRef<str>) {
+        let bytes = s.as_ref().as_bytes();
+        let formats = STRING_TARGETS
+            .iter()
+            .map(|format| ClipboardFormat::new(format, bytes))
+            .collect::<Vec<_>>();
+        self.put_formats(&formats);
     }
 
     /// Put multi-format data on the system clipboard.
-    pub fn put_formats(&mut self, _formats: &[ClipboardFormat]) {
-        tracing::warn!("clipboard copy not implemented");
-        todo!();
+    pub fn put_formats(&mut self, formats: &[ClipboardFormat]) {
+        self.0.borrow_mut().put_formats(formats)
     }
 
     /// Get a string from the system clipboard, if one is available.
     pub fn get_string(&self) -> Option<String> {
-        todo!();
+        STRING_TARGETS.iter().find_map(|target| {
+            self.get_format(target)
+                .and_then(|data| String::from_utf8(data).ok())
+        })
+    }
+
+    // TODO: Implement these with the primary selection protocol. They are called on every
+    // selection change, so they don't warn.
+    pub fn put_primary(&mut self, _s: impl AsRef<str>) {}
+
+    pub fn get_primary(&self) -> Option<String> {
+        None
     }
 
     /// Given a list of supported clipboard types, returns the supported type which has
     /// highest priority on the system clipboard, or `None` if no types are supported.
-    pub fn preferred_format(&self, _formats: &[FormatId]) -> Option<FormatId> {
-        tracing::warn!("clipboard preferred_format not implemented");
-        None
+    pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
+        let available = self.available_type_names();
+        formats
+            .iter()
+            .find(|f1| available.iter().any(|f2| *f1 == f2))
+            .copied()
     }
 
     /// Return data in a given format, if available.
     ///
     /// It is recommended that the `fmt` argument be a format returned by
     /// [`Clipboard::preferred_format`]
-    pub fn get_format(&self, _format: FormatId) -> Option<Vec<u8>> {
-        todo!();
+    pub fn get_format(&self, format: FormatId) -> Option<Vec<u8>> {
+        self.0.borrow().get_format(format)
     }
 
     pub fn available_type_names(&self) -> Vec<String> {
-        tracing::warn!("clipboard available_type_names not implemented");
-        Vec::new()
+        self.0.borrow().available_type_names()
+    }
+}
+
+/// The clipboard state shared between the `Clipboard` handles and the event loop
+pub(super) struct ClipboardState {
+    connection: Connection,
+    queue: QueueHandle<WaylandState>,
+    /// `None` if the compositor doesn't support `wl_data_device_manager`
+    manager: Option<DataDeviceManagerState>,
+    /// `None` if the compositor doesn't support `wlr-data-control`
+    data_control: Option<ZwlrDataControlManagerV1>,
+    seats: Vec<SeatClipboard>,
+    /// The seat which received input most recently, which we set the selection on
+    last_seat: Option<WlSeat>,
+    /// Our selection, while other clients can read `contents` from it
+    source: Option<CopyPasteSource>,
+    /// What we last put on the clipboard
+    contents: Vec<ClipboardFormat>,
+    /// Whether `contents` is waiting for an input serial before it can be set as the selection
+    pending: bool,
+}
+
+struct SeatClipboard {
+    seat: WlSeat,
+    device: Option<DataDevice>,
+    control_device: Option<ZwlrDataControlDeviceV1>,
+    /// The serial of the latest input event, while we have keyboard focus
+    serial: Option<u32>,
+    /// The current selection, as reported by `wlr-data-control`
+    control_offer: Option<ZwlrDataControlOfferV1>,
+}
+
+/// The seat of a `wlr-data-control` device
+pub(super) struct ControlDeviceData(WlSeat);
+
+/// The mime types of a `wlr-data-control` offer, which are sent before it becomes the selection
+#[derive(Default)]
+pub(super) struct ControlOfferData {
+    mime_types: Mutex<Vec<String>>,
+}
+
+/// A selection which we can read from
+enum Offer<'a> {
+    Control(&'a ZwlrDataControlOfferV1),
+    Device(SelectionOffer),
+}
+
+impl ClipboardState {
+    pub(super) fn new(
+        connection: Connection,
+        queue: QueueHandle<WaylandState>,
+        manager: Option<DataDeviceManagerState>,
+        data_control: Option<ZwlrDataControlManagerV1>,
+    ) -> Self {
+        Self {
+            connection,
+            queue,
+            manager,
+            data_control,
+            seats: Vec::new(),
+            last_seat: None,
+            source: None,
+            contents: Vec::new(),
+            pending: false,
+        }
+    }
+
+    pub(super) fn add_seat(&mut self, seat: &WlSeat) {
+        let device = self
+            .manager
+            .as_ref()
+            .map(|manager| manager.get_data_device(&self.queue, seat));
+        let control_device = self.data_control.as_ref().map(|manager| {
+            manager.get_data_device(seat, &self.queue, ControlDeviceData(seat.clone()))
+        });
+        self.seats.push(SeatClipboard {
+            seat: seat.clone(),
+            device,
+            control_device,
+            serial: None,
+            control_offer: None,
+        });
+    }
+
+    pub(super) fn remove_seat(&mut self, seat: &WlSeat) {
+        self.seats.retain(|it| &it.seat != seat);
+        if self.last_seat.as_ref() == Some(seat) {
+            self.last_seat = None;
+        }
+    }
+
+    fn seat_mut(&mut self, seat: &WlSeat) -> Option<&mut SeatClipboard> {
+        self.seats.iter_mut().find(|it| &it.seat == seat)
+    }
+
+    /// Records the serial of an input event, which lets us set the selection
+    pub(super) fn input_serial(&mut self, seat: &WlSeat, serial: u32) {
+        let Some(info) = self.seat_mut(seat) else {
+            return;
+        };
+        info.serial = Some(serial);
+        self.last_seat = Some(seat.clone());
+        if self.pending && self.set_selection() {
+            tracing::debug!("applied the queued clipboard write");
+            self.pending = false;
+        }
+    }
+
+    /// The seat which most recently received input, and the serial of that input.
+    ///
+    /// This is also what popups grab with.
+    pub(super) fn latest_serial(&self) -> Option<(WlSeat, u32)> {
+        let seat = self.last_seat.as_ref()?;
+        let info = self.seats.iter().find(|it| &it.seat == seat)?;
+        Some((seat.clone(), info.serial?))
+    }
+
+    /// The compositor won't accept our serials once we lose keyboard focus
+    pub(super) fn keyboard_left(&mut self, seat: &WlSeat) {
+        if let Some(info) = self.seat_mut(seat) {
+            info.serial = None;
+        }
+    }
+
+    fn put_formats(&mut self, formats: &[ClipboardFormat]) {
+        if self.manager.is_none() {
+            tracing::warn!("the compositor doesn't support wl_data_device_manager");
+            return;
+        }
+        self.contents = formats.to_vec();
+        self.pending = !self.set_selection();
+        if self.pending {
+            tracing::debug!("no input serial to set the clipboard with, waiting for input");
+            self.source = None;
+        }
+    }
+
+    /// Offers `contents` as the selection, returning false if we have no serial to do so with
+    fn set_selection(&mut self) -> bool {
+        let Some(manager) = self.manager.as_ref() else {
+            return false;
+        };
+        let Some(seat) = self
+            .last_seat
+            .as_ref()
+            .and_then(|seat| self.seats.iter().find(|it| &it.seat == seat))
+        else {
+            return false;
+        };
+        let (Some(device), Some(serial)) = (seat.device.as_ref(), seat.serial) else {
+            return false;
+        };
+        let mime_types = self
+            .contents
+            .iter()
+            .map(|it| it.identifier)
+            .collect::<Vec<_>>();
+        let source = manager.create_copy_paste_source(&self.queue, mime_types);
+        source.set_selection(device, serial);
+        // This drops the previous source, if any
+        self.source = Some(source);
+        true
+    }
+
+    /// Whether reads should be answered from `contents`, as reading our own selection through
+    /// the compositor would deadlock
+    fn owns_selection(&self) -> bool {
+        self.source.is_some() || self.pending
+    }
+
+    fn offer(&self) -> Option<Offer<'_>> {
+        let seats = || {
+            // Prefer the seat the user is interacting with
+            let last = self
+                .last_seat
+                .as_ref()
+                .and_then(|seat| self.seats.iter().find(|it| &it.seat == seat));
+            last.into_iter().chain(self.seats.iter())
+        };
+        if let Some(offer) = seats().find_map(|it| it.control_offer.as_ref()) {
+            return Some(Offer::Control(offer));
+        }
+        seats()
+            .find_map(|it| it.device.as_ref()?.data().selection_offer())
+            .map(Offer::Device)
+    }
+
+    fn get_format(&self, format: FormatId) -> Option<Vec<u8>> {
+        if self.owns_selection() {
+            return self
+                .contents
+                .iter()
+                .find(|it| it.identifier == format)
+                .map(|it| it.data.clone());
+        }
+        let offer = self.offer()?;
+        if !offer.mime_types().iter().any(|it| it == format) {
+            return None;
+        }
+        offer.receive(format, &self.connection)
+    }
+
+    fn available_type_names(&self) -> Vec<String> {
+        if self.owns_selection() {
+            return self
+                .contents
+                .iter()
+                .map(|it| it.identifier.to_string())
+                .collect();
+        }
+        self.offer()
+            .map(|offer| offer.mime_types())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn control_selection(
+        &mut self,
+        seat: &WlSeat,
+        offer: Option<ZwlrDataControlOfferV1>,
+    ) {
+        let Some(info) = self.seat_mut(seat) else {
+            return;
+        };
+        if let Some(old) = std::mem::replace(&mut info.control_offer, offer) {
+            old.destroy();
+        }
+    }
+
+    pub(super) fn control_finished(&mut self, seat: &WlSeat) {
+        let Some(info) = self.seat_mut(seat) else {
+            return;
+        };
+        if let Some(offer) = info.control_offer.take() {
+            offer.destroy();
+        }
+        if let Some(device) = info.control_device.take() {
+            device.destroy();
+        }
+    }
+}
+
+impl Offer<'_> {
+    fn mime_types(&self) -> Vec<String> {
+        match self {
+            Offer::Control(offer) => offer
+                .data::<ControlOfferData>()
+                .map(|data| data.mime_types.lock().unwrap().clone())
+                .unwrap_or_default(),
+            Offer::Device(offer) => offer.with_mime_types(|mime_types| mime_types.to_vec()),
+        }
+    }
+
+    fn receive(&self, mime_type: &str, connection: &Connection) -> Option<Vec<u8>> {
+        let pipe = match self {
+            Offer::Control(offer) => {
+                let (read, write) = match pipe2(OFlag::O_CLOEXEC) {
+                    Ok(fds) => fds,
+                    Err(e) => {
+                        tracing::warn!("failed to create a pipe to read the clipboard: {e}");
+                        return None;
+                    }
+                };
+                // Safety: `pipe2` gave us ownership of both ends
+                let (read, write) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+                offer.receive(mime_type.into(), write.as_raw_fd());
+                read
+            }
+            Offer::Device(offer) => match offer.receive(mime_type.into()) {
+                // Safety: we own the read end of the pipe
+                Ok(pipe) => unsafe { File::from_raw_fd(pipe.into_raw_fd()) },
+                Err(e) => {
+                    tracing::warn!("failed to read the clipboard: {e}");
+                    return None;
+                }
+            },
+        };
+        // The owner only starts writing once our request reaches the compositor
+        if let Err(e) = connection.flush() {
+            tracing::warn!("failed to request the clipboard: {e}");
+            return None;
+        }
+        read_pipe(pipe)
+    }
+}
+
+/// Reads until the writer closes the pipe, or `READ_TIMEOUT` passes
+fn read_pipe(mut pipe: File) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + READ_TIMEOUT;
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut fds = [PollFd::new(pipe.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, remaining.as_millis() as i32) {
+            Ok(0) => {
+                tracing::warn!("timed out reading the clipboard");
+                return None;
+            }
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(e) => {
+                tracing::warn!("failed to read the clipboard: {e}");
+                return None;
+            }
+        }
+        match pipe.read(&mut buf) {
+            Ok(0) => return Some(data),
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => {
+                tracing::warn!("failed to read the clipboard: {e}");
+                return None;
+            }
+        }
+    }
+}
+
+impl DataSourceHandler for WaylandState {
+    fn accept_mime(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &WlDataSource,
+        _: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        source: &WlDataSource,
+        mime: String,
+        mut fd: WritePipe,
+    ) {
+        let clipboard = self.clipboard.borrow();
+        if clipboard.source.as_ref().map(|it| it.inner()) != Some(source) {
+            return;
+        }
+        let Some(format) = clipboard.contents.iter().find(|it| it.identifier == mime) else {
+            return;
+        };
+        if let Err(e) = fd.write_all(&format.data) {
+            tracing::warn!("failed to send the clipboard contents: {e}");
+        }
+    }
+
+    fn cancelled(&mut self, _: &Connection, _: &QueueHandle<Self>, source: &WlDataSource) {
+        // Another client has taken the selection
+        let mut clipboard = self.clipboard.borrow_mut();
+        if clipboard.source.as_ref().map(|it| it.inner()) == Some(source) {
+            clipboard.source = None;
+            clipboard.contents.clear();
+        }
+    }
+
+    fn dnd_dropped(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource) {}
+
+    fn dnd_finished(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource) {}
+
+    fn action(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataSource, _: DndAction) {}
+}
+
+// The selection is read when the application asks for it, and we don't support drag and drop
+impl DataDeviceHandler for WaylandState {
+    fn enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+
+    fn leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+
+    fn motion(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+
+    fn selection(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+
+    fn drop_performed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlDataDevice) {}
+}
+
+impl DataOfferHandler for WaylandState {
+    fn source_actions(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+
+    fn selected_action(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &mut DragOffer,
+        _: DndAction,
+    ) {
+    }
+}
+
+delegate_data_device_manager!(WaylandState);
+delegate_data_device!(WaylandState);
+delegate_data_source!(WaylandState);
+delegate_data_offer!(WaylandState);
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlManagerV1,
+        _: <ZwlrDataControlManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // The manager has no events
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ControlDeviceData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        data: &ControlDeviceData,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            // The offer's mime types are collected in its `ControlOfferData`
+            zwlr_data_control_device_v1::Event::DataOffer { .. } => (),
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                state.clipboard.borrow_mut().control_selection(&data.0, id)
+            }
+            zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                if let Some(offer) = id {
+                    offer.destroy();
+                }
+            }
+            zwlr_data_control_device_v1::Event::Finished => {
+                state.clipboard.borrow_mut().control_finished(&data.0)
+            }
+            _ => tracing::error!(?event, "unexpected zwlr_data_control_device_v1 event"),
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ControlOfferData::default())
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ControlOfferData> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        data: &ControlOfferData,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_offer_v1::Event::Offer { mime_type } => {
+                data.mime_types.lock().unwrap().push(mime_type)
+            }
+            _ => tracing::error!(?event, "unexpected zwlr_data_control_offer_v1 event"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::application::Application;
+    use super::*;
+    use compositor::{Compositor, Event};
+
+    fn pipe() -> (File, File) {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC).unwrap();
+        // Safety: `pipe2` gave us ownership of both ends
+        unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) }
+    }
+
+    #[test]
+    fn read_pipe_until_closed() {
+        let (read, mut write) = pipe();
+        // More than fits in the pipe's buffer, so the reads and writes interleave
+        let data = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+        let expected = data.clone();
+        let writer = std::thread::spawn(move || write.write_all(&data).unwrap());
+        assert_eq!(read_pipe(read), Some(expected));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn read_pipe_times_out() {
+        // The write end stays open, as it would if the owner never answered
+        let (read, _write) = pipe();
+        assert_eq!(read_pipe(read), None);
+    }
+    /// Connect an `Application` to a new compositor, once its clipboard has its data devices
+    fn connect(data_control: bool) -> (Compositor, Application, Clipboard) {
+        let (compositor, socket) = Compositor::start(data_control);
+        let app = Application::with_connection(Connection::from_socket(socket).unwrap()).unwrap();
+        let clipboard = app.clipboard();
+        let mut devices = vec![];
+        let expected = if data_control {
+            vec![Event::ControlDevice, Event::DataDevice]
+        } else {
+            vec![Event::DataDevice]
+        };
+        while devices.len() < expected.len() {
+            devices.push(dispatch_until(&app, &clipboard, || {
+                compositor.events.try_recv().ok()
+            }));
+        }
+        devices.sort_by_key(|it| format!("{it:?}"));
+        assert_eq!(devices, expected);
+        (compositor, app, clipboard)
+    }
+
+    /// Dispatch the application's events until `done` returns something
+    fn dispatch_until<T>(
+        app: &Application,
+        clipboard: &Clipboard,
+        mut done: impl FnMut() -> Option<T>,
+    ) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(it) = done() {
+                return it;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for the compositor"
+            );
+            clipboard.0.borrow().connection.flush().unwrap();
+            app.dispatch_events(Duration::from_millis(10));
+        }
+    }
+
+    fn reads_offered_selection(data_control: bool) {
+        let (compositor, app, mut clipboard) = connect(data_control);
+        compositor.run(|state, handle| {
+            state.offer_selection(handle, "text/plain;charset=utf-8", b"from another client")
+        });
+        dispatch_until(&app, &clipboard, || {
+            (!clipboard.available_type_names().is_empty()).then_some(())
+        });
+        assert_eq!(
+            clipboard.available_type_names(),
+            vec!["text/plain;charset=utf-8"]
+        );
+        assert_eq!(
+            clipboard.get_string().as_deref(),
+            Some("from another client")
+        );
+        assert_eq!(clipboard.get_format("image/png"), None);
+
+        // A selection we make replaces the offer, and is answered without the compositor
+        clipboard.put_string("ours");
+        assert_eq!(clipboard.get_string().as_deref(), Some("ours"));
+    }
+
+    #[test]
+    fn reads_the_data_control_selection() {
+        reads_offered_selection(true);
+    }
+
+    #[test]
+    fn reads_the_data_device_selection() {
+        reads_offered_selection(false);
+    }
+
+    #[test]
+    fn sets_the_selection_once_there_is_a_serial() {
+        let (compositor, app, mut clipboard) = connect(false);
+        clipboard.put_string("ours");
+        // Without an input serial, the write waits, but we can still read it back
+        assert!(clipboard.0.borrow().pending);
+        assert_eq!(clipboard.get_string().as_deref(), Some("ours"));
+
+        let seat = clipboard.0.borrow().seats[0].seat.clone();
+        clipboard.0.borrow_mut().input_serial(&seat, 7);
+        let selection = dispatch_until(&app, &clipboard, || compositor.events.try_recv().ok());
+        assert_eq!(
+            selection,
+            Event::Selection {
+                serial: 7,
+                mime_types: STRING_TARGETS.iter().map(|it| it.to_string()).collect(),
+                contents: b"ours".to_vec(),
+            }
+        );
+
+        // Another client taking the selection clears ours
+        compositor.run(|state, _| state.cancel_selection());
+        dispatch_until(&app, &clipboard, || {
+            clipboard.available_type_names().is_empty().then_some(())
+        });
+        assert_eq!(clipboard.get_string(), None);
+    }
+
+    /// A compositor with the globals `Application::new` needs, and a clipboard. It runs on a
+    /// thread of its own, as reads from the clipboard block until the data arrives.
+    mod compositor {
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+        use std::os::unix::net::UnixStream;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{mpsc, Arc, Mutex};
+        use std::thread::JoinHandle;
+        use std::time::Duration;
+
+        use nix::{fcntl::OFlag, unistd::pipe2};
+        use wayland_protocols::xdg::shell::server::xdg_wm_base::XdgWmBase;
+        use wayland_protocols_wlr::data_control::v1::server::{
+            zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+            zwlr_data_control_manager_v1::{self, ZwlrDataControlManagerV1},
+            zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+            zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+        };
+        use wayland_server::backend::{ClientData, ClientId, DisconnectReason};
+        use wayland_server::protocol::{
+            wl_compositor::WlCompositor,
+            wl_data_device::{self, WlDataDevice},
+            wl_data_device_manager::{self, WlDataDeviceManager},
+            wl_data_offer::{self, WlDataOffer},
+            wl_data_source::{self, WlDataSource},
+            wl_seat::{self, WlSeat},
+            wl_shm::{self, WlShm},
+        };
+        use wayland_server::{
+            Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, New, Resource,
+        };
+
+        /// What the client did, as the compositor saw it
+        #[derive(Debug, PartialEq, Eq)]
+        pub(super) enum Event {
+            DataDevice,
+            ControlDevice,
+            /// The client set the selection, offering `mime_types`, and sent `contents` when the
+            /// first of them was read
+            Selection {
+                serial: u32,
+                mime_types: Vec<String>,
+                contents: Vec<u8>,
+            },
+        }
+
+        type Command = Box<dyn FnOnce(&mut State, &DisplayHandle) + Send>;
+
+        pub(super) struct Compositor {
+            commands: mpsc::Sender<Command>,
+            pub(super) events: mpsc::Receiver<Event>,
+            stop: Arc<AtomicBool>,
+            thread: Option<JoinHandle<()>>,
+        }
+
+        impl Compositor {
+            /// Start the compositor, returning the socket of its only client
+            pub(super) fn start(data_control: bool) -> (Self, UnixStream) {
+                let (server, client) = UnixStream::pair().unwrap();
+                let (commands, command_receiver) = mpsc::channel::<Command>();
+                let (events, event_receiver) = mpsc::channel();
+                let stop = Arc::new(AtomicBool::new(false));
+                let stopped = stop.clone();
+                let thread = std::thread::spawn(move || {
+                    let mut display = Display::<State>::new().unwrap();
+                    let mut handle = display.handle();
+                    handle.create_global::<State, WlCompositor, ()>(5, ());
+                    handle.create_global::<State, XdgWmBase, ()>(3, ());
+                    handle.create_global::<State, WlShm, ()>(1, ());
+                    handle.create_global::<State, WlSeat, ()>(5, ());
+                    handle.create_global::<State, WlDataDeviceManager, ()>(3, ());
+                    if data_control {
+                        handle.create_global::<State, ZwlrDataControlManagerV1, ()>(2, ());
+                    }
+                    handle.insert_client(server, Arc::new(ClientState)).unwrap();
+                    let mut state = State {
+                        events,
+                        data_devices: vec![],
+                        control_devices: vec![],
+                        selection: None,
+                        sent_fds: vec![],
+                    };
+                    while !stopped.load(Ordering::Acquire) {
+                        if display.dispatch_clients(&mut state).is_err() {
+                            // The client has gone
+                            return;
+                        }
+                        for command in command_receiver.try_iter() {
+                            command(&mut state, &handle);
+                        }
+                        let _ = display.flush_clients();
+                        // The fds we sent have been passed on now
+                        state.sent_fds.clear();
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                });
+                let compositor = Compositor {
+                    commands,
+                    events: event_receiver,
+                    stop,
+                    thread: Some(thread),
+                };
+                (compositor, client)
+            }
+
+            /// Run `command` on the compositor's thread
+            pub(super) fn run(
+                &self,
+                command: impl FnOnce(&mut State, &DisplayHandle) + Send + 'static,
+            ) {
+                self.commands.send(Box::new(command)).unwrap();
+            }
+        }
+
+        impl Drop for Compositor {
+            fn drop(&mut self) {
+                self.stop.store(true, Ordering::Release);
+                if let Some(thread) = self.thread.take() {
+                    thread.join().unwrap();
+                }
+            }
+        }
+
+        pub(super) struct State {
+            events: mpsc::Sender<Event>,
+            data_devices: Vec<WlDataDevice>,
+            control_devices: Vec<ZwlrDataControlDeviceV1>,
+            /// The source the client last set as the selection
+            selection: Option<WlDataSource>,
+            /// Kept open until the events they were sent with have been flushed
+            sent_fds: Vec<File>,
+        }
+
+        impl State {
+            /// Offer `contents` as the selection, as another client would
+            pub(super) fn offer_selection(
+                &mut self,
+                handle: &DisplayHandle,
+                mime_type: &str,
+                contents: &[u8],
+            ) {
+                let contents = Arc::new(contents.to_vec());
+                for device in &self.control_devices {
+                    let client = handle.get_client(device.id()).unwrap();
+                    let offer = client
+                        .create_resource::<ZwlrDataControlOfferV1, _, State>(
+                            handle,
+                            device.version(),
+                            contents.clone(),
+                        )
+                        .unwrap();
+                    device.data_offer(&offer);
+                    offer.offer(mime_type.into());
+                    device.selection(Some(&offer));
+                }
+                for device in &self.data_devices {
+                    let client = handle.get_client(device.id()).unwrap();
+                    let offer = client
+                        .create_resource::<WlDataOffer, _, State>(
+                            handle,
+                            device.version(),
+                            contents.clone(),
+                        )
+                        .unwrap();
+                    device.data_offer(&offer);
+                    offer.offer(mime_type.into());
+                    device.selection(Some(&offer));
+                }
+            }
+
+            /// Tell the client another client has taken the selection
+            pub(super) fn cancel_selection(&mut self) {
+                if let Some(source) = self.selection.take() {
+                    source.cancelled();
+                }
+            }
+
+            /// Read the first mime type of the client's new selection
+            fn selection_set(&mut self, source: WlDataSource, serial: u32) {
+                let mime_types = source
+                    .data::<Mutex<Vec<String>>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .clone();
+                let (read, write) = pipe2(OFlag::O_CLOEXEC).unwrap();
+                // Safety: `pipe2` gave us ownership of both ends
+                let (mut read, write) =
+                    unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+                source.send(mime_types[0].clone(), write.as_raw_fd());
+                self.sent_fds.push(write);
+                self.selection = Some(source);
+                let events = self.events.clone();
+                std::thread::spawn(move || {
+                    let mut contents = vec![];
+                    read.read_to_end(&mut contents).unwrap();
+                    let _ = events.send(Event::Selection {
+                        serial,
+                        mime_types,
+                        contents,
+                    });
+                });
+            }
+        }
+
+        /// Write `contents` to a pipe the client gave us, which closes it
+        fn send_contents(fd: RawFd, contents: &[u8]) {
+            // Safety: the fd was sent to us, so we own it
+            let mut pipe = unsafe { File::from_raw_fd(fd) };
+            pipe.write_all(contents).unwrap();
+        }
+
+        struct ClientState;
+
+        impl ClientData for ClientState {
+            fn initialized(&self, _: ClientId) {}
+
+            fn disconnected(&self, _: ClientId, _: DisconnectReason) {}
+        }
+
+        /// Globals which only need to exist
+        macro_rules! bare_global {
+            ($($interface:ty),*) => {$(
+                impl GlobalDispatch<$interface, ()> for State {
+                    fn bind(
+                        _: &mut Self,
+                        _: &DisplayHandle,
+                        _: &Client,
+                        resource: New<$interface>,
+                        _: &(),
+                        data_init: &mut DataInit<'_, Self>,
+                    ) {
+                        data_init.init(resource, ());
+                    }
+                }
+
+                impl Dispatch<$interface, ()> for State {
+                    fn request(
+                        _: &mut Self,
+                        _: &Client,
+                        _: &$interface,
+                        _: <$interface as Resource>::Request,
+                        _: &(),
+                        _: &DisplayHandle,
+                        _: &mut DataInit<'_, Self>,
+                    ) {
+                    }
+                }
+            )*};
+        }
+
+        bare_global!(WlCompositor, XdgWmBase);
+
+        impl GlobalDispatch<WlShm, ()> for State {
+            fn bind(
+                _: &mut Self,
+                _: &DisplayHandle,
+                _: &Client,
+                resource: New<WlShm>,
+                _: &(),
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                let shm = data_init.init(resource, ());
+                shm.format(wl_shm::Format::Argb8888);
+                shm.format(wl_shm::Format::Xrgb8888);
+            }
+        }
+
+        impl Dispatch<WlShm, ()> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &WlShm,
+                _: wl_shm::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+            }
+        }
+
+        impl GlobalDispatch<WlSeat, ()> for State {
+            fn bind(
+                _: &mut Self,
+                _: &DisplayHandle,
+                _: &Client,
+                resource: New<WlSeat>,
+                _: &(),
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                // No keyboard or pointer, so the tests give the clipboard its serials
+                let seat = data_init.init(resource, ());
+                seat.capabilities(wl_seat::Capability::empty());
+                if seat.version() >= 2 {
+                    seat.name("seat0".into());
+                }
+            }
+        }
+
+        impl Dispatch<WlSeat, ()> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &WlSeat,
+                _: wl_seat::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+            }
+        }
+
+        impl GlobalDispatch<WlDataDeviceManager, ()> for State {
+            fn bind(
+                _: &mut Self,
+                _: &DisplayHandle,
+                _: &Client,
+                resource: New<WlDataDeviceManager>,
+                _: &(),
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                data_init.init(resource, ());
+            }
+        }
+
+        impl Dispatch<WlDataDeviceManager, ()> for State {
+            fn request(
+                state: &mut Self,
+                _: &Client,
+                _: &WlDataDeviceManager,
+                request: wl_data_device_manager::Request,
+                _: &(),
+                _: &DisplayHandle,
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                match request {
+                    wl_data_device_manager::Request::CreateDataSource { id } => {
+                        data_init.init(id, Mutex::new(Vec::<String>::new()));
+                    }
+                    wl_data_device_manager::Request::GetDataDevice { id, .. } => {
+                        state.data_devices.push(data_init.init(id, ()));
+                        state.events.send(Event::DataDevice).unwrap();
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        impl Dispatch<WlDataDevice, ()> for State {
+            fn request(
+                state: &mut Self,
+                _: &Client,
+                _: &WlDataDevice,
+                request: wl_data_device::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+                if let wl_data_device::Request::SetSelection {
+                    source: Some(source),
+                    serial,
+                } = request
+                {
+                    state.selection_set(source, serial);
+                }
+            }
+        }
+
+        impl Dispatch<WlDataSource, Mutex<Vec<String>>> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &WlDataSource,
+                request: wl_data_source::Request,
+                mime_types: &Mutex<Vec<String>>,
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+                if let wl_data_source::Request::Offer { mime_type } = request {
+                    mime_types.lock().unwrap().push(mime_type);
+                }
+            }
+        }
+
+        impl Dispatch<WlDataOffer, Arc<Vec<u8>>> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &WlDataOffer,
+                request: wl_data_offer::Request,
+                contents: &Arc<Vec<u8>>,
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+                if let wl_data_offer::Request::Receive { fd, .. } = request {
+                    send_contents(fd, contents);
+                }
+            }
+        }
+
+        impl GlobalDispatch<ZwlrDataControlManagerV1, ()> for State {
+            fn bind(
+                _: &mut Self,
+                _: &DisplayHandle,
+                _: &Client,
+                resource: New<ZwlrDataControlManagerV1>,
+                _: &(),
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                data_init.init(resource, ());
+            }
+        }
+
+        impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+            fn request(
+                state: &mut Self,
+                _: &Client,
+                _: &ZwlrDataControlManagerV1,
+                request: zwlr_data_control_manager_v1::Request,
+                _: &(),
+                _: &DisplayHandle,
+                data_init: &mut DataInit<'_, Self>,
+            ) {
+                match request {
+                    zwlr_data_control_manager_v1::Request::CreateDataSource { id } => {
+                        data_init.init(id, ());
+                    }
+                    zwlr_data_control_manager_v1::Request::GetDataDevice { id, .. } => {
+                        state.control_devices.push(data_init.init(id, ()));
+                        state.events.send(Event::ControlDevice).unwrap();
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &ZwlrDataControlDeviceV1,
+                _: zwlr_data_control_device_v1::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<ZwlrDataControlSourceV1, ()> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &ZwlrDataControlSourceV1,
+                _: zwlr_data_control_source_v1::Request,
+                _: &(),
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<ZwlrDataControlOfferV1, Arc<Vec<u8>>> for State {
+            fn request(
+                _: &mut Self,
+                _: &Client,
+                _: &ZwlrDataControlOfferV1,
+                request: zwlr_data_control_offer_v1::Request,
+                contents: &Arc<Vec<u8>>,
+                _: &DisplayHandle,
+                _: &mut DataInit<'_, Self>,
+            ) {
+                if let zwlr_data_control_offer_v1::Request::Receive { fd, .. } = request {
+                    send_contents(fd, contents);
+                }
+            }
+        }
     }
 }
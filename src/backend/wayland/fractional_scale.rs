@@ -0,0 +1,119 @@
+//! Rendering at fractional scales with the fractional-scale and viewporter protocols.
+//!
+//! Without these, `wl_surface` only tells us integer scales, so a 150% output gets buffers
+//! drawn at 200% or 100%. With them, the compositor tells us the exact scale it prefers for
+//! each surface, and we draw buffers of that many pixels with a buffer scale of 1. The
+//! viewport then maps the buffer onto the surface's size in display points.
+
+use smithay_client_toolkit::reexports::{
+    client::{
+        delegate_noop, globals::GlobalList, protocol::wl_surface::WlSurface, Connection, Dispatch,
+        QueueHandle,
+    },
+    protocols::wp::{
+        fractional_scale::v1::client::{
+            wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+            wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+        },
+        viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+    },
+};
+
+use super::{window::WindowId, WaylandState};
+use crate::{common_util, kurbo::Size};
+
+/// The preferred scale is sent as a fraction with this denominator
+const SCALE_DENOMINATOR: f64 = 120.;
+
+/// The globals needed for fractional scaling, if the compositor supports both.
+#[derive(Clone)]
+pub(super) struct FractionalScaleManager {
+    manager: WpFractionalScaleManagerV1,
+    viewporter: WpViewporter,
+}
+
+impl FractionalScaleManager {
+    pub fn bind(globals: &GlobalList, qh: &QueueHandle<WaylandState>) -> Option<Self> {
+        let manager = globals.bind(qh, 1..=1, ()).ok()?;
+        let viewporter = match globals.bind(qh, 1..=1, ()) {
+            Ok(viewporter) => viewporter,
+            Err(e) => {
+                tracing::info!("fractional scaling is unavailable without wp_viewporter: {e}");
+                manager.destroy();
+                return None;
+            }
+        };
+        Some(FractionalScaleManager {
+            manager,
+            viewporter,
+        })
+    }
+
+    /// Start following the preferred scale of the surface of the window `window_id`.
+    pub fn for_surface(
+        &self,
+        surface: &WlSurface,
+        qh: &QueueHandle<WaylandState>,
+        window_id: WindowId,
+    ) -> SurfaceScale {
+        SurfaceScale {
+            fractional_scale: self.manager.get_fractional_scale(surface, qh, window_id),
+            viewport: self.viewporter.get_viewport(surface, qh, ()),
+        }
+    }
+}
+
+/// The per-surface objects for fractional scaling, which are destroyed with the window.
+pub(super) struct SurfaceScale {
+    fractional_scale: WpFractionalScaleV1,
+    viewport: WpViewport,
+}
+
+impl SurfaceScale {
+    /// Show the buffers attached from now on at `size`, in display points.
+    ///
+    /// Like the rest of the surface's state, this takes effect on the next commit.
+    pub fn set_size(&self, size: Size) {
+        let size = size.round();
+        if size.width < 1. || size.height < 1. {
+            // The protocol doesn't allow empty destinations
+            return;
+        }
+        self.viewport
+            .set_destination(size.width as i32, size.height as i32);
+    }
+}
+
+impl Drop for SurfaceScale {
+    fn drop(&mut self) {
+        self.viewport.destroy();
+        self.fractional_scale.destroy();
+    }
+}
+
+delegate_noop!(WaylandState: WpFractionalScaleManagerV1);
+delegate_noop!(WaylandState: WpViewporter);
+delegate_noop!(WaylandState: WpViewport);
+
+impl Dispatch<WpFractionalScaleV1, WindowId> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        window_id: &WindowId,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let _span = common_util::event_span("wp_fractional_scale_v1.preferred_scale");
+        match event {
+            // The compositor sends this again whenever the window moves to an output with a
+            // different scale
+            wp_fractional_scale_v1::Event::PreferredScale { scale } => {
+                if let Some(window) = state.windows.get_mut(window_id) {
+                    window.set_scale(f64::from(scale) / SCALE_DENOMINATOR);
+                }
+            }
+            _ => tracing::error!(?event, "unexpected wp_fractional_scale_v1 event"),
+        }
+    }
+}
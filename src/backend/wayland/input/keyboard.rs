@@ -5,7 +5,7 @@ use crate::{
         shared::xkb::{ActiveModifiers, Keymap, State},
         wayland::window::WindowId,
     },
-    KeyEvent,
+    common_util, KeyEvent, Modifiers,
 };
 
 use super::{SeatName, WaylandState};
@@ -57,6 +57,13 @@ impl KeyboardState {
             cached_keys: vec![],
         }
     }
+
+    /// The modifiers which are held, which pointer events on the same seat carry
+    pub(super) fn modifiers(&self) -> Modifiers {
+        self.xkb_state
+            .as_ref()
+            .map_or_else(Modifiers::empty, |(state, _)| state.modifiers())
+    }
 }
 
 impl WaylandState {
@@ -87,6 +94,7 @@ impl Dispatch<wl_keyboard::WlKeyboard, KeyboardUserData> for WaylandState {
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
+        let _span = common_util::event_span("wl_keyboard");
         match event {
             wl_keyboard::Event::Keymap { format, fd, size } => match format {
                 WEnum::Value(KeymapFormat::XkbV1) => {
@@ -144,15 +152,27 @@ impl Dispatch<wl_keyboard::WlKeyboard, KeyboardUserData> for WaylandState {
                 }
             },
             wl_keyboard::Event::Enter {
-                serial: _,
+                serial,
                 surface,
                 keys: _,
             } => {
                 // TODO: Handle `keys`
+                let seat = state.input_state(data.0).seat.clone();
+                state.clipboard.borrow_mut().input_serial(&seat, serial);
                 let keyboard = state.keyboard(data);
-                keyboard.focused_window = Some(WindowId::of_surface(&surface));
+                let window = WindowId::of_surface(&surface);
+                keyboard.focused_window = Some(window.clone());
+                #[cfg(feature = "accesskit")]
+                {
+                    let enabled = state.accessibility.enabled();
+                    if let Some(window) = state.windows.get_mut(&window) {
+                        window.accesskit_focus_changed(true, enabled);
+                    }
+                }
             }
             wl_keyboard::Event::Leave { surface, .. } => {
+                let seat = state.input_state(data.0).seat.clone();
+                state.clipboard.borrow_mut().keyboard_left(&seat);
                 let keyboard = state.keyboard(data);
                 debug_assert_eq!(
                     keyboard.focused_window.as_ref().unwrap(),
@@ -160,6 +180,13 @@ impl Dispatch<wl_keyboard::WlKeyboard, KeyboardUserData> for WaylandState {
                 );
                 keyboard.focused_window = None;
                 keyboard.cached_keys.clear();
+                #[cfg(feature = "accesskit")]
+                {
+                    let enabled = state.accessibility.enabled();
+                    if let Some(window) = state.windows.get_mut(&WindowId::of_surface(&surface)) {
+                        window.accesskit_focus_changed(false, enabled);
+                    }
+                }
             }
             wl_keyboard::Event::Modifiers {
                 serial: _,
@@ -184,7 +211,7 @@ impl Dispatch<wl_keyboard::WlKeyboard, KeyboardUserData> for WaylandState {
                 })
             }
             wl_keyboard::Event::Key {
-                serial: _,
+                serial,
                 time: _, // TODO: Report the time of the event to the keyboard
                 key,
                 state: key_state,
@@ -212,6 +239,11 @@ impl Dispatch<wl_keyboard::WlKeyboard, KeyboardUserData> for WaylandState {
                     window_id = keyboard.focused_window.as_ref().unwrap().clone();
                     repeats = xkb_keymap.repeats(scancode);
                 }
+                // Before the handler sees the key, so that it can copy in response
+                if event.state == KeyState::Down {
+                    let seat = state.input_state(data.0).seat.clone();
+                    state.clipboard.borrow_mut().input_serial(&seat, serial);
+                }
                 let window = state.windows.get_mut(&window_id).unwrap();
                 window.handle_key_event(event.clone());
                 // Handle repeating
@@ -1,15 +1,21 @@
-use crate::Counter;
+use crate::common_util::ClickCounter;
+use crate::kurbo::Point;
+use crate::{Counter, PointerButtons};
 
 use self::{keyboard::KeyboardState, text_input::InputState};
 
-use super::WaylandState;
+use super::{window::WindowId, WaylandState};
 use smithay_client_toolkit::{
     delegate_seat,
     reexports::client::{protocol::wl_seat, Connection, QueueHandle},
-    seat::SeatHandler,
+    seat::{
+        pointer::{ThemeSpec, ThemedPointer},
+        SeatHandler,
+    },
 };
 
 mod keyboard;
+mod pointer;
 mod text_input;
 
 pub(super) use text_input::TextFieldChange;
@@ -32,6 +38,18 @@ pub(super) struct SeatInfo {
     seat: wl_seat::WlSeat,
     keyboard_state: Option<KeyboardState>,
     input_state: Option<InputState>,
+    pointer: Option<ThemedPointer>,
+    /// The window whose fallback decorations the pointer is over
+    frame_focus: Option<WindowId>,
+    /// The window whose content the pointer is over, which gets its events
+    pointer_focus: Option<WindowId>,
+    /// Where the pointer last was on `pointer_focus`, as presses and releases don't say
+    pointer_pos: Point,
+    /// The buttons which are held
+    buttons: PointerButtons,
+    click_counter: ClickCounter,
+    /// The name of the cursor we last showed, so that it's only set when it changes
+    cursor: Option<String>,
 }
 
 /// Identifier for a seat
@@ -72,7 +90,15 @@ impl WaylandState {
             seat,
             keyboard_state: None,
             input_state: None,
+            pointer: None,
+            frame_focus: None,
+            pointer_focus: None,
+            pointer_pos: Point::ZERO,
+            buttons: PointerButtons::new(),
+            click_counter: ClickCounter::default(),
+            cursor: None,
         };
+        self.clipboard.borrow_mut().add_seat(&new_info.seat);
         let idx = self.input_states.len();
         self.input_states.push(new_info);
         let input = &mut self.input_states[idx];
@@ -112,7 +138,21 @@ impl SeatHandler for WaylandState {
                 let state = KeyboardState::new(qh, seat_info.id, seat);
                 seat_info.keyboard_state = Some(state);
             }
-            smithay_client_toolkit::seat::Capability::Pointer => {}
+            smithay_client_toolkit::seat::Capability::Pointer => {
+                // The pointer shows cursors from the user's theme on a surface of its own
+                let surface = self.compositor_state.create_surface(qh);
+                let pointer = self.seats.get_pointer_with_theme(
+                    qh,
+                    &seat,
+                    self.shm.wl_shm(),
+                    surface,
+                    ThemeSpec::default(),
+                );
+                match pointer {
+                    Ok(pointer) => self.info_of_seat(&seat).pointer = Some(pointer),
+                    Err(e) => tracing::warn!(?seat, "Failed to get pointer: {e}"),
+                }
+            }
             smithay_client_toolkit::seat::Capability::Touch => {}
             it => tracing::warn!(?seat, "Unknown seat capability {it}"),
         }
@@ -128,7 +168,13 @@ impl SeatHandler for WaylandState {
         let state = self.info_of_seat(&seat);
         match capability {
             smithay_client_toolkit::seat::Capability::Keyboard => state.keyboard_state = None,
-            smithay_client_toolkit::seat::Capability::Pointer => {}
+            smithay_client_toolkit::seat::Capability::Pointer => {
+                state.pointer = None;
+                state.frame_focus = None;
+                state.pointer_focus = None;
+                state.buttons = PointerButtons::new();
+                state.cursor = None;
+            }
             smithay_client_toolkit::seat::Capability::Touch => {}
             it => tracing::info!(?seat, "Removed unknown seat capability {it}"),
         }
@@ -136,7 +182,8 @@ impl SeatHandler for WaylandState {
 
     fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, seat: wl_seat::WlSeat) {
         // Keep every other seat
-        self.input_states.retain(|it| it.seat != seat)
+        self.input_states.retain(|it| it.seat != seat);
+        self.clipboard.borrow_mut().remove_seat(&seat);
     }
 }
 
@@ -0,0 +1,191 @@
+//! Pointer input, which is delivered to the handler of the window under the pointer, or drives
+//! the fallback decorations, their cursors and the clipboard serial.
+
+use smithay_client_toolkit::{
+    delegate_pointer,
+    reexports::client::{protocol::wl_pointer::WlPointer, Connection, Proxy, QueueHandle},
+    seat::pointer::{PointerEvent as WlPointerEvent, PointerEventKind, PointerHandler},
+    shell::xdg::frame::FrameClick,
+};
+
+use super::{SeatInfo, WaylandState};
+use crate::backend::wayland::window::{WaylandWindowState, WindowId};
+use crate::common_util;
+use crate::kurbo::{Point, Vec2};
+use crate::{MouseInfo, PointerButton, PointerEvent, PointerType};
+
+// From linux/input-event-codes.h
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+
+/// The cursor shown everywhere but on the decorations, as handlers can't yet set their own
+const DEFAULT_CURSOR: &str = "left_ptr";
+
+impl PointerHandler for WaylandState {
+    fn pointer_frame(
+        &mut self,
+        conn: &Connection,
+        _: &QueueHandle<Self>,
+        pointer: &WlPointer,
+        events: &[WlPointerEvent],
+    ) {
+        let _span = common_util::event_span("wl_pointer.frame");
+        let Some(seat) = self
+            .input_states
+            .iter_mut()
+            .find(|it| it.pointer.as_ref().map(|it| it.pointer()) == Some(pointer))
+        else {
+            return;
+        };
+        let mods = seat
+            .keyboard_state
+            .as_ref()
+            .map(|keyboard| keyboard.modifiers())
+            .unwrap_or_default();
+        for event in events {
+            // Events on the window's own surface go to its handler, and those on the
+            // subsurfaces of its decorations to them
+            let content = WindowId::of_surface(&event.surface);
+            let content = self.windows.contains_key(&content).then_some(content);
+            match event.kind {
+                PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
+                    let surface = event.surface.id();
+                    let (x, y) = event.position;
+                    seat.frame_focus = None;
+                    let mut cursor = None;
+                    for (id, window) in &mut self.windows {
+                        if let Some(name) = window.frame_pointer_moved(&surface, x, y) {
+                            seat.frame_focus = Some(id.clone());
+                            cursor = Some(name.to_owned());
+                        }
+                    }
+                    let cursor = cursor.unwrap_or_else(|| DEFAULT_CURSOR.to_owned());
+                    // Entering a surface leaves the cursor unset until we set it again
+                    let entered = matches!(event.kind, PointerEventKind::Enter { .. });
+                    if entered || seat.cursor.as_ref() != Some(&cursor) {
+                        if let Some(pointer) = &seat.pointer {
+                            if let Err(e) = pointer.set_cursor(conn, &cursor) {
+                                tracing::warn!("failed to show the {cursor} cursor: {e}");
+                            }
+                        }
+                        seat.cursor = Some(cursor);
+                    }
+                    if let Some(id) = content {
+                        seat.pointer_focus = Some(id.clone());
+                        seat.pointer_pos = Point::new(x, y);
+                        if let Some(window) = self.windows.get_mut(&id) {
+                            let event = pointer_event(seat, mods, PointerButton::None);
+                            window.handler.pointer_move(&event);
+                        }
+                    }
+                }
+                PointerEventKind::Leave { .. } => {
+                    if content.is_some() {
+                        let window = seat
+                            .pointer_focus
+                            .take()
+                            .and_then(|id| self.windows.get_mut(&id));
+                        if let Some(window) = window {
+                            window.handler.pointer_leave();
+                        }
+                        continue;
+                    }
+                    let window = seat
+                        .frame_focus
+                        .take()
+                        .and_then(|id| self.windows.get_mut(&id));
+                    if let Some(window) = window {
+                        window.frame_pointer_left();
+                    }
+                }
+                PointerEventKind::Press { button, serial, .. }
+                | PointerEventKind::Release { button, serial, .. } => {
+                    self.clipboard.borrow_mut().input_serial(&seat.seat, serial);
+                    let pressed = matches!(event.kind, PointerEventKind::Press { .. });
+                    let focus = seat.pointer_focus.clone();
+                    if let Some(window) = focus.and_then(|id| self.windows.get_mut(&id)) {
+                        deliver_button(window, seat, mods, button, pressed);
+                        continue;
+                    }
+                    let click = match button {
+                        BTN_LEFT => FrameClick::Normal,
+                        BTN_RIGHT => FrameClick::Alternate,
+                        _ => continue,
+                    };
+                    let window = seat
+                        .frame_focus
+                        .as_ref()
+                        .and_then(|id| self.windows.get_mut(id));
+                    if let Some(window) = window {
+                        window.frame_clicked(click, pressed, &seat.seat, serial);
+                    }
+                }
+                PointerEventKind::Axis {
+                    horizontal,
+                    vertical,
+                    ..
+                } => {
+                    let focus = seat.pointer_focus.clone();
+                    if let Some(window) = focus.and_then(|id| self.windows.get_mut(&id)) {
+                        let mut event = pointer_event(seat, mods, PointerButton::None);
+                        event.pointer_type = PointerType::Mouse(MouseInfo {
+                            wheel_delta: Vec2::new(horizontal.absolute, vertical.absolute),
+                        });
+                        window.handler.wheel(&event);
+                    }
+                }
+            }
+        }
+        // Hovering changes how the decorations are drawn
+        for window in self.windows.values_mut() {
+            window.redraw_frame();
+        }
+    }
+}
+
+fn deliver_button(
+    window: &mut WaylandWindowState,
+    seat: &mut SeatInfo,
+    mods: crate::Modifiers,
+    button: u32,
+    pressed: bool,
+) {
+    let button = match button {
+        BTN_LEFT => PointerButton::Primary,
+        BTN_RIGHT => PointerButton::Secondary,
+        BTN_MIDDLE => PointerButton::Auxiliary,
+        BTN_SIDE => PointerButton::X1,
+        BTN_EXTRA => PointerButton::X2,
+        _ => return,
+    };
+    if pressed {
+        seat.buttons.insert(button);
+        let mut event = pointer_event(seat, mods, button);
+        event.count = seat.click_counter.count_for_click(seat.pointer_pos);
+        window.handler.pointer_down(&event);
+    } else {
+        seat.buttons.remove(button);
+        let event = pointer_event(seat, mods, button);
+        window.handler.pointer_up(&event);
+    }
+}
+
+/// An event at where the pointer of `seat` is, with the buttons it holds
+fn pointer_event(
+    seat: &SeatInfo,
+    modifiers: crate::Modifiers,
+    button: PointerButton,
+) -> PointerEvent {
+    PointerEvent {
+        pos: seat.pointer_pos,
+        buttons: seat.buttons,
+        modifiers,
+        button,
+        ..PointerEvent::default()
+    }
+}
+
+delegate_pointer!(WaylandState);
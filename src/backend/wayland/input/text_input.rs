@@ -144,14 +144,19 @@ impl InputState {
     ///
     /// Used alongside the enable request, or in response to the leave event
     fn reset(&mut self) {
+        self.clear_pending();
+        self.buffer_start = None;
+        self.token = None;
+    }
+
+    /// Forget the changes sent since the last `done` event, as each only applies once
+    fn clear_pending(&mut self) {
         self.commit_string = None;
         self.preedit_string = None;
         self.delete_surrounding_before = 0;
         self.delete_surrounding_after = 0;
         self.new_cursor_begin = 0;
         self.new_cursor_end = 0;
-        self.buffer_start = None;
-        self.token = None;
     }
 
     fn sync_state(
@@ -303,8 +308,12 @@ impl InputState {
         if self.delete_surrounding_before > 0 || self.delete_surrounding_after > 0 {
             // The spec is unclear on how this should be handled when there is a cursor range.
             // The relevant verbiage is "current cursor index"
-            let delete_range = (selection.active - self.delete_surrounding_before as usize)
-                ..(selection.active + self.delete_surrounding_after as usize);
+            // The lengths are in bytes of the surrounding text, which a confused input method
+            // could take past the ends of the buffer
+            let delete_range = selection
+                .active
+                .saturating_sub(self.delete_surrounding_before as usize)
+                ..(selection.active + self.delete_surrounding_after as usize).min(handler.len());
             if delete_range.contains(&selection.anchor) {
                 selection.anchor = delete_range.start;
             }
@@ -326,11 +335,15 @@ impl InputState {
             let selection_start = range.start;
             handler.replace_range(range, &preedit);
             handler.set_composition_range(Some(selection_start..(selection_start + preedit.len())));
-            let selection_start = selection_start as i32;
             // 6. Place cursor inside preedit text.
+            // The cursor is hidden when its positions are -1, so then it goes at the end
+            let cursor = |offset: i32| {
+                selection_start
+                    + usize::try_from(offset).map_or(preedit.len(), |it| it.min(preedit.len()))
+            };
             handler.set_selection(Selection::new(
-                (selection_start + self.new_cursor_begin) as usize,
-                (selection_start + self.new_cursor_end) as usize,
+                cursor(self.new_cursor_begin),
+                cursor(self.new_cursor_end),
             ));
         } else {
             handler.set_composition_range(None);
@@ -371,7 +384,9 @@ impl Dispatch<ZwpTextInputV3, InputUserData> for WaylandState {
         match event {
             zwp_text_input_v3::Event::Enter { surface } => {
                 let window_id = WindowId::of_surface(&surface);
-                let win = state.windows.get_mut(&window_id).unwrap();
+                let Some(win) = state.windows.get_mut(&window_id) else {
+                    return;
+                };
                 win.set_input_seat(data.0);
                 let input_state = text_input(&mut state.input_states, data);
                 input_state.active_window = Some(window_id);
@@ -425,24 +440,31 @@ impl Dispatch<ZwpTextInputV3, InputUserData> for WaylandState {
             }
             zwp_text_input_v3::Event::Done { serial } => {
                 let input_state = text_input(&mut state.input_states, data);
-                let Some(win) = state.windows.get_mut(input_state.active_window.as_ref().unwrap()) else {return;};
-                let input_lock = win.get_input_lock(true);
-                if let Some((mut handler, token)) = input_lock {
-                    if Some(token) == input_state.token {
-                        input_state.done(&mut *handler);
-                        if serial == input_state.commit_count
-                            && input_state.state_might_have_changed
-                        {
-                            input_state.sync_state(
-                                &mut *handler,
-                                zwp_text_input_v3::ChangeCause::InputMethod,
-                            );
-                            input_state.state_might_have_changed = false;
+                // This can arrive after we have left the surface, so there's nothing to apply
+                let win = match &input_state.active_window {
+                    Some(window_id) => state.windows.get_mut(window_id),
+                    None => None,
+                };
+                if let Some(win) = win {
+                    if let Some((mut handler, token)) = win.get_input_lock(true) {
+                        if Some(token) == input_state.token {
+                            input_state.done(&mut *handler);
+                            if serial == input_state.commit_count
+                                && input_state.state_might_have_changed
+                            {
+                                input_state.sync_state(
+                                    &mut *handler,
+                                    zwp_text_input_v3::ChangeCause::InputMethod,
+                                );
+                                input_state.state_might_have_changed = false;
+                            }
                         }
+                        win.release_input_lock(token);
                     }
                 }
+                input_state.clear_pending();
             }
-            _ => todo!(),
+            _ => tracing::error!(?event, "unexpected zwp_text_input_v3 event"),
         }
     }
 }
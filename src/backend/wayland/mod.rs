@@ -15,9 +15,13 @@
 //! wayland platform support
 
 use std::{
+    cell::{Cell, RefCell},
     collections::HashMap,
     rc::Rc,
-    sync::mpsc::{Receiver, Sender},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
 };
 
 use smithay_client_toolkit::{
@@ -32,12 +36,15 @@ use smithay_client_toolkit::{
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::SeatState,
-    shell::xdg::XdgShell,
+    shell::{wlr_layer::LayerShell, xdg::XdgShell},
+    shm::Shm,
+    subcompositor::SubcompositorState,
 };
 
 use crate::{AppHandler, IdleToken};
 
 use self::{
+    clipboard::ClipboardState,
     input::SeatInfo,
     window::{WaylandWindowState, WindowAction, WindowId},
 };
@@ -47,8 +54,10 @@ use super::shared::xkb::Context;
 pub mod application;
 pub mod clipboard;
 pub mod error;
+mod fractional_scale;
 mod input;
 pub mod menu;
+mod presentation;
 pub mod screen;
 pub mod window;
 
@@ -72,11 +81,16 @@ struct WaylandState {
     pub registry_state: RegistryState,
 
     pub output_state: OutputState,
-    // TODO: Do we need to keep this around
-    // It is unused because(?) wgpu creates the surfaces through RawDisplayHandle(?)
-    pub _compositor_state: CompositorState,
+    /// Used to create the surfaces of cursors. Windows are created through `Application`
+    pub compositor_state: CompositorState,
+    /// Used to draw the fallback decorations, if the compositor supports subsurfaces
+    pub subcompositor: Option<Arc<SubcompositorState>>,
     // Is used: Keep the XdgShell alive, which is a Weak in all Handles
     pub _xdg_shell_state: Rc<XdgShell>,
+    // Likewise, if the compositor supports it
+    pub _layer_shell_state: Option<Rc<LayerShell>>,
+    /// Used by `WindowHandle::present_pixels`
+    pub shm: Shm,
     pub wayland_queue: QueueHandle<Self>,
 
     pub event_loop: Option<EventLoop<'static, Self>>,
@@ -85,6 +99,9 @@ struct WaylandState {
     // TODO: Should we keep this around here?
     pub _idle_sender: Sender<IdleAction>,
     pub loop_signal: LoopSignal,
+    /// Set whenever `loop_signal` is stopped, so that `Application::run_loop_iteration` knows
+    /// to exit.
+    pub quitting: Rc<Cell<bool>>,
 
     // TODO: Should we keep this around here?
     pub _loop_sender: channel::Sender<ActiveAction>,
@@ -95,6 +112,15 @@ struct WaylandState {
     pub input_states: Vec<SeatInfo>,
     pub xkb_context: Context,
     pub text_input: Option<ZwpTextInputManagerV3>,
+    /// Shared with every `Clipboard`
+    pub clipboard: Rc<RefCell<ClipboardState>>,
+    /// The clock which `wp_presentation` reports timestamps in
+    pub presentation_clock: u32,
+    /// Whether `AppHandler::idle` was called before the loop last waited
+    pub idling: bool,
+    /// Shared with `Application`, which watches it
+    #[cfg(feature = "accesskit")]
+    pub accessibility: crate::backend::shared::linux::accesskit::AccessibilityStatus,
 }
 
 delegate_registry!(WaylandState);
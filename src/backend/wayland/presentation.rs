@@ -0,0 +1,93 @@
+//! Measuring when frames reach the screen with the presentation-time protocol.
+
+use std::time::{Duration, Instant};
+
+use nix::time::{clock_gettime, ClockId};
+use smithay_client_toolkit::reexports::{
+    client::{Connection, Dispatch, QueueHandle},
+    protocols::wp::presentation_time::client::{
+        wp_presentation::{self, WpPresentation},
+        wp_presentation_feedback::{self, WpPresentationFeedback},
+    },
+};
+
+use super::{window::WindowId, WaylandState};
+
+impl Dispatch<WpPresentation, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &WpPresentation,
+        event: wp_presentation::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wp_presentation::Event::ClockId { clk_id } => state.presentation_clock = clk_id,
+            _ => tracing::error!(?event, "unexpected wp_presentation event"),
+        }
+    }
+}
+
+/// Identifies the frame a `wp_presentation_feedback` is for.
+pub(super) struct FeedbackData {
+    pub window_id: WindowId,
+    /// The number `FrameTimingsCache::paint_started` gave the frame
+    pub frame: u64,
+}
+
+impl Dispatch<WpPresentationFeedback, FeedbackData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        data: &FeedbackData,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let (presented, refresh) = match event {
+            wp_presentation_feedback::Event::Presented {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+                refresh,
+                ..
+            } => {
+                let secs = (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+                let presented = Duration::new(secs, tv_nsec);
+                let Some(vsync) = clock_to_instant(state.presentation_clock, presented) else {
+                    return;
+                };
+                // A refresh of zero means the output doesn't have a constant refresh rate.
+                let refresh = (refresh != 0).then(|| Duration::from_nanos(refresh.into()));
+                (Some(vsync), refresh)
+            }
+            // The frame was replaced before it was shown.
+            wp_presentation_feedback::Event::Discarded => (None, None),
+            wp_presentation_feedback::Event::SyncOutput { .. } => return,
+            _ => {
+                tracing::error!(?event, "unexpected wp_presentation_feedback event");
+                return;
+            }
+        };
+        if let Some(window) = state.windows.get_mut(&data.window_id) {
+            window.frame_feedback(data.frame, presented, refresh);
+        }
+    }
+}
+
+/// Converts a timestamp from the compositor's presentation clock to an `Instant`.
+fn clock_to_instant(clock: u32, time: Duration) -> Option<Instant> {
+    let now = Instant::now();
+    let clock_now = match clock_gettime(ClockId::from_raw(clock as _)) {
+        Ok(clock_now) => Duration::new(clock_now.tv_sec() as u64, clock_now.tv_nsec() as u32),
+        Err(e) => {
+            tracing::warn!("failed to read the presentation clock: {e}");
+            return None;
+        }
+    };
+    match clock_now.checked_sub(time) {
+        Some(ago) => now.checked_sub(ago),
+        None => now.checked_add(time - clock_now),
+    }
+}
@@ -14,42 +14,79 @@
 #![allow(clippy::single_match)]
 
 use std::cell::RefCell;
+use std::num::NonZeroU32;
 use std::os::raw::c_void;
+use std::ptr::NonNull;
 use std::rc::{Rc, Weak};
 use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+#[cfg(feature = "accesskit")]
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "accesskit")]
+use accesskit_unix::Adapter as AccessKitAdapter;
 use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
-    WaylandDisplayHandle, WaylandWindowHandle,
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
+use sctk_adwaita::{AdwaitaFrame, FrameConfig};
 use smithay_client_toolkit::compositor::CompositorHandler;
-use smithay_client_toolkit::reexports::calloop::channel;
+use smithay_client_toolkit::reexports::calloop::timer::{TimeoutAction, Timer};
+use smithay_client_toolkit::reexports::calloop::{channel, LoopHandle};
 use smithay_client_toolkit::reexports::client::protocol::wl_compositor::WlCompositor;
+use smithay_client_toolkit::reexports::client::protocol::wl_seat::WlSeat;
 use smithay_client_toolkit::reexports::client::protocol::wl_surface::WlSurface;
 use smithay_client_toolkit::reexports::client::{protocol, Connection, Proxy, QueueHandle};
+use smithay_client_toolkit::reexports::protocols::wp::presentation_time::client::wp_presentation::WpPresentation;
+use smithay_client_toolkit::reexports::protocols::xdg::shell::client::{
+    xdg_positioner, xdg_surface::XdgSurface,
+};
+use smithay_client_toolkit::shell::wlr_layer::{
+    self, LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure,
+};
+use smithay_client_toolkit::shell::xdg::frame::{DecorationsFrame, FrameAction, FrameClick};
+use smithay_client_toolkit::shell::xdg::popup::{Popup, PopupConfigure, PopupHandler};
 use smithay_client_toolkit::shell::xdg::window::{
     DecorationMode, Window, WindowConfigure, WindowDecorations, WindowHandler,
 };
-use smithay_client_toolkit::shell::xdg::XdgShell;
+use smithay_client_toolkit::shell::xdg::{XdgPositioner, XdgShell};
 use smithay_client_toolkit::shell::WaylandSurface;
-use smithay_client_toolkit::{delegate_compositor, delegate_xdg_shell, delegate_xdg_window};
+use smithay_client_toolkit::shm::slot::{Buffer, SlotPool};
+use smithay_client_toolkit::shm::{Shm, ShmHandler};
+use smithay_client_toolkit::subcompositor::SubcompositorState;
+use smithay_client_toolkit::{
+    delegate_compositor, delegate_layer, delegate_shm, delegate_subcompositor, delegate_xdg_popup,
+    delegate_xdg_shell, delegate_xdg_window,
+};
 use tracing;
 use wayland_backend::client::ObjectId;
 
 use super::application::{self};
+use super::fractional_scale::{FractionalScaleManager, SurfaceScale};
 use super::input::{SeatName, TextFieldChange};
 use super::menu::Menu;
+use super::presentation::FeedbackData;
 use super::{ActiveAction, IdleAction, WaylandState};
 
+#[cfg(feature = "accesskit")]
+use crate::backend::shared::linux;
+use crate::common_util::{self, FrameSchedule, FrameThrottle, FrameTimingsCache};
 use crate::text::{simulate_input, InputHandler};
 use crate::{
     dialog::FileDialogOptions,
     error::Error as ShellError,
     kurbo::{Insets, Point, Rect, Size},
     mouse::{Cursor, CursorDesc},
+    platform::linux::{self as platform, LayerShellConfig},
     scale::Scale,
     text::Event,
-    window::{self, FileDialogToken, TimerToken, WinHandler, WindowLevel},
+    window::{
+        self, FileDialogToken, FrameTimings, ImageBuf, PixelFormat, PopupAnchor, PopupConfig,
+        TimerToken, Visibility, WinHandler, WindowLevel, WindowType,
+    },
     TextFieldToken,
 };
 use crate::{IdleToken, KeyEvent, Region, Scalable};
@@ -64,50 +101,163 @@ pub struct WindowHandle {
 }
 
 impl WindowHandle {
-    fn id(&self) -> WindowId {
-        let props = self.properties();
+    fn id(&self) -> Option<WindowId> {
+        let props = self.properties()?;
         let props = props.borrow();
-        WindowId::new(&props.wayland_window)
+        Some(WindowId::new(&props.wayland_window))
     }
 
     fn defer(&self, action: WindowAction) {
-        self.loop_sender
-            .send(ActiveAction::Window(self.id(), action))
-            .expect("Running on a window should only occur whilst application is active")
+        let Some(id) = self.id() else {
+            return;
+        };
+        let action = ActiveAction::Window(id, action);
+        if self.loop_sender.send(action).is_err() {
+            tracing::debug!("the application has already been dropped");
+        }
+    }
+
+    /// The window's properties, which are dropped when it closes. Calls after that do nothing.
+    fn properties(&self) -> Option<Rc<RefCell<WindowProperties>>> {
+        let props = self.properties.upgrade();
+        if props.is_none() {
+            tracing::debug!("the window has already been closed");
+        }
+        props
     }
 
-    fn properties(&self) -> Rc<RefCell<WindowProperties>> {
-        self.properties.upgrade().unwrap()
+    pub fn is_alive(&self) -> bool {
+        self.properties.upgrade().is_some()
     }
 
     pub fn show(&self) {
         tracing::debug!("show initiated");
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return;
+        };
         let props = props.borrow();
-        // TODO: Is this valid? Do we instead need to
+        if props.popup_grab {
+            // Grabbing needs the serial of the input event which opened the popup
+            drop(props);
+            self.defer(WindowAction::ShowGrabbingPopup);
+            return;
+        }
+        // This first commit has no buffer, which asks the compositor for the initial configure.
+        // The surface is only mapped by the commit of the frame the handler paints in response,
+        // so it's never shown unpainted.
         props.wayland_window.commit();
     }
 
+    pub fn hide(&self) {
+        tracing::debug!("hide initiated");
+        self.defer(WindowAction::Hide);
+    }
+
+    pub fn set_skip_taskbar(&self, _skip: bool) {
+        // xdg-shell has no way to hint which windows a taskbar should show.
+        tracing::warn!("set_skip_taskbar is unimplemented on wayland");
+    }
+
+    pub fn set_window_level(&self, _level: window::StackingLevel) {
+        // xdg-shell leaves stacking entirely to the compositor. A layer shell surface can be
+        // placed on a `Layer` instead.
+        tracing::warn!("set_window_level is unimplemented on wayland");
+    }
+
+    pub fn set_titlebar_theme(&self, _theme: Option<window::Theme>) {
+        // The compositor draws server side decorations in its own theme.
+        tracing::warn!("set_titlebar_theme is unimplemented on wayland");
+    }
+
+    pub fn begin_sheet(
+        &self,
+        sheet: &WindowHandle,
+        completion: Box<dyn FnOnce(window::ModalResponse)>,
+    ) {
+        // There is no modal hint in xdg-shell, so the sheet is only made a child of this window
+        {
+            let Some(props) = self.properties() else {
+                return;
+            };
+            let props = props.borrow();
+            let Some(sheet_props) = sheet.properties() else {
+                return;
+            };
+            let mut sheet_props = sheet_props.borrow_mut();
+            match (
+                props.wayland_window.toplevel(),
+                sheet_props.wayland_window.toplevel(),
+            ) {
+                (Some(parent), Some(child)) => child.set_parent(Some(parent)),
+                _ => tracing::warn!("sheets are only supported between toplevel windows"),
+            }
+            sheet_props.sheet_completion = Some(completion);
+            sheet_props.sheet_parent = Some(WindowId::new(&props.wayland_window));
+        }
+        sheet.show();
+    }
+
+    pub fn end_sheet(&self, response: window::ModalResponse) {
+        let Some(props) = self.properties() else {
+            return;
+        };
+        let completion = {
+            let mut props = props.borrow_mut();
+            props.sheet_parent = None;
+            props.sheet_completion.take()
+        };
+        if let Some(completion) = completion {
+            self.hide();
+            completion(response);
+        }
+    }
+
+    pub fn run_modal(&self) -> window::ModalResponse {
+        // The event loop's state is borrowed for as long as the handler which called this runs,
+        // so we can't dispatch it again from here
+        tracing::warn!("run_modal is unimplemented on wayland");
+        window::ModalResponse::Cancel
+    }
+
+    pub fn end_modal(&self, _response: window::ModalResponse) {
+        tracing::warn!("end_modal is unimplemented on wayland");
+    }
+
+    pub fn focus_next_window(&self) {
+        tracing::warn!("focus_next_window is unimplemented on wayland");
+    }
+
     pub fn resizable(&self, _resizable: bool) {
         tracing::warn!("resizable is unimplemented on wayland");
         // TODO: If we are using fallback decorations, we should be able to disable
         // dragging based resizing
     }
 
+    pub fn set_aspect_ratio(&self, _ratio: Option<f64>) {
+        // xdg-shell has no aspect ratio, and the compositor decides the size while resizing
+        tracing::warn!("set_aspect_ratio is unimplemented on wayland");
+    }
+
+    pub fn set_resize_increments(&self, _increments: Option<Size>) {
+        // xdg-shell has no resize increments either
+        tracing::warn!("set_resize_increments is unimplemented on wayland");
+    }
+
     pub fn show_titlebar(&self, show_titlebar: bool) {
-        tracing::info!("show_titlebar is implemented on a best-effort basis on wayland");
-        // TODO: Track this into the fallback decorations when we add those
-        let props = self.properties();
-        let props = props.borrow();
-        if show_titlebar {
-            props
-                .wayland_window
-                .request_decoration_mode(Some(DecorationMode::Server))
-        } else {
-            props
-                .wayland_window
-                .request_decoration_mode(Some(DecorationMode::Client))
+        {
+            let Some(props) = self.properties() else {
+                return;
+            };
+            let mut props = props.borrow_mut();
+            props.show_titlebar = show_titlebar;
+            let mode = props.requested_decoration_mode();
+            if let Some(toplevel) = props.wayland_window.toplevel() {
+                toplevel.request_decoration_mode(Some(mode));
+            }
         }
+        // Compositors without xdg-decoration won't send a new configure, so update our
+        // fallback decorations directly
+        self.defer(WindowAction::DecorationsChanged);
     }
 
     pub fn set_position(&self, _position: Point) {
@@ -128,8 +278,23 @@ impl WindowHandle {
     }
 
     pub fn set_size(&self, size: Size) {
-        let props = self.properties();
-        props.borrow_mut().requested_size = Some(size);
+        let Some(props) = self.properties() else {
+            return;
+        };
+        let mut props = props.borrow_mut();
+        props.requested_size = Some(size);
+        if let SurfaceRole::Layer(layer) = &props.wayland_window {
+            // The compositor answers with a configure, which resizes the window
+            layer.set_size(size.width.round() as u32, size.height.round() as u32);
+            layer.commit();
+            return;
+        }
+        if let SurfaceRole::Popup(_) = &props.wayland_window {
+            // This would need `xdg_popup.reposition`, from version 3
+            tracing::warn!("set_size is unimplemented for wayland popups");
+            return;
+        }
+        drop(props);
 
         // We don't need to tell the server about changing the size - so long as the size of the surface gets changed properly
         // So, all we need to do is to tell the handler about this change (after caching it here)
@@ -138,19 +303,26 @@ impl WindowHandle {
     }
 
     pub fn get_size(&self) -> Size {
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return Size::ZERO;
+        };
         let props = props.borrow();
         props.current_size
     }
 
     pub fn set_window_state(&mut self, state: window::WindowState) {
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return;
+        };
         let props = props.borrow();
+        let Some(toplevel) = props.wayland_window.toplevel() else {
+            return;
+        };
         match state {
-            crate::WindowState::Maximized => props.wayland_window.set_maximized(),
-            crate::WindowState::Minimized => props.wayland_window.set_minimized(),
+            crate::WindowState::Maximized => toplevel.set_maximized(),
+            crate::WindowState::Minimized => toplevel.set_minimized(),
             // TODO: I don't think we can do much better than this - we can't unset being minimised
-            crate::WindowState::Restored => props.wayland_window.unset_maximized(),
+            crate::WindowState::Restored => toplevel.unset_maximized(),
         }
     }
 
@@ -160,6 +332,11 @@ impl WindowHandle {
         window::WindowState::Maximized
     }
 
+    pub fn set_maximize_mode(&self, _mode: window::MaximizeMode) {
+        // The compositor decides the maximized geometry, there is no way to ask for more.
+        tracing::warn!("set_maximize_mode is unimplemented on wayland");
+    }
+
     pub fn handle_titlebar(&self, _val: bool) {
         tracing::warn!("handle_titlebar is unimplemented on wayland");
     }
@@ -176,10 +353,13 @@ impl WindowHandle {
 
     /// Request a new paint, but without invalidating anything.
     pub fn request_anim_frame(&self) {
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return;
+        };
         let mut props = props.borrow_mut();
         props.will_repaint = true;
-        if !props.pending_frame_callback {
+        // Without vsync, we paint without waiting for the frame callback in flight
+        if !props.pending_frame_callback || !props.vsync {
             drop(props);
             self.defer(WindowAction::AnimationRequested);
         }
@@ -187,13 +367,84 @@ impl WindowHandle {
 
     /// Request invalidation of the entire window contents.
     pub fn invalidate(&self) {
+        {
+            let Some(props) = self.properties() else {
+                return;
+            };
+            let mut props = props.borrow_mut();
+            let rect = props.current_size.to_rect();
+            props.invalid.set_rect(rect);
+        }
         self.request_anim_frame();
     }
 
     /// Request invalidation of one rectangle, which is given in display points relative to the
     /// drawing area.
-    pub fn invalidate_rect(&self, _rect: Rect) {
-        todo!()
+    pub fn invalidate_rect(&self, rect: Rect) {
+        let Some(props) = self.properties() else {
+            return;
+        };
+        props.borrow_mut().invalid.add_rect(rect);
+        self.request_anim_frame();
+    }
+
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), ShellError> {
+        let props = self.properties.upgrade().ok_or(ShellError::WindowDropped)?;
+        let size_px = {
+            let props = props.borrow();
+            if !props.managed_surface {
+                return Err(anyhow::anyhow!(
+                    "present_pixels is not supported on windows without a managed surface"
+                )
+                .into());
+            }
+            props.size_px(props.current_size)
+        };
+        if (width as f64, height as f64) != (size_px.width, size_px.height) {
+            return Err(anyhow::anyhow!(
+                "pixel buffer is {width}x{height}, but the window is {}x{}",
+                size_px.width,
+                size_px.height
+            )
+            .into());
+        }
+        // wl_shm formats are little endian, so XRGB8888 is stored as BGRX
+        // We must defer this, because the shm pool lives in the event loop's state
+        self.defer(WindowAction::PresentPixels {
+            pixels: Rc::from(&*format.to_bgra(buf)),
+            width,
+            height,
+        });
+        Ok(())
+    }
+
+    pub fn read_back_pixels(&self, _format: PixelFormat) -> Result<Vec<u8>, ShellError> {
+        Err(ShellError::Unsupported("read_back_pixels"))
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        // Clients can't read back their surfaces, as that needs a screencopy protocol, but we
+        // still have the buffer which `present_pixels` last attached
+        let props = self.properties()?;
+        let props = props.borrow();
+        let Some((width, height, scale, pixels)) = &props.presented else {
+            tracing::warn!("capture is only supported on wayland after present_pixels");
+            return None;
+        };
+        let mut pixels = pixels.to_vec();
+        // The buffers are XRGB8888, so the alpha channel is unset
+        for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+            px[3] = 0xff;
+        }
+        Some(
+            ImageBuf::new(*width, *height, PixelFormat::Rgba8.from_bgra(pixels)).with_scale(*scale),
+        )
     }
 
     pub fn add_text_field(&self) -> TextFieldToken {
@@ -201,7 +452,9 @@ impl WindowHandle {
     }
 
     pub fn remove_text_field(&self, token: TextFieldToken) {
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return;
+        };
         let mut props = props.borrow_mut();
         if props.focused_text_field.is_some_and(|it| it == token) {
             props.focused_text_field = None;
@@ -211,13 +464,20 @@ impl WindowHandle {
     }
 
     pub fn set_focused_text_field(&self, active_field: Option<TextFieldToken>) {
-        let props = self.properties();
+        let Some(props) = self.properties() else {
+            return;
+        };
         let mut props = props.borrow_mut();
         props.focused_text_field = active_field;
         drop(props);
         self.defer(WindowAction::TextField(TextFieldChange::Changed));
     }
 
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.properties()?.borrow().focused_text_field
+    }
+
     pub fn update_text_field(&self, token: TextFieldToken, update: Event) {
         self.defer(WindowAction::TextField(TextFieldChange::Updated(
             token, update,
@@ -225,8 +485,10 @@ impl WindowHandle {
         // noop until we get a real text input implementation
     }
 
-    pub fn request_timer(&self, _deadline: std::time::Instant) -> TimerToken {
-        todo!()
+    pub fn request_timer(&self, deadline: std::time::Instant) -> TimerToken {
+        let token = TimerToken::next();
+        self.defer(WindowAction::Timer { token, deadline });
+        token
     }
 
     pub fn set_cursor(&mut self, _cursor: &Cursor) {
@@ -252,17 +514,40 @@ impl WindowHandle {
     pub fn get_idle_handle(&self) -> Option<IdleHandle> {
         Some(IdleHandle {
             idle_sender: self.idle_sender.clone(),
-            window: self.id(),
+            window: self.id()?,
         })
     }
 
     /// Get the `Scale` of the window.
     pub fn get_scale(&self) -> Result<Scale, ShellError> {
-        let props = self.properties();
+        let props = self.properties().ok_or(ShellError::WindowDropped)?;
         let props = props.borrow();
         Ok(props.current_scale)
     }
 
+    pub fn set_backing_scale_override(&self, _scale: Option<f64>) {
+        tracing::warn!("set_backing_scale_override is unimplemented on wayland");
+    }
+
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        let Some(props) = self.properties() else {
+            return FrameTimings::default();
+        };
+        let props = props.borrow();
+        props.frame_timings.last()
+    }
+
+    pub fn set_present_feedback(&self, enabled: bool) {
+        let Some(props) = self.properties() else {
+            return;
+        };
+        let props = props.borrow();
+        if enabled && props.presentation.is_none() {
+            tracing::warn!("present feedback is unavailable, as the compositor doesn't support wp_presentation");
+        }
+        props.frame_timings.set_feedback(enabled);
+    }
+
     pub fn set_menu(&self, _menu: Menu) {
         tracing::warn!("set_menu not implement for wayland");
     }
@@ -271,16 +556,62 @@ impl WindowHandle {
         tracing::warn!("show_context_menu not implement for wayland");
     }
 
-    pub fn set_title(&self, _title: impl Into<String>) {
-        todo!()
+    pub fn show_character_palette(&self) {
+        // Neither text-input-v3 nor any other protocol can ask for a picker, so there's nothing
+        // to show. Some input methods have a shortcut for their own.
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        let Some(props) = self.properties() else {
+            return;
+        };
+        let mut props = props.borrow_mut();
+        props.vsync = enabled;
+        if !enabled && props.will_repaint && props.pending_frame_callback {
+            // Stop waiting for the frame callback
+            drop(props);
+            self.defer(WindowAction::AnimationRequested);
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        let Some(props) = self.properties() else {
+            return true;
+        };
+        let props = props.borrow();
+        props.vsync
+    }
+
+    pub fn set_title(&self, title: impl Into<String>) {
+        let Some(props) = self.properties() else {
+            return;
+        };
+        let mut props = props.borrow_mut();
+        props.title = title.into();
+        if let Some(toplevel) = props.wayland_window.toplevel() {
+            toplevel.set_title(props.title.clone());
+        }
+        drop(props);
+        // The fallback decorations draw the title too
+        self.defer(WindowAction::TitleChanged);
     }
 
     #[cfg(feature = "accesskit")]
     pub fn update_accesskit_if_active(
         &self,
-        _update_factory: impl FnOnce() -> accesskit::TreeUpdate,
+        update_factory: impl FnOnce() -> accesskit::TreeUpdate,
     ) {
-        // AccessKit doesn't yet support this backend.
+        let Some(props) = self.properties() else {
+            return;
+        };
+        if props.borrow().accesskit_adapter.is_none() {
+            return;
+        }
+        // The factory may use this handle, so the properties aren't borrowed while it runs
+        let update = update_factory();
+        if let Some(adapter) = props.borrow().accesskit_adapter.as_ref() {
+            adapter.update(update);
+        }
     }
 }
 
@@ -307,22 +638,52 @@ impl Default for WindowHandle {
     }
 }
 
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        // The properties are dropped along with the window, which destroys the surface.
+        let props = self.properties.upgrade().ok_or(HandleError::Unavailable)?;
+        let surface = props.borrow().wayland_window.wl_surface().id().as_ptr();
+        let surface = NonNull::new(surface as *mut c_void).ok_or(HandleError::Unavailable)?;
+        let handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(surface));
+        // SAFETY: The surface is alive, as the window hasn't been dropped.
+        Ok(unsafe { raw_window_handle::WindowHandle::borrow_raw(handle) })
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        if self.properties.upgrade().is_none() {
+            return Err(HandleError::Unavailable);
+        }
+        let display = self
+            .raw_display_handle
+            .and_then(NonNull::new)
+            .ok_or(HandleError::Unavailable)?;
+        let handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display));
+        // SAFETY: The display outlives the windows created on it.
+        Ok(unsafe { DisplayHandle::borrow_raw(handle) })
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        let mut handle = WaylandWindowHandle::empty();
-        let props = self.properties();
-        handle.surface = props.borrow().wayland_window.wl_surface().id().as_ptr() as *mut _;
-        RawWindowHandle::Wayland(handle)
+    fn raw_window_handle(&self) -> rwh_05::RawWindowHandle {
+        let mut handle = rwh_05::WaylandWindowHandle::empty();
+        if let Some(props) = self.properties() {
+            handle.surface = props.borrow().wayland_window.wl_surface().id().as_ptr() as *mut _;
+        }
+        rwh_05::RawWindowHandle::Wayland(handle)
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
-    fn raw_display_handle(&self) -> RawDisplayHandle {
-        let mut handle = WaylandDisplayHandle::empty();
+    fn raw_display_handle(&self) -> rwh_05::RawDisplayHandle {
+        let mut handle = rwh_05::WaylandDisplayHandle::empty();
         handle.display = self
             .raw_display_handle
             .expect("Window can only be created with a valid display pointer");
-        RawDisplayHandle::Wayland(handle)
+        rwh_05::RawDisplayHandle::Wayland(handle)
     }
 }
 
@@ -389,12 +750,25 @@ pub(crate) struct WindowBuilder {
     min_size: Option<Size>,
     resizable: bool,
     show_titlebar: bool,
+    prefer_client_side_decorations: bool,
+    kiosk: bool,
+    visible: bool,
+    managed_surface: bool,
+    layer_shell_config: Option<LayerShellConfig>,
+    /// Set with `WindowBuilder::with_parent`
+    owner: Option<crate::WindowHandle>,
+    popup: Option<PopupConfig>,
     compositor: WlCompositor,
+    presentation: Option<WpPresentation>,
+    fractional_scale: Option<FractionalScaleManager>,
     wayland_queue: QueueHandle<WaylandState>,
     xdg_state: Weak<XdgShell>,
+    layer_shell: Weak<LayerShell>,
     idle_sender: Sender<IdleAction>,
     loop_sender: channel::Sender<ActiveAction>,
+    loop_handle: LoopHandle<'static, WaylandState>,
     raw_display_handle: *mut c_void,
+    app_id: Option<String>,
 }
 
 impl WindowBuilder {
@@ -410,12 +784,24 @@ impl WindowBuilder {
             min_size: None,
             resizable: true,
             show_titlebar: true,
+            prefer_client_side_decorations: false,
+            kiosk: false,
+            visible: false,
+            managed_surface: true,
+            layer_shell_config: None,
+            owner: None,
+            popup: None,
             compositor: app.compositor,
+            presentation: app.presentation,
+            fractional_scale: app.fractional_scale,
             wayland_queue: app.wayland_queue,
             xdg_state: app.xdg_shell,
+            layer_shell: app.layer_shell,
             idle_sender: app.idle_sender,
             loop_sender: app.loop_sender,
+            loop_handle: app.loop_handle,
             raw_display_handle: app.raw_display_handle,
+            app_id: app.app_id.borrow().clone(),
         }
     }
 
@@ -444,6 +830,11 @@ impl WindowBuilder {
         self
     }
 
+    pub fn prefer_client_side_decorations(mut self, prefer: bool) -> Self {
+        self.prefer_client_side_decorations = prefer;
+        self
+    }
+
     pub fn transparent(self, _transparent: bool) -> Self {
         tracing::info!(
             "WindowBuilder::transparent is unimplemented for Wayland, it allows transparency by default"
@@ -461,11 +852,78 @@ impl WindowBuilder {
         self
     }
 
+    pub fn with_window_type(self, _window_type: WindowType) -> Self {
+        // xdg-shell has no window types, and the compositor treats surfaces by their role
+        self
+    }
+
+    pub fn with_frame_autosave_name(self, _name: &str, _fullscreen: bool) -> Self {
+        // Surfaces can't find out where they are, so there's nothing to save
+        self
+    }
+
     pub fn window_state(mut self, state: window::WindowState) -> Self {
         self.state = Some(state);
         self
     }
 
+    pub fn maximize_mode(self, _mode: window::MaximizeMode) -> Self {
+        tracing::warn!("WindowBuilder::maximize_mode is unimplemented on wayland");
+        self
+    }
+
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self.kiosk = kiosk;
+        self
+    }
+
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self.managed_surface = managed;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_skip_taskbar(self, skip: bool) -> Self {
+        if skip {
+            tracing::warn!("WindowBuilder::with_skip_taskbar is unimplemented on wayland");
+        }
+        self
+    }
+
+    pub fn with_accepts_focus(self, accepts_focus: bool) -> Self {
+        // The compositor decides which surface gets keyboard focus.
+        if !accepts_focus {
+            tracing::warn!("WindowBuilder::with_accepts_focus is unimplemented on wayland");
+        }
+        self
+    }
+
+    pub fn with_parent(mut self, parent: crate::WindowHandle) -> Self {
+        self.owner = Some(parent);
+        self
+    }
+
+    pub fn popup(mut self, popup: PopupConfig) -> Self {
+        self.popup = Some(popup);
+        self
+    }
+
+    pub fn layer_shell(mut self, config: LayerShellConfig) -> Self {
+        self.layer_shell_config = Some(config);
+        self
+    }
+
+    pub fn offscreen(self, offscreen: bool) -> Self {
+        if offscreen {
+            tracing::warn!("WindowBuilder::offscreen is unimplemented on wayland");
+        }
+        self
+    }
+
     pub fn title(mut self, title: impl Into<String>) -> Self {
         self.title = title.into();
         self
@@ -476,26 +934,30 @@ impl WindowBuilder {
         self
     }
 
-    pub fn build(self) -> Result<WindowHandle, ShellError> {
-        let surface = self
-            .compositor
-            .create_surface(&self.wayland_queue, Default::default());
-        let xdg_shell = self
-            .xdg_state
-            .upgrade()
-            .expect("Can only build whilst event loop hasn't ended");
-        let wayland_window = xdg_shell.create_window(
-            surface,
-            // Request server decorations, because we don't yet do client decorations properly
-            WindowDecorations::RequestServer,
-            &self.wayland_queue,
-        );
-        wayland_window.set_title(self.title);
-        // TODO: Pass this down
-        wayland_window.set_app_id("org.linebender.glazier.user_app");
-        // TODO: Convert properly, set all properties
-        // wayland_window.set_min_size(self.min_size);
+    pub fn build_as_child(self, _parent: RawWindowHandle) -> Result<WindowHandle, ShellError> {
+        // A subsurface would have to be made on the connection which owns the parent surface
+        Err(ShellError::Unsupported("child windows"))
+    }
+
+    pub fn build(mut self) -> Result<WindowHandle, ShellError> {
+        if self.popup.is_some() && (self.kiosk || self.layer_shell_config.is_some()) {
+            return Err(ShellError::IncompatibleOptions(
+                "a popup can't be a kiosk window or a layer shell surface",
+            ));
+        }
+        let wayland_window = match (self.layer_shell_config.take(), &self.popup) {
+            (Some(config), _) => SurfaceRole::Layer(self.create_layer_surface(config)?),
+            (None, Some(popup)) => SurfaceRole::Popup(self.create_popup(popup)?),
+            (None, None) => SurfaceRole::Toplevel(self.create_toplevel()?),
+        };
         let window_id = WindowId::new(&wayland_window);
+        let surface_scale = self.fractional_scale.as_ref().map(|manager| {
+            manager.for_surface(
+                wayland_window.wl_surface(),
+                &self.wayland_queue,
+                window_id.clone(),
+            )
+        });
         let properties = WindowProperties {
             window_id: window_id.clone(),
             configure: None,
@@ -503,12 +965,36 @@ impl WindowBuilder {
             // This is just used as the default sizes, as we don't call `size` until the requested size is used
             current_size: Size::new(600., 800.),
             current_scale: Scale::new(1., 1.), // TODO: NaN? - these values should (must?) not be used
+            surface_scale,
             wayland_window,
             wayland_queue: self.wayland_queue.clone(),
+            presentation: self.presentation,
+            frame_timings: FrameTimingsCache::default(),
             will_repaint: false,
+            vsync: true,
             pending_frame_callback: false,
+            frame_requested_at: None,
+            starvation_timer: false,
+            invalid: Region::EMPTY,
+            visibility: Visibility::Hidden,
             configured: false,
+            interactive_resize: false,
+            configure_pending: false,
             focused_text_field: None,
+            kiosk: self.kiosk,
+            managed_surface: self.managed_surface,
+            popup_grab: self.popup.as_ref().map_or(false, |popup| popup.grab),
+            title: self.title,
+            show_titlebar: self.show_titlebar,
+            prefer_client_side_decorations: self.prefer_client_side_decorations,
+            decoration_mode: None,
+            sheet_completion: None,
+            sheet_parent: None,
+            presented: None,
+            #[cfg(feature = "accesskit")]
+            accesskit_adapter: None,
+            #[cfg(feature = "accesskit")]
+            focused: false,
         };
         let properties_strong = Rc::new(RefCell::new(properties));
 
@@ -529,37 +1015,261 @@ impl WindowBuilder {
                         properties: properties_strong,
                         text_input_seat: None,
                         loop_sender: self.loop_sender.clone(),
+                        loop_handle: self.loop_handle,
+                        frame_throttle: FrameThrottle::default(),
+                        shm_pool: None,
+                        shm_buffer: None,
+                        frame: None,
                     },
                     handle.clone(),
                 ),
             ))
-            .expect("Event loop should still be valid");
+            .map_err(|_| ShellError::ApplicationDropped)?;
+        if self.visible {
+            handle.show();
+        }
 
         Ok(handle)
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-// TODO: According to https://github.com/linebender/druid/pull/2033, this should not be
-// synced with the ID of the surface
-pub(super) struct WindowId(ObjectId);
+impl WindowBuilder {
+    fn create_toplevel(&self) -> Result<Window, ShellError> {
+        let xdg_shell = self
+            .xdg_state
+            .upgrade()
+            .ok_or(ShellError::ApplicationDropped)?;
+        let surface = self
+            .compositor
+            .create_surface(&self.wayland_queue, Default::default());
+        let decorations = if self.kiosk {
+            WindowDecorations::None
+        } else if self.prefer_client_side_decorations || !self.show_titlebar {
+            // Hiding the titlebar is only possible if we draw the decorations ourselves
+            WindowDecorations::RequestClient
+        } else {
+            WindowDecorations::RequestServer
+        };
+        let wayland_window = xdg_shell.create_window(surface, decorations, &self.wayland_queue);
+        wayland_window.set_title(self.title.clone());
+        if self.kiosk {
+            // Wayland has no way to stop the user from moving or minimizing a window,
+            // but a fullscreen surface gets neither decorations nor a frame.
+            wayland_window.set_fullscreen(None);
+        }
+        let app_id = self.app_id.as_deref();
+        wayland_window.set_app_id(app_id.unwrap_or("org.linebender.glazier.user_app"));
+        let owner = self
+            .owner
+            .as_ref()
+            .and_then(|owner| owner.0.unwrap_wayland().properties());
+        if let Some(owner) = owner {
+            let owner = owner.borrow();
+            match owner.wayland_window.toplevel() {
+                // The compositor keeps the window above its parent
+                Some(parent) => wayland_window.set_parent(Some(parent)),
+                None => tracing::warn!("only toplevel windows can be the parent of a window"),
+            }
+        }
+        // TODO: Convert properly, set all properties
+        // wayland_window.set_min_size(self.min_size);
+        Ok(wayland_window)
+    }
 
-impl WindowId {
-    pub fn new(surface: &impl WaylandSurface) -> Self {
-        Self::of_surface(surface.wl_surface())
+    fn create_layer_surface(&self, config: LayerShellConfig) -> Result<LayerSurface, ShellError> {
+        // The compositor doesn't support wlr-layer-shell, which is needed for layer shell windows
+        let layer_shell = self
+            .layer_shell
+            .upgrade()
+            .ok_or(ShellError::Unsupported("layer shell windows"))?;
+        let surface = self
+            .compositor
+            .create_surface(&self.wayland_queue, Default::default());
+        let layer = match config.layer {
+            platform::Layer::Background => wlr_layer::Layer::Background,
+            platform::Layer::Bottom => wlr_layer::Layer::Bottom,
+            platform::Layer::Top => wlr_layer::Layer::Top,
+            platform::Layer::Overlay => wlr_layer::Layer::Overlay,
+        };
+        let namespace = Some(config.namespace).filter(|it| !it.is_empty());
+        let layer_surface =
+            layer_shell.create_layer_surface(&self.wayland_queue, surface, layer, namespace, None);
+        // Our anchor bits are the protocol's
+        layer_surface.set_anchor(wlr_layer::Anchor::from_bits_truncate(config.anchor.bits()));
+        let margin = config.margin;
+        layer_surface.set_margin(
+            margin.y0.round() as i32,
+            margin.x1.round() as i32,
+            margin.y1.round() as i32,
+            margin.x0.round() as i32,
+        );
+        layer_surface.set_exclusive_zone(config.exclusive_zone);
+        layer_surface.set_keyboard_interactivity(match config.keyboard_interactivity {
+            platform::KeyboardInteractivity::None => wlr_layer::KeyboardInteractivity::None,
+            platform::KeyboardInteractivity::Exclusive => {
+                wlr_layer::KeyboardInteractivity::Exclusive
+            }
+            platform::KeyboardInteractivity::OnDemand => wlr_layer::KeyboardInteractivity::OnDemand,
+        });
+        // A zero size lets the compositor decide along axes the surface is stretched across
+        let size = self.size.unwrap_or(Size::ZERO);
+        layer_surface.set_size(size.width.round() as u32, size.height.round() as u32);
+        Ok(layer_surface)
     }
-    pub fn of_surface(surface: &WlSurface) -> Self {
-        Self(surface.id())
+
+    fn create_popup(&self, config: &PopupConfig) -> Result<Popup, ShellError> {
+        let xdg_shell = self
+            .xdg_state
+            .upgrade()
+            .ok_or(ShellError::ApplicationDropped)?;
+        let positioner = XdgPositioner::new(&*xdg_shell)
+            .map_err(|e| anyhow::anyhow!("failed to create an xdg_positioner: {e}"))?;
+        // The protocol doesn't allow empty popups or anchor rectangles
+        let size = self.size.unwrap_or(Size::new(1., 1.));
+        positioner.set_size(
+            (size.width.round() as i32).max(1),
+            (size.height.round() as i32).max(1),
+        );
+        let rect = config.anchor_rect;
+        positioner.set_anchor_rect(
+            rect.x0.round() as i32,
+            rect.y0.round() as i32,
+            (rect.width().round() as i32).max(1),
+            (rect.height().round() as i32).max(1),
+        );
+        positioner.set_anchor(positioner_anchor(config.anchor));
+        positioner.set_gravity(positioner_gravity(config.gravity));
+        // Keep the popup on screen, by flipping it to the other side of the anchor first
+        positioner.set_constraint_adjustment(
+            (xdg_positioner::ConstraintAdjustment::FlipX
+                | xdg_positioner::ConstraintAdjustment::FlipY
+                | xdg_positioner::ConstraintAdjustment::SlideX
+                | xdg_positioner::ConstraintAdjustment::SlideY)
+                .bits(),
+        );
+
+        let parent = config
+            .parent
+            .0
+            .unwrap_wayland()
+            .properties()
+            .ok_or(ShellError::WindowDropped)?;
+        let parent = parent.borrow();
+        let surface = self
+            .compositor
+            .create_surface(&self.wayland_queue, Default::default());
+        let popup = Popup::from_surface(
+            parent.wayland_window.xdg_surface(),
+            &positioner,
+            &self.wayland_queue,
+            surface,
+            &*xdg_shell,
+        )
+        .map_err(|e| anyhow::anyhow!("failed to create an xdg_popup: {e}"))?;
+        if let SurfaceRole::Layer(layer) = &parent.wayland_window {
+            layer.get_popup(popup.xdg_popup());
+        }
+        Ok(popup)
     }
 }
 
-/// The state associated with each window, stored in [`WaylandState`]
-pub(super) struct WaylandWindowState {
-    pub handler: Box<dyn WinHandler>,
-    // TODO: Rc<RefCell>?
-    properties: Rc<RefCell<WindowProperties>>,
-    text_input_seat: Option<SeatName>,
-    loop_sender: channel::Sender<ActiveAction>,
+/// The role of a window's surface, which decides how the compositor places it
+enum SurfaceRole {
+    Toplevel(Window),
+    /// Created with `WindowBuilderExt::set_layer_shell`
+    Layer(LayerSurface),
+    /// Created with `WindowBuilder::set_popup`
+    Popup(Popup),
+}
+
+impl SurfaceRole {
+    /// The xdg toplevel, which most operations on windows need
+    fn toplevel(&self) -> Option<&Window> {
+        match self {
+            SurfaceRole::Toplevel(window) => Some(window),
+            SurfaceRole::Layer(_) | SurfaceRole::Popup(_) => None,
+        }
+    }
+
+    /// The xdg surface which popups of this window are attached to
+    fn xdg_surface(&self) -> Option<&XdgSurface> {
+        match self {
+            SurfaceRole::Toplevel(window) => Some(window.xdg_surface()),
+            SurfaceRole::Popup(popup) => Some(popup.xdg_surface()),
+            // Layer surfaces adopt their popups with `get_popup` instead
+            SurfaceRole::Layer(_) => None,
+        }
+    }
+}
+
+impl WaylandSurface for SurfaceRole {
+    fn wl_surface(&self) -> &WlSurface {
+        match self {
+            SurfaceRole::Toplevel(window) => window.wl_surface(),
+            SurfaceRole::Layer(layer) => layer.wl_surface(),
+            SurfaceRole::Popup(popup) => popup.wl_surface(),
+        }
+    }
+}
+
+fn positioner_anchor(anchor: PopupAnchor) -> xdg_positioner::Anchor {
+    match anchor {
+        PopupAnchor::Center => xdg_positioner::Anchor::None,
+        PopupAnchor::Top => xdg_positioner::Anchor::Top,
+        PopupAnchor::Bottom => xdg_positioner::Anchor::Bottom,
+        PopupAnchor::Left => xdg_positioner::Anchor::Left,
+        PopupAnchor::Right => xdg_positioner::Anchor::Right,
+        PopupAnchor::TopLeft => xdg_positioner::Anchor::TopLeft,
+        PopupAnchor::TopRight => xdg_positioner::Anchor::TopRight,
+        PopupAnchor::BottomLeft => xdg_positioner::Anchor::BottomLeft,
+        PopupAnchor::BottomRight => xdg_positioner::Anchor::BottomRight,
+    }
+}
+
+fn positioner_gravity(gravity: PopupAnchor) -> xdg_positioner::Gravity {
+    match gravity {
+        PopupAnchor::Center => xdg_positioner::Gravity::None,
+        PopupAnchor::Top => xdg_positioner::Gravity::Top,
+        PopupAnchor::Bottom => xdg_positioner::Gravity::Bottom,
+        PopupAnchor::Left => xdg_positioner::Gravity::Left,
+        PopupAnchor::Right => xdg_positioner::Gravity::Right,
+        PopupAnchor::TopLeft => xdg_positioner::Gravity::TopLeft,
+        PopupAnchor::TopRight => xdg_positioner::Gravity::TopRight,
+        PopupAnchor::BottomLeft => xdg_positioner::Gravity::BottomLeft,
+        PopupAnchor::BottomRight => xdg_positioner::Gravity::BottomRight,
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+// TODO: According to https://github.com/linebender/druid/pull/2033, this should not be
+// synced with the ID of the surface
+pub(super) struct WindowId(ObjectId);
+
+impl WindowId {
+    pub fn new(surface: &impl WaylandSurface) -> Self {
+        Self::of_surface(surface.wl_surface())
+    }
+    pub fn of_surface(surface: &WlSurface) -> Self {
+        Self(surface.id())
+    }
+}
+
+/// The state associated with each window, stored in [`WaylandState`]
+pub(super) struct WaylandWindowState {
+    pub handler: Box<dyn WinHandler>,
+    // TODO: Rc<RefCell>?
+    properties: Rc<RefCell<WindowProperties>>,
+    text_input_seat: Option<SeatName>,
+    loop_sender: channel::Sender<ActiveAction>,
+    loop_handle: LoopHandle<'static, WaylandState>,
+    frame_throttle: FrameThrottle,
+    /// Created on the first call to `present_pixels`
+    shm_pool: Option<SlotPool>,
+    /// The buffer most recently attached by `present_pixels`, kept alive until it is replaced
+    shm_buffer: Option<Buffer>,
+    /// The decorations we draw when the compositor leaves them to us, but the application
+    /// doesn't draw its own
+    frame: Option<AdwaitaFrame<WaylandState>>,
 }
 
 struct WindowProperties {
@@ -573,11 +1283,16 @@ struct WindowProperties {
     // so, for example an application using wgpu could have the surface configured to be a different size
     current_size: Size,
     current_scale: Scale,
+    /// Set if the compositor supports fractional scaling, in which case we ignore the integer
+    /// scale of the surface
+    surface_scale: Option<SurfaceScale>,
     // The underlying wayland Window
     // The way to close this Window is to drop the handle
     // We make this the only handle, so we can definitely drop it
-    wayland_window: Window,
+    wayland_window: SurfaceRole,
     wayland_queue: QueueHandle<WaylandState>,
+    presentation: Option<WpPresentation>,
+    frame_timings: FrameTimingsCache,
 
     /// Wayland requires frame (throttling) callbacks be requested *before* running commit.
     /// However, user code controls when commit is called (generally through wgpu's
@@ -587,16 +1302,76 @@ struct WindowProperties {
     /// - Only action that hint if a request_anim_frame (or equivalent) was called
     /// - If there is no running hint, manually run this process when calling request_anim_frame
     will_repaint: bool,
+    /// Whether animation frames wait for `frame` callbacks. The compositor still decides when
+    /// our commits reach the screen either way
+    vsync: bool,
     /// Whether a `frame` callback has been skipped
     /// If this is false, and painting is requested, we need to manually run our own painting
     pending_frame_callback: bool,
+    /// When the `frame` callback in flight was requested
+    frame_requested_at: Option<Instant>,
+    /// Whether a timer is watching for a `frame` callback which never arrives
+    starvation_timer: bool,
+    /// The region invalidated since we last painted, in display points
+    invalid: Region,
+    /// Wayland doesn't tell us whether the window is visible. Instead, we infer that it is
+    /// occluded when the compositor stops sending `frame` callbacks
+    visibility: Visibility,
     // We can't draw before being configured
     configured: bool,
+    /// Whether the last configure had the resizing state, which is reported to the handler
+    interactive_resize: bool,
+    /// Whether a configure arrived during an interactive resize while a `frame` callback was
+    /// in flight, and waits for it
+    configure_pending: bool,
 
     focused_text_field: Option<TextFieldToken>,
+    /// Kiosk windows ignore close requests from the compositor
+    kiosk: bool,
+    /// Whether `present_pixels` may attach buffers to the surface, which is otherwise left to
+    /// the application
+    managed_surface: bool,
+    /// Popups which grab the pointer and keyboard when they are shown
+    popup_grab: bool,
+    title: String,
+    show_titlebar: bool,
+    /// Whether the application draws its own decorations when the compositor doesn't
+    prefer_client_side_decorations: bool,
+    /// The mode last reported to `WinHandler::decoration_mode_changed`
+    decoration_mode: Option<window::DecorationMode>,
+    /// Set while this window is shown as a sheet, with `WindowHandle::begin_sheet`
+    sheet_completion: Option<Box<dyn FnOnce(window::ModalResponse)>>,
+    /// The window this is shown as a sheet of, which closes this first when it closes
+    sheet_parent: Option<WindowId>,
+    /// The size, scale and BGRX pixels of the buffer `present_pixels` last attached, for
+    /// `capture`
+    presented: Option<(u32, u32, Scale, Rc<[u8]>)>,
+    /// Created once assistive technology is running, which the application watches for
+    #[cfg(feature = "accesskit")]
+    accesskit_adapter: Option<AccessKitAdapter>,
+    /// Whether the window has the keyboard focus, which a new adapter is told
+    #[cfg(feature = "accesskit")]
+    focused: bool,
 }
 
 impl WindowProperties {
+    /// The size in pixels of buffers which fill `size`, in display points
+    ///
+    /// At fractional scales, the compositor expects this to be rounded.
+    fn size_px(&self, size: Size) -> Size {
+        size.to_px(self.current_scale).round()
+    }
+
+    /// The decoration mode we ask the compositor for
+    fn requested_decoration_mode(&self) -> DecorationMode {
+        // Hiding the titlebar is only possible if we draw the decorations ourselves
+        if self.prefer_client_side_decorations || !self.show_titlebar {
+            DecorationMode::Client
+        } else {
+            DecorationMode::Server
+        }
+    }
+
     /// Calculate the size that this window should be, given the current configuration
     /// Called in response to a configure event or a resize being requested
     ///
@@ -644,6 +1419,9 @@ impl WindowProperties {
     }
 }
 
+/// How long a `frame` callback can be outstanding before we consider the window occluded
+const FRAME_STARVATION_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// The context do_paint is called in
 enum PaintContext {
     /// Painting occurs during a `frame` callback and finished, we know that there are no more frame callbacks
@@ -652,7 +1430,209 @@ enum PaintContext {
     Configure,
 }
 
+/// Runs AccessKit's actions on the event loop, which they are requested from outside of
+#[cfg(feature = "accesskit")]
+struct AccessKitActionHandler {
+    app: Mutex<application::AppHandle>,
+    window: WindowId,
+}
+
+#[cfg(feature = "accesskit")]
+impl accesskit::ActionHandler for AccessKitActionHandler {
+    fn do_action(&self, request: accesskit::ActionRequest) {
+        let window = self.window.clone();
+        self.app.lock().unwrap().run_on_state(move |state| {
+            if let Some(window) = state.windows.get_mut(&window) {
+                window.handler.accesskit_action(request);
+            }
+        });
+    }
+}
+
 impl WaylandWindowState {
+    /// Create the window's AccessKit adapter, unless it already has one
+    ///
+    /// This is only done once assistive technology is running
+    #[cfg(feature = "accesskit")]
+    pub(super) fn init_accesskit(&mut self) {
+        if self.properties.borrow().accesskit_adapter.is_some() {
+            return;
+        }
+        let action_handler = AccessKitActionHandler {
+            app: Mutex::new(application::AppHandle::new(self.loop_sender.clone())),
+            window: self.properties.borrow().window_id.clone(),
+        };
+        let adapter = linux::accesskit::new_adapter(
+            || Some(self.handler.accesskit_tree()),
+            Box::new(action_handler),
+        );
+        if let Some(adapter) = adapter {
+            let mut props = self.properties.borrow_mut();
+            // The compositor doesn't tell us where the window is, so AT-SPI can't be told its
+            // bounds
+            adapter.update_window_focus_state(props.focused);
+            props.accesskit_adapter = Some(adapter);
+        }
+    }
+
+    /// Tell AT-SPI whether the window has the keyboard focus, which is when it is active
+    #[cfg(feature = "accesskit")]
+    pub(super) fn accesskit_focus_changed(&mut self, focused: bool, accessibility_enabled: bool) {
+        let has_adapter = {
+            let mut props = self.properties.borrow_mut();
+            props.focused = focused;
+            if let Some(adapter) = props.accesskit_adapter.as_ref() {
+                adapter.update_window_focus_state(focused);
+            }
+            props.accesskit_adapter.is_some()
+        };
+        // The application gives every window an adapter once assistive technology starts, but
+        // it may not have gotten to this one yet
+        if !has_adapter && focused && accessibility_enabled {
+            self.init_accesskit();
+        }
+    }
+
+    /// Drop the window, which destroys its surface, then tell the handler
+    ///
+    /// This is the last call the handler gets, and its handles are already dead by then
+    pub(super) fn destroy(self) {
+        let WaylandWindowState {
+            mut handler,
+            properties,
+            ..
+        } = self;
+        drop(properties);
+        handler.destroy();
+    }
+
+    /// Tell the handler about a new size, in display points
+    ///
+    /// The surface only changes size when a buffer of the new size is committed, which
+    /// doesn't happen until the handler paints. This means `surface_resized` can run immediately
+    fn resized(&mut self, size: Size) {
+        let size_px = {
+            let props = self.properties.borrow();
+            if let Some(surface_scale) = &props.surface_scale {
+                surface_scale.set_size(size);
+            }
+            props.size_px(size)
+        };
+        self.handler.size(size);
+        self.handler.surface_resized(size_px);
+    }
+
+    /// Follow a new scale from the compositor, such as when the window moves to another output
+    ///
+    /// The window keeps its size in display points, which is what the compositor configures, so
+    /// only its size in pixels changes.
+    pub(super) fn set_scale(&mut self, factor: f64) {
+        let scale = Scale::new(factor, factor);
+        let size = {
+            let mut props = self.properties.borrow_mut();
+            if props.current_scale == scale {
+                return;
+            }
+            props.current_scale = scale;
+            props.current_size
+        };
+        if let Some(frame) = &mut self.frame {
+            frame.set_scaling_factor(factor);
+        }
+        self.handler.scale(scale);
+        self.resized(size);
+        self.update_window_geometry();
+        // The buffer we last committed is now the wrong size, so it gets stretched until we paint
+        self.do_paint(true, PaintContext::Requested);
+    }
+
+    /// Record what the compositor told us about `frame`, and pass it on to the handler
+    pub(super) fn frame_feedback(
+        &mut self,
+        frame: u64,
+        presented: Option<Instant>,
+        refresh: Option<Duration>,
+    ) {
+        let frame_timings = self.properties.borrow().frame_timings.clone();
+        frame_timings.frame_feedback(frame, presented, refresh);
+        for info in frame_timings.take_feedback() {
+            self.handler.frame_presented(info);
+        }
+    }
+
+    /// Unmaps the surface by attaching a null buffer.
+    ///
+    /// The next commit maps it again, after which we must wait for a new configure before drawing.
+    fn hide(&mut self) {
+        {
+            let mut props = self.properties.borrow_mut();
+            let surface = props.wayland_window.wl_surface().clone();
+            surface.attach(None, 0, 0);
+            surface.commit();
+            props.configured = false;
+        }
+        self.set_visibility(Visibility::Hidden);
+    }
+
+    fn set_visibility(&mut self, visibility: Visibility) {
+        let changed = {
+            let mut props = self.properties.borrow_mut();
+            std::mem::replace(&mut props.visibility, visibility) != visibility
+        };
+        if changed {
+            self.handler.visibility_changed(visibility);
+        }
+    }
+
+    /// Resize the window for the latest configure, and paint it at that size
+    fn apply_configure(&mut self) {
+        let display_size = {
+            let mut props = self.properties.borrow_mut();
+            props.configure_pending = false;
+            props.configured = true;
+            props.calculate_size()
+        };
+        // Client toolkit has already acked this configure, but the ack only takes effect on our
+        // next commit. That happens when the handler paints, after it has seen the new size
+        self.resized(display_size);
+        self.update_window_geometry();
+        self.do_paint(true, PaintContext::Configure);
+    }
+
+    /// Reports the window as occluded if the `frame` callback in flight doesn't arrive in time
+    ///
+    /// The timer keeps itself running for as long as we keep requesting frames
+    fn watch_frame_starvation(&self, window_id: WindowId) {
+        let timer = Timer::from_duration(FRAME_STARVATION_TIMEOUT);
+        let res = self
+            .loop_handle
+            .insert_source(timer, move |_, _, state: &mut WaylandState| {
+                let Some(window) = state.windows.get_mut(&window_id) else {
+                    return TimeoutAction::Drop;
+                };
+                let requested_at = window.properties.borrow().frame_requested_at;
+                match requested_at {
+                    Some(at) if at.elapsed() >= FRAME_STARVATION_TIMEOUT => {
+                        window.properties.borrow_mut().starvation_timer = false;
+                        window.set_visibility(Visibility::Occluded);
+                        // A configure waiting for the `frame` callback mustn't wait forever
+                        if window.properties.borrow().configure_pending {
+                            window.apply_configure();
+                        }
+                        TimeoutAction::Drop
+                    }
+                    Some(at) => TimeoutAction::ToInstant(at + FRAME_STARVATION_TIMEOUT),
+                    None => {
+                        window.properties.borrow_mut().starvation_timer = false;
+                        TimeoutAction::Drop
+                    }
+                }
+            });
+        if let Err(e) = res {
+            tracing::warn!("failed to watch for occlusion: {e}");
+        }
+    }
+
     fn do_paint(&mut self, force: bool, context: PaintContext) {
         {
             let mut props = self.properties.borrow_mut();
@@ -662,7 +1642,30 @@ impl WaylandWindowState {
             if !props.configured || (!props.will_repaint && !force) {
                 return;
             }
+            // Only animation frames are throttled, so keep `will_repaint` set until we paint
+            if !force {
+                match self.frame_throttle.schedule() {
+                    FrameSchedule::Now => (),
+                    FrameSchedule::After(delay) => {
+                        self.delay_frame(props.window_id.clone(), delay);
+                        return;
+                    }
+                    FrameSchedule::Scheduled => return,
+                }
+            }
             props.will_repaint = false;
+            // Like frame callbacks, feedback has to be requested before the handler commits
+            let frame = props.frame_timings.paint_started();
+            if let Some(presentation) = &props.presentation {
+                presentation.feedback(
+                    props.wayland_window.wl_surface(),
+                    &props.wayland_queue,
+                    FeedbackData {
+                        window_id: props.window_id.clone(),
+                        frame,
+                    },
+                );
+            }
             // If there is not a frame callback in flight, we request it here
             // This branch could be skipped e.g. on `configure`, which ignores frame throttling hints and
             // always paints eagerly, even if there is a frame callback running
@@ -671,23 +1674,269 @@ impl WaylandWindowState {
                 props.pending_frame_callback = true;
                 let surface = props.wayland_window.wl_surface();
                 surface.frame(&props.wayland_queue.clone(), surface.clone());
+                props.frame_requested_at = Some(Instant::now());
+                if !props.starvation_timer {
+                    props.starvation_timer = true;
+                    self.watch_frame_starvation(props.window_id.clone());
+                }
             }
         }
         self.handler.prepare_paint();
-        // TODO: Apply invalid properly
-        // When forcing, should mark the entire region as damaged
-        let mut region = Region::EMPTY;
-        {
-            let props = self.properties.borrow();
-            let size = props.current_size.to_dp(props.current_scale);
-            region.add_rect(Rect {
-                x0: 0.0,
-                y0: 0.0,
-                x1: size.width,
-                y1: size.height,
+        // Take the region after `prepare_paint`, which can invalidate more
+        let region = {
+            let mut props = self.properties.borrow_mut();
+            if force {
+                // We're painting because of the compositor, so everything is damaged
+                let rect = props.current_size.to_rect();
+                props.invalid.set_rect(rect);
+            }
+            std::mem::replace(&mut props.invalid, Region::EMPTY)
+        };
+        self.handler.paint(&region);
+        self.frame_throttle.frame_painted();
+    }
+
+    /// Paint the next animation frame after `delay`, instead of when the compositor asks
+    fn delay_frame(&self, window_id: WindowId, delay: Duration) {
+        let timer = Timer::from_duration(delay);
+        let res = self
+            .loop_handle
+            .insert_source(timer, move |_, _, state: &mut WaylandState| {
+                if let Some(window) = state.windows.get_mut(&window_id) {
+                    window.frame_throttle.delayed_frame_due();
+                    window.do_paint(false, PaintContext::Requested);
+                }
+                TimeoutAction::Drop
             });
+        if let Err(e) = res {
+            tracing::warn!("failed to delay animation frame: {e}");
+            self.frame_throttle.delayed_frame_due();
+        }
+    }
+
+    /// Copy `pixels`, which are in BGRX order, into shared memory and attach them to the surface
+    fn present_pixels(&mut self, shm: &Shm, pixels: Rc<[u8]>, width: u32, height: u32) {
+        let pool = match &mut self.shm_pool {
+            Some(pool) => pool,
+            None => match SlotPool::new(pixels.len(), shm) {
+                Ok(pool) => self.shm_pool.insert(pool),
+                Err(e) => {
+                    tracing::error!("Failed to create shm pool: {e}");
+                    return;
+                }
+            },
+        };
+        // The pool grows as needed, and won't hand out memory the compositor is still reading
+        let (buffer, canvas) = match pool.create_buffer(
+            width as i32,
+            height as i32,
+            (width as usize * PixelFormat::BYTES_PER_PIXEL) as i32,
+            protocol::wl_shm::Format::Xrgb8888,
+        ) {
+            Ok(it) => it,
+            Err(e) => {
+                tracing::error!("Failed to create shm buffer: {e}");
+                return;
+            }
+        };
+        canvas.copy_from_slice(&pixels);
+        let mut props = self.properties.borrow_mut();
+        let surface = props.wayland_window.wl_surface();
+        if props.surface_scale.is_none() {
+            // Without fractional scaling our scale is always integral. Otherwise the viewport
+            // does the scaling, and the buffer scale stays at 1
+            surface.set_buffer_scale(props.current_scale.x() as i32);
+        }
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        if let Err(e) = buffer.attach_to(surface) {
+            tracing::error!("Failed to attach shm buffer: {e}");
+            return;
+        }
+        surface.commit();
+        props.presented = Some((width, height, props.current_scale, pixels));
+        self.shm_buffer = Some(buffer);
+    }
+
+    /// Follow the decoration mode the compositor chose in `configure`
+    ///
+    /// When the compositor leaves the decorations to us and the application doesn't draw its
+    /// own, we draw an Adwaita style frame with the window's title, in the system's light or
+    /// dark theme.
+    fn configure_decorations(
+        &mut self,
+        configure: &WindowConfigure,
+        shm: &Shm,
+        subcompositor: Option<&Arc<SubcompositorState>>,
+        qh: &QueueHandle<WaylandState>,
+    ) {
+        let mode = match configure.decoration_mode {
+            DecorationMode::Client => window::DecorationMode::Client,
+            DecorationMode::Server => window::DecorationMode::Server,
+        };
+        let changed = {
+            let mut props = self.properties.borrow_mut();
+            let wants_frame = mode == window::DecorationMode::Client
+                && !props.prefer_client_side_decorations
+                && !props.kiosk;
+            if !wants_frame {
+                self.frame = None;
+            } else if self.frame.is_none() {
+                let frame = subcompositor.map(|subcompositor| {
+                    AdwaitaFrame::new(
+                        &props.wayland_window,
+                        shm,
+                        subcompositor.clone(),
+                        qh.clone(),
+                        FrameConfig::auto(),
+                    )
+                });
+                match frame {
+                    Some(Ok(mut frame)) => {
+                        frame.set_title(props.title.clone());
+                        frame.set_scaling_factor(props.current_scale.x());
+                        self.frame = Some(frame);
+                    }
+                    Some(Err(e)) => tracing::warn!("Failed to create decorations: {e:?}"),
+                    None => tracing::warn!(
+                        "The compositor doesn't support subsurfaces, so we can't draw decorations"
+                    ),
+                }
+            }
+            if let Some(frame) = &mut self.frame {
+                frame.set_hidden(!props.show_titlebar);
+                frame.update_state(configure.state);
+                frame.update_wm_capabilities(configure.capabilities);
+            }
+            props.decoration_mode.replace(mode) != Some(mode)
+        };
+        if changed {
+            self.handler.decoration_mode_changed(mode);
+        }
+    }
+
+    /// The part of a configured size which is left for the window's content, once our
+    /// decorations have been drawn around it
+    fn content_size(
+        &self,
+        size: (Option<NonZeroU32>, Option<NonZeroU32>),
+    ) -> (Option<NonZeroU32>, Option<NonZeroU32>) {
+        match (&self.frame, size) {
+            (Some(frame), (Some(width), Some(height))) => frame.subtract_borders(width, height),
+            _ => size,
+        }
+    }
+
+    /// Fit our decorations around the window's content, and tell the compositor which part of
+    /// the surfaces is the window itself
+    fn update_window_geometry(&mut self) {
+        let props = self.properties.borrow();
+        let width = props.current_size.width.round() as u32;
+        let height = props.current_size.height.round() as u32;
+        let (Some(width), Some(height)) = (NonZeroU32::new(width), NonZeroU32::new(height)) else {
+            return;
+        };
+        let (x, y, outer_width, outer_height) = match &mut self.frame {
+            Some(frame) => {
+                frame.resize(width, height);
+                let (x, y) = frame.location();
+                let (outer_width, outer_height) = frame.add_borders(width.get(), height.get());
+                if frame.is_dirty() {
+                    frame.draw();
+                }
+                (x, y, outer_width, outer_height)
+            }
+            None => (0, 0, width.get(), height.get()),
+        };
+        let Some(toplevel) = props.wayland_window.toplevel() else {
+            return;
+        };
+        toplevel
+            .xdg_surface()
+            .set_window_geometry(x, y, outer_width as i32, outer_height as i32);
+    }
+
+    /// Show or hide our decorations, following `show_titlebar`
+    fn decorations_changed(&mut self) {
+        let Some(frame) = &mut self.frame else { return };
+        frame.set_hidden(!self.properties.borrow().show_titlebar);
+        self.update_window_geometry();
+        self.properties
+            .borrow()
+            .wayland_window
+            .wl_surface()
+            .commit();
+    }
+
+    /// Tell our decorations that the pointer is at `(x, y)` on `surface`
+    ///
+    /// Returns the name of the cursor to show if the pointer is over the decorations, such as
+    /// a resize cursor on their borders.
+    pub(super) fn frame_pointer_moved(
+        &mut self,
+        surface: &ObjectId,
+        x: f64,
+        y: f64,
+    ) -> Option<&str> {
+        self.frame.as_mut()?.click_point_moved(surface, x, y)
+    }
+
+    pub(super) fn frame_pointer_left(&mut self) {
+        if let Some(frame) = &mut self.frame {
+            frame.click_point_left();
+        }
+    }
+
+    /// Run whatever a click on our decorations asks for
+    pub(super) fn frame_clicked(
+        &mut self,
+        click: FrameClick,
+        pressed: bool,
+        seat: &WlSeat,
+        serial: u32,
+    ) {
+        let Some(frame) = &mut self.frame else { return };
+        let Some(action) = frame.on_click(click, pressed) else {
+            return;
+        };
+        let props = self.properties.borrow();
+        let Some(wayland_window) = props.wayland_window.toplevel() else {
+            return;
+        };
+        match action {
+            FrameAction::Minimize => wayland_window.set_minimized(),
+            FrameAction::Maximize => wayland_window.set_maximized(),
+            FrameAction::UnMaximize => wayland_window.unset_maximized(),
+            // Kiosk windows never get a frame, so there's nothing to ignore
+            FrameAction::Close => {
+                drop(props);
+                self.handler.request_close();
+            }
+            FrameAction::Move => wayland_window.move_(seat, serial),
+            FrameAction::Resize(edge) => wayland_window.resize(seat, serial, edge),
+            FrameAction::ShowMenu(x, y) => wayland_window.show_window_menu(seat, serial, (x, y)),
+            _ => (),
+        }
+    }
+
+    /// Show the new title in our decorations
+    fn title_changed(&mut self) {
+        let Some(frame) = &mut self.frame else { return };
+        frame.set_title(self.properties.borrow().title.clone());
+        self.redraw_frame();
+    }
+
+    /// Redraw our decorations if they have changed, for example because they are hovered
+    pub(super) fn redraw_frame(&mut self) {
+        let Some(frame) = &mut self.frame else { return };
+        if frame.is_dirty() {
+            frame.draw();
+            // The frame's subsurfaces are only updated when our surface is committed
+            self.properties
+                .borrow()
+                .wayland_window
+                .wl_surface()
+                .commit();
         }
-        self.handler.paint(&region);
     }
 
     pub(super) fn handle_key_event(&mut self, event: KeyEvent) {
@@ -751,6 +2000,14 @@ delegate_xdg_shell!(WaylandState);
 delegate_xdg_window!(WaylandState);
 
 delegate_compositor!(WaylandState);
+delegate_subcompositor!(WaylandState);
+delegate_shm!(WaylandState);
+
+impl ShmHandler for WaylandState {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
 
 impl CompositorHandler for WaylandState {
     fn scale_factor_changed(
@@ -758,28 +2015,18 @@ impl CompositorHandler for WaylandState {
         _: &Connection,
         _: &QueueHandle<Self>,
         surface: &protocol::wl_surface::WlSurface,
-        // TODO: Support the fractional-scaling extension instead
-        // This requires an update in client-toolkit and wayland-protocols
         new_factor: i32,
     ) {
-        let window = self.windows.get_mut(&WindowId::of_surface(surface));
-        let window = window.expect("Should only get events for real windows");
-        let factor = f64::from(new_factor);
-        let scale = Scale::new(factor, factor);
-        let new_size;
-        {
-            let mut props = window.properties.borrow_mut();
-            // TODO: Effectively, we need to re-evaluate the size calculation
-            // That means we need to cache the WindowConfigure or (mostly) equivalent
-            let cur_size_raw: Size = props.current_size.to_px(props.current_scale);
-            new_size = cur_size_raw.to_dp(scale);
-            props.current_scale = scale;
-            props.current_size = new_size;
-            // avoid locking the properties into user code
+        let _span = common_util::event_span("wl_surface.scale");
+        // The surfaces of our decorations also get these events, but follow their window
+        let Some(window) = self.windows.get_mut(&WindowId::of_surface(surface)) else {
+            return;
+        };
+        if window.properties.borrow().surface_scale.is_some() {
+            // `wp_fractional_scale_v1` tells us the exact scale instead
+            return;
         }
-        window.handler.scale(scale);
-        window.handler.size(new_size);
-        // TODO: Do we repaint here?
+        window.set_scale(f64::from(new_factor));
     }
 
     fn frame(
@@ -789,8 +2036,22 @@ impl CompositorHandler for WaylandState {
         surface: &protocol::wl_surface::WlSurface,
         _time: u32,
     ) {
+        let _span = common_util::event_span("wl_callback.done");
         let Some(window) = self.windows.get_mut(&WindowId::of_surface(surface)) else { return };
-        window.do_paint(false, PaintContext::Frame);
+        let configure_pending = {
+            let mut props = window.properties.borrow_mut();
+            props.frame_requested_at = None;
+            props.configure_pending
+        };
+        // The compositor only sends frame callbacks when it thinks we're visible
+        window.set_visibility(Visibility::Visible);
+        if configure_pending {
+            // Painting the new size covers any animation frame which was requested
+            window.properties.borrow_mut().pending_frame_callback = false;
+            window.apply_configure();
+        } else {
+            window.do_paint(false, PaintContext::Frame);
+        }
     }
 }
 
@@ -801,7 +2062,11 @@ impl WindowHandler for WaylandState {
         _: &QueueHandle<Self>,
         wl_window: &smithay_client_toolkit::shell::xdg::window::Window,
     ) {
+        let _span = common_util::event_span("xdg_toplevel.close");
         let Some(window)= self.windows.get_mut(&WindowId::new(wl_window)) else { return };
+        if window.properties.borrow().kiosk {
+            return;
+        }
         window.handler.request_close();
     }
 
@@ -810,9 +2075,10 @@ impl WindowHandler for WaylandState {
         _: &Connection,
         _: &QueueHandle<Self>,
         window: &smithay_client_toolkit::shell::xdg::window::Window,
-        configure: smithay_client_toolkit::shell::xdg::window::WindowConfigure,
+        mut configure: smithay_client_toolkit::shell::xdg::window::WindowConfigure,
         _: u32,
     ) {
+        let _span = common_util::event_span("xdg_toplevel.configure");
         let window = if let Some(window) = self.windows.get_mut(&WindowId::new(window)) {
             window
         } else {
@@ -820,19 +2086,130 @@ impl WindowHandler for WaylandState {
             tracing::warn!("Recieved configure event for unknown window");
             return;
         };
+        window.configure_decorations(
+            &configure,
+            &self.shm,
+            self.subcompositor.as_ref(),
+            &self.wayland_queue,
+        );
+        // The configured size includes our decorations, which the handler doesn't draw
+        configure.new_size = window.content_size(configure.new_size);
         // TODO: Actually use the suggestions from requested_size
-        let display_size;
+        let resizing = configure.is_resizing();
+        let (began, ended, defer);
         {
             let mut props = window.properties.borrow_mut();
             props.configure = Some(configure);
-            display_size = props.calculate_size();
+            began = resizing && !props.interactive_resize;
+            ended = !resizing && props.interactive_resize;
+            props.interactive_resize = resizing;
+            // While the user drags an edge, the compositor can send a configure for every pointer
+            // motion. Painting them all would only queue up stale sizes, so those which arrive
+            // while a frame is in flight wait for its `frame` callback, and the latest is painted.
+            // Only the last configure acked before a commit counts, so the others are dropped
+            defer = resizing
+                && props.configured
+                && props.pending_frame_callback
+                && props.visibility == Visibility::Visible;
+            props.configure_pending = defer;
+        };
+        if began {
+            window.handler.interactive_resize_began();
+        }
+        if !defer {
+            window.apply_configure();
+        }
+        if ended {
+            window.handler.interactive_resize_ended();
+        }
+    }
+}
+
+delegate_layer!(WaylandState);
+
+impl LayerShellHandler for WaylandState {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, layer: &LayerSurface) {
+        let _span = common_util::event_span("zwlr_layer_surface_v1.closed");
+        let Some(window) = self.windows.get_mut(&WindowId::new(layer)) else {
+            return;
+        };
+        // The compositor won't show the surface again, for example because its output is gone
+        window.handler.request_close();
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _: u32,
+    ) {
+        let _span = common_util::event_span("zwlr_layer_surface_v1.configure");
+        let Some(window) = self.windows.get_mut(&WindowId::new(layer)) else {
+            tracing::warn!("Recieved configure event for unknown layer surface");
+            return;
+        };
+        let display_size = {
+            let mut props = window.properties.borrow_mut();
+            // The compositor leaves the size along axes we aren't stretched across to us
+            let requested_size = props.requested_size.unwrap_or(props.current_size);
+            let (width, height) = configure.new_size;
+            props.current_size = Size::new(
+                if width == 0 {
+                    requested_size.width
+                } else {
+                    width.into()
+                },
+                if height == 0 {
+                    requested_size.height
+                } else {
+                    height.into()
+                },
+            );
             props.configured = true;
+            props.current_size
         };
-        window.handler.size(display_size);
+        window.resized(display_size);
         window.do_paint(true, PaintContext::Configure);
     }
 }
 
+delegate_xdg_popup!(WaylandState);
+
+impl PopupHandler for WaylandState {
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        popup: &Popup,
+        configure: PopupConfigure,
+    ) {
+        let _span = common_util::event_span("xdg_popup.configure");
+        let Some(window) = self.windows.get_mut(&WindowId::new(popup)) else {
+            tracing::warn!("Recieved configure event for unknown popup");
+            return;
+        };
+        // The compositor may have shrunk the popup to keep it on screen
+        let display_size = {
+            let mut props = window.properties.borrow_mut();
+            props.current_size = Size::new(configure.width.into(), configure.height.into());
+            props.configured = true;
+            props.current_size
+        };
+        window.resized(display_size);
+        window.do_paint(true, PaintContext::Configure);
+    }
+
+    fn done(&mut self, _: &Connection, _: &QueueHandle<Self>, popup: &Popup) {
+        let _span = common_util::event_span("xdg_popup.popup_done");
+        let Some(window) = self.windows.get_mut(&WindowId::new(popup)) else {
+            return;
+        };
+        window.handler.popup_dismissed();
+    }
+}
+
 pub(super) enum WindowAction {
     /// Change the window size, based on `requested_size`
     ///
@@ -843,6 +2220,25 @@ pub(super) enum WindowAction {
     Create(WaylandWindowState, WindowHandle),
     AnimationRequested,
     TextField(TextFieldChange),
+    /// Present a buffer of BGRX pixels, which must match the size of the window
+    PresentPixels {
+        pixels: Rc<[u8]>,
+        width: u32,
+        height: u32,
+    },
+    /// Show or hide the fallback decorations, after `show_titlebar` was changed
+    DecorationsChanged,
+    /// Show the new title in the fallback decorations, after `set_title`
+    TitleChanged,
+    /// Unmap the surface, until `show` is called again
+    Hide,
+    /// Grab the seat which most recently received input for a popup, then show it
+    ShowGrabbingPopup,
+    /// Call the handler's `timer` with `token` once `deadline` has passed
+    Timer {
+        token: TimerToken,
+        deadline: Instant,
+    },
 }
 
 impl WindowAction {
@@ -855,28 +2251,63 @@ impl WindowAction {
                     props.calculate_size()
                 };
                 // TODO: Ensure we follow the rules laid out by the compositor in `configure`
-                window.handler.size(size);
+                window.resized(size);
+                window.update_window_geometry();
                 // Force repainting now that the size has changed.
                 // TODO: Should this only happen if the size is actually different?
                 window.do_paint(true, PaintContext::Requested);
             }
             WindowAction::Close => {
+                // A sheet of the window is ended and closed before it
+                let sheets = state
+                    .windows
+                    .iter()
+                    .filter(|(_, window)| {
+                        window.properties.borrow().sheet_parent.as_ref() == Some(&window_id)
+                    })
+                    .map(|(id, _)| id.clone())
+                    .collect::<Vec<_>>();
+                for sheet in sheets {
+                    WindowAction::Close.run(state, sheet);
+                }
                 // Remove the window from tracking
-                let Some(_) = state.windows.remove(&window_id) else {
+                let Some(closed) = state.windows.remove(&window_id) else {
                     tracing::error!("Tried to close the same window twice");
                     return;
                 };
-                // We will drop the proper wayland window later when we Drop window.props
+                // A sheet which is closed is cancelled
+                let completion = closed.properties.borrow_mut().sheet_completion.take();
+                if let Some(completion) = completion {
+                    completion(window::ModalResponse::Cancel);
+                }
+                closed.destroy();
                 if state.windows.is_empty() {
+                    state.quitting.set(true);
                     state.loop_signal.stop();
                 }
             }
             WindowAction::Create(win_state, handle) => {
                 let res = state.windows.entry(window_id);
                 let win_state = res.or_insert(win_state);
+                {
+                    // The surface only enters an output once it's mapped, which is after its
+                    // first frame, so guess its scale rather than painting that frame at 1x
+                    let mut props = win_state.properties.borrow_mut();
+                    let guess = state
+                        .output_state
+                        .outputs()
+                        .filter_map(|output| state.output_state.info(&output))
+                        .map(|info| info.scale_factor)
+                        .max();
+                    if let (None, Some(guess)) = (&props.surface_scale, guess) {
+                        props.current_scale = Scale::new(guess.into(), guess.into());
+                    }
+                }
                 win_state.handler.connect(&crate::WindowHandle(
                     crate::backend::window::WindowHandle::Wayland(handle),
                 ));
+                // We haven't been shown yet
+                win_state.handler.visibility_changed(Visibility::Hidden);
             }
             WindowAction::AnimationRequested => {
                 let Some(window) = state.windows.get_mut(&window_id) else { return };
@@ -889,6 +2320,63 @@ impl WindowAction {
                 let Some(seat) = props.text_input_seat else {return;};
                 change.apply(props, &mut state.input_states, seat);
             }
+            WindowAction::PresentPixels {
+                pixels,
+                width,
+                height,
+            } => {
+                let Some(window) = state.windows.get_mut(&window_id) else { return };
+                window.present_pixels(&state.shm, pixels, width, height);
+            }
+            WindowAction::DecorationsChanged => {
+                let Some(window) = state.windows.get_mut(&window_id) else {
+                    return;
+                };
+                window.decorations_changed();
+            }
+            WindowAction::TitleChanged => {
+                let Some(window) = state.windows.get_mut(&window_id) else {
+                    return;
+                };
+                window.title_changed();
+            }
+            WindowAction::Hide => {
+                let Some(window) = state.windows.get_mut(&window_id) else {
+                    return;
+                };
+                window.hide();
+            }
+            WindowAction::ShowGrabbingPopup => {
+                let Some(window) = state.windows.get(&window_id) else {
+                    return;
+                };
+                let props = window.properties.borrow();
+                let SurfaceRole::Popup(popup) = &props.wayland_window else {
+                    return;
+                };
+                // The grab must be requested before the popup is first committed
+                match state.clipboard.borrow().latest_serial() {
+                    Some((seat, serial)) => popup.xdg_popup().grab(&seat, serial),
+                    None => tracing::warn!("no input event to grab the popup with"),
+                }
+                popup.wl_surface().commit();
+            }
+            WindowAction::Timer { token, deadline } => {
+                let Some(window) = state.windows.get(&window_id) else {
+                    return;
+                };
+                let timer = Timer::from_deadline(deadline);
+                let res = window.loop_handle.insert_source(timer, move |_, _, state| {
+                    // The window may have closed while the timer was pending
+                    if let Some(window) = state.windows.get_mut(&window_id) {
+                        window.handler.timer(token);
+                    }
+                    TimeoutAction::Drop
+                });
+                if let Err(e) = res {
+                    tracing::warn!("failed to start a timer: {e}");
+                }
+            }
         }
     }
 }
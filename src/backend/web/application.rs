@@ -14,7 +14,16 @@
 
 //! Web implementation of features at the application scope.
 
-use crate::application::AppHandler;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::application::{
+    A11yPrefs, AppHandler, Color, FontDescription, LoopMode, LoopStatus, PowerState, SystemColor,
+    SystemFonts, TextAntialiasing, TextRenderingPrefs, UiDirection, UiMetrics, WakeLockKind,
+};
+use crate::common_util;
+use crate::error::Error as ShellError;
+use crate::notification::{Notification, NotificationId};
 
 use super::clipboard::Clipboard;
 use super::error::Error;
@@ -29,8 +38,20 @@ impl Application {
 
     pub fn run(self, _handler: Option<Box<dyn AppHandler>>) {}
 
+    pub fn set_handler(&self, _handler: Option<Box<dyn AppHandler>>) {}
+
+    pub fn run_loop_iteration(&self, _mode: LoopMode) -> LoopStatus {
+        // The browser delivers events to us, so like `run` there is nothing to wait for.
+        tracing::warn!("Application::run_loop_iteration unimplemented for web.");
+        LoopStatus::Exit
+    }
+
     pub fn quit(&self) {}
 
+    pub fn set_app_id(&self, _app_id: &str) {
+        // Ignored
+    }
+
     pub fn clipboard(&self) -> Clipboard {
         Clipboard
     }
@@ -41,11 +62,115 @@ impl Application {
             .unwrap_or_else(|| "en-US".into())
     }
 
+    pub fn get_locales() -> Vec<String> {
+        let languages =
+            web_sys::window().map_or_else(js_sys::Array::new, |w| w.navigator().languages());
+        let mut locales: Vec<String> = languages
+            .iter()
+            .filter_map(|language| language.as_string())
+            .filter_map(|language| common_util::normalize_locale(&language))
+            .collect();
+        if locales.is_empty() {
+            locales.push(Self::get_locale());
+        }
+        locales
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        // The page's `dir` is the page author's choice, rather than the user's
+        common_util::ui_direction_for_locale(&Self::get_locales()[0])
+    }
+
     pub fn get_handle(&self) -> Option<AppHandle> {
         None
     }
+
+    pub fn power_state(&self) -> PowerState {
+        tracing::warn!("Application::power_state unimplemented for web.");
+        PowerState::default()
+    }
+
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        tracing::warn!("Application::accessibility_preferences unimplemented for web.");
+        A11yPrefs::default()
+    }
+
+    pub fn system_fonts(&self) -> SystemFonts {
+        tracing::warn!("Application::system_fonts unimplemented for web.");
+        // These are the defaults of every browser's style sheet.
+        let font = |family: &str| FontDescription {
+            family: family.into(),
+            size: 16.0,
+        };
+        SystemFonts {
+            ui: font("sans-serif"),
+            monospace: font("monospace"),
+            menu: font("sans-serif"),
+            title: font("sans-serif"),
+            small: font("sans-serif"),
+        }
+    }
+
+    /// Browsers zoom the whole page instead, which is reported as part of the scale.
+    pub fn text_scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    pub fn accent_color(&self) -> Option<Color> {
+        tracing::warn!("Application::accent_color unimplemented for web.");
+        None
+    }
+
+    pub fn system_color(&self, _color: SystemColor) -> Option<Color> {
+        tracing::warn!("Application::system_color unimplemented for web.");
+        None
+    }
+
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        tracing::warn!("Application::text_rendering_prefs unimplemented for web.");
+        TextRenderingPrefs {
+            antialiasing: TextAntialiasing::Grayscale,
+            hinting: false,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn ui_metrics(&self) -> UiMetrics {
+        tracing::warn!("Application::ui_metrics unimplemented for web.");
+        UiMetrics {
+            scrollbar_width: 15.0,
+            caret_width: 1.0,
+            caret_blink: Some(Duration::from_millis(500)),
+            drag_threshold: 4.0,
+            menu_show_delay: Duration::from_millis(400),
+        }
+    }
+
+    pub fn acquire_wake_lock(
+        &self,
+        _kind: WakeLockKind,
+        _reason: &str,
+    ) -> Result<SleepGuard, ShellError> {
+        Err(ShellError::Unsupported("acquire_wake_lock"))
+    }
+
+    pub fn add_recent_document(&self, _path: &Path) {
+        tracing::warn!("Application::add_recent_document unimplemented for web.");
+    }
+
+    pub fn send_notification(
+        &self,
+        _notification: &Notification,
+        _id: NotificationId,
+    ) -> Result<(), ShellError> {
+        Err(ShellError::Unsupported("send_notification"))
+    }
+
+    pub fn close_notification(&self, _id: NotificationId) {}
 }
 
+pub(crate) struct SleepGuard;
+
 #[derive(Clone)]
 pub(crate) struct AppHandle;
 
@@ -13,15 +13,45 @@
 // limitations under the License.
 
 //! Interactions with the browser pasteboard.
+//!
+//! With the `web_apis` feature, text goes through `navigator.clipboard`. Browsers only read it
+//! asynchronously, so `get_string` returns the text which was last seen on the clipboard, and
+//! starts a read which updates it for the next call.
+
+#[cfg(feature = "web_apis")]
+use std::cell::RefCell;
+
+#[cfg(feature = "web_apis")]
+use wasm_bindgen::prelude::*;
+#[cfg(feature = "web_apis")]
+use wasm_bindgen::JsCast;
 
 use crate::clipboard::{ClipboardFormat, FormatId};
 
+#[cfg(feature = "web_apis")]
+thread_local! {
+    /// The text last put on the clipboard, or read from it.
+    static CONTENTS: RefCell<Option<String>> = RefCell::new(None);
+}
+
 /// The browser clipboard.
 #[derive(Debug, Clone, Default)]
 pub struct Clipboard;
 
 impl Clipboard {
     /// Put a string onto the system clipboard.
+    #[cfg(feature = "web_apis")]
+    pub fn put_string(&mut self, s: impl AsRef<str>) {
+        let s = s.as_ref();
+        CONTENTS.with(|contents| *contents.borrow_mut() = Some(s.to_owned()));
+        let Some(promise) = call_clipboard("writeText", &JsValue::from_str(s)) else {
+            return;
+        };
+        on_settled(&promise, |_| {}, |e| log_failure("write", &e));
+    }
+
+    /// Put a string onto the system clipboard.
+    #[cfg(not(feature = "web_apis"))]
     pub fn put_string(&mut self, _s: impl AsRef<str>) {
         tracing::warn!("unimplemented");
     }
@@ -32,11 +62,37 @@ impl Clipboard {
     }
 
     /// Get a string from the system clipboard, if one is available.
+    #[cfg(feature = "web_apis")]
+    pub fn get_string(&self) -> Option<String> {
+        if let Some(promise) = call_clipboard("readText", &JsValue::UNDEFINED) {
+            on_settled(
+                &promise,
+                |text| {
+                    if let Some(text) = text.as_string() {
+                        CONTENTS.with(|contents| *contents.borrow_mut() = Some(text));
+                    }
+                },
+                |e| log_failure("read", &e),
+            );
+        }
+        CONTENTS.with(|contents| contents.borrow().clone())
+    }
+
+    /// Get a string from the system clipboard, if one is available.
+    #[cfg(not(feature = "web_apis"))]
     pub fn get_string(&self) -> Option<String> {
         tracing::warn!("unimplemented");
         None
     }
 
+    /// There is no primary selection here.
+    pub fn put_primary(&mut self, _s: impl AsRef<str>) {}
+
+    /// There is no primary selection here.
+    pub fn get_primary(&self) -> Option<String> {
+        None
+    }
+
     /// Given a list of supported clipboard types, returns the supported type which has
     /// highest priority on the system clipboard, or `None` if no types are supported.
     pub fn preferred_format(&self, _formats: &[FormatId]) -> Option<FormatId> {
@@ -58,3 +114,58 @@ impl Clipboard {
         Vec::new()
     }
 }
+
+/// Call `method` of `navigator.clipboard` with `arg`, returning its promise.
+///
+/// This goes through reflection, since web-sys only has the clipboard behind its unstable APIs.
+#[cfg(feature = "web_apis")]
+fn call_clipboard(method: &str, arg: &JsValue) -> Option<js_sys::Promise> {
+    let navigator = web_sys::window()?.navigator();
+    let clipboard = js_sys::Reflect::get(&navigator, &"clipboard".into()).ok()?;
+    if clipboard.is_undefined() {
+        // It is only there in secure contexts.
+        tracing::warn!("navigator.clipboard is unavailable, the page must be served over https");
+        return None;
+    }
+    let function = js_sys::Reflect::get(&clipboard, &method.into())
+        .ok()?
+        .dyn_into::<js_sys::Function>()
+        .ok()?;
+    match function.call1(&clipboard, arg) {
+        Ok(promise) => promise.dyn_into().ok(),
+        Err(e) => {
+            log_failure(method, &e);
+            None
+        }
+    }
+}
+
+/// Call `on_ok` or `on_err` with the result of `promise`, once it settles.
+#[cfg(feature = "web_apis")]
+fn on_settled(
+    promise: &js_sys::Promise,
+    on_ok: impl FnOnce(JsValue) + 'static,
+    on_err: impl FnOnce(JsValue) + 'static,
+) {
+    let on_ok: Closure<dyn FnMut(JsValue)> = Closure::once(on_ok);
+    let on_err: Closure<dyn FnMut(JsValue)> = Closure::once(on_err);
+    let _ = promise.then2(&on_ok, &on_err);
+    // Only one of them is called, so neither can free the other, and both are leaked.
+    on_ok.forget();
+    on_err.forget();
+}
+
+#[cfg(feature = "web_apis")]
+fn log_failure(action: &str, error: &JsValue) {
+    let name = js_sys::Reflect::get(error, &"name".into())
+        .ok()
+        .and_then(|name| name.as_string());
+    if name.as_deref() == Some("NotAllowedError") {
+        tracing::error!(
+            "the clipboard {} was denied, it needs permission and a user gesture",
+            action
+        );
+    } else {
+        tracing::error!("the clipboard {} failed: {:?}", action, error);
+    }
+}
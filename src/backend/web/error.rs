@@ -24,7 +24,6 @@ pub enum Error {
     JsCast,
     NoElementById(String),
     NoContext,
-    Unimplemented,
 }
 
 impl std::fmt::Display for Error {
@@ -36,7 +35,6 @@ impl std::fmt::Display for Error {
             Error::JsCast => write!(f, "JavaScript cast error"),
             Error::NoElementById(err) => write!(f, "get_element_by_id error: {}", err),
             Error::NoContext => write!(f, "Failed to get a draw context"),
-            Error::Unimplemented => write!(f, "Requested an unimplemented feature"),
         }
     }
 }
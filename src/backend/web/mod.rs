@@ -20,4 +20,5 @@ pub mod error;
 pub mod keycodes;
 pub mod menu;
 pub mod screen;
+pub mod text_input;
 pub mod window;
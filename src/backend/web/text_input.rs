@@ -0,0 +1,145 @@
+//! Text input through a hidden `<textarea>`.
+//!
+//! Browsers only send IME compositions, dead keys and their other text input to elements which
+//! can be edited, so a transparent textarea is kept focused while a text field is. The keys
+//! which edit or move through the text still arrive as `keydown` events and go through
+//! `simulate_input`, but characters are left for the browser to put in the textarea, and are
+//! taken from it by the `input` and composition events. The textarea is emptied after each of
+//! them, so the `InputHandler` always has the whole text.
+
+use std::cell::Cell;
+
+use wasm_bindgen::JsCast;
+
+use super::error::Error;
+use crate::kurbo::Rect;
+use crate::text::{InputHandler, Selection};
+
+pub(crate) struct TextInput {
+    textarea: web_sys::HtmlTextAreaElement,
+    /// Set between `compositionstart` and `compositionend`.
+    composing: Cell<bool>,
+}
+
+impl TextInput {
+    /// Create the textarea, over the top left corner of `canvas`.
+    pub(crate) fn new(
+        document: &web_sys::Document,
+        canvas: &web_sys::HtmlCanvasElement,
+    ) -> Result<TextInput, Error> {
+        let textarea = document
+            .create_element("textarea")?
+            .dyn_into::<web_sys::HtmlTextAreaElement>()
+            .map_err(|_| Error::JsCast)?;
+        for (name, value) in [
+            ("autocapitalize", "off"),
+            ("autocomplete", "off"),
+            ("autocorrect", "off"),
+            ("spellcheck", "false"),
+            ("tabindex", "-1"),
+            ("aria-hidden", "true"),
+        ] {
+            textarea.set_attribute(name, value)?;
+        }
+        // It has to stay in the layout for the browser to place the IME's candidate window next
+        // to it, so it is made invisible rather than hidden.
+        let style = textarea.style();
+        for (name, value) in [
+            ("position", "absolute"),
+            ("opacity", "0"),
+            ("pointer-events", "none"),
+            ("resize", "none"),
+            ("border", "0"),
+            ("padding", "0"),
+            ("width", "1px"),
+            ("height", "1em"),
+        ] {
+            style.set_property(name, value)?;
+        }
+        let parent = canvas.parent_node().ok_or(Error::NoDocument)?;
+        parent.insert_before(&textarea, canvas.next_sibling().as_ref())?;
+        let text_input = TextInput {
+            textarea,
+            composing: Cell::new(false),
+        };
+        text_input.move_to(canvas, Rect::ZERO);
+        Ok(text_input)
+    }
+
+    pub(crate) fn element(&self) -> &web_sys::HtmlTextAreaElement {
+        &self.textarea
+    }
+
+    pub(crate) fn is_composing(&self) -> bool {
+        self.composing.get()
+    }
+
+    pub(crate) fn focus(&self) {
+        let _ = self.textarea.focus();
+    }
+
+    /// Stop taking text input, which also ends any composition.
+    pub(crate) fn blur(&self) {
+        self.composing.set(false);
+        self.textarea.set_value("");
+        let _ = self.textarea.blur();
+    }
+
+    /// Move the textarea to `rect` of the canvas, in CSS pixels, which is usually the caret.
+    pub(crate) fn move_to(&self, canvas: &web_sys::HtmlCanvasElement, rect: Rect) {
+        let style = self.textarea.style();
+        let left = canvas.offset_left() as f64 + rect.x0;
+        let top = canvas.offset_top() as f64 + rect.y0;
+        let _ = style.set_property("left", &format!("{left}px"));
+        let _ = style.set_property("top", &format!("{top}px"));
+        if rect.height() > 0.0 {
+            let _ = style.set_property("height", &format!("{}px", rect.height()));
+        }
+    }
+
+    /// Take the text the browser put in the textarea, outside of a composition.
+    pub(crate) fn take_input(&self) -> Option<String> {
+        if self.composing.get() {
+            return None;
+        }
+        let value = self.textarea.value();
+        self.textarea.set_value("");
+        (!value.is_empty()).then_some(value)
+    }
+
+    pub(crate) fn composition_started(&self) {
+        self.composing.set(true);
+    }
+
+    pub(crate) fn composition_ended(&self) {
+        self.composing.set(false);
+        self.textarea.set_value("");
+    }
+}
+
+/// Replace the composition, or the selection if there is none, with `text`, which remains
+/// composed, and put the caret at its end.
+pub(crate) fn set_composition(handler: &mut dyn InputHandler, text: &str) {
+    let range = handler
+        .composition_range()
+        .unwrap_or_else(|| handler.selection().range());
+    handler.replace_range(range.clone(), text);
+    let end = range.start + text.len();
+    if text.is_empty() {
+        handler.set_composition_range(None);
+    } else {
+        handler.set_composition_range(Some(range.start..end));
+    }
+    handler.set_selection(Selection::caret(end));
+}
+
+/// Replace the composition, or the selection if there is none, with `text`, and put the caret
+/// at its end.
+pub(crate) fn commit(handler: &mut dyn InputHandler, text: &str) {
+    let range = handler
+        .composition_range()
+        .unwrap_or_else(|| handler.selection().range());
+    handler.replace_range(range.clone(), text);
+    handler.set_composition_range(None);
+    handler.set_selection(Selection::caret(range.start + text.len()));
+}
@@ -18,13 +18,16 @@ use std::cell::{Cell, RefCell};
 use std::ffi::OsString;
 use std::rc::{Rc, Weak};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use instant::Instant;
 use tracing::{error, warn};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, WebWindowHandle};
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle};
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawWindowHandle, RawWindowHandle, WebWindowHandle};
 
 use crate::kurbo::{Insets, Point, Rect, Size, Vec2};
 
@@ -34,18 +37,25 @@ use super::application::Application;
 use super::error::Error;
 use super::keycodes::convert_keyboard_event;
 use super::menu::Menu;
-use crate::common_util::{ClickCounter, IdleCallback};
+use super::text_input::{self, TextInput};
+use crate::common_util::{self, ClickCounter, IdleCallback, PaintRequests};
 use crate::dialog::{FileDialogOptions, FileDialogType};
 use crate::error::Error as ShellError;
 use crate::scale::{Scale, ScaledArea};
 
-use crate::keyboard::{KeyState, Modifiers};
+use crate::keyboard::{KbKey, KeyState, Modifiers};
 use crate::mouse::{Cursor, CursorDesc, MouseButton, MouseButtons, MouseEvent};
+#[cfg(feature = "web_apis")]
+use crate::pointer::{
+    Angle, MouseInfo, PenInclination, PenInfo, PointerButton, PointerButtons, PointerEvent,
+    PointerId, PointerType, TouchInfo,
+};
 use crate::region::Region;
-use crate::text::{simulate_input, Event};
+use crate::text::{simulate_input, Event, InputHandler};
 use crate::window;
 use crate::window::{
-    FileDialogToken, IdleToken, TextFieldToken, TimerToken, WinHandler, WindowLevel,
+    FileDialogToken, FrameTimings, IdleToken, ImageBuf, PixelFormat, PopupConfig, TextFieldToken,
+    TimerToken, WinHandler, WindowLevel, WindowType,
 };
 
 // This is a macro instead of a function since KeyboardEvent and MouseEvent has identical functions
@@ -89,6 +99,21 @@ impl PartialEq for WindowHandle {
 }
 impl Eq for WindowHandle {}
 
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        error!("HasWindowHandle trait not implemented for wasm.");
+        Err(HandleError::NotSupported)
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        error!("HasDisplayHandle trait not implemented for wasm.");
+        Err(HandleError::NotSupported)
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
     fn raw_window_handle(&self) -> RawWindowHandle {
         error!("HasRawWindowHandle trait not implemented for wasm.");
@@ -121,7 +146,17 @@ struct WindowState {
     invalid: RefCell<Region>,
     click_counter: ClickCounter,
     active_text_input: Cell<Option<TextFieldToken>>,
-    rendering_soon: Cell<bool>,
+    paint_requests: PaintRequests,
+    /// Browsers always present in step with the display, so this is only reported back.
+    vsync: Cell<bool>,
+    /// The start of the animation frame the last paint ran in.
+    frame_timings: Cell<FrameTimings>,
+    /// The visibility last reported to the handler.
+    visibility: Cell<window::Visibility>,
+    /// Where the browser sends text input, if it could be created.
+    text_input: Option<TextInput>,
+    /// Set while moving `text_input` to the caret is waiting for the next animation frame.
+    caret_update_pending: Cell<bool>,
 }
 
 // TODO: support custom cursors
@@ -157,12 +192,58 @@ impl WindowState {
         }
     }
 
-    fn request_animation_frame(&self, f: impl FnOnce() + 'static) -> Result<i32, Error> {
+    /// Call `f` before the browser paints its next frame, with the time the frame started.
+    fn request_animation_frame(&self, f: impl FnOnce(f64) + 'static) -> Result<i32, Error> {
         Ok(self
             .window
             .request_animation_frame(Closure::once_into_js(f).as_ref().unchecked_ref())?)
     }
 
+    /// Call `f` with the input handler of the focused text field, if there is one.
+    fn with_input_handler(&self, f: impl FnOnce(&mut dyn InputHandler)) {
+        let Some(token) = self.active_text_input.get() else {
+            return;
+        };
+        let mut handler = self.handler.borrow_mut();
+        let mut input_handler = handler.acquire_input_lock(token, true);
+        f(&mut *input_handler);
+        drop(input_handler);
+        handler.release_input_lock(token);
+    }
+
+    /// Move the textarea to the caret of the focused text field on the next animation frame,
+    /// since the handler may be busy now.
+    fn caret_moved(self: &Rc<Self>) {
+        if self.caret_update_pending.replace(true) {
+            return;
+        }
+        let state = self.clone();
+        let _ = self.request_animation_frame(move |_| {
+            state.caret_update_pending.set(false);
+            let (Some(token), Some(text_input)) =
+                (state.active_text_input.get(), &state.text_input)
+            else {
+                return;
+            };
+            let mut handler = state.handler.borrow_mut();
+            let input_handler = handler.acquire_input_lock(token, false);
+            let caret = input_handler.slice_bounding_box(input_handler.selection().range());
+            drop(input_handler);
+            handler.release_input_lock(token);
+            if let Some(caret) = caret {
+                text_input.move_to(&state.canvas, caret);
+            }
+        });
+    }
+
+    /// The `Instant` of a `requestAnimationFrame` timestamp, which uses the clock of
+    /// `performance.now()`.
+    fn frame_instant(&self, timestamp: f64) -> Option<Instant> {
+        let now = self.window.performance()?.now();
+        let age = Duration::from_secs_f64(((now - timestamp) / 1000.0).max(0.0));
+        Instant::now().checked_sub(age)
+    }
+
     /// Returns the window size in css units
     fn get_window_size_and_dpr(&self) -> (f64, f64, f64) {
         let w = &self.window;
@@ -193,6 +274,178 @@ impl WindowState {
     }
 }
 
+#[cfg(feature = "web_apis")]
+impl WindowState {
+    /// Called when the canvas is laid out at a new size, in CSS pixels, and possibly in device
+    /// pixels when the browser knows them exactly.
+    fn canvas_resized(&self, size_dp: Size, size_px: Option<Size>) {
+        let dpr = self.window.device_pixel_ratio();
+        let scale = Scale::new(dpr, dpr);
+        let size_px =
+            size_px.unwrap_or_else(|| Size::new(size_dp.width * dpr, size_dp.height * dpr).round());
+        // A canvas without a CSS size is laid out at its pixel size, so matching the pixel size
+        // to it would grow it on every resize.
+        if dpr != 1.0
+            && size_dp.width == self.canvas.width() as f64
+            && size_dp.height == self.canvas.height() as f64
+        {
+            warn!("the canvas needs a CSS size for glazier to follow its resizes");
+            return;
+        }
+        let area = ScaledArea::from_px(size_px, scale);
+        self.canvas.set_width(size_px.width as u32);
+        self.canvas.set_height(size_px.height as u32);
+        // Resizing the canvas resets its transform.
+        let _ = self.context.scale(scale.x(), scale.y());
+        let scale_changed = self.scale.replace(scale) != scale;
+        let size_changed = self.area.replace(area).size_dp() != area.size_dp();
+        if scale_changed {
+            self.handler.borrow_mut().scale(scale);
+        }
+        if size_changed {
+            self.handler.borrow_mut().size(area.size_dp());
+        }
+    }
+
+    /// The cross-platform event for a pointer event from the browser.
+    fn pointer_event(&self, event: &web_sys::PointerEvent) -> PointerEvent {
+        let pointer_type = match event.pointer_type().as_str() {
+            "pen" => {
+                let mut pen = PenInfo {
+                    pressure: event.pressure() as f64,
+                    tangential_pressure: event.tangential_pressure() as f64,
+                    twist: Angle::degrees(event.twist() as f64),
+                    ..PenInfo::default()
+                };
+                let (tilt_x, tilt_y) = (event.tilt_x() as f64, event.tilt_y() as f64);
+                if let Some(inclination) = PenInclination::from_tilt(tilt_x, tilt_y) {
+                    pen.inclination = inclination;
+                }
+                // Browsers report the eraser end as the fifth button.
+                if event.buttons() & 1 << 5 != 0 {
+                    PointerType::Eraser(pen)
+                } else {
+                    PointerType::Pen(pen)
+                }
+            }
+            "touch" => PointerType::Touch(TouchInfo {
+                contact_geometry: Size::new(event.width() as f64, event.height() as f64),
+                pressure: event.pressure(),
+            }),
+            _ => PointerType::Mouse(MouseInfo {
+                wheel_delta: Vec2::ZERO,
+            }),
+        };
+        PointerEvent {
+            pointer_id: PointerId(event.pointer_id() as u32 as u64),
+            is_primary: event.is_primary(),
+            pointer_type,
+            pos: Point::new(event.offset_x() as f64, event.offset_y() as f64),
+            buttons: mouse_buttons(event.buttons()).into(),
+            modifiers: get_modifiers!(event),
+            button: mouse_button(event.button()).map_or(PointerButton::None, Into::into),
+            focus: false,
+            count: 0,
+            coalesced: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "web_apis")]
+fn setup_pointer_callbacks(ws: &Rc<WindowState>) {
+    // Otherwise the browser takes touches and pens for scrolling and zooming the page.
+    if ws
+        .canvas
+        .style()
+        .set_property("touch-action", "none")
+        .is_err()
+    {
+        warn!("Failed to set touch-action");
+    }
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "pointerdown", move |event: web_sys::PointerEvent| {
+        let mut pointer = state.pointer_event(&event);
+        pointer.count = state.click_counter.count_for_click(pointer.pos);
+        // Keep getting the pointer's events while it is dragged off the canvas.
+        let _ = state.canvas.set_pointer_capture(event.pointer_id());
+        state.handler.borrow_mut().pointer_down(&pointer);
+    });
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "pointermove", move |event: web_sys::PointerEvent| {
+        let pointer = state.pointer_event(&event);
+        state.handler.borrow_mut().pointer_move(&pointer);
+    });
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "pointerup", move |event: web_sys::PointerEvent| {
+        let pointer = state.pointer_event(&event);
+        state.handler.borrow_mut().pointer_up(&pointer);
+    });
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "pointercancel", move |event: web_sys::PointerEvent| {
+        // The browser took the pointer for a gesture of its own. The handler has no cancel, so
+        // the contact is ended, which keeps its drags from getting stuck.
+        let mut pointer = state.pointer_event(&event);
+        pointer.button = PointerButton::Primary;
+        pointer.buttons = PointerButtons::new();
+        state.handler.borrow_mut().pointer_up(&pointer);
+    });
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "pointerleave", move |event: web_sys::PointerEvent| {
+        // Touches leave every time they are lifted.
+        if event.pointer_type() != "touch" {
+            state.handler.borrow_mut().pointer_leave();
+        }
+    });
+}
+
+#[cfg(feature = "web_apis")]
+fn setup_resize_observer(ws: &Rc<WindowState>) {
+    let state = ws.clone();
+    let closure = Closure::wrap(Box::new(move |entries: js_sys::Array| {
+        let Some(entry) = entries
+            .iter()
+            .filter_map(|entry| entry.dyn_into::<web_sys::ResizeObserverEntry>().ok())
+            .last()
+        else {
+            return;
+        };
+        let rect = entry.content_rect();
+        let size_dp = Size::new(rect.width(), rect.height());
+        // This is only there when the canvas is observed with `device-pixel-content-box`.
+        let size_px = entry
+            .device_pixel_content_box_size()
+            .get(0)
+            .dyn_into::<web_sys::ResizeObserverSize>()
+            .ok()
+            .map(|size| Size::new(size.inline_size(), size.block_size()));
+        state.canvas_resized(size_dp, size_px);
+    }) as Box<dyn FnMut(js_sys::Array)>);
+    let observer = match web_sys::ResizeObserver::new(closure.as_ref().unchecked_ref()) {
+        Ok(observer) => observer,
+        Err(e) => {
+            warn!(
+                "ResizeObserver is unsupported, falling back to window resizes: {:?}",
+                e
+            );
+            setup_resize_callback(ws);
+            return;
+        }
+    };
+    closure.forget();
+    let mut options = web_sys::ResizeObserverOptions::new();
+    options.box_(web_sys::ResizeObserverBoxOptions::DevicePixelContentBox);
+    // Safari doesn't support `device-pixel-content-box`, and throws for it.
+    if observer.observe_with_options(&ws.canvas, &options).is_err() {
+        observer.observe(&ws.canvas);
+    }
+}
+
+#[cfg_attr(feature = "web_apis", allow(dead_code))]
 fn setup_mouse_down_callback(ws: &Rc<WindowState>) {
     let state = ws.clone();
     register_canvas_event_listener(ws, "mousedown", move |event: web_sys::MouseEvent| {
@@ -209,12 +462,14 @@ fn setup_mouse_down_callback(ws: &Rc<WindowState>) {
                 focus: false,
                 button,
                 wheel_delta: Vec2::ZERO,
+                coalesced: Vec::new(),
             };
             state.handler.borrow_mut().mouse_down(&event);
         }
     });
 }
 
+#[cfg_attr(feature = "web_apis", allow(dead_code))]
 fn setup_mouse_up_callback(ws: &Rc<WindowState>) {
     let state = ws.clone();
     register_canvas_event_listener(ws, "mouseup", move |event: web_sys::MouseEvent| {
@@ -228,12 +483,14 @@ fn setup_mouse_up_callback(ws: &Rc<WindowState>) {
                 focus: false,
                 button,
                 wheel_delta: Vec2::ZERO,
+                coalesced: Vec::new(),
             };
             state.handler.borrow_mut().mouse_up(&event);
         }
     });
 }
 
+#[cfg_attr(feature = "web_apis", allow(dead_code))]
 fn setup_mouse_move_callback(ws: &Rc<WindowState>) {
     let state = ws.clone();
     register_canvas_event_listener(ws, "mousemove", move |event: web_sys::MouseEvent| {
@@ -246,6 +503,7 @@ fn setup_mouse_move_callback(ws: &Rc<WindowState>) {
             focus: false,
             button: MouseButton::None,
             wheel_delta: Vec2::ZERO,
+            coalesced: Vec::new(),
         };
         state.handler.borrow_mut().mouse_move(&event);
     });
@@ -281,6 +539,7 @@ fn setup_scroll_callback(ws: &Rc<WindowState>) {
             focus: false,
             button: MouseButton::None,
             wheel_delta,
+            coalesced: Vec::new(),
         };
         state.handler.borrow_mut().wheel(&event);
     });
@@ -296,6 +555,73 @@ fn setup_resize_callback(ws: &Rc<WindowState>) {
     });
 }
 
+/// Tell the handler when the device pixel ratio changes, such as when the page is zoomed or
+/// moved to another display.
+fn watch_device_pixel_ratio(state: Rc<WindowState>) {
+    // This only matches the current ratio, so it changes once, and is replaced after that.
+    let query = format!("(resolution: {}dppx)", state.window.device_pixel_ratio());
+    let Ok(Some(list)) = state.window.match_media(&query) else {
+        warn!("Failed to watch the device pixel ratio");
+        return;
+    };
+    let closure = Closure::once_into_js(move || {
+        #[cfg(feature = "web_apis")]
+        if state.canvas_size.is_none() {
+            // The resize observer follows the canvas, so only the ratio has changed.
+            state.canvas_resized(state.area.get().size_dp(), None);
+            watch_device_pixel_ratio(state);
+            return;
+        }
+        let (scale, area) = state.update_scale_and_area();
+        state.handler.borrow_mut().scale(scale);
+        state.handler.borrow_mut().size(area.size_dp());
+        watch_device_pixel_ratio(state);
+    });
+    let mut options = web_sys::AddEventListenerOptions::new();
+    options.once(true);
+    if list
+        .add_event_listener_with_callback_and_add_event_listener_options(
+            "change",
+            closure.unchecked_ref(),
+            &options,
+        )
+        .is_err()
+    {
+        warn!("Failed to watch the device pixel ratio");
+    }
+}
+
+fn setup_visibility_callback(ws: &Rc<WindowState>) {
+    let Some(document) = ws.window.document() else {
+        return;
+    };
+    let state = ws.clone();
+    let closure = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        let visibility = page_visibility(&state.window);
+        if state.visibility.replace(visibility) != visibility {
+            state.handler.borrow_mut().visibility_changed(visibility);
+        }
+    }) as Box<dyn FnMut(_)>);
+    document
+        .add_event_listener_with_callback("visibilitychange", closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+}
+
+/// The visibility of the page, which is hidden while its tab is in the background, or its
+/// browser window is minimized.
+fn page_visibility(window: &web_sys::Window) -> window::Visibility {
+    let hidden = window.document().map_or(false, |document| {
+        document.visibility_state() == web_sys::VisibilityState::Hidden
+    });
+    if hidden {
+        // The page is still shown in its tab, just not on screen.
+        window::Visibility::Occluded
+    } else {
+        window::Visibility::Visible
+    }
+}
+
 fn setup_keyup_callback(ws: &Rc<WindowState>) {
     let state = ws.clone();
     register_window_event_listener(ws, "keyup", move |event: web_sys::KeyboardEvent| {
@@ -308,22 +634,107 @@ fn setup_keyup_callback(ws: &Rc<WindowState>) {
 fn setup_keydown_callback(ws: &Rc<WindowState>) {
     let state = ws.clone();
     register_window_event_listener(ws, "keydown", move |event: web_sys::KeyboardEvent| {
+        // The keys which the browser hands to the IME, including the one which starts a
+        // composition, come with key code 229, and the composition events follow.
+        if event.is_composing() || event.key_code() == 229 {
+            return;
+        }
         let modifiers = get_modifiers!(event);
         let kb_event = convert_keyboard_event(&event, modifiers, KeyState::Down);
-        let mut handler = state.handler.borrow_mut();
-        if simulate_input(&mut **handler, state.active_text_input.get(), kb_event) {
+        let token = state.active_text_input.get();
+        let handled = match kb_event.key {
+            // These are left for the browser to put in the textarea, where dead keys are
+            // combined with the next key, and then taken from its `input` event.
+            KbKey::Character(_) | KbKey::Dead
+                if token.is_some()
+                    && state.text_input.is_some()
+                    && !modifiers.ctrl()
+                    && !modifiers.meta() =>
+            {
+                state.handler.borrow_mut().key_down(kb_event)
+            }
+            _ => simulate_input(&mut **state.handler.borrow_mut(), token, kb_event),
+        };
+        // This keeps the browser's own shortcuts, such as saving the page, from running too.
+        if handled {
+            event.prevent_default();
+        }
+    });
+}
+
+fn setup_text_input_callbacks(ws: &Rc<WindowState>) {
+    let Some(text_input) = &ws.text_input else {
+        return;
+    };
+    let textarea = text_input.element();
+
+    let state = ws.clone();
+    register_event_listener(textarea, "compositionstart", move |_: web_sys::Event| {
+        if let Some(text_input) = &state.text_input {
+            text_input.composition_started();
+        }
+    });
+
+    let state = ws.clone();
+    register_event_listener(
+        textarea,
+        "compositionupdate",
+        move |event: web_sys::CompositionEvent| {
+            let text = event.data().unwrap_or_default();
+            state.with_input_handler(|handler| text_input::set_composition(handler, &text));
+        },
+    );
+
+    let state = ws.clone();
+    register_event_listener(
+        textarea,
+        "compositionend",
+        move |event: web_sys::CompositionEvent| {
+            if let Some(text_input) = &state.text_input {
+                text_input.composition_ended();
+            }
+            let text = event.data().unwrap_or_default();
+            state.with_input_handler(|handler| text_input::commit(handler, &text));
+        },
+    );
+
+    let state = ws.clone();
+    register_event_listener(textarea, "input", move |_: web_sys::Event| {
+        let text = state.text_input.as_ref().and_then(|t| t.take_input());
+        if let Some(text) = text {
+            state.with_input_handler(|handler| text_input::commit(handler, &text));
+        }
+    });
+
+    let state = ws.clone();
+    register_canvas_event_listener(ws, "mousedown", move |event: web_sys::MouseEvent| {
+        // Otherwise the click takes the focus away from the textarea.
+        if state.active_text_input.get().is_some() {
             event.prevent_default();
         }
     });
 }
 
+/// Wrap an event listener in a [`common_util::event_span`] named by its `event_type`.
+fn traced<F, E>(event_type: &str, mut f: F) -> Box<dyn FnMut(E)>
+where
+    F: 'static + FnMut(E),
+    E: 'static,
+{
+    let event_type = event_type.to_string();
+    Box::new(move |event| {
+        let _span = common_util::event_span(&event_type);
+        f(event)
+    })
+}
+
 /// A helper function to register a window event listener with `addEventListener`.
 fn register_window_event_listener<F, E>(window_state: &Rc<WindowState>, event_type: &str, f: F)
 where
     F: 'static + FnMut(E),
     E: 'static + wasm_bindgen::convert::FromWasmAbi,
 {
-    let closure = Closure::wrap(Box::new(f) as Box<dyn FnMut(_)>);
+    let closure = Closure::wrap(traced(event_type, f));
     window_state
         .window
         .add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())
@@ -331,13 +742,26 @@ where
     closure.forget();
 }
 
+/// A helper function to register an event listener on any element with `addEventListener`.
+fn register_event_listener<F, E>(target: &web_sys::EventTarget, event_type: &str, f: F)
+where
+    F: 'static + FnMut(E),
+    E: 'static + wasm_bindgen::convert::FromWasmAbi,
+{
+    let closure = Closure::wrap(traced(event_type, f));
+    target
+        .add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())
+        .unwrap();
+    closure.forget();
+}
+
 /// A helper function to register a canvas event listener with `addEventListener`.
 fn register_canvas_event_listener<F, E>(window_state: &Rc<WindowState>, event_type: &str, f: F)
 where
     F: 'static + FnMut(E),
     E: 'static + wasm_bindgen::convert::FromWasmAbi,
 {
-    let closure = Closure::wrap(Box::new(f) as Box<dyn FnMut(_)>);
+    let closure = Closure::wrap(traced(event_type, f));
     window_state
         .canvas
         .add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())
@@ -346,13 +770,29 @@ where
 }
 
 fn setup_web_callbacks(window_state: &Rc<WindowState>) {
-    setup_mouse_down_callback(window_state);
-    setup_mouse_move_callback(window_state);
-    setup_mouse_up_callback(window_state);
-    setup_resize_callback(window_state);
+    #[cfg(feature = "web_apis")]
+    {
+        setup_pointer_callbacks(window_state);
+        // The canvas keeps the size it was given, so there is nothing to observe.
+        if window_state.canvas_size.is_some() {
+            setup_resize_callback(window_state);
+        } else {
+            setup_resize_observer(window_state);
+        }
+    }
+    #[cfg(not(feature = "web_apis"))]
+    {
+        setup_mouse_down_callback(window_state);
+        setup_mouse_move_callback(window_state);
+        setup_mouse_up_callback(window_state);
+        setup_resize_callback(window_state);
+    }
     setup_scroll_callback(window_state);
+    setup_visibility_callback(window_state);
+    watch_device_pixel_ratio(window_state.clone());
     setup_keyup_callback(window_state);
     setup_keydown_callback(window_state);
+    setup_text_input_callbacks(window_state);
 }
 
 impl WindowBuilder {
@@ -391,6 +831,11 @@ impl WindowBuilder {
         self
     }
 
+    pub fn prefer_client_side_decorations(self, _prefer: bool) -> Self {
+        // Ignored
+        self
+    }
+
     pub fn transparent(self, _transparent: bool) -> Self {
         // Ignored
         self
@@ -406,11 +851,76 @@ impl WindowBuilder {
         self
     }
 
+    pub fn maximize_mode(self, _mode: window::MaximizeMode) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn kiosk(self, _kiosk: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn offscreen(self, _offscreen: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn popup(self, _popup: PopupConfig) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_visible(self, _visible: bool) -> Self {
+        // Ignored, the canvas is always visible
+        self
+    }
+
+    pub fn with_skip_taskbar(self, _skip: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_managed_surface(self, _managed: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn use_pointer_api(self, _enable: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_coalesced_pointer_events(self, _coalesce: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_accepts_focus(self, _accepts_focus: bool) -> Self {
+        // Ignored
+        self
+    }
+
+    pub fn with_parent(self, _parent: crate::WindowHandle) -> Self {
+        // Ignored
+        self
+    }
+
     pub fn level(self, _level: WindowLevel) -> Self {
         // ignored
         self
     }
 
+    pub fn with_window_type(self, _window_type: WindowType) -> Self {
+        // ignored
+        self
+    }
+
+    pub fn with_frame_autosave_name(self, _name: &str, _fullscreen: bool) -> Self {
+        // ignored
+        self
+    }
+
     pub fn title<S: Into<String>>(mut self, title: S) -> Self {
         self.title = title.into();
         self
@@ -421,6 +931,13 @@ impl WindowBuilder {
         self
     }
 
+    pub fn build_as_child(
+        self,
+        _parent: raw_window_handle::RawWindowHandle,
+    ) -> Result<WindowHandle, ShellError> {
+        Err(ShellError::Unsupported("child windows"))
+    }
+
     pub fn build(self) -> Result<WindowHandle, Error> {
         let window = web_sys::window().ok_or(Error::NoWindow)?;
         let canvas = window
@@ -467,6 +984,13 @@ impl WindowBuilder {
 
         let handler = self.handler.unwrap();
 
+        let text_input = window
+            .document()
+            .ok_or(Error::NoDocument)
+            .and_then(|document| TextInput::new(&document, &canvas))
+            .map_err(|e| warn!("Failed to create the textarea for text input: {}", e))
+            .ok();
+
         let window = Rc::new(WindowState {
             scale: Cell::new(scale),
             area: Cell::new(area),
@@ -479,7 +1003,12 @@ impl WindowBuilder {
             invalid: RefCell::new(Region::EMPTY),
             click_counter: ClickCounter::default(),
             active_text_input: Cell::new(None),
-            rendering_soon: Cell::new(false),
+            paint_requests: PaintRequests::default(),
+            vsync: Cell::new(true),
+            frame_timings: Cell::new(FrameTimings::default()),
+            visibility: Cell::new(page_visibility(&window)),
+            text_input,
+            caret_update_pending: Cell::new(false),
         });
 
         setup_web_callbacks(&window);
@@ -487,7 +1016,7 @@ impl WindowBuilder {
         // Register the scale & size with the window handler.
         let wh = window.clone();
         window
-            .request_animation_frame(move || {
+            .request_animation_frame(move |_| {
                 wh.handler.borrow_mut().scale(scale);
                 wh.handler.borrow_mut().size(size_dp);
             })
@@ -496,6 +1025,8 @@ impl WindowBuilder {
         let handle = WindowHandle(Rc::downgrade(&window));
 
         window.handler.borrow_mut().connect(&handle.clone().into());
+        let visibility = window.visibility.get();
+        window.handler.borrow_mut().visibility_changed(visibility);
 
         Ok(handle)
     }
@@ -506,10 +1037,59 @@ impl WindowHandle {
         self.render_soon();
     }
 
+    pub fn hide(&self) {
+        warn!("WindowHandle::hide unimplemented for web");
+    }
+
     pub fn resizable(&self, _resizable: bool) {
         warn!("resizable unimplemented for web");
     }
 
+    pub fn set_aspect_ratio(&self, _ratio: Option<f64>) {
+        warn!("set_aspect_ratio unimplemented for web");
+    }
+
+    pub fn set_resize_increments(&self, _increments: Option<Size>) {
+        warn!("set_resize_increments unimplemented for web");
+    }
+
+    pub fn set_skip_taskbar(&self, _skip: bool) {
+        warn!("set_skip_taskbar unimplemented for web");
+    }
+
+    pub fn set_window_level(&self, _level: window::StackingLevel) {
+        warn!("set_window_level unimplemented for web");
+    }
+
+    pub fn set_titlebar_theme(&self, _theme: Option<window::Theme>) {
+        warn!("set_titlebar_theme unimplemented for web");
+    }
+
+    pub fn begin_sheet(
+        &self,
+        _sheet: &WindowHandle,
+        _completion: Box<dyn FnOnce(window::ModalResponse)>,
+    ) {
+        warn!("begin_sheet unimplemented for web");
+    }
+
+    pub fn end_sheet(&self, _response: window::ModalResponse) {
+        warn!("end_sheet unimplemented for web");
+    }
+
+    pub fn run_modal(&self) -> window::ModalResponse {
+        warn!("run_modal unimplemented for web");
+        window::ModalResponse::Cancel
+    }
+
+    pub fn end_modal(&self, _response: window::ModalResponse) {
+        warn!("end_modal unimplemented for web");
+    }
+
+    pub fn focus_next_window(&self) {
+        warn!("focus_next_window unimplemented for web");
+    }
+
     pub fn show_titlebar(&self, _show_titlebar: bool) {
         warn!("show_titlebar unimplemented for web");
     }
@@ -546,6 +1126,10 @@ impl WindowHandle {
         window::WindowState::Restored
     }
 
+    pub fn set_maximize_mode(&self, _mode: window::MaximizeMode) {
+        warn!("WindowHandle::set_maximize_mode unimplemented for web.");
+    }
+
     pub fn handle_titlebar(&self, _val: bool) {
         warn!("WindowHandle::handle_titlebar unimplemented for web.");
     }
@@ -554,6 +1138,10 @@ impl WindowHandle {
         // TODO
     }
 
+    pub fn is_alive(&self) -> bool {
+        self.0.upgrade().is_some()
+    }
+
     pub fn bring_to_front_and_focus(&self) {
         warn!("bring_to_frontand_focus unimplemented for web");
     }
@@ -569,6 +1157,27 @@ impl WindowHandle {
         self.render_soon();
     }
 
+    pub fn present_pixels(
+        &self,
+        _buf: &[u8],
+        _width: u32,
+        _height: u32,
+        _format: PixelFormat,
+    ) -> Result<(), ShellError> {
+        warn!("WindowHandle::present_pixels unimplemented for web.");
+        Err(ShellError::Unsupported("present_pixels"))
+    }
+
+    pub fn read_back_pixels(&self, _format: PixelFormat) -> Result<Vec<u8>, ShellError> {
+        warn!("WindowHandle::read_back_pixels unimplemented for web.");
+        Err(ShellError::Unsupported("read_back_pixels"))
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        warn!("WindowHandle::capture unimplemented for web.");
+        None
+    }
+
     pub fn invalidate(&self) {
         if let Some(s) = self.0.upgrade() {
             s.invalid
@@ -595,18 +1204,51 @@ impl WindowHandle {
         if let Some(state) = self.0.upgrade() {
             if state.active_text_input.get() == Some(token) {
                 state.active_text_input.set(None);
+                if let Some(text_input) = &state.text_input {
+                    text_input.blur();
+                }
             }
         }
     }
 
     pub fn set_focused_text_field(&self, active_field: Option<TextFieldToken>) {
         if let Some(state) = self.0.upgrade() {
-            state.active_text_input.set(active_field);
+            let previous = state.active_text_input.replace(active_field);
+            let Some(text_input) = &state.text_input else {
+                return;
+            };
+            if previous.is_some() && previous != active_field {
+                // This ends the composition in the field which lost focus.
+                text_input.blur();
+            }
+            if active_field.is_some() {
+                text_input.focus();
+                state.caret_moved();
+            }
         }
     }
 
-    pub fn update_text_field(&self, _token: TextFieldToken, _update: Event) {
-        // no-op for now, until we get a properly implemented text input
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.0.upgrade()?.active_text_input.get()
+    }
+
+    pub fn update_text_field(&self, token: TextFieldToken, update: Event) {
+        if let Some(state) = self.0.upgrade() {
+            if state.active_text_input.get() != Some(token) {
+                return;
+            }
+            let Some(text_input) = &state.text_input else {
+                return;
+            };
+            if update == Event::Reset && text_input.is_composing() {
+                // The text changed under the composition, which can only be ended by taking
+                // the focus away.
+                text_input.blur();
+                text_input.focus();
+            }
+            state.caret_moved();
+        }
     }
 
     pub fn request_timer(&self, deadline: Instant) -> TimerToken {
@@ -664,10 +1306,13 @@ impl WindowHandle {
     fn render_soon(&self) {
         if let Some(s) = self.0.upgrade() {
             let state = s.clone();
-            if !state.rendering_soon.get() {
-                state.rendering_soon.set(true);
-                s.request_animation_frame(move || {
-                    state.rendering_soon.set(false);
+            if state.paint_requests.request() {
+                s.request_animation_frame(move |timestamp| {
+                    state.paint_requests.paint_started();
+                    state.frame_timings.set(FrameTimings {
+                        vsync: state.frame_instant(timestamp),
+                        ..FrameTimings::default()
+                    });
                     state.render();
                 })
                 .expect("Failed to request animation frame");
@@ -680,7 +1325,7 @@ impl WindowHandle {
         _ty: FileDialogType,
         _options: FileDialogOptions,
     ) -> Result<OsString, ShellError> {
-        Err(ShellError::Platform(Error::Unimplemented))
+        Err(ShellError::Unsupported("file dialogs"))
     }
 
     /// Get a handle that can be used to schedule an idle task.
@@ -691,6 +1336,13 @@ impl WindowHandle {
         })
     }
 
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        // Browsers don't tell us when frames are presented, only when they start
+        self.0
+            .upgrade()
+            .map_or_else(FrameTimings::default, |s| s.frame_timings.get())
+    }
+
     /// Get the `Scale` of the window.
     pub fn get_scale(&self) -> Result<Scale, ShellError> {
         Ok(self
@@ -701,6 +1353,10 @@ impl WindowHandle {
             .get())
     }
 
+    pub fn set_backing_scale_override(&self, _scale: Option<f64>) {
+        warn!("set_backing_scale_override unimplemented for web");
+    }
+
     pub fn set_menu(&self, _menu: Menu) {
         warn!("set_menu unimplemented for web");
     }
@@ -709,6 +1365,24 @@ impl WindowHandle {
         warn!("show_context_menu unimplemented for web");
     }
 
+    pub fn show_character_palette(&self) {
+        // Browsers don't let pages open the system's picker.
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        if let Some(s) = self.0.upgrade() {
+            s.vsync.set(enabled);
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.0.upgrade().map_or(true, |s| s.vsync.get())
+    }
+
+    pub fn set_present_feedback(&self, _enabled: bool) {
+        warn!("set_present_feedback unimplemented for web");
+    }
+
     pub fn set_title(&self, title: impl Into<String>) {
         if let Some(state) = self.0.upgrade() {
             state.canvas.set_title(&(title.into()))
@@ -740,7 +1414,7 @@ impl IdleHandle {
             if let Some(window_state) = self.state.upgrade() {
                 let state = window_state.clone();
                 window_state
-                    .request_animation_frame(move || {
+                    .request_animation_frame(move |_| {
                         state.process_idle_queue();
                     })
                     .expect("request_animation_frame failed");
@@ -756,7 +1430,7 @@ impl IdleHandle {
             if let Some(window_state) = self.state.upgrade() {
                 let state = window_state.clone();
                 window_state
-                    .request_animation_frame(move || {
+                    .request_animation_frame(move |_| {
                         state.process_idle_queue();
                     })
                     .expect("request_animation_frame failed");
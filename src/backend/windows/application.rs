@@ -15,46 +15,122 @@
 //! Windows implementation of features at the application scope.
 
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::mem;
+use std::path::Path;
 use std::ptr;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use winapi::shared::minwindef::{DWORD, FALSE, HINSTANCE};
-use winapi::shared::ntdef::LPCWSTR;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, HINSTANCE, TRUE, UINT, WPARAM};
+use winapi::shared::ntdef::{BOOLEAN, HANDLE, LPCWSTR, LPWSTR, PVOID, ULONG};
 use winapi::shared::windef::{DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, HCURSOR, HWND};
-use winapi::shared::winerror::HRESULT_FROM_WIN32;
+use winapi::shared::winerror::{HRESULT_FROM_WIN32, SUCCEEDED};
+use winapi::um::dwmapi::DwmGetColorizationColor;
 use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::minwinbase::REASON_CONTEXT;
 use winapi::um::processthreadsapi::GetCurrentThreadId;
 use winapi::um::shellscalingapi::PROCESS_PER_MONITOR_DPI_AWARE;
-use winapi::um::winnls::GetUserDefaultLocaleName;
-use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+use winapi::um::threadpoollegacyapiset::UnregisterWaitEx;
+use winapi::um::winbase::{
+    GetSystemPowerStatus, PowerCreateRequest, PowerSetRequest, RegisterWaitForSingleObject,
+    UnregisterWait, INFINITE, SYSTEM_POWER_STATUS,
+};
+use winapi::um::wingdi::{LAYOUT_RTL, LOGFONTW};
+use winapi::um::winnls::{
+    GetLocaleInfoEx, GetUserDefaultLocaleName, GetUserPreferredUILanguages, LOCALE_IREADINGLAYOUT,
+    LOCALE_RETURN_NUMBER, MUI_LANGUAGE_NAME,
+};
+use winapi::um::winnt::{
+    PowerRequestDisplayRequired, PowerRequestSystemRequired, LOCALE_NAME_MAX_LENGTH,
+    POWER_REQUEST_CONTEXT_SIMPLE_STRING, POWER_REQUEST_CONTEXT_VERSION, POWER_REQUEST_TYPE,
+    WT_EXECUTEONLYONCE,
+};
+use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
 use winapi::um::winuser::{
-    DispatchMessageW, GetAncestor, GetMessageW, LoadIconW, PeekMessageW, PostMessageW,
-    PostQuitMessage, PostThreadMessageW, RegisterClassW, TranslateAcceleratorW, TranslateMessage,
-    GA_ROOT, IDI_APPLICATION, MSG, PM_NOREMOVE, WM_TIMER, WNDCLASSW,
+    DestroyWindow, DispatchMessageW, GetAncestor, GetCaretBlinkTime, GetMessageW,
+    GetProcessDefaultLayout, GetSysColor, GetSystemMetrics, IsWindow, LoadIconW,
+    MsgWaitForMultipleObjectsEx, PeekMessageW, PostMessageW, PostQuitMessage, PostThreadMessageW,
+    RegisterClassW, SystemParametersInfoW, TranslateAcceleratorW, TranslateMessage,
+    COLOR_HIGHLIGHT, COLOR_WINDOW, COLOR_WINDOWTEXT, FE_FONTSMOOTHINGCLEARTYPE,
+    FE_FONTSMOOTHINGORIENTATIONBGR, FE_FONTSMOOTHINGORIENTATIONRGB, GA_ROOT, HCF_HIGHCONTRASTON,
+    HIGHCONTRASTW, IDI_APPLICATION, MSG, MWMO_INPUTAVAILABLE, NONCLIENTMETRICSW, PM_NOREMOVE,
+    PM_REMOVE, QS_ALLINPUT, SM_CXDRAG, SM_CXVSCROLL, SPI_GETCARETWIDTH, SPI_GETCLIENTAREAANIMATION,
+    SPI_GETFONTSMOOTHING, SPI_GETFONTSMOOTHINGCONTRAST, SPI_GETFONTSMOOTHINGORIENTATION,
+    SPI_GETFONTSMOOTHINGTYPE, SPI_GETHIGHCONTRAST, SPI_GETMENUSHOWDELAY, SPI_GETNONCLIENTMETRICS,
+    WM_QUIT, WM_TIMER, WNDCLASSW,
 };
 
-use crate::application::AppHandler;
-use crate::common_util::{shared_queue, SharedDequeuer, SharedEnqueuer};
+use crate::application::{
+    A11yPrefs, AppHandler, Color, EventSourceToken, FontDescription, LoopMode, LoopStatus,
+    PowerState, SubpixelOrder, SystemColor, SystemFonts, TextAntialiasing, TextRenderingPrefs,
+    UiDirection, UiMetrics, WakeLockKind,
+};
+use crate::common_util::{self, shared_queue, SharedDequeuer, SharedEnqueuer};
+use crate::error::Error as ShellError;
+use crate::notification::{Notification, NotificationId};
+use crate::platform::windows::JumpList;
 
 use super::accels;
 use super::clipboard::Clipboard;
 use super::error::Error;
-use super::msgs::WM_RUN_MAIN_CB_QUEUE;
+use super::jump_list;
+use super::msgs::{WM_EVENT_SOURCE_SIGNALLED, WM_RUN_MAIN_CB_QUEUE};
+use super::notification::NotificationWindow;
+use super::session::SessionWindow;
 use super::util::{self, FromWide, ToWide, CLASS_NAME, OPTIONAL_FUNCTIONS};
-use super::window::{self, DS_REQUEST_DESTROY};
+use super::window::{self, DS_REQUEST_DESTROY, SCALE_TARGET_DPI};
 
 #[derive(Clone)]
 pub(crate) struct Application {
     state: Rc<RefCell<State>>,
+    /// The handler passed to `run`, or set for running loop iterations, which is borrowed while
+    /// they handle messages.
+    handler: Rc<RefCell<Option<Box<dyn AppHandler>>>>,
 }
 
 struct State {
     quitting: bool,
+    /// Whether the loop has received `WM_QUIT`, after which there are no more messages.
+    exited: bool,
     windows: HashSet<HWND>,
     main_thread_cb_queue: (SharedEnqueuer<MainThreadCb>, SharedDequeuer<MainThreadCb>),
+    /// The last power state reported to the `AppHandler`.
+    ///
+    /// Every top-level window is told about power changes, so this filters out the duplicates.
+    power_state: Option<PowerState>,
+    /// Read again whenever a window receives `WM_SETTINGCHANGE`.
+    a11y_prefs: A11yPrefs,
+    /// As are the system fonts.
+    system_fonts: SystemFonts,
+    /// And the text scale factor.
+    text_scale_factor: f64,
+    /// Read again whenever a window is told that the colors have changed, or the app mode.
+    colors: Colors,
+    event_sources: HashMap<EventSourceToken, EventSource>,
+    /// Receives the sleep and session lock notifications, which are only sent to windows.
+    session_window: Option<SessionWindow>,
+    /// Shows the notifications, and is created when the first one is sent.
+    notification_window: Option<NotificationWindow>,
+}
+
+/// A handle which the thread pool waits on for us.
+struct EventSource {
+    handle: HANDLE,
+    /// The current wait, which only waits for a single signal so that the handle isn't
+    /// signalled again before the callback has had a chance to reset it.
+    wait: HANDLE,
+    /// Used by the thread pool callback, so it must outlive every wait.
+    context: Box<WaitContext>,
+    /// This is taken while the callback runs, so that it can add and remove event sources.
+    callback: Option<Box<dyn FnMut(Option<&mut dyn AppHandler>)>>,
+}
+
+struct WaitContext {
+    main_thread_id: DWORD,
+    token: EventSourceToken,
 }
 
 /// Used to ensure the window class is registered only once per process.
@@ -65,25 +141,43 @@ impl Application {
         Application::init()?;
         let state = Rc::new(RefCell::new(State {
             quitting: false,
+            exited: false,
             windows: HashSet::new(),
             main_thread_cb_queue: shared_queue(),
+            power_state: None,
+            a11y_prefs: read_accessibility_preferences(),
+            system_fonts: read_system_fonts(),
+            text_scale_factor: read_text_scale_factor(),
+            colors: read_colors(),
+            event_sources: HashMap::new(),
+            session_window: None,
+            notification_window: None,
         }));
-        Ok(Application { state })
+        let app = Application {
+            state,
+            handler: Default::default(),
+        };
+        let session_window = app.get_handle().and_then(SessionWindow::new);
+        app.state.borrow_mut().session_window = session_window;
+        Ok(app)
     }
 
     /// Initialize the app. At the moment, this is mostly needed for hi-dpi.
-    // TODO: Report back an error instead of panicking
-    #[allow(clippy::unnecessary_wraps)]
     fn init() -> Result<(), Error> {
         util::attach_console();
-        if let Some(func) = OPTIONAL_FUNCTIONS.SetProcessDpiAwarenessContext {
-            // This function is only supported on windows 10
-            unsafe {
-                func(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
-            }
-        } else if let Some(func) = OPTIONAL_FUNCTIONS.SetProcessDpiAwareness {
-            unsafe {
-                func(PROCESS_PER_MONITOR_DPI_AWARE);
+        // Per-monitor v2 scales the title bar and dialogs too, but it needs Windows 10 1703.
+        // Earlier versions have this function without accepting v2, and fall back to
+        // per-monitor awareness, where `win_proc_dispatch` scales the title bar.
+        let per_monitor_v2 = OPTIONAL_FUNCTIONS
+            .SetProcessDpiAwarenessContext
+            .map_or(false, |func| unsafe {
+                func(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) != FALSE
+            });
+        if !per_monitor_v2 {
+            if let Some(func) = OPTIONAL_FUNCTIONS.SetProcessDpiAwareness {
+                unsafe {
+                    func(PROCESS_PER_MONITOR_DPI_AWARE);
+                }
             }
         }
         if WINDOW_CLASS_REGISTERED
@@ -106,7 +200,10 @@ impl Application {
             };
             let class_atom = unsafe { RegisterClassW(&wnd) };
             if class_atom == 0 {
-                panic!("Error registering class");
+                let error = unsafe { GetLastError() };
+                // Let the next application try again.
+                WINDOW_CLASS_REGISTERED.store(false, Ordering::Release);
+                return Err(Error::Hr(HRESULT_FROM_WIN32(error)));
             }
         }
         Ok(())
@@ -120,28 +217,19 @@ impl Application {
         self.state.borrow_mut().windows.remove(&hwnd)
     }
 
-    pub fn run(self, mut handler: Option<Box<dyn AppHandler>>) {
-        unsafe {
-            let run_main_cb_queue_msg_id = WM_RUN_MAIN_CB_QUEUE.get();
+    pub(crate) fn windows(&self) -> Vec<HWND> {
+        self.state.borrow().windows.iter().copied().collect()
+    }
 
-            // Handle windows messages.
-            //
-            // NOTE: Code here will not run when we aren't in charge of the message loop. That
-            // will include when moving or resizing the window, and when showing modal dialogs.
-            loop {
+    /// Run a nested message loop until `done` returns `true`, for `WindowHandle::run_modal`.
+    ///
+    /// A `WM_QUIT` also ends the loop, and is posted again for the loop outside of this one.
+    pub(crate) fn run_modal_loop(&self, done: impl Fn() -> bool) {
+        // The `AppHandler` belongs to the outer loop.
+        let mut handler = None;
+        unsafe {
+            while !done() {
                 let mut msg = mem::MaybeUninit::uninit();
-
-                // Timer messages have a low priority and tend to get delayed. Peeking for them
-                // helps for some reason; see
-                // https://devblogs.microsoft.com/oldnewthing/20191108-00/?p=103080
-                PeekMessageW(
-                    msg.as_mut_ptr(),
-                    ptr::null_mut(),
-                    WM_TIMER,
-                    WM_TIMER,
-                    PM_NOREMOVE,
-                );
-
                 let res = GetMessageW(msg.as_mut_ptr(), ptr::null_mut(), 0, 0);
                 if res <= 0 {
                     if res == -1 {
@@ -149,32 +237,123 @@ impl Application {
                             "GetMessageW failed: {}",
                             Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
                         );
+                    } else {
+                        PostQuitMessage(msg.assume_init().wParam as i32);
                     }
-                    break;
+                    return;
                 }
                 let mut msg: MSG = msg.assume_init();
+                self.dispatch_message(&mut msg, &mut handler);
+            }
+        }
+    }
 
-                if msg.message == run_main_cb_queue_msg_id {
-                    for cb in &mut self.state.borrow_mut().main_thread_cb_queue.1 {
-                        cb(match handler.as_mut() {
-                            Some(handler) => Some(handler.as_mut()),
-                            None => None,
-                        });
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        *self.handler.borrow_mut() = handler;
+    }
+
+    pub fn run(self, handler: Option<Box<dyn AppHandler>>) {
+        self.set_handler(handler);
+        // NOTE: Code here will not run when we aren't in charge of the message loop. That
+        // will include when moving or resizing the window, and when showing modal dialogs.
+        while self.run_loop_iteration(LoopMode::Wait) == LoopStatus::Continue {}
+    }
+
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        if self.state.borrow().exited {
+            return LoopStatus::Exit;
+        }
+        let mut handler = self.handler.borrow_mut();
+        unsafe {
+            let mut msg = mem::MaybeUninit::uninit();
+
+            // Timer messages have a low priority and tend to get delayed. Peeking for them
+            // helps for some reason; see
+            // https://devblogs.microsoft.com/oldnewthing/20191108-00/?p=103080
+            PeekMessageW(
+                msg.as_mut_ptr(),
+                ptr::null_mut(),
+                WM_TIMER,
+                WM_TIMER,
+                PM_NOREMOVE,
+            );
+
+            // Wait for a message, unless there are already some queued. This includes paints
+            // and timers which are due.
+            let idle = mode != LoopMode::Poll
+                && PeekMessageW(msg.as_mut_ptr(), ptr::null_mut(), 0, 0, PM_NOREMOVE) == 0;
+            if idle {
+                let timeout = match mode {
+                    LoopMode::WaitTimeout(timeout) => {
+                        timeout.as_millis().min((INFINITE - 1) as u128) as DWORD
                     }
+                    _ => INFINITE,
+                };
+                if let Some(handler) = handler.as_mut() {
+                    handler.idle();
+                }
+                MsgWaitForMultipleObjectsEx(
+                    0,
+                    ptr::null(),
+                    timeout,
+                    QS_ALLINPUT,
+                    MWMO_INPUTAVAILABLE,
+                );
+                if let Some(handler) = handler.as_mut() {
+                    handler.idle_ended();
                 }
+            }
 
-                let accels = accels::find_accels(GetAncestor(msg.hwnd, GA_ROOT));
-                let translated = accels.map_or(false, |it| {
-                    TranslateAcceleratorW(msg.hwnd, it.handle(), &mut msg) != 0
-                });
-                if !translated {
-                    TranslateMessage(&msg);
-                    DispatchMessageW(&msg);
+            while PeekMessageW(msg.as_mut_ptr(), ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+                let mut msg: MSG = msg.assume_init();
+                if msg.message == WM_QUIT {
+                    self.state.borrow_mut().exited = true;
+                    self.destroy_remaining_windows();
+                    handler.take();
+                    return LoopStatus::Exit;
+                }
+                self.dispatch_message(&mut msg, &mut handler);
+            }
+        }
+        LoopStatus::Continue
+    }
+
+    /// Destroy the windows which are still open once the loop has stopped, so that their
+    /// handlers are told, such as when `WM_QUIT` was posted by someone else.
+    fn destroy_remaining_windows(&self) {
+        for hwnd in self.windows() {
+            // Owned windows are destroyed along with their owner.
+            unsafe {
+                if IsWindow(hwnd) != FALSE {
+                    DestroyWindow(hwnd);
                 }
             }
         }
     }
 
+    /// Handle a message which was taken from the queue by `run_loop_iteration`.
+    unsafe fn dispatch_message(&self, msg: &mut MSG, handler: &mut Option<Box<dyn AppHandler>>) {
+        if msg.message == WM_RUN_MAIN_CB_QUEUE.get() {
+            for cb in &mut self.state.borrow_mut().main_thread_cb_queue.1 {
+                cb(match handler.as_mut() {
+                    Some(handler) => Some(handler.as_mut()),
+                    None => None,
+                });
+            }
+        } else if msg.message == WM_EVENT_SOURCE_SIGNALLED.get() {
+            self.event_source_signalled(msg.wParam, handler);
+        }
+
+        let accels = accels::find_accels(GetAncestor(msg.hwnd, GA_ROOT));
+        let translated = accels.map_or(false, |it| {
+            TranslateAcceleratorW(msg.hwnd, it.handle(), msg) != 0
+        });
+        if !translated {
+            TranslateMessage(msg);
+            DispatchMessageW(msg);
+        }
+    }
+
     pub fn quit(&self) {
         if let Ok(mut state) = self.state.try_borrow_mut() {
             if !state.quitting {
@@ -202,6 +381,10 @@ impl Application {
         }
     }
 
+    pub fn set_app_id(&self, _app_id: &str) {
+        // Ignored
+    }
+
     pub fn clipboard(&self) -> Clipboard {
         Clipboard
     }
@@ -221,12 +404,657 @@ impl Application {
         })
     }
 
+    pub fn get_locales() -> Vec<String> {
+        let mut count: ULONG = 0;
+        let mut len: ULONG = 0;
+        let mut locales = Vec::new();
+        unsafe {
+            // The first call gets the length of the list
+            if GetUserPreferredUILanguages(MUI_LANGUAGE_NAME, &mut count, ptr::null_mut(), &mut len)
+                != FALSE
+            {
+                let mut buf = vec![0u16; len as usize];
+                if GetUserPreferredUILanguages(
+                    MUI_LANGUAGE_NAME,
+                    &mut count,
+                    buf.as_mut_ptr(),
+                    &mut len,
+                ) != FALSE
+                {
+                    // The list is separated by nulls, and ends with two of them
+                    locales = buf
+                        .split(|c| *c == 0)
+                        .filter_map(|language| String::from_utf16(language).ok())
+                        .filter_map(|language| common_util::normalize_locale(&language))
+                        .collect();
+                }
+            }
+        }
+        if locales.is_empty() {
+            locales.push(Self::get_locale());
+        }
+        locales
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        unsafe {
+            // This is right to left if the application has asked for it with
+            // `SetProcessDefaultLayout`
+            let mut layout: DWORD = 0;
+            if GetProcessDefaultLayout(&mut layout) != FALSE && layout & LAYOUT_RTL != 0 {
+                return UiDirection::Rtl;
+            }
+            // Otherwise it follows the language Windows itself is shown in, where 1 is right
+            // to left, and the other layouts are vertical or left to right
+            let language = Self::get_locales().swap_remove(0).to_wide();
+            let mut reading_layout: DWORD = 0;
+            let len = GetLocaleInfoEx(
+                language.as_ptr(),
+                LOCALE_IREADINGLAYOUT | LOCALE_RETURN_NUMBER,
+                &mut reading_layout as *mut DWORD as LPWSTR,
+                (mem::size_of::<DWORD>() / mem::size_of::<u16>()) as _,
+            );
+            if len > 0 && reading_layout == 1 {
+                UiDirection::Rtl
+            } else {
+                UiDirection::Ltr
+            }
+        }
+    }
+
     pub fn get_handle(&self) -> Option<AppHandle> {
         Some(AppHandle {
             main_thread_id: unsafe { GetCurrentThreadId() },
             enqueuer: self.state.borrow().main_thread_cb_queue.0.clone(),
         })
     }
+
+    pub fn power_state(&self) -> PowerState {
+        let mut status: SYSTEM_POWER_STATUS = unsafe { mem::zeroed() };
+        if unsafe { GetSystemPowerStatus(&mut status) } == FALSE {
+            tracing::warn!(
+                "GetSystemPowerStatus failed: {}",
+                Error::Hr(HRESULT_FROM_WIN32(unsafe { GetLastError() }))
+            );
+            return PowerState::default();
+        }
+        // 255 means unknown, and a BatteryFlag of 128 means there is no battery.
+        let has_battery = status.BatteryFlag != 128 && status.BatteryFlag != 255;
+        PowerState {
+            on_battery: status.ACLineStatus == 0,
+            battery_level: (has_battery && status.BatteryLifePercent != 255)
+                .then(|| status.BatteryLifePercent as f64 / 100.0),
+            // This is `SystemStatusFlag` in newer SDKs, which is 1 while battery saver is on.
+            low_power_mode: status.Reserved1 == 1,
+        }
+    }
+
+    /// Called by the session window when it receives `PBT_APMPOWERSTATUSCHANGE`, or a change
+    /// to the battery saver status.
+    pub(crate) fn power_status_changed(&self) {
+        let power_state = self.power_state();
+        let mut state = self.state.borrow_mut();
+        if state.power_state == Some(power_state) {
+            return;
+        }
+        state.power_state = Some(power_state);
+        drop(state);
+        // We're inside a window procedure here, so run the handler from the main loop.
+        if let Some(handle) = self.get_handle() {
+            common_util::debounce_power_state(power_state, move |power_state| {
+                handle.run_on_main(move |handler| {
+                    common_util::power_state_changed(handler, power_state)
+                })
+            });
+        }
+    }
+
+    pub fn accessibility_preferences(&self) -> A11yPrefs {
+        self.state.borrow().a11y_prefs
+    }
+
+    pub fn system_fonts(&self) -> SystemFonts {
+        self.state.borrow().system_fonts.clone()
+    }
+
+    pub fn text_scale_factor(&self) -> f64 {
+        self.state.borrow().text_scale_factor
+    }
+
+    pub fn accent_color(&self) -> Option<Color> {
+        self.state.borrow().colors.accent
+    }
+
+    pub fn system_color(&self, color: SystemColor) -> Option<Color> {
+        let colors = self.state.borrow().colors;
+        Some(match color {
+            SystemColor::Highlight => colors.highlight,
+            SystemColor::Window => colors.window,
+            SystemColor::Text => colors.text,
+        })
+    }
+
+    pub fn text_rendering_prefs(&self) -> TextRenderingPrefs {
+        read_text_rendering_prefs()
+    }
+
+    pub fn ui_metrics(&self) -> UiMetrics {
+        read_ui_metrics()
+    }
+
+    /// Called by windows when they receive `WM_SYSCOLORCHANGE` or
+    /// `WM_DWMCOLORIZATIONCOLORCHANGED`, and by `settings_changed`.
+    ///
+    /// Every top-level window is told about these, so only the first call for a change
+    /// reaches the handler.
+    pub(crate) fn colors_changed(&self) {
+        let colors = read_colors();
+        let mut state = self.state.borrow_mut();
+        if state.colors == colors {
+            return;
+        }
+        state.colors = colors;
+        drop(state);
+        if let Some(handle) = self.get_handle() {
+            handle.run_on_main(move |handler| {
+                if let Some(handler) = handler {
+                    handler.accent_color_changed(colors.accent);
+                }
+            });
+        }
+    }
+
+    /// Called by windows when they receive `WM_SETTINGCHANGE`.
+    pub(crate) fn settings_changed(&self) {
+        let prefs = read_accessibility_preferences();
+        let fonts = read_system_fonts();
+        let text_scale_factor = read_text_scale_factor();
+        let mut state = self.state.borrow_mut();
+        let prefs_changed = state.a11y_prefs != prefs;
+        let fonts_changed = state.system_fonts != fonts;
+        let text_scale_changed = state.text_scale_factor != text_scale_factor;
+        state.a11y_prefs = prefs;
+        state.system_fonts = fonts.clone();
+        state.text_scale_factor = text_scale_factor;
+        drop(state);
+        let Some(handle) = self.get_handle() else {
+            return;
+        };
+        if prefs_changed {
+            handle.run_on_main(move |handler| {
+                if let Some(handler) = handler {
+                    handler.accessibility_preferences_changed(prefs);
+                }
+            });
+        }
+        if fonts_changed {
+            handle.run_on_main(move |handler| {
+                if let Some(handler) = handler {
+                    handler.system_fonts_changed(fonts);
+                }
+            });
+        }
+        if text_scale_changed {
+            handle.run_on_main(move |handler| {
+                if let Some(handler) = handler {
+                    handler.text_scale_factor_changed(text_scale_factor);
+                }
+            });
+        }
+        // The app mode is a setting too.
+        self.colors_changed();
+    }
+
+    pub fn add_event_source(
+        &self,
+        handle: HANDLE,
+        callback: Box<dyn FnMut(Option<&mut dyn AppHandler>)>,
+    ) -> EventSourceToken {
+        let token = EventSourceToken::next();
+        let context = Box::new(WaitContext {
+            main_thread_id: unsafe { GetCurrentThreadId() },
+            token,
+        });
+        let Some(wait) = register_wait(handle, &context) else {
+            return token;
+        };
+        self.state.borrow_mut().event_sources.insert(
+            token,
+            EventSource {
+                handle,
+                wait,
+                context,
+                callback: Some(callback),
+            },
+        );
+        token
+    }
+
+    pub fn remove_event_source(&self, token: EventSourceToken) {
+        let source = self.state.borrow_mut().event_sources.remove(&token);
+        if let Some(source) = source {
+            // This waits for a running thread pool callback, after which the context is unused.
+            if unsafe { UnregisterWaitEx(source.wait, INVALID_HANDLE_VALUE) } == FALSE {
+                tracing::warn!(
+                    "UnregisterWaitEx failed: {}",
+                    Error::Hr(HRESULT_FROM_WIN32(unsafe { GetLastError() }))
+                );
+            }
+        }
+    }
+
+    fn event_source_signalled(&self, token: WPARAM, handler: &mut Option<Box<dyn AppHandler>>) {
+        let token = EventSourceToken::from_raw(token as u64);
+        // The source may have been removed after the message was posted.
+        let callback = match self.state.borrow_mut().event_sources.get_mut(&token) {
+            Some(source) => source.callback.take(),
+            None => return,
+        };
+        let Some(mut callback) = callback else {
+            return;
+        };
+        callback(match handler.as_mut() {
+            Some(handler) => Some(handler.as_mut()),
+            None => None,
+        });
+        let mut state = self.state.borrow_mut();
+        if let Some(source) = state.event_sources.get_mut(&token) {
+            source.callback = Some(callback);
+            // The last wait has finished, so wait for the next signal.
+            unsafe { UnregisterWait(source.wait) };
+            match register_wait(source.handle, &source.context) {
+                Some(wait) => source.wait = wait,
+                None => {
+                    state.event_sources.remove(&token);
+                }
+            }
+        }
+    }
+
+    pub fn acquire_wake_lock(
+        &self,
+        kind: WakeLockKind,
+        reason: &str,
+    ) -> Result<SleepGuard, ShellError> {
+        SleepGuard::new(kind, reason)
+    }
+
+    pub fn send_notification(
+        &self,
+        notification: &Notification,
+        id: NotificationId,
+    ) -> Result<(), ShellError> {
+        let handle = self.get_handle().unwrap();
+        let mut state = self.state.borrow_mut();
+        if state.notification_window.is_none() {
+            state.notification_window = Some(NotificationWindow::new(handle)?);
+        }
+        state
+            .notification_window
+            .as_ref()
+            .unwrap()
+            .send(notification, id)
+    }
+
+    pub fn close_notification(&self, id: NotificationId) {
+        if let Some(window) = &self.state.borrow().notification_window {
+            window.close(id);
+        }
+    }
+
+    pub fn add_recent_document(&self, path: &Path) {
+        jump_list::add_recent_document(path);
+    }
+}
+
+impl crate::platform::windows::ApplicationExt for crate::Application {
+    fn add_event_source(
+        &self,
+        handle: *mut std::ffi::c_void,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> EventSourceToken {
+        self.backend_app
+            .add_event_source(handle as HANDLE, Box::new(callback))
+    }
+
+    fn remove_event_source(&self, token: EventSourceToken) {
+        self.backend_app.remove_event_source(token)
+    }
+
+    fn set_jump_list(&self, list: JumpList) {
+        if let Err(e) = jump_list::set_jump_list(&list) {
+            tracing::warn!("failed to set the jump list: {}", e);
+        }
+    }
+}
+
+fn read_accessibility_preferences() -> A11yPrefs {
+    unsafe {
+        let mut animations: BOOL = TRUE;
+        if SystemParametersInfoW(
+            SPI_GETCLIENTAREAANIMATION,
+            0,
+            &mut animations as *mut BOOL as *mut _,
+            0,
+        ) == FALSE
+        {
+            tracing::warn!("failed to read the client area animation setting");
+        }
+        let mut high_contrast = HIGHCONTRASTW {
+            cbSize: mem::size_of::<HIGHCONTRASTW>() as u32,
+            ..mem::zeroed()
+        };
+        if SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            high_contrast.cbSize,
+            &mut high_contrast as *mut HIGHCONTRASTW as *mut _,
+            0,
+        ) == FALSE
+        {
+            tracing::warn!("failed to read the high contrast setting");
+        }
+        // This is the "Transparency effects" switch in the personalization settings. It's missing
+        // before Windows 10, which has no transparency effects to turn off.
+        let mut transparency: DWORD = 1;
+        let mut size = mem::size_of::<DWORD>() as DWORD;
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            "Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"
+                .to_wide()
+                .as_ptr(),
+            "EnableTransparency".to_wide().as_ptr(),
+            RRF_RT_REG_DWORD,
+            ptr::null_mut(),
+            &mut transparency as *mut DWORD as *mut _,
+            &mut size,
+        );
+        A11yPrefs {
+            reduce_motion: animations == FALSE,
+            high_contrast: high_contrast.dwFlags & HCF_HIGHCONTRASTON != 0,
+            prefer_reduced_transparency: transparency == 0,
+        }
+    }
+}
+
+/// The DPI that system metrics are given at. These don't follow the DPI of any one monitor.
+fn system_dpi() -> f64 {
+    match OPTIONAL_FUNCTIONS.GetDpiForSystem {
+        Some(func) => unsafe { func() as f64 },
+        None => SCALE_TARGET_DPI,
+    }
+}
+
+/// The sizes of window frames and the fonts used in them, which also apply to most controls.
+///
+/// If these can't be read, everything is zeroed.
+fn non_client_metrics() -> NONCLIENTMETRICSW {
+    unsafe {
+        let mut metrics = NONCLIENTMETRICSW {
+            cbSize: mem::size_of::<NONCLIENTMETRICSW>() as u32,
+            ..mem::zeroed()
+        };
+        if SystemParametersInfoW(
+            SPI_GETNONCLIENTMETRICS,
+            metrics.cbSize,
+            &mut metrics as *mut NONCLIENTMETRICSW as *mut _,
+            0,
+        ) == FALSE
+        {
+            tracing::warn!("failed to read the non-client metrics");
+            metrics = mem::zeroed();
+        }
+        metrics
+    }
+}
+
+fn read_system_fonts() -> SystemFonts {
+    let metrics = non_client_metrics();
+    let dpi = system_dpi();
+    let ui = font_description(&metrics.lfMessageFont, dpi);
+    SystemFonts {
+        // Windows has no monospace system font, but Consolas has shipped since Vista.
+        monospace: FontDescription {
+            family: "Consolas".into(),
+            size: ui.size,
+        },
+        menu: font_description(&metrics.lfMenuFont, dpi),
+        title: font_description(&metrics.lfCaptionFont, dpi),
+        small: font_description(&metrics.lfStatusFont, dpi),
+        ui,
+    }
+}
+
+/// The "Text size" in the accessibility settings, which Windows 10 added. It's only in the
+/// registry once it has been changed.
+fn read_text_scale_factor() -> f64 {
+    let mut percent: DWORD = 100;
+    let mut size = mem::size_of::<DWORD>() as DWORD;
+    unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            "Software\\Microsoft\\Accessibility".to_wide().as_ptr(),
+            "TextScaleFactor".to_wide().as_ptr(),
+            RRF_RT_REG_DWORD,
+            ptr::null_mut(),
+            &mut percent as *mut DWORD as *mut _,
+            &mut size,
+        );
+    }
+    // The setting goes from 100% to 225%.
+    percent.clamp(100, 225) as f64 / 100.0
+}
+
+fn font_description(font: &LOGFONTW, dpi: f64) -> FontDescription {
+    let len = font
+        .lfFaceName
+        .iter()
+        .position(|c| *c == 0)
+        .unwrap_or(font.lfFaceName.len());
+    if len == 0 {
+        // The metrics couldn't be read, so use the default of 9pt Segoe UI.
+        return FontDescription {
+            family: "Segoe UI".into(),
+            size: 12.0,
+        };
+    }
+    // A negative height is the size of the em square, which is what other platforms report.
+    // A positive height includes the internal leading, but is close enough.
+    FontDescription {
+        family: String::from_utf16_lossy(&font.lfFaceName[..len]),
+        size: font.lfHeight.abs() as f64 * SCALE_TARGET_DPI / dpi,
+    }
+}
+
+/// The accent color, and the system colors in the current app mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Colors {
+    accent: Option<Color>,
+    highlight: Color,
+    window: Color,
+    text: Color,
+}
+
+fn read_colors() -> Colors {
+    unsafe {
+        let mut colorization: DWORD = 0;
+        let mut opaque: BOOL = FALSE;
+        // This fails while desktop composition is off, before Windows 8. The color is reported
+        // as `0xAARRGGBB`, but its alpha is how much it is blended with the window, so is
+        // dropped.
+        let accent =
+            SUCCEEDED(DwmGetColorizationColor(&mut colorization, &mut opaque)).then(|| {
+                let [_, r, g, b] = colorization.to_be_bytes();
+                Color::rgb8(r, g, b)
+            });
+        let sys_color = |index| {
+            let [r, g, b, _] = GetSysColor(index).to_le_bytes();
+            Color::rgb8(r, g, b)
+        };
+        // The system colors are always light, so dark mode uses the colors of dark WinUI apps.
+        let (background, text) = if window::system_prefers_dark_theme() {
+            (Color::rgb8(0x20, 0x20, 0x20), Color::rgb8(0xff, 0xff, 0xff))
+        } else {
+            (sys_color(COLOR_WINDOW), sys_color(COLOR_WINDOWTEXT))
+        };
+        Colors {
+            accent,
+            highlight: sys_color(COLOR_HIGHLIGHT),
+            window: background,
+            text,
+        }
+    }
+}
+
+fn read_text_rendering_prefs() -> TextRenderingPrefs {
+    // Each of these is left at its default if it can't be read.
+    let read = |action, default: UINT| unsafe {
+        let mut value = default;
+        SystemParametersInfoW(action, 0, &mut value as *mut UINT as *mut _, 0);
+        value
+    };
+    let antialiasing = if read(SPI_GETFONTSMOOTHING, TRUE as UINT) == FALSE as UINT {
+        TextAntialiasing::None
+    } else if read(SPI_GETFONTSMOOTHINGTYPE, FE_FONTSMOOTHINGCLEARTYPE) == FE_FONTSMOOTHINGCLEARTYPE
+    {
+        // ClearType is only ever horizontal.
+        match read(
+            SPI_GETFONTSMOOTHINGORIENTATION,
+            FE_FONTSMOOTHINGORIENTATIONRGB,
+        ) {
+            FE_FONTSMOOTHINGORIENTATIONBGR => TextAntialiasing::Subpixel(SubpixelOrder::Bgr),
+            _ => TextAntialiasing::Subpixel(SubpixelOrder::Rgb),
+        }
+    } else {
+        TextAntialiasing::Grayscale
+    };
+    TextRenderingPrefs {
+        antialiasing,
+        // GDI and DirectWrite always hint the system's fonts.
+        hinting: true,
+        // The ClearType contrast is the gamma times 1000, between 1000 and 2200.
+        gamma: read(SPI_GETFONTSMOOTHINGCONTRAST, 1400) as f64 / 1000.0,
+    }
+}
+
+fn read_ui_metrics() -> UiMetrics {
+    unsafe {
+        let scrollbar_width = match non_client_metrics().iScrollWidth {
+            0 => GetSystemMetrics(SM_CXVSCROLL),
+            width => width,
+        };
+        let mut menu_show_delay: DWORD = 400;
+        if SystemParametersInfoW(
+            SPI_GETMENUSHOWDELAY,
+            0,
+            &mut menu_show_delay as *mut DWORD as *mut _,
+            0,
+        ) == FALSE
+        {
+            tracing::warn!("failed to read the menu show delay");
+        }
+        let mut caret_width: DWORD = 1;
+        if SystemParametersInfoW(
+            SPI_GETCARETWIDTH,
+            0,
+            &mut caret_width as *mut DWORD as *mut _,
+            0,
+        ) == FALSE
+        {
+            tracing::warn!("failed to read the caret width");
+        }
+        let dpi_scale = SCALE_TARGET_DPI / system_dpi();
+        let caret_blink = GetCaretBlinkTime();
+        UiMetrics {
+            scrollbar_width: scrollbar_width as f64 * dpi_scale,
+            caret_width: caret_width.max(1) as f64 * dpi_scale,
+            // An infinite blink time is how the caret is told not to blink.
+            caret_blink: (caret_blink != 0 && caret_blink != INFINITE)
+                .then(|| Duration::from_millis(caret_blink.into())),
+            drag_threshold: GetSystemMetrics(SM_CXDRAG) as f64 * dpi_scale,
+            menu_show_delay: Duration::from_millis(menu_show_delay.into()),
+        }
+    }
+}
+
+/// A power request, which is cleared when dropped.
+///
+/// Unlike the execution state set by `SetThreadExecutionState`, each request is separate, and
+/// its reason is shown to the user by `powercfg /requests`.
+pub(crate) struct SleepGuard(HANDLE);
+
+impl SleepGuard {
+    fn new(kind: WakeLockKind, reason: &str) -> Result<SleepGuard, ShellError> {
+        let mut reason = reason.to_wide();
+        let mut context = REASON_CONTEXT {
+            Version: POWER_REQUEST_CONTEXT_VERSION,
+            Flags: POWER_REQUEST_CONTEXT_SIMPLE_STRING,
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            *context.Reason.SimpleString_mut() = reason.as_mut_ptr();
+            let request = PowerCreateRequest(&mut context);
+            if request == INVALID_HANDLE_VALUE {
+                return Err(Error::Hr(HRESULT_FROM_WIN32(GetLastError())).into());
+            }
+            // Keeping the display on also keeps the system awake
+            let request_types: &[POWER_REQUEST_TYPE] = match kind {
+                WakeLockKind::Display => &[PowerRequestDisplayRequired, PowerRequestSystemRequired],
+                WakeLockKind::System => &[PowerRequestSystemRequired],
+            };
+            for &request_type in request_types {
+                if PowerSetRequest(request, request_type) == FALSE {
+                    let error = Error::Hr(HRESULT_FROM_WIN32(GetLastError()));
+                    CloseHandle(request);
+                    return Err(error.into());
+                }
+            }
+            Ok(SleepGuard(request))
+        }
+    }
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        // Closing the request clears it
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
+
+/// Ask the thread pool to wait for `handle` to be signalled once.
+fn register_wait(handle: HANDLE, context: &WaitContext) -> Option<HANDLE> {
+    let mut wait = ptr::null_mut();
+    let context = context as *const WaitContext as PVOID;
+    let res = unsafe {
+        RegisterWaitForSingleObject(
+            &mut wait,
+            handle,
+            Some(wait_callback),
+            context,
+            INFINITE,
+            WT_EXECUTEONLYONCE,
+        )
+    };
+    if res == FALSE {
+        tracing::warn!(
+            "RegisterWaitForSingleObject failed: {}",
+            Error::Hr(HRESULT_FROM_WIN32(unsafe { GetLastError() }))
+        );
+        return None;
+    }
+    Some(wait)
+}
+
+/// Runs on a thread pool thread, so all it does is tell the main thread.
+unsafe extern "system" fn wait_callback(context: PVOID, _timed_out: BOOLEAN) {
+    let context = &*(context as *const WaitContext);
+    PostThreadMessageW(
+        context.main_thread_id,
+        WM_EVENT_SOURCE_SIGNALLED.get(),
+        context.token.into_raw() as WPARAM,
+        0,
+    );
 }
 
 type MainThreadCb = Box<dyn FnOnce(Option<&mut dyn AppHandler>) + Send>;
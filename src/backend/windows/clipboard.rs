@@ -72,6 +72,14 @@ impl Clipboard {
         });
     }
 
+    /// There is no primary selection here.
+    pub fn put_primary(&mut self, _s: impl AsRef<str>) {}
+
+    /// There is no primary selection here.
+    pub fn get_primary(&self) -> Option<String> {
+        None
+    }
+
     /// Get a string from the system clipboard, if one is available.
     pub fn get_string(&self) -> Option<String> {
         with_clipboard(|| unsafe {
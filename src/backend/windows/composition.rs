@@ -0,0 +1,73 @@
+//! DirectComposition, for windows built with [`PresentMode::Composition`].
+//!
+//! These windows have no redirection surface. Instead, the application presents a swapchain
+//! created for composition, and sets it as the content of a visual which is the root of the
+//! window's composition target.
+//!
+//! [`PresentMode::Composition`]: crate::platform::windows::PresentMode::Composition
+
+use std::ptr::{null, null_mut};
+
+use winapi::shared::minwindef::FALSE;
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::E_NOTIMPL;
+use winapi::um::dcomp::{IDCompositionDevice, IDCompositionTarget, IDCompositionVisual};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use super::error::Error;
+use super::util::{as_result, OPTIONAL_FUNCTIONS};
+
+pub(crate) struct Composition {
+    device: ComPtr<IDCompositionDevice>,
+    // Kept alive for as long as the window shows the visual.
+    _target: ComPtr<IDCompositionTarget>,
+    visual: ComPtr<IDCompositionVisual>,
+}
+
+impl Composition {
+    /// Make an empty visual the root of `hwnd`'s composition target.
+    ///
+    /// The window must have been created with `WS_EX_NOREDIRECTIONBITMAP`, or the redirection
+    /// surface is drawn over the visual.
+    pub(crate) fn new(hwnd: HWND) -> Result<Composition, Error> {
+        let create_device = OPTIONAL_FUNCTIONS
+            .DCompositionCreateDevice
+            .ok_or(Error::Hr(E_NOTIMPL))?;
+        unsafe {
+            // The DXGI device is only needed for composition surfaces, which we don't make.
+            let mut device = null_mut();
+            as_result(create_device(
+                null(),
+                &IDCompositionDevice::uuidof(),
+                &mut device,
+            ))?;
+            let device = ComPtr::from_raw(device as *mut IDCompositionDevice);
+            let mut target = null_mut();
+            as_result(device.CreateTargetForHwnd(hwnd, FALSE, &mut target))?;
+            let target = ComPtr::from_raw(target);
+            let mut visual = null_mut();
+            as_result(device.CreateVisual(&mut visual))?;
+            let visual = ComPtr::from_raw(visual);
+            as_result(target.SetRoot(visual.as_raw()))?;
+            as_result(device.Commit())?;
+            Ok(Composition {
+                device,
+                _target: target,
+                visual,
+            })
+        }
+    }
+
+    /// The `IDCompositionVisual` which the application sets its swapchain as the content of.
+    pub(crate) fn visual(&self) -> *mut IDCompositionVisual {
+        self.visual.as_raw()
+    }
+
+    /// Show the changes which have been made to the visual.
+    pub(crate) fn commit(&self) {
+        if let Err(e) = as_result(unsafe { self.device.Commit() }) {
+            tracing::warn!("failed to commit the window's composition: {e}");
+        }
+    }
+}
@@ -34,8 +34,6 @@ pub enum Error {
     Hr(HRESULT),
     // Maybe include the full error from the direct2d crate.
     Direct2D,
-    /// A function is available on newer version of windows.
-    OldWindows,
     /// The `hwnd` pointer was null.
     NullHwnd,
 }
@@ -76,7 +74,6 @@ impl fmt::Display for Error {
                 Ok(())
             }
             Error::Direct2D => write!(f, "Direct2D error"),
-            Error::OldWindows => write!(f, "Attempted newer API on older Windows"),
             Error::NullHwnd => write!(f, "Window handle is Null"),
         }
     }
@@ -0,0 +1,228 @@
+//! The jump list of the application's taskbar button, and the recent documents it can show.
+//!
+//! The list is built with `ICustomDestinationList`, out of shell links which start the
+//! executable again with each item's arguments.
+
+#![allow(non_upper_case_globals, non_snake_case)]
+
+use std::mem;
+use std::path::Path;
+use std::ptr::null_mut;
+
+use tracing::warn;
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{GUID, REFIID};
+use winapi::shared::minwindef::{INT, UINT};
+use winapi::shared::ntdef::{HRESULT, LPCWSTR};
+use winapi::shared::wtypes::{PROPERTYKEY, VARTYPE, VT_LPWSTR};
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::CoCreateInstance;
+use winapi::um::objectarray::{IObjectArray, IObjectCollection};
+use winapi::um::propidl::PROPVARIANT;
+use winapi::um::propsys::IPropertyStore;
+use winapi::um::shlobj::{SHAddToRecentDocs, SHARD_PATHW};
+use winapi::um::shobjidl_core::IShellLinkW;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::{Interface, DEFINE_GUID, RIDL};
+use wio::com::ComPtr;
+
+use super::error::Error;
+use super::util::{as_result, init_com, ToWide};
+use crate::platform::windows::{JumpList, JumpListItem, KnownCategory};
+
+// TODO: remove these when they get added to winapi
+DEFINE_GUID! {CLSID_DestinationList,
+0x77F1_0CF0, 0x3DB5, 0x4966, 0xB5, 0x20, 0xB7, 0xC5, 0x4F, 0xD3, 0x5E, 0xD6}
+DEFINE_GUID! {CLSID_EnumerableObjectCollection,
+0x2D34_68C1, 0x36A7, 0x43B6, 0xAC, 0x24, 0xD3, 0xF0, 0x2F, 0xD9, 0x60, 0x7A}
+DEFINE_GUID! {CLSID_ShellLink,
+0x0002_1401, 0x0000, 0x0000, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46}
+
+const KDC_FREQUENT: UINT = 1;
+const KDC_RECENT: UINT = 2;
+
+/// `System.Title`, which is the text shown for a shell link in a jump list.
+const PKEY_Title: PROPERTYKEY = PROPERTYKEY {
+    fmtid: GUID {
+        Data1: 0xF29F_85E0,
+        Data2: 0x4FF9,
+        Data3: 0x1068,
+        Data4: [0xAB, 0x91, 0x08, 0x00, 0x2B, 0x27, 0xB3, 0xD9],
+    },
+    pid: 2,
+};
+
+RIDL! {#[uuid(0x6332_DEBF, 0x87B5, 0x4670, 0x90, 0xC0, 0x5E, 0x57, 0xB4, 0x08, 0xA4, 0x9E)]
+interface ICustomDestinationList(ICustomDestinationListVtbl): IUnknown(IUnknownVtbl) {
+    fn SetAppID(
+        pszAppID: LPCWSTR,
+    ) -> HRESULT,
+    fn BeginList(
+        pcMinSlots: *mut UINT,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT,
+    fn AppendCategory(
+        pszCategory: LPCWSTR,
+        poa: *mut IObjectArray,
+    ) -> HRESULT,
+    fn AppendKnownCategory(
+        category: UINT,
+    ) -> HRESULT,
+    fn AddUserTasks(
+        poa: *mut IObjectArray,
+    ) -> HRESULT,
+    fn CommitList() -> HRESULT,
+    fn GetRemovedDestinations(
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT,
+    fn DeleteList(
+        pszAppID: LPCWSTR,
+    ) -> HRESULT,
+    fn AbortList() -> HRESULT,
+}}
+
+/// Replace the application's jump list with `list`.
+pub(crate) fn set_jump_list(list: &JumpList) -> Result<(), Error> {
+    init_com()?;
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe.to_wide(),
+        Err(e) => {
+            warn!("failed to find the executable for the jump list: {}", e);
+            return Ok(());
+        }
+    };
+    unsafe {
+        let dest_list: ComPtr<ICustomDestinationList> = create_instance(&CLSID_DestinationList)?;
+        let mut min_slots = 0;
+        let mut removed: *mut IObjectArray = null_mut();
+        as_result(dest_list.BeginList(
+            &mut min_slots,
+            &IObjectArray::uuidof(),
+            &mut removed as *mut *mut IObjectArray as *mut _,
+        ))?;
+        let removed = removed_arguments(ComPtr::from_raw(removed));
+
+        if let Some(category) = list.known_category {
+            let category = match category {
+                KnownCategory::Recent => KDC_RECENT,
+                KnownCategory::Frequent => KDC_FREQUENT,
+            };
+            // This fails if the user has turned off the recent items.
+            if let Err(e) = as_result(dest_list.AppendKnownCategory(category)) {
+                warn!("failed to add the known category to the jump list: {}", e);
+            }
+        }
+        for category in &list.categories {
+            let items = create_collection(&exe, &category.items, &removed)?;
+            let title = category.title.to_wide();
+            if let Err(e) = as_result(dest_list.AppendCategory(title.as_ptr(), items.as_raw())) {
+                warn!(
+                    "failed to add the category {:?} to the jump list: {}",
+                    category.title, e
+                );
+            }
+        }
+        if !list.tasks.is_empty() {
+            let tasks = create_collection(&exe, &list.tasks, &removed)?;
+            as_result(dest_list.AddUserTasks(tasks.as_raw()))?;
+        }
+        as_result(dest_list.CommitList())
+    }
+}
+
+/// Add `path` to the documents which the shell has recently opened with the application.
+pub(crate) fn add_recent_document(path: &Path) {
+    let path = path.to_wide();
+    unsafe { SHAddToRecentDocs(SHARD_PATHW, path.as_ptr() as *const _) };
+}
+
+unsafe fn create_instance<T: Interface>(clsid: &GUID) -> Result<ComPtr<T>, Error> {
+    let mut ptr: *mut T = null_mut();
+    as_result(CoCreateInstance(
+        clsid,
+        null_mut(),
+        CLSCTX_INPROC_SERVER,
+        &T::uuidof(),
+        &mut ptr as *mut *mut T as *mut _,
+    ))?;
+    Ok(ComPtr::from_raw(ptr))
+}
+
+/// The arguments of the items which the user removed from the list.
+///
+/// Adding one of them to the list again would fail, so they are left out.
+unsafe fn removed_arguments(removed: ComPtr<IObjectArray>) -> Vec<Vec<u16>> {
+    let mut count = 0;
+    if removed.GetCount(&mut count) < 0 {
+        return Vec::new();
+    }
+    (0..count)
+        .filter_map(|i| {
+            let mut link: *mut IShellLinkW = null_mut();
+            let hr = removed.GetAt(
+                i,
+                &IShellLinkW::uuidof(),
+                &mut link as *mut *mut IShellLinkW as *mut _,
+            );
+            if hr < 0 {
+                return None;
+            }
+            let link = ComPtr::from_raw(link);
+            let mut arguments = vec![0u16; 1024];
+            if link.GetArguments(arguments.as_mut_ptr(), arguments.len() as INT) < 0 {
+                return None;
+            }
+            let len = arguments
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(arguments.len());
+            arguments.truncate(len);
+            Some(arguments)
+        })
+        .collect()
+}
+
+unsafe fn create_collection(
+    exe: &[u16],
+    items: &[JumpListItem],
+    removed: &[Vec<u16>],
+) -> Result<ComPtr<IObjectArray>, Error> {
+    let collection: ComPtr<IObjectCollection> = create_instance(&CLSID_EnumerableObjectCollection)?;
+    for item in items {
+        if removed.contains(&item.arguments.to_wide_sized()) {
+            continue;
+        }
+        let link = create_link(exe, item)?;
+        as_result(collection.AddObject(link.as_raw() as *mut IUnknown))?;
+    }
+    Ok(collection.cast()?)
+}
+
+unsafe fn create_link(exe: &[u16], item: &JumpListItem) -> Result<ComPtr<IShellLinkW>, Error> {
+    let link: ComPtr<IShellLinkW> = create_instance(&CLSID_ShellLink)?;
+    as_result(link.SetPath(exe.as_ptr()))?;
+    as_result(link.SetArguments(item.arguments.to_wide().as_ptr()))?;
+    as_result(link.SetDescription(item.description.to_wide().as_ptr()))?;
+    if let Some((path, index)) = &item.icon {
+        // The list is still useful without the icon.
+        if path.is_file() {
+            if let Err(e) = as_result(link.SetIconLocation(path.to_wide().as_ptr(), *index)) {
+                warn!("failed to set the jump list icon {}: {}", path.display(), e);
+            }
+        } else {
+            warn!("the jump list icon {} doesn't exist", path.display());
+        }
+    }
+
+    let title = item.title.to_wide();
+    let mut value: PROPVARIANT = mem::zeroed();
+    value.vt = VT_LPWSTR as VARTYPE;
+    // The store copies the string, which we own, so the value mustn't be cleared.
+    *value.data.pwszVal_mut() = title.as_ptr() as *mut _;
+    let store = link.cast::<IPropertyStore>()?;
+    as_result(store.SetValue(&PKEY_Title, &value))?;
+    as_result(store.Commit())?;
+    Ok(link)
+}
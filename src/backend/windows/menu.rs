@@ -20,10 +20,13 @@ use std::mem;
 use std::ptr::null;
 
 use winapi::shared::basetsd::*;
+use winapi::shared::minwindef::UINT;
 use winapi::shared::windef::*;
 use winapi::um::winuser::*;
 
+use super::application::Application;
 use super::util::ToWide;
+use crate::application::UiDirection;
 use crate::hotkey::HotKey;
 use crate::keyboard::{KbKey, Modifiers};
 
@@ -80,7 +83,7 @@ impl Menu {
         self.accels.extend(child_accels);
 
         unsafe {
-            let mut flags = MF_POPUP;
+            let mut flags = MF_POPUP | direction_flags();
             if !enabled {
                 flags |= MF_GRAYED;
             }
@@ -108,7 +111,7 @@ impl Menu {
             format_hotkey(key, &mut anno_text);
         }
         unsafe {
-            let mut flags = MF_STRING;
+            let mut flags = MF_STRING | direction_flags();
             if !enabled {
                 flags |= MF_GRAYED;
             }
@@ -146,6 +149,15 @@ impl Menu {
     }
 }
 
+/// Makes the menu's items, and the submenus they open, run right to left when the user
+/// interface does.
+fn direction_flags() -> UINT {
+    match Application::ui_direction() {
+        UiDirection::Ltr => 0,
+        UiDirection::Rtl => MFT_RIGHTORDER,
+    }
+}
+
 /// Convert a hotkey to an accelerator.
 ///
 /// Note that this conversion is dependent on the keyboard map.
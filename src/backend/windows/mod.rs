@@ -17,14 +17,20 @@
 mod accels;
 pub mod application;
 pub mod clipboard;
+mod composition;
 // pub mod dcomp;
 pub mod dialog;
 pub mod error;
+mod jump_list;
 mod keyboard;
 pub mod menu;
 pub mod msgs;
+mod notification;
 //pub mod paint;
+mod pointer;
 pub mod screen;
+mod session;
+mod taskbar;
 mod timers;
 pub mod util;
 pub mod window;
@@ -5,6 +5,12 @@ use winapi::um::winuser::RegisterWindowMessageW;
 use super::util::ToWide;
 
 pub(crate) static WM_RUN_MAIN_CB_QUEUE: LazyMsg = LazyMsg::new("WM_RUN_MAIN_CB_QUEUE");
+/// Posted to the main thread when an event source's handle is signalled, with the source's
+/// token in `wParam`.
+pub(crate) static WM_EVENT_SOURCE_SIGNALLED: LazyMsg = LazyMsg::new("WM_EVENT_SOURCE_SIGNALLED");
+/// Sent by the taskbar when a window's button has been created, after which `ITaskbarList3`
+/// can be used with the window.
+pub(crate) static WM_TASKBAR_BUTTON_CREATED: LazyMsg = LazyMsg::new("TaskbarButtonCreated");
 
 pub(crate) struct LazyMsg {
     // NOTE: we are fine to use the `race` variant of `OnceBox` here because `RegisterWindowMessage`
@@ -0,0 +1,309 @@
+//! Notifications, shown as balloons from a notification area icon.
+//!
+//! Toasts need an AppUserModelID which an installer has registered, so a hidden window adds
+//! an icon to the notification area while a notification is shown, and shows the notification
+//! as its balloon. Windows 10 and later show these balloons as toasts. Only one is shown at a
+//! time, so sending a notification replaces the one before it.
+
+use std::mem;
+use std::ptr::{self, null_mut};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use winapi::shared::minwindef::{HINSTANCE, LOWORD, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::{HICON, HWND};
+use winapi::shared::winerror::HRESULT_FROM_WIN32;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::shellapi::{
+    Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_LARGE_ICON, NIIF_NONE,
+    NIIF_USER, NIM_ADD, NIM_DELETE, NIM_MODIFY, NIM_SETVERSION, NIN_BALLOONHIDE,
+    NIN_BALLOONTIMEOUT, NIN_BALLOONUSERCLICK, NOTIFYICONDATAW, NOTIFYICON_VERSION_4,
+};
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyIcon, DestroyWindow, GetWindowLongPtrW, LoadIconW,
+    LoadImageW, RegisterClassW, SetWindowLongPtrW, CREATESTRUCTW, GWLP_USERDATA, HWND_MESSAGE,
+    IDI_APPLICATION, IMAGE_ICON, LR_DEFAULTSIZE, LR_LOADFROMFILE, WM_CREATE, WM_NCDESTROY, WM_USER,
+    WNDCLASSW,
+};
+
+use crate::error::Error as ShellError;
+use crate::{Notification, NotificationId};
+
+use super::application::AppHandle;
+use super::error::Error;
+use super::util::ToWide;
+
+const CLASS_NAME: &str = "glazier notifications";
+
+/// Sent by the notification area when the user interacts with the balloon.
+const WM_NOTIFY_ICON: UINT = WM_USER + 1;
+
+/// Used to ensure the window class is registered only once per process.
+static CLASS_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// The hidden window, which is destroyed when this is dropped.
+pub(crate) struct NotificationWindow(HWND);
+
+/// Owned by the window, and freed when it's destroyed.
+struct NotificationState {
+    handle: AppHandle,
+    /// The notification being shown, if the icon is in the notification area.
+    current: Option<NotificationId>,
+    /// The image shown in the balloon, which is destroyed with the icon.
+    image: HICON,
+}
+
+impl NotificationWindow {
+    pub(crate) fn new(handle: AppHandle) -> Result<NotificationWindow, Error> {
+        let class_name = CLASS_NAME.to_wide();
+        unsafe {
+            if CLASS_REGISTERED
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let wnd = WNDCLASSW {
+                    lpfnWndProc: Some(notification_proc),
+                    lpszClassName: class_name.as_ptr(),
+                    ..mem::zeroed()
+                };
+                if RegisterClassW(&wnd) == 0 {
+                    CLASS_REGISTERED.store(false, Ordering::Release);
+                    return Err(last_error());
+                }
+            }
+            let state = Box::new(NotificationState {
+                handle,
+                current: None,
+                image: null_mut(),
+            });
+            // The notification area posts its messages, so a message-only window will do.
+            let hwnd = CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                ptr::null(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                null_mut(),
+                null_mut(),
+                Box::into_raw(state).cast(),
+            );
+            if hwnd.is_null() {
+                return Err(last_error());
+            }
+            Ok(NotificationWindow(hwnd))
+        }
+    }
+
+    /// Show `notification`, replacing any notification which is still shown.
+    pub(crate) fn send(
+        &self,
+        notification: &Notification,
+        id: NotificationId,
+    ) -> Result<(), ShellError> {
+        unsafe {
+            let state = &mut *self.state();
+            let mut data = icon_data(self.0);
+            data.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP | NIF_INFO;
+            data.uCallbackMessage = WM_NOTIFY_ICON;
+            data.hIcon = LoadIconW(0 as HINSTANCE, IDI_APPLICATION);
+            copy_wide(&mut data.szTip, &notification.title);
+            copy_wide(&mut data.szInfoTitle, &notification.title);
+            // The balloon isn't shown without any text
+            let body = match notification.body.as_str() {
+                "" => " ",
+                body => body,
+            };
+            copy_wide(&mut data.szInfo, body);
+            let image = notification.image.as_ref().map_or(null_mut(), |path| {
+                let image = LoadImageW(
+                    null_mut(),
+                    path.to_wide().as_ptr(),
+                    IMAGE_ICON,
+                    0,
+                    0,
+                    LR_LOADFROMFILE | LR_DEFAULTSIZE,
+                );
+                if image.is_null() {
+                    tracing::warn!(
+                        "the notification's image {} can't be shown: {}",
+                        path.display(),
+                        last_error()
+                    );
+                }
+                image as HICON
+            });
+            if image.is_null() {
+                data.dwInfoFlags = NIIF_NONE;
+            } else {
+                data.dwInfoFlags = NIIF_USER | NIIF_LARGE_ICON;
+                data.hBalloonIcon = image;
+            }
+
+            let message = if state.current.is_some() {
+                NIM_MODIFY
+            } else {
+                NIM_ADD
+            };
+            if Shell_NotifyIconW(message, &mut data) == 0 {
+                if !image.is_null() {
+                    DestroyIcon(image);
+                }
+                return Err(anyhow::anyhow!("failed to show the notification").into());
+            }
+            if message == NIM_ADD {
+                // Version 4 reports the balloon's events in the low word of `lParam`
+                *data.u.uVersion_mut() = NOTIFYICON_VERSION_4;
+                Shell_NotifyIconW(NIM_SETVERSION, &mut data);
+            }
+            if !state.image.is_null() {
+                DestroyIcon(state.image);
+            }
+            state.image = image;
+            state.current = Some(id);
+            Ok(())
+        }
+    }
+
+    /// Remove the notification, if it's the one being shown.
+    pub(crate) fn close(&self, id: NotificationId) {
+        unsafe {
+            let state = &mut *self.state();
+            if state.current == Some(id) {
+                remove_icon(self.0, state);
+            }
+        }
+    }
+
+    fn state(&self) -> *mut NotificationState {
+        unsafe { GetWindowLongPtrW(self.0, GWLP_USERDATA) as *mut NotificationState }
+    }
+}
+
+impl Drop for NotificationWindow {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyWindow(self.0);
+        }
+    }
+}
+
+fn last_error() -> Error {
+    Error::Hr(HRESULT_FROM_WIN32(unsafe { GetLastError() }))
+}
+
+/// The data identifying our icon, which is the only one the window has.
+fn icon_data(hwnd: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: hwnd,
+        uID: 0,
+        ..unsafe { mem::zeroed() }
+    }
+}
+
+/// Copy `text` into a fixed size buffer, cutting it short if it doesn't fit.
+fn copy_wide(buffer: &mut [u16], text: &str) {
+    let mut text = text
+        .encode_utf16()
+        .take(buffer.len() - 1)
+        .collect::<Vec<_>>();
+    text.push(0);
+    buffer[..text.len()].copy_from_slice(&text);
+}
+
+/// What to do about `event` from the notification area, while `current` is shown.
+///
+/// Returns the notification which was activated, and whether to remove the icon. Balloons
+/// have no actions, so only the notification itself can be activated.
+fn balloon_event(event: UINT, current: Option<NotificationId>) -> (Option<NotificationId>, bool) {
+    match event {
+        NIN_BALLOONUSERCLICK => (current, true),
+        // The icon is only wanted while the balloon is shown
+        NIN_BALLOONTIMEOUT | NIN_BALLOONHIDE => (None, true),
+        _ => (None, false),
+    }
+}
+
+unsafe fn remove_icon(hwnd: HWND, state: &mut NotificationState) {
+    if state.current.take().is_some() {
+        Shell_NotifyIconW(NIM_DELETE, &mut icon_data(hwnd));
+    }
+    if !state.image.is_null() {
+        DestroyIcon(state.image);
+        state.image = null_mut();
+    }
+}
+
+unsafe extern "system" fn notification_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_CREATE {
+        let create_struct = &*(lparam as *const CREATESTRUCTW);
+        let state = create_struct.lpCreateParams as *mut NotificationState;
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, state as _);
+    }
+    let state = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut NotificationState;
+    if state.is_null() {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    match msg {
+        WM_NOTIFY_ICON => {
+            let event = LOWORD(lparam as u32) as UINT;
+            let (activated, remove) = balloon_event(event, (*state).current);
+            if let Some(id) = activated {
+                // We're inside a window procedure here, so run the handler from the main
+                // loop.
+                (*state).handle.run_on_main(move |handler| {
+                    if let Some(handler) = handler {
+                        handler.notification_activated(id, None);
+                    }
+                });
+            }
+            if remove {
+                remove_icon(hwnd, &mut *state);
+            }
+        }
+        WM_NCDESTROY => {
+            remove_icon(hwnd, &mut *state);
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+            drop(Box::from_raw(state));
+        }
+        _ => (),
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+#[cfg(test)]
+mod test {
+    use winapi::um::shellapi::NIN_BALLOONSHOW;
+
+    use super::*;
+
+    #[test]
+    fn balloon_events() {
+        let id = NotificationId::from_raw(1);
+        assert_eq!(
+            balloon_event(NIN_BALLOONUSERCLICK, Some(id)),
+            (Some(id), true)
+        );
+        assert_eq!(balloon_event(NIN_BALLOONUSERCLICK, None), (None, true));
+        assert_eq!(balloon_event(NIN_BALLOONTIMEOUT, Some(id)), (None, true));
+        assert_eq!(balloon_event(NIN_BALLOONHIDE, Some(id)), (None, true));
+        assert_eq!(balloon_event(NIN_BALLOONSHOW, Some(id)), (None, false));
+    }
+
+    #[test]
+    fn long_text_is_cut_short() {
+        let mut buffer = [0xffff; 4];
+        copy_wide(&mut buffer, "hi");
+        assert_eq!(buffer, [b'h' as u16, b'i' as u16, 0, 0xffff]);
+        copy_wide(&mut buffer, "hello");
+        assert_eq!(buffer, [b'h' as u16, b'e' as u16, b'l' as u16, 0]);
+    }
+}
@@ -0,0 +1,344 @@
+//! Input from the pointer messages, which windows built with `WindowBuilder::use_pointer_api`
+//! handle instead of the mouse messages.
+//!
+//! `EnableMouseInPointer` makes the mouse send pointer messages too, so that every kind of
+//! pointer arrives through `WM_POINTER*`. It can't be undone and applies to the whole process,
+//! but `DefWindowProc` turns the pointer messages which other windows don't handle back into
+//! mouse messages.
+//!
+//! The pointer functions are only available from Windows 8, so they are loaded at runtime.
+
+use std::mem;
+
+use lazy_static::lazy_static;
+use tracing::warn;
+use winapi::shared::basetsd::UINT32;
+use winapi::shared::minwindef::{BOOL, FALSE, LOWORD, TRUE, WPARAM};
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::shared::winerror::HRESULT_FROM_WIN32;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winuser::*;
+
+use super::error::Error;
+use super::util::OPTIONAL_FUNCTIONS;
+use crate::kurbo::{Point, Size};
+use crate::mouse::MouseButton;
+use crate::pointer::{
+    Angle, MouseInfo, PenInclination, PenInfo, PointerButton, PointerButtons, PointerEvent,
+    PointerId, PointerType, TouchInfo,
+};
+use crate::scale::{Scalable, Scale};
+use crate::Modifiers;
+
+/// The signature which Windows puts in the extra info of the mouse messages that it synthesizes
+/// from touch and pen input.
+const MI_WP_SIGNATURE: usize = 0xFF51_5700;
+const SIGNATURE_MASK: usize = 0xFFFF_FF00;
+
+/// The pen and touch pressures range from 0 to this.
+const MAX_PRESSURE: f64 = 1024.0;
+
+lazy_static! {
+    /// Whether the mouse sends pointer messages, which can only be turned on once.
+    static ref MOUSE_IN_POINTER: bool = enable_mouse_in_pointer();
+}
+
+/// What a pointer did in one of its samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PointerAction {
+    Move,
+    Down,
+    Up,
+}
+
+/// Make the mouse send pointer messages, and return whether the pointer messages can be used.
+pub(crate) fn enable_pointer_api() -> bool {
+    *MOUSE_IN_POINTER
+}
+
+fn enable_mouse_in_pointer() -> bool {
+    let f = &*OPTIONAL_FUNCTIONS;
+    let (Some(enable), true) = (
+        f.EnableMouseInPointer,
+        f.GetPointerType.is_some()
+            && f.GetPointerInfoHistory.is_some()
+            && f.GetPointerPenInfoHistory.is_some()
+            && f.GetPointerTouchInfoHistory.is_some(),
+    ) else {
+        warn!("The pointer messages need Windows 8 or later, using the mouse messages instead");
+        return false;
+    };
+    unsafe {
+        if enable(TRUE) == FALSE {
+            warn!(
+                "EnableMouseInPointer failed: {}",
+                Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+            );
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether the mouse message being handled was synthesized from touch or pen input, which has
+/// already been delivered from the pointer messages.
+pub(crate) fn is_promoted_mouse_message() -> bool {
+    let extra_info = unsafe { GetMessageExtraInfo() } as usize;
+    extra_info & SIGNATURE_MASK == MI_WP_SIGNATURE
+}
+
+/// The samples of the pointer whose id is in the `wparam` of a pointer message, oldest first.
+///
+/// With `coalesced`, this includes the moves which Windows coalesced into the message, rather
+/// than just the latest sample.
+pub(crate) fn pointer_events(
+    hwnd: HWND,
+    wparam: WPARAM,
+    coalesced: bool,
+    scale: Scale,
+    mods: Modifiers,
+) -> Vec<(PointerAction, PointerEvent)> {
+    let f = &*OPTIONAL_FUNCTIONS;
+    let (Some(get_type), Some(get_info), Some(get_pen_info), Some(get_touch_info)) = (
+        f.GetPointerType,
+        f.GetPointerInfoHistory,
+        f.GetPointerPenInfoHistory,
+        f.GetPointerTouchInfoHistory,
+    ) else {
+        return Vec::new();
+    };
+    let pointer_id = LOWORD(wparam as u32) as UINT32;
+    let mut origin = POINT { x: 0, y: 0 };
+    let mut pointer_type = PT_POINTER;
+    let mut events: Vec<_> = unsafe {
+        ClientToScreen(hwnd, &mut origin);
+        if get_type(pointer_id, &mut pointer_type) == FALSE {
+            warn!(
+                "GetPointerType failed: {}",
+                Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+            );
+            return Vec::new();
+        }
+        match pointer_type {
+            PT_PEN => history(get_pen_info, pointer_id, coalesced)
+                .iter()
+                .map(|info| {
+                    let pos = precise_location(&info.pointerInfo);
+                    let pen = pen_info(info);
+                    // The eraser end of the pen is inverted while it hovers.
+                    let eraser = info.penFlags & (PEN_FLAG_ERASER | PEN_FLAG_INVERTED) != 0;
+                    let pointer_type = if eraser {
+                        PointerType::Eraser(pen)
+                    } else {
+                        PointerType::Pen(pen)
+                    };
+                    event(&info.pointerInfo, pos, pointer_type, origin, scale, mods)
+                })
+                .collect(),
+            PT_TOUCH => history(get_touch_info, pointer_id, coalesced)
+                .iter()
+                .map(|info| {
+                    let pos = precise_location(&info.pointerInfo);
+                    let touch = touch_info(info, scale);
+                    event(
+                        &info.pointerInfo,
+                        pos,
+                        PointerType::Touch(touch),
+                        origin,
+                        scale,
+                        mods,
+                    )
+                })
+                .collect(),
+            // The mouse, and touchpads which move the cursor.
+            _ => history(get_info, pointer_id, coalesced)
+                .iter()
+                .map(|info| {
+                    let pos =
+                        Point::new(info.ptPixelLocation.x as f64, info.ptPixelLocation.y as f64);
+                    let pointer_type = PointerType::Mouse(MouseInfo {
+                        wheel_delta: Default::default(),
+                    });
+                    event(info, pos, pointer_type, origin, scale, mods)
+                })
+                .collect(),
+        }
+    };
+    events.reverse();
+    events
+}
+
+/// The mouse button which corresponds to a pointer button.
+pub(crate) fn mouse_button(button: PointerButton) -> MouseButton {
+    match button {
+        PointerButton::None => MouseButton::None,
+        PointerButton::Primary => MouseButton::Primary,
+        PointerButton::Secondary => MouseButton::Secondary,
+        PointerButton::Auxiliary => MouseButton::Auxiliary,
+        PointerButton::X1 => MouseButton::X1,
+        PointerButton::X2 => MouseButton::X2,
+    }
+}
+
+/// The history of a pointer, newest first. Unless `all` is set, this is only the newest entry.
+unsafe fn history<T>(
+    get_history: unsafe extern "system" fn(UINT32, *mut UINT32, *mut T) -> BOOL,
+    pointer_id: UINT32,
+    all: bool,
+) -> Vec<T> {
+    let mut entries: Vec<T> = vec![mem::zeroed()];
+    // This is updated with the number of entries there are, even when they don't all fit.
+    let mut count = 1;
+    if get_history(pointer_id, &mut count, entries.as_mut_ptr()) == FALSE {
+        warn!(
+            "failed to get the pointer history: {}",
+            Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+        );
+        return Vec::new();
+    }
+    if all && count > 1 {
+        let mut all_entries: Vec<T> = (0..count).map(|_| mem::zeroed()).collect();
+        if get_history(pointer_id, &mut count, all_entries.as_mut_ptr()) != FALSE {
+            all_entries.truncate(count as usize);
+            return all_entries;
+        }
+    }
+    entries
+}
+
+fn event(
+    info: &POINTER_INFO,
+    screen_pos: Point,
+    pointer_type: PointerType,
+    origin: POINT,
+    scale: Scale,
+    mods: Modifiers,
+) -> (PointerAction, PointerEvent) {
+    let flags = info.pointerFlags;
+    let (action, button) = match info.ButtonChangeType {
+        POINTER_CHANGE_FIRSTBUTTON_DOWN => (PointerAction::Down, PointerButton::Primary),
+        POINTER_CHANGE_FIRSTBUTTON_UP => (PointerAction::Up, PointerButton::Primary),
+        POINTER_CHANGE_SECONDBUTTON_DOWN => (PointerAction::Down, PointerButton::Secondary),
+        POINTER_CHANGE_SECONDBUTTON_UP => (PointerAction::Up, PointerButton::Secondary),
+        POINTER_CHANGE_THIRDBUTTON_DOWN => (PointerAction::Down, PointerButton::Auxiliary),
+        POINTER_CHANGE_THIRDBUTTON_UP => (PointerAction::Up, PointerButton::Auxiliary),
+        POINTER_CHANGE_FOURTHBUTTON_DOWN => (PointerAction::Down, PointerButton::X1),
+        POINTER_CHANGE_FOURTHBUTTON_UP => (PointerAction::Up, PointerButton::X1),
+        POINTER_CHANGE_FIFTHBUTTON_DOWN => (PointerAction::Down, PointerButton::X2),
+        POINTER_CHANGE_FIFTHBUTTON_UP => (PointerAction::Up, PointerButton::X2),
+        // Touches and pens coming into contact.
+        _ if flags & POINTER_FLAG_DOWN != 0 => (PointerAction::Down, PointerButton::Primary),
+        _ if flags & POINTER_FLAG_UP != 0 => (PointerAction::Up, PointerButton::Primary),
+        _ => (PointerAction::Move, PointerButton::None),
+    };
+    let mut buttons = PointerButtons::new();
+    for (flag, button) in [
+        (POINTER_FLAG_FIRSTBUTTON, PointerButton::Primary),
+        (POINTER_FLAG_SECONDBUTTON, PointerButton::Secondary),
+        (POINTER_FLAG_THIRDBUTTON, PointerButton::Auxiliary),
+        (POINTER_FLAG_FOURTHBUTTON, PointerButton::X1),
+        (POINTER_FLAG_FIFTHBUTTON, PointerButton::X2),
+    ] {
+        if flags & flag != 0 {
+            buttons.insert(button);
+        }
+    }
+    let pos = Point::new(
+        screen_pos.x - origin.x as f64,
+        screen_pos.y - origin.y as f64,
+    );
+    let event = PointerEvent {
+        pointer_id: PointerId(info.pointerId as u64),
+        is_primary: flags & POINTER_FLAG_PRIMARY != 0,
+        pointer_type,
+        pos: pos.to_dp(scale),
+        buttons,
+        modifiers: mods,
+        button,
+        focus: false,
+        count: 0,
+        coalesced: Vec::new(),
+    };
+    (action, event)
+}
+
+/// Merge each run of moves in `events` into its last move, which keeps the positions of the
+/// others in `coalesced`.
+pub(crate) fn coalesce_moves(
+    events: Vec<(PointerAction, PointerEvent)>,
+) -> Vec<(PointerAction, PointerEvent)> {
+    let mut merged: Vec<(PointerAction, PointerEvent)> = Vec::with_capacity(events.len());
+    for (action, mut event) in events {
+        match merged.last_mut() {
+            Some((PointerAction::Move, last)) if action == PointerAction::Move => {
+                event.coalesced = mem::take(&mut last.coalesced);
+                event.coalesced.push(last.pos);
+                *last = event;
+            }
+            _ => merged.push((action, event)),
+        }
+    }
+    merged
+}
+
+/// The position of a pen or touch, in screen pixels.
+///
+/// `ptPixelLocation` is rounded to whole pixels, so this maps the more precise HIMETRIC location
+/// from the digitizer onto the display instead, when it can.
+fn precise_location(info: &POINTER_INFO) -> Point {
+    let pixels = Point::new(info.ptPixelLocation.x as f64, info.ptPixelLocation.y as f64);
+    let Some(get_rects) = OPTIONAL_FUNCTIONS.GetPointerDeviceRects else {
+        return pixels;
+    };
+    let mut device: RECT = unsafe { mem::zeroed() };
+    let mut display: RECT = unsafe { mem::zeroed() };
+    if unsafe { get_rects(info.sourceDevice, &mut device, &mut display) } == FALSE {
+        return pixels;
+    }
+    let device_size = (device.right - device.left, device.bottom - device.top);
+    if device_size.0 <= 0 || device_size.1 <= 0 {
+        return pixels;
+    }
+    let himetric = info.ptHimetricLocation;
+    let x = display.left as f64
+        + (himetric.x - device.left) as f64 * (display.right - display.left) as f64
+            / device_size.0 as f64;
+    let y = display.top as f64
+        + (himetric.y - device.top) as f64 * (display.bottom - display.top) as f64
+            / device_size.1 as f64;
+    Point::new(x, y)
+}
+
+fn pen_info(info: &POINTER_PEN_INFO) -> PenInfo {
+    let mut pen = PenInfo::default();
+    if info.penMask & PEN_MASK_PRESSURE != 0 {
+        pen.pressure = info.pressure as f64 / MAX_PRESSURE;
+    } else if info.pointerInfo.pointerFlags & POINTER_FLAG_INCONTACT == 0 {
+        pen.pressure = 0.0;
+    }
+    if info.penMask & (PEN_MASK_TILT_X | PEN_MASK_TILT_Y) != 0 {
+        if let Some(inclination) = PenInclination::from_tilt(info.tiltX as f64, info.tiltY as f64) {
+            pen.inclination = inclination;
+        }
+    }
+    if info.penMask & PEN_MASK_ROTATION != 0 {
+        pen.twist = Angle::degrees(info.rotation as f64);
+    }
+    pen
+}
+
+fn touch_info(info: &POINTER_TOUCH_INFO, scale: Scale) -> TouchInfo {
+    let mut touch = TouchInfo::default();
+    if info.touchMask & TOUCH_MASK_CONTACTAREA != 0 {
+        let contact = info.rcContact;
+        touch.contact_geometry = Size::new(
+            (contact.right - contact.left) as f64,
+            (contact.bottom - contact.top) as f64,
+        )
+        .to_dp(scale);
+    }
+    if info.touchMask & TOUCH_MASK_PRESSURE != 0 {
+        touch.pressure = (info.pressure as f64 / MAX_PRESSURE) as f32;
+    }
+    touch
+}
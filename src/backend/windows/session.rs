@@ -0,0 +1,199 @@
+//! Sleep, session lock, power source and locale change notifications.
+//!
+//! Windows only sends these to windows, so a hidden window receives them for the
+//! application, whether or not it has any windows open.
+
+use std::ptr::{self, null_mut};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{LPARAM, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::ntdef::{HANDLE, LPWSTR};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::HRESULT_FROM_WIN32;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetWindowLongPtrW, RegisterClassW,
+    RegisterPowerSettingNotification, SetWindowLongPtrW, UnregisterPowerSettingNotification,
+    CREATESTRUCTW, DEVICE_NOTIFY_WINDOW_HANDLE, GWLP_USERDATA, HPOWERNOTIFY,
+    PBT_APMPOWERSTATUSCHANGE, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, PBT_POWERSETTINGCHANGE,
+    WM_CREATE, WM_DESTROY, WM_NCDESTROY, WM_POWERBROADCAST, WM_SETTINGCHANGE, WM_WTSSESSION_CHANGE,
+    WNDCLASSW, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
+use winapi::um::wtsapi32::{
+    WTSRegisterSessionNotification, WTSUnRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+
+use crate::AppHandler;
+
+use super::application::AppHandle;
+use super::error::Error;
+use super::util::{FromWide, ToWide, OPTIONAL_FUNCTIONS};
+
+const CLASS_NAME: &str = "glazier session";
+
+/// {E00958C0-C213-4ACE-AC77-FECCED2EEEA5}, which tells us whether battery saver is on.
+const GUID_POWER_SAVING_STATUS: GUID = GUID {
+    Data1: 0xe00958c0,
+    Data2: 0xc213,
+    Data3: 0x4ace,
+    Data4: [0xac, 0x77, 0xfe, 0xcc, 0xed, 0x2e, 0xee, 0xa5],
+};
+
+/// Used to ensure the window class is registered only once per process.
+static CLASS_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// The hidden window, which is destroyed when this is dropped.
+pub(crate) struct SessionWindow(HWND);
+
+/// Owned by the window, and freed when it's destroyed.
+struct SessionState {
+    handle: AppHandle,
+    /// Modern standby is only reported to windows which register for it.
+    suspend_notify: HPOWERNOTIFY,
+    /// Tells us when battery saver is toggled.
+    power_saving_notify: HPOWERNOTIFY,
+}
+
+impl SessionWindow {
+    pub(crate) fn new(handle: AppHandle) -> Option<SessionWindow> {
+        let class_name = CLASS_NAME.to_wide();
+        unsafe {
+            if CLASS_REGISTERED
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let wnd = WNDCLASSW {
+                    lpfnWndProc: Some(session_proc),
+                    lpszClassName: class_name.as_ptr(),
+                    ..std::mem::zeroed()
+                };
+                if RegisterClassW(&wnd) == 0 {
+                    CLASS_REGISTERED.store(false, Ordering::Release);
+                    log_error("RegisterClassW");
+                    return None;
+                }
+            }
+            let state = Box::new(SessionState {
+                handle,
+                suspend_notify: null_mut(),
+                power_saving_notify: null_mut(),
+            });
+            // This isn't a message-only window, as those don't get broadcasts. It's never shown.
+            let hwnd = CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                ptr::null(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+                Box::into_raw(state).cast(),
+            );
+            if hwnd.is_null() {
+                log_error("CreateWindowExW");
+                return None;
+            }
+            Some(SessionWindow(hwnd))
+        }
+    }
+}
+
+impl Drop for SessionWindow {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyWindow(self.0);
+        }
+    }
+}
+
+fn log_error(function: &str) {
+    let error = Error::Hr(HRESULT_FROM_WIN32(unsafe { GetLastError() }));
+    tracing::warn!("{function} failed, so session changes aren't reported: {error}");
+}
+
+unsafe extern "system" fn session_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_CREATE {
+        let create_struct = &*(lparam as *const CREATESTRUCTW);
+        let state = &mut *(create_struct.lpCreateParams as *mut SessionState);
+        SetWindowLongPtrW(hwnd, GWLP_USERDATA, state as *mut SessionState as _);
+        if WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) == 0 {
+            log_error("WTSRegisterSessionNotification");
+        }
+        if let Some(register) = OPTIONAL_FUNCTIONS.RegisterSuspendResumeNotification {
+            state.suspend_notify = register(hwnd as HANDLE, DEVICE_NOTIFY_WINDOW_HANDLE);
+        }
+        state.power_saving_notify = RegisterPowerSettingNotification(
+            hwnd as HANDLE,
+            &GUID_POWER_SAVING_STATUS,
+            DEVICE_NOTIFY_WINDOW_HANDLE,
+        );
+    }
+    let state = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut SessionState;
+    if state.is_null() {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+    // We're inside a window procedure here, so run the handler from the main loop.
+    let notify = |f: fn(&mut dyn AppHandler)| {
+        (*state).handle.run_on_main(move |handler| {
+            if let Some(handler) = handler {
+                f(handler);
+            }
+        })
+    };
+    match msg {
+        WM_POWERBROADCAST => {
+            match wparam {
+                PBT_APMSUSPEND => notify(|handler| handler.system_will_sleep()),
+                // This is sent however the system was woken, unlike `PBT_APMRESUMESUSPEND`
+                PBT_APMRESUMEAUTOMATIC => notify(|handler| handler.system_did_wake()),
+                // We only register for the battery saver power setting
+                PBT_APMPOWERSTATUSCHANGE | PBT_POWERSETTINGCHANGE => {
+                    if let Some(app) = crate::Application::try_global() {
+                        app.backend_app.power_status_changed();
+                    }
+                }
+                _ => (),
+            }
+            return TRUE as LRESULT;
+        }
+        // The language and region settings are sent as a change to the "intl" section
+        WM_SETTINGCHANGE => {
+            let section = lparam as LPWSTR;
+            if !section.is_null() && section.to_string().as_deref() == Some("intl") {
+                notify(|handler| handler.locale_changed());
+            }
+        }
+        WM_WTSSESSION_CHANGE => match wparam {
+            WTS_SESSION_LOCK => notify(|handler| handler.session_locked()),
+            WTS_SESSION_UNLOCK => notify(|handler| handler.session_unlocked()),
+            _ => (),
+        },
+        WM_DESTROY => {
+            WTSUnRegisterSessionNotification(hwnd);
+            if let Some(unregister) = OPTIONAL_FUNCTIONS.UnregisterSuspendResumeNotification {
+                if !(*state).suspend_notify.is_null() {
+                    unregister((*state).suspend_notify);
+                }
+            }
+            if !(*state).power_saving_notify.is_null() {
+                UnregisterPowerSettingNotification((*state).power_saving_notify);
+            }
+        }
+        WM_NCDESTROY => {
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
+            drop(Box::from_raw(state));
+        }
+        _ => (),
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
@@ -0,0 +1,211 @@
+//! The window's taskbar button: progress, overlay icons and flashing.
+//!
+//! The progress and the overlay icon go through `ITaskbarList3`, which can only be used once
+//! the taskbar has sent us `TaskbarButtonCreated`. Until then, we keep what was asked for and
+//! apply it when the message arrives. It is sent again if Explorer restarts, after which
+//! everything is applied again.
+
+use std::mem;
+use std::ptr::{null, null_mut};
+
+use tracing::warn;
+use winapi::shared::minwindef::{DWORD, TRUE, UINT};
+use winapi::shared::windef::{HICON, HWND};
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::CoCreateInstance;
+use winapi::um::shobjidl_core::{
+    ITaskbarList3, TBPFLAG, TBPF_ERROR, TBPF_INDETERMINATE, TBPF_NOPROGRESS, TBPF_NORMAL,
+    TBPF_PAUSED,
+};
+use winapi::um::wingdi::{
+    CreateBitmap, CreateDIBSection, DeleteObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+    DIB_RGB_COLORS,
+};
+use winapi::um::winuser::{
+    CreateIconIndirect, DestroyIcon, FlashWindowEx, FLASHWINFO, FLASHW_ALL, FLASHW_STOP,
+    FLASHW_TIMERNOFG, ICONINFO,
+};
+use winapi::{Interface, DEFINE_GUID};
+use wio::com::ComPtr;
+
+use super::util::{as_result, init_com, ToWide};
+use crate::platform::windows::ProgressState;
+use crate::window::ImageBuf;
+use crate::PixelFormat;
+
+// TODO: remove this when it gets added to winapi
+DEFINE_GUID! {CLSID_TaskbarList,
+0x56FD_F344, 0xFD6D, 0x11D0, 0x95, 0x8A, 0x00, 0x60, 0x97, 0xC9, 0xA0, 0x90}
+
+/// The number of steps `ITaskbarList3::SetProgressValue` divides the bar into.
+const PROGRESS_STEPS: u64 = 10_000;
+
+#[derive(Default)]
+pub(crate) struct TaskbarButton {
+    list: Option<ComPtr<ITaskbarList3>>,
+    progress: Option<(ProgressState, Option<f64>)>,
+    overlay: Option<(ImageBuf, String)>,
+}
+
+impl TaskbarButton {
+    /// Called when the window receives `TaskbarButtonCreated`.
+    pub fn created(&mut self, hwnd: HWND) {
+        self.list = match create_taskbar_list() {
+            Ok(list) => Some(list),
+            Err(hr) => {
+                warn!("failed to create ITaskbarList3: {:#x}", hr);
+                return;
+            }
+        };
+        if let Some((state, value)) = self.progress {
+            self.apply_progress(hwnd, state, value);
+        }
+        if self.overlay.is_some() {
+            self.apply_overlay(hwnd);
+        }
+    }
+
+    pub fn set_progress(&mut self, hwnd: HWND, state: ProgressState, value: Option<f64>) {
+        self.progress = Some((state, value));
+        self.apply_progress(hwnd, state, value);
+    }
+
+    pub fn set_overlay(&mut self, hwnd: HWND, icon: Option<ImageBuf>, description: &str) {
+        self.overlay = icon.map(|icon| (icon, description.to_owned()));
+        self.apply_overlay(hwnd);
+    }
+
+    fn apply_progress(&self, hwnd: HWND, state: ProgressState, value: Option<f64>) {
+        let Some(list) = &self.list else {
+            return;
+        };
+        let flag: TBPFLAG = match state {
+            ProgressState::None => TBPF_NOPROGRESS,
+            ProgressState::Normal => TBPF_NORMAL,
+            ProgressState::Indeterminate => TBPF_INDETERMINATE,
+            ProgressState::Paused => TBPF_PAUSED,
+            ProgressState::Error => TBPF_ERROR,
+        };
+        unsafe {
+            if let Err(e) = as_result(list.SetProgressState(hwnd, flag)) {
+                warn!("failed to set the taskbar progress state: {}", e);
+            }
+            // Setting a value leaves the indeterminate state, so it is only set otherwise.
+            if let Some(value) = value.filter(|_| state != ProgressState::Indeterminate) {
+                let completed = (value.clamp(0.0, 1.0) * PROGRESS_STEPS as f64).round() as u64;
+                if let Err(e) = as_result(list.SetProgressValue(hwnd, completed, PROGRESS_STEPS)) {
+                    warn!("failed to set the taskbar progress: {}", e);
+                }
+            }
+        }
+    }
+
+    fn apply_overlay(&self, hwnd: HWND) {
+        let Some(list) = &self.list else {
+            return;
+        };
+        unsafe {
+            let (icon, description) = match &self.overlay {
+                Some((image, description)) => (create_icon(image), Some(description.to_wide())),
+                None => (null_mut(), None),
+            };
+            let description_ptr = description.as_ref().map_or(null(), |d| d.as_ptr());
+            if let Err(e) = as_result(list.SetOverlayIcon(hwnd, icon, description_ptr)) {
+                warn!("failed to set the taskbar overlay icon: {}", e);
+            }
+            // The taskbar keeps a copy of the icon.
+            if !icon.is_null() {
+                DestroyIcon(icon);
+            }
+        }
+    }
+}
+
+/// Flash the taskbar button until the window is activated, or stop flashing it.
+pub(crate) fn flash(hwnd: HWND, flash: bool) {
+    let mut info = FLASHWINFO {
+        cbSize: mem::size_of::<FLASHWINFO>() as UINT,
+        hwnd,
+        dwFlags: if flash {
+            FLASHW_ALL | FLASHW_TIMERNOFG
+        } else {
+            FLASHW_STOP
+        },
+        uCount: 0,
+        dwTimeout: 0,
+    };
+    unsafe {
+        FlashWindowEx(&mut info);
+    }
+}
+
+fn create_taskbar_list() -> Result<ComPtr<ITaskbarList3>, i32> {
+    unsafe {
+        init_com()?;
+        let mut list: *mut ITaskbarList3 = null_mut();
+        let hr = CoCreateInstance(
+            &CLSID_TaskbarList,
+            null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &ITaskbarList3::uuidof(),
+            &mut list as *mut *mut ITaskbarList3 as *mut _,
+        );
+        if hr < 0 {
+            return Err(hr);
+        }
+        let list = ComPtr::from_raw(list);
+        let hr = list.HrInit();
+        if hr < 0 {
+            return Err(hr);
+        }
+        Ok(list)
+    }
+}
+
+/// Create an icon from `image`, or a null icon if that fails.
+unsafe fn create_icon(image: &ImageBuf) -> HICON {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+            biWidth: width,
+            // Negative heights are stored from the top row down, like `ImageBuf`.
+            biHeight: -height,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB,
+            ..mem::zeroed()
+        },
+        ..mem::zeroed()
+    };
+    let mut bits = null_mut();
+    let color = CreateDIBSection(null_mut(), &info, DIB_RGB_COLORS, &mut bits, null_mut(), 0);
+    if color.is_null() {
+        warn!("failed to create a bitmap for the overlay icon");
+        return null_mut();
+    }
+    // Icons are BGRA, with straight alpha.
+    let pixels = std::slice::from_raw_parts_mut(bits as *mut u8, image.pixels().len());
+    for (dst, src) in pixels
+        .chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL)
+        .zip(image.pixels().chunks_exact(PixelFormat::BYTES_PER_PIXEL))
+    {
+        dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+    }
+    // The mask is unused, since the color bitmap has an alpha channel, but it must be there.
+    let mask = CreateBitmap(width, height, 1, 1, null_mut());
+    let mut icon_info = ICONINFO {
+        fIcon: TRUE,
+        xHotspot: 0,
+        yHotspot: 0,
+        hbmMask: mask,
+        hbmColor: color,
+    };
+    let icon = CreateIconIndirect(&mut icon_info);
+    DeleteObject(color as _);
+    DeleteObject(mask as _);
+    if icon.is_null() {
+        warn!("failed to create the overlay icon");
+    }
+    icon
+}
@@ -25,20 +25,26 @@ use std::slice;
 
 use lazy_static::lazy_static;
 use winapi::ctypes::c_void;
+use winapi::shared::basetsd::UINT32;
 use winapi::shared::dxgi::IDXGIDevice;
 use winapi::shared::guiddef::REFIID;
-use winapi::shared::minwindef::{BOOL, HMODULE, UINT};
-use winapi::shared::ntdef::{HRESULT, LPWSTR};
+use winapi::shared::minwindef::{BOOL, DWORD, HMODULE, UINT};
+use winapi::shared::ntdef::{HANDLE, HRESULT, LPWSTR};
 use winapi::shared::windef::{HMONITOR, HWND, RECT};
-use winapi::shared::winerror::SUCCEEDED;
+use winapi::shared::winerror::{RPC_E_CHANGED_MODE, SUCCEEDED};
+use winapi::um::combaseapi::CoInitializeEx;
 use winapi::um::fileapi::{CreateFileA, GetFileType, OPEN_EXISTING};
 use winapi::um::handleapi::INVALID_HANDLE_VALUE;
 use winapi::um::libloaderapi::{GetModuleHandleW, GetProcAddress, LoadLibraryW};
+use winapi::um::objbase::COINIT_APARTMENTTHREADED;
 use winapi::um::processenv::{GetStdHandle, SetStdHandle};
 use winapi::um::shellscalingapi::{MONITOR_DPI_TYPE, PROCESS_DPI_AWARENESS};
 use winapi::um::winbase::{FILE_TYPE_UNKNOWN, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
 use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
 use winapi::um::winnt::{FILE_SHARE_WRITE, GENERIC_READ, GENERIC_WRITE};
+use winapi::um::winuser::{
+    HPOWERNOTIFY, POINTER_INFO, POINTER_INPUT_TYPE, POINTER_PEN_INFO, POINTER_TOUCH_INFO,
+};
 
 use super::error::Error;
 use crate::kurbo::Rect;
@@ -104,6 +110,18 @@ impl FromWide for [u16] {
     }
 }
 
+/// Initializes COM on the current thread, as a single-threaded apartment.
+///
+/// Nothing else needs COM on the main thread, so it may not have been initialized yet. It is
+/// fine if it has, in either mode.
+pub(crate) fn init_com() -> Result<(), HRESULT> {
+    let hr = unsafe { CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED) };
+    if hr < 0 && hr != RPC_E_CHANGED_MODE {
+        return Err(hr);
+    }
+    Ok(())
+}
+
 /// Converts a winapi `RECT` to a `Rect`.
 #[inline]
 pub(crate) fn recti_to_rect(rect: RECT) -> Rect {
@@ -121,8 +139,20 @@ type GetDpiForSystem = unsafe extern "system" fn() -> UINT;
 type GetDpiForWindow = unsafe extern "system" fn(HWND) -> UINT;
 type SetProcessDpiAwarenessContext =
     unsafe extern "system" fn(winapi::shared::windef::DPI_AWARENESS_CONTEXT) -> BOOL;
+type EnableNonClientDpiScaling = unsafe extern "system" fn(HWND) -> BOOL;
 type GetSystemMetricsForDpi =
     unsafe extern "system" fn(winapi::ctypes::c_int, UINT) -> winapi::ctypes::c_int;
+type EnableMouseInPointer = unsafe extern "system" fn(BOOL) -> BOOL;
+type GetPointerType = unsafe extern "system" fn(UINT32, *mut POINTER_INPUT_TYPE) -> BOOL;
+type GetPointerInfoHistory =
+    unsafe extern "system" fn(UINT32, *mut UINT32, *mut POINTER_INFO) -> BOOL;
+type GetPointerPenInfoHistory =
+    unsafe extern "system" fn(UINT32, *mut UINT32, *mut POINTER_PEN_INFO) -> BOOL;
+type GetPointerTouchInfoHistory =
+    unsafe extern "system" fn(UINT32, *mut UINT32, *mut POINTER_TOUCH_INFO) -> BOOL;
+type GetPointerDeviceRects = unsafe extern "system" fn(HANDLE, *mut RECT, *mut RECT) -> BOOL;
+type RegisterSuspendResumeNotification = unsafe extern "system" fn(HANDLE, DWORD) -> HPOWERNOTIFY;
+type UnregisterSuspendResumeNotification = unsafe extern "system" fn(HPOWERNOTIFY) -> BOOL;
 // from shcore.dll
 type GetDpiForMonitor = unsafe extern "system" fn(HMONITOR, MONITOR_DPI_TYPE, *mut UINT, *mut UINT);
 type SetProcessDpiAwareness = unsafe extern "system" fn(PROCESS_DPI_AWARENESS) -> HRESULT;
@@ -139,8 +169,17 @@ pub struct OptionalFunctions {
     pub SetProcessDpiAwarenessContext: Option<SetProcessDpiAwarenessContext>,
     pub GetDpiForMonitor: Option<GetDpiForMonitor>,
     pub SetProcessDpiAwareness: Option<SetProcessDpiAwareness>,
+    pub EnableNonClientDpiScaling: Option<EnableNonClientDpiScaling>,
     pub GetSystemMetricsForDpi: Option<GetSystemMetricsForDpi>,
     pub DCompositionCreateDevice: Option<DCompositionCreateDevice>,
+    pub EnableMouseInPointer: Option<EnableMouseInPointer>,
+    pub GetPointerType: Option<GetPointerType>,
+    pub GetPointerInfoHistory: Option<GetPointerInfoHistory>,
+    pub GetPointerPenInfoHistory: Option<GetPointerPenInfoHistory>,
+    pub GetPointerTouchInfoHistory: Option<GetPointerTouchInfoHistory>,
+    pub GetPointerDeviceRects: Option<GetPointerDeviceRects>,
+    pub RegisterSuspendResumeNotification: Option<RegisterSuspendResumeNotification>,
+    pub UnregisterSuspendResumeNotification: Option<UnregisterSuspendResumeNotification>,
 }
 
 #[allow(non_snake_case)] // For local variables
@@ -193,8 +232,17 @@ fn load_optional_functions() -> OptionalFunctions {
     let mut GetDpiForWindow = None;
     let mut SetProcessDpiAwarenessContext = None;
     let mut SetProcessDpiAwareness = None;
+    let mut EnableNonClientDpiScaling = None;
     let mut GetSystemMetricsForDpi = None;
     let mut DCompositionCreateDevice = None;
+    let mut EnableMouseInPointer = None;
+    let mut GetPointerType = None;
+    let mut GetPointerInfoHistory = None;
+    let mut GetPointerPenInfoHistory = None;
+    let mut GetPointerTouchInfoHistory = None;
+    let mut GetPointerDeviceRects = None;
+    let mut RegisterSuspendResumeNotification = None;
+    let mut UnregisterSuspendResumeNotification = None;
 
     if shcore.is_null() {
         tracing::info!("No shcore.dll");
@@ -209,7 +257,16 @@ fn load_optional_functions() -> OptionalFunctions {
         load_function!(user32, GetDpiForSystem, "10");
         load_function!(user32, GetDpiForWindow, "10");
         load_function!(user32, SetProcessDpiAwarenessContext, "10");
+        load_function!(user32, EnableNonClientDpiScaling, "10");
         load_function!(user32, GetSystemMetricsForDpi, "10");
+        load_function!(user32, EnableMouseInPointer, "8");
+        load_function!(user32, GetPointerType, "8");
+        load_function!(user32, GetPointerInfoHistory, "8");
+        load_function!(user32, GetPointerPenInfoHistory, "8");
+        load_function!(user32, GetPointerTouchInfoHistory, "8");
+        load_function!(user32, GetPointerDeviceRects, "8");
+        load_function!(user32, RegisterSuspendResumeNotification, "8");
+        load_function!(user32, UnregisterSuspendResumeNotification, "8");
     }
 
     if dcomp.is_null() {
@@ -224,8 +281,17 @@ fn load_optional_functions() -> OptionalFunctions {
         SetProcessDpiAwarenessContext,
         GetDpiForMonitor,
         SetProcessDpiAwareness,
+        EnableNonClientDpiScaling,
         GetSystemMetricsForDpi,
         DCompositionCreateDevice,
+        EnableMouseInPointer,
+        GetPointerType,
+        GetPointerInfoHistory,
+        GetPointerPenInfoHistory,
+        GetPointerTouchInfoHistory,
+        GetPointerDeviceRects,
+        RegisterSuspendResumeNotification,
+        UnregisterSuspendResumeNotification,
     }
 }
 
@@ -18,6 +18,7 @@
 
 use std::cell::{Cell, RefCell};
 use std::mem;
+use std::num::NonZeroIsize;
 use std::panic::Location;
 use std::ptr::{null, null_mut};
 use std::rc::{Rc, Weak};
@@ -29,47 +30,68 @@ use accesskit_windows::{Adapter as AccessKitAdapter, UiaInitMarker};
 #[cfg(feature = "accesskit")]
 use once_cell::unsync::OnceCell;
 use scopeguard::defer;
-use tracing::{error, warn};
+use tracing::{debug, error, warn};
 use winapi::ctypes::{c_int, c_void};
 use winapi::shared::minwindef::*;
 use winapi::shared::windef::*;
 use winapi::shared::winerror::*;
-use winapi::um::dwmapi::{DwmExtendFrameIntoClientArea, DwmSetWindowAttribute};
+use winapi::um::dwmapi::{
+    DwmExtendFrameIntoClientArea, DwmGetCompositionTimingInfo, DwmGetWindowAttribute,
+    DwmSetWindowAttribute, DWMWA_CLOAK, DWMWA_CLOAKED, DWM_TIMING_INFO,
+};
 use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::profileapi::{QueryPerformanceCounter, QueryPerformanceFrequency};
 use winapi::um::shellscalingapi::MDT_EFFECTIVE_DPI;
 use winapi::um::uxtheme::*;
 use winapi::um::wingdi::*;
 use winapi::um::winnt::*;
+use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
 use winapi::um::winuser::*;
 
 use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle, Win32WindowHandle,
-    WindowsDisplayHandle,
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, Win32WindowHandle, WindowsDisplayHandle,
 };
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
 
 use crate::kurbo::{Insets, Point, Rect, Size, Vec2};
 
 use super::accels::register_accel;
 use super::application::Application;
+use super::composition::Composition;
 use super::dialog::get_file_dialog_path;
 use super::error::Error;
 use super::keyboard::{self, KeyboardState};
 use super::menu::Menu;
 // use super::paint;
+use super::msgs::WM_TASKBAR_BUTTON_CREATED;
+use super::pointer::{self, PointerAction};
+use super::taskbar::{self, TaskbarButton};
 use super::timers::TimerSlots;
-use super::util::{self, ToWide, OPTIONAL_FUNCTIONS};
+use super::util::{self, FromWide, ToWide, OPTIONAL_FUNCTIONS};
 
-use crate::common_util::IdleCallback;
+use crate::application::UiDirection;
+use crate::common_util::{
+    self, min_size_with_aspect_ratio, snap_to_increment, FrameSchedule, FrameThrottle,
+    FrameTimingsCache, IdleCallback,
+};
 use crate::dialog::{FileDialogOptions, FileDialogType, FileInfo};
 use crate::error::Error as ShellError;
 use crate::keyboard::{KbKey, KeyState};
 use crate::mouse::{Cursor, CursorDesc, MouseButton, MouseButtons, MouseEvent};
+use crate::platform::windows::{
+    Backdrop, CornerPreference, PresentMode, ProgressState, TitlebarColors,
+};
+use crate::pointer::{MouseInfo, PointerType};
 use crate::region::Region;
 use crate::scale::{Scalable, Scale, ScaledArea};
 use crate::text::{simulate_input, Event};
 use crate::window;
 use crate::window::{
-    FileDialogToken, IdleToken, TextFieldToken, TimerToken, WinHandler, WindowLevel,
+    FileDialogToken, FrameTimings, IdleToken, ImageBuf, MaximizeMode, ModalResponse, PixelFormat,
+    PopupConfig, StackingLevel, TextFieldToken, Theme, TimerToken, Visibility, WinHandler,
+    WindowLevel, WindowType,
 };
 
 /// The backend target DPI.
@@ -84,6 +106,7 @@ pub(crate) struct WindowBuilder {
     title: String,
     menu: Option<Menu>,
     present_strategy: PresentStrategy,
+    present_mode: PresentMode,
     resizable: bool,
     show_titlebar: bool,
     size: Option<Size>,
@@ -91,7 +114,22 @@ pub(crate) struct WindowBuilder {
     min_size: Option<Size>,
     position: Option<Point>,
     level: Option<WindowLevel>,
+    window_type: Option<WindowType>,
     state: window::WindowState,
+    maximize_mode: MaximizeMode,
+    kiosk: bool,
+    offscreen: bool,
+    visible: bool,
+    skip_taskbar: bool,
+    accepts_focus: bool,
+    managed_surface: bool,
+    use_pointer_api: bool,
+    coalesce_pointer_events: bool,
+    owner: Option<crate::WindowHandle>,
+    popup: Option<PopupConfig>,
+    frame_autosave_name: Option<String>,
+    /// Set by `build_as_child`, for windows inside a window we don't own.
+    foreign_parent: Option<HWND>,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
@@ -154,9 +192,42 @@ enum DeferredOp {
     SetSize(Size),
     SetResizable(bool),
     SetWindowState(window::WindowState),
+    SetWindowLevel(StackingLevel),
+    SetSkipTaskbar(bool),
+    SetTitlebarTheme(Option<Theme>),
+    SetTitlebarColors(TitlebarColors),
+    /// Tells the handler about a new backing scale override.
+    UpdateBackingScale,
+    /// Deferred on the parent, which is disabled while the sheet is shown.
+    BeginSheet(WindowHandle),
+    /// Deferred on the sheet.
+    EndSheet(ModalResponse),
     ReleaseMouseCapture,
 }
 
+/// The state of a window which is shown as a sheet, set by `WindowHandle::begin_sheet`.
+struct Sheet {
+    parent: WindowHandle,
+    completion: Box<dyn FnOnce(ModalResponse)>,
+}
+
+impl Sheet {
+    /// Enable the parent again, hide the sheet if it isn't being destroyed, and tell the
+    /// application how the sheet ended.
+    fn end(self, hwnd: Option<HWND>, response: ModalResponse) {
+        unsafe {
+            if let Some(parent) = self.parent.get_hwnd() {
+                EnableWindow(parent, TRUE);
+            }
+            // The parent is enabled first, so that it is activated in the sheet's place.
+            if let Some(hwnd) = hwnd {
+                ShowWindow(hwnd, SW_HIDE);
+            }
+        }
+        (self.completion)(response);
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct WindowHandle {
     state: Weak<WindowState>,
@@ -173,25 +244,54 @@ impl PartialEq for WindowHandle {
 }
 impl Eq for WindowHandle {}
 
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        // The window state is dropped at `WM_NCDESTROY`, so this fails once the window is gone.
+        let hwnd = self
+            .get_hwnd()
+            .and_then(|hwnd| NonZeroIsize::new(hwnd as isize))
+            .ok_or(HandleError::Unavailable)?;
+        let mut handle = Win32WindowHandle::new(hwnd);
+        handle.hinstance = NonZeroIsize::new(unsafe {
+            winapi::um::libloaderapi::GetModuleHandleW(0 as LPCWSTR) as isize
+        });
+        // SAFETY: The hwnd is valid, as the window hasn't been destroyed yet.
+        Ok(unsafe { raw_window_handle::WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    /// See:
+    ///  * <https://github.com/rust-windowing/raw-window-handle/issues/92>
+    ///  * <https://github.com/rust-windowing/winit/blob/92fdf5ba85f920262a61cee4590f4a11ad5738d1/src/platform_impl/windows/window.rs#L285>
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        if self.state.upgrade().is_none() {
+            return Err(HandleError::Unavailable);
+        }
+        let handle = RawDisplayHandle::Windows(WindowsDisplayHandle::new());
+        // SAFETY: The Windows display handle doesn't contain any pointers.
+        Ok(unsafe { DisplayHandle::borrow_raw(handle) })
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        let mut handle = Win32WindowHandle::empty();
+    fn raw_window_handle(&self) -> rwh_05::RawWindowHandle {
+        let mut handle = rwh_05::Win32WindowHandle::empty();
         if let Some(hwnd) = self.get_hwnd() {
             handle.hwnd = hwnd as *mut core::ffi::c_void;
             handle.hinstance = unsafe {
                 winapi::um::libloaderapi::GetModuleHandleW(0 as LPCWSTR) as *mut core::ffi::c_void
             };
         }
-        RawWindowHandle::Win32(handle)
+        rwh_05::RawWindowHandle::Win32(handle)
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
-    /// See:
-    ///  * <https://github.com/rust-windowing/raw-window-handle/issues/92>
-    ///  * <https://github.com/rust-windowing/winit/blob/92fdf5ba85f920262a61cee4590f4a11ad5738d1/src/platform_impl/windows/window.rs#L285>
-    fn raw_display_handle(&self) -> RawDisplayHandle {
-        RawDisplayHandle::Windows(WindowsDisplayHandle::empty())
+    fn raw_display_handle(&self) -> rwh_05::RawDisplayHandle {
+        rwh_05::RawDisplayHandle::Windows(rwh_05::WindowsDisplayHandle::empty())
     }
 }
 
@@ -232,12 +332,74 @@ struct WindowState {
     is_transparent: Cell<bool>,
     // For resizable borders, window can still be resized with code.
     is_resizable: Cell<bool>,
+    // The width over the height of the client area, which resizing by the user keeps.
+    aspect_ratio: Cell<Option<f64>>,
+    // The steps the client area grows and shrinks by from its minimum size, in display points.
+    resize_increments: Cell<Option<Size>>,
     handle_titlebar: Cell<bool>,
+    maximize_mode: Cell<MaximizeMode>,
     active_text_input: Cell<Option<TextFieldToken>>,
+    // Set at `WM_DESTROY`, before the handler is told. The state lives on until `WM_NCDESTROY`.
+    is_destroyed: Cell<bool>,
     // Is the window focusable ("activatable" in Win32 terminology)?
-    // False for tooltips, to prevent stealing focus from owner window.
+    // False for tooltips and palettes, to prevent stealing focus from owner window.
     is_focusable: bool,
+    // Built inside a window we don't own, with `build_as_child`. These have no frame, menu or
+    // window state.
+    is_child: bool,
+    // Kiosk windows can't be moved, resized, minimized or closed by the user.
+    is_kiosk: bool,
+    // Popups which capture the mouse when shown, and are dismissed by clicks outside of them.
+    is_popup_grab: bool,
+    // Whether we hold the popup's mouse capture. Losing it dismisses the popup.
+    popup_grabbing: Cell<bool>,
+    // The last pixels presented to an offscreen window, as BGRA. This is `None` for windows
+    // which are on screen, and empty until the first present.
+    offscreen_pixels: RefCell<Option<Vec<u8>>>,
+    // Whether `present_pixels` may draw to the window, which is otherwise left to the
+    // application.
+    managed_surface: bool,
+    // Whether input comes from the pointer messages, in which case the mouse messages which
+    // Windows synthesizes from touch and pen input are ignored.
+    use_pointer_api: bool,
+    // Whether the moves which arrive together are delivered as one event.
+    coalesce_pointer_events: bool,
+    // The visibility last reported to the handler.
+    visibility: Cell<Visibility>,
+    frame_throttle: FrameThrottle,
+    frame_timings: FrameTimingsCache,
+    // Whether the user is dragging the window's frame, from the first `WM_SIZING` until
+    // `WM_EXITSIZEMOVE`.
+    interactive_resize: Cell<bool>,
+    // The area from a `WM_SIZE` which came too soon after the last one the handler saw, and
+    // waits for `RESIZE_TIMER_ID`.
+    pending_resize: Cell<Option<ScaledArea>>,
+    // When the handler was last told the window's size.
+    last_resize: Cell<Option<Instant>>,
+    // DWM always composites at the vertical blank, so this is only reported back.
+    vsync: Cell<bool>,
     window_level: WindowLevel,
+    // Windows only keeps topmost windows in place, so we hold bottom windows there ourselves.
+    stacking_level: Cell<StackingLevel>,
+    /// Set while the window is shown as a sheet.
+    sheet: RefCell<Option<Sheet>>,
+    // The theme of the titlebar, or `None` to follow the system.
+    titlebar_theme: Cell<Option<Theme>>,
+    // The system material behind the window, for which the frame covers the whole window.
+    backdrop: Cell<Backdrop>,
+    // The visual shown instead of a redirection surface, for `PresentMode::Composition`.
+    composition: RefCell<Option<Composition>>,
+    backdrop: Cell<Backdrop>,
+    // The scale the handler renders the surface at, instead of `scale`.
+    backing_scale_override: Cell<Option<f64>>,
+    // The name the window's placement is saved under when the user stops moving or resizing
+    // it, and when it's destroyed.
+    frame_autosave_name: Option<String>,
+    // Whether `run_modal` is running a loop for this window, and how `end_modal` ended it.
+    modal_running: Cell<bool>,
+    modal_response: Cell<Option<ModalResponse>>,
+    app: Application,
+    taskbar: RefCell<TaskbarButton>,
     #[cfg(feature = "accesskit")]
     uia_init_marker: UiaInitMarker, // zero size
     #[cfg(feature = "accesskit")]
@@ -286,6 +448,9 @@ struct WndState {
     last_click_time: Instant,
     last_click_pos: (i32, i32),
     click_count: u8,
+    // The last `WM_MOUSEMOVE`, as `GetMouseMovePointsEx` expects it, which the history of the
+    // next one goes back to.
+    last_mouse_move: Option<MOUSEMOVEPOINT>,
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -317,6 +482,20 @@ const DS_RUN_IDLE: UINT = WM_USER;
 /// time it is handled, we can successfully borrow the handler.
 pub(crate) const DS_REQUEST_DESTROY: UINT = WM_USER + 1;
 
+/// The id of the timer used to delay throttled animation frames.
+///
+/// Timer ids for `request_timer` count up from 1, so they won't reach this.
+const ANIM_FRAME_TIMER_ID: usize = usize::MAX;
+
+/// The id of the timer used to poll DWM for present feedback while frames are pending.
+const PRESENT_FEEDBACK_TIMER_ID: usize = usize::MAX - 1;
+
+/// The id of the timer which delivers a coalesced `WM_SIZE` during an interactive resize.
+const RESIZE_TIMER_ID: usize = usize::MAX - 2;
+
+/// Where offscreen windows are placed, which is well past the edge of any monitor setup.
+const OFFSCREEN_POSITION: i32 = -30000;
+
 /// Extract the buttons that are being held down from wparam in mouse events.
 fn get_buttons(wparam: WPARAM) -> MouseButtons {
     let mut buttons = MouseButtons::new();
@@ -338,6 +517,65 @@ fn get_buttons(wparam: WPARAM) -> MouseButtons {
     buttons
 }
 
+/// The positions which the mouse passed through after the previous `WM_MOUSEMOVE`, on its way
+/// to (`x`, `y`), oldest first and in client pixels like (`x`, `y`).
+///
+/// Windows only reports the latest position of the mouse when its message is handled, but
+/// keeps the recent ones for `GetMouseMovePointsEx`. `previous` is the point of the previous
+/// move, and is replaced with this one.
+fn mouse_move_history(
+    hwnd: HWND,
+    x: i32,
+    y: i32,
+    previous: &mut Option<MOUSEMOVEPOINT>,
+) -> Vec<Point> {
+    const MAX_POINTS: usize = 64;
+    let mut origin = POINT { x: 0, y: 0 };
+    unsafe {
+        ClientToScreen(hwnd, &mut origin);
+    }
+    // The history holds screen coordinates modulo 2^16.
+    let mut current = MOUSEMOVEPOINT {
+        x: (x + origin.x) & 0xFFFF,
+        y: (y + origin.y) & 0xFFFF,
+        time: unsafe { GetMessageTime() } as DWORD,
+        dwExtraInfo: 0,
+    };
+    let Some(last) = previous.replace(current) else {
+        return Vec::new();
+    };
+    let mut points: [MOUSEMOVEPOINT; MAX_POINTS] = unsafe { mem::zeroed() };
+    let count = unsafe {
+        GetMouseMovePointsEx(
+            mem::size_of::<MOUSEMOVEPOINT>() as UINT,
+            &mut current,
+            points.as_mut_ptr(),
+            MAX_POINTS as c_int,
+            GMMP_USE_DISPLAY_POINTS,
+        )
+    };
+    // This fails when the point has already left the history.
+    if count <= 0 {
+        return Vec::new();
+    }
+    let unwrap = |c: i32| if c > 0x7FFF { c - 0x1_0000 } else { c };
+    // The points are newest first, starting with the current one.
+    let mut history: Vec<_> = points[1..count as usize]
+        .iter()
+        .take_while(|p| {
+            p.time > last.time || (p.time == last.time && (p.x, p.y) != (last.x, last.y))
+        })
+        .map(|p| {
+            Point::new(
+                (unwrap(p.x) - origin.x) as f64,
+                (unwrap(p.y) - origin.y) as f64,
+            )
+        })
+        .collect();
+    history.reverse();
+    history
+}
+
 fn is_point_in_client_rect(hwnd: HWND, x: i32, y: i32) -> bool {
     unsafe {
         let mut client_rect = mem::MaybeUninit::uninit();
@@ -354,6 +592,98 @@ fn is_point_in_client_rect(hwnd: HWND, x: i32, y: i32) -> bool {
     }
 }
 
+/// Windows doesn't tell us when a window is covered by other windows, but it does tell us
+/// when it is cloaked, e.g. because it is on another virtual desktop.
+fn visibility(hwnd: HWND) -> Visibility {
+    unsafe {
+        if IsWindowVisible(hwnd) == FALSE || IsIconic(hwnd) != FALSE {
+            return Visibility::Hidden;
+        }
+        let mut cloaked: DWORD = 0;
+        let hr = DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_CLOAKED,
+            &mut cloaked as *mut DWORD as *mut _,
+            mem::size_of::<DWORD>() as u32,
+        );
+        if SUCCEEDED(hr) && cloaked != 0 {
+            Visibility::Occluded
+        } else {
+            Visibility::Visible
+        }
+    }
+}
+
+/// Cloak or uncloak the window, which hides it from the user but not from the rest of the
+/// system, so it is still laid out and painted.
+fn set_cloaked(hwnd: HWND, cloaked: bool) {
+    let value: BOOL = cloaked.into();
+    let hr = unsafe {
+        DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_CLOAK,
+            &value as *const BOOL as *const c_void,
+            mem::size_of::<BOOL>() as u32,
+        )
+    };
+    if !SUCCEEDED(hr) {
+        debug!("failed to cloak the window: {}", Error::Hr(hr));
+    }
+}
+
+/// When DWM last showed a frame, and the refresh period of the display.
+///
+/// `None` if DWM has no timing info, or the performance counter can't be read.
+fn dwm_frame_displayed() -> Option<(Instant, Duration)> {
+    unsafe {
+        let mut info: DWM_TIMING_INFO = mem::zeroed();
+        info.cbSize = mem::size_of::<DWM_TIMING_INFO>() as u32;
+        // Since Windows 8.1, timing info is only available for the whole desktop.
+        if !SUCCEEDED(DwmGetCompositionTimingInfo(null_mut(), &mut info)) {
+            return None;
+        }
+        let mut frequency = mem::zeroed();
+        let mut now = mem::zeroed();
+        if QueryPerformanceFrequency(&mut frequency) == 0 || QueryPerformanceCounter(&mut now) == 0
+        {
+            return None;
+        }
+        // The timing info is in performance counter ticks.
+        let frequency = *frequency.QuadPart() as f64;
+        let ticks_ago = (*now.QuadPart() as u64).saturating_sub(info.qpcFrameDisplayed);
+        let displayed =
+            Instant::now().checked_sub(Duration::from_secs_f64(ticks_ago as f64 / frequency))?;
+        let refresh = Duration::from_secs_f64(info.qpcRefreshPeriod as f64 / frequency);
+        Some((displayed, refresh))
+    }
+}
+
+/// Returns the rectangles making up the window's update region, in pixels.
+fn update_rects(hwnd: HWND) -> Vec<RECT> {
+    unsafe {
+        let rgn = CreateRectRgn(0, 0, 0, 0);
+        if rgn.is_null() {
+            return Vec::new();
+        }
+        let mut rects = Vec::new();
+        let kind = GetUpdateRgn(hwnd, rgn, FALSE);
+        if kind == SIMPLEREGION || kind == COMPLEXREGION {
+            let size = GetRegionData(rgn, 0, null_mut());
+            // Use a buffer of RECTs so that the data is suitably aligned.
+            let len = (size as usize + mem::size_of::<RECT>() - 1) / mem::size_of::<RECT>();
+            let mut buf: Vec<RECT> = vec![mem::zeroed(); len];
+            if size != 0 && GetRegionData(rgn, size, buf.as_mut_ptr() as *mut RGNDATA) == size {
+                let data = &*(buf.as_ptr() as *const RGNDATA);
+                let first = data.Buffer.as_ptr() as *const RECT;
+                rects
+                    .extend_from_slice(std::slice::from_raw_parts(first, data.rdh.nCount as usize));
+            }
+        }
+        DeleteObject(rgn as _);
+        rects
+    }
+}
+
 fn set_style(hwnd: HWND, resizable: bool, titlebar: bool) {
     unsafe {
         let mut style = GetWindowLongPtrW(hwnd, GWL_STYLE) as u32;
@@ -405,6 +735,199 @@ fn set_style(hwnd: HWND, resizable: bool, titlebar: bool) {
     }
 }
 
+/// Whether "Choose your default app mode" is set to dark in the personalization settings.
+pub(crate) fn system_prefers_dark_theme() -> bool {
+    // This is missing before Windows 10, which only has light apps.
+    let mut light: DWORD = 1;
+    let mut size = mem::size_of::<DWORD>() as DWORD;
+    unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            "Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"
+                .to_wide()
+                .as_ptr(),
+            "AppsUseLightTheme".to_wide().as_ptr(),
+            RRF_RT_REG_DWORD,
+            null_mut(),
+            &mut light as *mut DWORD as *mut _,
+            &mut size,
+        );
+    }
+    light == 0
+}
+
+/// Draws the titlebar and frame of the window in the given theme, or the system's.
+///
+/// https://docs.microsoft.com/en-us/windows/apps/desktop/modernize/apply-windows-themes
+fn set_titlebar_theme(hwnd: HWND, theme: Option<Theme>) {
+    // Windows 10 before 20H1 used an undocumented attribute number.
+    const DWMWA_USE_IMMERSIVE_DARK_MODE: DWORD = 20;
+    const DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1: DWORD = 19;
+    let dark = match theme {
+        Some(theme) => theme == Theme::Dark,
+        None => system_prefers_dark_theme(),
+    };
+    let value = dark as BOOL;
+    unsafe {
+        let set = |attribute| {
+            DwmSetWindowAttribute(
+                hwnd,
+                attribute,
+                &value as *const BOOL as *const c_void,
+                mem::size_of::<BOOL>() as u32,
+            )
+        };
+        if !SUCCEEDED(set(DWMWA_USE_IMMERSIVE_DARK_MODE))
+            && !SUCCEEDED(set(DWMWA_USE_IMMERSIVE_DARK_MODE_BEFORE_20H1))
+        {
+            debug!("dark titlebars are not supported by this version of Windows");
+        }
+    }
+    redraw_frame(hwnd);
+}
+
+/// Sets the system material behind the window, which Windows 11 22H2 supports, falling back
+/// to Mica alone on the first release of Windows 11.
+///
+/// https://learn.microsoft.com/en-us/windows/apps/desktop/modernize/apply-mica-win32
+fn set_system_backdrop(
+    hwnd: HWND,
+    backdrop: Backdrop,
+    has_frame_margin: bool,
+) -> Result<(), ShellError> {
+    const DWMWA_SYSTEMBACKDROP_TYPE: DWORD = 38;
+    const DWMSBT_NONE: DWORD = 1;
+    const DWMSBT_MAINWINDOW: DWORD = 2;
+    const DWMSBT_TRANSIENTWINDOW: DWORD = 3;
+    const DWMSBT_TABBEDWINDOW: DWORD = 4;
+    let value = match backdrop {
+        Backdrop::None => DWMSBT_NONE,
+        Backdrop::Mica => DWMSBT_MAINWINDOW,
+        Backdrop::MicaAlt => DWMSBT_TABBEDWINDOW,
+        Backdrop::Acrylic => DWMSBT_TRANSIENTWINDOW,
+    };
+    unsafe {
+        // This fails on older versions, before anything has changed.
+        let hr = DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_SYSTEMBACKDROP_TYPE,
+            &value as *const DWORD as *const c_void,
+            mem::size_of::<DWORD>() as u32,
+        );
+        if !SUCCEEDED(hr) {
+            // Windows 11 21H2 only has Mica, behind an undocumented attribute.
+            const DWMWA_MICA_EFFECT: DWORD = 1029;
+            let mica: Option<BOOL> = match backdrop {
+                Backdrop::None => Some(FALSE),
+                Backdrop::Mica | Backdrop::MicaAlt => Some(TRUE),
+                Backdrop::Acrylic => None,
+            };
+            let has_mica = mica.map_or(false, |enable| {
+                SUCCEEDED(DwmSetWindowAttribute(
+                    hwnd,
+                    DWMWA_MICA_EFFECT,
+                    &enable as *const BOOL as *const c_void,
+                    mem::size_of::<BOOL>() as u32,
+                ))
+            });
+            if !has_mica {
+                debug!("this system backdrop is not supported by this version of Windows");
+                return Err(ShellError::Unsupported("system backdrops"));
+            }
+        }
+        // The material is drawn in the frame, so extending the frame over the whole window lets
+        // it show through the client area.
+        let margin = if backdrop != Backdrop::None { -1 } else { 0 };
+        let margins = MARGINS {
+            cxLeftWidth: margin,
+            cxRightWidth: margin,
+            cyTopHeight: if has_frame_margin && margin == 0 {
+                1
+            } else {
+                margin
+            },
+            cyBottomHeight: margin,
+        };
+        util::as_result(DwmExtendFrameIntoClientArea(hwnd, &margins))?;
+        InvalidateRect(hwnd, null(), FALSE);
+    }
+    Ok(())
+}
+
+/// Sets how the corners of the window are rounded, which Windows 11 supports.
+fn set_corner_preference(hwnd: HWND, preference: CornerPreference) -> Result<(), ShellError> {
+    const DWMWA_WINDOW_CORNER_PREFERENCE: DWORD = 33;
+    const DWMWCP_DEFAULT: DWORD = 0;
+    const DWMWCP_DONOTROUND: DWORD = 1;
+    const DWMWCP_ROUND: DWORD = 2;
+    const DWMWCP_ROUNDSMALL: DWORD = 3;
+    let value = match preference {
+        CornerPreference::Default => DWMWCP_DEFAULT,
+        CornerPreference::DoNotRound => DWMWCP_DONOTROUND,
+        CornerPreference::Round => DWMWCP_ROUND,
+        CornerPreference::RoundSmall => DWMWCP_ROUNDSMALL,
+    };
+    let hr = unsafe {
+        DwmSetWindowAttribute(
+            hwnd,
+            DWMWA_WINDOW_CORNER_PREFERENCE,
+            &value as *const DWORD as *const c_void,
+            mem::size_of::<DWORD>() as u32,
+        )
+    };
+    if !SUCCEEDED(hr) {
+        debug!("rounded corners are not supported by this version of Windows");
+        return Err(ShellError::Unsupported("rounded corners"));
+    }
+    Ok(())
+}
+
+/// Sets the colors of the titlebar and the window border, which Windows 11 supports.
+fn set_titlebar_colors(hwnd: HWND, colors: TitlebarColors) {
+    const DWMWA_BORDER_COLOR: DWORD = 34;
+    const DWMWA_CAPTION_COLOR: DWORD = 35;
+    const DWMWA_TEXT_COLOR: DWORD = 36;
+    // Restores the color which the system would use.
+    const DWMWA_COLOR_DEFAULT: COLORREF = 0xFFFF_FFFF;
+    let attributes = [
+        (DWMWA_CAPTION_COLOR, colors.caption),
+        (DWMWA_TEXT_COLOR, colors.text),
+        (DWMWA_BORDER_COLOR, colors.border),
+    ];
+    for (attribute, color) in attributes {
+        let value = color.map_or(DWMWA_COLOR_DEFAULT, |[r, g, b]| RGB(r, g, b));
+        let hr = unsafe {
+            DwmSetWindowAttribute(
+                hwnd,
+                attribute,
+                &value as *const COLORREF as *const c_void,
+                mem::size_of::<COLORREF>() as u32,
+            )
+        };
+        if !SUCCEEDED(hr) {
+            debug!("titlebar colors are not supported by this version of Windows");
+            return;
+        }
+    }
+    redraw_frame(hwnd);
+}
+
+/// DWM only redraws the frame of an inactive window with its new attributes once the window
+/// is activated, unless we tell it that the frame changed.
+fn redraw_frame(hwnd: HWND) {
+    unsafe {
+        SetWindowPos(
+            hwnd,
+            null_mut(),
+            0,
+            0,
+            0,
+            0,
+            SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED,
+        );
+    }
+}
+
 impl WndState {
     // Renders but does not present.
     fn render(&mut self, invalid: &Region) {
@@ -427,6 +950,161 @@ impl WndState {
 }
 
 impl MyWndProc {
+    /// Whether a click at the given client coordinates, in pixels, is outside of a popup which
+    /// holds the mouse capture.
+    fn is_outside_grab(&self, x: i32, y: i32) -> bool {
+        self.with_window_state(|s| {
+            let size = s.area.get().size_px();
+            s.popup_grabbing.get()
+                && (x < 0 || y < 0 || x as f64 >= size.width || y as f64 >= size.height)
+        })
+    }
+
+    /// Count a click at the given client coordinates, in pixels, returning how many clicks there
+    /// have been in a row.
+    fn click_count(&self, s: &mut WndState, x: i32, y: i32) -> u8 {
+        // TODO: it may be more precise to use the timestamp from the event.
+        let this_click = Instant::now();
+        let thresh_x = self.get_system_metric(SM_CXDOUBLECLK);
+        let thresh_y = self.get_system_metric(SM_CYDOUBLECLK);
+        let in_box = (x - s.last_click_pos.0).abs() <= thresh_x / 2
+            && (y - s.last_click_pos.1).abs() <= thresh_y / 2;
+        let threshold = Duration::from_millis(unsafe { GetDoubleClickTime() } as u64);
+        if this_click - s.last_click_time >= threshold || !in_box {
+            s.click_count = 0;
+        }
+        s.click_count = s.click_count.saturating_add(1);
+        s.last_click_time = this_click;
+        s.last_click_pos = (x, y);
+        s.click_count
+    }
+
+    /// Deliver a pointer message, which is only handled for windows that use the pointer API.
+    fn handle_pointer(&self, hwnd: HWND, msg: UINT, wparam: WPARAM) {
+        let scale = self.scale();
+        let coalesce = self.with_window_state(|s| s.coalesce_pointer_events);
+        self.with_wnd_state(|s| {
+            let mods = s.keyboard_state.get_modifiers();
+            let history = msg == WM_POINTERUPDATE;
+            let mut events = pointer::pointer_events(hwnd, wparam, history, scale, mods);
+            if coalesce {
+                events = pointer::coalesce_moves(events);
+            }
+            for (action, mut event) in events {
+                if msg == WM_POINTERWHEEL || msg == WM_POINTERHWHEEL {
+                    let delta = HIWORD(wparam as u32) as i16 as f64;
+                    let wheel_delta = match msg {
+                        WM_POINTERWHEEL if mods.shift() => Vec2::new(-delta, 0.),
+                        WM_POINTERWHEEL => Vec2::new(0., -delta),
+                        _ => Vec2::new(delta, 0.),
+                    };
+                    event.pointer_type = PointerType::Mouse(MouseInfo { wheel_delta });
+                    s.handler.wheel(&event);
+                    continue;
+                }
+                match action {
+                    PointerAction::Move => s.handler.pointer_move(&event),
+                    PointerAction::Down => {
+                        let pos = event.pos.to_px(scale);
+                        let (x, y) = (pos.x.round() as i32, pos.y.round() as i32);
+                        if self.is_outside_grab(x, y) {
+                            // This dismisses the popup, in WM_CAPTURECHANGED
+                            unsafe {
+                                ReleaseCapture();
+                            }
+                            return;
+                        }
+                        event.count = self.click_count(s, x, y);
+                        // Touches and pens are captured by the window they come down in, but the
+                        // mouse isn't.
+                        if event.is_mouse() {
+                            s.enter_mouse_capture(hwnd, pointer::mouse_button(event.button));
+                        }
+                        s.handler.pointer_down(&event);
+                    }
+                    PointerAction::Up => {
+                        s.handler.pointer_up(&event);
+                        if event.is_mouse() {
+                            let grabbing = self.with_window_state(|s| s.popup_grabbing.get());
+                            let button = pointer::mouse_button(event.button);
+                            if s.exit_mouse_capture(button) && !grabbing {
+                                self.handle.borrow().defer(DeferredOp::ReleaseMouseCapture);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Tell the handler if the window's visibility has changed.
+    fn update_visibility(&self, hwnd: HWND) {
+        let visibility = visibility(hwnd);
+        if self.with_window_state(|s| s.visibility.replace(visibility)) != visibility {
+            self.with_wnd_state(|s| s.handler.visibility_changed(visibility));
+        }
+    }
+
+    /// Show what the handler did to the composition visual, if the window has one.
+    fn commit_composition(&self) {
+        self.with_window_state(|s| {
+            if let Some(composition) = &*s.composition.borrow() {
+                composition.commit();
+            }
+        });
+    }
+
+    /// Whether the handler should wait to see `area`, because the window is being resized
+    /// interactively and it has already seen a size during this refresh of the display.
+    ///
+    /// The size is then sent when the next refresh starts, or when the resize ends.
+    fn defer_resize(&self, hwnd: HWND, area: ScaledArea) -> bool {
+        let (resizing, last) =
+            self.with_window_state(|s| (s.interactive_resize.get(), s.last_resize.get()));
+        let delay = match (resizing, last, dwm_frame_displayed()) {
+            (true, Some(last), Some((_, refresh))) => refresh.checked_sub(last.elapsed()),
+            _ => None,
+        };
+        let Some(delay) = delay.filter(|delay| !delay.is_zero()) else {
+            return false;
+        };
+        let timer_set = self.with_window_state(|s| s.pending_resize.replace(Some(area)).is_some());
+        if !timer_set {
+            let elapse = (delay.as_millis() as u32).max(USER_TIMER_MINIMUM);
+            if unsafe { SetTimer(hwnd, RESIZE_TIMER_ID, elapse, None) } == 0 {
+                self.with_window_state(|s| s.pending_resize.set(None));
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Tell the handler that the window's client area is now `area`, and paint it at that size.
+    fn resized(&self, area: ScaledArea) -> Option<()> {
+        self.with_window_state(|s| {
+            s.pending_resize.set(None);
+            s.last_resize.set(Some(Instant::now()));
+        });
+        self.with_wnd_state(|s| {
+            let size_dp = area.size_dp();
+            self.set_area(area);
+            s.handler.size(size_dp);
+            // The client area has already been resized by the time we get WM_SIZE.
+            s.handler.surface_resized(self.surface_size(area));
+            s.render(&size_dp.to_rect().into());
+        })
+        .map(|_| self.commit_composition())
+    }
+
+    fn autosave_frame(&self) {
+        let name = self.with_window_state(|s| s.frame_autosave_name.clone());
+        if let Some(name) = name {
+            let handle = crate::WindowHandle(self.handle.borrow().clone());
+            // Windows can only be fullscreen here as kiosks, which the app makes them
+            crate::autosave::save(&name, &handle, false);
+        }
+    }
+
     fn with_window_state<F, R>(&self, f: F) -> R
     where
         F: FnOnce(Rc<WindowState>) -> R,
@@ -466,6 +1144,23 @@ impl MyWndProc {
         self.with_window_state(move |state| state.scale.set(scale))
     }
 
+    /// The scale the handler renders the surface at, which is the backing scale override if
+    /// there is one.
+    fn surface_scale(&self) -> Scale {
+        match self.with_window_state(|state| state.backing_scale_override.get()) {
+            Some(scale) => Scale::new(scale, scale),
+            None => self.scale(),
+        }
+    }
+
+    /// The size in pixels of a surface covering `area`, at the surface scale.
+    fn surface_size(&self, area: ScaledArea) -> Size {
+        match self.with_window_state(|state| state.backing_scale_override.get()) {
+            Some(scale) => (area.size_dp() * scale).round(),
+            None => area.size_px(),
+        }
+    }
+
     /// Takes the invalid region and returns it, replacing it with the empty region.
     fn take_invalid(&self) -> Region {
         self.with_window_state(|state| {
@@ -547,6 +1242,70 @@ impl MyWndProc {
                     self.with_window_state(|s| s.has_titlebar.set(titlebar));
                     set_style(hwnd, self.resizable(), titlebar);
                 }
+                DeferredOp::SetWindowLevel(level) => unsafe {
+                    self.with_window_state(|s| s.stacking_level.set(level));
+                    // Windows which can't be focused at all already have `WS_EX_NOACTIVATE`.
+                    if self.handle.borrow().is_focusable() {
+                        let mut ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+                        if level == StackingLevel::DesktopWidget {
+                            ex_style |= WS_EX_NOACTIVATE;
+                        } else {
+                            ex_style &= !WS_EX_NOACTIVATE;
+                        }
+                        SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style as _);
+                    }
+                    let insert_after = match level {
+                        StackingLevel::Normal => HWND_NOTOPMOST,
+                        StackingLevel::AlwaysOnTop => HWND_TOPMOST,
+                        StackingLevel::AlwaysOnBottom | StackingLevel::DesktopWidget => HWND_BOTTOM,
+                    };
+                    if SetWindowPos(
+                        hwnd,
+                        insert_after,
+                        0,
+                        0,
+                        0,
+                        0,
+                        SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE | SWP_FRAMECHANGED,
+                    ) == 0
+                    {
+                        warn!(
+                            "failed to set window level: {}",
+                            Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                        );
+                    }
+                },
+                DeferredOp::SetSkipTaskbar(skip) => unsafe {
+                    let mut ex_style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32;
+                    if skip {
+                        ex_style = (ex_style | WS_EX_TOOLWINDOW) & !WS_EX_APPWINDOW;
+                    } else {
+                        ex_style &= !WS_EX_TOOLWINDOW;
+                    }
+                    // The taskbar only notices the change when the window is shown again.
+                    let visible = IsWindowVisible(hwnd) != FALSE;
+                    if visible {
+                        ShowWindow(hwnd, SW_HIDE);
+                    }
+                    SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style as _);
+                    if visible {
+                        ShowWindow(hwnd, SW_SHOWNA);
+                    }
+                },
+                DeferredOp::SetTitlebarTheme(theme) => {
+                    self.with_window_state(|s| s.titlebar_theme.set(theme));
+                    set_titlebar_theme(hwnd, theme);
+                }
+                DeferredOp::SetTitlebarColors(colors) => set_titlebar_colors(hwnd, colors),
+                DeferredOp::UpdateBackingScale => {
+                    let scale = self.surface_scale();
+                    let size = self.surface_size(self.with_window_state(|s| s.area.get()));
+                    self.with_wnd_state(|s| {
+                        s.handler.scale(scale);
+                        s.handler.surface_resized(size);
+                    });
+                    self.handle.borrow().invalidate();
+                }
                 DeferredOp::SetResizable(resizable) => {
                     self.with_window_state(|s| s.is_resizable.set(resizable));
                     set_style(hwnd, resizable, self.has_titlebar());
@@ -596,8 +1355,13 @@ impl MyWndProc {
                             y: pos.y as i32,
                         };
                         ClientToScreen(hwnd, &mut point);
-                        if TrackPopupMenu(hmenu, TPM_LEFTALIGN, point.x, point.y, 0, hwnd, null())
-                            == FALSE
+                        // Right to left menus open leftwards from the point, with their items
+                        // mirrored
+                        let flags = match Application::ui_direction() {
+                            UiDirection::Ltr => TPM_LEFTALIGN,
+                            UiDirection::Rtl => TPM_RIGHTALIGN | TPM_LAYOUTRTL,
+                        };
+                        if TrackPopupMenu(hmenu, flags, point.x, point.y, 0, hwnd, null()) == FALSE
                         {
                             warn!("failed to track popup menu");
                         }
@@ -613,6 +1377,41 @@ impl MyWndProc {
                         }
                     }
                 },
+                DeferredOp::BeginSheet(sheet) => {
+                    let Some(sheet_hwnd) = sheet.get_hwnd() else {
+                        return;
+                    };
+                    unsafe {
+                        // Owned windows stay above their owner.
+                        SetWindowLongPtrW(sheet_hwnd, GWLP_HWNDPARENT, hwnd as _);
+                        let mut parent_rect: RECT = mem::zeroed();
+                        let mut sheet_rect: RECT = mem::zeroed();
+                        if GetWindowRect(hwnd, &mut parent_rect) != 0
+                            && GetWindowRect(sheet_hwnd, &mut sheet_rect) != 0
+                        {
+                            let width = sheet_rect.right - sheet_rect.left;
+                            let height = sheet_rect.bottom - sheet_rect.top;
+                            SetWindowPos(
+                                sheet_hwnd,
+                                0 as HWND,
+                                (parent_rect.left + parent_rect.right - width) / 2,
+                                (parent_rect.top + parent_rect.bottom - height) / 2,
+                                0,
+                                0,
+                                SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+                            );
+                        }
+                        // Like the owner of a modal dialog, the parent gets no input until the
+                        // sheet ends.
+                        EnableWindow(hwnd, FALSE);
+                    }
+                    sheet.show();
+                }
+                DeferredOp::EndSheet(response) => {
+                    if let Some(sheet) = self.with_window_state(|s| s.sheet.take()) {
+                        sheet.end(Some(hwnd), response);
+                    }
+                }
             }
         } else {
             warn!("Could not get HWND");
@@ -688,13 +1487,21 @@ impl WndProc for MyWndProc {
                 if let Some(state) = self.state.borrow_mut().as_mut() {
                     let handle = self.handle.borrow().to_owned();
                     state.handler.connect(&handle.into());
+                    // We haven't been shown yet.
+                    state.handler.visibility_changed(Visibility::Hidden);
                 }
                 Some(0)
             }
             WM_ACTIVATE => {
+                // Switching virtual desktops deactivates the window, and cloaks it.
+                self.update_visibility(hwnd);
                 if LOWORD(wparam as u32) as u32 != 0 {
                     unsafe {
-                        if !self.has_titlebar() && !self.is_transparent() {
+                        let backdrop = self.with_window_state(|s| s.backdrop.get());
+                        if !self.has_titlebar()
+                            && !self.is_transparent()
+                            && backdrop == Backdrop::None
+                        {
                             // This makes windows paint the drop-shadow around the window
                             // since we give it a "1 pixel frame" that we paint over anyway.
                             // From my testing top seems to be the best option when it comes to avoiding resize artifacts.
@@ -741,46 +1548,69 @@ impl WndProc for MyWndProc {
                 Some(0)
             }
             WM_PAINT => unsafe {
+                self.update_visibility(hwnd);
+                self.with_window_state(|s| s.frame_timings.paint_started());
                 self.with_wnd_state(|s| {
-                    // We call prepare_paint before GetUpdateRect, so that anything invalidated during
-                    // prepare_paint will be reflected in GetUpdateRect.
+                    // We call prepare_paint before GetUpdateRgn, so that anything invalidated during
+                    // prepare_paint will be reflected in GetUpdateRgn.
                     s.handler.prepare_paint();
 
-                    let mut rect: RECT = mem::zeroed();
-                    // TODO: use GetUpdateRgn for more conservative invalidation
-                    GetUpdateRect(hwnd, &mut rect, FALSE);
-                    ValidateRect(hwnd, null_mut());
-                    let rect_dp = util::recti_to_rect(rect).to_dp(self.scale());
-                    if rect_dp.area() != 0.0 {
-                        self.invalidate_rect(rect_dp);
+                    for rect in update_rects(hwnd) {
+                        let rect_dp = util::recti_to_rect(rect).to_dp(self.scale());
+                        if rect_dp.area() != 0.0 {
+                            self.invalidate_rect(rect_dp);
+                        }
                     }
+                    ValidateRect(hwnd, null_mut());
                     let invalid = self.take_invalid();
                     if !invalid.rects().is_empty() {
                         s.handler.rebuild_resources();
                         s.render(&invalid);
                     }
                 });
+                self.commit_composition();
+                self.with_window_state(|s| s.frame_throttle.frame_painted());
+                if self.with_window_state(|s| s.frame_timings.feedback()) {
+                    // DWM doesn't tell us when frames are shown, so we poll it until they are.
+                    SetTimer(hwnd, PRESENT_FEEDBACK_TIMER_ID, USER_TIMER_MINIMUM, None);
+                }
                 Some(0)
             },
             WM_DPICHANGED => unsafe {
-                let x = HIWORD(wparam as u32) as f64 / SCALE_TARGET_DPI;
-                let y = LOWORD(wparam as u32) as f64 / SCALE_TARGET_DPI;
+                let x = LOWORD(wparam as u32) as f64 / SCALE_TARGET_DPI;
+                let y = HIWORD(wparam as u32) as f64 / SCALE_TARGET_DPI;
                 let scale = Scale::new(x, y);
                 self.set_scale(scale);
+                let surface_scale = self.surface_scale();
+                self.with_wnd_state(|s| s.handler.scale(surface_scale));
+                let old_area = self.with_window_state(|s| s.area.get());
+                // Windows suggests a rect which keeps the window the same size in display points,
+                // and which keeps it on the monitor the user dragged it to.
                 let rect: *mut RECT = lparam as *mut RECT;
                 SetWindowPos(
                     hwnd,
-                    HWND_TOPMOST,
+                    null_mut(),
                     (*rect).left,
                     (*rect).top,
                     (*rect).right - (*rect).left,
                     (*rect).bottom - (*rect).top,
-                    SWP_NOZORDER
-                        | SWP_FRAMECHANGED
-                        | SWP_DRAWFRAME
-                        | SWP_NOOWNERZORDER
-                        | SWP_NOACTIVATE,
+                    SWP_NOZORDER | SWP_NOOWNERZORDER | SWP_NOACTIVATE,
                 );
+                // There's no `WM_SIZE` if the client area kept its size in pixels, but then its
+                // size in display points has still changed.
+                if self.with_window_state(|s| s.area.get()) == old_area {
+                    let mut client = mem::zeroed::<RECT>();
+                    GetClientRect(hwnd, &mut client);
+                    let size_px = (client.right as f64, client.bottom as f64);
+                    let area = ScaledArea::from_px(size_px, scale);
+                    self.set_area(area);
+                    let size_dp = area.size_dp();
+                    self.with_wnd_state(|s| {
+                        s.handler.size(size_dp);
+                        s.handler.surface_resized(self.surface_size(area));
+                    });
+                }
+                self.handle.borrow().invalidate();
                 Some(0)
             },
             WM_NCCALCSIZE => unsafe {
@@ -865,21 +1695,64 @@ impl WndProc for MyWndProc {
                 if width == 0 || height == 0 {
                     return Some(0);
                 }
-                self.with_wnd_state(|s| {
-                    let scale = self.scale();
-                    let area = ScaledArea::from_px((width as f64, height as f64), scale);
-                    let size_dp = area.size_dp();
-                    self.set_area(area);
-                    s.handler.size(size_dp);
-                    s.render(&size_dp.to_rect().into());
-                })
-                .map(|_| 0)
+                let area = ScaledArea::from_px((width as f64, height as f64), self.scale());
+                // Dragging the frame can resize the window many times per refresh.
+                if self.defer_resize(hwnd, area) {
+                    return Some(0);
+                }
+                self.resized(area).map(|_| 0)
             }
             WM_COMMAND => {
                 self.with_wnd_state(|s| s.handler.command(LOWORD(wparam as u32) as u32));
                 Some(0)
             }
-            //TODO: WM_SYSCOMMAND
+            WM_WINDOWPOSCHANGING => {
+                let level = self.with_window_state(|s| s.stacking_level.get());
+                if matches!(
+                    level,
+                    StackingLevel::AlwaysOnBottom | StackingLevel::DesktopWidget
+                ) {
+                    let pos = unsafe { &mut *(lparam as *mut WINDOWPOS) };
+                    if pos.flags & SWP_NOZORDER == 0 {
+                        pos.hwndInsertAfter = HWND_BOTTOM;
+                    }
+                }
+                None
+            }
+            WM_WINDOWPOSCHANGED => {
+                self.update_visibility(hwnd);
+                // DefWindowProc sends WM_SIZE and WM_MOVE from this.
+                None
+            }
+            WM_SETTINGCHANGE => {
+                // The app mode is sent as a change to the "ImmersiveColorSet" policy.
+                let policy = lparam as LPWSTR;
+                if !policy.is_null() && policy.to_string().as_deref() == Some("ImmersiveColorSet") {
+                    let theme = self.with_window_state(|s| s.titlebar_theme.get());
+                    if theme.is_none() {
+                        set_titlebar_theme(hwnd, None);
+                    }
+                }
+                self.app.settings_changed();
+                None
+            }
+            WM_SYSCOLORCHANGE | WM_DWMCOLORIZATIONCOLORCHANGED => {
+                self.app.colors_changed();
+                None
+            }
+            WM_SYSCOMMAND => {
+                // The low four bits are used internally by the system.
+                let blocked = match wparam & 0xFFF0 {
+                    SC_MOVE | SC_SIZE | SC_MINIMIZE | SC_MAXIMIZE | SC_RESTORE => true,
+                    SC_CLOSE | SC_KEYMENU => true,
+                    _ => false,
+                };
+                if blocked && self.with_window_state(|s| s.is_kiosk) {
+                    Some(0)
+                } else {
+                    None
+                }
+            }
             WM_CHAR | WM_SYSCHAR | WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP
             | WM_INPUTLANGCHANGE => {
                 unsafe {
@@ -933,6 +1806,37 @@ impl WndProc for MyWndProc {
                     }
                 }
             }
+            WM_POINTERDOWN | WM_POINTERUPDATE | WM_POINTERUP | WM_POINTERWHEEL
+            | WM_POINTERHWHEEL
+                if self.with_window_state(|s| s.use_pointer_api) =>
+            {
+                self.handle_pointer(hwnd, msg, wparam);
+                Some(0)
+            }
+            WM_POINTERLEAVE if self.with_window_state(|s| s.use_pointer_api) => {
+                // Touches leave every time they are lifted, which isn't the pointer leaving the
+                // window in the sense the handler expects.
+                let scale = self.scale();
+                self.with_wnd_state(|s| {
+                    let mods = s.keyboard_state.get_modifiers();
+                    let events = pointer::pointer_events(hwnd, wparam, false, scale, mods);
+                    if events.iter().any(|(_, event)| !event.is_touch()) {
+                        s.handler.pointer_leave();
+                    }
+                });
+                Some(0)
+            }
+            // These were synthesized from touch and pen input, which the pointer messages have
+            // already delivered.
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL | WM_MOUSEMOVE | WM_LBUTTONDBLCLK | WM_LBUTTONDOWN
+            | WM_LBUTTONUP | WM_RBUTTONDBLCLK | WM_RBUTTONDOWN | WM_RBUTTONUP
+            | WM_MBUTTONDBLCLK | WM_MBUTTONDOWN | WM_MBUTTONUP | WM_XBUTTONDBLCLK
+            | WM_XBUTTONDOWN | WM_XBUTTONUP
+                if self.with_window_state(|s| s.use_pointer_api)
+                    && pointer::is_promoted_mouse_message() =>
+            {
+                Some(0)
+            }
             WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
                 // TODO: apply mouse sensitivity based on
                 // SPI_GETWHEELSCROLLLINES setting.
@@ -972,6 +1876,7 @@ impl WndProc for MyWndProc {
                         focus: false,
                         button: MouseButton::None,
                         wheel_delta,
+                        coalesced: Vec::new(),
                     };
                     s.handler.mouse_wheel(&event);
                     true
@@ -1010,18 +1915,33 @@ impl WndProc for MyWndProc {
                         }
                     }
 
-                    let pos = Point::new(x as f64, y as f64).to_dp(self.scale());
+                    let scale = self.scale();
+                    let history = mouse_move_history(hwnd, x, y, &mut s.last_mouse_move)
+                        .into_iter()
+                        .map(|pos| pos.to_dp(scale));
                     let mods = s.keyboard_state.get_modifiers();
                     let buttons = get_buttons(wparam);
-                    let event = MouseEvent {
-                        pos,
+                    let mut event = MouseEvent {
+                        pos: Point::new(x as f64, y as f64).to_dp(scale),
                         buttons,
                         mods,
                         count: 0,
                         focus: false,
                         button: MouseButton::None,
                         wheel_delta: Vec2::ZERO,
+                        coalesced: Vec::new(),
                     };
+                    if self.with_window_state(|s| s.coalesce_pointer_events) {
+                        event.coalesced = history.collect();
+                    } else {
+                        // Windows has already merged the moves, so they are taken apart again.
+                        for pos in history {
+                            s.handler.mouse_move(&MouseEvent {
+                                pos,
+                                ..event.clone()
+                            });
+                        }
+                    }
                     s.handler.mouse_move(&event);
                 });
                 Some(0)
@@ -1029,6 +1949,7 @@ impl WndProc for MyWndProc {
             WM_MOUSELEAVE => {
                 self.with_wnd_state(|s| {
                     s.has_mouse_focus = false;
+                    s.last_mouse_move = None;
                     s.handler.mouse_leave();
                 });
                 Some(0)
@@ -1075,28 +1996,17 @@ impl WndProc for MyWndProc {
                         );
                         let x = LOWORD(lparam as u32) as i16 as i32;
                         let y = HIWORD(lparam as u32) as i16 as i32;
+                        if down && self.is_outside_grab(x, y) {
+                            // This dismisses the popup, in WM_CAPTURECHANGED
+                            unsafe {
+                                ReleaseCapture();
+                            }
+                            return;
+                        }
                         let pos = Point::new(x as f64, y as f64).to_dp(self.scale());
                         let mods = s.keyboard_state.get_modifiers();
                         let buttons = get_buttons(wparam);
-                        let dct = unsafe { GetDoubleClickTime() };
-                        let count = if down {
-                            // TODO: it may be more precise to use the timestamp from the event.
-                            let this_click = Instant::now();
-                            let thresh_x = self.get_system_metric(SM_CXDOUBLECLK);
-                            let thresh_y = self.get_system_metric(SM_CYDOUBLECLK);
-                            let in_box = (x - s.last_click_pos.0).abs() <= thresh_x / 2
-                                && (y - s.last_click_pos.1).abs() <= thresh_y / 2;
-                            let threshold = Duration::from_millis(dct as u64);
-                            if this_click - s.last_click_time >= threshold || !in_box {
-                                s.click_count = 0;
-                            }
-                            s.click_count = s.click_count.saturating_add(1);
-                            s.last_click_time = this_click;
-                            s.last_click_pos = (x, y);
-                            s.click_count
-                        } else {
-                            0
-                        };
+                        let count = if down { self.click_count(s, x, y) } else { 0 };
                         let event = MouseEvent {
                             pos,
                             buttons,
@@ -1105,13 +2015,15 @@ impl WndProc for MyWndProc {
                             focus: false,
                             button,
                             wheel_delta: Vec2::ZERO,
+                            coalesced: Vec::new(),
                         };
                         if count > 0 {
                             s.enter_mouse_capture(hwnd, button);
                             s.handler.mouse_down(&event);
                         } else {
                             s.handler.mouse_up(&event);
-                            if s.exit_mouse_capture(button) {
+                            let grabbing = self.with_window_state(|s| s.popup_grabbing.get());
+                            if s.exit_mouse_capture(button) && !grabbing {
                                 self.handle.borrow().defer(DeferredOp::ReleaseMouseCapture);
                             }
                         }
@@ -1120,19 +2032,88 @@ impl WndProc for MyWndProc {
 
                 Some(0)
             }
-            WM_CLOSE => self
-                .with_wnd_state(|s| s.handler.request_close())
-                .map(|_| 0),
+            WM_CLOSE => {
+                if self.with_window_state(|s| s.is_kiosk) {
+                    // Kiosk windows can only be closed with `WindowHandle::close`.
+                    Some(0)
+                } else {
+                    self.with_wnd_state(|s| s.handler.request_close())
+                        .map(|_| 0)
+                }
+            }
             DS_REQUEST_DESTROY => {
                 unsafe {
                     DestroyWindow(hwnd);
                 }
                 Some(0)
             }
+            WM_EXITSIZEMOVE => {
+                if self.with_window_state(|s| s.interactive_resize.replace(false)) {
+                    // The final size is never left waiting.
+                    if let Some(area) = self.with_window_state(|s| s.pending_resize.get()) {
+                        unsafe {
+                            KillTimer(hwnd, RESIZE_TIMER_ID);
+                        }
+                        self.resized(area);
+                    }
+                    self.with_wnd_state(|s| s.handler.interactive_resize_ended());
+                }
+                self.autosave_frame();
+                None
+            }
             WM_DESTROY => {
+                // The window still has its placement until it's gone
+                self.autosave_frame();
+                self.with_window_state(|s| s.is_destroyed.set(true));
+                if let Some(sheet) = self.with_window_state(|s| s.sheet.take()) {
+                    sheet.end(None, ModalResponse::Cancel);
+                }
                 self.with_wnd_state(|s| s.handler.destroy());
                 Some(0)
             }
+            WM_TIMER if wparam == ANIM_FRAME_TIMER_ID => {
+                unsafe {
+                    KillTimer(hwnd, ANIM_FRAME_TIMER_ID);
+                }
+                self.with_window_state(|s| s.frame_throttle.delayed_frame_due());
+                self.handle.borrow().request_anim_frame();
+                Some(0)
+            }
+            WM_TIMER if wparam == RESIZE_TIMER_ID => {
+                unsafe {
+                    KillTimer(hwnd, RESIZE_TIMER_ID);
+                }
+                if let Some(area) = self.with_window_state(|s| s.pending_resize.get()) {
+                    self.resized(area);
+                }
+                Some(0)
+            }
+            WM_TIMER if wparam == PRESENT_FEEDBACK_TIMER_ID => {
+                let (frame_timings, visible) = self.with_window_state(|s| {
+                    (
+                        s.frame_timings.clone(),
+                        s.visibility.get() == Visibility::Visible,
+                    )
+                });
+                let displayed = dwm_frame_displayed();
+                if let Some((displayed, refresh)) = displayed {
+                    frame_timings.frame_presented(displayed, Some(refresh));
+                }
+                // DWM doesn't compose windows which can't be seen, so stop polling for them.
+                if displayed.is_none()
+                    || !visible
+                    || !frame_timings.has_pending()
+                    || !frame_timings.feedback()
+                {
+                    unsafe {
+                        KillTimer(hwnd, PRESENT_FEEDBACK_TIMER_ID);
+                    }
+                }
+                for info in frame_timings.take_feedback() {
+                    self.with_wnd_state(|s| s.handler.frame_presented(info));
+                }
+                Some(0)
+            }
             WM_TIMER => {
                 let id = wparam;
                 unsafe {
@@ -1143,19 +2124,133 @@ impl WndProc for MyWndProc {
                 self.with_wnd_state(|s| s.handler.timer(token));
                 Some(1)
             }
+            WM_SIZING => {
+                // This is only sent while the user drags the frame, before each `WM_SIZE`.
+                if !self.with_window_state(|s| s.interactive_resize.replace(true)) {
+                    self.with_wnd_state(|s| s.handler.interactive_resize_began());
+                }
+                let (aspect_ratio, increments) =
+                    self.with_window_state(|s| (s.aspect_ratio.get(), s.resize_increments.get()));
+                if aspect_ratio.is_none() && increments.is_none() {
+                    return None;
+                }
+                let rect = unsafe { &mut *(lparam as *mut RECT) };
+                // The rectangle includes the frame, which isn't part of the client area.
+                let (frame_width, frame_height) = unsafe {
+                    let mut window_rect: RECT = mem::zeroed();
+                    let mut client_rect: RECT = mem::zeroed();
+                    GetWindowRect(hwnd, &mut window_rect);
+                    GetClientRect(hwnd, &mut client_rect);
+                    (
+                        (window_rect.right - window_rect.left)
+                            - (client_rect.right - client_rect.left),
+                        (window_rect.bottom - window_rect.top)
+                            - (client_rect.bottom - client_rect.top),
+                    )
+                };
+                let width = (rect.right - rect.left - frame_width) as f64;
+                let height = (rect.bottom - rect.top - frame_height) as f64;
+                if let Some(ratio) = aspect_ratio {
+                    // The edge being dragged decides the size, and the one next to it follows.
+                    match wparam as u32 {
+                        WMSZ_TOP | WMSZ_BOTTOM => {
+                            rect.right = rect.left + (height * ratio).round() as i32 + frame_width;
+                        }
+                        WMSZ_TOPLEFT | WMSZ_TOPRIGHT => {
+                            rect.top = rect.bottom - (width / ratio).round() as i32 - frame_height;
+                        }
+                        _ => {
+                            rect.bottom = rect.top + (width / ratio).round() as i32 + frame_height;
+                        }
+                    }
+                } else if let Some(increments) = increments {
+                    let scale = self.scale();
+                    let increments = increments.to_px(scale);
+                    let base = self
+                        .with_wnd_state(|s| s.min_size)
+                        .flatten()
+                        .unwrap_or_default()
+                        .to_px(scale);
+                    let snap = |size, base, increment| {
+                        snap_to_increment(size, base, increment).round() as i32
+                    };
+                    let width = snap(width, base.width, increments.width) + frame_width;
+                    let height = snap(height, base.height, increments.height) + frame_height;
+                    // The edges which aren't being dragged stay where they are.
+                    match wparam as u32 {
+                        WMSZ_LEFT | WMSZ_TOPLEFT | WMSZ_BOTTOMLEFT => {
+                            rect.left = rect.right - width
+                        }
+                        _ => rect.right = rect.left + width,
+                    }
+                    match wparam as u32 {
+                        WMSZ_TOP | WMSZ_TOPLEFT | WMSZ_TOPRIGHT => rect.top = rect.bottom - height,
+                        _ => rect.bottom = rect.top + height,
+                    }
+                }
+                Some(TRUE as LRESULT)
+            }
             WM_CAPTURECHANGED => {
                 self.with_wnd_state(|s| s.captured_mouse_buttons.clear());
+                if self.with_window_state(|s| s.popup_grabbing.replace(false)) {
+                    self.with_wnd_state(|s| s.handler.popup_dismissed());
+                }
                 Some(0)
             }
             WM_GETMINMAXINFO => {
                 let min_max_info = unsafe { &mut *(lparam as *mut MINMAXINFO) };
+                // This message is sent before WM_CREATE, so the state might not be connected yet.
+                let aspect_ratio = self
+                    .handle
+                    .borrow()
+                    .state
+                    .upgrade()
+                    .and_then(|state| state.aspect_ratio.get());
                 self.with_wnd_state(|s| {
                     if let Some(min_size_dp) = s.min_size {
+                        let min_size_dp = match aspect_ratio {
+                            Some(ratio) => min_size_with_aspect_ratio(min_size_dp, ratio),
+                            None => min_size_dp,
+                        };
                         let min_size_px = min_size_dp.to_px(self.scale());
                         min_max_info.ptMinTrackSize.x = min_size_px.width.round() as i32;
                         min_max_info.ptMinTrackSize.y = min_size_px.height.round() as i32;
                     }
                 });
+                let maximize_mode = self
+                    .handle
+                    .borrow()
+                    .state
+                    .upgrade()
+                    .map(|state| state.maximize_mode.get())
+                    .unwrap_or_default();
+                if maximize_mode == MaximizeMode::FullMonitor {
+                    unsafe {
+                        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+                        let mut info: MONITORINFO = mem::zeroed();
+                        info.cbSize = mem::size_of::<MONITORINFO>() as u32;
+                        if GetMonitorInfoW(monitor, &mut info) != 0 {
+                            // The maximized position is relative to the monitor, not to its
+                            // work area, so the origin places the window over the taskbar.
+                            let monitor_rect = info.rcMonitor;
+                            let width = monitor_rect.right - monitor_rect.left;
+                            let height = monitor_rect.bottom - monitor_rect.top;
+                            min_max_info.ptMaxPosition.x = 0;
+                            min_max_info.ptMaxPosition.y = 0;
+                            min_max_info.ptMaxSize.x = width;
+                            min_max_info.ptMaxSize.y = height;
+                            min_max_info.ptMaxTrackSize.x =
+                                min_max_info.ptMaxTrackSize.x.max(width);
+                            min_max_info.ptMaxTrackSize.y =
+                                min_max_info.ptMaxTrackSize.y.max(height);
+                        } else {
+                            warn!(
+                                "failed to get monitor info: {}",
+                                Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                            );
+                        }
+                    }
+                }
                 Some(0)
             }
             DS_RUN_IDLE => self
@@ -1204,6 +2299,10 @@ impl WndProc for MyWndProc {
                     .flatten()
                 })
                 .map(|result| result.into().0),
+            _ if msg == WM_TASKBAR_BUTTON_CREATED.get() => {
+                self.with_window_state(|s| s.taskbar.borrow_mut().created(hwnd));
+                Some(0)
+            }
             _ => None,
         }
     }
@@ -1220,11 +2319,26 @@ impl WindowBuilder {
             show_titlebar: true,
             transparent: false,
             present_strategy: Default::default(),
+            present_mode: PresentMode::default(),
             size: None,
             min_size: None,
             position: None,
             level: None,
+            window_type: None,
             state: window::WindowState::Restored,
+            maximize_mode: MaximizeMode::default(),
+            kiosk: false,
+            offscreen: false,
+            visible: false,
+            skip_taskbar: false,
+            accepts_focus: true,
+            managed_surface: true,
+            use_pointer_api: false,
+            coalesce_pointer_events: true,
+            owner: None,
+            popup: None,
+            frame_autosave_name: None,
+            foreign_parent: None,
         }
     }
 
@@ -1254,6 +2368,11 @@ impl WindowBuilder {
         self
     }
 
+    pub fn prefer_client_side_decorations(self, _prefer: bool) -> Self {
+        // The system always draws the decorations
+        self
+    }
+
     pub fn transparent(mut self, transparent: bool) -> Self {
         // Transparency and Flip is only supported on Windows 8 and newer and
         // require DComposition
@@ -1288,12 +2407,103 @@ impl WindowBuilder {
         self
     }
 
+    pub fn maximize_mode(mut self, mode: MaximizeMode) -> Self {
+        self.maximize_mode = mode;
+        self
+    }
+
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self.kiosk = kiosk;
+        self
+    }
+
+    pub fn offscreen(mut self, offscreen: bool) -> Self {
+        self.offscreen = offscreen;
+        self
+    }
+
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self.managed_surface = managed;
+        self
+    }
+
+    pub fn use_pointer_api(mut self, enable: bool) -> Self {
+        self.use_pointer_api = enable;
+        self
+    }
+
+    pub fn with_coalesced_pointer_events(mut self, coalesce: bool) -> Self {
+        self.coalesce_pointer_events = coalesce;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_skip_taskbar(mut self, skip: bool) -> Self {
+        self.skip_taskbar = skip;
+        self
+    }
+
+    pub fn with_accepts_focus(mut self, accepts_focus: bool) -> Self {
+        self.accepts_focus = accepts_focus;
+        self
+    }
+
+    pub fn with_parent(mut self, parent: crate::WindowHandle) -> Self {
+        self.owner = Some(parent);
+        self
+    }
+
+    pub fn popup(mut self, popup: PopupConfig) -> Self {
+        self.popup = Some(popup);
+        self
+    }
+
     pub fn level(mut self, level: WindowLevel) -> Self {
         self.level = Some(level);
         self
     }
 
-    pub fn build(self) -> Result<WindowHandle, Error> {
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = Some(window_type);
+        self
+    }
+
+    pub fn with_frame_autosave_name(mut self, name: &str, _fullscreen: bool) -> Self {
+        self.frame_autosave_name = Some(name.to_owned());
+        self
+    }
+
+    pub fn build_as_child(mut self, parent: RawWindowHandle) -> Result<WindowHandle, ShellError> {
+        let RawWindowHandle::Win32(parent) = parent else {
+            return Err(ShellError::Unsupported(
+                "child windows of a window which isn't Win32",
+            ));
+        };
+        if self.kiosk || self.offscreen || self.popup.is_some() {
+            return Err(ShellError::IncompatibleOptions(
+                "a child window can't be a kiosk, offscreen or popup window",
+            ));
+        }
+        // The menu would be taken as the child's control id
+        self.menu = None;
+        self.foreign_parent = Some(parent.hwnd.get() as HWND);
+        self.build()
+    }
+
+    pub fn build(mut self) -> Result<WindowHandle, ShellError> {
+        if self.kiosk && self.popup.is_some() {
+            return Err(ShellError::IncompatibleOptions(
+                "a popup can't be a kiosk window",
+            ));
+        }
+        if let Some(popup) = &self.popup {
+            self.level = Some(WindowLevel::DropDown(popup.parent.clone()));
+            self.position = Some(popup.origin(self.size.unwrap_or_default()));
+        }
         unsafe {
             let class_name = util::CLASS_NAME.to_wide();
             let wndproc = MyWndProc {
@@ -1311,7 +2521,7 @@ impl WindowBuilder {
             let scale = Scale::new(1.0, 1.0);
 
             let mut area = ScaledArea::default();
-            let (width, height) = self
+            let (mut width, mut height) = self
                 .size
                 .map(|size| {
                     area = ScaledArea::from_dp(size, scale);
@@ -1363,6 +2573,65 @@ impl WindowBuilder {
                 window_level = WindowLevel::AppWindow;
             }
 
+            if self.skip_taskbar {
+                dwExStyle |= WS_EX_TOOLWINDOW;
+            }
+
+            // Windows has no window types, so they only pick the closest style
+            match self.window_type {
+                Some(WindowType::Dialog) => {
+                    dwStyle &= !(WS_MINIMIZEBOX | WS_MAXIMIZEBOX);
+                    dwExStyle |= WS_EX_DLGMODALFRAME;
+                }
+                Some(WindowType::Menu | WindowType::Tooltip | WindowType::DropDown) => {
+                    dwStyle = WS_POPUP;
+                    dwExStyle |= WS_EX_TOOLWINDOW;
+                }
+                // Tool windows have a smaller caption, and aren't in the taskbar
+                Some(WindowType::Utility) => dwExStyle |= WS_EX_TOOLWINDOW,
+                Some(WindowType::Splash) => dwStyle = WS_POPUP,
+                Some(WindowType::Normal) | None => (),
+            }
+
+            if let Some(owner) = &self.owner {
+                // The parent of a window which isn't `WS_CHILD` is its owner, the same as
+                // setting `GWLP_HWNDPARENT` afterwards. Owned windows stay above their owner,
+                // are hidden while it is minimized, and are destroyed along with it.
+                parent_hwnd = parent_hwnd.or_else(|| owner.0.get_hwnd());
+                // Window managers elsewhere center dialogs over their parent, but here we
+                // have to place them ourselves.
+                if let (Some(WindowType::Dialog), None, Some(size)) =
+                    (self.window_type, self.position, self.size)
+                {
+                    let owner_size = owner.get_size();
+                    let center = owner.get_position()
+                        + Vec2::new(
+                            (owner_size.width - size.width) / 2.0,
+                            (owner_size.height - size.height) / 2.0,
+                        );
+                    let scaled_point =
+                        WindowBuilder::scale_sub_window_position(center, owner.get_scale());
+                    pos_x = scaled_point.x as i32;
+                    pos_y = scaled_point.y as i32;
+                }
+            }
+
+            if !self.accepts_focus {
+                // Clicks are still delivered, but leave the active window as it is.
+                dwExStyle |= WS_EX_NOACTIVATE;
+                focusable = false;
+            }
+
+            if self.offscreen {
+                // The window is still shown, so that it gets painted, but it is placed beyond
+                // the edge of any monitor, and kept out of the taskbar and away from focus.
+                dwStyle = WS_POPUP;
+                dwExStyle |= WS_EX_NOACTIVATE | WS_EX_TOOLWINDOW;
+                focusable = false;
+                pos_x = OFFSCREEN_POSITION;
+                pos_y = OFFSCREEN_POSITION;
+            }
+
             let window = WindowState {
                 hwnd: Cell::new(0 as HWND),
                 scale: Cell::new(scale),
@@ -1375,10 +2644,40 @@ impl WindowBuilder {
                 deferred_queue: RefCell::new(Vec::new()),
                 has_titlebar: Cell::new(self.show_titlebar),
                 is_resizable: Cell::new(self.resizable),
+                aspect_ratio: Cell::new(None),
+                resize_increments: Cell::new(None),
                 is_transparent: Cell::new(self.transparent),
                 handle_titlebar: Cell::new(false),
+                maximize_mode: Cell::new(self.maximize_mode),
                 active_text_input: Cell::new(None),
+                is_destroyed: Cell::new(false),
                 is_focusable: focusable,
+                is_child: self.foreign_parent.is_some(),
+                is_kiosk: self.kiosk,
+                is_popup_grab: self.popup.as_ref().map_or(false, |popup| popup.grab),
+                popup_grabbing: Cell::new(false),
+                stacking_level: Cell::new(StackingLevel::Normal),
+                sheet: RefCell::new(None),
+                titlebar_theme: Cell::new(None),
+                backdrop: Cell::new(Backdrop::None),
+                composition: RefCell::new(None),
+                backing_scale_override: Cell::new(None),
+                frame_autosave_name: self.frame_autosave_name.take(),
+                modal_running: Cell::new(false),
+                modal_response: Cell::new(None),
+                app: self.app.clone(),
+                taskbar: RefCell::new(TaskbarButton::default()),
+                offscreen_pixels: RefCell::new(self.offscreen.then(Vec::new)),
+                managed_surface: self.managed_surface,
+                use_pointer_api: self.use_pointer_api && pointer::enable_pointer_api(),
+                coalesce_pointer_events: self.coalesce_pointer_events,
+                visibility: Cell::new(Visibility::Hidden),
+                frame_throttle: FrameThrottle::default(),
+                frame_timings: FrameTimingsCache::default(),
+                interactive_resize: Cell::new(false),
+                pending_resize: Cell::new(None),
+                last_resize: Cell::new(None),
+                vsync: Cell::new(true),
                 window_level,
                 #[cfg(feature = "accesskit")]
                 uia_init_marker: UiaInitMarker::new(),
@@ -1396,6 +2695,7 @@ impl WindowBuilder {
                 keyboard_state: KeyboardState::new(),
                 captured_mouse_buttons: MouseButtons::new(),
                 has_mouse_focus: false,
+                last_mouse_move: None,
                 last_click_time: Instant::now(),
                 last_click_pos: (0, 0),
                 click_count: 0,
@@ -1409,7 +2709,12 @@ impl WindowBuilder {
                 dwStyle &= !(WS_SYSMENU | WS_OVERLAPPED);
             }
 
-            if self.present_strategy == PresentStrategy::Flip {
+            let mut composition = self.present_mode == PresentMode::Composition;
+            if composition && OPTIONAL_FUNCTIONS.DCompositionCreateDevice.is_none() {
+                warn!("composition needs Windows 8 or newer, so the window is redirected");
+                composition = false;
+            }
+            if self.present_strategy == PresentStrategy::Flip || composition {
                 dwExStyle |= WS_EX_NOREDIRECTIONBITMAP;
             }
 
@@ -1419,6 +2724,44 @@ impl WindowBuilder {
                 _ => (),
             };
 
+            if self.kiosk {
+                // No caption, system menu or sizing border, covering the primary monitor.
+                dwStyle = WS_POPUP;
+                dwExStyle |= WS_EX_TOPMOST;
+                let monitor = MonitorFromPoint(POINT { x: 0, y: 0 }, MONITOR_DEFAULTTOPRIMARY);
+                let mut info: MONITORINFO = mem::zeroed();
+                info.cbSize = mem::size_of::<MONITORINFO>() as u32;
+                if GetMonitorInfoW(monitor, &mut info) != 0 {
+                    let monitor_rect = info.rcMonitor;
+                    pos_x = monitor_rect.left;
+                    pos_y = monitor_rect.top;
+                    width = monitor_rect.right - monitor_rect.left;
+                    height = monitor_rect.bottom - monitor_rect.top;
+                } else {
+                    warn!(
+                        "failed to get monitor info: {}",
+                        Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                    );
+                }
+            }
+
+            if let Some(parent) = self.foreign_parent {
+                // Child windows are drawn in their parent's client area, without a frame, and
+                // are positioned relative to it.
+                dwStyle = WS_CHILD | WS_CLIPSIBLINGS | WS_CLIPCHILDREN;
+                dwExStyle &= WS_EX_NOREDIRECTIONBITMAP | WS_EX_NOACTIVATE;
+                parent_hwnd = Some(parent);
+                if self.position.is_none() {
+                    (pos_x, pos_y) = (0, 0);
+                }
+                // `CW_USEDEFAULT` isn't allowed for child windows, so they fill their parent.
+                if self.size.is_none() {
+                    let mut rect = mem::zeroed::<RECT>();
+                    GetClientRect(parent, &mut rect);
+                    (width, height) = (rect.right, rect.bottom);
+                }
+            }
+
             let hwnd = create_window(
                 dwExStyle,
                 class_name.as_ptr(),
@@ -1434,10 +2777,22 @@ impl WindowBuilder {
                 win,
             );
             if hwnd.is_null() {
-                return Err(Error::NullHwnd);
+                return Err(Error::NullHwnd.into());
+            }
+
+            if composition {
+                match Composition::new(hwnd) {
+                    Ok(composition) => {
+                        if let Some(w) = handle.state.upgrade() {
+                            *w.composition.borrow_mut() = Some(composition);
+                        }
+                    }
+                    Err(e) => warn!("failed to set up the window's composition: {e}"),
+                }
             }
 
-            if let Some(size_dp) = self.size {
+            // Kiosk windows keep the size of the monitor.
+            if let Some(size_dp) = self.size.filter(|_| !self.kiosk) {
                 if let Ok(scale) = handle.get_scale() {
                     let size_px = size_dp.to_px(scale);
                     if SetWindowPos(
@@ -1458,16 +2813,15 @@ impl WindowBuilder {
                 }
             }
 
-            // Dark mode support
-            // https://docs.microsoft.com/en-us/windows/apps/desktop/modernize/apply-windows-themes
-            const DWMWA_USE_IMMERSIVE_DARK_MODE: u32 = 20;
-            let value: BOOL = 1;
-            let value_ptr = &value as *const _ as *const c_void;
-            DwmSetWindowAttribute(
+            set_titlebar_theme(hwnd, None);
+
+            // The taskbar runs at a lower integrity level than an elevated process, which
+            // would block its message otherwise.
+            ChangeWindowMessageFilterEx(
                 hwnd,
-                DWMWA_USE_IMMERSIVE_DARK_MODE,
-                value_ptr,
-                mem::size_of::<BOOL>() as u32,
+                WM_TASKBAR_BUTTON_CREATED.get(),
+                MSGFLT_ALLOW,
+                null_mut(),
             );
 
             self.app.add_window(hwnd);
@@ -1475,6 +2829,9 @@ impl WindowBuilder {
             if let Some(accels) = accels {
                 register_accel(hwnd, &accels);
             }
+            if self.visible {
+                handle.show();
+            }
             Ok(handle)
         }
     }
@@ -1506,6 +2863,14 @@ pub(crate) unsafe extern "system" fn win_proc_dispatch(
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
+    let _span = common_util::event_span(message_name(msg));
+    if msg == WM_NCCREATE {
+        // This is done for us with per-monitor v2 awareness, but before it the title bar
+        // keeps the scale of the monitor the window was created on unless we ask.
+        if let Some(func) = OPTIONAL_FUNCTIONS.EnableNonClientDpiScaling {
+            func(hwnd);
+        }
+    }
     if msg == WM_CREATE {
         let create_struct = &*(lparam as *const CREATESTRUCTW);
         let wndproc_ptr = create_struct.lpCreateParams;
@@ -1532,6 +2897,52 @@ pub(crate) unsafe extern "system" fn win_proc_dispatch(
     }
 }
 
+/// The name of a window message, for [`common_util::event_span`].
+fn message_name(msg: UINT) -> &'static str {
+    match msg {
+        WM_CREATE => "WM_CREATE",
+        WM_ACTIVATE => "WM_ACTIVATE",
+        WM_SETFOCUS => "WM_SETFOCUS",
+        WM_KILLFOCUS => "WM_KILLFOCUS",
+        WM_PAINT => "WM_PAINT",
+        WM_ERASEBKGND => "WM_ERASEBKGND",
+        WM_DPICHANGED => "WM_DPICHANGED",
+        WM_NCCALCSIZE => "WM_NCCALCSIZE",
+        WM_NCHITTEST => "WM_NCHITTEST",
+        WM_SIZE => "WM_SIZE",
+        WM_SIZING => "WM_SIZING",
+        WM_WINDOWPOSCHANGING => "WM_WINDOWPOSCHANGING",
+        WM_WINDOWPOSCHANGED => "WM_WINDOWPOSCHANGED",
+        WM_GETMINMAXINFO => "WM_GETMINMAXINFO",
+        WM_COMMAND => "WM_COMMAND",
+        WM_SYSCOMMAND => "WM_SYSCOMMAND",
+        WM_KEYDOWN => "WM_KEYDOWN",
+        WM_KEYUP => "WM_KEYUP",
+        WM_SYSKEYDOWN => "WM_SYSKEYDOWN",
+        WM_SYSKEYUP => "WM_SYSKEYUP",
+        WM_CHAR => "WM_CHAR",
+        WM_MOUSEMOVE => "WM_MOUSEMOVE",
+        WM_MOUSEWHEEL => "WM_MOUSEWHEEL",
+        WM_MOUSEHWHEEL => "WM_MOUSEHWHEEL",
+        WM_MOUSELEAVE => "WM_MOUSELEAVE",
+        WM_LBUTTONDOWN => "WM_LBUTTONDOWN",
+        WM_LBUTTONUP => "WM_LBUTTONUP",
+        WM_RBUTTONDOWN => "WM_RBUTTONDOWN",
+        WM_RBUTTONUP => "WM_RBUTTONUP",
+        WM_POINTERDOWN => "WM_POINTERDOWN",
+        WM_POINTERUP => "WM_POINTERUP",
+        WM_POINTERUPDATE => "WM_POINTERUPDATE",
+        WM_TIMER => "WM_TIMER",
+        WM_CLOSE => "WM_CLOSE",
+        WM_DESTROY => "WM_DESTROY",
+        WM_NCDESTROY => "WM_NCDESTROY",
+        WM_SETTINGCHANGE => "WM_SETTINGCHANGE",
+        WM_POWERBROADCAST => "WM_POWERBROADCAST",
+        WM_GETOBJECT => "WM_GETOBJECT",
+        _ => "window message",
+    }
+}
+
 /// Create a window (same parameters as CreateWindowExW) with associated WndProc.
 #[allow(clippy::too_many_arguments)]
 unsafe fn create_window(
@@ -1600,13 +3011,40 @@ impl WindowHandle {
             } else {
                 SW_SHOWNOACTIVATE
             };
+            // A window which is being revealed is cloaked until it has painted, so the user
+            // never sees it before its first frame.
+            let cloak = unsafe { IsWindowVisible(hwnd) == FALSE } && show != SW_MINIMIZE;
             unsafe {
+                if cloak {
+                    set_cloaked(hwnd, true);
+                }
                 ShowWindow(hwnd, show);
                 UpdateWindow(hwnd);
+                if cloak {
+                    set_cloaked(hwnd, false);
+                }
+                if w.is_popup_grab {
+                    SetCapture(hwnd);
+                    w.popup_grabbing.set(true);
+                }
+            }
+        }
+    }
+
+    pub fn hide(&self) {
+        if let Some(w) = self.state.upgrade() {
+            unsafe {
+                ShowWindow(w.hwnd.get(), SW_HIDE);
             }
         }
     }
 
+    pub fn is_alive(&self) -> bool {
+        self.state
+            .upgrade()
+            .map_or(false, |w| !w.is_destroyed.get())
+    }
+
     pub fn close(&self) {
         if let Some(w) = self.state.upgrade() {
             let hwnd = w.hwnd.get();
@@ -1625,6 +3063,18 @@ impl WindowHandle {
     pub fn request_anim_frame(&self) {
         if let Some(w) = self.state.upgrade() {
             let hwnd = w.hwnd.get();
+            match w.frame_throttle.schedule() {
+                FrameSchedule::Now => (),
+                FrameSchedule::After(delay) => {
+                    let elapse = delay.as_millis() as u32;
+                    if unsafe { SetTimer(hwnd, ANIM_FRAME_TIMER_ID, elapse, None) } != 0 {
+                        return;
+                    }
+                    // If we can't delay the frame, paint it now.
+                    w.frame_throttle.delayed_frame_due();
+                }
+                FrameSchedule::Scheduled => return,
+            }
             unsafe {
                 // With the RDW_INTERNALPAINT flag, RedrawWindow causes a WM_PAINT message, but without
                 // invalidating anything. We do this because we won't know the final invalidated region
@@ -1661,12 +3111,213 @@ impl WindowHandle {
         self.request_anim_frame();
     }
 
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), ShellError> {
+        let w = self.state.upgrade().ok_or(ShellError::WindowDropped)?;
+        if !w.managed_surface {
+            return Err(anyhow::anyhow!(
+                "present_pixels is not supported on windows without a managed surface"
+            )
+            .into());
+        }
+        if let Some(offscreen_pixels) = w.offscreen_pixels.borrow_mut().as_mut() {
+            *offscreen_pixels = format.to_bgra(buf).into_owned();
+            return Ok(());
+        }
+        if w.composition.borrow().is_some() {
+            return Err(ShellError::Unsupported(
+                "present_pixels on windows presented with composition",
+            ));
+        }
+        // Transparent windows have no redirection bitmap, so GDI output would never be seen.
+        if w.is_transparent.get() {
+            return Err(ShellError::Unsupported(
+                "present_pixels on transparent windows",
+            ));
+        }
+        let size_px = w.area.get().size_px();
+        if (width as f64, height as f64) != (size_px.width, size_px.height) {
+            return Err(anyhow::anyhow!(
+                "pixel buffer is {width}x{height}, but the window is {}x{}",
+                size_px.width,
+                size_px.height
+            )
+            .into());
+        }
+        // With BI_RGB, 32 bit DIBs are stored as BGRX.
+        let pixels = format.to_bgra(buf);
+        let width = width as c_int;
+        let height = height as c_int;
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+                biWidth: width,
+                // A negative height makes the DIB top-down.
+                biHeight: -height,
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB,
+                ..unsafe { mem::zeroed() }
+            },
+            ..unsafe { mem::zeroed() }
+        };
+        let hwnd = w.hwnd.get();
+        unsafe {
+            let hdc = GetDC(hwnd);
+            if hdc.is_null() {
+                return Err(Error::Hr(HRESULT_FROM_WIN32(GetLastError())).into());
+            }
+            defer!(ReleaseDC(hwnd, hdc););
+            let lines = StretchDIBits(
+                hdc,
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                pixels.as_ptr() as *const c_void,
+                &info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+            if lines == 0 {
+                return Err(Error::Hr(HRESULT_FROM_WIN32(GetLastError())).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, ShellError> {
+        let w = self.state.upgrade().ok_or(ShellError::WindowDropped)?;
+        let offscreen_pixels = w.offscreen_pixels.borrow();
+        let pixels = offscreen_pixels.as_ref().ok_or(ShellError::Unsupported(
+            "read_back_pixels on windows that aren't offscreen",
+        ))?;
+        let size_px = w.area.get().size_px();
+        let len = size_px.width as usize * size_px.height as usize * PixelFormat::BYTES_PER_PIXEL;
+        // Nothing has been presented at the current size yet.
+        if pixels.len() != len {
+            return Ok([0, 0, 0, 0xff].repeat(len / PixelFormat::BYTES_PER_PIXEL));
+        }
+        Ok(format.from_bgra(pixels.clone()))
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        // Not in winapi: also capture content drawn with DirectComposition or DirectX.
+        const PW_RENDERFULLCONTENT: UINT = 0x2;
+        let w = self.state.upgrade()?;
+        let size_px = w.area.get().size_px();
+        let (width, height) = (size_px.width as u32, size_px.height as u32);
+        if w.offscreen_pixels.borrow().is_some() {
+            // Offscreen windows are never drawn, so `PrintWindow` would only see black.
+            let pixels = self.read_back_pixels(PixelFormat::Rgba8).ok()?;
+            return Some(ImageBuf::new(width, height, pixels).with_scale(w.scale.get()));
+        }
+        let hwnd = w.hwnd.get();
+        let info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+                biWidth: width as c_int,
+                // A negative height makes the DIB top-down.
+                biHeight: -(height as c_int),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB,
+                ..unsafe { mem::zeroed() }
+            },
+            ..unsafe { mem::zeroed() }
+        };
+        unsafe {
+            let mut affinity: DWORD = WDA_NONE;
+            if GetWindowDisplayAffinity(hwnd, &mut affinity) != FALSE && affinity != WDA_NONE {
+                // The window is protected from capture.
+                return None;
+            }
+            let hdc = GetDC(hwnd);
+            if hdc.is_null() {
+                warn!(
+                    "failed to capture window: {}",
+                    Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                );
+                return None;
+            }
+            defer!(ReleaseDC(hwnd, hdc););
+            let mem_dc = CreateCompatibleDC(hdc);
+            defer!(DeleteDC(mem_dc););
+            let mut bits = null_mut();
+            let bitmap = CreateDIBSection(hdc, &info, DIB_RGB_COLORS, &mut bits, null_mut(), 0);
+            if bitmap.is_null() {
+                warn!(
+                    "failed to capture window: {}",
+                    Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                );
+                return None;
+            }
+            defer!(DeleteObject(bitmap as HGDIOBJ););
+            let old_bitmap = SelectObject(mem_dc, bitmap as HGDIOBJ);
+            defer!(SelectObject(mem_dc, old_bitmap););
+            if PrintWindow(hwnd, mem_dc, PW_CLIENTONLY | PW_RENDERFULLCONTENT) == FALSE {
+                warn!("PrintWindow failed to capture the window");
+                return None;
+            }
+            GdiFlush();
+            let len = width as usize * height as usize * PixelFormat::BYTES_PER_PIXEL;
+            let mut pixels = std::slice::from_raw_parts(bits as *const u8, len).to_vec();
+            // With BI_RGB, 32 bit DIBs are stored as BGRX, so the alpha channel is unset.
+            for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+                px[3] = 0xff;
+            }
+            Some(
+                ImageBuf::new(width, height, PixelFormat::Rgba8.from_bgra(pixels))
+                    .with_scale(w.scale.get()),
+            )
+        }
+    }
+
+    pub fn begin_sheet(&self, sheet: &WindowHandle, completion: Box<dyn FnOnce(ModalResponse)>) {
+        if let Some(w) = sheet.state.upgrade() {
+            *w.sheet.borrow_mut() = Some(Sheet {
+                parent: self.clone(),
+                completion,
+            });
+            self.defer(DeferredOp::BeginSheet(sheet.clone()));
+        }
+    }
+
+    pub fn end_sheet(&self, response: ModalResponse) {
+        self.defer(DeferredOp::EndSheet(response));
+    }
+
     fn defer(&self, op: DeferredOp) {
         if let Some(w) = self.state.upgrade() {
             w.deferred_queue.borrow_mut().push(op);
         }
     }
 
+    pub fn set_present_feedback(&self, enabled: bool) {
+        if let Some(w) = self.state.upgrade() {
+            w.frame_timings.set_feedback(enabled);
+        }
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        if let Some(w) = self.state.upgrade() {
+            w.vsync.set(enabled);
+        }
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.state.upgrade().map_or(true, |w| w.vsync.get())
+    }
+
     /// Set the title for this menu.
     pub fn set_title(&self, title: &str) {
         if let Some(w) = self.state.upgrade() {
@@ -1680,6 +3331,10 @@ impl WindowHandle {
     }
 
     pub fn show_titlebar(&self, show_titlebar: bool) {
+        if self.is_child() {
+            debug!("child windows have no titlebar");
+            return;
+        }
         self.defer(DeferredOp::ShowTitlebar(show_titlebar));
     }
 
@@ -1789,11 +3444,111 @@ impl WindowHandle {
         self.defer(DeferredOp::SetResizable(resizable));
     }
 
+    pub fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        if let Some(w) = self.state.upgrade() {
+            if ratio.is_some() {
+                w.resize_increments.set(None);
+            }
+            w.aspect_ratio.set(ratio);
+        }
+    }
+
+    pub fn set_resize_increments(&self, increments: Option<Size>) {
+        if let Some(w) = self.state.upgrade() {
+            if increments.is_some() {
+                w.aspect_ratio.set(None);
+            }
+            w.resize_increments.set(increments);
+        }
+    }
+
+    pub fn set_window_level(&self, level: StackingLevel) {
+        self.defer(DeferredOp::SetWindowLevel(level));
+    }
+
+    pub fn set_skip_taskbar(&self, skip: bool) {
+        self.defer(DeferredOp::SetSkipTaskbar(skip));
+    }
+
+    pub fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        self.defer(DeferredOp::SetTitlebarTheme(theme));
+    }
+
+    pub fn run_modal(&self) -> ModalResponse {
+        let Some(w) = self.state.upgrade() else {
+            return ModalResponse::Cancel;
+        };
+        if w.modal_running.replace(true) {
+            warn!("the window is already running a modal loop");
+            return ModalResponse::Cancel;
+        }
+        let hwnd = w.hwnd.get();
+        // Disable the other windows, as `DialogBox` does with its owner.
+        let disabled: Vec<HWND> = w
+            .app
+            .windows()
+            .into_iter()
+            .filter(|&other| other != hwnd && unsafe { IsWindowEnabled(other) } != FALSE)
+            .collect();
+        for &other in &disabled {
+            unsafe { EnableWindow(other, FALSE) };
+        }
+        self.show();
+        w.app.run_modal_loop(|| {
+            w.modal_response.get().is_some() || unsafe { IsWindow(hwnd) } == FALSE
+        });
+        for other in disabled {
+            unsafe { EnableWindow(other, TRUE) };
+        }
+        w.modal_running.set(false);
+        w.modal_response.take().unwrap_or(ModalResponse::Cancel)
+    }
+
+    pub fn end_modal(&self, response: ModalResponse) {
+        if let Some(w) = self.state.upgrade() {
+            if w.modal_running.get() {
+                w.modal_response.set(Some(response));
+            }
+        }
+    }
+
+    pub fn focus_next_window(&self) {
+        let Some(w) = self.state.upgrade() else {
+            return;
+        };
+        let hwnd = w.hwnd.get();
+        let windows = w.app.windows();
+        unsafe {
+            // Activate the one furthest back, so that calling this again moves on through all of
+            // the windows rather than back to this one.
+            let mut next = GetWindow(hwnd, GW_HWNDLAST);
+            while !next.is_null() {
+                if next != hwnd
+                    && windows.contains(&next)
+                    && IsWindowVisible(next) != FALSE
+                    && IsWindowEnabled(next) != FALSE
+                {
+                    SetForegroundWindow(next);
+                    return;
+                }
+                next = GetWindow(next, GW_HWNDPREV);
+            }
+        }
+    }
+
     // Sets the window state.
     pub fn set_window_state(&self, state: window::WindowState) {
+        if self.is_child() {
+            debug!("child windows can't be minimized or maximized");
+            return;
+        }
         self.defer(DeferredOp::SetWindowState(state));
     }
 
+    fn is_child(&self) -> bool {
+        self.state.upgrade().map_or(false, |w| w.is_child)
+    }
+
     // Gets the window state.
     pub fn get_window_state(&self) -> window::WindowState {
         // We can not store state internally because it could be modified externally.
@@ -1820,6 +3575,12 @@ impl WindowHandle {
         }
     }
 
+    pub fn set_maximize_mode(&self, mode: MaximizeMode) {
+        if let Some(w) = self.state.upgrade() {
+            w.maximize_mode.set(mode);
+        }
+    }
+
     // Allows windows to handle a custom titlebar like it was the default one.
     pub fn handle_titlebar(&self, val: bool) {
         if let Some(w) = self.state.upgrade() {
@@ -1828,6 +3589,10 @@ impl WindowHandle {
     }
 
     pub fn set_menu(&self, menu: Menu) {
+        if self.is_child() {
+            debug!("child windows have no menu bar");
+            return;
+        }
         let accels = menu.accels();
         let hmenu = menu.into_hmenu();
         if let Some(w) = self.state.upgrade() {
@@ -1869,10 +3634,59 @@ impl WindowHandle {
         }
     }
 
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.state.upgrade()?.active_text_input.get()
+    }
+
     pub fn update_text_field(&self, _token: TextFieldToken, _update: Event) {
         // noop until we get a real text input implementation
     }
 
+    /// Open the emoji panel, whose picks arrive as `WM_CHAR`s like typed text.
+    ///
+    /// It has no API, so the shortcut the user would press is sent instead.
+    pub fn show_character_palette(&self) {
+        let Some(hwnd) = self.get_hwnd() else {
+            return;
+        };
+        unsafe {
+            // Synthesized input goes to the foreground window, whichever that is.
+            if GetForegroundWindow() != hwnd {
+                warn!("show_character_palette: the window isn't in the foreground");
+                return;
+            }
+            let mut inputs: [INPUT; 4] = std::mem::zeroed();
+            let keys = [
+                (VK_LWIN, 0),
+                (VK_OEM_PERIOD, 0),
+                (VK_OEM_PERIOD, KEYEVENTF_KEYUP),
+                (VK_LWIN, KEYEVENTF_KEYUP),
+            ];
+            for (input, (vk, flags)) in inputs.iter_mut().zip(keys) {
+                input.type_ = INPUT_KEYBOARD;
+                *input.u.ki_mut() = KEYBDINPUT {
+                    wVk: vk as WORD,
+                    wScan: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                };
+            }
+            let sent = SendInput(
+                inputs.len() as UINT,
+                inputs.as_mut_ptr(),
+                std::mem::size_of::<INPUT>() as c_int,
+            );
+            if sent != inputs.len() as UINT {
+                warn!(
+                    "failed to open the emoji panel: {}",
+                    Error::Hr(HRESULT_FROM_WIN32(GetLastError()))
+                );
+            }
+        }
+    }
+
     /// Request a timer event.
     ///
     /// The return value is an identifier.
@@ -2002,6 +3816,28 @@ impl WindowHandle {
         }
     }
 
+    /// Get the timings of the last frame which reached the screen.
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        match self.state.upgrade() {
+            Some(w) => {
+                // DWM only keeps the time of the last frame it showed, so check for it now.
+                if let Some((displayed, refresh)) = dwm_frame_displayed() {
+                    w.frame_timings.frame_presented(displayed, Some(refresh));
+                }
+                w.frame_timings.last()
+            }
+            None => FrameTimings::default(),
+        }
+    }
+
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        if let Some(w) = self.state.upgrade() {
+            if w.backing_scale_override.replace(scale) != scale {
+                self.defer(DeferredOp::UpdateBackingScale);
+            }
+        }
+    }
+
     /// Get the `Scale` of the window.
     pub fn get_scale(&self) -> Result<Scale, ShellError> {
         Ok(self
@@ -2078,6 +3914,61 @@ impl IdleHandle {
     }
 }
 
+impl crate::platform::windows::WindowExt for crate::WindowHandle {
+    fn set_titlebar_colors(&self, colors: TitlebarColors) {
+        self.0.defer(DeferredOp::SetTitlebarColors(colors));
+    }
+
+    fn set_taskbar_progress(&self, state: ProgressState, value: Option<f64>) {
+        if let Some(w) = self.0.state.upgrade() {
+            w.taskbar
+                .borrow_mut()
+                .set_progress(w.hwnd.get(), state, value);
+        }
+    }
+
+    fn set_taskbar_overlay_icon(&self, icon: Option<ImageBuf>, description: &str) {
+        if let Some(w) = self.0.state.upgrade() {
+            w.taskbar
+                .borrow_mut()
+                .set_overlay(w.hwnd.get(), icon, description);
+        }
+    }
+
+    fn flash_taskbar_button(&self, flash: bool) {
+        if let Some(hwnd) = self.0.get_hwnd() {
+            taskbar::flash(hwnd, flash);
+        }
+    }
+
+    fn set_system_backdrop(&self, backdrop: Backdrop) -> Result<(), ShellError> {
+        let w = self.0.state.upgrade().ok_or(ShellError::WindowDropped)?;
+        // Windows without a titlebar keep a one pixel frame for their drop shadow.
+        let has_frame_margin = !w.has_titlebar.get() && !w.is_transparent.get();
+        set_system_backdrop(w.hwnd.get(), backdrop, has_frame_margin)?;
+        w.backdrop.set(backdrop);
+        Ok(())
+    }
+
+    fn set_corner_preference(&self, preference: CornerPreference) -> Result<(), ShellError> {
+        let hwnd = self.0.get_hwnd().ok_or(ShellError::WindowDropped)?;
+        set_corner_preference(hwnd, preference)
+    }
+
+    fn composition_visual(&self) -> Option<*mut std::ffi::c_void> {
+        let w = self.0.state.upgrade()?;
+        let composition = w.composition.borrow();
+        composition.as_ref().map(|it| it.visual().cast())
+    }
+}
+
+impl crate::platform::windows::WindowBuilderExt for crate::WindowBuilder {
+    fn with_present_mode(mut self, mode: PresentMode) -> Self {
+        self.0.present_mode = mode;
+        self
+    }
+}
+
 #[cfg(feature = "accesskit")]
 impl accesskit::ActionHandler for AccessKitActionHandler {
     fn do_action(&self, request: accesskit::ActionRequest) {
@@ -23,24 +23,31 @@ use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Error};
 use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::present::{self, ConnectionExt as _};
+use x11rb::protocol::randr::{self, ConnectionExt as _};
 use x11rb::protocol::render::{self, ConnectionExt as _, Pictformat};
-use x11rb::protocol::xinput::ChangeReason;
+use x11rb::protocol::sync::{self, ConnectionExt as _};
+use x11rb::protocol::xinput::{self, ChangeReason, PointerEventFlags};
 use x11rb::protocol::xkb::{EventType, MapPart, SelectEventsAux};
 use x11rb::protocol::xproto::{
-    self, ConnectionExt as _, CreateWindowAux, EventMask, Timestamp, Visualtype, WindowClass,
+    self, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask,
+    Timestamp, Visualtype, WindowClass,
 };
 use x11rb::protocol::Event;
-use x11rb::resource_manager::{
-    new_from_default as new_resource_db_from_default, Database as ResourceDb,
-};
+use x11rb::resource_manager::new_from_default as new_resource_db_from_default;
 use x11rb::xcb_ffi::XCBConnection;
 
-use crate::application::AppHandler;
+use crate::application::{AppHandler, EventSourceToken, LoopMode, LoopStatus, UiDirection};
+use crate::common_util::{self, shared_queue, SharedDequeuer, SharedEnqueuer};
+use crate::kurbo::Vec2;
+use crate::scale::Scale;
 
+use super::buffers;
 use super::clipboard::Clipboard;
 use super::pointer::{DeviceInfo, PointersState};
 use super::util;
 use super::window::Window;
+use super::xsettings::{self, XSettings};
 use crate::backend::shared::linux;
 use crate::backend::shared::xkb;
 
@@ -64,12 +71,37 @@ use crate::backend::shared::xkb;
 //
 // https://www.x.org/releases/X11R7.6/doc/xorg-docs/specs/ICCCM/icccm.html#window_deletion
 //
+// _NET_WM_PING
+//
+// Including this atom in WM_PROTOCOLS lets the window manager check that we're still
+// responding. It sends the ping as a client message, which we send back to the root window.
+// A window manager may offer to kill an application which doesn't answer in time.
+//
+// https://specifications.freedesktop.org/wm-spec/wm-spec-1.3.html (_NET_WM_PING)
+//
 // _NET_WM_PID
 //
 // A property containing the PID of the process that created the window.
 //
 // https://specifications.freedesktop.org/wm-spec/wm-spec-1.3.html#idm45805407915360
 //
+// _NET_WM_SYNC_REQUEST_COUNTER
+//
+// The sync counters a window's frames are synchronized with its compositor by. While the
+// second, extended, counter is odd, the window is drawing a frame and isn't repainted by the
+// compositor, which we use to keep a new window from being shown until it has painted.
+//
+// https://fishsoup.net/misc/wm-spec-synchronization.html
+//
+// _NET_WM_SYNC_REQUEST
+//
+// Including this atom in WM_PROTOCOLS tells the window manager that we answer its sync
+// requests. Before each resize, it sends us a value which we set a sync counter to once we
+// have painted at the new size, and it doesn't resize us again until we do. This keeps an
+// interactive resize going at the rate we can paint.
+//
+// https://specifications.freedesktop.org/wm-spec/wm-spec-1.3.html (_NET_WM_SYNC_REQUEST)
+//
 // _NET_WM_NAME
 //
 // A version of WM_NAME supporting UTF8 text.
@@ -80,6 +112,26 @@ use crate::backend::shared::xkb;
 //
 // The type of _NET_WM_NAME
 //
+// _NET_STARTUP_ID
+//
+// The startup notification ID a window was launched with, so that the launcher knows which
+// startup sequence the window belongs to.
+//
+// _NET_STARTUP_INFO_BEGIN, _NET_STARTUP_INFO
+//
+// The message types for startup notification messages, which are split across as many client
+// messages as they need.
+//
+// https://specifications.freedesktop.org/startup-notification-spec/startup-notification-0.1.txt
+//
+// _XSETTINGS_SETTINGS
+//
+// The property of the XSETTINGS manager's window which holds the settings, and its type
+//
+// MANAGER
+//
+// The client message a new XSETTINGS manager sends to the root window, to announce itself
+//
 // CLIPBOARD
 //
 // The name of the clipboard selection; used for implementing copy&paste
@@ -95,22 +147,54 @@ use crate::backend::shared::xkb;
 // INCR
 //
 // Type used for incremental selection transfers
+//
+// TEXT
+//
+// A target for getting the selection as text, in whichever encoding the owner picks
 x11rb::atom_manager! {
     pub(crate) AppAtoms: AppAtomsCookie {
         WM_PROTOCOLS,
         WM_DELETE_WINDOW,
+        _NET_WM_PING,
         _NET_WM_PID,
+        _NET_WM_SYNC_REQUEST_COUNTER,
+        _NET_WM_SYNC_REQUEST,
         _NET_WM_NAME,
         UTF8_STRING,
+        _NET_STARTUP_ID,
+        _NET_STARTUP_INFO_BEGIN,
+        _NET_STARTUP_INFO,
+        _XSETTINGS_SETTINGS,
+        MANAGER,
         _NET_WM_WINDOW_TYPE,
         _NET_WM_WINDOW_TYPE_NORMAL,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _NET_WM_WINDOW_TYPE_MENU,
+        _NET_WM_WINDOW_TYPE_SPLASH,
         _NET_WM_WINDOW_TYPE_DROPDOWN_MENU,
         _NET_WM_WINDOW_TYPE_TOOLTIP,
         _NET_WM_WINDOW_TYPE_DIALOG,
+        _NET_WM_WINDOW_TYPE_DESKTOP,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_BELOW,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_MODAL,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        _NET_WM_STATE_HIDDEN,
+        _NET_FRAME_EXTENTS,
+        WM_CHANGE_STATE,
+        _GTK_THEME_VARIANT,
+        _MOTIF_WM_HINTS,
         CLIPBOARD,
         PRIMARY,
         TARGETS,
         INCR,
+        TEXT,
         ABS_X: b"Abs X",
         ABS_Y: b"Abs Y",
         ABS_PRESSURE: b"Abs Pressure",
@@ -179,8 +263,6 @@ pub(crate) struct AppInner {
     /// The visual for windows with transparent backgrounds, if supported
     argb_visual_type: Option<Visualtype>,
 
-    /// The X11 resource database used to query dpi.
-    pub(crate) rdb: ResourceDb,
     pub(crate) cursors: Cursors,
     /// The clipboard implementation
     clipboard: Clipboard,
@@ -204,8 +286,59 @@ pub(crate) struct AppInner {
     idle_write: RawFd,
     /// Support for the render extension in at least version 0.5?
     render_argb32_pictformat_cursor: Option<Pictformat>,
+    /// Support for the present extension, which tells us when frames reach the screen
+    present_supported: bool,
+    /// Whether frames can be uploaded through shared memory, see `buffers`
+    shm_supported: bool,
+    /// Support for the sync extension, which holds back a window's first frame
+    sync_supported: bool,
+    /// The window which `WindowHandle::run_modal` is running a loop for. The other windows
+    /// ignore input until it ends.
+    modal_window: Cell<Option<u32>>,
     /// The attached input devices, with internal mutability because X events can make them change.
     pointers: RefCell<PointersState>,
+    /// The handler passed to `Application::run`, or set for running loop iterations.
+    handler: RefCell<Option<Box<dyn AppHandler>>>,
+    /// Callbacks sent from other threads with `AppHandle::run_on_main`.
+    main_thread_cb_queue: (SharedEnqueuer<MainThreadCb>, SharedDequeuer<MainThreadCb>),
+    /// File descriptors polled alongside the X11 connection.
+    event_sources: RefCell<Vec<EventSource>>,
+    /// How often the idle loop runs, once it has been worked out from the refresh rate.
+    idle_interval: Cell<Option<Duration>>,
+    /// When the idle loop last ran.
+    last_idle_time: Cell<Instant>,
+    /// Whether the DBus watchers have been started, by the first loop iteration.
+    watchers_started: Cell<bool>,
+    /// Whether the event loop has finished, after which the idle pipe is closed.
+    exited: Cell<bool>,
+    /// Set by `Application::new_with_name`, and used as the `WM_CLASS` of new windows.
+    app_id: RefCell<Option<String>>,
+    /// The startup notification ID we were launched with, until the first window is mapped.
+    startup_id: RefCell<Option<String>>,
+    /// The `_XSETTINGS_S<screen>` selection, which the XSETTINGS manager owns.
+    xsettings_selection: xproto::Atom,
+    /// The window of the current XSETTINGS manager.
+    xsettings_manager: Cell<Option<xproto::Window>>,
+    xsettings: Cell<Option<XSettings>>,
+    /// `Xft.dpi` from the X resource database.
+    resource_dpi: Cell<Option<f64>>,
+    /// The scale of every window, see `xsettings::scale` for where it comes from.
+    scale: Cell<Scale>,
+    /// Whether assistive technology is running, so that windows need AccessKit adapters.
+    #[cfg(feature = "accesskit")]
+    accessibility: linux::accesskit::AccessibilityStatus,
+    /// Whether `accessibility` was enabled when the idle loop last looked.
+    #[cfg(feature = "accesskit")]
+    accessibility_seen: Cell<bool>,
+}
+
+type MainThreadCb = Box<dyn FnOnce(Option<&mut dyn AppHandler>) + Send>;
+
+struct EventSource {
+    token: EventSourceToken,
+    fd: RawFd,
+    /// This is taken while the callback runs, so that it can add and remove event sources.
+    callback: Option<Box<dyn FnMut(Option<&mut dyn AppHandler>)>>,
 }
 
 /// The mutable `Application` state.
@@ -229,7 +362,10 @@ pub(crate) struct Cursors {
 }
 
 #[derive(Clone)]
-pub(crate) struct AppHandle;
+pub(crate) struct AppHandle {
+    enqueuer: SharedEnqueuer<MainThreadCb>,
+    idle_write: RawFd,
+}
 
 impl Application {
     pub fn new() -> Result<Application, Error> {
@@ -237,9 +373,96 @@ impl Application {
         Ok(Application { inner })
     }
 
-    pub fn run(self, _handler: Option<Box<dyn AppHandler>>) {
-        if let Err(e) = self.inner.run_inner() {
-            tracing::error!("{}", e);
+    pub fn set_handler(&self, handler: Option<Box<dyn AppHandler>>) {
+        *self.inner.handler.borrow_mut() = handler;
+    }
+
+    pub fn run(self, handler: Option<Box<dyn AppHandler>>) {
+        self.set_handler(handler);
+        while self.run_loop_iteration(LoopMode::Wait) == LoopStatus::Continue {}
+    }
+
+    pub fn run_loop_iteration(&self, mode: LoopMode) -> LoopStatus {
+        if self.inner.exited.get() {
+            return LoopStatus::Exit;
+        }
+        self.start_watchers();
+        let status = match self.inner.run_iteration(mode) {
+            Ok(false) => LoopStatus::Continue,
+            Ok(true) => LoopStatus::Exit,
+            Err(e) => {
+                self.inner.backend_error(e);
+                self.inner.exited.set(true);
+                LoopStatus::Exit
+            }
+        };
+        if status == LoopStatus::Exit {
+            self.inner.destroy_remaining_windows();
+            self.set_handler(None);
+        }
+        status
+    }
+
+    /// Start watching DBus for changes to the power state, the session and desktop settings,
+    /// and for assistive technology.
+    fn start_watchers(&self) {
+        if self.inner.watchers_started.replace(true) {
+            return;
+        }
+        #[cfg(feature = "accesskit")]
+        if let Some(handle) = self.get_handle() {
+            // The idle loop creates the adapters once this wakes it
+            self.inner.accessibility.watch(move || handle.wake());
+        }
+        if let Some(handle) = self.get_handle() {
+            linux::power::watch_power_state(move |power_state| {
+                let handle = handle.clone();
+                common_util::debounce_power_state(power_state, move |power_state| {
+                    handle.run_on_main(move |handler| {
+                        common_util::power_state_changed(handler, power_state)
+                    })
+                })
+            });
+        }
+        if let Some(handle) = self.get_handle() {
+            linux::session::watch_session(move |event| {
+                handle.run_on_main(move |handler| event.deliver(handler))
+            });
+        }
+        if let Some(handle) = self.get_handle() {
+            let fonts_handle = handle.clone();
+            let text_scale_handle = handle.clone();
+            let colors_handle = handle.clone();
+            linux::settings::watch_settings(
+                move |prefs| {
+                    handle.run_on_main(move |handler| {
+                        if let Some(handler) = handler {
+                            handler.accessibility_preferences_changed(prefs);
+                        }
+                    })
+                },
+                move |fonts| {
+                    fonts_handle.run_on_main(move |handler| {
+                        if let Some(handler) = handler {
+                            handler.system_fonts_changed(fonts);
+                        }
+                    })
+                },
+                move |factor| {
+                    text_scale_handle.run_on_main(move |handler| {
+                        if let Some(handler) = handler {
+                            handler.text_scale_factor_changed(factor);
+                        }
+                    })
+                },
+                move |color| {
+                    colors_handle.run_on_main(move |handler| {
+                        if let Some(handler) = handler {
+                            handler.accent_color_changed(color);
+                        }
+                    })
+                },
+            );
         }
     }
 
@@ -271,8 +494,39 @@ impl Application {
         linux::env::locale()
     }
 
+    pub fn get_locales() -> Vec<String> {
+        linux::env::locales()
+    }
+
+    pub fn ui_direction() -> UiDirection {
+        linux::env::ui_direction()
+    }
+
     pub fn get_handle(&self) -> Option<AppHandle> {
-        None
+        Some(AppHandle {
+            enqueuer: self.main_thread_cb_queue.0.clone(),
+            idle_write: self.idle_write,
+        })
+    }
+
+    pub fn add_event_source(
+        &self,
+        fd: RawFd,
+        callback: Box<dyn FnMut(Option<&mut dyn AppHandler>)>,
+    ) -> EventSourceToken {
+        let token = EventSourceToken::next();
+        self.event_sources.borrow_mut().push(EventSource {
+            token,
+            fd,
+            callback: Some(callback),
+        });
+        token
+    }
+
+    pub fn remove_event_source(&self, token: EventSourceToken) {
+        self.event_sources
+            .borrow_mut()
+            .retain(|source| source.token != token);
     }
 }
 
@@ -287,6 +541,9 @@ impl AppInner {
         //
         // https://github.com/linebender/druid/pull/1025#discussion_r442777892
         let (connection, screen_num) = XCBConnection::connect(None)?;
+        // The ID is only meant for us, so it mustn't be inherited by processes we launch.
+        let startup_id = std::env::var("DESKTOP_STARTUP_ID").ok();
+        std::env::remove_var("DESKTOP_STARTUP_ID");
         let rdb = new_resource_db_from_default(&connection)?;
         let xkb_context = xkb::Context::new();
         xkb_context.set_log_level(tracing::Level::DEBUG);
@@ -355,6 +612,20 @@ impl AppInner {
             None
         };
 
+        let present_supported = connection
+            .extension_information(present::X11_EXTENSION_NAME)?
+            .and_then(|_| connection.present_query_version(1, 0).ok())
+            .map(|cookie| cookie.reply())
+            .transpose()?
+            .is_some();
+        let shm_supported = buffers::shm_supported(&connection);
+        let sync_supported = connection
+            .extension_information(sync::X11_EXTENSION_NAME)?
+            .and_then(|_| connection.sync_initialize(3, 1).ok())
+            .map(|cookie| cookie.reply())
+            .transpose()?
+            .is_some();
+
         let handle = x11rb::cursor::Handle::new(&connection, screen_num, &rdb)?.reply()?;
         let load_cursor = |cursor| {
             handle
@@ -388,6 +659,29 @@ impl AppInner {
             .ok_or_else(|| anyhow!("Couldn't get visual from screen"))?;
         let argb_visual_type = util::get_argb_visual_type(&connection, screen)?;
 
+        // Settings managers announce themselves to the root window, where `xrdb` also keeps the
+        // resource database.
+        connection
+            .change_window_attributes(
+                screen.root,
+                &ChangeWindowAttributesAux::new()
+                    .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE),
+            )?
+            .check()
+            .context("select root window events")?;
+        if connection
+            .extension_information(randr::X11_EXTENSION_NAME)?
+            .is_some()
+        {
+            connection.randr_select_input(screen.root, randr::NotifyMask::SCREEN_CHANGE)?;
+        }
+        let xsettings_selection = connection
+            .intern_atom(false, format!("_XSETTINGS_S{screen_num}").as_bytes())?
+            .reply()
+            .context("intern XSETTINGS selection")?
+            .atom;
+        let resource_dpi = xsettings::resource_dpi(&connection);
+
         let timestamp = Cell::new(x11rb::CURRENT_TIME);
 
         let shared = Rc::new(AppShared {
@@ -399,12 +693,12 @@ impl AppInner {
             timestamp,
         });
 
-        let clipboard = Clipboard::new(Rc::clone(&shared), atoms.CLIPBOARD);
         let primary = Clipboard::new(Rc::clone(&shared), atoms.PRIMARY);
+        let clipboard =
+            Clipboard::new(Rc::clone(&shared), atoms.CLIPBOARD).with_primary(primary.clone());
 
-        Ok(Rc::new(AppInner {
+        let inner = Rc::new(AppInner {
             shared,
-            rdb,
             window_id,
             state,
             idle_read,
@@ -415,8 +709,138 @@ impl AppInner {
             root_visual_type,
             argb_visual_type,
             render_argb32_pictformat_cursor,
+            present_supported,
+            shm_supported,
+            sync_supported,
+            modal_window: Cell::new(None),
             pointers: RefCell::new(pointers),
-        }))
+            handler: RefCell::new(None),
+            main_thread_cb_queue: shared_queue(),
+            event_sources: RefCell::new(Vec::new()),
+            idle_interval: Cell::new(None),
+            last_idle_time: Cell::new(Instant::now()),
+            watchers_started: Cell::new(false),
+            exited: Cell::new(false),
+            app_id: RefCell::new(None),
+            startup_id: RefCell::new(startup_id),
+            xsettings_selection,
+            xsettings_manager: Cell::new(None),
+            xsettings: Cell::new(None),
+            resource_dpi: Cell::new(resource_dpi),
+            scale: Cell::new(Scale::default()),
+            #[cfg(feature = "accesskit")]
+            accessibility: Default::default(),
+            #[cfg(feature = "accesskit")]
+            accessibility_seen: Cell::new(false),
+        });
+        inner.update_xsettings_manager();
+        inner.update_scale();
+        Ok(inner)
+    }
+
+    /// Finds the XSETTINGS manager, starts watching it and reads its settings.
+    fn update_xsettings_manager(&self) {
+        let conn = self.connection();
+        let manager = match xsettings::manager_window(conn, self.xsettings_selection) {
+            Ok(manager) => manager,
+            Err(e) => {
+                tracing::warn!("failed to find the XSETTINGS manager: {e}");
+                None
+            }
+        };
+        if let Some(manager) = manager {
+            // We're told when the settings change, and when the manager exits.
+            log_x11!(conn.change_window_attributes(
+                manager,
+                &ChangeWindowAttributesAux::new()
+                    .event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY),
+            ));
+        }
+        self.xsettings_manager.set(manager);
+        self.read_xsettings();
+    }
+
+    fn read_xsettings(&self) {
+        let settings = self.xsettings_manager.get().and_then(|manager| {
+            xsettings::read(self.connection(), self.atoms(), manager)
+                .map_err(|e| tracing::warn!("failed to read XSETTINGS: {e}"))
+                .ok()
+        });
+        self.xsettings.set(settings);
+    }
+
+    /// Works out the scale again, and passes it on to every window if it changed.
+    fn update_scale(&self) {
+        let scale = xsettings::scale(self.xsettings.get().as_ref(), self.resource_dpi.get());
+        if self.scale.replace(scale) == scale {
+            return;
+        }
+        // Windows call their handlers, which may create or close windows.
+        let windows: Vec<_> = match borrow!(self.state) {
+            Ok(state) => state.windows.values().cloned().collect(),
+            Err(e) => {
+                tracing::error!("failed to update the scale: {e}");
+                return;
+            }
+        };
+        for window in windows {
+            window.set_scale(scale);
+        }
+    }
+
+    /// The scale of every window.
+    pub(crate) fn scale(&self) -> Scale {
+        self.scale.get()
+    }
+
+    pub(crate) fn root_window(&self) -> xproto::Window {
+        self.connection().setup().roots[self.screen_num()].root
+    }
+
+    pub(crate) fn set_app_id(&self, app_id: &str) {
+        *self.app_id.borrow_mut() = Some(app_id.to_owned());
+    }
+
+    pub(crate) fn app_id(&self) -> Option<String> {
+        self.app_id.borrow().clone()
+    }
+
+    /// The startup notification ID, if we were launched with one and haven't shown a window yet.
+    pub(crate) fn startup_id(&self) -> Option<String> {
+        self.startup_id.borrow().clone()
+    }
+
+    /// Tells the launcher that startup has finished, which stops the busy cursor.
+    ///
+    /// This is called whenever a window is mapped, and only sends the message the first time.
+    pub(crate) fn complete_startup(&self) {
+        let Some(id) = self.startup_id.borrow_mut().take() else {
+            return;
+        };
+        let id = id.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut message = format!("remove: ID=\"{id}\"").into_bytes();
+        message.push(0);
+
+        let conn = self.connection();
+        let atoms = self.atoms();
+        let root = self.root_window();
+        for (i, chunk) in message.chunks(20).enumerate() {
+            let mut data = [0; 20];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let message_type = if i == 0 {
+                atoms._NET_STARTUP_INFO_BEGIN
+            } else {
+                atoms._NET_STARTUP_INFO
+            };
+            let event = xproto::ClientMessageEvent::new(8, self.window_id, message_type, data);
+            if let Err(e) = conn.send_event(false, root, EventMask::PROPERTY_CHANGE, event) {
+                tracing::warn!("failed to complete startup notification: {e}");
+                return;
+            }
+        }
+        if let Err(e) = conn.flush() {
+            tracing::warn!("failed to complete startup notification: {e}");
+        }
     }
 
     /// Return the ARGB32 pictformat of the server, but only if RENDER's CreateCursor is supported
@@ -425,6 +849,40 @@ impl AppInner {
         self.render_argb32_pictformat_cursor
     }
 
+    /// Whether the server supports the present extension
+    #[inline]
+    pub(crate) fn present_supported(&self) -> bool {
+        self.present_supported
+    }
+
+    /// Whether the server can read frames from shared memory
+    #[inline]
+    pub(crate) fn shm_supported(&self) -> bool {
+        self.shm_supported
+    }
+
+    /// Whether assistive technology is running, as AT-SPI last told us.
+    #[cfg(feature = "accesskit")]
+    pub(crate) fn accessibility_enabled(&self) -> bool {
+        self.accessibility.enabled()
+    }
+
+    /// Whether the server supports the sync extension
+    #[inline]
+    pub(crate) fn sync_supported(&self) -> bool {
+        self.sync_supported
+    }
+
+    #[inline]
+    pub(crate) fn modal_window(&self) -> Option<u32> {
+        self.modal_window.get()
+    }
+
+    /// Returns the window which was modal before.
+    pub(crate) fn set_modal_window(&self, window: Option<u32>) -> Option<u32> {
+        self.modal_window.replace(window)
+    }
+
     fn create_event_window(conn: &XCBConnection, screen_num: usize) -> Result<u32, Error> {
         let id = conn.generate_id()?;
         let setup = conn.setup();
@@ -476,6 +934,15 @@ impl AppInner {
         Ok(state.windows.len())
     }
 
+    /// All of the application's windows, in the order they were created.
+    pub(crate) fn windows(&self) -> Result<Vec<Rc<Window>>, Error> {
+        let state = borrow!(self.state)?;
+        let mut windows: Vec<_> = state.windows.iter().collect();
+        // We allocate the ids in increasing order.
+        windows.sort_by_key(|(id, _)| **id);
+        Ok(windows.into_iter().map(|(_, w)| w.clone()).collect())
+    }
+
     fn window(&self, id: u32) -> Result<Rc<Window>, Error> {
         borrow!(self.state)?
             .windows
@@ -521,6 +988,13 @@ impl AppInner {
         self.pointers.borrow().device_info(id).cloned()
     }
 
+    /// The smooth scrolling in a motion event, in steps of a mouse wheel.
+    pub(crate) fn scroll_delta(&self, ev: &xinput::MotionEvent) -> Vec2 {
+        self.pointers
+            .borrow_mut()
+            .scroll_delta(ev.sourceid, &ev.valuator_mask, &ev.axisvalues)
+    }
+
     fn reinitialize_pointers(&self) {
         match super::pointer::initialize_pointers(
             &self.shared.connection,
@@ -548,6 +1022,7 @@ impl AppInner {
 
     /// Returns `Ok(true)` if we want to exit the main loop.
     fn handle_event(&self, ev: &Event) -> Result<bool, Error> {
+        let _span = common_util::event_span(event_name(ev));
         if ev.server_generated() {
             // Update our latest timestamp
             let timestamp = match ev {
@@ -621,16 +1096,25 @@ impl AppInner {
             Event::XinputDeviceChanged(ev) if ev.reason == ChangeReason::DEVICE_CHANGE => {
                 self.reinitialize_pointers();
             }
+            Event::XinputDeviceChanged(ev) if ev.reason == ChangeReason::SLAVE_SWITCH => {
+                // The master device's scroll valuators now follow a different device
+                self.pointers.borrow_mut().reset_scroll();
+            }
             Event::XinputButtonPress(ev) => {
                 let w = self
                     .window(ev.event)
                     .context("BUTTON_PRESS - failed to get window")?;
 
                 // X doesn't have dedicated scroll events: it uses mouse buttons instead.
-                // Buttons 4/5 are vertical; 6/7 are horizontal.
+                // Buttons 4/5 are vertical; 6/7 are horizontal. For devices with scroll
+                // valuators, the server also sends these for every step the valuators move,
+                // and we use the valuators instead.
                 if ev.detail >= 4 && ev.detail <= 7 {
-                    w.handle_wheel(ev)
-                        .context("BUTTON_PRESS - failed to handle wheel")?;
+                    let emulated = PointerEventFlags::POINTER_EMULATED;
+                    if (ev.flags | emulated) != ev.flags {
+                        w.handle_wheel(ev)
+                            .context("BUTTON_PRESS - failed to handle wheel")?;
+                    }
                 } else {
                     w.handle_button_press(ev)?;
                 }
@@ -650,7 +1134,12 @@ impl AppInner {
                 let w = self
                     .window(ev.event)
                     .context("MOTION_NOTIFY - failed to get window")?;
-                w.handle_motion_notify(ev)?;
+                if w.coalesces_pointer_events() {
+                    let (ev, skipped) = self.compress_motion(ev.clone())?;
+                    w.handle_motion_notify(&ev, &skipped)?;
+                } else {
+                    w.handle_motion_notify(ev, &[])?;
+                }
             }
             Event::XinputTouchBegin(ev) => {
                 let w = self
@@ -674,13 +1163,25 @@ impl AppInner {
                 let w = self
                     .window(ev.event)
                     .context("LEAVE_NOTIFY - failed to get window")?;
+                // Scrolling elsewhere moves the scroll valuators without us being told
+                self.pointers.borrow_mut().reset_scroll();
                 w.handle_leave_notify(ev)?;
             }
             Event::ClientMessage(ev) => {
-                let w = self
-                    .window(ev.window)
-                    .context("CLIENT_MESSAGE - failed to get window")?;
-                w.handle_client_message(ev);
+                if ev.window == self.root_window() {
+                    // Other messages to the root window are for the window manager.
+                    if ev.type_ == self.atoms().MANAGER
+                        && ev.data.as_data32()[1] == self.xsettings_selection
+                    {
+                        self.update_xsettings_manager();
+                        self.update_scale();
+                    }
+                } else {
+                    let w = self
+                        .window(ev.window)
+                        .context("CLIENT_MESSAGE - failed to get window")?;
+                    w.handle_client_message(ev);
+                }
             }
             Event::DestroyNotify(ev) => {
                 if ev.window == self.window_id {
@@ -688,6 +1189,12 @@ impl AppInner {
                     // we need to quit the run loop.
                     return Ok(true);
                 }
+                if Some(ev.window) == self.xsettings_manager.get() {
+                    // Another manager may already have taken over.
+                    self.update_xsettings_manager();
+                    self.update_scale();
+                    return Ok(false);
+                }
 
                 let w = self
                     .window(ev.window)
@@ -704,7 +1211,7 @@ impl AppInner {
                 }
             }
             Event::ConfigureNotify(ev) => {
-                if ev.window != self.window_id {
+                if ev.window != self.window_id && ev.window != self.root_window() {
                     let w = self
                         .window(ev.window)
                         .context("CONFIGURE_NOTIFY - failed to get window")?;
@@ -712,6 +1219,35 @@ impl AppInner {
                         .context("CONFIGURE_NOTIFY - failed to handle")?;
                 }
             }
+            Event::VisibilityNotify(ev) => {
+                let w = self
+                    .window(ev.window)
+                    .context("VISIBILITY_NOTIFY - failed to get window")?;
+                w.handle_visibility_notify(ev);
+            }
+            Event::MapNotify(ev) => {
+                if ev.window != self.window_id {
+                    let w = self
+                        .window(ev.window)
+                        .context("MAP_NOTIFY - failed to get window")?;
+                    w.handle_map_notify(ev);
+                }
+            }
+            Event::ButtonPress(ev) => {
+                // We use XInput for pointer events, so these only come from popup grabs
+                let w = self
+                    .window(ev.event)
+                    .context("BUTTON_PRESS - failed to get window")?;
+                w.handle_grabbed_button_press(ev);
+            }
+            Event::UnmapNotify(ev) => {
+                if ev.window != self.window_id {
+                    let w = self
+                        .window(ev.window)
+                        .context("UNMAP_NOTIFY - failed to get window")?;
+                    w.handle_unmap_notify(ev);
+                }
+            }
             Event::SelectionClear(ev) => {
                 self.clipboard
                     .handle_clear(*ev)
@@ -729,6 +1265,23 @@ impl AppInner {
                     .context("SELECTION_REQUEST event handling for primary")?;
             }
             Event::PropertyNotify(ev) => {
+                if Some(ev.window) == self.xsettings_manager.get()
+                    && ev.atom == self.atoms()._XSETTINGS_SETTINGS
+                {
+                    self.read_xsettings();
+                    self.update_scale();
+                } else if ev.window == self.root_window()
+                    && ev.atom == u32::from(AtomEnum::RESOURCE_MANAGER)
+                {
+                    self.resource_dpi
+                        .set(xsettings::resource_dpi(self.connection()));
+                    self.update_scale();
+                } else if ev.atom == self.atoms()._NET_WM_STATE {
+                    if let Ok(w) = self.window(ev.window) {
+                        w.handle_net_wm_state_changed()
+                            .context("PROPERTY_NOTIFY - failed to read _NET_WM_STATE")?;
+                    }
+                }
                 self.clipboard
                     .handle_property_notify(*ev)
                     .context("PROPERTY_NOTIFY event handling for clipboard")?;
@@ -748,11 +1301,30 @@ impl AppInner {
                     .context("FOCUS_OUT - failed to get window")?;
                 w.handle_lost_focus();
             }
+            Event::PresentCompleteNotify(ev) => {
+                let w = self
+                    .window(ev.window)
+                    .context("PRESENT_COMPLETE_NOTIFY - failed to get window")?;
+                w.handle_present_complete_notify(ev);
+            }
+            Event::PresentIdleNotify(ev) => {
+                let w = self
+                    .window(ev.window)
+                    .context("PRESENT_IDLE_NOTIFY - failed to get window")?;
+                w.handle_present_idle_notify(ev);
+            }
+            Event::RandrScreenChangeNotify(_) => {
+                // Desktops often change the scale along with the monitors, so read it again.
+                self.read_xsettings();
+                self.resource_dpi
+                    .set(xsettings::resource_dpi(self.connection()));
+                self.update_scale();
+            }
             Event::Error(e) => {
                 // TODO: if an error is caused by the present extension, disable it and fall back
                 // to copying pixels. This was blocked on
                 // https://github.com/psychon/x11rb/issues/503 but no longer is
-                return Err(x11rb::errors::ReplyError::from(e.clone()).into());
+                self.backend_error(x11rb::errors::ReplyError::from(e.clone()).into());
             }
             ev => {
                 tracing::debug!("unhandled event {ev:?}");
@@ -761,7 +1333,47 @@ impl AppInner {
         Ok(false)
     }
 
-    fn run_inner(&self) -> Result<(), Error> {
+    /// The next event to handle, from the ones we have put aside or from the connection.
+    fn next_event(&self) -> Result<Option<Event>, Error> {
+        let pending = self.shared.pending_events.borrow_mut().pop_front();
+        match pending {
+            Some(event) => Ok(Some(event)),
+            None => Ok(self.shared.connection.poll_for_event()?),
+        }
+    }
+
+    /// Take the motion events which directly follow `ev`, for the same window and device, and
+    /// return the latest of them along with the ones it replaces, oldest first.
+    ///
+    /// Only the events which have already arrived are taken, so this never waits. The first
+    /// event of any other kind is put aside, to be handled next.
+    fn compress_motion(
+        &self,
+        mut ev: xinput::MotionEvent,
+    ) -> Result<(xinput::MotionEvent, Vec<xinput::MotionEvent>), Error> {
+        let mut skipped = Vec::new();
+        loop {
+            match self.next_event()? {
+                Some(Event::XinputMotion(next))
+                    if next.event == ev.event && next.deviceid == ev.deviceid =>
+                {
+                    skipped.push(std::mem::replace(&mut ev, next));
+                }
+                Some(other) => {
+                    self.shared.pending_events.borrow_mut().push_front(other);
+                    break;
+                }
+                None => break,
+            }
+        }
+        Ok((ev, skipped))
+    }
+
+    /// How long to wait between runs of the idle loop.
+    fn idle_interval(&self) -> Duration {
+        if let Some(interval) = self.idle_interval.get() {
+            return interval;
+        }
         // Try to figure out the refresh rate of the current screen. We run the idle loop at that
         // rate. The rate-limiting of the idle loop has two purposes:
         //  - When the present extension is disabled, we paint in the idle loop. By limiting the
@@ -770,87 +1382,242 @@ impl AppInner {
         //    connection is otherwise idle, we'll wake up at most once per frame, run *all* the
         //    pending idle commands, and then go back to sleep.
         let refresh_rate = util::refresh_rate(self.connection(), self.window_id).unwrap_or(60.0);
-        let timeout = Duration::from_millis((1000.0 / refresh_rate) as u64);
-        let mut last_idle_time = Instant::now();
-        loop {
-            // Figure out when the next wakeup needs to happen
-            let next_timeout = if let Ok(state) = self.state.try_borrow() {
-                state
-                    .windows
-                    .values()
-                    .filter_map(|w| w.next_timeout())
-                    .min()
-            } else {
-                tracing::error!("Getting next timeout, application state already borrowed");
-                None
-            };
-            let next_idle_time = last_idle_time + timeout;
-
-            self.shared.connection.flush()?;
+        let interval = Duration::from_millis((1000.0 / refresh_rate) as u64);
+        self.idle_interval.set(Some(interval));
+        interval
+    }
 
-            // Deal with pending events
-            let mut event = self.shared.pending_events.borrow_mut().pop_front();
+    /// Wait for events as `mode` says, and handle them along with any timers and idle callbacks
+    /// which are due.
+    ///
+    /// Returns `true` once the application has quit.
+    fn run_iteration(&self, mode: LoopMode) -> Result<bool, Error> {
+        // Figure out when the next wakeup needs to happen
+        let next_timeout = if let Ok(state) = self.state.try_borrow() {
+            state
+                .windows
+                .values()
+                .filter_map(|w| w.next_timeout())
+                .min()
+        } else {
+            tracing::error!("Getting next timeout, application state already borrowed");
+            None
+        };
+        let next_idle_time = self.last_idle_time.get() + self.idle_interval();
 
-            // Before we poll on the connection's file descriptor, check whether there are any
-            // events ready. It could be that XCB has some events in its internal buffers because
-            // of something that happened during the idle loop.
-            if event.is_none() {
-                event = self.shared.connection.poll_for_event()?;
-            }
+        self.shared.connection.flush()?;
 
-            if event.is_none() {
-                poll_with_timeout(
-                    &self.shared.connection,
-                    self.idle_read,
-                    next_timeout,
-                    next_idle_time,
-                )
-                .context("Error while waiting for X11 connection")?;
-            }
+        // Deal with pending events. Before we poll on the connection's file descriptor, check
+        // whether there are any events ready. It could be that XCB has some events in its
+        // internal buffers because of something that happened during the idle loop.
+        let mut event = self.next_event()?;
 
-            while let Some(ev) = event {
-                match self.handle_event(&ev) {
-                    Ok(quit) => {
-                        if quit {
-                            return Ok(());
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Error handling event: {:#}", e);
-                    }
+        if event.is_none() && mode != LoopMode::Poll {
+            self.with_handler(|handler| {
+                if let Some(handler) = handler {
+                    handler.idle();
                 }
-                event = self.shared.connection.poll_for_event()?;
-            }
+            });
+            let sources = self.event_source_fds();
+            let deadline = match mode {
+                LoopMode::WaitTimeout(timeout) => Instant::now().checked_add(timeout),
+                _ => None,
+            };
+            let wake_time = match (next_timeout, deadline) {
+                (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+                (timeout, deadline) => timeout.or(deadline),
+            };
+            poll_with_timeout(
+                &self.shared.connection,
+                self.idle_read,
+                &sources,
+                wake_time,
+                next_idle_time,
+            )
+            .context("Error while waiting for X11 connection")?;
+            self.with_handler(|handler| {
+                if let Some(handler) = handler {
+                    handler.idle_ended();
+                }
+            });
+            // Pick up anything which arrived while waiting, so this iteration handles it.
+            event = self.shared.connection.poll_for_event()?;
+        }
 
-            let now = Instant::now();
-            if let Some(timeout) = next_timeout {
-                if timeout <= now {
-                    if let Ok(state) = self.state.try_borrow() {
-                        let values = state.windows.values().cloned().collect::<Vec<_>>();
-                        drop(state);
-                        for w in values {
-                            w.run_timers(now);
-                        }
-                    } else {
-                        tracing::error!("In timer loop, application state already borrowed");
+        while let Some(ev) = event {
+            match self.handle_event(&ev) {
+                Ok(quit) => {
+                    if quit {
+                        self.exited.set(true);
+                        return Ok(true);
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Error handling event: {:#}", e);
+                }
             }
-            if now >= next_idle_time {
-                last_idle_time = now;
-                drain_idle_pipe(self.idle_read)?;
+            // This includes any events which were put aside while handling this one.
+            event = self.next_event()?;
+        }
 
+        self.dispatch_event_sources()?;
+
+        let now = Instant::now();
+        if let Some(timeout) = next_timeout {
+            if timeout <= now {
                 if let Ok(state) = self.state.try_borrow() {
-                    for w in state.windows.values() {
-                        w.run_idle();
+                    let values = state.windows.values().cloned().collect::<Vec<_>>();
+                    drop(state);
+                    for w in values {
+                        w.run_timers(now);
                     }
                 } else {
-                    tracing::error!("In idle loop, application state already borrowed");
+                    tracing::error!("In timer loop, application state already borrowed");
+                }
+            }
+        }
+        if now >= next_idle_time {
+            self.last_idle_time.set(now);
+            drain_idle_pipe(self.idle_read)?;
+
+            while let Some(cb) = self.main_thread_cb_queue.1.try_dequeue() {
+                self.with_handler(cb);
+            }
+
+            #[cfg(feature = "accesskit")]
+            self.accessibility_changed();
+
+            if let Ok(state) = self.state.try_borrow() {
+                for w in state.windows.values() {
+                    w.run_idle();
                 }
+            } else {
+                tracing::error!("In idle loop, application state already borrowed");
+            }
+        }
+        Ok(false)
+    }
+
+    /// Give every window an AccessKit adapter once assistive technology starts running, rather
+    /// than waiting for one to be focused.
+    #[cfg(feature = "accesskit")]
+    fn accessibility_changed(&self) {
+        let enabled = self.accessibility.enabled();
+        if self.accessibility_seen.replace(enabled) || !enabled {
+            return;
+        }
+        let windows = match self.state.try_borrow() {
+            Ok(state) => state.windows.values().cloned().collect::<Vec<_>>(),
+            Err(_) => {
+                tracing::error!("Creating AccessKit adapters, application state already borrowed");
+                return;
+            }
+        };
+        for window in windows {
+            window.init_accesskit();
+        }
+    }
+
+    /// Log an error which happened outside of any call, and pass it to the `AppHandler`.
+    fn backend_error(&self, error: Error) {
+        tracing::error!("{:#}", error);
+        self.with_handler(|handler| {
+            if let Some(handler) = handler {
+                handler.backend_error(error.into());
+            }
+        });
+    }
+
+    /// Call `f` with the `AppHandler`, unless it is already in use.
+    fn with_handler<T>(&self, f: impl FnOnce(Option<&mut dyn AppHandler>) -> T) -> T {
+        match self.handler.try_borrow_mut() {
+            Ok(mut handler) => f(match handler.as_mut() {
+                Some(handler) => Some(handler.as_mut()),
+                None => None,
+            }),
+            Err(_) => {
+                tracing::error!("AppHandler is already borrowed");
+                f(None)
+            }
+        }
+    }
+
+    fn event_source_fds(&self) -> Vec<RawFd> {
+        match self.event_sources.try_borrow() {
+            Ok(sources) => sources.iter().map(|source| source.fd).collect(),
+            Err(_) => {
+                tracing::error!("Getting event sources, they are already borrowed");
+                Vec::new()
             }
         }
     }
 
+    /// Run the callbacks of the event sources which are ready to read.
+    fn dispatch_event_sources(&self) -> Result<(), Error> {
+        use nix::poll::{poll, PollFd, PollFlags};
+
+        let tokens_and_fds = match self.event_sources.try_borrow() {
+            Ok(sources) => sources
+                .iter()
+                .map(|source| (source.token, source.fd))
+                .collect::<Vec<_>>(),
+            Err(_) => {
+                tracing::error!("Dispatching event sources, they are already borrowed");
+                return Ok(());
+            }
+        };
+        if tokens_and_fds.is_empty() {
+            return Ok(());
+        }
+        let mut poll_fds = tokens_and_fds
+            .iter()
+            .map(|(_, fd)| PollFd::new(*fd, PollFlags::POLLIN))
+            .collect::<Vec<_>>();
+        match poll(&mut poll_fds, 0) {
+            Ok(_) => {}
+            Err(nix::errno::Errno::EINTR) => return Ok(()),
+            Err(e) => return Err(e).context("Failed to poll event sources"),
+        }
+        for ((token, _), poll_fd) in tokens_and_fds.iter().zip(poll_fds) {
+            if poll_fd.revents().map_or(true, |revents| revents.is_empty()) {
+                continue;
+            }
+            // An earlier callback may have removed this source.
+            let callback = borrow_mut!(self.event_sources)?
+                .iter_mut()
+                .find(|source| source.token == *token)
+                .and_then(|source| source.callback.take());
+            let Some(mut callback) = callback else {
+                continue;
+            };
+            self.with_handler(|handler| callback(handler));
+            if let Some(source) = borrow_mut!(self.event_sources)?
+                .iter_mut()
+                .find(|source| source.token == *token)
+            {
+                source.callback = Some(callback);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tell the handlers of the windows which are still open that they are gone, once the
+    /// event loop has stopped early, such as because the connection to the X server was lost.
+    fn destroy_remaining_windows(&self) {
+        let windows = match self.state.try_borrow_mut() {
+            Ok(mut state) => std::mem::take(&mut state.windows),
+            Err(_) => {
+                tracing::error!("Application state already borrowed");
+                return;
+            }
+        };
+        let mut windows: Vec<_> = windows.into_iter().collect();
+        windows.sort_by_key(|(id, _)| *id);
+        for (_, window) in windows {
+            window.connection_lost();
+        }
+    }
+
     fn finalize_quit(&self) {
         log_x11!(self.shared.connection.destroy_window(self.window_id));
         if let Err(e) = nix::unistd::close(self.idle_read) {
@@ -867,16 +1634,70 @@ impl AppInner {
 }
 
 impl AppHandle {
-    pub fn run_on_main<F>(&self, _callback: F)
+    pub fn run_on_main<F>(&self, callback: F)
     where
         F: FnOnce(Option<&mut dyn AppHandler>) + Send + 'static,
     {
-        todo!()
+        if self.enqueuer.enqueue(Box::new(callback)) {
+            // The main loop runs the queue along with idle callbacks
+            self.wake();
+        }
+    }
+
+    /// Wake the main loop with the idle pipe, so that it runs the idle loop.
+    fn wake(&self) {
+        loop {
+            match nix::unistd::write(self.idle_write, &[0]) {
+                Err(nix::errno::Errno::EINTR) => {}
+                Err(nix::errno::Errno::EAGAIN) => break,
+                Err(e) => {
+                    tracing::error!("Failed to write to idle pipe: {}", e);
+                    break;
+                }
+                Ok(_) => break,
+            }
+        }
     }
 }
 
 /// Clears out our idle pipe; `idle_read` should be the reading end of a pipe that was opened with
 /// O_NONBLOCK.
+/// The name of an event, for [`common_util::event_span`].
+fn event_name(ev: &Event) -> &'static str {
+    match ev {
+        Event::Expose(_) => "Expose",
+        Event::KeyPress(_) => "KeyPress",
+        Event::KeyRelease(_) => "KeyRelease",
+        Event::XkbStateNotify(_) => "XkbStateNotify",
+        Event::XinputHierarchy(_) => "XinputHierarchy",
+        Event::XinputDeviceChanged(_) => "XinputDeviceChanged",
+        Event::XinputButtonPress(_) => "XinputButtonPress",
+        Event::XinputButtonRelease(_) => "XinputButtonRelease",
+        Event::XinputMotion(_) => "XinputMotion",
+        Event::XinputTouchBegin(_) => "XinputTouchBegin",
+        Event::XinputTouchUpdate(_) => "XinputTouchUpdate",
+        Event::XinputTouchEnd(_) => "XinputTouchEnd",
+        Event::LeaveNotify(_) => "LeaveNotify",
+        Event::ButtonPress(_) => "ButtonPress",
+        Event::ClientMessage(_) => "ClientMessage",
+        Event::ConfigureNotify(_) => "ConfigureNotify",
+        Event::MapNotify(_) => "MapNotify",
+        Event::UnmapNotify(_) => "UnmapNotify",
+        Event::VisibilityNotify(_) => "VisibilityNotify",
+        Event::DestroyNotify(_) => "DestroyNotify",
+        Event::FocusIn(_) => "FocusIn",
+        Event::FocusOut(_) => "FocusOut",
+        Event::PropertyNotify(_) => "PropertyNotify",
+        Event::SelectionClear(_) => "SelectionClear",
+        Event::SelectionRequest(_) => "SelectionRequest",
+        Event::PresentCompleteNotify(_) => "PresentCompleteNotify",
+        Event::PresentIdleNotify(_) => "PresentIdleNotify",
+        Event::RandrScreenChangeNotify(_) => "RandrScreenChangeNotify",
+        Event::Error(_) => "Error",
+        _ => "event",
+    }
+}
+
 fn drain_idle_pipe(idle_read: RawFd) -> Result<(), Error> {
     // Each write to the idle pipe adds one byte; it's unlikely that there will be much in it, but
     // read it 16 bytes at a time just in case.
@@ -904,13 +1725,14 @@ fn drain_idle_pipe(idle_read: RawFd) -> Result<(), Error> {
     Ok(())
 }
 
-/// Returns when there is an event ready to read from `conn`, or we got signalled by another thread
-/// writing into our idle pipe and the `timeout` has passed.
+/// Returns when there is an event ready to read from `conn` or one of the event `sources`, or we
+/// got signalled by another thread writing into our idle pipe and the `timeout` has passed.
 // This was taken, with minor modifications, from the xclock_utc example in the x11rb crate.
 // https://github.com/psychon/x11rb/blob/a6bd1453fd8e931394b9b1f2185fad48b7cca5fe/examples/xclock_utc.rs
 fn poll_with_timeout(
     conn: &XCBConnection,
     idle: RawFd,
+    sources: &[RawFd],
     timer_timeout: Option<Instant>,
     idle_timeout: Instant,
 ) -> Result<(), Error> {
@@ -921,12 +1743,12 @@ fn poll_with_timeout(
     let mut now = Instant::now();
     let earliest_timeout = idle_timeout.min(timer_timeout.unwrap_or(idle_timeout));
     let fd = conn.as_raw_fd();
-    let mut both_poll_fds = [
-        PollFd::new(fd, PollFlags::POLLIN),
-        PollFd::new(idle, PollFlags::POLLIN),
-    ];
-    let mut just_connection = [PollFd::new(fd, PollFlags::POLLIN)];
-    let mut poll_fds = &mut both_poll_fds[..];
+    // The connection, then the idle pipe, then the event sources
+    let mut poll_fds = [fd, idle]
+        .iter()
+        .chain(sources)
+        .map(|fd| PollFd::new(*fd, PollFlags::POLLIN))
+        .collect::<Vec<_>>();
 
     // We start with no timeout in the poll call. If we get something from the idle handler, we'll
     // start setting one.
@@ -960,20 +1782,27 @@ fn poll_with_timeout(
             -1
         };
 
-        match poll(poll_fds, poll_timeout) {
+        match poll(&mut poll_fds, poll_timeout) {
             Ok(_) => {
                 if readable(poll_fds[0]) {
                     // There is an X11 event ready to be handled.
                     break;
                 }
+                if poll_fds[2..]
+                    .iter()
+                    .any(|p| p.revents().map_or(false, |revents| !revents.is_empty()))
+                {
+                    // An event source is ready, or has hung up.
+                    break;
+                }
                 now = Instant::now();
                 if timer_timeout.is_some() && now >= timer_timeout.unwrap() {
                     break;
                 }
-                if poll_fds.len() == 1 || readable(poll_fds[1]) {
+                if honor_idle_timeout || readable(poll_fds[1]) {
                     // Now that we got signalled, stop polling from the idle pipe and use a timeout
-                    // instead.
-                    poll_fds = &mut just_connection;
+                    // instead. poll() ignores negative file descriptors.
+                    poll_fds[1] = PollFd::new(-1, PollFlags::empty());
                     honor_idle_timeout = true;
                     if now >= idle_timeout {
                         break;
@@ -0,0 +1,355 @@
+//! The pixmaps which `WindowHandle::present_pixels` uploads frames to, before the present
+//! extension shows them.
+//!
+//! Sending a large frame through the X11 socket is slow, and keeps the X server busy. When the
+//! server runs on the same machine, each pixmap has a shared memory segment (MIT-SHM) which the
+//! frame is written to, so the server only needs to copy it from there.
+//!
+//! There are several buffers, so that the next frame can be uploaded while the last one is still
+//! waiting to be shown. A buffer is only written to once the server has said that it is done
+//! with it, so if the server falls behind, the newest frame waits for a buffer instead.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::num::NonZeroUsize;
+use std::os::unix::io::AsRawFd;
+use std::ptr::NonNull;
+
+use anyhow::{Context, Error};
+use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use nix::sys::socket::{getsockname, AddressFamily, SockaddrLike, SockaddrStorage};
+use x11rb::connection::Connection;
+use x11rb::protocol::present::{self, ConnectionExt as _};
+use x11rb::protocol::shm::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{self, ConnectionExt as _, CreateGCAux, ImageFormat};
+use x11rb::utils::RawFdContainer;
+use x11rb::xcb_ffi::XCBConnection;
+
+use crate::PixelFormat;
+
+/// The number of buffers after which we stop allocating, and frames wait for the server to be
+/// done with one.
+const MAX_BUFFERS: usize = 3;
+
+/// Whether MIT-SHM can be used: the server needs to support passing the segments as file
+/// descriptors (version 1.2), which only works if we're connected with a unix socket.
+pub(crate) fn shm_supported(conn: &XCBConnection) -> bool {
+    let version = match conn.extension_information(shm::X11_EXTENSION_NAME) {
+        Ok(Some(_)) => conn.shm_query_version().ok().and_then(|c| c.reply().ok()),
+        _ => None,
+    };
+    let Some(version) = version else {
+        return false;
+    };
+    if (version.major_version, version.minor_version) < (1, 2) {
+        return false;
+    }
+    // A remote server can't map our memory.
+    getsockname::<SockaddrStorage>(conn.as_raw_fd())
+        .map_or(false, |addr| addr.family() == Some(AddressFamily::Unix))
+}
+
+/// Upload `pixels`, which are `width` pixels wide in the server's format, to `drawable`.
+pub(crate) fn put_image(
+    conn: &XCBConnection,
+    drawable: xproto::Drawable,
+    depth: u8,
+    width: u32,
+    pixels: &[u8],
+) -> Result<(), Error> {
+    let gc = conn.generate_id()?;
+    conn.create_gc(gc, drawable, &CreateGCAux::new())?;
+    // Large images don't fit in a single request, so send them in bands of rows.
+    let stride = width as usize * PixelFormat::BYTES_PER_PIXEL;
+    // The PutImage request header is 24 bytes.
+    let rows_per_band = ((conn.maximum_request_bytes() - 24) / stride).max(1);
+    for (i, band) in pixels.chunks(rows_per_band * stride).enumerate() {
+        conn.put_image(
+            ImageFormat::Z_PIXMAP,
+            drawable,
+            gc,
+            width as u16,
+            (band.len() / stride) as u16,
+            0,
+            (i * rows_per_band) as i16,
+            0,
+            depth,
+            band,
+        )?;
+    }
+    conn.free_gc(gc)?;
+    Ok(())
+}
+
+/// The buffers of one window.
+pub(crate) struct BufferPool {
+    window: xproto::Window,
+    depth: u8,
+    /// The size of every buffer, in pixels.
+    size: Cell<(u16, u16)>,
+    buffers: RefCell<Vec<Buffer>>,
+    /// Cleared if a shared memory segment can't be created, after which frames are sent
+    /// through the socket.
+    shm: Cell<bool>,
+    /// The serial of the last frame we presented.
+    serial: Cell<u32>,
+    /// The newest frame, if every buffer was busy when it arrived.
+    pending: RefCell<Option<Frame>>,
+}
+
+struct Buffer {
+    pixmap: xproto::Pixmap,
+    shm: Option<ShmSegment>,
+    /// Whether the server is done with the pixmap, so it can be painted again.
+    idle: bool,
+}
+
+/// A frame of pixels in the server's format.
+struct Frame {
+    width: u16,
+    height: u16,
+    pixels: Vec<u8>,
+    vsync: bool,
+}
+
+/// Memory which we share with the X server.
+struct ShmSegment {
+    seg: shm::Seg,
+    ptr: NonNull<u8>,
+    len: NonZeroUsize,
+}
+
+impl BufferPool {
+    pub fn new(window: xproto::Window, depth: u8, shm: bool) -> BufferPool {
+        BufferPool {
+            window,
+            depth,
+            size: Cell::new((0, 0)),
+            buffers: RefCell::new(Vec::new()),
+            shm: Cell::new(shm),
+            serial: Cell::new(0),
+            pending: RefCell::new(None),
+        }
+    }
+
+    /// Upload a frame of `width` by `height` pixels, in the server's format, and present it.
+    ///
+    /// With `vsync`, the frame is shown at the next vblank, instead of as soon as possible.
+    ///
+    /// If the server is still using every buffer, the frame waits until it is done with one,
+    /// unless a newer frame replaces it first.
+    pub fn present(
+        &self,
+        conn: &XCBConnection,
+        width: u16,
+        height: u16,
+        pixels: Vec<u8>,
+        vsync: bool,
+    ) -> Result<(), Error> {
+        if self.size.replace((width, height)) != (width, height) {
+            // The server keeps the old pixmaps alive until it is done with them.
+            self.free(conn);
+        }
+        let frame = Frame {
+            width,
+            height,
+            pixels,
+            vsync,
+        };
+        let mut buffers = self.buffers.borrow_mut();
+        let index = match buffers.iter().position(|buffer| buffer.idle) {
+            Some(index) => index,
+            None if buffers.len() < MAX_BUFFERS => {
+                buffers.push(self.create_buffer(conn, width, height)?);
+                buffers.len() - 1
+            }
+            None => {
+                // The server is falling behind, so don't keep allocating.
+                *self.pending.borrow_mut() = Some(frame);
+                return Ok(());
+            }
+        };
+        // This frame is newer than any which was waiting.
+        self.pending.take();
+        self.upload(conn, &mut buffers[index], &frame)
+    }
+
+    /// Write `frame` to `buffer`, which the server must be done with, and present it.
+    fn upload(
+        &self,
+        conn: &XCBConnection,
+        buffer: &mut Buffer,
+        frame: &Frame,
+    ) -> Result<(), Error> {
+        let Frame {
+            width,
+            height,
+            ref pixels,
+            vsync,
+        } = *frame;
+        match &buffer.shm {
+            Some(segment) => {
+                // Safety: the server is done with this buffer, so nobody else uses the memory.
+                let memory = unsafe {
+                    std::slice::from_raw_parts_mut(segment.ptr.as_ptr(), segment.len.get())
+                };
+                memory[..pixels.len()].copy_from_slice(pixels);
+                let gc = conn.generate_id()?;
+                conn.create_gc(gc, buffer.pixmap, &CreateGCAux::new())?;
+                conn.shm_put_image(
+                    buffer.pixmap,
+                    gc,
+                    width,
+                    height,
+                    0,
+                    0,
+                    width,
+                    height,
+                    0,
+                    0,
+                    self.depth,
+                    ImageFormat::Z_PIXMAP.into(),
+                    false,
+                    segment.seg,
+                    0,
+                )?;
+                conn.free_gc(gc)?;
+            }
+            None => put_image(conn, buffer.pixmap, self.depth, width.into(), pixels)?,
+        }
+
+        let serial = self.serial.get().wrapping_add(1);
+        self.serial.set(serial);
+        buffer.idle = false;
+        let options = if vsync {
+            present::Option::NONE
+        } else {
+            present::Option::ASYNC
+        };
+        conn.present_pixmap(
+            self.window,
+            buffer.pixmap,
+            serial,
+            x11rb::NONE,
+            x11rb::NONE,
+            0,
+            0,
+            x11rb::NONE,
+            x11rb::NONE,
+            x11rb::NONE,
+            options.into(),
+            0,
+            0,
+            0,
+            &[],
+        )?;
+        Ok(())
+    }
+
+    fn create_buffer(
+        &self,
+        conn: &XCBConnection,
+        width: u16,
+        height: u16,
+    ) -> Result<Buffer, Error> {
+        let pixmap = conn.generate_id()?;
+        conn.create_pixmap(self.depth, pixmap, self.window, width, height)?
+            .check()
+            .context("create pixmap for presenting")?;
+        let len = usize::from(width) * usize::from(height) * PixelFormat::BYTES_PER_PIXEL;
+        let shm = match NonZeroUsize::new(len).filter(|_| self.shm.get()) {
+            Some(len) => match ShmSegment::new(conn, len) {
+                Ok(segment) => Some(segment),
+                Err(e) => {
+                    tracing::warn!("failed to share memory with the X server: {e:#}");
+                    self.shm.set(false);
+                    None
+                }
+            },
+            None => None,
+        };
+        Ok(Buffer {
+            pixmap,
+            shm,
+            idle: true,
+        })
+    }
+
+    /// Called when the server no longer needs a pixmap which we presented.
+    ///
+    /// Returns whether a frame which was waiting for a buffer has now been presented in it.
+    pub fn handle_idle_notify(
+        &self,
+        conn: &XCBConnection,
+        event: &present::IdleNotifyEvent,
+    ) -> Result<bool, Error> {
+        let mut buffers = self.buffers.borrow_mut();
+        // The pixmaps from before a resize have already been freed.
+        let Some(buffer) = buffers.iter_mut().find(|b| b.pixmap == event.pixmap) else {
+            return Ok(false);
+        };
+        buffer.idle = true;
+        match self.pending.take() {
+            Some(frame) => {
+                self.upload(conn, buffer, &frame)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Free all of the buffers, and drop any frame which was waiting for one.
+    pub fn free(&self, conn: &XCBConnection) {
+        self.pending.take();
+        for buffer in self.buffers.take() {
+            log_x11!(conn.free_pixmap(buffer.pixmap));
+            if let Some(segment) = buffer.shm {
+                log_x11!(conn.shm_detach(segment.seg));
+            }
+        }
+    }
+}
+
+impl ShmSegment {
+    fn new(conn: &XCBConnection, len: NonZeroUsize) -> Result<ShmSegment, Error> {
+        let fd = memfd_create(
+            std::ffi::CStr::from_bytes_with_nul(b"glazier-x11-buffer\0").unwrap(),
+            MemFdCreateFlag::MFD_CLOEXEC,
+        )
+        .context("create memfd")?;
+        // The server gets its own copy of the file descriptor, and closes ours once it is sent.
+        let fd = RawFdContainer::new(fd);
+        nix::unistd::ftruncate(fd.as_raw_fd(), len.get() as _).context("resize memfd")?;
+        // Safety: we map a new file, which nothing else has mapped.
+        let ptr = unsafe {
+            mmap(
+                None,
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        }
+        .context("map memfd")?;
+        let segment = ShmSegment {
+            seg: conn.generate_id()?,
+            ptr: NonNull::new(ptr as *mut u8).unwrap(),
+            len,
+        };
+        conn.shm_attach_fd(segment.seg, fd, false)?
+            .check()
+            .context("attach shared memory segment")?;
+        Ok(segment)
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        // Safety: the memory was mapped by `ShmSegment::new`, and all references to it are gone.
+        if let Err(e) = unsafe { munmap(self.ptr.as_ptr() as *mut c_void, self.len.get()) } {
+            tracing::error!("failed to unmap a shared memory segment: {e}");
+        }
+    }
+}
@@ -37,11 +37,15 @@ use tracing::{debug, error, warn};
 // We can pick an arbitrary atom that is used for the transfer. This is our pick.
 const TRANSFER_ATOM: AtomEnum = AtomEnum::CUT_BUFFE_R4;
 
+/// The targets text is offered and asked for as, from the most to the least preferred.
+///
+/// `STRING` is ISO 8859-1, and `TEXT` is in whatever encoding the owner answers with; the
+/// others are UTF-8.
 const STRING_TARGETS: [&str; 5] = [
     "UTF8_STRING",
+    "text/plain;charset=utf-8",
     "TEXT",
     "STRING",
-    "text/plain;charset=utf-8",
     "text/plain",
 ];
 
@@ -56,6 +60,13 @@ impl Clipboard {
         ))))
     }
 
+    /// Make `primary` the clipboard used by [`put_primary`](Clipboard::put_primary) and
+    /// [`get_primary`](Clipboard::get_primary).
+    pub(crate) fn with_primary(self, primary: Clipboard) -> Self {
+        self.0.borrow_mut().primary = Some(primary);
+        self
+    }
+
     pub(crate) fn handle_clear(&self, event: SelectionClearEvent) -> Result<(), ConnectionError> {
         self.0.borrow_mut().handle_clear(event)
     }
@@ -75,10 +86,14 @@ impl Clipboard {
     }
 
     pub fn put_string(&mut self, s: impl AsRef<str>) {
-        let bytes = s.as_ref().as_bytes();
+        let s = s.as_ref();
+        let latin1 = encode_latin1(s);
         let formats = STRING_TARGETS
             .iter()
-            .map(|format| ClipboardFormat::new(format, bytes))
+            .map(|&format| match format {
+                "STRING" => ClipboardFormat::new(format, latin1.clone()),
+                _ => ClipboardFormat::new(format, s.as_bytes()),
+            })
             .collect::<Vec<_>>();
         self.put_formats(&formats);
     }
@@ -93,6 +108,23 @@ impl Clipboard {
         self.0.borrow().get_string()
     }
 
+    pub fn put_primary(&mut self, s: impl AsRef<str>) {
+        // The primary clipboard has no primary of its own, and is its own primary selection
+        let primary = self.0.borrow().primary.clone();
+        match primary {
+            Some(mut primary) => primary.put_string(s),
+            None => self.put_string(s),
+        }
+    }
+
+    pub fn get_primary(&self) -> Option<String> {
+        let primary = self.0.borrow().primary.clone();
+        match primary {
+            Some(primary) => primary.get_string(),
+            None => self.get_string(),
+        }
+    }
+
     pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
         self.0.borrow().preferred_format(formats)
     }
@@ -112,6 +144,8 @@ struct ClipboardState {
     selection_name: Atom,
     contents: Option<ClipboardContents>,
     incremental: Vec<IncrementalTransfer>,
+    /// The PRIMARY selection, for the CLIPBOARD one.
+    primary: Option<Clipboard>,
 }
 
 impl ClipboardState {
@@ -121,6 +155,7 @@ impl ClipboardState {
             selection_name,
             contents: None,
             incremental: Vec::new(),
+            primary: None,
         }
     }
 
@@ -155,10 +190,36 @@ impl ClipboardState {
     }
 
     fn get_string(&self) -> Option<String> {
-        STRING_TARGETS.iter().find_map(|target| {
-            self.get_format(target)
-                .and_then(|data| String::from_utf8(data).ok())
-        })
+        // Asking for the targets first means the text is only converted once, in the best
+        // encoding the owner has. Owners which don't answer are asked for each target in turn.
+        let available = self.available_type_names();
+        STRING_TARGETS
+            .iter()
+            .filter(|target| available.is_empty() || available.iter().any(|a| a == *target))
+            .find_map(|target| self.get_text(target))
+    }
+
+    /// Get the selection as text in the `target` format, decoding it by the type it comes as.
+    fn get_text(&self, target: FormatId) -> Option<String> {
+        let (type_, data) = if self.contents.is_some() {
+            let type_ = match target {
+                "STRING" => AtomEnum::STRING.into(),
+                _ => self.app.atoms.UTF8_STRING,
+            };
+            (type_, self.get_format(target)?)
+        } else {
+            let mut type_ = x11rb::NONE;
+            let data = self.do_transfer(target, |prop| {
+                type_ = prop.type_;
+                prop.value
+            })?;
+            (type_, data)
+        };
+        if type_ == Atom::from(AtomEnum::STRING) {
+            Some(decode_latin1(&data))
+        } else {
+            String::from_utf8(data).ok()
+        }
     }
 
     fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
@@ -388,12 +449,19 @@ impl ClipboardState {
                     return Ok(());
                 }
                 Some((atom, _, data)) => {
+                    // TEXT leaves the encoding to us, and ours is always UTF-8.
+                    let type_ = if *atom == self.app.atoms.TEXT {
+                        self.app.atoms.UTF8_STRING
+                    } else {
+                        *atom
+                    };
                     if data.len() > maximum_property_length(conn) {
                         // We need to do an INCR transfer.
                         debug!("Starting new INCR transfer");
                         let transfer = IncrementalTransfer::new(
                             conn,
                             event,
+                            type_,
                             Rc::clone(data),
                             self.app.atoms.INCR,
                         );
@@ -410,7 +478,7 @@ impl ClipboardState {
                             PropMode::REPLACE,
                             event.requestor,
                             event.property,
-                            *atom,
+                            type_,
                             data,
                         )?;
                     }
@@ -521,7 +589,7 @@ impl ClipboardContents {
 #[derive(Debug)]
 struct IncrementalTransfer {
     requestor: Window,
-    target: Atom,
+    type_: Atom,
     property: Atom,
     data: Rc<[u8]>,
     data_offset: usize,
@@ -531,6 +599,7 @@ impl IncrementalTransfer {
     fn new(
         conn: &XCBConnection,
         event: &SelectionRequestEvent,
+        type_: Atom,
         data: Rc<[u8]>,
         incr: Atom,
     ) -> Result<Self, ConnectionError> {
@@ -550,7 +619,7 @@ impl IncrementalTransfer {
         )?;
         Ok(Self {
             requestor: event.requestor,
-            target: event.target,
+            type_,
             property: event.property,
             data,
             data_offset: 0,
@@ -565,7 +634,7 @@ impl IncrementalTransfer {
             PropMode::REPLACE,
             self.requestor,
             self.property,
-            self.target,
+            self.type_,
             &remaining[..next_length],
         )?;
         self.data_offset += next_length;
@@ -613,6 +682,15 @@ fn maximum_property_length(connection: &XCBConnection) -> usize {
     max_request_length - change_property_header_size
 }
 
+/// Encode `s` as ISO 8859-1, for the `STRING` target, replacing what it can't hold with `?`.
+fn encode_latin1(s: &str) -> Vec<u8> {
+    s.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
 fn reject_transfer(
     conn: &XCBConnection,
     event: &SelectionRequestEvent,
@@ -670,3 +748,16 @@ fn wait_for_event_with_deadline(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latin1_round_trip() {
+        assert_eq!(encode_latin1("café €5"), b"caf\xe9 ?5");
+        assert_eq!(decode_latin1(b"caf\xe9"), "café");
+        let long = "ÿ".repeat(1 << 20);
+        assert_eq!(decode_latin1(&encode_latin1(&long)), long);
+    }
+}
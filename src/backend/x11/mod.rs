@@ -34,6 +34,7 @@
 mod util;
 
 pub mod application;
+mod buffers;
 pub mod clipboard;
 pub mod dialog;
 pub mod error;
@@ -41,3 +42,4 @@ pub mod menu;
 pub mod pointer;
 pub mod screen;
 pub mod window;
+mod xsettings;
@@ -5,16 +5,24 @@ use memchr::memmem;
 use x11rb::{
     protocol::xinput::{
         self, ConnectionExt as _, DeviceClass, DeviceClassData, DeviceType, EventMask, Fp3232,
-        XIDeviceInfo, XIEventMask,
+        ScrollType, XIDeviceInfo, XIEventMask,
     },
     xcb_ffi::XCBConnection,
 };
 
+use crate::kurbo::Vec2;
+
 use super::application::AppAtoms;
 
 #[derive(Clone, Debug, Default)]
 pub struct PointersState {
     pub device_infos: HashMap<u16, DeviceInfo>,
+    /// The last value of each scroll valuator, by device and valuator.
+    ///
+    /// The valuators are absolute, so a scroll is the difference from the last value. They
+    /// keep changing while the pointer is over other windows, so the values are forgotten
+    /// when it leaves ours, and the first event after that only sets the value again.
+    scroll_positions: HashMap<(u16, usize), f64>,
 }
 
 #[derive(Clone, Debug)]
@@ -41,10 +49,77 @@ pub enum DeviceKind {
     Mouse,
 }
 
+/// A valuator which reports smooth scrolling, which XInput 2.1 added.
+#[derive(Clone, Debug)]
+pub struct ScrollValuator {
+    pub idx: usize,
+    pub horizontal: bool,
+    /// How much the valuator changes for one step of a mouse wheel.
+    pub increment: f64,
+}
+
 impl PointersState {
     pub fn device_info(&self, id: u16) -> Option<&DeviceInfo> {
         self.device_infos.get(&id)
     }
+
+    /// The scrolling in an event from `device`, in steps of a mouse wheel, positive being
+    /// down and right.
+    pub fn scroll_delta(
+        &mut self,
+        device: u16,
+        valuator_mask: &[u32],
+        axisvalues: &[Fp3232],
+    ) -> Vec2 {
+        let Some(info) = self.device_infos.get(&device) else {
+            return Vec2::ZERO;
+        };
+        let mut delta = Vec2::ZERO;
+        for valuator in &info.scroll_valuators {
+            let Some(value) = valuator_value(valuator_mask, axisvalues, valuator.idx) else {
+                continue;
+            };
+            let Some(last) = self.scroll_positions.insert((device, valuator.idx), value) else {
+                continue;
+            };
+            let steps = (value - last) / valuator.increment;
+            if valuator.horizontal {
+                delta.x += steps;
+            } else {
+                delta.y += steps;
+            }
+        }
+        delta
+    }
+
+    /// Forget the scroll valuators' values, as they may change without us being told.
+    pub fn reset_scroll(&mut self) {
+        self.scroll_positions.clear();
+    }
+}
+
+/// The value of the valuator `idx` in an event, if it's there.
+///
+/// Events only carry the values of the valuators which are set in their mask, in order.
+pub fn valuator_value(valuator_mask: &[u32], axisvalues: &[Fp3232], idx: usize) -> Option<f64> {
+    let is_set = |idx: usize| {
+        valuator_mask
+            .get(idx / 32)
+            .map_or(false, |word| word & (1 << (idx % 32)) != 0)
+    };
+    if !is_set(idx) {
+        return None;
+    }
+    let position = (0..idx).filter(|&idx| is_set(idx)).count();
+    axisvalues.get(position).map(|x| fixed_to_floating(*x))
+}
+
+/// Whether an event moved the pointer, rather than only reporting other valuators such as the
+/// scroll valuators.
+///
+/// The first two valuators are always the pointer's position.
+pub fn moves_pointer(valuator_mask: &[u32]) -> bool {
+    valuator_mask.first().map_or(false, |word| word & 0b11 != 0)
 }
 
 #[derive(Clone, Debug, Default)]
@@ -89,6 +164,7 @@ pub struct DeviceInfo {
     pub device_type: DeviceType,
     pub device_kind: DeviceKind,
     pub valuators: PenValuators,
+    pub scroll_valuators: Vec<ScrollValuator>,
 }
 
 impl std::fmt::Debug for DeviceInfo {
@@ -99,6 +175,7 @@ impl std::fmt::Debug for DeviceInfo {
             .field("device_type", &self.device_type)
             .field("device_kind", &self.device_kind)
             .field("valuators", &self.valuators)
+            .field("scroll_valuators", &self.scroll_valuators)
             .finish()
     }
 }
@@ -123,6 +200,19 @@ impl DeviceInfo {
             device_type: dev.type_,
             device_kind: DeviceKind::Mouse,
             valuators: PenValuators::new(&dev.classes, atoms),
+            scroll_valuators: dev
+                .classes
+                .iter()
+                .filter_map(|cl| match &cl.data {
+                    DeviceClassData::Scroll(scroll) => Some(ScrollValuator {
+                        idx: scroll.number as usize,
+                        horizontal: scroll.scroll_type == ScrollType::HORIZONTAL,
+                        increment: fixed_to_floating(scroll.increment),
+                    }),
+                    _ => None,
+                })
+                .filter(|valuator| valuator.increment != 0.0)
+                .collect(),
         };
 
         ret.detect_device_kind(&dev.classes);
@@ -161,8 +251,10 @@ pub(crate) fn initialize_pointers(
     atoms: &AppAtoms,
     window: u32,
 ) -> anyhow::Result<PointersState> {
-    let version = conn.xinput_get_extension_version(b"xinput")?.reply()?;
-    if (version.server_major, version.server_minor) < (2, 2) {
+    // The server only sends the events of the versions it has been told we support, such as
+    // the scroll valuators of 2.1
+    let version = conn.xinput_xi_query_version(2, 2)?.reply()?;
+    if (version.major_version, version.minor_version) < (2, 2) {
         // xinput 2.2 added multitouch; xorg has supported it since 2012
         bail!("xinput version {version:?} found, but we require at least 2.2");
     }
@@ -188,7 +280,10 @@ pub(crate) fn initialize_pointers(
     )?
     .check()?;
 
-    Ok(PointersState { device_infos })
+    Ok(PointersState {
+        device_infos,
+        scroll_positions: HashMap::new(),
+    })
 }
 
 pub(crate) fn enable_window_pointers(conn: &XCBConnection, window: u32) -> anyhow::Result<()> {
@@ -209,3 +304,62 @@ pub(crate) fn enable_window_pointers(conn: &XCBConnection, window: u32) -> anyho
     .check()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixed(x: f64) -> Fp3232 {
+        Fp3232 {
+            integral: x.floor() as i32,
+            frac: (x.fract() * (1u64 << 32) as f64) as u32,
+        }
+    }
+
+    #[test]
+    fn scroll_from_valuators() {
+        let mut state = PointersState::default();
+        state.device_infos.insert(
+            2,
+            DeviceInfo {
+                id: 2,
+                name: b"touchpad".to_vec(),
+                device_type: DeviceType::SLAVE_POINTER,
+                device_kind: DeviceKind::Mouse,
+                valuators: PenValuators::default(),
+                scroll_valuators: vec![
+                    ScrollValuator {
+                        idx: 2,
+                        horizontal: true,
+                        increment: 15.0,
+                    },
+                    ScrollValuator {
+                        idx: 3,
+                        horizontal: false,
+                        increment: 15.0,
+                    },
+                ],
+            },
+        );
+        // Only the vertical valuator, after the position
+        let mask = [0b1011];
+        let values = |y| [fixed(10.0), fixed(20.0), fixed(y)];
+        assert_eq!(valuator_value(&mask, &values(7.5), 3), Some(7.5));
+        assert_eq!(valuator_value(&mask, &values(7.5), 2), None);
+
+        // The first value is only where the valuator starts
+        assert_eq!(state.scroll_delta(2, &mask, &values(100.0)), Vec2::ZERO);
+        assert_eq!(
+            state.scroll_delta(2, &mask, &values(107.5)),
+            Vec2::new(0.0, 0.5)
+        );
+        assert_eq!(
+            state.scroll_delta(2, &mask, &values(92.5)),
+            Vec2::new(0.0, -1.0)
+        );
+        state.reset_scroll();
+        assert_eq!(state.scroll_delta(2, &mask, &values(0.0)), Vec2::ZERO);
+        assert!(moves_pointer(&mask));
+        assert!(!moves_pointer(&[0b1000]));
+    }
+}
@@ -17,55 +17,80 @@
 use std::cell::{Cell, RefCell};
 use std::collections::BinaryHeap;
 use std::convert::TryFrom;
+use std::num::NonZeroU32;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::RawFd;
 use std::panic::Location;
+use std::ptr::NonNull;
 use std::rc::{Rc, Weak};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "accesskit")]
+use accesskit_unix::Adapter as AccessKitAdapter;
 
 use crate::pointer::{
     Angle, MouseInfo, PenInclination, PenInfo, PointerId, PointerType, TouchInfo,
 };
 use crate::scale::Scalable;
 use anyhow::{anyhow, Context, Error};
-use tracing::{error, warn};
+use tracing::{debug, error, warn};
 use x11rb::connection::Connection;
 use x11rb::errors::ReplyOrIdError;
-use x11rb::properties::{WmHints, WmHintsState, WmSizeHints};
+use x11rb::properties::{AspectRatio, WmHints, WmHintsState, WmSizeHints};
+use x11rb::protocol::present::{self, ConnectionExt as _};
 use x11rb::protocol::render::Pictformat;
+use x11rb::protocol::sync::{self, ConnectionExt as _};
 use x11rb::protocol::xinput::{self, DeviceType, ModifierInfo, TouchEventFlags};
 use x11rb::protocol::xproto::{
     self, AtomEnum, ChangeWindowAttributesAux, ColormapAlloc, ConfigureNotifyEvent,
-    ConfigureWindowAux, ConnectionExt, EventMask, ImageOrder as X11ImageOrder, KeyButMask,
-    PropMode, Visualtype, WindowClass,
+    ConfigureWindowAux, ConnectionExt, CreateGCAux, Drawable, EventMask, GrabMode, ImageFormat,
+    ImageOrder as X11ImageOrder, KeyButMask, PropMode, Visualtype, WindowClass,
 };
 use x11rb::wrapper::ConnectionExt as _;
 use x11rb::xcb_ffi::XCBConnection;
 
 use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle, XcbDisplayHandle,
-    XcbWindowHandle,
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, XcbDisplayHandle, XcbWindowHandle,
 };
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle};
 
+#[cfg(feature = "accesskit")]
+use crate::backend::shared::linux;
 use crate::backend::shared::Timer;
-use crate::common_util::IdleCallback;
+use crate::common_util::{
+    min_size_with_aspect_ratio, FrameSchedule, FrameThrottle, FrameTimingsCache, IdleCallback,
+    PaintRequests,
+};
 use crate::dialog::FileDialogOptions;
 use crate::error::Error as ShellError;
 use crate::keyboard::{KeyState, Modifiers};
 use crate::kurbo::{Insets, Point, Rect, Size, Vec2};
 use crate::mouse::{Cursor, CursorDesc};
+use crate::platform::linux::LayerShellConfig;
 use crate::region::Region;
 use crate::scale::Scale;
 use crate::text::{simulate_input, Event};
 use crate::window::{
-    FileDialogToken, IdleToken, TextFieldToken, TimerToken, WinHandler, WindowLevel,
+    FileDialogToken, FrameTimings, IdleToken, ImageBuf, ModalResponse, PixelFormat, PopupConfig,
+    StackingLevel, TextFieldToken, Theme, TimerToken, Visibility, WinHandler, WindowLevel,
+    WindowType,
+};
+use crate::{
+    window, KeyEvent, LoopMode, LoopStatus, PointerButton, PointerButtons, PointerEvent, ScaledArea,
 };
-use crate::{window, KeyEvent, PointerButton, PointerButtons, PointerEvent, ScaledArea};
 
-use super::application::Application;
+use super::application::{AppAtoms, Application};
+use super::buffers::{self, BufferPool};
 use super::dialog;
 use super::menu::Menu;
 
+/// How long to wait for a frame from `present_pixels` to be shown, before painting the next one
+/// anyway.
+const PRESENT_TIMEOUT: Duration = Duration::from_millis(100);
+
 /// A version of XCB's `xcb_visualtype_t` struct. This was copied from the [example] in x11rb; it
 /// is used to interoperate with cairo.
 ///
@@ -101,10 +126,33 @@ impl From<Visualtype> for xcb_visualtype_t {
     }
 }
 
-fn size_hints(resizable: bool, size: Size, min_size: Size) -> WmSizeHints {
+fn size_hints(
+    resizable: bool,
+    size: Size,
+    min_size: Size,
+    aspect_ratio: Option<f64>,
+    increments: Option<Size>,
+) -> WmSizeHints {
     let mut size_hints = WmSizeHints::new();
     if resizable {
+        let min_size = match aspect_ratio {
+            Some(ratio) => {
+                // The window manager keeps the ratio between the minimum and the maximum.
+                let aspect = AspectRatio::new((ratio * 10000.0).round() as i32, 10000);
+                size_hints.aspect = Some((aspect, aspect));
+                min_size_with_aspect_ratio(min_size, ratio)
+            }
+            None => min_size,
+        };
         size_hints.min_size = Some((min_size.width as i32, min_size.height as i32));
+        if let Some(increments) = increments {
+            // The window manager resizes the window to the base size plus whole increments.
+            size_hints.base_size = size_hints.min_size;
+            size_hints.size_increment = Some((
+                (increments.width.round() as i32).max(1),
+                (increments.height.round() as i32).max(1),
+            ));
+        }
     } else {
         size_hints.min_size = Some((size.width as i32, size.height as i32));
         size_hints.max_size = Some((size.width as i32, size.height as i32));
@@ -122,7 +170,23 @@ pub(crate) struct WindowBuilder {
     min_size: Size,
     resizable: bool,
     level: WindowLevel,
+    window_type: Option<WindowType>,
     state: Option<window::WindowState>,
+    maximize_mode: window::MaximizeMode,
+    kiosk: bool,
+    /// Whether the window was fullscreen when its frame was saved.
+    fullscreen: bool,
+    offscreen: bool,
+    visible: bool,
+    skip_taskbar: bool,
+    accepts_focus: bool,
+    managed_surface: bool,
+    coalesce_pointer_events: bool,
+    owner: Option<crate::WindowHandle>,
+    popup: Option<PopupConfig>,
+    frame_autosave_name: Option<String>,
+    /// Set by `build_as_child`, for windows inside a window we don't own.
+    foreign_parent: Option<xproto::Window>,
 }
 
 impl WindowBuilder {
@@ -137,7 +201,21 @@ impl WindowBuilder {
             min_size: Size::new(0.0, 0.0),
             resizable: true,
             level: WindowLevel::AppWindow,
+            window_type: None,
             state: None,
+            maximize_mode: window::MaximizeMode::default(),
+            kiosk: false,
+            fullscreen: false,
+            offscreen: false,
+            visible: false,
+            skip_taskbar: false,
+            accepts_focus: true,
+            managed_surface: true,
+            coalesce_pointer_events: true,
+            owner: None,
+            popup: None,
+            frame_autosave_name: None,
+            foreign_parent: None,
         }
     }
 
@@ -172,6 +250,18 @@ impl WindowBuilder {
         self
     }
 
+    pub fn layer_shell(self, _config: LayerShellConfig) -> Self {
+        warn!(
+            "WindowBuilder::set_layer_shell is only supported on Wayland, building a normal window"
+        );
+        self
+    }
+
+    pub fn prefer_client_side_decorations(self, _prefer: bool) -> Self {
+        // The window manager always draws the decorations on X11
+        self
+    }
+
     pub fn transparent(mut self, transparent: bool) -> Self {
         self.transparent = transparent;
         self
@@ -187,11 +277,72 @@ impl WindowBuilder {
         self
     }
 
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = Some(window_type);
+        self
+    }
+
+    pub fn with_frame_autosave_name(mut self, name: &str, fullscreen: bool) -> Self {
+        self.frame_autosave_name = Some(name.to_owned());
+        self.fullscreen = fullscreen;
+        self
+    }
+
     pub fn window_state(mut self, state: window::WindowState) -> Self {
         self.state = Some(state);
         self
     }
 
+    pub fn maximize_mode(mut self, mode: window::MaximizeMode) -> Self {
+        self.maximize_mode = mode;
+        self
+    }
+
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self.kiosk = kiosk;
+        self
+    }
+
+    pub fn offscreen(mut self, offscreen: bool) -> Self {
+        self.offscreen = offscreen;
+        self
+    }
+
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self.managed_surface = managed;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_skip_taskbar(mut self, skip: bool) -> Self {
+        self.skip_taskbar = skip;
+        self
+    }
+
+    pub fn with_accepts_focus(mut self, accepts_focus: bool) -> Self {
+        self.accepts_focus = accepts_focus;
+        self
+    }
+
+    pub fn with_coalesced_pointer_events(mut self, coalesce: bool) -> Self {
+        self.coalesce_pointer_events = coalesce;
+        self
+    }
+
+    pub fn with_parent(mut self, parent: crate::WindowHandle) -> Self {
+        self.owner = Some(parent);
+        self
+    }
+
+    pub fn popup(mut self, popup: PopupConfig) -> Self {
+        self.popup = Some(popup);
+        self
+    }
+
     pub fn title<S: Into<String>>(mut self, title: S) -> Self {
         self.title = title.into();
         self
@@ -203,32 +354,52 @@ impl WindowBuilder {
         self
     }
 
+    pub fn build_as_child(mut self, parent: RawWindowHandle) -> Result<WindowHandle, ShellError> {
+        let parent = match parent {
+            RawWindowHandle::Xcb(handle) => handle.window.get(),
+            RawWindowHandle::Xlib(handle) => handle.window as xproto::Window,
+            _ => {
+                return Err(ShellError::Unsupported(
+                    "child windows of a window which isn't an X11 window",
+                ))
+            }
+        };
+        if self.kiosk || self.offscreen || self.transparent || self.popup.is_some() {
+            return Err(ShellError::IncompatibleOptions(
+                "a child window can't be a kiosk, offscreen, transparent or popup window",
+            ));
+        }
+        self.foreign_parent = Some(parent);
+        self.build()
+    }
+
+    pub fn build(self) -> Result<WindowHandle, ShellError> {
+        if self.kiosk && self.popup.is_some() {
+            return Err(ShellError::IncompatibleOptions(
+                "a popup can't be a kiosk window",
+            ));
+        }
+        if self.transparent && self.app.argb_visual_type().is_none() {
+            return Err(ShellError::IncompatibleOptions(
+                "transparent windows need a compositing manager, and none is running",
+            ));
+        }
+        self.build_window().map_err(Into::into)
+    }
+
     // TODO(x11/menus): make menus if requested
-    pub fn build(self) -> Result<WindowHandle, Error> {
+    fn build_window(mut self) -> Result<WindowHandle, Error> {
+        if let Some(popup) = &self.popup {
+            // Dropdowns are override-redirect, so we can place them ourselves
+            self.level = WindowLevel::DropDown(popup.parent.clone());
+            self.position = Some(popup.origin(self.size));
+        }
         let conn = self.app.connection();
         let screen_num = self.app.screen_num();
         let id = conn.generate_id()?;
         let setup = conn.setup();
 
-        let env_dpi = std::env::var("DRUID_X11_DPI")
-            .ok()
-            .map(|x| x.parse::<f64>());
-
-        let scale = match env_dpi.or_else(|| self.app.rdb.get_value("Xft.dpi", "").transpose()) {
-            Some(Ok(dpi)) => {
-                let scale = dpi / 96.;
-                Scale::new(scale, scale)
-            }
-            None => Scale::default(),
-            Some(Err(err)) => {
-                let default = Scale::default();
-                warn!(
-                    "Unable to parse dpi: {:?}, defaulting to {:?}",
-                    err, default
-                );
-                default
-            }
-        };
+        let scale = self.app.scale();
 
         let size_px = self.size.to_px(scale);
         let screen = setup
@@ -248,14 +419,20 @@ impl WindowBuilder {
             warn!("Windows with transparent backgrounds do not work");
         }
 
-        let mut cw_values = xproto::CreateWindowAux::new().event_mask(
-            EventMask::EXPOSURE
-                | EventMask::STRUCTURE_NOTIFY
-                | EventMask::KEY_PRESS
-                | EventMask::KEY_RELEASE
-                | EventMask::FOCUS_CHANGE
-                | EventMask::LEAVE_WINDOW,
-        );
+        let mut cw_values = xproto::CreateWindowAux::new()
+            .event_mask(
+                EventMask::EXPOSURE
+                    | EventMask::STRUCTURE_NOTIFY
+                    | EventMask::KEY_PRESS
+                    | EventMask::KEY_RELEASE
+                    | EventMask::FOCUS_CHANGE
+                    | EventMask::LEAVE_WINDOW
+                    | EventMask::VISIBILITY_CHANGE
+                    | EventMask::PROPERTY_CHANGE,
+            )
+            // Without a background, the server doesn't fill the window when it's mapped or
+            // resized, so nothing is shown there before the handler paints.
+            .background_pixmap(x11rb::NONE);
         if transparent {
             let colormap = conn.generate_id()?;
             conn.create_colormap(
@@ -293,9 +470,10 @@ impl WindowBuilder {
             depth,
             // The new window's ID
             id,
-            // Parent window of this new window
+            // Parent window of this new window. Window IDs belong to the server, so a parent
+            // created by another client, on another connection, works too.
             // TODO(#468): either `screen.root()` (no parent window) or pass parent here to attach
-            screen.root,
+            self.foreign_parent.unwrap_or(screen.root),
             // X-coordinate of the new window
             pos.x as _,
             // Y-coordinate of the new window
@@ -318,6 +496,17 @@ impl WindowBuilder {
 
         super::pointer::enable_window_pointers(conn, id)?;
 
+        if self.app.present_supported() {
+            let eid = conn.generate_id()?;
+            conn.present_select_input(
+                eid,
+                id,
+                present::EventMask::COMPLETE_NOTIFY | present::EventMask::IDLE_NOTIFY,
+            )?
+            .check()
+            .context("select present events")?;
+        }
+
         if let Some(colormap) = cw_values.colormap {
             conn.free_colormap(colormap)?;
         }
@@ -337,8 +526,45 @@ impl WindowBuilder {
             .check()
             .context("set _NET_WM_PID")?;
         }
+        // _NET_WM_PID is only meaningful together with the machine the process runs on.
+        match nix::unistd::gethostname() {
+            Ok(hostname) => {
+                conn.change_property8(
+                    PropMode::REPLACE,
+                    id,
+                    AtomEnum::WM_CLIENT_MACHINE,
+                    AtomEnum::STRING,
+                    hostname.as_bytes(),
+                )?;
+            }
+            Err(e) => warn!("failed to get the hostname for WM_CLIENT_MACHINE: {e}"),
+        }
+        if let Some(startup_id) = self.app.startup_id() {
+            conn.change_property8(
+                PropMode::REPLACE,
+                id,
+                atoms._NET_STARTUP_ID,
+                atoms.UTF8_STRING,
+                startup_id.as_bytes(),
+            )?;
+        }
 
-        if let Some(name) = std::env::args_os().next() {
+        if let Some(app_id) = self.app.app_id() {
+            // Desktop environments match WM_CLASS against the name of the application's
+            // `.desktop` file, to group its windows and find their icon.
+            let mut wm_class = Vec::with_capacity(2 * (app_id.len() + 1));
+            for _ in 0..2 {
+                wm_class.extend(app_id.as_bytes());
+                wm_class.push(0);
+            }
+            conn.change_property8(
+                PropMode::REPLACE,
+                id,
+                AtomEnum::WM_CLASS,
+                AtomEnum::STRING,
+                &wm_class,
+            )?;
+        } else if let Some(name) = std::env::args_os().next() {
             // ICCCM § 4.1.2.5:
             // The WM_CLASS property (of type STRING without control characters) contains two
             // consecutive null-terminated strings. These specify the Instance and Class names.
@@ -375,7 +601,10 @@ impl WindowBuilder {
         }
 
         // Replace the window's WM_PROTOCOLS with the following.
-        let protocols = [atoms.WM_DELETE_WINDOW];
+        let mut protocols = vec![atoms.WM_DELETE_WINDOW, atoms._NET_WM_PING];
+        if self.app.sync_supported() && !self.offscreen {
+            protocols.push(atoms._NET_WM_SYNC_REQUEST);
+        }
         conn.change_property32(
             PropMode::REPLACE,
             id,
@@ -386,12 +615,49 @@ impl WindowBuilder {
         .check()
         .context("set WM_PROTOCOLS")?;
 
+        // The extended counter stays odd until the handler has painted, which tells compositors
+        // that synchronize with their windows' frames not to show this one yet.
+        let sync_counters = if self.app.sync_supported() && !self.offscreen {
+            let basic = conn.generate_id()?;
+            let extended = conn.generate_id()?;
+            conn.sync_create_counter(basic, sync::Int64 { hi: 0, lo: 0 })?;
+            conn.sync_create_counter(extended, sync::Int64 { hi: 0, lo: 1 })?;
+            conn.change_property32(
+                PropMode::REPLACE,
+                id,
+                atoms._NET_WM_SYNC_REQUEST_COUNTER,
+                AtomEnum::CARDINAL,
+                &[basic, extended],
+            )?
+            .check()
+            .context("set _NET_WM_SYNC_REQUEST_COUNTER")?;
+            Some((basic, extended))
+        } else {
+            None
+        };
+
+        let owner = self
+            .owner
+            .as_ref()
+            .and_then(|owner| owner.0.unwrap_x11().window.upgrade());
+        if let Some(owner) = &owner {
+            // ICCCM § 4.1.2.6: window managers keep transient windows above the window they are
+            // transient for, and usually minimize them along with it.
+            log_x11!(conn.change_property32(
+                PropMode::REPLACE,
+                id,
+                AtomEnum::WM_TRANSIENT_FOR,
+                AtomEnum::WINDOW,
+                &[owner.id],
+            ));
+        }
+
         let min_size = self.min_size.to_px(scale);
-        log_x11!(size_hints(self.resizable, size_px, min_size)
+        let resizable = self.resizable && !self.kiosk;
+        log_x11!(size_hints(resizable, size_px, min_size, None, None)
             .set_normal_hints(conn, id)
             .context("set wm normal hints"));
 
-        // TODO: set _NET_WM_STATE
         let mut hints = WmHints::new();
         if let Some(state) = self.state {
             hints.initial_state = Some(match state {
@@ -400,15 +666,64 @@ impl WindowBuilder {
                 window::WindowState::Restored => WmHintsState::Normal,
             });
         }
+        if !self.accepts_focus {
+            // Without WM_TAKE_FOCUS in WM_PROTOCOLS either, the window manager never focuses us.
+            hints.input = Some(false);
+        }
         log_x11!(hints.set(conn, id).context("set wm hints"));
 
+        if self.kiosk {
+            // Remove the decorations and all window manager functions (move, resize, minimize,
+            // maximize and close), see the `MwmHints` struct of Motif's `MwmUtil.h`.
+            const MWM_HINTS_FUNCTIONS: u32 = 1 << 0;
+            const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+            log_x11!(conn.change_property32(
+                PropMode::REPLACE,
+                id,
+                atoms._MOTIF_WM_HINTS,
+                atoms._MOTIF_WM_HINTS,
+                &[MWM_HINTS_FUNCTIONS | MWM_HINTS_DECORATIONS, 0, 0, 0, 0],
+            ));
+        }
+        let mut net_wm_state = Vec::new();
+        if self.kiosk {
+            net_wm_state.extend([atoms._NET_WM_STATE_FULLSCREEN, atoms._NET_WM_STATE_ABOVE]);
+        } else if self.fullscreen {
+            net_wm_state.push(atoms._NET_WM_STATE_FULLSCREEN);
+        }
+        if self.state == Some(window::WindowState::Maximized) {
+            // A window maximized over the whole monitor is maximized by the window manager
+            // first, see `Window::handle_net_wm_state_changed`.
+            net_wm_state.extend(maximized_atoms(atoms));
+        }
+        if !net_wm_state.is_empty() {
+            // Setting _NET_WM_STATE before the window is mapped is allowed by the EWMH spec.
+            log_x11!(conn.change_property32(
+                PropMode::REPLACE,
+                id,
+                atoms._NET_WM_STATE,
+                AtomEnum::ATOM,
+                &net_wm_state,
+            ));
+        }
+
         // set level
-        {
-            let window_type = match self.level {
-                WindowLevel::AppWindow => atoms._NET_WM_WINDOW_TYPE_NORMAL,
-                WindowLevel::Tooltip(_) => atoms._NET_WM_WINDOW_TYPE_TOOLTIP,
-                WindowLevel::Modal(_) => atoms._NET_WM_WINDOW_TYPE_DIALOG,
-                WindowLevel::DropDown(_) => atoms._NET_WM_WINDOW_TYPE_DROPDOWN_MENU,
+        let window_type = {
+            let window_type = match (self.window_type, &self.level) {
+                (Some(WindowType::Normal), _) => atoms._NET_WM_WINDOW_TYPE_NORMAL,
+                (Some(WindowType::Dialog), _) => atoms._NET_WM_WINDOW_TYPE_DIALOG,
+                (Some(WindowType::Menu), _) => atoms._NET_WM_WINDOW_TYPE_MENU,
+                (Some(WindowType::Tooltip), _) => atoms._NET_WM_WINDOW_TYPE_TOOLTIP,
+                (Some(WindowType::Utility), _) => atoms._NET_WM_WINDOW_TYPE_UTILITY,
+                (Some(WindowType::Splash), _) => atoms._NET_WM_WINDOW_TYPE_SPLASH,
+                (Some(WindowType::DropDown), _) => atoms._NET_WM_WINDOW_TYPE_DROPDOWN_MENU,
+                (None, WindowLevel::AppWindow) if !self.accepts_focus => {
+                    atoms._NET_WM_WINDOW_TYPE_UTILITY
+                }
+                (None, WindowLevel::AppWindow) => atoms._NET_WM_WINDOW_TYPE_NORMAL,
+                (None, WindowLevel::Tooltip(_)) => atoms._NET_WM_WINDOW_TYPE_TOOLTIP,
+                (None, WindowLevel::Modal(_)) => atoms._NET_WM_WINDOW_TYPE_DIALOG,
+                (None, WindowLevel::DropDown(_)) => atoms._NET_WM_WINDOW_TYPE_DROPDOWN_MENU,
             };
 
             let conn = self.app.connection();
@@ -428,7 +743,8 @@ impl WindowBuilder {
                     &ChangeWindowAttributesAux::new().override_redirect(1),
                 ));
             }
-        }
+            window_type
+        };
 
         let window = Rc::new(Window {
             id,
@@ -436,7 +752,11 @@ impl WindowBuilder {
             handler,
             area: Cell::new(ScaledArea::from_px(size_px, scale)),
             scale: Cell::new(scale),
+            backing_scale_override: Cell::new(None),
             min_size,
+            resizable: Cell::new(resizable),
+            aspect_ratio: Cell::new(None),
+            resize_increments: Cell::new(None),
             invalid: RefCell::new(Region::EMPTY),
             destroyed: Cell::new(false),
             timer_queue: Mutex::new(BinaryHeap::new()),
@@ -444,17 +764,68 @@ impl WindowBuilder {
             idle_pipe: self.app.idle_pipe(),
             active_text_field: Cell::new(None),
             parent,
+            owned: RefCell::new(Vec::new()),
+            kiosk: self.kiosk,
+            depth,
+            offscreen: self.offscreen,
+            managed_surface: self.managed_surface,
+            coalesce_pointer_events: self.coalesce_pointer_events,
+            popup_grab: self.popup.as_ref().map_or(false, |popup| popup.grab),
+            window_type,
+            frame_autosave_name: self.frame_autosave_name.take(),
+            mapped: Cell::new(false),
+            stacking_level: Cell::new(StackingLevel::Normal),
+            skip_taskbar: Cell::new(self.skip_taskbar),
+            net_wm_state: RefCell::new(net_wm_state),
+            maximize_mode: Cell::new(self.maximize_mode),
+            full_monitor_restore: Cell::new(None),
+            full_monitor_pending: Cell::new(false),
+            sheet: RefCell::new(None),
+            has_sheet: Cell::new(false),
+            modal_running: Cell::new(false),
+            modal_response: Cell::new(None),
+            backing: Cell::new(None),
+            visibility: Cell::new(Visibility::Hidden),
+            frame_throttle: FrameThrottle::default(),
+            paint_requests: PaintRequests::default(),
+            delayed_frame: Cell::new(None),
+            buffers: BufferPool::new(id, depth, self.app.shm_supported()),
+            present_pending: Cell::new(None),
+            frame_waiting: Cell::new(false),
+            vsync: Cell::new(true),
+            frame_timings: FrameTimingsCache::default(),
+            last_msc: Cell::new(None),
+            sync_counters,
+            first_frame_pending: Cell::new(sync_counters.is_some()),
+            sync_request: Cell::new(None),
+            sync_reply: Cell::new(None),
+            #[cfg(feature = "accesskit")]
+            accesskit_adapter: RefCell::new(None),
+            #[cfg(feature = "accesskit")]
+            focused: Cell::new(false),
         });
 
+        if let Some(owner) = owner {
+            owner.owned.borrow_mut().push(Rc::downgrade(&window));
+        }
         window.set_title(&self.title);
+        if self.skip_taskbar {
+            window.update_wm_state();
+        }
         if let Some(pos) = self.position {
             window.set_position(pos);
         }
+        if self.offscreen {
+            window.create_backing(size_px)?;
+        }
 
         let handle = WindowHandle::new(id, visual_type.visual_id, Rc::downgrade(&window));
         window.connect(handle.clone())?;
 
-        self.app.add_window(id, window)?;
+        self.app.add_window(id, window.clone())?;
+        if self.visible {
+            window.show();
+        }
 
         Ok(handle)
     }
@@ -483,8 +854,15 @@ pub(crate) struct Window {
     handler: RefCell<Box<dyn WinHandler>>,
     area: Cell<ScaledArea>,
     scale: Cell<Scale>,
+    /// The scale the handler renders the surface at, instead of `scale`.
+    backing_scale_override: Cell<Option<f64>>,
     // min size in px
     min_size: Size,
+    /// Whether the user may resize the window, which `size_hints` needs.
+    resizable: Cell<bool>,
+    aspect_ratio: Cell<Option<f64>>,
+    /// The steps the window is resized by, in display points.
+    resize_increments: Cell<Option<Size>>,
     /// We've told X11 to destroy this window, so don't so any more X requests with this window id.
     destroyed: Cell<bool>,
     /// The region that was invalidated since the last time we rendered.
@@ -496,11 +874,104 @@ pub(crate) struct Window {
     idle_pipe: RawFd,
     active_text_field: Cell<Option<TextFieldToken>>,
     parent: Weak<Window>,
+    /// The windows built with this one as their parent, which are destroyed along with it.
+    owned: RefCell<Vec<Weak<Window>>>,
+    /// Kiosk windows ignore close requests from the window manager.
+    kiosk: bool,
+    /// The depth the window was created with, needed when uploading images to it.
+    depth: u8,
+    /// Offscreen windows are never mapped, so they get no input or expose events from the server.
+    offscreen: bool,
+    /// Whether `present_pixels` may draw to the window, which is otherwise left to the
+    /// application.
+    managed_surface: bool,
+    /// Whether the motion events which are queued together are delivered as one move.
+    coalesce_pointer_events: bool,
+    /// Popups which grab the pointer when mapped, and are dismissed by clicks outside of them.
+    popup_grab: bool,
+    /// The `_NET_WM_WINDOW_TYPE` the window was built with, restored when it stops being a
+    /// desktop widget.
+    window_type: xproto::Atom,
+    /// The name the window's placement is saved under when it's closed.
+    frame_autosave_name: Option<String>,
+    /// Whether the server has mapped the window, after which the window manager owns its
+    /// `_NET_WM_STATE`.
+    mapped: Cell<bool>,
+    stacking_level: Cell<StackingLevel>,
+    /// Whether the window is kept out of taskbars and pagers.
+    skip_taskbar: Cell<bool>,
+    /// The window's `_NET_WM_STATE`, as of the last time it changed.
+    net_wm_state: RefCell<Vec<xproto::Atom>>,
+    maximize_mode: Cell<window::MaximizeMode>,
+    /// Where the window goes back to, in pixels relative to the root window, while it is
+    /// maximized over the whole monitor.
+    full_monitor_restore: Cell<Option<Rect>>,
+    /// Set while the window manager is asked to restore a window it maximized, so that it can
+    /// be maximized over the whole monitor instead.
+    full_monitor_pending: Cell<bool>,
+    /// Set while this window is shown as a sheet.
+    sheet: RefCell<Option<Sheet>>,
+    /// Whether a sheet of this window is shown, which blocks its keyboard and pointer input.
+    has_sheet: Cell<bool>,
+    /// Whether `run_modal` is running a loop for this window, and how `end_modal` ended it.
+    modal_running: Cell<bool>,
+    modal_response: Cell<Option<ModalResponse>>,
+    /// The pixmap which offscreen windows are presented to, since an unmapped window keeps no
+    /// contents. It is recreated whenever the window is resized.
+    backing: Cell<Option<xproto::Pixmap>>,
+    /// The visibility last reported to the handler.
+    visibility: Cell<Visibility>,
+    frame_throttle: FrameThrottle,
+    /// Whether a redraw is already on the idle queue, which the other requests wait for.
+    paint_requests: PaintRequests,
+    /// When to paint an animation frame which was delayed by `frame_throttle`.
+    delayed_frame: Cell<Option<Instant>>,
+    /// The pixmaps which `present_pixels` uploads to, when the present extension is supported.
+    buffers: BufferPool,
+    /// When the last frame from `present_pixels` was presented, until it has been shown.
+    present_pending: Cell<Option<Instant>>,
+    /// Whether an animation frame was requested while `present_pending`, which is painted once
+    /// the pending frame has been shown.
+    frame_waiting: Cell<bool>,
+    /// Whether `present_pixels` waits for the vblank. Other ways of presenting don't
+    /// synchronize with the display yet.
+    vsync: Cell<bool>,
+    frame_timings: FrameTimingsCache,
+    /// The UST (in microseconds) and MSC of the last present `CompleteNotify`, which give us the
+    /// refresh interval.
+    last_msc: Cell<Option<(u64, u64)>>,
+    /// The basic and extended `_NET_WM_SYNC_REQUEST_COUNTER`s, if the server supports them.
+    sync_counters: Option<(sync::Counter, sync::Counter)>,
+    /// Whether the extended sync counter is still holding back the window's first frame.
+    first_frame_pending: Cell<bool>,
+    /// The value from the last `_NET_WM_SYNC_REQUEST`, which announces the next
+    /// `ConfigureNotify`, and whether it is for the extended counter.
+    sync_request: Cell<Option<(sync::Int64, bool)>>,
+    /// A sync request whose `ConfigureNotify` has arrived, which is answered once the window
+    /// has been painted at its new size.
+    sync_reply: Cell<Option<(sync::Int64, bool)>>,
+    /// Created once assistive technology is running, which the application watches for.
+    #[cfg(feature = "accesskit")]
+    accesskit_adapter: RefCell<Option<AccessKitAdapter>>,
+    /// Whether the window has the input focus, which a new adapter is told.
+    #[cfg(feature = "accesskit")]
+    focused: Cell<bool>,
+}
+
+#[cfg(feature = "accesskit")]
+struct AccessKitActionHandler {
+    idle_handle: IdleHandle,
 }
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct CustomCursor(xproto::Cursor);
 
+/// The state of a window which is shown as a sheet, set by `WindowHandle::begin_sheet`.
+struct Sheet {
+    parent: Weak<Window>,
+    completion: Box<dyn FnOnce(ModalResponse)>,
+}
+
 impl Window {
     #[track_caller]
     fn with_handler<T, F: FnOnce(&mut dyn WinHandler) -> T>(&self, f: F) -> Option<T> {
@@ -528,11 +999,13 @@ impl Window {
 
     fn connect(&self, handle: WindowHandle) -> Result<(), Error> {
         let size = self.size().size_dp();
-        let scale = self.scale.get();
+        let scale = self.surface_scale();
         self.with_handler(|h| {
             h.connect(&handle.into());
             h.scale(scale);
             h.size(size);
+            // We haven't been mapped yet.
+            h.visibility_changed(Visibility::Hidden);
         });
         Ok(())
     }
@@ -541,14 +1014,59 @@ impl Window {
     pub fn destroy(&self) {
         if !self.destroyed() {
             self.destroyed.set(true);
+            for owned in self.owned.take() {
+                if let Some(owned) = owned.upgrade() {
+                    owned.destroy();
+                }
+            }
+            if let Some(pixmap) = self.backing.take() {
+                log_x11!(self.app.connection().free_pixmap(pixmap));
+            }
+            self.buffers.free(self.app.connection());
+            if let Some((basic, extended)) = self.sync_counters {
+                log_x11!(self.app.connection().sync_destroy_counter(basic));
+                log_x11!(self.app.connection().sync_destroy_counter(extended));
+            }
             log_x11!(self.app.connection().destroy_window(self.id));
         }
     }
 
+    /// Replace the backing pixmap of an offscreen window with a black one of the given size.
+    fn create_backing(&self, size_px: Size) -> Result<(), Error> {
+        let conn = self.app.connection();
+        let (width, height) = (size_px.width as u16, size_px.height as u16);
+        let pixmap = conn.generate_id()?;
+        conn.create_pixmap(self.depth, pixmap, self.id, width.max(1), height.max(1))?
+            .check()
+            .context("create backing pixmap")?;
+        let gc = conn.generate_id()?;
+        conn.create_gc(gc, pixmap, &CreateGCAux::new().foreground(0))?;
+        conn.poly_fill_rectangle(
+            pixmap,
+            gc,
+            &[xproto::Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+        )?;
+        conn.free_gc(gc)?;
+        if let Some(old) = self.backing.replace(Some(pixmap)) {
+            conn.free_pixmap(old)?;
+        }
+        Ok(())
+    }
+
     fn destroyed(&self) -> bool {
         self.destroyed.get()
     }
 
+    /// Whether keyboard and pointer input is ignored, because of a sheet or a modal window.
+    fn input_blocked(&self) -> bool {
+        self.has_sheet.get() || self.app.modal_window().map_or(false, |id| id != self.id)
+    }
+
     fn size(&self) -> ScaledArea {
         self.area.get()
     }
@@ -565,13 +1083,67 @@ impl Window {
             }
         };
         if new_size {
+            if self.offscreen {
+                self.create_backing(size)?;
+            }
             self.add_invalid_rect(size.to_dp(scale).to_rect())?;
+            let surface_scale = self.surface_scale();
+            self.with_handler(|h| h.scale(surface_scale));
             self.with_handler(|h| h.size(size.to_dp(scale)));
-            self.with_handler(|h| h.scale(scale));
+            // By the time we get a ConfigureNotify, the X server has already resized the window.
+            let surface_size = self.surface_size();
+            self.with_handler(|h| h.surface_resized(surface_size));
         }
         Ok(())
     }
 
+    /// The scale the handler renders the surface at, which is the backing scale override if
+    /// there is one.
+    fn surface_scale(&self) -> Scale {
+        match self.backing_scale_override.get() {
+            Some(scale) => Scale::new(scale, scale),
+            None => self.scale.get(),
+        }
+    }
+
+    /// The size of the surface in pixels, at the surface scale.
+    fn surface_size(&self) -> Size {
+        match self.backing_scale_override.get() {
+            Some(scale) => (self.size().size_dp() * scale).round(),
+            None => self.size().size_px(),
+        }
+    }
+
+    fn set_backing_scale_override(&self, scale: Option<f64>) {
+        if self.destroyed() || self.backing_scale_override.replace(scale) == scale {
+            return;
+        }
+        let surface_scale = self.surface_scale();
+        let surface_size = self.surface_size();
+        self.with_handler(|h| h.scale(surface_scale));
+        self.with_handler(|h| h.surface_resized(surface_size));
+        self.invalidate();
+    }
+
+    /// Called when the scale of the desktop changes.
+    ///
+    /// The window keeps its size in display points, so it is resized to match. Until the
+    /// `ConfigureNotify` arrives, the handler sees its old size in pixels at the new scale.
+    pub(crate) fn set_scale(&self, scale: Scale) {
+        if self.destroyed() || self.scale.get() == scale {
+            return;
+        }
+        let size_dp = self.size().size_dp();
+        let size_px = self.size().size_px();
+        self.scale.set(scale);
+        self.area.set(ScaledArea::from_px(size_px, scale));
+        let surface_scale = self.surface_scale();
+        self.with_handler(|h| h.scale(surface_scale));
+        self.with_handler(|h| h.size(size_px.to_dp(scale)));
+        self.invalidate();
+        self.set_size(size_dp);
+    }
+
     fn render(&self) -> Result<(), Error> {
         self.with_handler(|h| h.prepare_paint());
 
@@ -580,27 +1152,121 @@ impl Window {
         }
 
         let invalid = std::mem::replace(&mut *borrow_mut!(self.invalid)?, Region::EMPTY);
+        self.frame_timings.paint_started();
         self.with_handler_and_dont_check_the_other_borrows(|handler| {
             handler.paint(&invalid);
         });
+        self.frame_throttle.frame_painted();
+        if self.app.present_supported() {
+            // Ask to be told about the next vblank, which is when what we just painted is shown.
+            log_x11!(self
+                .app
+                .connection()
+                .present_notify_msc(self.id, 0, 0, 1, 0));
+        }
+        // A frame from `present_pixels` is only drawn once the server has presented it.
+        if self.present_pending.get().is_none() {
+            self.first_frame_drawn();
+        }
+        self.answer_sync_request();
 
         Ok(())
     }
 
+    /// Tell the window manager that the last resize it synchronized with has been painted, so
+    /// that it can send the next.
+    fn answer_sync_request(&self) {
+        let (Some((value, extended)), Some((basic_counter, extended_counter))) =
+            (self.sync_reply.take(), self.sync_counters)
+        else {
+            return;
+        };
+        let counter = if extended {
+            // The value is past the first frame's, which is then no longer held back.
+            self.first_frame_pending.set(false);
+            extended_counter
+        } else {
+            basic_counter
+        };
+        log_x11!(self.app.connection().sync_set_counter(counter, value));
+    }
+
+    /// Let the compositor show the window, now that it has been painted.
+    fn first_frame_drawn(&self) {
+        if !self.first_frame_pending.replace(false) {
+            return;
+        }
+        if let Some((_, extended)) = self.sync_counters {
+            log_x11!(self
+                .app
+                .connection()
+                .sync_set_counter(extended, sync::Int64 { hi: 0, lo: 2 }));
+        }
+    }
+
     fn show(&self) {
-        if !self.destroyed() {
+        if self.destroyed() {
+            return;
+        }
+        if self.offscreen {
+            // There won't be any visibility or expose events, so make them up.
+            self.set_visibility(Visibility::Visible);
+            self.invalidate();
+        } else {
             log_x11!(self.app.connection().map_window(self.id));
         }
     }
 
+    fn hide(&self) {
+        if self.destroyed() {
+            return;
+        }
+        if self.offscreen {
+            self.set_visibility(Visibility::Hidden);
+        } else {
+            // The unmap notify will update the visibility.
+            log_x11!(self.app.connection().unmap_window(self.id));
+        }
+    }
+
     fn close(&self) {
         self.destroy();
     }
 
     /// Set whether the window should be resizable
     fn resizable(&self, resizable: bool) {
+        self.resizable.set(resizable);
+        self.update_size_hints();
+    }
+
+    fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        if ratio.is_some() {
+            self.resize_increments.set(None);
+        }
+        self.aspect_ratio.set(ratio);
+        self.update_size_hints();
+    }
+
+    fn set_resize_increments(&self, increments: Option<Size>) {
+        if increments.is_some() {
+            self.aspect_ratio.set(None);
+        }
+        self.resize_increments.set(increments);
+        self.update_size_hints();
+    }
+
+    fn update_size_hints(&self) {
         let conn = self.app.connection();
-        log_x11!(size_hints(resizable, self.size().size_px(), self.min_size)
+        let hints = size_hints(
+            self.resizable.get(),
+            self.size().size_px(),
+            self.min_size,
+            self.aspect_ratio.get(),
+            self.resize_increments
+                .get()
+                .map(|size| size.to_px(self.scale.get())),
+        );
+        log_x11!(hints
             .set_normal_hints(conn, self.id)
             .context("set normal hints"));
     }
@@ -610,6 +1276,449 @@ impl Window {
         warn!("Window::show_titlebar is currently unimplemented for X11 backend.");
     }
 
+    fn set_window_level(&self, level: StackingLevel) {
+        if self.destroyed() {
+            return;
+        }
+        let atoms = self.app.atoms();
+
+        // Window managers generally only look at the window type when a window is mapped, so
+        // this takes effect the next time the window is shown.
+        let window_type = match level {
+            StackingLevel::DesktopWidget => atoms._NET_WM_WINDOW_TYPE_DESKTOP,
+            _ => self.window_type,
+        };
+        log_x11!(self.app.connection().change_property32(
+            PropMode::REPLACE,
+            self.id,
+            atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[window_type],
+        ));
+
+        self.stacking_level.set(level);
+        self.update_wm_state();
+    }
+
+    fn set_skip_taskbar(&self, skip: bool) {
+        if self.destroyed() {
+            return;
+        }
+        self.skip_taskbar.set(skip);
+        self.update_wm_state();
+    }
+
+    fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        if self.destroyed() {
+            return;
+        }
+        // Mutter and other GTK based window managers draw the decorations to match.
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        let variant = match theme {
+            Some(Theme::Light) => "light",
+            Some(Theme::Dark) => "dark",
+            None => {
+                log_x11!(conn.delete_property(self.id, atoms._GTK_THEME_VARIANT));
+                return;
+            }
+        };
+        log_x11!(conn.change_property8(
+            PropMode::REPLACE,
+            self.id,
+            atoms._GTK_THEME_VARIANT,
+            atoms.UTF8_STRING,
+            variant.as_bytes(),
+        ));
+    }
+
+    /// Show `sheet_window` as a modal dialog, centered over this window.
+    fn begin_sheet(&self, sheet_window: &Rc<Window>, sheet: Sheet) {
+        if self.destroyed() || sheet_window.destroyed() {
+            return;
+        }
+        // Window managers keep transient windows above their parent, and modal ones focused.
+        log_x11!(self.app.connection().change_property32(
+            PropMode::REPLACE,
+            sheet_window.id,
+            AtomEnum::WM_TRANSIENT_FOR,
+            AtomEnum::WINDOW,
+            &[self.id],
+        ));
+        *sheet_window.sheet.borrow_mut() = Some(sheet);
+        // Like the windows built with this one as their owner, the sheet is destroyed first.
+        let weak = Rc::downgrade(sheet_window);
+        let mut owned = self.owned.borrow_mut();
+        if !owned.iter().any(|w| w.ptr_eq(&weak)) {
+            owned.push(weak);
+        }
+        drop(owned);
+        sheet_window.update_wm_state();
+        let offset = (self.size().size_dp() - sheet_window.size().size_dp()) / 2.0;
+        sheet_window.set_position(self.get_position() + offset.to_vec2());
+        self.has_sheet.set(true);
+        sheet_window.show();
+    }
+
+    fn run_modal(&self) -> ModalResponse {
+        if self.destroyed() {
+            return ModalResponse::Cancel;
+        }
+        if self.modal_running.replace(true) {
+            warn!("Window {} is already running a modal loop", self.id);
+            return ModalResponse::Cancel;
+        }
+        // Rather than grabbing the pointer and keyboard, which would also stop the window
+        // manager from moving the window, our other windows ignore input.
+        let previous = self.app.set_modal_window(Some(self.id));
+        self.show();
+        while self.modal_response.get().is_none() && !self.destroyed() {
+            if self.app.run_loop_iteration(LoopMode::Wait) == LoopStatus::Exit {
+                break;
+            }
+        }
+        self.app.set_modal_window(previous);
+        self.modal_running.set(false);
+        self.modal_response.take().unwrap_or(ModalResponse::Cancel)
+    }
+
+    fn end_modal(&self, response: ModalResponse) {
+        if self.modal_running.get() {
+            self.modal_response.set(Some(response));
+        }
+    }
+
+    fn focus_next_window(&self) {
+        if self.destroyed() {
+            return;
+        }
+        let windows = match self.app.windows() {
+            Ok(windows) => windows,
+            Err(e) => {
+                error!("failed to find the next window: {}", e);
+                return;
+            }
+        };
+        // The windows which follow this one, and then the ones before it.
+        let index = windows.iter().position(|w| w.id == self.id).unwrap_or(0);
+        let (before, after) = windows.split_at(index);
+        let next = after
+            .iter()
+            .skip(1)
+            .chain(before)
+            .find(|w| w.mapped.get() && !w.destroyed() && !w.input_blocked());
+        if let Some(next) = next {
+            next.bring_to_front_and_focus();
+        }
+    }
+
+    fn end_sheet(&self, response: ModalResponse) {
+        let Some(sheet) = self.sheet.take() else {
+            return;
+        };
+        if let Some(parent) = sheet.parent.upgrade() {
+            parent.has_sheet.set(false);
+        }
+        if !self.destroyed() {
+            self.hide();
+            self.update_wm_state();
+        }
+        (sheet.completion)(response);
+    }
+
+    /// The `_NET_WM_STATE` atoms we manage, and whether each should be set.
+    fn wm_state(&self) -> [(xproto::Atom, bool); 7] {
+        let atoms = self.app.atoms();
+        let level = self.stacking_level.get();
+        let skip_taskbar = self.skip_taskbar.get();
+        [
+            (
+                atoms._NET_WM_STATE_FULLSCREEN,
+                self.kiosk || self.is_fullscreen(),
+            ),
+            (
+                atoms._NET_WM_STATE_ABOVE,
+                self.kiosk || level == StackingLevel::AlwaysOnTop,
+            ),
+            (
+                atoms._NET_WM_STATE_BELOW,
+                matches!(
+                    level,
+                    StackingLevel::AlwaysOnBottom | StackingLevel::DesktopWidget
+                ),
+            ),
+            (
+                atoms._NET_WM_STATE_STICKY,
+                level == StackingLevel::DesktopWidget,
+            ),
+            (atoms._NET_WM_STATE_SKIP_TASKBAR, skip_taskbar),
+            (atoms._NET_WM_STATE_SKIP_PAGER, skip_taskbar),
+            (atoms._NET_WM_STATE_MODAL, self.sheet.borrow().is_some()),
+        ]
+    }
+
+    /// Whether the window is fullscreen, which the user can make it through the window manager.
+    fn is_fullscreen(&self) -> bool {
+        let fullscreen = self.app.atoms()._NET_WM_STATE_FULLSCREEN;
+        self.net_wm_state.borrow().contains(&fullscreen)
+    }
+
+    /// Whether the window manager has maximized the window, which it does to the work area.
+    fn is_wm_maximized(&self) -> bool {
+        let state = self.net_wm_state.borrow();
+        maximized_atoms(self.app.atoms())
+            .iter()
+            .all(|atom| state.contains(atom))
+    }
+
+    pub fn handle_net_wm_state_changed(&self) -> Result<(), Error> {
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        let reply = conn
+            .get_property(
+                false,
+                self.id,
+                atoms._NET_WM_STATE,
+                AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+        *borrow_mut!(self.net_wm_state)? = reply.value32().into_iter().flatten().collect();
+
+        let wm_maximized = self.is_wm_maximized();
+        if self.full_monitor_pending.get() && !wm_maximized {
+            self.full_monitor_pending.set(false);
+            self.maximize_over_monitor()?;
+        } else if wm_maximized && self.maximize_mode.get() == window::MaximizeMode::FullMonitor {
+            if self.full_monitor_restore.get().is_some() {
+                // It is already maximized as far as we're concerned, so maximize works as
+                // restore does on the window manager's own maximized windows.
+                self.set_wm_maximized(false);
+                self.restore_from_full_monitor();
+            } else {
+                // The window manager only maximizes to the work area, and ignores requests to
+                // resize maximized windows, so it is asked to restore the window first.
+                self.full_monitor_pending.set(true);
+                self.set_wm_maximized(false);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_wm_maximized(&self, maximized: bool) {
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        let [vert, horz] = maximized_atoms(atoms);
+        let root = conn.setup().roots[self.app.screen_num()].root;
+        let event = xproto::ClientMessageEvent::new(
+            32,
+            self.id,
+            atoms._NET_WM_STATE,
+            [u32::from(maximized), vert, horz, 1, 0],
+        );
+        log_x11!(conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        ));
+    }
+
+    /// The window's drawing area, in pixels relative to the root window.
+    fn root_rect(&self) -> Result<Rect, Error> {
+        let conn = self.app.connection();
+        let geom = conn.get_geometry(self.id)?.reply()?;
+        let origin = conn
+            .translate_coordinates(self.id, geom.root, 0, 0)?
+            .reply()?;
+        Ok(Rect::from_origin_size(
+            (origin.dst_x as f64, origin.dst_y as f64),
+            (geom.width as f64, geom.height as f64),
+        ))
+    }
+
+    /// The size of the decorations the window manager puts around the window.
+    fn frame_extents(&self) -> Insets {
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        let reply = conn
+            .get_property(
+                false,
+                self.id,
+                atoms._NET_FRAME_EXTENTS,
+                AtomEnum::CARDINAL,
+                0,
+                4,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        let extents: Vec<u32> = reply
+            .and_then(|reply| Some(reply.value32()?.collect()))
+            .unwrap_or_default();
+        match extents[..] {
+            [left, right, top, bottom] => {
+                Insets::new(left.into(), top.into(), right.into(), bottom.into())
+            }
+            _ => Insets::ZERO,
+        }
+    }
+
+    /// Move the window, with its decorations, so that its drawing area is `rect`, which is in
+    /// pixels relative to the root window.
+    fn configure_to(&self, rect: Rect) {
+        let conn = self.app.connection();
+        // Windows are moved by the top left corner of their decorations
+        let frame = self.frame_extents();
+        log_x11!(conn.configure_window(
+            self.id,
+            &ConfigureWindowAux::new()
+                .x((rect.x0 - frame.x0).round() as i32)
+                .y((rect.y0 - frame.y0).round() as i32)
+                .width(rect.width().round() as u32)
+                .height(rect.height().round() as u32),
+        ));
+    }
+
+    /// Put the window over the whole monitor it's on, remembering where it was.
+    fn maximize_over_monitor(&self) -> Result<(), Error> {
+        let rect = self.root_rect()?;
+        let monitors = super::screen::get_monitors(&self.app);
+        let Some(monitor) = monitors
+            .iter()
+            .find(|m| m.virtual_rect().contains(rect.center()))
+            .or_else(|| monitors.iter().find(|m| m.is_primary()))
+        else {
+            return Ok(());
+        };
+        let monitor = monitor.virtual_rect();
+        let frame = self.frame_extents();
+        self.full_monitor_restore.set(Some(rect));
+        self.configure_to(Rect::new(
+            monitor.x0 + frame.x0,
+            monitor.y0 + frame.y0,
+            monitor.x1 - frame.x1,
+            monitor.y1 - frame.y1,
+        ));
+        Ok(())
+    }
+
+    fn restore_from_full_monitor(&self) {
+        if let Some(rect) = self.full_monitor_restore.take() {
+            self.configure_to(rect);
+        }
+    }
+
+    fn get_window_state(&self) -> window::WindowState {
+        let hidden = self.app.atoms()._NET_WM_STATE_HIDDEN;
+        if self.net_wm_state.borrow().contains(&hidden) {
+            window::WindowState::Minimized
+        } else if self.full_monitor_restore.get().is_some() || self.is_wm_maximized() {
+            window::WindowState::Maximized
+        } else {
+            window::WindowState::Restored
+        }
+    }
+
+    fn set_window_state(&self, state: window::WindowState) {
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        match state {
+            window::WindowState::Maximized => match self.maximize_mode.get() {
+                window::MaximizeMode::WorkArea => {
+                    self.restore_from_full_monitor();
+                    self.set_wm_maximized(true);
+                }
+                window::MaximizeMode::FullMonitor => {
+                    if self.full_monitor_restore.get().is_some() {
+                        return;
+                    }
+                    if self.is_wm_maximized() {
+                        self.full_monitor_pending.set(true);
+                        self.set_wm_maximized(false);
+                    } else {
+                        log_x11!(self.maximize_over_monitor());
+                    }
+                }
+            },
+            window::WindowState::Minimized => {
+                // ICCCM 4.1.4, with the `IconicState` of `WM_STATE`.
+                const ICONIC_STATE: u32 = 3;
+                let root = conn.setup().roots[self.app.screen_num()].root;
+                let event = xproto::ClientMessageEvent::new(
+                    32,
+                    self.id,
+                    atoms.WM_CHANGE_STATE,
+                    [ICONIC_STATE, 0, 0, 0, 0],
+                );
+                log_x11!(conn.send_event(
+                    false,
+                    root,
+                    EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                    event,
+                ));
+            }
+            window::WindowState::Restored => {
+                if self.get_window_state() == window::WindowState::Minimized {
+                    // Mapping an iconified window restores it.
+                    log_x11!(conn.map_window(self.id));
+                }
+                self.restore_from_full_monitor();
+                self.set_wm_maximized(false);
+            }
+        }
+    }
+
+    fn update_wm_state(&self) {
+        let conn = self.app.connection();
+        let atoms = self.app.atoms();
+        if !self.mapped.get() {
+            // Before the window is mapped, its state is set directly, keeping the atoms we
+            // don't manage here, such as those which maximize it.
+            let wm_state = self.wm_state();
+            let mut state: Vec<_> = self
+                .net_wm_state
+                .borrow()
+                .iter()
+                .copied()
+                .filter(|atom| wm_state.iter().all(|(managed, _)| managed != atom))
+                .collect();
+            state.extend(
+                wm_state
+                    .iter()
+                    .filter_map(|&(atom, set)| set.then_some(atom)),
+            );
+            self.net_wm_state.replace(state.clone());
+            log_x11!(conn.change_property32(
+                PropMode::REPLACE,
+                self.id,
+                atoms._NET_WM_STATE,
+                AtomEnum::ATOM,
+                &state,
+            ));
+            return;
+        }
+
+        // Afterwards, the window manager has to be asked to change it.
+        let root = conn.setup().roots[self.app.screen_num()].root;
+        for (atom, set) in self.wm_state() {
+            // The last two fields are the second property to change, and that the request
+            // comes from a normal application.
+            let event = xproto::ClientMessageEvent::new(
+                32,
+                self.id,
+                atoms._NET_WM_STATE,
+                [u32::from(set), atom, 0, 1, 0],
+            );
+            log_x11!(conn.send_event(
+                false,
+                root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            ));
+        }
+    }
+
     fn parent_origin(&self) -> Vec2 {
         self.parent
             .upgrade()
@@ -654,6 +1763,12 @@ impl Window {
                 .width(size.width as u32)
                 .height(size.height as u32),
         ));
+        if self.offscreen {
+            // Unmapped windows are resized straight away, without waiting for the window manager,
+            // so there is no need to wait for the ConfigureNotify.
+            log_x11!(self.size_changed(size));
+            self.request_anim_frame();
+        }
     }
 
     /// Bring this window to the front of the window stack and give it focus.
@@ -693,6 +1808,27 @@ impl Window {
     /// Schedule a redraw on the idle loop, or if we are waiting on present then schedule it for
     /// when the current present finishes.
     fn request_anim_frame(&self) {
+        if let Some(presented) = self.present_pending.get() {
+            if presented.elapsed() < PRESENT_TIMEOUT {
+                // Painting before the last frame is shown would only produce a frame which is
+                // never seen, so animations run at the refresh rate.
+                self.frame_waiting.set(true);
+                return;
+            }
+            self.present_pending.set(None);
+        }
+        match self.frame_throttle.schedule() {
+            FrameSchedule::Now => (),
+            FrameSchedule::After(delay) => {
+                // The event loop wakes up for this along with our timers.
+                self.delayed_frame.set(Some(Instant::now() + delay));
+                return;
+            }
+            FrameSchedule::Scheduled => return,
+        }
+        if !self.paint_requests.request() {
+            return;
+        }
         let idle = IdleHandle {
             queue: Arc::clone(&self.idle_queue),
             pipe: self.idle_pipe,
@@ -705,15 +1841,113 @@ impl Window {
         self.add_invalid_rect(rect)
             .unwrap_or_else(|err| error!("Window::invalidate - failed to invalidate: {}", err));
 
-        self.request_anim_frame();
+        self.request_anim_frame();
+    }
+
+    fn invalidate_rect(&self, rect: Rect) {
+        if let Err(err) = self.add_invalid_rect(rect) {
+            error!("Window::invalidate_rect - failed to enlarge rect: {}", err);
+        }
+
+        self.request_anim_frame();
+    }
+
+    fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), Error> {
+        if !self.managed_surface {
+            return Err(anyhow!(
+                "present_pixels is not supported on windows without a managed surface"
+            ));
+        }
+        let size_px = self.size().size_px();
+        if (width as f64, height as f64) != (size_px.width, size_px.height) {
+            return Err(anyhow!(
+                "pixel buffer is {}x{}, but the window is {}x{}",
+                width,
+                height,
+                size_px.width,
+                size_px.height
+            ));
+        }
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let conn = self.app.connection();
+        let msb_first = conn.setup().image_byte_order == X11ImageOrder::MSB_FIRST;
+        // ZPixmap images of depth 24 and 32 are stored as 32 bit pixels in the server's byte
+        // order, which for LSBFirst is BGRX.
+        let mut pixels = format.to_bgra(buf).into_owned();
+        for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+            // Presented pixels are always opaque, which matters for 32 bit (transparent) windows.
+            px[3] = 0xff;
+            if msb_first {
+                px.reverse();
+            }
+        }
+
+        if let Some(pixmap) = self.backing.get() {
+            buffers::put_image(conn, pixmap, self.depth, width, &pixels)?;
+        } else if self.app.present_supported() {
+            // A frame which waits for a buffer is still pending, as it will be shown later.
+            self.buffers
+                .present(conn, width as u16, height as u16, pixels, self.vsync.get())?;
+            self.present_pending.set(Some(Instant::now()));
+        } else {
+            buffers::put_image(conn, self.id, self.depth, width, &pixels)?;
+        }
+        conn.flush()?;
+        Ok(())
+    }
+
+    fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, ShellError> {
+        let pixmap = self.backing.get().ok_or(ShellError::Unsupported(
+            "read_back_pixels on windows that aren't offscreen",
+        ))?;
+        let size_px = self.size().size_px();
+        let pixels = self
+            .get_pixels(pixmap, size_px)
+            .context("read back the backing pixmap")?;
+        Ok(format.from_bgra(pixels))
+    }
+
+    fn capture(&self) -> Result<ImageBuf, Error> {
+        // Offscreen windows are never mapped, so their contents are only in the pixmap.
+        let drawable = self.backing.get().unwrap_or(self.id);
+        let size_px = self.size().size_px();
+        let pixels = self
+            .get_pixels(drawable, size_px)
+            .context("capture the window")?;
+        Ok(ImageBuf::new(
+            size_px.width as u32,
+            size_px.height as u32,
+            PixelFormat::Rgba8.from_bgra(pixels),
+        )
+        .with_scale(self.scale.get()))
     }
 
-    fn invalidate_rect(&self, rect: Rect) {
-        if let Err(err) = self.add_invalid_rect(rect) {
-            error!("Window::invalidate_rect - failed to enlarge rect: {}", err);
+    /// Read the pixels of `drawable`, which must be `size_px` large, in the BGRA layout.
+    fn get_pixels(&self, drawable: Drawable, size_px: Size) -> Result<Vec<u8>, Error> {
+        let (width, height) = (size_px.width as u16, size_px.height as u16);
+        let conn = self.app.connection();
+        let image = conn
+            .get_image(ImageFormat::Z_PIXMAP, drawable, 0, 0, width, height, !0)?
+            .reply()?;
+        let msb_first = conn.setup().image_byte_order == X11ImageOrder::MSB_FIRST;
+        let mut pixels = image.data;
+        // This undoes the conversion in `present_pixels`.
+        for px in pixels.chunks_exact_mut(PixelFormat::BYTES_PER_PIXEL) {
+            if msb_first {
+                px.reverse();
+            }
+            px[3] = 0xff;
         }
-
-        self.request_anim_frame();
+        Ok(pixels)
     }
 
     fn set_title(&self, title: &str) {
@@ -793,6 +2027,9 @@ impl Window {
     }
 
     pub fn handle_key_event(&self, event: KeyEvent) {
+        if self.input_blocked() {
+            return;
+        }
         self.with_handler(|h| match event.state {
             KeyState::Down => {
                 simulate_input(h, self.active_text_field.get(), event);
@@ -809,10 +2046,6 @@ impl Window {
         detail: u32,
         src_id: u16,
     ) -> PointerEvent {
-        // In x11rb, xinput x and y coordinates are i32's but in the protocol they're fixed-precision FP1616s
-        // https://github.com/psychon/x11rb/blob/dacfba5e2a8eef4b80df75d9bec9061c3d98d279/xcb-proto-1.15.2/src/xinput.xml#L2374
-        let (ev_x, ev_y) = (x as f64 / 65536.0, y as f64 / 65536.0);
-        let scale = self.scale.get();
         let mods = mods.base | mods.locked | mods.latched;
         // TODO: what are the high 16 bits for? Maybe virtual modifiers?
         let mods = (mods as u16).into();
@@ -824,15 +2057,23 @@ impl Window {
             pointer_type: PointerType::Mouse(MouseInfo {
                 wheel_delta: Default::default(),
             }),
-            pos: Point::new(ev_x, ev_y).to_dp(scale),
+            pos: self.pointer_pos(x, y),
             buttons: pointer_buttons(mods),
             modifiers: key_mods(mods),
             button,
             focus: false,
             count: 0,
+            coalesced: Vec::new(),
         }
     }
 
+    /// The position of a pointer event, in display points.
+    fn pointer_pos(&self, x: i32, y: i32) -> Point {
+        // In x11rb, xinput x and y coordinates are i32's but in the protocol they're fixed-precision FP1616s
+        // https://github.com/psychon/x11rb/blob/dacfba5e2a8eef4b80df75d9bec9061c3d98d279/xcb-proto-1.15.2/src/xinput.xml#L2374
+        Point::new(x as f64 / 65536.0, y as f64 / 65536.0).to_dp(self.scale.get())
+    }
+
     fn pointer_touch_event(&self, ev: &xinput::TouchBeginEvent) -> PointerEvent {
         // TODO: I think future x11rb will have BitAnd?
         let is_primary = (ev.flags | TouchEventFlags::TOUCH_EMULATING_POINTER) == ev.flags;
@@ -919,6 +2160,9 @@ impl Window {
     }
 
     pub fn handle_button_press(&self, ev: &xinput::ButtonPressEvent) -> Result<(), Error> {
+        if self.input_blocked() {
+            return Ok(());
+        }
         let mut pointer_ev = self.pointer_event(ev);
         // The xcb state field doesn't include the newly pressed button, but
         // druid wants it to be included.
@@ -930,6 +2174,9 @@ impl Window {
     }
 
     pub fn handle_button_release(&self, ev: &xinput::ButtonPressEvent) -> Result<(), Error> {
+        if self.input_blocked() {
+            return Ok(());
+        }
         let mut pointer_ev = self.pointer_event(ev);
         // The xcb state includes the newly released button, but druid
         // doesn't want it.
@@ -939,6 +2186,9 @@ impl Window {
     }
 
     pub fn handle_touch_begin(&self, ev: &xinput::TouchBeginEvent) -> Result<(), Error> {
+        if self.input_blocked() {
+            return Ok(());
+        }
         let mut pointer_ev = self.pointer_touch_event(ev);
         pointer_ev.buttons = pointer_ev.buttons.with(pointer_ev.button);
         self.with_handler(|h| h.pointer_down(&pointer_ev));
@@ -946,12 +2196,18 @@ impl Window {
     }
 
     pub fn handle_touch_update(&self, ev: &xinput::TouchBeginEvent) -> Result<(), Error> {
+        if self.input_blocked() {
+            return Ok(());
+        }
         let pointer_ev = self.pointer_touch_event(ev);
         self.with_handler(|h| h.pointer_move(&pointer_ev));
         Ok(())
     }
 
     pub fn handle_touch_end(&self, ev: &xinput::TouchBeginEvent) -> Result<(), Error> {
+        if self.input_blocked() {
+            return Ok(());
+        }
         let mut pointer_ev = self.pointer_touch_event(ev);
         pointer_ev.buttons = pointer_ev.buttons.without(pointer_ev.button);
         self.with_handler(|h| h.pointer_move(&pointer_ev));
@@ -959,35 +2215,68 @@ impl Window {
     }
 
     pub fn handle_wheel(&self, ev: &xinput::ButtonPressEvent) -> Result<(), Error> {
+        let steps = match ev.detail {
+            4 => Vec2::new(0.0, -1.0),
+            5 => Vec2::new(0.0, 1.0),
+            6 => Vec2::new(-1.0, 0.0),
+            7 => Vec2::new(1.0, 0.0),
+            _ => return Err(anyhow!("unexpected mouse wheel button: {}", ev.detail)),
+        };
+        self.scroll(ev, steps);
+        Ok(())
+    }
+
+    /// Deliver a scroll of `steps` steps of a mouse wheel, which may be fractional.
+    fn scroll(&self, ev: &xinput::ButtonPressEvent, steps: Vec2) {
+        if self.input_blocked() {
+            return;
+        }
         let mut pointer_ev = self.pointer_event(ev);
 
         // We use a delta of 120 per tick to match the behavior of Windows.
-        let is_shift = pointer_ev.modifiers.shift();
-        let delta = match ev.detail {
-            4 if is_shift => (-120.0, 0.0),
-            4 => (0.0, -120.0),
-            5 if is_shift => (120.0, 0.0),
-            5 => (0.0, 120.0),
-            6 => (-120.0, 0.0),
-            7 => (120.0, 0.0),
-            _ => return Err(anyhow!("unexpected mouse wheel button: {}", ev.detail)),
-        };
-        pointer_ev.pointer_type = PointerType::Mouse(MouseInfo {
-            wheel_delta: delta.into(),
-        });
+        let mut delta = steps * 120.0;
+        // Shift turns a vertical wheel into a horizontal one
+        if pointer_ev.modifiers.shift() && delta.x == 0.0 {
+            delta = Vec2::new(delta.y, 0.0);
+        }
+        pointer_ev.pointer_type = PointerType::Mouse(MouseInfo { wheel_delta: delta });
         pointer_ev.button = PointerButton::None;
 
         self.with_handler(|h| h.wheel(&pointer_ev));
-        Ok(())
     }
 
-    pub fn handle_motion_notify(&self, ev: &xinput::ButtonPressEvent) -> Result<(), Error> {
+    /// Deliver a motion event, along with the ones which were merged into it, oldest first.
+    ///
+    /// Smooth scrolling is reported by moving the scroll valuators, so these can also be
+    /// scroll events.
+    pub fn handle_motion_notify(
+        &self,
+        ev: &xinput::ButtonPressEvent,
+        skipped: &[xinput::ButtonPressEvent],
+    ) -> Result<(), Error> {
+        let events = || skipped.iter().chain(Some(ev));
+        let steps = events().fold(Vec2::ZERO, |steps, ev| steps + self.app.scroll_delta(ev));
+        if steps != Vec2::ZERO {
+            self.scroll(ev, steps);
+            if !events().any(|ev| super::pointer::moves_pointer(&ev.valuator_mask)) {
+                return Ok(());
+            }
+        }
+
         let mut pointer_ev = self.pointer_event(ev);
         pointer_ev.button = PointerButton::None;
+        pointer_ev.coalesced = skipped
+            .iter()
+            .map(|ev| self.pointer_pos(ev.event_x, ev.event_y))
+            .collect();
         self.with_handler(|h| h.pointer_move(&pointer_ev));
         Ok(())
     }
 
+    pub fn coalesces_pointer_events(&self) -> bool {
+        self.coalesce_pointer_events
+    }
+
     pub fn handle_leave_notify(
         &self,
         _leave_notify: &xproto::LeaveNotifyEvent,
@@ -997,10 +2286,14 @@ impl Window {
     }
 
     pub fn handle_got_focus(&self) {
+        #[cfg(feature = "accesskit")]
+        self.accesskit_focus_changed(true);
         self.with_handler(|h| h.got_focus());
     }
 
     pub fn handle_lost_focus(&self) {
+        #[cfg(feature = "accesskit")]
+        self.accesskit_focus_changed(false);
         self.with_handler(|h| h.lost_focus());
     }
 
@@ -1010,19 +2303,227 @@ impl Window {
         let atoms = self.app.atoms();
         if client_message.type_ == atoms.WM_PROTOCOLS && client_message.format == 32 {
             let protocol = client_message.data.as_data32()[0];
-            if protocol == atoms.WM_DELETE_WINDOW {
+            if protocol == atoms.WM_DELETE_WINDOW && !self.kiosk {
                 self.with_handler(|h| h.request_close());
+            } else if protocol == atoms._NET_WM_PING {
+                self.pong(client_message);
+            } else if protocol == atoms._NET_WM_SYNC_REQUEST {
+                let data = client_message.data.as_data32();
+                let value = sync::Int64 {
+                    hi: data[3] as i32,
+                    lo: data[2],
+                };
+                self.sync_request.set(Some((value, data[4] != 0)));
             }
         }
     }
 
+    /// Answer the window manager's `_NET_WM_PING`, by sending it back to the root window.
+    ///
+    /// This happens as soon as the event loop gets to the ping, so it only goes unanswered
+    /// while a handler is blocking the main thread, which is what the ping is there to detect.
+    fn pong(&self, ping: &xproto::ClientMessageEvent) {
+        let conn = self.app.connection();
+        let root = conn.setup().roots[self.app.screen_num()].root;
+        let mut pong = *ping;
+        pong.window = root;
+        log_x11!(conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            pong,
+        ));
+    }
+
+    /// Called once the event loop has stopped without the window being destroyed. Nothing
+    /// can be sent to the X server for it any more.
+    pub(crate) fn connection_lost(&self) {
+        self.destroyed.set(true);
+        self.with_handler(|h| h.destroy());
+    }
+
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub fn handle_destroy_notify(&self, _destroy_notify: &xproto::DestroyNotifyEvent) {
+        self.end_sheet(ModalResponse::Cancel);
         self.with_handler(|h| h.destroy());
     }
 
     pub fn handle_configure_notify(&self, event: &ConfigureNotifyEvent) -> Result<(), Error> {
-        self.size_changed(Size::new(event.width as f64, event.height as f64))
+        self.size_changed(Size::new(event.width as f64, event.height as f64))?;
+        if let Some(request) = self.sync_request.take() {
+            self.sync_reply.set(Some(request));
+            // Nothing will be painted if the size didn't change, so answer now.
+            if borrow!(self.invalid)?.is_empty() {
+                self.answer_sync_request();
+            }
+        }
+        #[cfg(feature = "accesskit")]
+        self.update_accesskit_bounds();
+        Ok(())
+    }
+
+    /// Create the window's AccessKit adapter, if assistive technology is running and it
+    /// doesn't have one yet.
+    #[cfg(feature = "accesskit")]
+    pub(crate) fn init_accesskit(&self) {
+        if self.destroyed()
+            || !self.app.accessibility_enabled()
+            || borrow!(self.accesskit_adapter).map_or(true, |a| a.is_some())
+        {
+            return;
+        }
+        let idle_handle = IdleHandle {
+            queue: Arc::clone(&self.idle_queue),
+            pipe: self.idle_pipe,
+        };
+        let adapter = linux::accesskit::new_adapter(
+            || self.with_handler(|h| h.accesskit_tree()),
+            Box::new(AccessKitActionHandler { idle_handle }),
+        );
+        if let Some(adapter) = adapter {
+            adapter.update_window_focus_state(self.focused.get());
+            if let Ok(mut a) = borrow_mut!(self.accesskit_adapter) {
+                *a = Some(adapter);
+            }
+            self.update_accesskit_bounds();
+        }
+    }
+
+    /// Tell AT-SPI whether the window is focused, which is when it is the active window.
+    #[cfg(feature = "accesskit")]
+    fn accesskit_focus_changed(&self, focused: bool) {
+        self.focused.set(focused);
+        let Ok(adapter) = borrow!(self.accesskit_adapter) else {
+            return;
+        };
+        match adapter.as_ref() {
+            Some(adapter) => adapter.update_window_focus_state(focused),
+            None => {
+                drop(adapter);
+                // The application gives every window an adapter once assistive technology
+                // starts, but it may not have gotten to this one yet.
+                if focused {
+                    self.init_accesskit();
+                }
+            }
+        }
+    }
+
+    /// Tell AT-SPI where the window is on the screen, in pixels.
+    #[cfg(feature = "accesskit")]
+    fn update_accesskit_bounds(&self) {
+        let Ok(adapter) = borrow!(self.accesskit_adapter) else {
+            return;
+        };
+        let Some(adapter) = adapter.as_ref() else {
+            return;
+        };
+        let origin = (self.get_position() + self.parent_origin()).to_px(self.scale.get());
+        let size = self.size().size_px();
+        // We don't know the size of the window manager's decorations.
+        let bounds = accesskit::Rect {
+            x0: origin.x,
+            y0: origin.y,
+            x1: origin.x + size.width,
+            y1: origin.y + size.height,
+        };
+        adapter.set_root_window_bounds(bounds, bounds);
+    }
+
+    pub fn handle_visibility_notify(&self, event: &xproto::VisibilityNotifyEvent) {
+        // Compositing window managers redirect our window offscreen, in which case the X server
+        // always reports it as unobscured.
+        if event.state == xproto::Visibility::FULLY_OBSCURED {
+            self.set_visibility(Visibility::Occluded);
+        } else {
+            self.set_visibility(Visibility::Visible);
+        }
+    }
+
+    pub fn handle_present_complete_notify(&self, event: &present::CompleteNotifyEvent) {
+        if event.kind == present::CompleteKind::PIXMAP {
+            // A frame from `present_pixels` was shown, or skipped.
+            self.present_pending.set(None);
+            self.first_frame_drawn();
+            if self.frame_waiting.replace(false) {
+                self.request_anim_frame();
+            }
+            return;
+        }
+        if event.kind != present::CompleteKind::NOTIFY_MSC {
+            return;
+        }
+        let refresh = match self.last_msc.replace(Some((event.ust, event.msc))) {
+            Some((ust, msc)) if event.msc > msc && event.ust > ust => {
+                Some(Duration::from_micros((event.ust - ust) / (event.msc - msc)))
+            }
+            _ => None,
+        };
+        let Some(vsync) = ust_to_instant(event.ust) else {
+            return;
+        };
+        self.frame_timings.frame_presented(vsync, refresh);
+        for info in self.frame_timings.take_feedback() {
+            self.with_handler(|h| h.frame_presented(info));
+        }
+    }
+
+    pub fn handle_present_idle_notify(&self, event: &present::IdleNotifyEvent) {
+        let conn = self.app.connection();
+        match self.buffers.handle_idle_notify(conn, event) {
+            Ok(true) => {
+                self.present_pending.set(Some(Instant::now()));
+                log_x11!(conn.flush());
+            }
+            Ok(false) => (),
+            Err(e) => error!("failed to present a frame which was waiting for a buffer: {e:#}"),
+        }
+    }
+
+    pub fn handle_map_notify(&self, _map_notify: &xproto::MapNotifyEvent) {
+        self.mapped.set(true);
+        self.app.complete_startup();
+        if self.popup_grab {
+            // With `owner_events`, our other windows get their events as usual, and only
+            // clicks elsewhere are reported to us.
+            log_x11!(self.app.connection().grab_pointer(
+                true,
+                self.id,
+                EventMask::BUTTON_PRESS,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                x11rb::NONE,
+                x11rb::NONE,
+                x11rb::CURRENT_TIME,
+            ));
+        }
+    }
+
+    /// Handles a core button press, which we only get for clicks outside of our windows while a
+    /// popup grabs the pointer.
+    pub fn handle_grabbed_button_press(&self, event: &xproto::ButtonPressEvent) {
+        if !self.popup_grab {
+            return;
+        }
+        let size = self.size().size_px();
+        let (x, y) = (f64::from(event.event_x), f64::from(event.event_y));
+        if x >= 0.0 && y >= 0.0 && x < size.width && y < size.height {
+            return;
+        }
+        log_x11!(self.app.connection().ungrab_pointer(x11rb::CURRENT_TIME));
+        self.with_handler(|h| h.popup_dismissed());
+    }
+
+    pub fn handle_unmap_notify(&self, _unmap_notify: &xproto::UnmapNotifyEvent) {
+        self.mapped.set(false);
+        // Minimizing a window also unmaps it. Mapping it again sends a `VisibilityNotify`.
+        self.set_visibility(Visibility::Hidden);
+    }
+
+    fn set_visibility(&self, visibility: Visibility) {
+        if self.visibility.replace(visibility) != visibility {
+            self.with_handler(|h| h.visibility_changed(visibility));
+        }
     }
 
     pub(crate) fn run_idle(&self) {
@@ -1047,13 +2548,14 @@ impl Window {
         });
 
         if needs_redraw {
+            self.paint_requests.paint_started();
             if let Err(e) = self.redraw_now() {
                 error!("Error redrawing: {}", e);
             }
         }
     }
 
-    pub(crate) fn next_timeout(&self) -> Option<Instant> {
+    fn next_timer(&self) -> Option<Instant> {
         self.timer_queue
             .lock()
             .unwrap()
@@ -1061,8 +2563,34 @@ impl Window {
             .map(|timer| timer.deadline())
     }
 
+    pub(crate) fn next_timeout(&self) -> Option<Instant> {
+        // If the server never tells us that the pending frame was shown, we stop waiting.
+        let waiting = self
+            .present_pending
+            .get()
+            .filter(|_| self.frame_waiting.get())
+            .map(|presented| presented + PRESENT_TIMEOUT);
+        [self.next_timer(), self.delayed_frame.get(), waiting]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
     pub(crate) fn run_timers(&self, now: Instant) {
-        while let Some(deadline) = self.next_timeout() {
+        let timed_out = self
+            .present_pending
+            .get()
+            .map_or(false, |presented| presented + PRESENT_TIMEOUT <= now);
+        if timed_out && self.frame_waiting.replace(false) {
+            self.present_pending.set(None);
+            self.request_anim_frame();
+        }
+        if self.delayed_frame.get().map_or(false, |frame| frame <= now) {
+            self.delayed_frame.set(None);
+            self.frame_throttle.delayed_frame_due();
+            self.request_anim_frame();
+        }
+        while let Some(deadline) = self.next_timer() {
             if deadline > now {
                 break;
             }
@@ -1074,6 +2602,14 @@ impl Window {
 }
 
 // Converts from, e.g., the `details` field of `xcb::xproto::ButtonPressEvent`
+/// The atoms of `_NET_WM_STATE` which together maximize a window.
+fn maximized_atoms(atoms: &AppAtoms) -> [xproto::Atom; 2] {
+    [
+        atoms._NET_WM_STATE_MAXIMIZED_VERT,
+        atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+    ]
+}
+
 fn pointer_button(button: u32) -> PointerButton {
     match button {
         0 => PointerButton::None,
@@ -1214,23 +2750,57 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.show();
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn hide(&self) {
+        if let Some(w) = self.window.upgrade() {
+            w.hide();
+        } else {
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
     pub fn close(&self) {
         if let Some(w) = self.window.upgrade() {
+            // The placement can't be read back once the window is destroyed
+            if let Some(name) = &w.frame_autosave_name {
+                let handle = crate::WindowHandle::from(self.clone());
+                // Kiosks are fullscreen because the app makes them, not the user
+                crate::autosave::save(name, &handle, !w.kiosk && w.is_fullscreen());
+            }
             w.close();
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
+    pub fn is_alive(&self) -> bool {
+        self.window.upgrade().map_or(false, |w| !w.destroyed())
+    }
+
     pub fn resizable(&self, resizable: bool) {
         if let Some(w) = self.window.upgrade() {
             w.resizable(resizable);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_aspect_ratio(ratio);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_resize_increments(&self, increments: Option<Size>) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_resize_increments(increments);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1238,7 +2808,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.show_titlebar(show_titlebar);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1246,7 +2816,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.set_position(position);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1254,7 +2824,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.get_position()
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
             Point::new(0.0, 0.0)
         }
     }
@@ -1268,7 +2838,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.set_size(size);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1276,18 +2846,104 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.size().size_dp()
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
             Size::ZERO
         }
     }
 
-    pub fn set_window_state(&self, _state: window::WindowState) {
-        warn!("WindowHandle::set_window_state is currently unimplemented for X11 backend.");
+    pub fn set_window_level(&self, level: StackingLevel) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_window_level(level);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_skip_taskbar(&self, skip: bool) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_skip_taskbar(skip);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_titlebar_theme(theme);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn begin_sheet(&self, sheet: &WindowHandle, completion: Box<dyn FnOnce(ModalResponse)>) {
+        match (self.window.upgrade(), sheet.window.upgrade()) {
+            (Some(w), Some(sheet_window)) => w.begin_sheet(
+                &sheet_window,
+                Sheet {
+                    parent: self.window.clone(),
+                    completion,
+                },
+            ),
+            _ => debug!("Window {} or its sheet has already been dropped", self.id),
+        }
+    }
+
+    pub fn end_sheet(&self, response: ModalResponse) {
+        if let Some(w) = self.window.upgrade() {
+            w.end_sheet(response);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn run_modal(&self) -> ModalResponse {
+        if let Some(w) = self.window.upgrade() {
+            w.run_modal()
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+            ModalResponse::Cancel
+        }
+    }
+
+    pub fn end_modal(&self, response: ModalResponse) {
+        if let Some(w) = self.window.upgrade() {
+            w.end_modal(response);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn focus_next_window(&self) {
+        if let Some(w) = self.window.upgrade() {
+            w.focus_next_window();
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_window_state(&self, state: window::WindowState) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_window_state(state);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
     }
 
     pub fn get_window_state(&self) -> window::WindowState {
-        warn!("WindowHandle::get_window_state is currently unimplemented for X11 backend.");
-        window::WindowState::Restored
+        if let Some(w) = self.window.upgrade() {
+            w.get_window_state()
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+            window::WindowState::Restored
+        }
+    }
+
+    pub fn set_maximize_mode(&self, mode: window::MaximizeMode) {
+        if let Some(w) = self.window.upgrade() {
+            w.maximize_mode.set(mode);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
     }
 
     pub fn handle_titlebar(&self, _val: bool) {
@@ -1298,7 +2954,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.bring_to_front_and_focus();
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1306,7 +2962,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.request_anim_frame();
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1314,7 +2970,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.invalidate();
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1322,15 +2978,54 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.invalidate_rect(rect);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), ShellError> {
+        let w = self.window.upgrade().ok_or(ShellError::WindowDropped)?;
+        Ok(w.present_pixels(buf, width, height, format)?)
+    }
+
+    pub fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, ShellError> {
+        let w = self.window.upgrade().ok_or(ShellError::WindowDropped)?;
+        w.read_back_pixels(format)
+    }
+
+    pub fn capture(&self) -> Option<ImageBuf> {
+        let w = self.window.upgrade()?;
+        match w.capture() {
+            Ok(image) => Some(image),
+            Err(e) => {
+                error!("Failed to capture window {}: {e:#}", self.id);
+                None
+            }
+        }
+    }
+
+    pub fn set_vsync(&self, enabled: bool) {
+        if let Some(w) = self.window.upgrade() {
+            w.vsync.set(enabled);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
+    pub fn vsync(&self) -> bool {
+        self.window.upgrade().map_or(true, |w| w.vsync.get())
+    }
+
     pub fn set_title(&self, title: &str) {
         if let Some(w) = self.window.upgrade() {
             w.set_title(title);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1338,7 +3033,7 @@ impl WindowHandle {
         if let Some(w) = self.window.upgrade() {
             w.set_menu(menu);
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
         }
     }
 
@@ -1360,10 +3055,19 @@ impl WindowHandle {
         }
     }
 
+    #[cfg(feature = "testing")]
+    pub fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.window.upgrade()?.active_text_field.get()
+    }
+
     pub fn update_text_field(&self, _token: TextFieldToken, _update: Event) {
         // noop until we get a real text input implementation
     }
 
+    pub fn show_character_palette(&self) {
+        // There's no standard picker to show; some input methods have a shortcut for their own.
+    }
+
     pub fn request_timer(&self, deadline: Instant) -> TimerToken {
         if let Some(w) = self.window.upgrade() {
             let timer = Timer::new(deadline, ());
@@ -1444,11 +3148,37 @@ impl WindowHandle {
         })
     }
 
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        self.window
+            .upgrade()
+            .map(|w| w.frame_timings.last())
+            .unwrap_or_default()
+    }
+
+    pub fn set_present_feedback(&self, enabled: bool) {
+        if let Some(w) = self.window.upgrade() {
+            if enabled && !w.app.present_supported() {
+                warn!("present feedback is unavailable, as the X server doesn't support the present extension");
+            }
+            w.frame_timings.set_feedback(enabled);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        if let Some(w) = self.window.upgrade() {
+            w.set_backing_scale_override(scale);
+        } else {
+            debug!("Window {} has already been dropped", self.id);
+        }
+    }
+
     pub fn get_scale(&self) -> Result<Scale, ShellError> {
         if let Some(w) = self.window.upgrade() {
             Ok(w.get_scale()?)
         } else {
-            error!("Window {} has already been dropped", self.id);
+            debug!("Window {} has already been dropped", self.id);
             Ok(Scale::new(1.0, 1.0))
         }
     }
@@ -1456,25 +3186,58 @@ impl WindowHandle {
     #[cfg(feature = "accesskit")]
     pub fn update_accesskit_if_active(
         &self,
-        _update_factory: impl FnOnce() -> accesskit::TreeUpdate,
+        update_factory: impl FnOnce() -> accesskit::TreeUpdate,
     ) {
-        // AccessKit doesn't yet support this backend.
+        if let Some(w) = self.window.upgrade() {
+            if let Ok(adapter) = borrow!(w.accesskit_adapter) {
+                if let Some(adapter) = adapter.as_ref() {
+                    adapter.update(update_factory());
+                }
+            }
+        }
     }
 }
 
+// We only have an XCB connection, so there are no Xlib handles.
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        match self.window.upgrade() {
+            Some(window) if !window.destroyed() => {}
+            _ => return Err(HandleError::Unavailable),
+        }
+        let window = NonZeroU32::new(self.id).ok_or(HandleError::Unavailable)?;
+        let mut handle = XcbWindowHandle::new(window);
+        handle.visual_id = NonZeroU32::new(self.visual_id);
+        // SAFETY: The window hasn't been destroyed yet.
+        Ok(unsafe { raw_window_handle::WindowHandle::borrow_raw(RawWindowHandle::Xcb(handle)) })
+    }
+}
+
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        let window = self.window.upgrade().ok_or(HandleError::Unavailable)?;
+        let connection = NonNull::new(window.app.connection().get_raw_xcb_connection());
+        let handle = XcbDisplayHandle::new(connection, window.app.screen_num() as _);
+        // SAFETY: The connection is owned by the application, which outlives its windows.
+        Ok(unsafe { DisplayHandle::borrow_raw(RawDisplayHandle::Xcb(handle)) })
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        let mut handle = XcbWindowHandle::empty();
+    fn raw_window_handle(&self) -> rwh_05::RawWindowHandle {
+        let mut handle = rwh_05::XcbWindowHandle::empty();
         handle.window = self.id;
         handle.visual_id = self.visual_id;
 
-        RawWindowHandle::Xcb(handle)
+        rwh_05::RawWindowHandle::Xcb(handle)
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
-    fn raw_display_handle(&self) -> RawDisplayHandle {
-        let mut handle = XcbDisplayHandle::empty();
+    fn raw_display_handle(&self) -> rwh_05::RawDisplayHandle {
+        let mut handle = rwh_05::XcbDisplayHandle::empty();
         if let Some(window) = self.window.upgrade() {
             handle.connection = window.app.connection().get_raw_xcb_connection();
         } else {
@@ -1482,7 +3245,7 @@ unsafe impl HasRawDisplayHandle for WindowHandle {
             // leaving those empty that cannot be derived.
             error!("Failed to get XCBConnection, returning incomplete handle");
         }
-        RawDisplayHandle::Xcb(handle)
+        rwh_05::RawDisplayHandle::Xcb(handle)
     }
 }
 fn make_cursor(
@@ -1494,3 +3257,29 @@ fn make_cursor(
 ) -> Result<Cursor, ReplyOrIdError> {
     Ok(Cursor::Arrow)
 }
+
+#[cfg(feature = "accesskit")]
+impl accesskit::ActionHandler for AccessKitActionHandler {
+    fn do_action(&self, request: accesskit::ActionRequest) {
+        self.idle_handle.add_idle_callback(move |handler| {
+            handler.accesskit_action(request);
+        });
+    }
+}
+
+/// Converts a present extension UST, which is CLOCK_MONOTONIC in microseconds, to an `Instant`.
+fn ust_to_instant(ust: u64) -> Option<Instant> {
+    let now = Instant::now();
+    let clock_now = match nix::time::clock_gettime(nix::time::ClockId::CLOCK_MONOTONIC) {
+        Ok(t) => Duration::new(t.tv_sec() as u64, t.tv_nsec() as u32),
+        Err(e) => {
+            warn!("failed to read the monotonic clock: {e}");
+            return None;
+        }
+    };
+    let ust = Duration::from_micros(ust);
+    match clock_now.checked_sub(ust) {
+        Some(ago) => now.checked_sub(ago),
+        None => now.checked_add(ust - clock_now),
+    }
+}
@@ -0,0 +1,366 @@
+//! Reading the display scale from XSETTINGS and the X resource database.
+//!
+//! XSETTINGS is how desktops like GNOME and Xfce publish their settings to X clients: a
+//! settings manager owns the `_XSETTINGS_S<screen>` selection, and keeps the settings in the
+//! `_XSETTINGS_SETTINGS` property of the selection owner's window.
+//!
+//! <https://specifications.freedesktop.org/xsettings-spec/xsettings-latest.html>
+
+use anyhow::{anyhow, Error};
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::resource_manager;
+use x11rb::xcb_ffi::XCBConnection;
+
+use crate::scale::Scale;
+
+use super::application::AppAtoms;
+
+/// The DPI at which the scale is 1.
+const BASE_DPI: f64 = 96.0;
+
+/// The settings which affect the scale.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct XSettings {
+    /// `Gdk/WindowScalingFactor`, the integer scale set by GNOME.
+    window_scaling_factor: Option<i32>,
+    /// `Gdk/UnscaledDPI`, the text DPI before `window_scaling_factor` is applied.
+    unscaled_dpi: Option<f64>,
+    /// `Xft/DPI`, which some settings managers multiply by `window_scaling_factor`.
+    xft_dpi: Option<f64>,
+}
+
+/// Reads the settings from the current settings manager, if there is one.
+pub(crate) fn read(
+    conn: &XCBConnection,
+    atoms: &AppAtoms,
+    manager: xproto::Window,
+) -> Result<XSettings, Error> {
+    let reply = conn
+        .get_property(
+            false,
+            manager,
+            atoms._XSETTINGS_SETTINGS,
+            atoms._XSETTINGS_SETTINGS,
+            0,
+            u32::MAX,
+        )?
+        .reply()?;
+    parse(&reply.value)
+}
+
+/// Finds the window of the settings manager, if one is running.
+pub(crate) fn manager_window(
+    conn: &XCBConnection,
+    selection: xproto::Atom,
+) -> Result<Option<xproto::Window>, Error> {
+    let owner = conn.get_selection_owner(selection)?.reply()?.owner;
+    Ok((owner != x11rb::NONE).then_some(owner))
+}
+
+/// Reads `Xft.dpi` from the `RESOURCE_MANAGER` property, which `xrdb` writes to.
+pub(crate) fn resource_dpi(conn: &XCBConnection) -> Option<f64> {
+    let rdb = match resource_manager::new_from_default(conn) {
+        Ok(rdb) => rdb,
+        Err(e) => {
+            tracing::warn!("failed to read the X resource database: {e}");
+            return None;
+        }
+    };
+    match rdb.get_value::<f64>("Xft.dpi", "") {
+        Ok(dpi) => dpi,
+        Err(e) => {
+            tracing::warn!("Unable to parse Xft.dpi: {e:?}");
+            None
+        }
+    }
+}
+
+/// Works out the scale of the display.
+///
+/// In order of precedence, the scale comes from:
+///
+/// 1. The `DRUID_X11_DPI` environment variable, for overriding everything else.
+/// 2. XSETTINGS. If the settings manager gives the DPI before the window scaling factor is
+///    applied, the two are multiplied. Otherwise, some managers already include the factor in
+///    `Xft/DPI` and others don't, so we take whichever of the two gives the larger scale,
+///    rather than risk applying the factor twice.
+/// 3. `Xft.dpi` in the X resource database.
+/// 4. A scale of 1.
+pub(crate) fn scale(settings: Option<&XSettings>, resource_dpi: Option<f64>) -> Scale {
+    let scale = env_scale()
+        .or_else(|| settings.and_then(XSettings::scale))
+        .or_else(|| resource_dpi.map(|dpi| dpi / BASE_DPI))
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(1.0);
+    Scale::new(scale, scale)
+}
+
+fn env_scale() -> Option<f64> {
+    let dpi = std::env::var("DRUID_X11_DPI").ok()?;
+    match dpi.parse::<f64>() {
+        Ok(dpi) => Some(dpi / BASE_DPI),
+        Err(e) => {
+            tracing::warn!("Unable to parse DRUID_X11_DPI: {e:?}");
+            None
+        }
+    }
+}
+
+impl XSettings {
+    fn scale(&self) -> Option<f64> {
+        let factor = self.window_scaling_factor.filter(|factor| *factor > 0);
+        match (factor, self.unscaled_dpi, self.xft_dpi) {
+            (None, None, None) => None,
+            (factor, Some(unscaled), _) => {
+                Some(f64::from(factor.unwrap_or(1)) * unscaled / BASE_DPI)
+            }
+            (factor, None, dpi) => {
+                let factor = f64::from(factor.unwrap_or(1));
+                Some(factor.max(dpi.map_or(0.0, |dpi| dpi / BASE_DPI)))
+            }
+        }
+    }
+}
+
+/// Parses the contents of `_XSETTINGS_SETTINGS`, keeping the settings we use.
+fn parse(data: &[u8]) -> Result<XSettings, Error> {
+    let mut reader = Reader {
+        data,
+        big_endian: *data.first().ok_or_else(|| anyhow!("empty XSETTINGS"))? != 0,
+    };
+    // The byte order is followed by three unused bytes and the serial.
+    reader.skip(8)?;
+    let count = reader.card32()?;
+
+    let mut settings = XSettings::default();
+    for _ in 0..count {
+        let kind = reader.card8()?;
+        reader.skip(1)?;
+        let name_len = reader.card16()? as usize;
+        let name = reader.bytes(name_len)?;
+        reader.skip(padding(name_len))?;
+        // The serial at which the setting last changed
+        reader.skip(4)?;
+        match kind {
+            // Integer
+            0 => {
+                let value = reader.card32()? as i32;
+                // The DPIs are given in 1024ths.
+                match name {
+                    b"Gdk/WindowScalingFactor" => settings.window_scaling_factor = Some(value),
+                    b"Gdk/UnscaledDPI" => settings.unscaled_dpi = Some(f64::from(value) / 1024.0),
+                    b"Xft/DPI" => settings.xft_dpi = Some(f64::from(value) / 1024.0),
+                    _ => (),
+                }
+            }
+            // String
+            1 => {
+                let len = reader.card32()? as usize;
+                reader.skip(len + padding(len))?;
+            }
+            // Color, as four 16-bit channels
+            2 => reader.skip(8)?,
+            kind => return Err(anyhow!("unknown XSETTINGS type {kind}")),
+        }
+    }
+    Ok(settings)
+}
+
+/// The number of bytes which pad `len` to a multiple of four.
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(anyhow!("truncated XSETTINGS"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Error> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn card8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn card16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn card32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds the contents of `_XSETTINGS_SETTINGS`, in either byte order.
+    struct Blob {
+        big_endian: bool,
+        count: u32,
+        records: Vec<u8>,
+    }
+
+    impl Blob {
+        fn new(big_endian: bool) -> Blob {
+            Blob {
+                big_endian,
+                count: 0,
+                records: Vec::new(),
+            }
+        }
+
+        fn card16(&mut self, value: u16) {
+            let bytes = if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            self.records.extend(bytes);
+        }
+
+        fn card32(&mut self, value: u32) {
+            let bytes = if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            self.records.extend(bytes);
+        }
+
+        fn padded(&mut self, bytes: &[u8]) {
+            self.records.extend(bytes);
+            self.records.extend(vec![0; padding(bytes.len())]);
+        }
+
+        /// Starts a record, up to its value.
+        fn header(&mut self, kind: u8, name: &str) {
+            self.count += 1;
+            self.records.extend([kind, 0]);
+            self.card16(name.len() as u16);
+            self.padded(name.as_bytes());
+            // The serial of the last change
+            self.card32(7);
+        }
+
+        fn int(mut self, name: &str, value: i32) -> Blob {
+            self.header(0, name);
+            self.card32(value as u32);
+            self
+        }
+
+        fn string(mut self, name: &str, value: &str) -> Blob {
+            self.header(1, name);
+            self.card32(value.len() as u32);
+            self.padded(value.as_bytes());
+            self
+        }
+
+        fn color(mut self, name: &str, rgba: [u16; 4]) -> Blob {
+            self.header(2, name);
+            for channel in rgba {
+                self.card16(channel);
+            }
+            self
+        }
+
+        fn build(self) -> Vec<u8> {
+            let mut data = vec![u8::from(self.big_endian), 0, 0, 0];
+            let (serial, count) = if self.big_endian {
+                (3u32.to_be_bytes(), self.count.to_be_bytes())
+            } else {
+                (3u32.to_le_bytes(), self.count.to_le_bytes())
+            };
+            data.extend(serial);
+            data.extend(count);
+            data.extend(self.records);
+            data
+        }
+    }
+
+    #[test]
+    fn parses_either_byte_order() {
+        for big_endian in [false, true] {
+            let data = Blob::new(big_endian)
+                // Names and strings of every length modulo four, to check their padding
+                .string("Net/ThemeName", "Breeze")
+                .color("Gtk/Color", [0xffff, 0x8000, 0, 0xffff])
+                .int("Gdk/WindowScalingFactor", 2)
+                .string("Gtk/FontName", "Cantarell 11")
+                .int("Gdk/UnscaledDPI", 96 * 1024)
+                .string("Net/IconThemeName", "")
+                .int("Xft/DPI", 192 * 1024)
+                .int("Net/DoubleClickTime", 400)
+                .build();
+            let expected = XSettings {
+                window_scaling_factor: Some(2),
+                unscaled_dpi: Some(96.0),
+                xft_dpi: Some(192.0),
+            };
+            assert_eq!(parse(&data).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        assert!(parse(&[]).is_err());
+        let data = Blob::new(false).int("Xft/DPI", 96 * 1024).build();
+        assert!(parse(&data[..data.len() - 1]).is_err());
+        let mut data = Blob::new(false).int("Xft/DPI", 96 * 1024).build();
+        // The type of the first record
+        data[12] = 3;
+        assert!(parse(&data).is_err());
+    }
+
+    #[test]
+    fn scale_precedence() {
+        let settings = |window_scaling_factor, unscaled_dpi, xft_dpi| XSettings {
+            window_scaling_factor,
+            unscaled_dpi,
+            xft_dpi,
+        };
+        // The unscaled DPI is multiplied by the factor, whatever `Xft/DPI` says
+        assert_eq!(
+            settings(Some(2), Some(120.0), Some(96.0)).scale(),
+            Some(2.5)
+        );
+        // Otherwise `Xft/DPI` may or may not include the factor, so the larger is taken
+        assert_eq!(settings(Some(2), None, Some(192.0)).scale(), Some(2.0));
+        assert_eq!(settings(Some(2), None, Some(96.0)).scale(), Some(2.0));
+        assert_eq!(settings(Some(1), None, Some(144.0)).scale(), Some(1.5));
+        assert_eq!(settings(None, None, Some(144.0)).scale(), Some(1.5));
+        assert_eq!(settings(Some(2), None, None).scale(), Some(2.0));
+        // A factor which isn't positive is ignored
+        assert_eq!(settings(Some(0), None, Some(144.0)).scale(), Some(1.5));
+        assert_eq!(settings(None, None, None).scale(), None);
+
+        // XSETTINGS are preferred to the resource database
+        let xsettings = settings(Some(2), None, None);
+        assert_eq!(scale(Some(&xsettings), Some(144.0)), Scale::new(2.0, 2.0));
+        assert_eq!(scale(None, Some(144.0)), Scale::new(1.5, 1.5));
+        assert_eq!(scale(None, None), Scale::new(1.0, 1.0));
+    }
+}
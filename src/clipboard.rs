@@ -13,6 +13,8 @@
 // limitations under the License.
 
 //! Interacting with the system pasteboard/clipboard.
+use std::path::PathBuf;
+
 pub use crate::backend::clipboard as backend;
 
 /// A handle to the system clipboard.
@@ -140,6 +142,21 @@ impl Clipboard {
         self.0.get_string()
     }
 
+    /// Put a string in the primary selection, which is pasted with the middle mouse button.
+    ///
+    /// Text editors should call this whenever the selection changes, with the selected text.
+    /// Only X11 has a primary selection, so this does nothing elsewhere.
+    pub fn put_primary(&mut self, s: impl AsRef<str>) {
+        self.0.put_primary(s)
+    }
+
+    /// Get the string in the primary selection, to paste it on a middle click.
+    ///
+    /// Only X11 has a primary selection, so this returns `None` elsewhere.
+    pub fn get_primary(&self) -> Option<String> {
+        self.0.get_primary()
+    }
+
     /// Given a list of supported clipboard types, returns the supported type which has
     /// highest priority on the system clipboard, or `None` if no types are supported.
     pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
@@ -154,6 +171,27 @@ impl Clipboard {
         self.0.get_format(format)
     }
 
+    /// Put data in all of its formats on the system clipboard.
+    pub fn put_data(&mut self, data: &DataTransfer) {
+        self.0.put_formats(&data.formats)
+    }
+
+    /// Read the data on the system clipboard, in each of `formats` it is available in.
+    ///
+    /// The formats are read in the order the clipboard prefers them, which the returned
+    /// [`DataTransfer`] keeps.
+    pub fn get_data(&self, formats: &[FormatId]) -> DataTransfer {
+        let mut remaining = formats.to_vec();
+        let mut data = DataTransfer::new();
+        while let Some(format) = self.0.preferred_format(&remaining) {
+            remaining.retain(|it| *it != format);
+            if let Some(bytes) = self.0.get_format(format) {
+                data.put_format(ClipboardFormat::new(format, bytes));
+            }
+        }
+        data
+    }
+
     /// For debugging: print the resolved identifiers for each type currently
     /// on the clipboard.
     #[doc(hidden)]
@@ -203,6 +241,167 @@ impl From<&str> for ClipboardFormat {
     }
 }
 
+/// Data in one or more formats, as it moves between applications.
+///
+/// This is what [`Clipboard::get_data`] reads and [`Clipboard::put_data`] writes. It uses the
+/// same [`FormatId`]s as the rest of the clipboard, and the platform's representation of the
+/// common ones, such as a list of files, is decoded here, so that reading the data works the
+/// same way wherever it came from.
+///
+/// # Examples
+///
+/// ```no_run
+/// use glazier::{Application, ClipboardFormat};
+///
+/// let clipboard = Application::global().clipboard();
+/// let data = clipboard.get_data(&[ClipboardFormat::FILES, ClipboardFormat::TEXT]);
+/// for path in data.get_files() {
+///     println!("pasted {}", path.display());
+/// }
+/// if let Some(text) = data.get_string() {
+///     println!("pasted {text:?}");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DataTransfer {
+    formats: Vec<ClipboardFormat>,
+}
+
+impl DataTransfer {
+    /// Create a `DataTransfer` with no data.
+    pub fn new() -> DataTransfer {
+        DataTransfer::default()
+    }
+
+    /// Add data in another format, after the ones already added.
+    ///
+    /// This replaces any data which was already added in the same format.
+    pub fn put_format(&mut self, format: ClipboardFormat) {
+        self.formats.retain(|it| it.identifier != format.identifier);
+        self.formats.push(format);
+    }
+
+    /// Add text, as [`ClipboardFormat::TEXT`].
+    pub fn put_string(&mut self, s: impl AsRef<str>) {
+        self.put_format(s.as_ref().into());
+    }
+
+    /// The formats the data is in, from the most preferred.
+    pub fn formats(&self) -> impl Iterator<Item = FormatId> + '_ {
+        self.formats.iter().map(|format| format.identifier)
+    }
+
+    /// Of the given formats, return the one the data prefers, or `None` if it is in none of
+    /// them.
+    pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
+        self.formats().find(|it| formats.contains(it))
+    }
+
+    /// Return the data in the given format, if it is in that format.
+    pub fn get_format(&self, format: FormatId) -> Option<&[u8]> {
+        self.formats
+            .iter()
+            .find(|it| it.identifier == format)
+            .map(|it| &*it.data)
+    }
+
+    /// Return the data as text, if it is in [`ClipboardFormat::TEXT`].
+    pub fn get_string(&self) -> Option<String> {
+        let data = self.get_format(ClipboardFormat::TEXT)?;
+        String::from_utf8(data.to_vec()).ok()
+    }
+
+    /// Return the paths in [`ClipboardFormat::FILES`], which is empty if the data isn't in that
+    /// format.
+    ///
+    /// Only local files are returned.
+    pub fn get_files(&self) -> Vec<PathBuf> {
+        let Some(data) = self.get_format(ClipboardFormat::FILES) else {
+            return Vec::new();
+        };
+        if cfg!(target_os = "windows") {
+            parse_drop_files(data)
+        } else {
+            parse_uri_list(data)
+        }
+    }
+}
+
+impl From<Vec<ClipboardFormat>> for DataTransfer {
+    fn from(formats: Vec<ClipboardFormat>) -> DataTransfer {
+        let mut data = DataTransfer::new();
+        for format in formats {
+            data.put_format(format);
+        }
+        data
+    }
+}
+
+/// The paths of the `file:` URIs in a `text/uri-list`, one per line.
+///
+/// A macOS file URL is a list of one.
+fn parse_uri_list(data: &[u8]) -> Vec<PathBuf> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(file_uri_to_path)
+        .collect()
+}
+
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Files on other hosts can't be opened, so only an empty host or `localhost` is kept.
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let high = (chars.next()? as char).to_digit(16)?;
+            let low = (chars.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// The paths in a `CF_HDROP`, which is a `DROPFILES` header followed by the paths, each ending
+/// in a nul, with another nul after the last.
+fn parse_drop_files(data: &[u8]) -> Vec<PathBuf> {
+    let field = |at: usize| {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    // `pFiles` is the offset of the paths, and `fWide` says whether they are UTF-16.
+    let (Some(offset), Some(wide)) = (field(0), field(16)) else {
+        return Vec::new();
+    };
+    let Some(list) = data.get(offset as usize..) else {
+        return Vec::new();
+    };
+    if wide != 0 {
+        let units: Vec<u16> = list
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        units
+            .split(|unit| *unit == 0)
+            .take_while(|path| !path.is_empty())
+            .map(|path| PathBuf::from(String::from_utf16_lossy(path)))
+            .collect()
+    } else {
+        list.split(|byte| *byte == 0)
+            .take_while(|path| !path.is_empty())
+            .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+            .collect()
+    }
+}
+
 impl From<backend::Clipboard> for Clipboard {
     fn from(src: backend::Clipboard) -> Clipboard {
         Clipboard(src)
@@ -215,6 +414,8 @@ cfg_if::cfg_if! {
             pub const PDF: &'static str = "com.adobe.pdf";
             pub const TEXT: &'static str = "public.utf8-plain-text";
             pub const SVG: &'static str = "public.svg-image";
+            pub const PNG: &'static str = "public.png";
+            pub const FILES: &'static str = "public.file-url";
         }
     } else {
         impl ClipboardFormat {
@@ -228,6 +429,63 @@ cfg_if::cfg_if! {
             }
             pub const PDF: &'static str = "application/pdf";
             pub const SVG: &'static str = "image/svg+xml";
+            cfg_if::cfg_if! {
+                if #[cfg(target_os = "windows")] {
+                    // The name browsers and Office register PNGs under.
+                    pub const PNG: &'static str = "PNG";
+                    pub const FILES: &'static str = "CF_HDROP";
+                } else {
+                    pub const PNG: &'static str = "image/png";
+                    pub const FILES: &'static str = "text/uri-list";
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_transfer_keeps_its_order() {
+        let mut data = DataTransfer::new();
+        data.put_string("old");
+        data.put_format(ClipboardFormat::new(ClipboardFormat::SVG, "<svg/>"));
+        data.put_string("new");
+        assert_eq!(
+            data.formats().collect::<Vec<_>>(),
+            [ClipboardFormat::SVG, ClipboardFormat::TEXT]
+        );
+        assert_eq!(
+            data.preferred_format(&[ClipboardFormat::TEXT, ClipboardFormat::SVG]),
+            Some(ClipboardFormat::SVG)
+        );
+        assert_eq!(data.get_string().as_deref(), Some("new"));
+        assert_eq!(data.preferred_format(&[ClipboardFormat::PDF]), None);
+    }
+
+    #[test]
+    fn uri_lists_are_parsed() {
+        let list = b"# a comment\r\nfile:///home/me/a%20b.txt\r\nfile://localhost/tmp/c\r\n\
+            https://example.com/\r\nfile://server/share/d\r\nfile:///bad%zz\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            [PathBuf::from("/home/me/a b.txt"), PathBuf::from("/tmp/c")]
+        );
+    }
+
+    #[test]
+    fn drop_files_are_parsed() {
+        // pFiles, pt, fNC and fWide
+        let mut data = vec![20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0];
+        for unit in "C:\\a.txt\0D:\\\u{e9}\0\0".encode_utf16() {
+            data.extend(unit.to_le_bytes());
         }
+        assert_eq!(
+            parse_drop_files(&data),
+            [PathBuf::from("C:\\a.txt"), PathBuf::from("D:\\\u{e9}")]
+        );
+        assert!(parse_drop_files(&data[..10]).is_empty());
     }
 }
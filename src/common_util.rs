@@ -14,16 +14,22 @@
 
 //! Common functions used by the backends
 
+use std::any::Any;
 use std::cell::Cell;
+use std::collections::VecDeque;
 use std::num::NonZeroU64;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::mpsc::TryRecvError;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
 
 use instant::Instant;
+use tracing::span::EnteredSpan;
+use tracing::Span;
 
-use crate::kurbo::Point;
+use crate::application::{AppHandler, PowerPreference, PowerState, UiDirection};
+use crate::kurbo::{Point, Size};
+use crate::region::Region;
+use crate::window::{FrameTimings, PresentInfo};
 use crate::WinHandler;
 
 // This is the default timing on windows.
@@ -31,6 +37,12 @@ const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(500);
 // the max distance between two clicks for them to count as a multi-click
 const MULTI_CLICK_MAX_DISTANCE: f64 = 5.0;
 
+/// The shortest time between animation frames while they are being throttled.
+const THROTTLED_FRAME_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+/// Whether animation frames are currently being throttled, see [`PowerPreference`].
+static THROTTLE_FRAMES: AtomicBool = AtomicBool::new(false);
+
 /// Strip the access keys from the menu string.
 ///
 /// Changes "E&xit" to "Exit". Actual ampersands are escaped as "&&".
@@ -72,26 +84,23 @@ pub(crate) type IdleCallback = Box<dyn for<'a> FnOnce(&'a mut dyn WinHandler) +
 #[allow(dead_code)]
 pub(crate) fn shared_queue<T>() -> (SharedEnqueuer<T>, SharedDequeuer<T>) {
     let (sender, receiver) = mpsc::channel();
-    let empty_flag = Arc::new(Mutex::new(true));
+    let len = Arc::new(Mutex::new(0));
 
     (
         SharedEnqueuer {
             sender,
-            empty_flag: Arc::clone(&empty_flag),
-        },
-        SharedDequeuer {
-            receiver,
-            empty_flag,
+            len: Arc::clone(&len),
         },
+        SharedDequeuer { receiver, len },
     )
 }
 
 /// A reference to a [`shared_queue`] that lets you enqueue callbacks.
 #[allow(dead_code)]
 pub(crate) struct SharedEnqueuer<T> {
-    // NOTE: All sends must be done with the `empty_flag` lock held and updated.
+    // NOTE: All sends must be done with the `len` lock held and updated.
     sender: mpsc::Sender<T>,
-    empty_flag: Arc<Mutex<bool>>,
+    len: Arc<Mutex<usize>>,
 }
 
 impl<T> SharedEnqueuer<T> {
@@ -100,13 +109,15 @@ impl<T> SharedEnqueuer<T> {
     #[allow(dead_code)]
     #[must_use]
     pub(crate) fn enqueue(&self, t: T) -> bool {
-        // Lock the empty flag before we send, otherwise it might become out of sync.
-        let mut empty_flag = self.empty_flag.lock().unwrap();
+        // Lock the length before we send, otherwise it might become out of sync.
+        let mut len = self.len.lock().unwrap();
 
         self.sender.send(t).unwrap();
 
-        let was_empty = *empty_flag;
-        *empty_flag = false;
+        let was_empty = *len == 0;
+        *len += 1;
+        #[cfg(feature = "instrument")]
+        tracing::trace!(depth = *len, "enqueued");
 
         was_empty
     }
@@ -116,7 +127,7 @@ impl<T> Clone for SharedEnqueuer<T> {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
-            empty_flag: Arc::clone(&self.empty_flag),
+            len: Arc::clone(&self.len),
         }
     }
 }
@@ -124,24 +135,26 @@ impl<T> Clone for SharedEnqueuer<T> {
 /// A reference to a [`shared_queue`] that lets you dequeue and consume callbacks.
 #[allow(dead_code)]
 pub(crate) struct SharedDequeuer<T> {
-    // NOTE: All recieves must be done with the `empty_flag` lock held and updated.
+    // NOTE: All recieves must be done with the `len` lock held and updated.
     receiver: mpsc::Receiver<T>,
-    empty_flag: Arc<Mutex<bool>>,
+    len: Arc<Mutex<usize>>,
 }
 
 impl<T> SharedDequeuer<T> {
     #[allow(dead_code)]
     pub(crate) fn try_dequeue(&self) -> Option<T> {
-        // Lock the empty flag before we receive, otherwise it might become out of sync.
-        let mut empty_flag = self.empty_flag.lock().unwrap();
+        // Lock the length before we receive, otherwise it might become out of sync.
+        let mut len = self.len.lock().unwrap();
 
-        let result = self.receiver.try_recv();
+        let result = self.receiver.try_recv().ok();
 
-        if matches!(result, Err(TryRecvError::Empty)) {
-            *empty_flag = true;
+        if result.is_some() {
+            *len -= 1;
+            #[cfg(feature = "instrument")]
+            tracing::trace!(depth = *len, "dequeued");
         }
 
-        result.ok()
+        result
     }
 }
 
@@ -244,3 +257,625 @@ impl Default for ClickCounter {
         ClickCounter::new(MULTI_CLICK_INTERVAL, MULTI_CLICK_MAX_DISTANCE)
     }
 }
+
+pub(crate) fn set_frame_throttling(throttle: bool) {
+    THROTTLE_FRAMES.store(throttle, Ordering::Relaxed);
+}
+
+/// Changes to the battery level alone are passed on to the handler at most this often.
+const BATTERY_LEVEL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The power state last passed on to the handler, and when, along with the latest change to
+/// the battery level which is being held back.
+static POWER_STATE: Mutex<PowerStateDebounce> = Mutex::new(PowerStateDebounce {
+    reported: None,
+    held: None,
+});
+
+struct PowerStateDebounce {
+    reported: Option<(PowerState, Instant)>,
+    held: Option<PowerState>,
+}
+
+/// When a new power state should be passed on to the handler.
+#[derive(Debug, PartialEq)]
+enum PowerStateReport {
+    Now,
+    /// Once the battery level interval has passed.
+    At(Instant),
+    /// It is the state the handler already has.
+    Unchanged,
+}
+
+/// Pass `state` on to `deliver`, unless only the battery level has changed since the last
+/// state which was.
+///
+/// Those changes are held until a minute after it, and then the latest of them is passed on
+/// from a background thread. `deliver` should use `run_on_main` to call
+/// [`power_state_changed`].
+#[allow(dead_code)]
+pub(crate) fn debounce_power_state<F>(state: PowerState, deliver: F)
+where
+    F: Fn(PowerState) + Send + 'static,
+{
+    let now = Instant::now();
+    let mut debounce = POWER_STATE.lock().unwrap();
+    match power_state_report(debounce.reported, state, now) {
+        PowerStateReport::Now => {
+            debounce.reported = Some((state, now));
+            debounce.held = None;
+            drop(debounce);
+            deliver(state);
+        }
+        PowerStateReport::At(due) => {
+            // If a change is already held, a thread is already waiting to pass it on
+            if debounce.held.replace(state).is_none() {
+                drop(debounce);
+                std::thread::spawn(move || {
+                    std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                    let held = POWER_STATE.lock().unwrap().held.take();
+                    if let Some(state) = held {
+                        debounce_power_state(state, deliver);
+                    }
+                });
+            }
+        }
+        // The battery level went back to what the handler was last told
+        PowerStateReport::Unchanged => debounce.held = None,
+    }
+}
+
+/// Tell the handler that the power state has changed, and apply its updated power preference.
+///
+/// Backends pass changes through [`debounce_power_state`] first.
+#[allow(dead_code)]
+pub(crate) fn power_state_changed(handler: Option<&mut dyn AppHandler>, state: PowerState) {
+    let preference = match handler {
+        Some(handler) => {
+            handler.power_state_changed(state);
+            handler.power_preference()
+        }
+        None => PowerPreference::Default,
+    };
+    set_frame_throttling(preference.throttles(state.low_power_mode));
+}
+
+fn power_state_report(
+    reported: Option<(PowerState, Instant)>,
+    state: PowerState,
+    now: Instant,
+) -> PowerStateReport {
+    let Some((last, at)) = reported else {
+        return PowerStateReport::Now;
+    };
+    if last.on_battery != state.on_battery
+        || last.low_power_mode != state.low_power_mode
+        || last.battery_level.is_some() != state.battery_level.is_some()
+    {
+        return PowerStateReport::Now;
+    }
+    if last.battery_level == state.battery_level {
+        return PowerStateReport::Unchanged;
+    }
+    let due = at + BATTERY_LEVEL_INTERVAL;
+    if now >= due {
+        PowerStateReport::Now
+    } else {
+        PowerStateReport::At(due)
+    }
+}
+
+/// When a window should paint its next animation frame.
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+pub(crate) enum FrameSchedule {
+    /// Paint as usual.
+    Now,
+    /// Paint after this delay, then call [`FrameThrottle::delayed_frame_due`].
+    After(Duration),
+    /// A delayed frame is already scheduled, so there's nothing to do.
+    Scheduled,
+}
+
+/// Caps the rate of a window's animation frames while they are being throttled.
+#[derive(Debug, Default)]
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+pub(crate) struct FrameThrottle {
+    last_frame: Cell<Option<Instant>>,
+    delayed_frame: Cell<bool>,
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+impl FrameThrottle {
+    /// Decide when to paint an animation frame which was just requested.
+    pub fn schedule(&self) -> FrameSchedule {
+        if self.delayed_frame.get() {
+            return FrameSchedule::Scheduled;
+        }
+        if !THROTTLE_FRAMES.load(Ordering::Relaxed) {
+            return FrameSchedule::Now;
+        }
+        let delay = self.last_frame.get().and_then(|last| {
+            THROTTLED_FRAME_INTERVAL
+                .checked_sub(last.elapsed())
+                .filter(|delay| !delay.is_zero())
+        });
+        match delay {
+            Some(delay) => {
+                self.delayed_frame.set(true);
+                FrameSchedule::After(delay)
+            }
+            None => FrameSchedule::Now,
+        }
+    }
+
+    /// Record that the delayed frame is due, and is about to be painted.
+    pub fn delayed_frame_due(&self) {
+        self.delayed_frame.set(false);
+    }
+
+    /// Record that a frame was painted just now.
+    pub fn frame_painted(&self) {
+        self.last_frame.set(Some(Instant::now()));
+    }
+}
+
+/// Coalesces the requests for a window's next paint, so that any number of them made before it
+/// happens schedule it once, and the handler paints once.
+#[derive(Debug, Default)]
+#[cfg_attr(
+    not(any(
+        target_os = "macos",
+        target_arch = "wasm32",
+        all(unix, feature = "x11")
+    )),
+    allow(unused)
+)]
+pub(crate) struct PaintRequests {
+    scheduled: Cell<bool>,
+}
+
+#[cfg_attr(
+    not(any(
+        target_os = "macos",
+        target_arch = "wasm32",
+        all(unix, feature = "x11")
+    )),
+    allow(unused)
+)]
+impl PaintRequests {
+    /// Record a request for the next paint, returning whether it still has to be scheduled.
+    pub fn request(&self) -> bool {
+        !self.scheduled.replace(true)
+    }
+
+    /// Record that the scheduled paint is happening, so that the requests made from now on,
+    /// including those made while painting, schedule the one after it.
+    pub fn paint_started(&self) {
+        self.scheduled.set(false);
+    }
+}
+
+/// Grows `min_size` until its width over its height is `ratio`, for windows which keep that
+/// aspect ratio while resizing.
+///
+/// Otherwise the window could be stuck at a size where keeping the ratio would take one side
+/// below its minimum.
+#[cfg_attr(
+    not(any(target_os = "windows", all(unix, feature = "x11"))),
+    allow(unused)
+)]
+pub(crate) fn min_size_with_aspect_ratio(min_size: Size, ratio: f64) -> Size {
+    let width = min_size.width.max(min_size.height * ratio);
+    Size::new(width, width / ratio)
+}
+
+/// Snaps one side of a window which is being resized to `base` plus a whole number of
+/// `increment`s, for windows which resize in steps, such as terminals by character cells.
+///
+/// The side never snaps below `base`.
+#[cfg_attr(not(target_os = "windows"), allow(unused))]
+pub(crate) fn snap_to_increment(size: f64, base: f64, increment: f64) -> f64 {
+    let steps = ((size - base) / increment).round().max(0.0);
+    base + steps * increment
+}
+
+/// Enter a span for one native event, named by its `kind`, with the `instrument` feature.
+///
+/// Without the feature, the span is disabled and costs nothing.
+#[cfg_attr(not(feature = "instrument"), allow(unused_variables))]
+pub(crate) fn event_span(kind: &str) -> EnteredSpan {
+    #[cfg(feature = "instrument")]
+    let span = tracing::trace_span!("event", kind);
+    #[cfg(not(feature = "instrument"))]
+    let span = Span::none();
+    span.entered()
+}
+
+/// Enter a span around a callback into the app's `handler`, with the `instrument` feature.
+///
+/// `window` identifies the window of a [`WinHandler`]. Paint callbacks go on to
+/// [`record_damage`] in this span.
+#[cfg_attr(not(feature = "instrument"), allow(unused_variables))]
+pub(crate) fn callback_span(
+    handler: &'static str,
+    callback: &str,
+    window: Option<u64>,
+) -> EnteredSpan {
+    #[cfg(feature = "instrument")]
+    let span = tracing::trace_span!(
+        "callback",
+        handler,
+        callback,
+        window,
+        damage_rects = tracing::field::Empty,
+        damage_area = tracing::field::Empty,
+    );
+    #[cfg(not(feature = "instrument"))]
+    let span = Span::none();
+    span.entered()
+}
+
+/// Record the size of the region being painted in the current [`callback_span`].
+#[cfg_attr(not(feature = "instrument"), allow(unused_variables))]
+pub(crate) fn record_damage(invalid: &Region) {
+    #[cfg(feature = "instrument")]
+    {
+        let area: f64 = invalid.rects().iter().map(|rect| rect.area()).sum();
+        Span::current()
+            .record("damage_rects", invalid.rects().len())
+            .record("damage_area", area);
+    }
+}
+
+/// The message a panic was started with, for logging it.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Turn a POSIX or Apple locale identifier, such as `en_US.UTF-8` or `en_US@rg=gbzzzz`, into
+/// a BCP 47 language tag, such as `en-US`.
+///
+/// POSIX modifiers which name a script, such as in `sr_RS@latin`, become a script subtag, as
+/// in `sr-Latn-RS`. Other modifiers are dropped.
+///
+/// The `C` and `POSIX` locales don't name a language, so they are `None`.
+pub(crate) fn normalize_locale(locale: &str) -> Option<String> {
+    const SCRIPTS: &[(&str, &str)] = &[
+        ("arabic", "Arab"),
+        ("cyrillic", "Cyrl"),
+        ("devanagari", "Deva"),
+        ("latin", "Latn"),
+    ];
+    let (locale, modifier) = locale.split_once('@').unwrap_or((locale, ""));
+    let locale = locale.split('.').next().unwrap_or_default().trim();
+    if let "" | "C" | "POSIX" = locale {
+        return None;
+    }
+    let mut subtags = locale.split(['_', '-']);
+    let mut tag = subtags.next().unwrap_or_default().to_owned();
+    let mut rest = subtags.peekable();
+    let has_script = rest
+        .peek()
+        .is_some_and(|it| it.len() == 4 && it.chars().all(|c| c.is_ascii_alphabetic()));
+    let script = SCRIPTS
+        .iter()
+        .find(|(name, _)| modifier.trim().eq_ignore_ascii_case(name));
+    if let (false, Some((_, script))) = (has_script, script) {
+        tag.push('-');
+        tag.push_str(script);
+    }
+    for subtag in rest {
+        tag.push('-');
+        tag.push_str(subtag);
+    }
+    Some(tag)
+}
+
+/// The direction a user interface in `locale`, a BCP 47 language tag, is laid out in.
+///
+/// A script subtag, such as in `az-Arab`, decides it. Otherwise it follows the language's
+/// usual script.
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(unused))]
+pub(crate) fn ui_direction_for_locale(locale: &str) -> UiDirection {
+    const RTL_SCRIPTS: &[&str] = &["arab", "hebr", "syrc", "thaa", "nkoo", "adlm", "rohg"];
+    const RTL_LANGUAGES: &[&str] = &[
+        "ar", "arc", "ckb", "dv", "fa", "he", "iw", "ks", "ps", "sd", "syr", "ug", "ur", "yi",
+    ];
+    let mut subtags = locale.split(['-', '_']).map(str::to_ascii_lowercase);
+    let language = subtags.next().unwrap_or_default();
+    let script = subtags.find(|it| it.len() == 4 && it.chars().all(|c| c.is_ascii_alphabetic()));
+    let rtl = match script {
+        Some(script) => RTL_SCRIPTS.contains(&script.as_str()),
+        None => RTL_LANGUAGES.contains(&language.as_str()),
+    };
+    if rtl {
+        UiDirection::Rtl
+    } else {
+        UiDirection::Ltr
+    }
+}
+
+/// Tracks when a window's frames reach the screen, for [`WindowHandle::last_frame_timings`] and
+/// [`WinHandler::frame_presented`].
+///
+/// Some platforms report presentation on another thread, so this is shared with it.
+///
+/// [`WindowHandle::last_frame_timings`]: crate::WindowHandle::last_frame_timings
+/// [`WinHandler::frame_presented`]: crate::WinHandler::frame_presented
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+pub(crate) struct FrameTimingsCache(Arc<Mutex<FrameTimingsState>>);
+
+#[derive(Debug, Default)]
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+struct FrameTimingsState {
+    /// The number of frames which have started painting.
+    frames: u64,
+    /// The frames which haven't been shown yet, oldest first, with when they started painting.
+    pending: VecDeque<(u64, Instant)>,
+    last: FrameTimings,
+    /// Whether the handler wants to hear about every frame.
+    feedback: bool,
+    /// Feedback which hasn't been passed to the handler yet.
+    undelivered: Vec<PresentInfo>,
+}
+
+/// We stop tracking frames which we never hear back about beyond this.
+const MAX_PENDING_FRAMES: usize = 16;
+
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+impl FrameTimingsCache {
+    /// Record that painting a frame has started, and return its number.
+    pub fn paint_started(&self) -> u64 {
+        let mut state = self.0.lock().unwrap();
+        state.frames += 1;
+        let frame = state.frames;
+        if state.pending.len() == MAX_PENDING_FRAMES {
+            state.pending.pop_front();
+        }
+        state.pending.push_back((frame, Instant::now()));
+        frame
+    }
+
+    /// Record that the window's contents were shown at `vsync`, on a display which refreshes
+    /// every `refresh`.
+    ///
+    /// This is for platforms where a new frame replaces the previous one even if it hasn't
+    /// been shown yet: the last frame which started painting before `vsync` is presented, and
+    /// any older ones were dropped.
+    pub fn frame_presented(&self, vsync: Instant, refresh: Option<Duration>) {
+        let mut state = self.0.lock().unwrap();
+        let Some(latest) = state
+            .pending
+            .iter()
+            .rposition(|(_, painted)| *painted <= vsync)
+        else {
+            return;
+        };
+        let frames: Vec<_> = state.pending.drain(..=latest).collect();
+        let (presented, dropped) = frames.split_last().unwrap();
+        for &(frame, painted) in dropped {
+            state.record(frame, painted, None, refresh);
+        }
+        state.record(presented.0, presented.1, Some(vsync), refresh);
+    }
+
+    /// Record what happened to a single frame, which was either shown at `presented`, or
+    /// dropped if that is `None`.
+    pub fn frame_feedback(
+        &self,
+        frame: u64,
+        presented: Option<Instant>,
+        refresh: Option<Duration>,
+    ) {
+        let mut state = self.0.lock().unwrap();
+        let Some(ix) = state.pending.iter().position(|(f, _)| *f == frame) else {
+            return;
+        };
+        let (_, painted) = state.pending.remove(ix).unwrap();
+        state.record(frame, painted, presented, refresh);
+    }
+
+    /// The timings of the last frame which was shown.
+    pub fn last(&self) -> FrameTimings {
+        self.0.lock().unwrap().last
+    }
+
+    pub fn set_feedback(&self, feedback: bool) {
+        let mut state = self.0.lock().unwrap();
+        state.feedback = feedback;
+        if !feedback {
+            state.undelivered.clear();
+        }
+    }
+
+    pub fn feedback(&self) -> bool {
+        self.0.lock().unwrap().feedback
+    }
+
+    /// Whether there are frames we're still waiting to hear about.
+    pub fn has_pending(&self) -> bool {
+        !self.0.lock().unwrap().pending.is_empty()
+    }
+
+    /// Whether there is feedback which hasn't been taken yet.
+    pub fn has_feedback(&self) -> bool {
+        !self.0.lock().unwrap().undelivered.is_empty()
+    }
+
+    /// Take the feedback which should be passed to [`WinHandler::frame_presented`].
+    pub fn take_feedback(&self) -> Vec<PresentInfo> {
+        std::mem::take(&mut self.0.lock().unwrap().undelivered)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", allow(unused))]
+impl FrameTimingsState {
+    fn record(
+        &mut self,
+        frame: u64,
+        painted: Instant,
+        presented: Option<Instant>,
+        refresh: Option<Duration>,
+    ) {
+        let time_to_present = presented.map(|vsync| {
+            if vsync > painted {
+                vsync - painted
+            } else {
+                Duration::ZERO
+            }
+        });
+        if let Some(vsync) = presented {
+            let missed_deadline = match (self.last.vsync, refresh) {
+                (Some(previous), Some(refresh)) => {
+                    let deadline = previous + refresh;
+                    // Allow for some jitter in the reported timestamps.
+                    painted < deadline && vsync > deadline + refresh / 2
+                }
+                _ => false,
+            };
+            self.last = FrameTimings {
+                vsync: Some(vsync),
+                time_to_present,
+                missed_deadline,
+            };
+        }
+        if self.feedback {
+            self.undelivered.push(PresentInfo {
+                frame,
+                presented,
+                time_to_present,
+                refresh_interval: refresh,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_requests_coalesce() {
+        let requests = PaintRequests::default();
+        // However many invalidations come between two frames, one paint is scheduled.
+        assert_eq!((0..10).filter(|_| requests.request()).count(), 1);
+        requests.paint_started();
+        // Requests made while painting are for the next frame.
+        assert!(requests.request());
+        assert!(!requests.request());
+    }
+
+    #[test]
+    fn min_size_keeps_aspect_ratio() {
+        let min_size = Size::new(400.0, 300.0);
+        assert_eq!(
+            min_size_with_aspect_ratio(min_size, 2.0),
+            Size::new(600.0, 300.0)
+        );
+        assert_eq!(
+            min_size_with_aspect_ratio(min_size, 1.0),
+            Size::new(400.0, 400.0)
+        );
+    }
+
+    #[test]
+    fn snap_to_whole_increments() {
+        assert_eq!(snap_to_increment(105.0, 20.0, 8.0), 100.0);
+        assert_eq!(snap_to_increment(106.0, 20.0, 8.0), 108.0);
+        assert_eq!(snap_to_increment(10.0, 20.0, 8.0), 20.0);
+    }
+
+    #[test]
+    fn locales_are_bcp47() {
+        assert_eq!(normalize_locale("en_US.UTF-8").as_deref(), Some("en-US"));
+        assert_eq!(
+            normalize_locale("sr_RS@latin").as_deref(),
+            Some("sr-Latn-RS")
+        );
+        assert_eq!(
+            normalize_locale("sr_RS.UTF-8@latin").as_deref(),
+            Some("sr-Latn-RS")
+        );
+        assert_eq!(
+            normalize_locale("uz_UZ@cyrillic").as_deref(),
+            Some("uz-Cyrl-UZ")
+        );
+        assert_eq!(
+            normalize_locale("ks_IN@devanagari").as_deref(),
+            Some("ks-Deva-IN")
+        );
+        assert_eq!(normalize_locale("de_DE@euro").as_deref(), Some("de-DE"));
+        assert_eq!(
+            normalize_locale("en_US@rg=gbzzzz").as_deref(),
+            Some("en-US")
+        );
+        assert_eq!(
+            normalize_locale("sr_Cyrl_RS@latin").as_deref(),
+            Some("sr-Cyrl-RS")
+        );
+        assert_eq!(
+            normalize_locale("zh-Hans-CN").as_deref(),
+            Some("zh-Hans-CN")
+        );
+        assert_eq!(normalize_locale("de").as_deref(), Some("de"));
+        assert_eq!(normalize_locale("C.UTF-8"), None);
+        assert_eq!(normalize_locale("POSIX"), None);
+    }
+
+    #[test]
+    fn battery_level_changes_are_coalesced() {
+        let start = Instant::now();
+        let state = PowerState {
+            on_battery: true,
+            battery_level: Some(0.5),
+            low_power_mode: false,
+        };
+        let reported = Some((state, start));
+        let drained = PowerState {
+            battery_level: Some(0.49),
+            ..state
+        };
+        assert_eq!(
+            power_state_report(None, state, start),
+            PowerStateReport::Now
+        );
+        assert_eq!(
+            power_state_report(reported, state, start),
+            PowerStateReport::Unchanged
+        );
+        // Held until the interval has passed, rather than dropped
+        assert_eq!(
+            power_state_report(reported, drained, start + Duration::from_secs(10)),
+            PowerStateReport::At(start + BATTERY_LEVEL_INTERVAL)
+        );
+        assert_eq!(
+            power_state_report(reported, drained, start + BATTERY_LEVEL_INTERVAL),
+            PowerStateReport::Now
+        );
+        let plugged_in = PowerState {
+            on_battery: false,
+            ..drained
+        };
+        assert_eq!(
+            power_state_report(reported, plugged_in, start),
+            PowerStateReport::Now
+        );
+    }
+
+    #[test]
+    fn rtl_locales() {
+        assert_eq!(ui_direction_for_locale("en-US"), UiDirection::Ltr);
+        assert_eq!(ui_direction_for_locale("ar-EG"), UiDirection::Rtl);
+        assert_eq!(ui_direction_for_locale("he"), UiDirection::Rtl);
+        assert_eq!(ui_direction_for_locale("az-Arab-IR"), UiDirection::Rtl);
+        assert_eq!(ui_direction_for_locale("ku-Latn"), UiDirection::Ltr);
+        assert_eq!(ui_direction_for_locale("sd-Deva-IN"), UiDirection::Ltr);
+    }
+}
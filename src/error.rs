@@ -20,6 +20,9 @@ use std::sync::Arc;
 use crate::backend::error as backend;
 
 /// Shell errors.
+///
+/// Errors which happen outside of any call, such as the connection to the display server
+/// being lost, are passed to [`AppHandler::backend_error`](crate::AppHandler::backend_error).
 #[derive(Debug)]
 pub enum Error {
     /// The Application instance has already been created.
@@ -28,6 +31,20 @@ pub enum Error {
     ApplicationDropped,
     /// The window has already been destroyed.
     WindowDropped,
+    /// The platform, or the version of it which is running, doesn't support a feature.
+    ///
+    /// This names the feature, such as `read_back_pixels`. It is only returned when the
+    /// feature can't work at all, so toolkits can rely on it to detect features at runtime and
+    /// fall back to something else.
+    Unsupported(&'static str),
+    /// The user, or the system's policy, hasn't allowed the application to use a feature.
+    ///
+    /// This names the feature, such as `notifications`.
+    PermissionDenied(&'static str),
+    /// A window was built with options which can't be used together.
+    ///
+    /// This describes the conflict, such as `a popup can't be a kiosk window`.
+    IncompatibleOptions(&'static str),
     /// Platform specific error.
     Platform(backend::Error),
     /// Other miscellaneous error.
@@ -48,6 +65,15 @@ impl fmt::Display for Error {
             }
             Error::Platform(err) => fmt::Display::fmt(err, f),
             Error::WindowDropped => write!(f, "The window has already been destroyed."),
+            Error::Unsupported(feature) => {
+                write!(f, "{feature} is not supported on this platform.")
+            }
+            Error::PermissionDenied(feature) => {
+                write!(f, "The application isn't allowed to use {feature}.")
+            }
+            Error::IncompatibleOptions(conflict) => {
+                write!(f, "Incompatible window options: {conflict}.")
+            }
             Error::Other(s) => write!(f, "{s}"),
         }
     }
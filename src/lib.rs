@@ -25,6 +25,13 @@
 //!
 //! - `glazier_DISABLE_X11_PRESENT`: if this is set and `glazier` is using the `x11`
 //! backend, it will avoid using the Present extension.
+//! - `GLAZIER_BACKEND`: on Linux and the BSDs, if this is set to `wayland`, `x11` or `headless`,
+//! `glazier` will use that backend, if its feature is enabled, instead of picking one for the
+//! session. See [`Application::new_with_backend`] and the
+//! [headless backend](platform::headless).
+//! - `GLAZIER_DISABLE_FRAME_AUTOSAVE`: if this is set, windows built with
+//! [`WindowBuilder::with_frame_autosave_name`] are neither restored nor saved, so they start
+//! where the application puts them.
 
 #![warn(rustdoc::broken_intra_doc_links)]
 #![allow(clippy::new_without_default)]
@@ -37,11 +44,16 @@ pub use kurbo;
 
 // Reexport the version of `raw_window_handle` we are using.
 pub use raw_window_handle;
+// Reexport the older version of `raw_window_handle`, for crates which haven't upgraded yet.
+#[cfg(feature = "rwh_05")]
+pub use rwh_05;
 
 #[macro_use]
 mod util;
 
 mod application;
+mod autosave;
+mod autoscroll;
 mod backend;
 mod clipboard;
 mod common_util;
@@ -51,17 +63,26 @@ mod hotkey;
 mod keyboard;
 mod menu;
 mod mouse;
+mod notification;
 mod pointer;
 mod region;
 mod scale;
 mod screen;
+#[cfg(feature = "testing")]
+mod testing;
 mod window;
 
 pub mod platform;
 pub mod text;
 
-pub use application::{AppHandle, AppHandler, Application};
-pub use clipboard::{Clipboard, ClipboardFormat, FormatId};
+pub use application::{
+    A11yPrefs, AppHandle, AppHandler, Application, Backend, Color, EventSourceToken,
+    FontDescription, LoopMode, LoopStatus, PowerPreference, PowerState, SleepGuard, SubpixelOrder,
+    SystemColor, SystemFonts, TextAntialiasing, TextRenderingPrefs, UiDirection, UiMetrics,
+    WakeLockKind, WakeLockToken,
+};
+pub use autoscroll::AutoScroll;
+pub use clipboard::{Clipboard, ClipboardFormat, DataTransfer, FormatId};
 pub use common_util::Counter;
 pub use dialog::{FileDialogOptions, FileInfo, FileSpec};
 pub use error::Error;
@@ -69,6 +90,7 @@ pub use hotkey::{HotKey, RawMods, SysMods};
 pub use keyboard::{Code, IntoKey, KbKey, KeyEvent, KeyState, Location, Modifiers};
 pub use menu::Menu;
 pub use mouse::{Cursor, CursorDesc, MouseButton, MouseButtons, MouseEvent};
+pub use notification::{Notification, NotificationHandle, NotificationId};
 pub use pointer::{
     MouseInfo, PenInclination, PenInfo, PointerButton, PointerButtons, PointerEvent, PointerId,
     PointerType, TouchInfo,
@@ -76,9 +98,13 @@ pub use pointer::{
 pub use region::Region;
 pub use scale::{Scalable, Scale, ScaledArea};
 pub use screen::{Monitor, Screen};
+#[cfg(feature = "testing")]
+pub use testing::InjectedEvent;
 pub use window::{
-    FileDialogToken, IdleHandle, IdleToken, TextFieldToken, TimerToken, WinHandler, WindowBuilder,
-    WindowHandle, WindowLevel, WindowState,
+    DecorationMode, FileDialogToken, FocusDirection, FrameTimings, IdleHandle, IdleToken, ImageBuf,
+    MaximizeMode, ModalResponse, PixelFormat, PopupAnchor, PresentInfo, StackingLevel,
+    TextFieldToken, Theme, TimerToken, Visibility, WeakWindowHandle, WinHandler, WindowBuilder,
+    WindowHandle, WindowLevel, WindowPlacement, WindowState, WindowType,
 };
 
 pub use keyboard_types;
@@ -54,6 +54,15 @@ pub struct MouseEvent {
     ///
     /// [WheelEvent]: https://w3c.github.io/uievents/#event-type-wheel
     pub wheel_delta: Vec2,
+    /// The positions of the moves which were merged into this one, oldest first, in
+    /// [display points] in relation to the current window.
+    ///
+    /// This is only filled in for mouse-move events, and only when the window coalesces pointer
+    /// events, see [`WindowBuilder::with_coalesced_pointer_events`].
+    ///
+    /// [display points]: crate::Scale
+    /// [`WindowBuilder::with_coalesced_pointer_events`]: crate::WindowBuilder::with_coalesced_pointer_events
+    pub coalesced: Vec<Point>,
 }
 
 /// An indicator of which mouse button was pressed.
@@ -0,0 +1,115 @@
+//! Desktop notifications.
+
+use std::path::PathBuf;
+
+use crate::application::Application;
+use crate::common_util::Counter;
+
+/// A notification, for [`Application::send_notification`].
+///
+/// Clicking the notification, or one of its actions, calls
+/// [`AppHandler::notification_activated`](crate::AppHandler::notification_activated).
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub(crate) title: String,
+    pub(crate) body: String,
+    pub(crate) image: Option<PathBuf>,
+    pub(crate) actions: Vec<String>,
+}
+
+impl Notification {
+    /// The most actions a notification can have.
+    pub const MAX_ACTIONS: usize = 3;
+
+    /// Create a notification with a title, and nothing else.
+    pub fn new(title: impl Into<String>) -> Notification {
+        Notification {
+            title: title.into(),
+            body: String::new(),
+            image: None,
+            actions: Vec::new(),
+        }
+    }
+
+    /// Set the text shown below the title.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Show the image at `path`, such as a PNG file.
+    ///
+    /// On Windows, only `.ico` files can be shown.
+    pub fn image(mut self, path: impl Into<PathBuf>) -> Self {
+        self.image = Some(path.into());
+        self
+    }
+
+    /// Add a button labelled `label`, whose index is passed to
+    /// [`AppHandler::notification_activated`](crate::AppHandler::notification_activated).
+    ///
+    /// At most [`MAX_ACTIONS`](Notification::MAX_ACTIONS) are shown. Windows doesn't show
+    /// actions, so there the user can only click the notification itself.
+    pub fn action(mut self, label: impl Into<String>) -> Self {
+        if self.actions.len() < Notification::MAX_ACTIONS {
+            self.actions.push(label.into());
+        } else {
+            tracing::warn!(
+                "notifications can have at most {} actions",
+                Self::MAX_ACTIONS
+            );
+        }
+        self
+    }
+}
+
+/// Identifies a notification sent with [`Application::send_notification`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NotificationId(u64);
+
+impl NotificationId {
+    pub(crate) fn next() -> NotificationId {
+        static NOTIFICATION_COUNTER: Counter = Counter::new();
+        NotificationId(NOTIFICATION_COUNTER.next())
+    }
+
+    /// Create an id from a raw value.
+    pub const fn from_raw(id: u64) -> NotificationId {
+        NotificationId(id)
+    }
+
+    /// Get the raw value for an id.
+    pub const fn into_raw(self) -> u64 {
+        self.0
+    }
+}
+
+/// A notification which has been sent, returned by [`Application::send_notification`].
+///
+/// Dropping the handle doesn't withdraw the notification, as the user might still want to
+/// act on it.
+#[derive(Debug, Clone)]
+pub struct NotificationHandle {
+    id: NotificationId,
+}
+
+impl NotificationHandle {
+    pub(crate) fn new(id: NotificationId) -> NotificationHandle {
+        NotificationHandle { id }
+    }
+
+    /// The id which is passed to the [`AppHandler`](crate::AppHandler) for this
+    /// notification.
+    pub fn id(&self) -> NotificationId {
+        self.id
+    }
+
+    /// Remove the notification, if it is still shown.
+    ///
+    /// This does nothing once the application has stopped running.
+    pub fn close(&self) {
+        if let Some(app) = Application::try_global() {
+            app.backend_app.close_notification(self.id);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Extensions for the headless backend, for testing without a display server.
+//!
+//! The headless backend is enabled with the `headless` feature. It is used when
+//! `GLAZIER_BACKEND=headless` is set, when the application is created with
+//! [`Backend::Headless`], or when no other Linux backend is enabled. Windows are never shown,
+//! but their handlers get the same callbacks as on other backends, in an order which only
+//! depends on what the application does:
+//!
+//! - time is a virtual clock, which only moves when [`ApplicationExt::advance_clock`] is
+//!   called, or when the event loop is waiting: [`Application::run`] skips ahead to the next
+//!   timer or animation frame, and [`Application::pump_events`] skips ahead by up to its
+//!   timeout,
+//! - input is injected with [`Application::inject_event`],
+//! - what the handler presented with [`WindowHandle::present_pixels`] can be read back with
+//!   [`WindowHandle::capture`],
+//! - the clipboard is kept in memory, and only shared by the application itself,
+//! - the system settings, such as [`Application::system_fonts`], are fixed.
+//!
+//! Unlike on other backends, [`Application::run`] also returns when there is nothing left to
+//! do, as nothing could happen while it waits.
+//!
+//! The headless backend is only available on Linux and the BSDs, where the backend is chosen
+//! when the application starts. On macOS, Windows and the web, the `headless` feature only
+//! enables [`Application::inject_event`], and the native backend is used.
+//!
+//! Headless windows have no native window, so their raw-window-handle 0.6 handles return
+//! [`HandleError::NotSupported`], and the 0.5 ones are empty Xlib handles.
+//!
+//! [`Backend::Headless`]: crate::Backend::Headless
+//! [`Application::run`]: crate::Application::run
+//! [`Application::pump_events`]: crate::Application::pump_events
+//! [`Application::inject_event`]: crate::Application::inject_event
+//! [`Application::system_fonts`]: crate::Application::system_fonts
+//! [`WindowHandle::present_pixels`]: crate::WindowHandle::present_pixels
+//! [`WindowHandle::capture`]: crate::WindowHandle::capture
+//! [`HandleError::NotSupported`]: raw_window_handle::HandleError::NotSupported
+
+use std::time::{Duration, Instant};
+
+/// Headless specific extensions to [`Application`]
+///
+/// [`Application`]: crate::Application
+pub trait ApplicationExt {
+    /// Returns `true` if the application is running on the headless backend.
+    fn is_headless(&self) -> bool;
+
+    /// Returns the time on the virtual clock.
+    ///
+    /// On other backends, this is the real time.
+    fn now(&self) -> Instant;
+
+    /// Move the virtual clock forward by `duration`.
+    ///
+    /// The timers and animation frames which become due are handled the next time the event
+    /// loop runs, such as during [`pump_events`](crate::Application::pump_events), in the
+    /// order of their deadlines. This does nothing on other backends.
+    fn advance_clock(&self, duration: Duration);
+}
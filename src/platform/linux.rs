@@ -13,7 +13,11 @@
 // limitations under the License.
 
 //! Linux specific extensions.
-use crate::Clipboard;
+use std::ops::{BitOr, BitOrAssign};
+use std::os::unix::io::RawFd;
+
+use crate::kurbo::Insets;
+use crate::{AppHandler, Clipboard, EventSourceToken};
 
 /// Linux specific extensions to [`Application`]
 ///
@@ -23,14 +27,176 @@ pub trait ApplicationExt {
     ///
     /// This is useful for middle mouse paste.
     fn primary_clipboard(&self) -> Clipboard;
+
+    /// Poll `fd` along with the application's own events, calling `callback` on the main thread
+    /// whenever it is ready to read.
+    ///
+    /// This lets other event loops, such as an async runtime's reactor, be driven from the main
+    /// thread without a thread of their own. The callback is called for as long as `fd` stays
+    /// readable, so it should read everything which is available. The file descriptor is not
+    /// owned by the application, and must stay open until the source is removed with
+    /// [`remove_event_source`](ApplicationExt::remove_event_source).
+    fn add_event_source(
+        &self,
+        fd: RawFd,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> EventSourceToken;
+
+    /// Stop polling an event source added with
+    /// [`add_event_source`](ApplicationExt::add_event_source).
+    fn remove_event_source(&self, token: EventSourceToken);
+}
+
+/// Linux specific extensions to [`WindowBuilder`]
+///
+/// [`WindowBuilder`]: crate::WindowBuilder
+pub trait WindowBuilderExt {
+    /// Build the window as a layer shell surface, for panels, docks, launchers and overlays.
+    ///
+    /// Layer shell surfaces are stacked in layers above or below normal windows, and are
+    /// placed relative to the edges of the screen, as described by `config`. The compositor
+    /// decides their size, which is reported to [`WinHandler::size`] as for any other window.
+    /// A size given to [`WindowBuilder::size`] or [`WindowHandle::set_size`] is a request,
+    /// and is required along any axis the surface isn't anchored to both edges of.
+    /// Operations which only make sense for normal windows, such as changing the window state,
+    /// are ignored.
+    ///
+    /// This is only supported on Wayland, with compositors which implement
+    /// `wlr-layer-shell`. On other compositors, [`WindowBuilder::build`] fails. On X11 this is
+    /// ignored, and a normal window is built.
+    ///
+    /// [`WinHandler::size`]: crate::WinHandler::size
+    /// [`WindowBuilder::size`]: crate::WindowBuilder::size
+    /// [`WindowBuilder::build`]: crate::WindowBuilder::build
+    /// [`WindowHandle::set_size`]: crate::WindowHandle::set_size
+    fn set_layer_shell(self, config: LayerShellConfig) -> Self;
+}
+
+/// How a layer shell surface is placed, see [`WindowBuilderExt::set_layer_shell`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerShellConfig {
+    /// The layer the surface is stacked in.
+    pub layer: Layer,
+    /// The edges of the screen the surface is attached to.
+    ///
+    /// A surface anchored to opposite edges stretches between them, and one anchored to
+    /// neither is centered along that axis.
+    pub anchor: Anchor,
+    /// The distance to keep from each anchored edge, in display points.
+    pub margin: Insets,
+    /// How much space to reserve along the anchored edge, in display points.
+    ///
+    /// Other surfaces which respect exclusive zones, such as maximized windows, are kept out of
+    /// it. Zero reserves nothing, but still keeps the surface out of the zones of others, and
+    /// `-1` lets it extend over them, up to the edge of the screen.
+    pub exclusive_zone: i32,
+    /// Whether the surface receives keyboard input.
+    pub keyboard_interactivity: KeyboardInteractivity,
+    /// Describes what the surface is for, such as `"panel"` or `"notifications"`.
+    ///
+    /// Compositors may use this to apply their own rules to the surface.
+    pub namespace: String,
+}
+
+impl Default for LayerShellConfig {
+    fn default() -> Self {
+        LayerShellConfig {
+            layer: Layer::Top,
+            anchor: Anchor::NONE,
+            margin: Insets::ZERO,
+            exclusive_zone: 0,
+            keyboard_interactivity: KeyboardInteractivity::None,
+            namespace: String::new(),
+        }
+    }
+}
+
+/// The layers of layer shell surfaces, from the bottom of the stack to the top.
+///
+/// Normal windows are between [`Bottom`](Layer::Bottom) and [`Top`](Layer::Top).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// Below everything else, for example for wallpapers.
+    Background,
+    /// Below normal windows.
+    Bottom,
+    /// Above normal windows, for example for panels.
+    Top,
+    /// Above everything, including fullscreen windows. This is used for lock screens and
+    /// notifications.
+    Overlay,
+}
+
+/// A set of screen edges a layer shell surface is anchored to, which can be combined with `|`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Anchor(u32);
+
+impl Anchor {
+    /// Not anchored to any edge, so the surface is centered on the screen.
+    pub const NONE: Anchor = Anchor(0);
+    /// The top edge of the screen.
+    pub const TOP: Anchor = Anchor(1 << 0);
+    /// The bottom edge of the screen.
+    pub const BOTTOM: Anchor = Anchor(1 << 1);
+    /// The left edge of the screen.
+    pub const LEFT: Anchor = Anchor(1 << 2);
+    /// The right edge of the screen.
+    pub const RIGHT: Anchor = Anchor(1 << 3);
+
+    /// Returns the raw `zwlr_layer_surface_v1.anchor` bits.
+    #[inline]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if the set is empty, which is the same as [`NONE`](Self::NONE).
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all the `edges` are in the set.
+    #[inline]
+    pub fn contains(self, edges: Anchor) -> bool {
+        self.0 & edges.0 == edges.0
+    }
+}
+
+impl BitOr for Anchor {
+    type Output = Anchor;
+
+    #[inline]
+    fn bitor(self, rhs: Anchor) -> Anchor {
+        Anchor(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Anchor {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Anchor) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Whether a layer shell surface receives keyboard input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyboardInteractivity {
+    /// The surface never has keyboard focus.
+    None,
+    /// The surface takes all keyboard input while it is shown, for example for a lock screen.
+    /// Only supported in the [`Top`](Layer::Top) and [`Overlay`](Layer::Overlay) layers.
+    Exclusive,
+    /// The surface can be focused like a normal window, for example by clicking it.
+    OnDemand,
 }
 
 #[cfg(test)]
 #[allow(unused_imports)]
 mod test {
-    use crate::Application;
+    use crate::{Application, WindowBuilder};
 
     use super::*;
     use static_assertions as sa;
     sa::assert_impl_all!(Application: ApplicationExt);
+    sa::assert_impl_all!(WindowBuilder: WindowBuilderExt);
 }
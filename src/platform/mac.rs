@@ -14,6 +14,13 @@
 
 //! macOS specific extensions.
 
+use std::ffi::c_void;
+use std::ops::{BitOr, BitOrAssign};
+use std::os::unix::io::RawFd;
+
+use crate::kurbo::Point;
+use crate::{AppHandler, Error, EventSourceToken, HotKey, WindowBuilder, WindowHandle};
+
 /// macOS specific extensions to [`Application`]
 ///
 /// [`Application`]: crate::Application
@@ -28,13 +35,363 @@ pub trait ApplicationExt {
     ///
     /// On platforms with no global application menu, this has no effect.
     fn set_menu(&self, menu: crate::Menu);
+
+    /// Sets the presentation options of the application.
+    ///
+    /// This can be used to hide the dock and the menu bar without making a window fullscreen.
+    /// Setting [`PresentationOptions::DEFAULT`] restores the normal desktop.
+    ///
+    /// Combinations which are rejected by macOS are ignored with a warning,
+    /// see [`PresentationOptions::is_valid`].
+    fn set_presentation_options(&self, options: PresentationOptions);
+
+    /// Watch `fd` with a `CFFileDescriptor` on the main run loop, calling `callback` whenever it
+    /// is ready to read.
+    ///
+    /// This lets other event loops, such as an async runtime's reactor, be driven from the main
+    /// thread without a thread of their own. The source is added to the run loop's common modes,
+    /// so it keeps running while menus are tracked and windows are resized. The callback is
+    /// called again for as long as `fd` stays readable, so it should read everything which is
+    /// available. The file descriptor is not owned by the application, and must stay open until
+    /// the source is removed with [`remove_event_source`](ApplicationExt::remove_event_source).
+    fn add_event_source(
+        &self,
+        fd: RawFd,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> EventSourceToken;
+
+    /// Stop watching an event source added with
+    /// [`add_event_source`](ApplicationExt::add_event_source).
+    fn remove_event_source(&self, token: EventSourceToken);
+
+    /// Sets whether AppKit may put windows into tabs of each other by itself, following the
+    /// user's preference, as `NSWindow`'s `allowsAutomaticWindowTabbing` does.
+    ///
+    /// When this is `false`, windows only become tabs through
+    /// [`WindowExt::add_tabbed_window`], or through the user merging them with the
+    /// [`SystemAction`]s. The default is `true`.
+    fn set_automatic_window_tabbing(&self, allowed: bool);
+}
+
+/// macOS specific extensions to [`WindowBuilder`]
+///
+/// [`WindowBuilder`]: crate::WindowBuilder
+pub trait WindowBuilderExt {
+    /// Sets the identifier of the tab group the window belongs to.
+    ///
+    /// AppKit only shows windows with the same identifier as tabs of each other, whether it puts
+    /// them there itself or the user merges them. Without one, the identifier is shared by all
+    /// the windows of the application.
+    fn set_tabbing_identifier(self, identifier: &str) -> Self;
+}
+
+/// macOS specific extensions to [`WindowHandle`]
+///
+/// [`WindowHandle`]: crate::WindowHandle
+pub trait WindowExt {
+    /// Returns the window's `CAMetalLayer`, installing it as the layer of the view first if this
+    /// is the first call.
+    ///
+    /// The layer replaces whatever layer the view had before, which makes the view layer-hosting.
+    /// Glazier keeps its `contentsScale` and `drawableSize` in sync with the backing scale and
+    /// size of the view, so the layer is always ready to draw at the size last passed to
+    /// [`WinHandler::surface_resized`]. [`WinHandler::paint`] is still called whenever the layer
+    /// needs displaying, which includes every resize: the drawable size changes along with the
+    /// view's frame, without being animated, and the paint happens in the same Core Animation
+    /// transaction, so the old drawable isn't stretched to the new size in the meantime.
+    ///
+    /// The returned pointer is retained on behalf of the caller, who must release it exactly once
+    /// (for example by sending it `release`, or by taking ownership of it with
+    /// `metal::MetalLayer::from_ptr`). Every call returns another reference, which must be
+    /// released separately. Glazier holds a reference of its own until the view is deallocated,
+    /// so the layer stays installed even if the caller releases theirs early.
+    ///
+    /// Returns a null pointer if the window has already been closed, or was built without a
+    /// [managed surface].
+    ///
+    /// [managed surface]: crate::WindowBuilder::with_managed_surface
+    /// [`WinHandler::surface_resized`]: crate::WinHandler::surface_resized
+    /// [`WinHandler::paint`]: crate::WinHandler::paint
+    fn metal_layer(&self) -> *mut c_void;
+
+    /// Sets `wantsExtendedDynamicRangeContent` on the window's `CAMetalLayer`.
+    ///
+    /// This installs the layer if needed, see [`metal_layer`](Self::metal_layer).
+    fn set_wants_extended_dynamic_range(&self, wants: bool);
+
+    /// Sets the color space the contents of the window's `CAMetalLayer` are interpreted in.
+    ///
+    /// This installs the layer if needed, see [`metal_layer`](Self::metal_layer).
+    fn set_colorspace(&self, colorspace: ColorSpace);
+
+    /// Sets `presentsWithTransaction` on the window's `CAMetalLayer`, which synchronizes
+    /// presenting drawables with Core Animation transactions. This avoids the contents lagging
+    /// behind the window edges during live resizing, at the cost of blocking on presentation.
+    ///
+    /// This installs the layer if needed, see [`metal_layer`](Self::metal_layer).
+    fn set_presents_with_transaction(&self, presents_with_transaction: bool);
+
+    /// Sets whether the titlebar is drawn without its background, so that the window's content
+    /// shows through it. This is usually combined with
+    /// [`set_fullsize_content_view`](Self::set_fullsize_content_view).
+    fn set_titlebar_transparent(&self, transparent: bool);
+
+    /// Sets whether the content view covers the whole window, including the area under the
+    /// titlebar, for a custom header with the traffic lights inline.
+    ///
+    /// The titlebar is still there, on top of the content. The content under it gets the mouse
+    /// events, so to keep the window draggable from there, call
+    /// [`WindowHandle::handle_titlebar`] from `pointer_down` over the parts of the header which
+    /// aren't controls.
+    ///
+    /// [`WindowHandle::handle_titlebar`]: crate::WindowHandle::handle_titlebar
+    fn set_fullsize_content_view(&self, fullsize: bool);
+
+    /// Sets whether the window's title is shown in the titlebar.
+    fn set_title_visibility(&self, visible: bool);
+
+    /// Moves the close, minimize and zoom buttons, so that the leftmost of them is at `offset`
+    /// from the top left corner of the window, in display points. `None` puts them back where
+    /// AppKit had them.
+    ///
+    /// AppKit lays out the titlebar again when the window is resized or leaves fullscreen, after
+    /// which the offset is applied again. It isn't applied in fullscreen, where the titlebar
+    /// only appears with the menu bar.
+    fn set_traffic_light_offset(&self, offset: Option<Point>);
+
+    /// Sets whether the close, minimize and zoom buttons are shown.
+    fn set_traffic_lights_visible(&self, visible: bool);
+
+    /// Sets how many animation frames a second the window paints at most, or `None` to paint
+    /// one for every refresh of the display, which is the default.
+    ///
+    /// While [vsync] is on, the frames requested with [`WindowHandle::request_anim_frame`]
+    /// are painted after the display's next refresh, at whatever rate the display the window
+    /// is on runs, which varies on ProMotion displays. With a preferred rate, refreshes are
+    /// skipped so that frames are at least `1 / rate` seconds apart, which still keeps them
+    /// aligned with the display: at 60 frames a second on a 120Hz display, every other refresh
+    /// is painted for.
+    ///
+    /// [vsync]: crate::WindowHandle::set_vsync
+    /// [`WindowHandle::request_anim_frame`]: crate::WindowHandle::request_anim_frame
+    fn set_preferred_frame_rate(&self, rate: Option<f64>);
+
+    /// Builds a window from `builder`, and adds it as a tab of this window.
+    ///
+    /// The new tab is selected, whether or not the builder is visible, and takes the tabbing
+    /// identifier of this window (see [`WindowBuilderExt::set_tabbing_identifier`]). Each tab
+    /// stays a window of its own, with its own handler: switching tabs moves the focus from one
+    /// window to the other, and only the selected tab is visible.
+    fn add_tabbed_window(&self, builder: WindowBuilder) -> Result<WindowHandle, Error>;
+}
+
+/// macOS specific extensions to [`Menu`]
+///
+/// [`Menu`]: crate::Menu
+pub trait MenuExt {
+    /// Add an item which performs `action`, for the standard items of the Window menu.
+    ///
+    /// These items are handled by AppKit, which sends them to the key window, so they never
+    /// reach [`WinHandler::command`].
+    ///
+    /// [`WinHandler::command`]: crate::WinHandler::command
+    fn add_system_item(&mut self, action: SystemAction, text: &str, key: Option<&HotKey>);
+
+    /// Add the "Open Recent" submenu, for the File menu.
+    ///
+    /// It lists the documents added with [`Application::add_recent_document`], which AppKit
+    /// keeps track of along with the Dock menu, most recent first. Choosing one opens it with
+    /// [`AppHandler::open_files`]. The submenu ends with an item titled `clear_text`, such as
+    /// "Clear Menu", which empties the list.
+    ///
+    /// [`Application::add_recent_document`]: crate::Application::add_recent_document
+    /// [`AppHandler::open_files`]: crate::AppHandler::open_files
+    fn add_recent_documents(&mut self, text: &str, clear_text: &str);
+
+    /// Add the Services submenu, for the application menu, which AppKit fills with the
+    /// services of other applications.
+    fn add_services(&mut self, text: &str);
+}
+
+/// An action which AppKit performs for a menu item, see [`MenuExt::add_system_item`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemAction {
+    /// Show or hide the tab bar of the key window, as `toggleTabBar:`.
+    ToggleTabBar,
+    /// Show the tabs of the key window as thumbnails, or go back, as `toggleTabOverview:`.
+    ToggleTabOverview,
+    /// Put all the windows into tabs of the key window, as `mergeAllWindows:`.
+    MergeAllWindows,
+    /// Move the selected tab of the key window into a window of its own, as
+    /// `moveTabToNewWindow:`.
+    MoveTabToNewWindow,
+    /// Select the tab after the selected one, as `selectNextTab:`.
+    SelectNextTab,
+    /// Select the tab before the selected one, as `selectPreviousTab:`.
+    SelectPreviousTab,
+}
+
+/// A color space for the contents of a `CAMetalLayer`.
+///
+/// Each of these corresponds to one of the named `CGColorSpace`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// `kCGColorSpaceSRGB`
+    Srgb,
+    /// `kCGColorSpaceDisplayP3`
+    DisplayP3,
+    /// `kCGColorSpaceExtendedSRGB`, which is sRGB with values outside of `0.0..=1.0` allowed.
+    ExtendedSrgb,
+    /// `kCGColorSpaceExtendedLinearSRGB`, usually used with floating point pixel formats for
+    /// extended dynamic range content.
+    ExtendedLinearSrgb,
+    /// `kCGColorSpaceExtendedLinearDisplayP3`
+    ExtendedLinearDisplayP3,
+}
+
+/// A set of options controlling how the desktop is presented while the application is active.
+///
+/// These correspond to [`NSApplicationPresentationOptions`], and can be combined with `|`.
+///
+/// Not every combination is valid, see [`PresentationOptions::is_valid`]. Some useful ones are:
+///
+/// - `AUTO_HIDE_DOCK | AUTO_HIDE_MENU_BAR` for an immersive mode in which the user can still
+///   reveal the dock and the menu bar by moving the mouse to the edge of the screen.
+/// - `HIDE_DOCK | HIDE_MENU_BAR` to remove both entirely.
+/// - `HIDE_DOCK | HIDE_MENU_BAR | DISABLE_PROCESS_SWITCHING | DISABLE_FORCE_QUIT` for
+///   kiosk-like applications the user shouldn't leave.
+///
+/// [`NSApplicationPresentationOptions`]: https://developer.apple.com/documentation/appkit/nsapplication/presentationoptions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PresentationOptions(u32);
+
+impl PresentationOptions {
+    /// The normal desktop, with the dock and the menu bar visible.
+    pub const DEFAULT: PresentationOptions = PresentationOptions(0);
+    /// The dock is hidden, but appears when the mouse moves to its edge of the screen.
+    pub const AUTO_HIDE_DOCK: PresentationOptions = PresentationOptions(1 << 0);
+    /// The dock is hidden.
+    pub const HIDE_DOCK: PresentationOptions = PresentationOptions(1 << 1);
+    /// The menu bar is hidden, but appears when the mouse moves to the top of the screen.
+    ///
+    /// Requires [`AUTO_HIDE_DOCK`](Self::AUTO_HIDE_DOCK) or [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const AUTO_HIDE_MENU_BAR: PresentationOptions = PresentationOptions(1 << 2);
+    /// The menu bar is hidden.
+    ///
+    /// Requires [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const HIDE_MENU_BAR: PresentationOptions = PresentationOptions(1 << 3);
+    /// All items in the Apple menu are disabled.
+    pub const DISABLE_APPLE_MENU: PresentationOptions = PresentationOptions(1 << 4);
+    /// Switching to other applications with cmd+tab is disabled.
+    ///
+    /// Requires [`AUTO_HIDE_DOCK`](Self::AUTO_HIDE_DOCK) or [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const DISABLE_PROCESS_SWITCHING: PresentationOptions = PresentationOptions(1 << 5);
+    /// The force quit panel (cmd+opt+esc) is disabled.
+    ///
+    /// Requires [`AUTO_HIDE_DOCK`](Self::AUTO_HIDE_DOCK) or [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const DISABLE_FORCE_QUIT: PresentationOptions = PresentationOptions(1 << 6);
+    /// The panel shown when the power button is pressed is disabled.
+    ///
+    /// Requires [`AUTO_HIDE_DOCK`](Self::AUTO_HIDE_DOCK) or [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const DISABLE_SESSION_TERMINATION: PresentationOptions = PresentationOptions(1 << 7);
+    /// The "Hide" menu item of the application menu is disabled.
+    ///
+    /// Requires [`AUTO_HIDE_DOCK`](Self::AUTO_HIDE_DOCK) or [`HIDE_DOCK`](Self::HIDE_DOCK).
+    pub const DISABLE_HIDE_APPLICATION: PresentationOptions = PresentationOptions(1 << 8);
+
+    /// Returns the raw `NSApplicationPresentationOptions` bits.
+    #[inline]
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if the set is empty, which is the same as [`DEFAULT`](Self::DEFAULT).
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all the `options` are in the set.
+    #[inline]
+    pub fn contains(self, options: PresentationOptions) -> bool {
+        self.0 & options.0 == options.0
+    }
+
+    /// Returns `true` if macOS accepts this combination of options.
+    ///
+    /// The dock options are mutually exclusive, as are the menu bar options. Hiding the menu bar
+    /// requires hiding the dock. Auto-hiding the menu bar, and disabling process switching, force
+    /// quit, session termination or hiding the application, all require either hiding or
+    /// auto-hiding the dock.
+    pub fn is_valid(self) -> bool {
+        let any_dock = self.contains(Self::AUTO_HIDE_DOCK) || self.contains(Self::HIDE_DOCK);
+        if self.contains(Self::AUTO_HIDE_DOCK | Self::HIDE_DOCK)
+            || self.contains(Self::AUTO_HIDE_MENU_BAR | Self::HIDE_MENU_BAR)
+        {
+            return false;
+        }
+        if self.contains(Self::HIDE_MENU_BAR) && !self.contains(Self::HIDE_DOCK) {
+            return false;
+        }
+        let needs_dock = [
+            Self::AUTO_HIDE_MENU_BAR,
+            Self::DISABLE_PROCESS_SWITCHING,
+            Self::DISABLE_FORCE_QUIT,
+            Self::DISABLE_SESSION_TERMINATION,
+            Self::DISABLE_HIDE_APPLICATION,
+        ];
+        any_dock || !needs_dock.into_iter().any(|option| self.contains(option))
+    }
+}
+
+impl BitOr for PresentationOptions {
+    type Output = PresentationOptions;
+
+    #[inline]
+    fn bitor(self, rhs: PresentationOptions) -> PresentationOptions {
+        PresentationOptions(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PresentationOptions {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: PresentationOptions) {
+        self.0 |= rhs.0;
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::Application;
+    use crate::{Application, Menu};
 
     use super::*;
     use static_assertions as sa;
     sa::assert_impl_all!(Application: ApplicationExt);
+    sa::assert_impl_all!(WindowHandle: WindowExt);
+    sa::assert_impl_all!(WindowBuilder: WindowBuilderExt);
+    sa::assert_impl_all!(Menu: MenuExt);
+
+    #[test]
+    fn presentation_options_validity() {
+        type P = PresentationOptions;
+        assert!(P::DEFAULT.is_valid());
+        assert!((P::HIDE_DOCK | P::HIDE_MENU_BAR).is_valid());
+        assert!((P::AUTO_HIDE_DOCK | P::AUTO_HIDE_MENU_BAR).is_valid());
+        assert!((P::HIDE_DOCK | P::AUTO_HIDE_MENU_BAR).is_valid());
+        assert!(!P::HIDE_MENU_BAR.is_valid());
+        assert!(!(P::AUTO_HIDE_DOCK | P::HIDE_MENU_BAR).is_valid());
+        assert!(!(P::AUTO_HIDE_DOCK | P::HIDE_DOCK).is_valid());
+        assert!(!P::DISABLE_PROCESS_SWITCHING.is_valid());
+        assert!(!P::DISABLE_FORCE_QUIT.is_valid());
+        assert!(!P::DISABLE_SESSION_TERMINATION.is_valid());
+        assert!(!P::DISABLE_HIDE_APPLICATION.is_valid());
+        assert!((P::AUTO_HIDE_DOCK | P::DISABLE_FORCE_QUIT).is_valid());
+        assert!((P::HIDE_DOCK
+            | P::HIDE_MENU_BAR
+            | P::DISABLE_PROCESS_SWITCHING
+            | P::DISABLE_FORCE_QUIT
+            | P::DISABLE_SESSION_TERMINATION
+            | P::DISABLE_HIDE_APPLICATION)
+            .is_valid());
+    }
 }
@@ -20,5 +20,17 @@
 ))]
 pub mod linux;
 
+#[cfg(any(
+    doc,
+    all(
+        feature = "headless",
+        any(target_os = "freebsd", target_os = "linux", target_os = "openbsd")
+    )
+))]
+pub mod headless;
+
 #[cfg(any(doc, target_os = "macos"))]
 pub mod mac;
+
+#[cfg(any(doc, target_os = "windows"))]
+pub mod windows;
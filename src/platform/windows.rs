@@ -0,0 +1,259 @@
+// Copyright 2021 The Druid Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Windows specific extensions.
+use std::ffi::c_void;
+use std::path::PathBuf;
+
+use crate::{AppHandler, Error, EventSourceToken, ImageBuf};
+
+/// Windows specific extensions to [`Application`]
+///
+/// [`Application`]: crate::Application
+pub trait ApplicationExt {
+    /// Wait for `handle` alongside the message loop, calling `callback` on the main thread
+    /// whenever it is signalled.
+    ///
+    /// `handle` can be any waitable `HANDLE`, such as an event or a waitable timer. The wait is
+    /// made on a thread pool thread, which posts a message to the main thread, and is only
+    /// started again once `callback` has returned. An auto-reset event will only call `callback`
+    /// once per signal, but a manual-reset event should be reset by `callback`, or it will be
+    /// called again straight away. The handle is not owned by the application, and must stay
+    /// valid until the source is removed with
+    /// [`remove_event_source`](ApplicationExt::remove_event_source).
+    fn add_event_source(
+        &self,
+        handle: *mut c_void,
+        callback: impl FnMut(Option<&mut dyn AppHandler>) + 'static,
+    ) -> EventSourceToken;
+
+    /// Stop waiting for an event source added with
+    /// [`add_event_source`](ApplicationExt::add_event_source).
+    fn remove_event_source(&self, token: EventSourceToken);
+
+    /// Sets the jump list, which is shown by right-clicking the application's taskbar button.
+    ///
+    /// This replaces the previous list, and is kept by Windows after the application exits.
+    /// Items which the user has removed from the list are left out, as are icons which can't be
+    /// found.
+    fn set_jump_list(&self, list: JumpList);
+}
+
+/// Windows specific extensions to [`WindowBuilder`]
+///
+/// [`WindowBuilder`]: crate::WindowBuilder
+pub trait WindowBuilderExt {
+    /// Sets how the window's contents reach the screen. The default is
+    /// [`PresentMode::Redirected`].
+    ///
+    /// [`PresentMode::Composition`] needs Windows 8. On older versions the window is built
+    /// redirected, which is logged as a warning.
+    fn with_present_mode(self, mode: PresentMode) -> Self;
+}
+
+/// Windows specific extensions to [`WindowHandle`]
+///
+/// [`WindowHandle`]: crate::WindowHandle
+pub trait WindowExt {
+    /// Sets the colors of the titlebar, its text, and the window border.
+    ///
+    /// This needs Windows 11. On older versions the colors are ignored, which is logged at the
+    /// debug level.
+    fn set_titlebar_colors(&self, colors: TitlebarColors);
+
+    /// Shows progress on the window's taskbar button, with `value` going from 0.0 to 1.0.
+    ///
+    /// A `value` of `None` keeps the last one, and it is ignored in the
+    /// [`Indeterminate`](ProgressState::Indeterminate) state. Progress set before the taskbar
+    /// button exists is shown once it has been created.
+    fn set_taskbar_progress(&self, state: ProgressState, value: Option<f64>);
+
+    /// Shows `icon` over the corner of the window's taskbar button, such as for a badge with a
+    /// count of unread messages, or removes it with `None`.
+    ///
+    /// `description` is read by screen readers. The icon should be 16x16 pixels, at 100%
+    /// scaling.
+    fn set_taskbar_overlay_icon(&self, icon: Option<ImageBuf>, description: &str);
+
+    /// Flashes the window's taskbar button until the window is activated, to get the user's
+    /// attention, or stops flashing it.
+    fn flash_taskbar_button(&self, flash: bool);
+
+    /// Fills the window's background with a system material, such as Mica, or goes back to an
+    /// opaque background with [`Backdrop::None`].
+    ///
+    /// This needs Windows 11 version 22H2, build 22621. The first release of Windows 11, build
+    /// 22000, only has Mica, which it shows for [`Backdrop::MicaAlt`] too. Backdrops which the
+    /// system doesn't have return [`Error::Unsupported`], and the window is left as it was, so
+    /// applications should fall back to painting their own opaque background.
+    ///
+    /// The material is drawn behind the whole window, including the client area, so it only
+    /// shows through where the window's content is transparent. Those pixels must be transparent
+    /// black, `[0, 0, 0, 0]` with premultiplied alpha, and anything partly transparent is
+    /// blended over the material. A surface attached to the window has to be created with an
+    /// alpha mode that keeps the transparency, such as premultiplied alpha through
+    /// DirectComposition, otherwise it covers the material. The material is light or dark
+    /// following the titlebar theme.
+    fn set_system_backdrop(&self, backdrop: Backdrop) -> Result<(), Error>;
+
+    /// Sets how the corners of the window are rounded.
+    ///
+    /// This needs Windows 11. On older versions, whose corners are never rounded,
+    /// [`Error::Unsupported`] is returned.
+    fn set_corner_preference(&self, preference: CornerPreference) -> Result<(), Error>;
+
+    /// Returns the window's `IDCompositionVisual`, for windows built with
+    /// [`PresentMode::Composition`].
+    ///
+    /// Set a swapchain made with `CreateSwapChainForComposition` as the visual's content, such as
+    /// by creating a wgpu surface from the visual. The visual is committed after every call to
+    /// [`WinHandler::paint`], so changes made to it there are shown. The pointer stays valid as
+    /// long as the window.
+    ///
+    /// [`WinHandler::paint`]: crate::WinHandler::paint
+    fn composition_visual(&self) -> Option<*mut c_void>;
+}
+
+/// How a window's contents reach the screen, for [`WindowBuilderExt::with_present_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PresentMode {
+    /// Windows keeps a redirection surface for the window, which GDI and swapchains made for
+    /// the window's `HWND` draw to. The surface is filled with the background when the window
+    /// grows, so resizing can flash before the next frame is presented.
+    #[default]
+    Redirected,
+    /// The window has no redirection surface, and shows a DirectComposition visual hosting the
+    /// application's swapchain, from [`WindowExt::composition_visual`].
+    ///
+    /// The visual keeps showing the last frame until a frame of the new size is presented, so
+    /// resizing doesn't flash, and presenting doesn't tear. Nothing drawn with GDI is shown,
+    /// including [`present_pixels`], which returns [`Error::Unsupported`].
+    ///
+    /// [`present_pixels`]: crate::WindowHandle::present_pixels
+    Composition,
+}
+
+/// How the progress on a taskbar button is shown, for [`WindowExt::set_taskbar_progress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ProgressState {
+    /// No progress is shown.
+    #[default]
+    None,
+    /// A green bar.
+    Normal,
+    /// A bar which moves back and forth, for progress which can't be measured.
+    Indeterminate,
+    /// A yellow bar.
+    Paused,
+    /// A red bar.
+    Error,
+}
+
+/// Colors for [`WindowExt::set_titlebar_colors`], as 8-bit sRGB `[red, green, blue]`.
+///
+/// `None` uses the color the system would use, which follows the titlebar theme.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TitlebarColors {
+    /// The background of the titlebar.
+    pub caption: Option<[u8; 3]>,
+    /// The title.
+    pub text: Option<[u8; 3]>,
+    /// The one pixel border around the window.
+    pub border: Option<[u8; 3]>,
+}
+
+/// The material behind a window, for [`WindowExt::set_system_backdrop`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Backdrop {
+    /// An opaque background.
+    #[default]
+    None,
+    /// Tinted with the desktop wallpaper, for the main window of an application.
+    Mica,
+    /// A stronger tint of the wallpaper, for windows with tabs in the titlebar.
+    MicaAlt,
+    /// Blurs what is behind the window, for transient windows like menus and popups.
+    Acrylic,
+}
+
+/// How the corners of a window are rounded, for [`WindowExt::set_corner_preference`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CornerPreference {
+    /// Windows decides, which rounds the corners of most windows.
+    #[default]
+    Default,
+    /// The corners are square.
+    DoNotRound,
+    /// The corners are rounded, even for windows which Windows would leave square.
+    Round,
+    /// The corners are rounded with a smaller radius, as menus are.
+    RoundSmall,
+}
+
+/// The contents of a jump list, for [`ApplicationExt::set_jump_list`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JumpList {
+    /// A list of documents which Windows keeps, to show first.
+    ///
+    /// Documents are added to these with [`Application::add_recent_document`], or when they are
+    /// opened through the shell. Windows only lists files of types which are registered to the
+    /// application.
+    ///
+    /// [`Application::add_recent_document`]: crate::Application::add_recent_document
+    pub known_category: Option<KnownCategory>,
+    /// Categories of the application's own items, shown after `known_category`.
+    pub categories: Vec<JumpListCategory>,
+    /// The items in the Tasks category, which is shown last.
+    pub tasks: Vec<JumpListItem>,
+}
+
+/// A list of documents which Windows keeps for a [`JumpList`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KnownCategory {
+    /// The documents which were opened most recently.
+    Recent,
+    /// The documents which are opened most often.
+    Frequent,
+}
+
+/// A titled category of a [`JumpList`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JumpListCategory {
+    pub title: String,
+    pub items: Vec<JumpListItem>,
+}
+
+/// An item of a [`JumpList`], which starts the application's executable again when clicked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JumpListItem {
+    pub title: String,
+    /// The command line arguments the executable is started with.
+    pub arguments: String,
+    /// A file containing the icon, and the index of the icon within it, such as in a `.dll`
+    /// or `.exe`. The index of a `.ico` file is 0.
+    pub icon: Option<(PathBuf, i32)>,
+    /// Shown as a tooltip.
+    pub description: String,
+}
+
+#[cfg(test)]
+#[allow(unused_imports)]
+mod test {
+    use crate::{Application, WindowHandle};
+
+    use super::*;
+    use static_assertions as sa;
+    sa::assert_impl_all!(Application: ApplicationExt);
+    sa::assert_impl_all!(WindowHandle: WindowExt);
+}
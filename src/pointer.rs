@@ -426,6 +426,15 @@ pub struct PointerEvent {
 
     // TODO: Should this be here, or only in mouse/pen events?
     pub count: u8,
+
+    /// The positions of the moves which were merged into this one, oldest first.
+    ///
+    /// This is only filled in for moves, and only when the window coalesces pointer events, see
+    /// [`WindowBuilder::with_coalesced_pointer_events`]. Applications which need the whole path
+    /// of the pointer, such as drawing tools, should use these before `pos`.
+    ///
+    /// [`WindowBuilder::with_coalesced_pointer_events`]: crate::WindowBuilder::with_coalesced_pointer_events
+    pub coalesced: Vec<Point>,
 }
 
 // Do we need a way of getting at maxTouchPoints?
@@ -439,6 +448,7 @@ impl Default for PointerEvent {
             button: PointerButton::None,
             focus: false,
             count: 0,
+            coalesced: Vec::new(),
             pointer_id: PointerId(0),
             is_primary: true,
             pointer_type: PointerType::Mouse(MouseInfo {
@@ -462,6 +472,7 @@ impl From<crate::MouseEvent> for PointerEvent {
             button: m.button.into(),
             focus: m.focus,
             count: m.count,
+            coalesced: m.coalesced,
         }
     }
 }
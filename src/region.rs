@@ -1,6 +1,13 @@
 use kurbo::{BezPath, Rect, Shape, Vec2};
 
+/// Regions with more rectangles than this are simplified to their bounding box.
+const MAX_RECTS: usize = 16;
+
 /// A union of rectangles, useful for describing an area that needs to be repainted.
+///
+/// Rectangles are merged as they are added, so that invalidating many small, nearby areas
+/// doesn't produce a large number of tiny rectangles. This means the region may be slightly
+/// larger than the union of the rectangles that were added, but never smaller.
 #[derive(Clone, Debug)]
 pub struct Region {
     rects: Vec<Rect>,
@@ -18,8 +25,26 @@ impl Region {
 
     /// Adds a rectangle to this region.
     pub fn add_rect(&mut self, rect: Rect) {
-        if rect.area() > 0.0 {
-            self.rects.push(rect);
+        if rect.area() <= 0.0 || self.rects.iter().any(|r| contains(*r, rect)) {
+            return;
+        }
+        self.rects.retain(|r| !contains(rect, *r));
+        // Merge with an existing rectangle if that doesn't cover much more than the two do.
+        let merge = self
+            .rects
+            .iter()
+            .position(|r| r.union(rect).area() <= r.area() + rect.area());
+        match merge {
+            Some(ix) => {
+                let merged = self.rects.swap_remove(ix).union(rect);
+                // The merged rectangle may cover others, or be mergeable itself.
+                self.add_rect(merged);
+            }
+            None => self.rects.push(rect),
+        }
+        if self.rects.len() > MAX_RECTS {
+            let bounding_box = self.bounding_box();
+            self.set_rect(bounding_box);
         }
     }
 
@@ -77,7 +102,9 @@ impl Region {
 
     /// Modifies this region by including everything in the other region.
     pub fn union_with(&mut self, other: &Region) {
-        self.rects.extend_from_slice(&other.rects);
+        for rect in &other.rects {
+            self.add_rect(*rect);
+        }
     }
 
     /// Modifies this region by intersecting it with the given rectangle.
@@ -90,6 +117,11 @@ impl Region {
     }
 }
 
+/// Returns `true` if `outer` covers all of `inner`.
+fn contains(outer: Rect, inner: Rect) -> bool {
+    outer.x0 <= inner.x0 && outer.y0 <= inner.y0 && outer.x1 >= inner.x1 && outer.y1 >= inner.y1
+}
+
 impl std::ops::AddAssign<Vec2> for Region {
     fn add_assign(&mut self, rhs: Vec2) {
         for r in &mut self.rects {
@@ -111,3 +143,32 @@ impl From<Rect> for Region {
         Region { rects: vec![rect] }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_covered_and_adjacent_rects() {
+        let mut region = Region::EMPTY;
+        region.add_rect(Rect::new(0.0, 0.0, 10.0, 10.0));
+        region.add_rect(Rect::new(2.0, 2.0, 8.0, 8.0));
+        assert_eq!(region.rects(), &[Rect::new(0.0, 0.0, 10.0, 10.0)]);
+        region.add_rect(Rect::new(10.0, 0.0, 20.0, 10.0));
+        assert_eq!(region.rects(), &[Rect::new(0.0, 0.0, 20.0, 10.0)]);
+        region.add_rect(Rect::new(100.0, 100.0, 110.0, 110.0));
+        assert_eq!(region.rects().len(), 2);
+    }
+
+    #[test]
+    fn simplifies_many_rects() {
+        let mut region = Region::EMPTY;
+        for i in 0..1000 {
+            let x = (i % 40) as f64 * 20.0;
+            let y = (i / 40) as f64 * 20.0;
+            region.add_rect(Rect::new(x, y, x + 1.0, y + 1.0));
+        }
+        assert!(region.rects().len() <= MAX_RECTS);
+        assert!(region.intersects(Rect::new(780.0, 480.0, 781.0, 481.0)));
+    }
+}
@@ -0,0 +1,91 @@
+//! Synthetic input, for testing how applications handle events.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::common_util::Counter;
+use crate::error::Error;
+use crate::text::{simulate_input, TextFieldToken};
+use crate::{KeyEvent, PointerEvent, WinHandler, WindowHandle};
+
+thread_local! {
+    /// The windows which injected events are waiting to be delivered to, by injection.
+    ///
+    /// Events are delivered by idle callbacks, which can't hold a window handle as they can be
+    /// sent between threads. They are run on the main thread, which events are injected from.
+    static TARGETS: RefCell<HashMap<u64, WindowHandle>> = RefCell::new(HashMap::new());
+}
+
+/// An input event which can be delivered to a window with [`Application::inject_event`].
+///
+/// These carry the same events as the platform would deliver, so the caller is responsible
+/// for filling in their modifiers, positions and click counts.
+///
+/// [`Application::inject_event`]: crate::Application::inject_event
+#[derive(Debug, Clone)]
+pub enum InjectedEvent {
+    /// Delivered to [`WinHandler::pointer_move`].
+    PointerMove(PointerEvent),
+    /// Delivered to [`WinHandler::pointer_down`].
+    PointerDown(PointerEvent),
+    /// Delivered to [`WinHandler::pointer_up`].
+    PointerUp(PointerEvent),
+    /// Delivered to [`WinHandler::pointer_leave`].
+    PointerLeave,
+    /// Delivered to [`WinHandler::wheel`].
+    Wheel(PointerEvent),
+    /// Delivered to [`WinHandler::key_down`].
+    ///
+    /// As when a key is pressed, a key which the handler doesn't handle is typed into the
+    /// window's [focused text field].
+    ///
+    /// [focused text field]: crate::WindowHandle::set_focused_text_field
+    KeyDown(KeyEvent),
+    /// Delivered to [`WinHandler::key_up`].
+    KeyUp(KeyEvent),
+    /// Delivered to [`WinHandler::zoom`].
+    Zoom(f64),
+    /// Delivered to [`WinHandler::got_focus`].
+    GotFocus,
+    /// Delivered to [`WinHandler::lost_focus`].
+    LostFocus,
+}
+
+impl InjectedEvent {
+    /// Queue the event to be delivered to `target`'s handler.
+    pub(crate) fn inject(self, target: &WindowHandle) -> Result<(), Error> {
+        static INJECTION_COUNTER: Counter = Counter::new();
+        let idle = target.get_idle_handle().ok_or(Error::WindowDropped)?;
+        let id = INJECTION_COUNTER.next();
+        TARGETS.with(|targets| {
+            let mut targets = targets.borrow_mut();
+            // The events of windows which were closed first are never delivered
+            targets.retain(|_, window| window.is_alive());
+            targets.insert(id, target.clone());
+        });
+        idle.add_idle(move |handler| {
+            let target = TARGETS.with(|targets| targets.borrow_mut().remove(&id));
+            // This is read now, as the handler may have focused another field since
+            let text_field = target.and_then(|target| target.focused_text_field());
+            self.dispatch(handler, text_field);
+        });
+        Ok(())
+    }
+
+    fn dispatch(self, handler: &mut dyn WinHandler, text_field: Option<TextFieldToken>) {
+        match self {
+            InjectedEvent::PointerMove(event) => handler.pointer_move(&event),
+            InjectedEvent::PointerDown(event) => handler.pointer_down(&event),
+            InjectedEvent::PointerUp(event) => handler.pointer_up(&event),
+            InjectedEvent::PointerLeave => handler.pointer_leave(),
+            InjectedEvent::Wheel(event) => handler.wheel(&event),
+            InjectedEvent::KeyDown(event) => {
+                simulate_input(handler, text_field, event);
+            }
+            InjectedEvent::KeyUp(event) => handler.key_up(event),
+            InjectedEvent::Zoom(delta) => handler.zoom(delta),
+            InjectedEvent::GotFocus => handler.got_focus(),
+            InjectedEvent::LostFocus => handler.lost_focus(),
+        }
+    }
+}
@@ -15,12 +15,16 @@
 //! Platform independent window types.
 
 use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::time::Duration;
 
 use crate::application::Application;
+use crate::autosave;
 use crate::backend::window as backend;
-use crate::common_util::Counter;
+use crate::common_util::{self, Counter};
 use crate::dialog::{FileDialogOptions, FileInfo};
 use crate::error::Error;
 use crate::keyboard::KeyEvent;
@@ -29,12 +33,15 @@ use crate::menu::Menu;
 use crate::mouse::{Cursor, CursorDesc, MouseEvent};
 use crate::region::Region;
 use crate::scale::Scale;
-use crate::text::{Event, InputHandler};
+use crate::screen::{Monitor, Screen};
+use crate::text::{Action, Affinity, Event, HitTestPoint, InputHandler, Selection};
 use crate::PointerEvent;
 
-use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
-};
+use instant::Instant;
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle};
+#[cfg(feature = "rwh_05")]
+use rwh_05::{HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle};
+use tracing::error;
 
 /// A token that uniquely identifies a running timer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
@@ -174,15 +181,424 @@ impl fmt::Debug for WindowLevel {
     }
 }
 
+/// Where a window sits in the stacking order, relative to other applications' windows.
+///
+/// This is separate from [`WindowLevel`], which describes a window's relationship to the
+/// parent it was built with. See [`WindowHandle::set_window_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackingLevel {
+    /// Stacked normally, and raised when activated.
+    #[default]
+    Normal,
+    /// Kept below all normal windows.
+    AlwaysOnBottom,
+    /// Kept above all normal windows.
+    AlwaysOnTop,
+    /// Placed on the desktop, below every other window, and never activated by clicks.
+    ///
+    /// This is intended for desktop widgets, and is kept on every workspace where the
+    /// platform supports it.
+    DesktopWidget,
+}
+
+/// What a window is for, which the window manager uses to decide how to place, decorate and
+/// animate it. See [`WindowBuilder::with_window_type`].
+///
+/// On X11, this is the window's `_NET_WM_WINDOW_TYPE`. Other platforms have no such hint, so
+/// they approximate it with the window's style and level, and Wayland and web ignore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WindowType {
+    /// An ordinary top level window.
+    Normal,
+    /// A dialog, which is usually centered over the window it was built with as its parent.
+    Dialog,
+    /// A menu torn off from a menu bar, or shown by it.
+    Menu,
+    /// A tooltip, which is shown without decorations.
+    Tooltip,
+    /// A small persistent window, such as a palette or toolbox, which is kept out of the
+    /// taskbar.
+    Utility,
+    /// A splash screen, shown without decorations while the application starts.
+    Splash,
+    /// A menu which drops down from a control in another window, like a combo box's list.
+    DropDown,
+}
+
+/// A light or dark appearance, see [`WindowHandle::set_titlebar_theme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+/// The direction in which keyboard focus moves, see [`WinHandler::wants_focus_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FocusDirection {
+    /// To the next view or window, as with Tab.
+    Forward,
+    /// To the previous view or window, as with Shift+Tab.
+    Backward,
+}
+
+/// How a modal window or sheet was ended, as returned by [`WindowHandle::run_modal`] or passed
+/// to the completion of [`WindowHandle::begin_sheet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModalResponse {
+    /// The dialog's action was confirmed.
+    Ok,
+    /// The dialog was dismissed, or closed without being ended.
+    Cancel,
+    /// A code of the application's choosing, for example to tell which of several buttons
+    /// ended the dialog.
+    ///
+    /// On macOS, this is passed through as the `NSModalResponse`, so `Other(1)` and `Other(0)`
+    /// come back as [`ModalResponse::Ok`] and [`ModalResponse::Cancel`].
+    Other(i32),
+}
+
+/// A point on the edge or at the center of a popup's anchor rectangle, or the direction in
+/// which a popup extends from that point.
+///
+/// See [`WindowBuilder::set_popup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupAnchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl PopupAnchor {
+    /// How far along each axis this is, from 0 at the top left to 1 at the bottom right.
+    fn fraction(self) -> (f64, f64) {
+        match self {
+            PopupAnchor::Center => (0.5, 0.5),
+            PopupAnchor::Top => (0.5, 0.0),
+            PopupAnchor::Bottom => (0.5, 1.0),
+            PopupAnchor::Left => (0.0, 0.5),
+            PopupAnchor::Right => (1.0, 0.5),
+            PopupAnchor::TopLeft => (0.0, 0.0),
+            PopupAnchor::TopRight => (1.0, 0.0),
+            PopupAnchor::BottomLeft => (0.0, 1.0),
+            PopupAnchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// How a popup is placed relative to its parent, as given to [`WindowBuilder::set_popup`].
+#[derive(Clone)]
+// The web backend ignores popups
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct PopupConfig {
+    pub parent: WindowHandle,
+    pub anchor_rect: Rect,
+    pub anchor: PopupAnchor,
+    pub gravity: PopupAnchor,
+    pub grab: bool,
+}
+
+impl PopupConfig {
+    /// The position of a popup of the given size, relative to its parent.
+    ///
+    /// This is used on platforms where the popup is placed by us; it isn't moved to stay on screen.
+    #[allow(dead_code)] // Unused on Wayland, where the compositor places popups
+    pub fn origin(&self, size: Size) -> Point {
+        let (anchor_x, anchor_y) = self.anchor.fraction();
+        let rect = self.anchor_rect;
+        let point = Point::new(
+            rect.x0 + rect.width() * anchor_x,
+            rect.y0 + rect.height() * anchor_y,
+        );
+        // A popup extending to the bottom right has its top left corner at the anchor point
+        let (gravity_x, gravity_y) = self.gravity.fraction();
+        Point::new(
+            point.x - size.width * (1.0 - gravity_x),
+            point.y - size.height * (1.0 - gravity_y),
+        )
+    }
+}
+
 /// Contains the different states a Window can be in.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WindowState {
     Maximized,
     Minimized,
     Restored,
 }
 
+/// Where a window is on screen, in a form which can be saved and used to put the window back
+/// when the app is next started.
+///
+/// Get it with [`WindowHandle::placement`], and restore it with
+/// [`WindowBuilder::with_placement`]. With the `serde` feature, it can be serialized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowPlacement {
+    /// The [`virtual_rect`] of the monitor the window was on.
+    ///
+    /// Monitors have no identifiers which last across runs, so they are told apart by where
+    /// they are in the virtual screen.
+    ///
+    /// [`virtual_rect`]: Monitor::virtual_rect
+    pub monitor: Rect,
+    /// The window, relative to the top left corner of the monitor's work area.
+    ///
+    /// Its origin is the window's [position], and its size that of its drawing area, as given
+    /// to [`WindowBuilder::size`].
+    ///
+    /// [position]: WindowHandle::get_position
+    pub rect: Rect,
+    /// The state of the window.
+    pub state: WindowState,
+}
+
+impl WindowPlacement {
+    /// Find where the window goes, given the monitors which are there now.
+    ///
+    /// When the monitor it was on is gone, the window is centered on the primary monitor, and
+    /// it is always kept within the work area. This returns `None` if the platform doesn't
+    /// list its monitors.
+    fn restored_rect(&self, monitors: &[Monitor]) -> Option<Rect> {
+        let (work, origin) = match monitors.iter().find(|m| m.virtual_rect() == self.monitor) {
+            Some(monitor) => {
+                let work = monitor.virtual_work_rect();
+                (work, work.origin() + self.rect.origin().to_vec2())
+            }
+            None => {
+                let monitor = monitors
+                    .iter()
+                    .find(|m| m.is_primary())
+                    .or_else(|| monitors.first())?;
+                let work = monitor.virtual_work_rect();
+                (work, work.center() - self.rect.size().to_vec2() / 2.0)
+            }
+        };
+        let size = Size::new(
+            self.rect.width().min(work.width()),
+            self.rect.height().min(work.height()),
+        );
+        let origin = Point::new(
+            origin.x.max(work.x0).min(work.x1 - size.width),
+            origin.y.max(work.y0).min(work.y1 - size.height),
+        );
+        Some(Rect::from_origin_size(origin, size))
+    }
+}
+
+/// The monitor which most of `rect` is on, or the primary monitor if it isn't on any.
+fn monitor_for(monitors: &[Monitor], rect: Rect) -> Option<&Monitor> {
+    let overlap = |m: &Monitor| m.virtual_rect().intersect(rect).area();
+    monitors
+        .iter()
+        .filter(|m| overlap(m) > 0.0)
+        .max_by(|a, b| overlap(a).total_cmp(&overlap(b)))
+        .or_else(|| monitors.iter().find(|m| m.is_primary()))
+}
+
+/// Whether the contents of a window can currently be seen by the user.
+///
+/// See [`WinHandler::visibility_changed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// At least part of the window is on screen.
+    Visible,
+    /// The window is shown, but nothing of it can be seen: it is fully covered by other
+    /// windows, or is on another virtual desktop.
+    Occluded,
+    /// The window is minimized, or has not been shown.
+    Hidden,
+}
+
+/// Who draws a window's titlebar and borders.
+///
+/// See [`WinHandler::decoration_mode_changed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationMode {
+    /// The compositor or window manager draws the decorations.
+    Server,
+    /// The application is responsible for its decorations.
+    Client,
+}
+
+/// When the last frame of a window reached the screen.
+///
+/// See [`WindowHandle::last_frame_timings`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTimings {
+    /// The vsync at which the frame was shown.
+    pub vsync: Option<Instant>,
+    /// How long it took from [`WinHandler::paint`] being called to the frame being shown.
+    pub time_to_present: Option<Duration>,
+    /// Whether the frame was shown at least one refresh later than the vsync following the
+    /// previous frame, even though painting started before that vsync. This is what users
+    /// perceive as a dropped frame.
+    pub missed_deadline: bool,
+}
+
+/// What happened to a frame painted by [`WinHandler::paint`].
+///
+/// See [`WinHandler::frame_presented`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentInfo {
+    /// The number of the frame. Every call to [`WinHandler::paint`] starts a new frame, counting
+    /// up from 1.
+    pub frame: u64,
+    /// When the frame was shown on screen, or `None` if it was dropped without ever being
+    /// shown, usually because a later frame replaced it first.
+    pub presented: Option<Instant>,
+    /// How long it took from [`WinHandler::paint`] being called to the frame being shown.
+    pub time_to_present: Option<Duration>,
+    /// The refresh interval of the display the frame was shown on, if known.
+    pub refresh_interval: Option<Duration>,
+}
+
+/// Determines the area a window occupies when it is maximized.
+///
+/// This is supported on Windows, macOS and X11. Window managers on X11 only maximize windows
+/// to the work area, so a window which is maximized over the whole monitor is moved there by
+/// glazier instead. The window manager then doesn't see it as maximized, and maximizing it from
+/// the window manager again restores it. On Wayland, the compositor decides the maximized size,
+/// so the mode is ignored there, as it is on web.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaximizeMode {
+    /// Fill the work area of the monitor, leaving room for the taskbar, dock or panels.
+    ///
+    /// This is the platform default.
+    #[default]
+    WorkArea,
+    /// Fill the entire monitor, covering the taskbar, dock or panels.
+    ///
+    /// Unlike true fullscreen, other windows can still overlay the maximized window.
+    FullMonitor,
+}
+
+/// The layout of a single pixel in a buffer passed to [`WindowHandle::present_pixels`].
+///
+/// Each pixel is four bytes, with the color channels stored in the order given by the
+/// variant name. The alpha channel is ignored; presented pixels are always opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red, green, blue, alpha.
+    Rgba8,
+    /// Blue, green, red, alpha.
+    Bgra8,
+}
+
+impl PixelFormat {
+    /// The number of bytes in a single pixel.
+    pub const BYTES_PER_PIXEL: usize = 4;
+
+    /// Returns `buf` with its pixels in the BGRA layout, copying only when a swizzle is needed.
+    #[allow(dead_code)]
+    pub(crate) fn to_bgra(self, buf: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            PixelFormat::Bgra8 => Cow::Borrowed(buf),
+            PixelFormat::Rgba8 => {
+                let mut out = buf.to_vec();
+                for px in out.chunks_exact_mut(Self::BYTES_PER_PIXEL) {
+                    px.swap(0, 2);
+                }
+                Cow::Owned(out)
+            }
+        }
+    }
+
+    /// Converts `buf`, which holds pixels in the BGRA layout, to this format in place.
+    #[allow(dead_code)]
+    pub(crate) fn from_bgra(self, mut buf: Vec<u8>) -> Vec<u8> {
+        if self == PixelFormat::Rgba8 {
+            for px in buf.chunks_exact_mut(Self::BYTES_PER_PIXEL) {
+                px.swap(0, 2);
+            }
+        }
+        buf
+    }
+}
+
+/// An image of a window's contents, as returned by [`WindowHandle::capture`].
+///
+/// The pixels are [`Rgba8`](PixelFormat::Rgba8), in rows from top to bottom with no padding
+/// between rows. Their alpha is straight, not premultiplied into the color channels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageBuf {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    scale: Scale,
+}
+
+impl ImageBuf {
+    /// Create an image from `width * height` RGBA pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` has the wrong length.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> ImageBuf {
+        let expected_len = width as usize * height as usize * PixelFormat::BYTES_PER_PIXEL;
+        assert_eq!(
+            pixels.len(),
+            expected_len,
+            "wrong number of pixels for a {width}x{height} image"
+        );
+        ImageBuf {
+            width,
+            height,
+            pixels,
+            scale: Scale::default(),
+        }
+    }
+
+    /// Set the scale the image was made at, which is 1 unless this is called.
+    pub fn with_scale(mut self, scale: Scale) -> ImageBuf {
+        self.scale = scale;
+        self
+    }
+
+    /// The width of the image, in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image, in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The scale the image was made at, which its size can be divided by to get its size in
+    /// display points.
+    ///
+    /// For the images from [`WindowHandle::capture`], this is the window's scale when it was
+    /// captured, or when the captured frame was presented on Wayland.
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    /// The pixels of the image.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Take the pixels of the image.
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+}
+
 /// A handle to a platform window object.
+///
+/// Handles don't keep their window alive, so they can be stored anywhere, for as long as
+/// needed. Once the window has closed, which is already the case when
+/// [`WinHandler::destroy`] is called, its handle's methods do nothing and return default
+/// values. Use [`is_alive`] to tell whether that has happened.
+///
+/// [`is_alive`]: WindowHandle::is_alive
 #[derive(Clone, PartialEq, Eq)]
 pub struct WindowHandle(pub(crate) backend::WindowHandle);
 
@@ -196,25 +612,108 @@ impl Default for WindowHandle {
     }
 }
 
+/// A handle to a window which has to be upgraded before it is used.
+///
+/// A [`WindowHandle`] doesn't keep its window alive either, but its methods quietly do nothing
+/// once the window has closed. Subsystems which outlive their windows, such as renderers and
+/// caches keyed by window, can hold this instead, so that each use starts by checking that the
+/// window is still there, and they can let go of it once it isn't.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct WeakWindowHandle(WindowHandle);
+
+impl WeakWindowHandle {
+    /// Returns a handle to the window, or `None` once it has closed.
+    ///
+    /// This is `None` from the time [`WinHandler::destroy`] is called.
+    pub fn upgrade(&self) -> Option<WindowHandle> {
+        self.0.is_alive().then(|| self.0.clone())
+    }
+}
+
 impl WindowHandle {
     /// Make this window visible.
     ///
     /// This is part of the initialization process; it should only be called
     /// once, when a window is first created.
+    ///
+    /// On Windows and macOS, the window is painted before it is revealed, so the user never
+    /// sees it empty. Wayland compositors only show a window once it has painted, and X11
+    /// windows have no background, so they don't flash either.
     pub fn show(&self) {
         self.0.show()
     }
 
+    /// Show or hide the window.
+    ///
+    /// A hidden window keeps its state, and is still told about changes to its size and scale,
+    /// so an application can finish setting it up before revealing it.
+    ///
+    /// On Wayland, a window is not given a size until it has been shown for the first time.
+    pub fn set_visible(&self, visible: bool) {
+        if visible {
+            self.0.show()
+        } else {
+            self.0.hide()
+        }
+    }
+
     /// Close the window.
     pub fn close(&self) {
         self.0.close()
     }
 
+    /// Returns `true` until the window closes.
+    ///
+    /// This is `false` from the time [`WinHandler::destroy`] is called, and for a default
+    /// handle.
+    pub fn is_alive(&self) -> bool {
+        self.0.is_alive()
+    }
+
+    /// Returns a [`WeakWindowHandle`] to this window.
+    pub fn downgrade(&self) -> WeakWindowHandle {
+        WeakWindowHandle(self.clone())
+    }
+
     /// Set whether the window should be resizable
     pub fn resizable(&self, resizable: bool) {
         self.0.resizable(resizable)
     }
 
+    /// Keeps the width of the window's content area over its height at `ratio` while the user
+    /// resizes it, such as `16.0 / 9.0` for a video. `None` removes the constraint.
+    ///
+    /// The minimum size is grown to the ratio, so the window can't get stuck between the two.
+    /// The window is not resized straight away, and sizes set by the application aren't
+    /// constrained.
+    ///
+    /// This is unimplemented on Wayland, whose shell has no way to ask for it, and on web.
+    pub fn set_aspect_ratio(&self, ratio: Option<f64>) {
+        self.0
+            .set_aspect_ratio(ratio.filter(|ratio| ratio.is_finite() && *ratio > 0.0))
+    }
+
+    /// Makes the window's content area resize in steps of `increments` while the user resizes
+    /// it, such as the size of a character cell for a terminal. `None` removes the constraint.
+    ///
+    /// The content size is then the minimum size plus a whole number of increments on each
+    /// side. As with [`set_aspect_ratio`], the window is not resized straight away, and
+    /// sizes set by the application aren't constrained. Setting resize increments removes the
+    /// aspect ratio, and setting an aspect ratio removes the increments.
+    ///
+    /// On macOS, the steps start from the size the window has when the user starts resizing
+    /// it rather than from the minimum size. This is unimplemented on Wayland and web.
+    ///
+    /// [`set_aspect_ratio`]: WindowHandle::set_aspect_ratio
+    pub fn set_resize_increments(&self, increments: Option<Size>) {
+        self.0.set_resize_increments(increments.filter(|size| {
+            size.width.is_finite()
+                && size.height.is_finite()
+                && size.width > 0.0
+                && size.height > 0.0
+        }))
+    }
+
     /// Sets the state of the window.
     pub fn set_window_state(&mut self, state: WindowState) {
         self.0.set_window_state(state);
@@ -225,12 +724,173 @@ impl WindowHandle {
         self.0.get_window_state()
     }
 
+    /// Returns where the window is, to be saved and restored with
+    /// [`WindowBuilder::with_placement`].
+    ///
+    /// While the window is maximized, its rect is the maximized one on most platforms.
+    pub fn placement(&self) -> WindowPlacement {
+        let position = self.get_position();
+        let size = (self.get_size().to_rect() - self.content_insets()).size();
+        let monitors = Screen::get_monitors();
+        let monitor = monitor_for(&monitors, Rect::from_origin_size(position, size));
+        let work_origin = monitor.map_or(Point::ZERO, |m| m.virtual_work_rect().origin());
+        WindowPlacement {
+            monitor: monitor.map_or(Rect::ZERO, |m| m.virtual_rect()),
+            rect: Rect::from_origin_size(position - work_origin.to_vec2(), size),
+            state: self.get_window_state(),
+        }
+    }
+
+    /// Sets whether the window is kept out of the taskbar and window switcher.
+    ///
+    /// On Windows, this gives the window the narrower titlebar of a tool window. On macOS,
+    /// where the Dock only shows applications, this excludes the window from the window
+    /// cycle, the Window menu and Mission Control. This is currently not implemented on
+    /// Wayland or web.
+    pub fn set_skip_taskbar(&self, skip: bool) {
+        self.0.set_skip_taskbar(skip)
+    }
+
+    /// Sets where the window is kept in the stacking order.
+    ///
+    /// This is currently not implemented on Wayland or web, where the compositor or browser
+    /// decides how windows are stacked.
+    pub fn set_window_level(&self, level: StackingLevel) {
+        self.0.set_window_level(level)
+    }
+
+    /// Sets the theme of the titlebar and window frame, or `None` to follow the system theme.
+    ///
+    /// Windows are drawn in the system theme by default, and follow it when it changes. The
+    /// theme is applied straight away, without waiting for the window to be activated.
+    ///
+    /// On X11, this is a hint which only some window managers follow. It is ignored on Wayland
+    /// and web.
+    pub fn set_titlebar_theme(&self, theme: Option<Theme>) {
+        self.0.set_titlebar_theme(theme)
+    }
+
+    /// Shows `sheet` as a document-modal dialog of this window.
+    ///
+    /// While the sheet is shown, this window gets no keyboard or pointer input. The sheet
+    /// stays until [`end_sheet`] is called on it, after which `completion` is called with the
+    /// response given to `end_sheet`. If the sheet is closed instead, the response is
+    /// [`ModalResponse::Cancel`].
+    ///
+    /// On macOS, the sheet slides out from under this window's titlebar. Elsewhere, it is shown
+    /// as a modal child window centered over this window; on Wayland, the compositor places it,
+    /// and this window still gets input. Sheets are not supported on web.
+    ///
+    /// If this window is closed while the sheet is shown, the sheet is ended with
+    /// [`ModalResponse::Cancel`] and then closed, before this window is destroyed.
+    ///
+    /// [`end_sheet`]: WindowHandle::end_sheet
+    pub fn begin_sheet(
+        &self,
+        sheet: WindowHandle,
+        completion: impl FnOnce(ModalResponse) + 'static,
+    ) {
+        self.0.begin_sheet(&sheet.0, Box::new(completion))
+    }
+
+    /// Builds `builder` and shows the window as a sheet of this one, with [`begin_sheet`].
+    ///
+    /// The new window runs its own [`WinHandler`], which gets keyboard focus, text input and
+    /// resizes like any other window, and which ends the sheet by calling [`end_sheet`] or
+    /// [`close`] on its handle. `completion` is called with the response, as in `begin_sheet`.
+    ///
+    /// Closing this window ends the sheet with [`ModalResponse::Cancel`] and closes it first:
+    ///
+    /// - On macOS, the window is attached with `beginSheet:completionHandler:`, and is ended
+    ///   and closed when this window is about to close.
+    /// - On Windows, the window is owned by this one, which is disabled until the sheet ends.
+    ///   Windows destroys owned windows before their owner.
+    /// - On X11, the window is transient for this one and has `_NET_WM_STATE_MODAL`, and is
+    ///   destroyed along with the windows this one owns.
+    /// - On Wayland, the window is made a child of this one, and is closed before it. The
+    ///   compositor places it, and this window still gets input.
+    /// - On the headless backend, the window is ended and destroyed before this one.
+    /// - On web, sheets aren't supported, so the window is built but is not shown as a sheet.
+    ///
+    /// [`begin_sheet`]: WindowHandle::begin_sheet
+    /// [`end_sheet`]: WindowHandle::end_sheet
+    /// [`close`]: WindowHandle::close
+    pub fn present_modal_child(
+        &self,
+        builder: WindowBuilder,
+        completion: impl FnOnce(ModalResponse) + 'static,
+    ) -> Result<WindowHandle, Error> {
+        let sheet = builder.build()?;
+        self.begin_sheet(sheet.clone(), completion);
+        Ok(sheet)
+    }
+
+    /// Ends the sheet which this window was shown as, with [`begin_sheet`], and hides it.
+    ///
+    /// The window is not closed, so it can be shown as a sheet again.
+    ///
+    /// [`begin_sheet`]: WindowHandle::begin_sheet
+    pub fn end_sheet(&self, response: ModalResponse) {
+        self.0.end_sheet(response)
+    }
+
+    /// Shows the window, and runs a nested event loop until [`end_modal`] is called on it,
+    /// returning the response given to `end_modal`. If the window is closed instead, or the
+    /// application quits, the response is [`ModalResponse::Cancel`].
+    ///
+    /// While the loop runs, the application's other windows get no keyboard or pointer input,
+    /// but timers, idle callbacks and [`AppHandle::run_on_main`] callbacks still run. The window
+    /// whose handler calls this is still borrowed by that call, so it isn't painted and its
+    /// other events are dropped until this returns.
+    ///
+    /// The window stays shown once the loop has ended. On X11, the other windows' input is
+    /// ignored by glazier rather than grabbed, so the window manager can still be used. This is
+    /// not supported on Wayland or web, where it returns [`ModalResponse::Cancel`] straight
+    /// away.
+    ///
+    /// [`end_modal`]: WindowHandle::end_modal
+    /// [`AppHandle::run_on_main`]: crate::AppHandle::run_on_main
+    pub fn run_modal(&self) -> ModalResponse {
+        self.0.run_modal()
+    }
+
+    /// Ends the modal loop which [`run_modal`] is running for this window.
+    ///
+    /// This does nothing if the window isn't running a modal loop.
+    ///
+    /// [`run_modal`]: WindowHandle::run_modal
+    pub fn end_modal(&self, response: ModalResponse) {
+        self.0.end_modal(response)
+    }
+
+    /// Moves keyboard focus out of this window, to whatever comes next in the system's focus
+    /// order.
+    ///
+    /// This is for apps which handle their own focus, once it has gone past their last control.
+    /// On macOS, focus moves to the next view in the window's key view loop, or to another of
+    /// the application's windows if there isn't one. Elsewhere, it moves to another of the
+    /// application's windows. This is not supported on Wayland or web.
+    pub fn focus_next_window(&self) {
+        self.0.focus_next_window()
+    }
+
+    /// Sets the area the window should occupy when it is maximized.
+    ///
+    /// This takes effect the next time the window is maximized. See [`MaximizeMode`] for the
+    /// platforms which support it.
+    pub fn set_maximize_mode(&self, mode: MaximizeMode) {
+        self.0.set_maximize_mode(mode)
+    }
+
     /// Informs the system that the current location of the mouse should be treated as part of the
     /// window's titlebar. This can be used to implement a custom titlebar widget. Note that
     /// because this refers to the current location of the mouse, you should probably call this
     /// function in response to every relevant [`WinHandler::mouse_move`].
     ///
-    /// This is currently only implemented on Windows
+    /// On macOS, this instead starts dragging the window when it is called while handling a press
+    /// of the primary button, such as from [`WinHandler::pointer_down`].
+    ///
+    /// This is currently only implemented on Windows and macOS.
     pub fn handle_titlebar(&self, val: bool) {
         self.0.handle_titlebar(val);
     }
@@ -306,6 +966,11 @@ impl WindowHandle {
     /// render another frame. This differs from [`invalidate`] and [`invalidate_rect`] in that it
     /// doesn't invalidate any part of the window.
     ///
+    /// Any number of calls to this, [`invalidate`] and [`invalidate_rect`] before the next frame
+    /// are coalesced, so the handler gets a single [`prepare_paint`] and [`paint`] for all of
+    /// them. Calls made while the handler paints ask for another frame, which is how animations
+    /// keep going.
+    ///
     /// [`invalidate`]: WindowHandle::invalidate
     /// [`invalidate_rect`]: WindowHandle::invalidate_rect
     /// [`paint`]: WinHandler::paint
@@ -315,15 +980,152 @@ impl WindowHandle {
     }
 
     /// Request invalidation of the entire window contents.
+    ///
+    /// This is cheap to call many times, see [`request_anim_frame`].
+    ///
+    /// [`request_anim_frame`]: WindowHandle::request_anim_frame
     pub fn invalidate(&self) {
         self.0.invalidate();
     }
 
     /// Request invalidation of a region of the window.
+    ///
+    /// Invalidated rectangles accumulate until the next [`paint`], which receives all of them
+    /// as its [`Region`].
+    ///
+    /// [`paint`]: WinHandler::paint
     pub fn invalidate_rect(&self, rect: Rect) {
         self.0.invalidate_rect(rect);
     }
 
+    /// Request invalidation of a non-rectangular region of the window.
+    ///
+    /// This is equivalent to calling [`invalidate_rect`] for each of the region's rectangles.
+    ///
+    /// [`invalidate_rect`]: WindowHandle::invalidate_rect
+    pub fn invalidate_region(&self, region: &Region) {
+        for rect in region.rects() {
+            self.0.invalidate_rect(*rect);
+        }
+    }
+
+    /// Present a CPU-rendered buffer as the contents of the window, without using the GPU.
+    ///
+    /// `buf` holds `width * height` pixels in the given [`PixelFormat`], with rows stored
+    /// top to bottom and tightly packed, so the stride is always `width * 4` bytes.
+    ///
+    /// The buffer is never scaled. `width` and `height` must match the physical size of the
+    /// window, which is [`get_size`] converted to pixels with [`get_scale`]. If they don't,
+    /// usually because the window was resized after the buffer was rendered, an error is
+    /// returned and nothing is presented; render again at the size reported to
+    /// [`WinHandler::size`].
+    ///
+    /// This should be called from within [`WinHandler::paint`].
+    ///
+    /// On X11, the buffer is sent through shared memory when the X server runs on the same
+    /// machine, and shown with the present extension when it is supported. Animation frames
+    /// which are requested before the last buffer is shown are then painted once it has been.
+    ///
+    /// [`get_size`]: WindowHandle::get_size
+    /// [`get_scale`]: WindowHandle::get_scale
+    pub fn present_pixels(
+        &self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<(), Error> {
+        let expected_len = width as usize * height as usize * PixelFormat::BYTES_PER_PIXEL;
+        if buf.len() != expected_len {
+            return Err(anyhow::anyhow!(
+                "pixel buffer is {} bytes, expected {expected_len} for {width}x{height}",
+                buf.len()
+            )
+            .into());
+        }
+        self.0.present_pixels(buf, width, height, format)
+    }
+
+    /// Read back the contents of an [offscreen] window, as last presented with
+    /// [`present_pixels`].
+    ///
+    /// The result holds the window's physical size in pixels, in rows from top to bottom, with
+    /// no padding between rows. Parts of the window which haven't been presented yet are black.
+    ///
+    /// This returns [`Error::Unsupported`] for windows which aren't offscreen, and on
+    /// platforms which don't support offscreen windows.
+    ///
+    /// [offscreen]: WindowBuilder::offscreen
+    /// [`present_pixels`]: WindowHandle::present_pixels
+    pub fn read_back_pixels(&self, format: PixelFormat) -> Result<Vec<u8>, Error> {
+        self.0.read_back_pixels(format).map_err(Into::into)
+    }
+
+    /// Capture what the window currently shows, at its physical size in pixels.
+    ///
+    /// Unlike [`read_back_pixels`], this works for any window, however it was painted. It is
+    /// meant for screenshot features and visual regression tests.
+    ///
+    /// The image is in physical pixels, so divide its size by its [`scale`] to compare it with
+    /// the window's size in display points. No permission to record the screen is needed, as
+    /// only our own window is captured.
+    ///
+    /// Returns `None` if the window can't be captured. This is the case for windows which are
+    /// protected from capture, and on platforms without support.
+    ///
+    /// - On macOS, this asks the window server for the window's image, which includes the
+    ///   parts covered by other windows. Windows whose `sharingType` is
+    ///   `NSWindowSharingNone` aren't captured.
+    /// - On Windows, this uses `PrintWindow`, which also renders the parts covered by other
+    ///   windows. Windows with a display affinity set by `SetWindowDisplayAffinity` aren't
+    ///   captured.
+    /// - On X11, this reads the window with `GetImage`. Without a compositor, parts of the
+    ///   window covered by other windows may be missing.
+    /// - On Wayland, clients can't read back what the compositor shows, so this only works
+    ///   for windows drawn with [`present_pixels`], and returns the last frame presented.
+    /// - This is not yet supported on the web.
+    ///
+    /// [`present_pixels`]: WindowHandle::present_pixels
+    /// [`read_back_pixels`]: WindowHandle::read_back_pixels
+    /// [`scale`]: ImageBuf::scale
+    pub fn capture(&self) -> Option<ImageBuf> {
+        self.0.capture()
+    }
+
+    /// Set whether presenting the window's contents should wait for the display's vertical
+    /// blank. Vsync is on by default.
+    ///
+    /// Turning it off trades tearing for lower latency, which is useful for latency sensitive
+    /// tools and for benchmarking. The change takes effect from the next present, so it is
+    /// safe to toggle while painting.
+    ///
+    /// Renderers which own a GPU surface should check [`vsync`] when presenting, and reconfigure
+    /// their surface (for example to wgpu's `PresentMode::Immediate`) when it changes.
+    ///
+    /// What glazier itself can do depends on the platform, and the request may be ignored:
+    ///
+    /// - On macOS, this sets `displaySyncEnabled` on the layer from
+    ///   `platform::mac::WindowExt::metal_layer`. The window server only lets that tear in
+    ///   fullscreen. Animation frames are also painted when they're requested instead of after
+    ///   the display's next refresh.
+    /// - On Wayland, animation frames are painted as soon as they're requested instead of
+    ///   waiting for the compositor's frame callback. The compositor never lets the window
+    ///   tear.
+    /// - On Windows, DWM composites [`present_pixels`] at the vertical blank either way.
+    /// - On X11, [`present_pixels`] shows frames as soon as possible instead of at the next
+    ///   vertical blank, if the server supports the present extension.
+    ///
+    /// [`vsync`]: WindowHandle::vsync
+    /// [`present_pixels`]: WindowHandle::present_pixels
+    pub fn set_vsync(&self, enabled: bool) {
+        self.0.set_vsync(enabled)
+    }
+
+    /// Returns whether vsync is on, as set by [`set_vsync`](WindowHandle::set_vsync).
+    pub fn vsync(&self) -> bool {
+        self.0.vsync()
+    }
+
     /// Set the title for this menu.
     pub fn set_title(&self, title: &str) {
         self.0.set_title(title)
@@ -363,6 +1165,14 @@ impl WindowHandle {
         self.0.set_focused_text_field(active_field)
     }
 
+    /// The text field last passed to [`set_focused_text_field`].
+    ///
+    /// [`set_focused_text_field`]: WindowHandle::set_focused_text_field
+    #[cfg(feature = "testing")]
+    pub(crate) fn focused_text_field(&self) -> Option<TextFieldToken> {
+        self.0.focused_text_field()
+    }
+
     /// Notify the platform that some text input state has changed, such as the
     /// selection, contents, etc.
     ///
@@ -373,6 +1183,28 @@ impl WindowHandle {
         self.0.update_text_field(token, update)
     }
 
+    /// Show the system's emoji and symbol picker.
+    ///
+    /// Whatever the user picks is inserted into the focused text field, through its
+    /// [`InputHandler`] like any other text input, so a text field should be focused first with
+    /// [`set_focused_text_field`].
+    ///
+    /// # Platform-specific
+    ///
+    /// - **macOS**: This shows the Character Viewer. Applications usually offer it as
+    ///   **Edit ▸ Emoji & Symbols**, with the ⌃⌘Space shortcut.
+    /// - **Windows**: This shows the emoji panel, by pressing <kbd>Win</kbd>+<kbd>.</kbd> for the
+    ///   user. Nothing happens unless the window is in the foreground.
+    /// - **Linux**: There is no standard picker, so this does nothing. Some input methods have
+    ///   one of their own, such as IBus's <kbd>Ctrl</kbd>+<kbd>.</kbd>.
+    /// - **Web**: This does nothing.
+    ///
+    /// [`InputHandler`]: crate::text::InputHandler
+    /// [`set_focused_text_field`]: WindowHandle::set_focused_text_field
+    pub fn show_character_palette(&self) {
+        self.0.show_character_palette()
+    }
+
     /// Schedule a timer.
     ///
     /// This causes a [`WinHandler::timer`] call at the deadline. The
@@ -435,6 +1267,51 @@ impl WindowHandle {
         self.0.get_scale().map_err(Into::into)
     }
 
+    /// Render the window's surface at `scale` pixels per display point, instead of at the
+    /// scale of its monitor, or follow the monitor again with `None`.
+    ///
+    /// This trades quality for speed, such as by rendering at 1x on a HiDPI display, or the
+    /// other way around by supersampling. The window keeps its size in display points, and
+    /// input positions are unaffected. The handler is told the new scale through
+    /// [`WinHandler::scale`], and the size of the surface through
+    /// [`WinHandler::surface_resized`], which keep reflecting the override from then on.
+    /// [`get_scale`](WindowHandle::get_scale) still returns the monitor's scale.
+    ///
+    /// On macOS, the `CAMetalLayer` is drawn at the overridden scale, and the compositor scales
+    /// it to the screen. On Windows and X11, the window's own surface stays at the monitor's
+    /// scale, so this is for renderers which size their swapchain to `surface_resized` and let
+    /// it be stretched to the window, such as with `DXGI_SCALING_STRETCH`.
+    ///
+    /// A scale which isn't positive is treated as `None`. This is ignored on Wayland and web.
+    pub fn set_backing_scale_override(&self, scale: Option<f64>) {
+        self.0
+            .set_backing_scale_override(scale.filter(|scale| *scale > 0.0))
+    }
+
+    /// Get the timings of the last frame which reached the screen, for logging jank.
+    ///
+    /// The timings come from the display link on macOS, DWM's composition timing info on
+    /// Windows, the presentation-time protocol on Wayland and the Present extension on X11, so
+    /// they are only as precise as those. They aren't available where the compositor or X
+    /// server doesn't support them, and then this returns the default. On the web, only
+    /// `vsync` is known, as the start of the animation frame which the last paint ran in.
+    ///
+    /// On macOS, the display link is only started by the first call to this (or by
+    /// [`set_present_feedback`]), so that windows which don't ask for timings don't pay for it.
+    ///
+    /// [`set_present_feedback`]: WindowHandle::set_present_feedback
+    pub fn last_frame_timings(&self) -> FrameTimings {
+        self.0.last_frame_timings()
+    }
+
+    /// Set whether [`WinHandler::frame_presented`] should be called for every frame.
+    ///
+    /// This is off by default, because it costs a display link on macOS and a timer polling DWM
+    /// while frames are pending on Windows. Frames painted while it is off aren't reported.
+    pub fn set_present_feedback(&self, enabled: bool) {
+        self.0.set_present_feedback(enabled)
+    }
+
     /// If and only if the AccessKit adapter has been initialized, call
     /// the provided function and apply the resulting update. The update must
     /// reflect all changes since the last tree returned by the handler's
@@ -449,12 +1326,30 @@ impl WindowHandle {
     }
 }
 
+/// The handle is only available while the window is alive. Once the window has been
+/// destroyed, this returns [`HandleError::Unavailable`].
+impl HasWindowHandle for WindowHandle {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        self.0.window_handle()
+    }
+}
+
+/// The handle is only available while the window is alive. Once the window has been
+/// destroyed, this returns [`HandleError::Unavailable`].
+impl HasDisplayHandle for WindowHandle {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        self.0.display_handle()
+    }
+}
+
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawWindowHandle for WindowHandle {
     fn raw_window_handle(&self) -> RawWindowHandle {
         self.0.raw_window_handle()
     }
 }
 
+#[cfg(feature = "rwh_05")]
 unsafe impl HasRawDisplayHandle for WindowHandle {
     fn raw_display_handle(&self) -> RawDisplayHandle {
         self.0.raw_display_handle()
@@ -462,7 +1357,7 @@ unsafe impl HasRawDisplayHandle for WindowHandle {
 }
 
 /// A builder type for creating new windows.
-pub struct WindowBuilder(backend::WindowBuilder);
+pub struct WindowBuilder(pub(crate) backend::WindowBuilder);
 
 impl WindowBuilder {
     /// Create a new `WindowBuilder`.
@@ -476,6 +1371,7 @@ impl WindowBuilder {
     ///
     /// This is the object that will receive callbacks from this window.
     pub fn handler(mut self, handler: Box<dyn WinHandler>) -> Self {
+        let handler: Box<dyn WinHandler> = Box::new(GuardedHandler::new(handler));
         self.0 = self.0.handler(handler);
         self
     }
@@ -520,6 +1416,17 @@ impl WindowBuilder {
         self
     }
 
+    /// Set whether the application draws its own decorations.
+    ///
+    /// This only affects Wayland, where compositors may leave decorations to the client.
+    /// By default, glazier then draws a fallback frame around the window. Applications which
+    /// draw their own titlebar should set this, and follow
+    /// [`WinHandler::decoration_mode_changed`].
+    pub fn prefer_client_side_decorations(mut self, prefer: bool) -> Self {
+        self.0 = self.0.prefer_client_side_decorations(prefer);
+        self
+    }
+
     /// Set whether the window background should be transparent
     pub fn transparent(mut self, transparent: bool) -> Self {
         self.0 = self.0.transparent(transparent);
@@ -543,9 +1450,57 @@ impl WindowBuilder {
         self
     }
 
-    /// Set the window's initial title.
-    pub fn title(mut self, title: impl Into<String>) -> Self {
-        self.0 = self.0.title(title);
+    /// Set what the window is for, so that the window manager treats it accordingly.
+    ///
+    /// By default, the type follows from the window's [`level`], and windows which don't
+    /// [accept focus] are utility windows. This doesn't change how the window is stacked
+    /// relative to its parent, which is still up to the level.
+    ///
+    /// [`level`]: WindowBuilder::level
+    /// [accept focus]: WindowBuilder::with_accepts_focus
+    pub fn with_window_type(mut self, window_type: WindowType) -> Self {
+        self.0 = self.0.with_window_type(window_type);
+        self
+    }
+
+    /// Make this window a popup, such as a tooltip or a completion list, attached to `parent`.
+    ///
+    /// The popup is placed at the `anchor` point of `anchor_rect`, which is in the parent's
+    /// coordinates, and extends from there in the direction of `gravity`. Popups can be the
+    /// parents of other popups, such as a submenu of a dropdown.
+    ///
+    /// With `grab`, the popup is dismissed when the user clicks outside of it, and its handler
+    /// gets [`WinHandler::popup_dismissed`]. On Wayland, this is only allowed in response to
+    /// user input, such as the click which opened the popup; the compositor may also take the
+    /// keyboard focus from the parent until the popup is dismissed.
+    ///
+    /// On Wayland, the compositor places the popup, and flips or slides it to keep it on screen.
+    /// Elsewhere, this overrides the [`level`] and [`position`] of the window, and grabbing is
+    /// not yet implemented on macOS.
+    ///
+    /// [`level`]: WindowBuilder::level
+    /// [`position`]: WindowBuilder::position
+    pub fn set_popup(
+        mut self,
+        parent: &WindowHandle,
+        anchor_rect: Rect,
+        anchor: PopupAnchor,
+        gravity: PopupAnchor,
+        grab: bool,
+    ) -> Self {
+        self.0 = self.0.popup(PopupConfig {
+            parent: parent.clone(),
+            anchor_rect,
+            anchor,
+            gravity,
+            grab,
+        });
+        self
+    }
+
+    /// Set the window's initial title.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.0 = self.0.title(title);
         self
     }
 
@@ -561,12 +1516,235 @@ impl WindowBuilder {
         self
     }
 
+    /// Put the window where it was when its [`placement`] was saved.
+    ///
+    /// This sets the window's [`position`], [`size`] and [`window_state`]. If the monitor it was
+    /// on is no longer there, it is centered on the primary monitor instead, and it is moved
+    /// and shrunk to fit in the monitor's work area. A window which was minimized is restored.
+    ///
+    /// [`placement`]: WindowHandle::placement
+    /// [`position`]: WindowBuilder::position
+    /// [`size`]: WindowBuilder::size
+    /// [`window_state`]: WindowBuilder::window_state
+    pub fn with_placement(self, placement: WindowPlacement) -> Self {
+        let state = match placement.state {
+            WindowState::Minimized => WindowState::Restored,
+            state => state,
+        };
+        let builder = self.window_state(state);
+        match placement.restored_rect(&Screen::get_monitors()) {
+            Some(rect) => builder.position(rect.origin()).size(rect.size()),
+            None => builder.size(placement.rect.size()),
+        }
+    }
+
+    /// Save where the window is under `name`, and put it back there when a window is next
+    /// built with the same name, as [`with_placement`] would.
+    ///
+    /// This overrides the [`position`], [`size`] and [`window_state`] set before it, if a
+    /// placement was saved. On macOS, this is the window's frame autosave name, and AppKit
+    /// saves and restores the frame. On Windows, the placement is saved when the user finishes
+    /// moving or resizing the window, and when it is closed. On X11, it is saved when the
+    /// window is closed, along with whether it is fullscreen. A window which is closed while
+    /// minimized comes back as a normal window. It is kept in a file in the user's local
+    /// application data, or XDG state directory, under the id given to
+    /// [`Application::new_with_name`] or else the executable's name. Windows on Wayland can't
+    /// tell where they are, so it isn't saved there, and it is ignored on web.
+    ///
+    /// Applications which keep their own settings can store a [`WindowPlacement`] themselves
+    /// instead. Setting the `GLAZIER_DISABLE_FRAME_AUTOSAVE` environment variable turns this
+    /// off, such as for tests.
+    ///
+    /// [`with_placement`]: WindowBuilder::with_placement
+    /// [`position`]: WindowBuilder::position
+    /// [`size`]: WindowBuilder::size
+    /// [`window_state`]: WindowBuilder::window_state
+    pub fn with_frame_autosave_name(self, name: &str) -> Self {
+        let (builder, fullscreen) = match autosave::load(name) {
+            Some(saved) => (self.with_placement(saved.placement), saved.fullscreen),
+            None => (self, false),
+        };
+        WindowBuilder(builder.0.with_frame_autosave_name(name, fullscreen))
+    }
+
+    /// Sets the area the window should occupy when it is maximized.
+    ///
+    /// See [`WindowHandle::set_maximize_mode`] for more information.
+    pub fn maximize_mode(mut self, mode: MaximizeMode) -> Self {
+        self.0 = self.0.maximize_mode(mode);
+        self
+    }
+
+    /// Set whether the window should be created in kiosk mode.
+    ///
+    /// A kiosk window has no decorations, covers the whole monitor and stays above other
+    /// windows. The user can't move, resize, minimize or close it, and platform close requests
+    /// are not forwarded to [`WinHandler::request_close`].
+    ///
+    /// The application can still close the window with [`WindowHandle::close`].
+    pub fn kiosk(mut self, kiosk: bool) -> Self {
+        self.0 = self.0.kiosk(kiosk);
+        self
+    }
+
+    /// Set whether the window should be shown as soon as it is built.
+    ///
+    /// By default, windows are built hidden on every platform, and are revealed with
+    /// [`WindowHandle::show`] or [`WindowHandle::set_visible`]. This allows the application
+    /// to finish setting the window up before the user sees it. On web, the canvas is always
+    /// visible.
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.0 = self.0.with_visible(visible);
+        self
+    }
+
+    /// Set whether the window should be kept out of the taskbar and window switcher.
+    ///
+    /// This is meant for utility windows like tool palettes and splash screens. The window
+    /// can still be focused. See [`WindowHandle::set_skip_taskbar`].
+    pub fn with_skip_taskbar(mut self, skip: bool) -> Self {
+        self.0 = self.0.with_skip_taskbar(skip);
+        self
+    }
+
+    /// Set whether the window can take keyboard focus.
+    ///
+    /// A window which doesn't accept focus still receives pointer events, but clicking it
+    /// leaves the focused window, and the active application, as they were. This is meant
+    /// for floating palettes, like color pickers and inspectors. The default is `true`.
+    ///
+    /// On macOS, such a window is an `NSPanel`. This is ignored on Wayland and web.
+    pub fn with_accepts_focus(mut self, accepts_focus: bool) -> Self {
+        self.0 = self.0.with_accepts_focus(accepts_focus);
+        self
+    }
+
+    /// Make this window owned by `parent`, like a dialog or a tool window.
+    ///
+    /// An owned window stays above its parent and is closed along with it. Where the platform
+    /// does so, it is also minimized and restored with its parent. Unlike the parent of a
+    /// [`level`] or a popup, this doesn't make the window's position relative to the parent.
+    ///
+    /// On Wayland, owned windows aren't closed with their parent. This is ignored on web.
+    ///
+    /// [`level`]: WindowBuilder::level
+    pub fn with_parent(mut self, parent: WindowHandle) -> Self {
+        self.0 = self.0.with_parent(parent);
+        self
+    }
+
+    /// Set whether the window should be created offscreen, for testing.
+    ///
+    /// An offscreen window is never shown to the user and receives no input, but otherwise
+    /// behaves like a visible window: it gets sized, painted and can present pixels, which can
+    /// be read back with [`WindowHandle::read_back_pixels`]. This allows rendering to be tested
+    /// in CI, without a display being looked at.
+    ///
+    /// This is currently supported on X11 and Windows, and ignored elsewhere.
+    pub fn offscreen(mut self, offscreen: bool) -> Self {
+        self.0 = self.0.offscreen(offscreen);
+        self
+    }
+
+    /// Set whether glazier manages the window's drawing surface. The default is `true`.
+    ///
+    /// A managed surface is the one which glazier presents to: the pixels given to
+    /// [`WindowHandle::present_pixels`], and on macOS the `CAMetalLayer` from
+    /// `platform::mac::WindowExt::metal_layer`, whose drawable glazier keeps at the view's size
+    /// and scale.
+    ///
+    /// Without one, glazier creates the native window and delivers its input and paint
+    /// callbacks, but never creates or presents to a surface itself. The application attaches
+    /// its own through the raw window handle, such as a wgpu surface, and is responsible for
+    /// resizing it: [`WinHandler::surface_resized`] is called with the new size in pixels
+    /// whenever it should be reconfigured. `present_pixels` then returns an error, and
+    /// `metal_layer` returns null.
+    ///
+    /// This is ignored on web.
+    pub fn with_managed_surface(mut self, managed: bool) -> Self {
+        self.0 = self.0.with_managed_surface(managed);
+        self
+    }
+
+    /// Set whether input on Windows comes from the pointer messages (`WM_POINTER*`), instead of
+    /// the legacy mouse messages. The default is `false`, but this is expected to change once
+    /// the pointer messages have had more testing.
+    ///
+    /// The pointer messages deliver pens and touches as themselves, with the pressure, tilt and
+    /// contact size that the pointer events describe, along with the moves which Windows merged
+    /// into each message (see [`with_coalesced_pointer_events`]). With the legacy messages,
+    /// they arrive as a mouse.
+    ///
+    /// [`with_coalesced_pointer_events`]: WindowBuilder::with_coalesced_pointer_events
+    ///
+    /// This needs Windows 8 or later, and is ignored on older versions and other platforms. It
+    /// also makes the mouse send pointer messages for the rest of the process, which windows
+    /// built without it turn back into mouse messages, so they are unaffected.
+    pub fn use_pointer_api(mut self, enable: bool) -> Self {
+        self.0 = self.0.use_pointer_api(enable);
+        self
+    }
+
+    /// Set whether pointer moves which arrive together are merged into one event. The default
+    /// is `true`.
+    ///
+    /// When they are merged, the handler gets only the latest position of each pointer for the
+    /// moves which are waiting to be handled, and the positions it skipped are in
+    /// [`PointerEvent::coalesced`]. This keeps fast mice from flooding the handler, without
+    /// losing the path which drawing applications need. Otherwise, every move is its own event.
+    ///
+    /// This is ignored on Wayland and web.
+    pub fn with_coalesced_pointer_events(mut self, coalesce: bool) -> Self {
+        self.0 = self.0.with_coalesced_pointer_events(coalesce);
+        self
+    }
+
     /// Attempt to construct the platform window.
     ///
+    /// Options which can't be used together, such as a kiosk popup or, on X11, a
+    /// [`transparent`] window without a compositing manager, are rejected with
+    /// [`Error::IncompatibleOptions`] before anything is created.
+    ///
     /// If this fails, your application should exit.
+    ///
+    /// [`transparent`]: WindowBuilder::transparent
     pub fn build(self) -> Result<WindowHandle, Error> {
         self.0.build().map(WindowHandle).map_err(Into::into)
     }
+
+    /// Construct the window inside a window which belongs to someone else, such as the editor
+    /// of an audio plugin, which its host gives a window to draw in.
+    ///
+    /// The window has no frame, and is placed relative to `parent`. Without a
+    /// [`size`](WindowBuilder::size), it fills `parent`, and the host should resize it with
+    /// [`WindowHandle::set_size`] as `parent` changes size. Painting, input, scale changes and
+    /// text input work as in other windows, but there is no titlebar, menu or window state, so
+    /// those are ignored. A kiosk, offscreen, popup or (on X11) transparent window can't be a
+    /// child, and returns [`Error::IncompatibleOptions`].
+    ///
+    /// The host usually runs the event loop, so use [`Application::pump_events`] rather than
+    /// [`Application::run`]:
+    ///
+    /// - On Windows and macOS, the host's loop dispatches the window's messages and events
+    ///   itself, so pumping is only needed to run [`AppHandle::run_on_main`] callbacks sooner.
+    /// - On X11, the window's events arrive on glazier's own connection, so the host should call
+    ///   `pump_events` with a zero timeout whenever it gives the plugin time, such as from a
+    ///   timer, or when the connection's file descriptor is readable.
+    ///
+    /// `parent` must be a Win32 window on Windows, an `NSView` on macOS, or an Xlib or XCB window
+    /// on X11. This isn't supported on Wayland or the web, where it returns
+    /// [`Error::Unsupported`].
+    ///
+    /// [`AppHandle::run_on_main`]: crate::AppHandle::run_on_main
+    pub fn build_as_child(
+        self,
+        parent: raw_window_handle::RawWindowHandle,
+    ) -> Result<WindowHandle, Error> {
+        self.0
+            .build_as_child(parent)
+            .map(WindowHandle)
+            .map_err(Into::into)
+    }
 }
 
 /// App behavior, supplied by the app.
@@ -592,6 +1770,96 @@ pub trait WinHandler {
     #[allow(unused_variables)]
     fn scale(&mut self, scale: Scale) {}
 
+    /// Called when the [`Visibility`] of the window changes.
+    ///
+    /// The initial visibility is reported immediately after [`connect`](WinHandler::connect).
+    /// Backends only report actual changes, so this doesn't flap while a window is moved.
+    /// While the window isn't [`Visible`](Visibility::Visible), there's little point in
+    /// continuing to [`request_anim_frame`](WindowHandle::request_anim_frame).
+    ///
+    /// How precisely occlusion is detected depends on the platform:
+    /// - On macOS, this follows the window's occlusion state.
+    /// - On Windows, windows on another virtual desktop are occluded, but windows covered by
+    ///   other windows are still reported as visible.
+    /// - On X11, this follows `VisibilityNotify`. Compositing window managers usually report
+    ///   windows as always visible.
+    /// - On Wayland, the compositor doesn't tell us, so `Occluded` is inferred when frame
+    ///   callbacks stop arriving while we are animating, and `Hidden` is never reported once
+    ///   the window has been shown.
+    /// - On the web, this follows the page's visibility, so pages in a background tab or a
+    ///   minimized browser window are `Occluded`. Browsers don't run animation frames for them.
+    #[allow(unused_variables)]
+    fn visibility_changed(&mut self, visibility: Visibility) {}
+
+    /// Called when the compositor has decided who draws this window's decorations.
+    ///
+    /// This is only called on Wayland, where decorations are negotiated with the compositor.
+    /// It is called with the initial mode before the window is first shown, and again when
+    /// the compositor changes its mind.
+    ///
+    /// [`Client`](DecorationMode::Client) decorations are drawn by the application if it
+    /// asked for them with [`WindowBuilder::prefer_client_side_decorations`]. Otherwise,
+    /// glazier draws a simple fallback frame.
+    #[allow(unused_variables)]
+    fn decoration_mode_changed(&mut self, mode: DecorationMode) {}
+
+    /// Called when a frame painted by [`paint`] has been shown on screen, or was dropped.
+    ///
+    /// This is only called once enabled with [`WindowHandle::set_present_feedback`]. Frames are
+    /// reported in the order they were painted, and together with
+    /// [`request_anim_frame`](WindowHandle::request_anim_frame) this is enough to build a frame
+    /// pacer.
+    ///
+    /// Where the platform only tells us when the window's contents were last updated on screen,
+    /// which is the case on macOS, Windows and X11, the last frame painted before then is
+    /// reported as presented, and any frames painted before it as dropped. On Wayland, the
+    /// compositor reports on every frame.
+    ///
+    /// [`paint`]: WinHandler::paint
+    #[allow(unused_variables)]
+    fn frame_presented(&mut self, info: PresentInfo) {}
+
+    /// Called when the platform surface backing the window has changed size.
+    ///
+    /// The `size` parameter is the new size of the surface in physical pixels. This is where a
+    /// GPU swapchain should be reconfigured.
+    ///
+    /// This is always called synchronously, immediately after the accompanying
+    /// [`size`](WinHandler::size), and before the window is next painted. The surface has
+    /// already taken its new dimensions when this is called on Windows, macOS and X11. On
+    /// Wayland, the surface only takes its new size once a buffer of that size is committed,
+    /// so reconfiguring here keeps the swapchain in step with the acknowledged configure.
+    #[allow(unused_variables)]
+    fn surface_resized(&mut self, size: Size) {}
+
+    /// Called when the user starts resizing the window by dragging its frame.
+    ///
+    /// Until [`interactive_resize_ended`], the window can change size many times a second. An
+    /// application might lay itself out more cheaply in the meantime, and properly once the
+    /// drag is over. The resizes are coalesced, so that [`size`] and the paint which follows it
+    /// happen at most once per frame of the display, and the last one, at the final size,
+    /// always arrives before [`interactive_resize_ended`].
+    ///
+    /// # Platform-specific
+    ///
+    /// - **macOS**: This is AppKit's live resize, which already paces itself to the display.
+    /// - **Wayland**: This is while the compositor configures the window as resizing.
+    /// - **X11**: Window managers don't say when a resize starts, so this isn't called. Those
+    ///   which support `_NET_WM_SYNC_REQUEST` wait for each resize to be painted before they
+    ///   send the next, which coalesces them in the same way.
+    ///
+    /// [`interactive_resize_ended`]: WinHandler::interactive_resize_ended
+    /// [`size`]: WinHandler::size
+    fn interactive_resize_began(&mut self) {}
+
+    /// Called when the user stops resizing the window, after the last [`size`] of the resize.
+    ///
+    /// See [`interactive_resize_began`].
+    ///
+    /// [`interactive_resize_began`]: WinHandler::interactive_resize_began
+    /// [`size`]: WinHandler::size
+    fn interactive_resize_ended(&mut self) {}
+
     /// Request the handler to prepare to paint the window contents.  In particular, if there are
     /// any regions that need to be repainted on the next call to `paint`, the handler should
     /// invalidate those regions by calling [`WindowHandle::invalidate_rect`] or
@@ -601,6 +1869,9 @@ pub trait WinHandler {
     /// Request the handler to paint the window contents.  `invalid` is the region in [display
     /// points](crate::Scale) that needs to be repainted; painting outside the invalid region will
     /// have no effect.
+    ///
+    /// The region includes everything invalidated since the last paint, along with anything the
+    /// system has asked us to repaint, such as part of the window being uncovered.
     fn paint(&mut self, invalid: &Region);
 
     /// Request the handler to return an [`accesskit::TreeUpdate`]
@@ -613,6 +1884,9 @@ pub trait WinHandler {
     /// request. The [`WindowHandle::update_accesskit_if_active`] method
     /// must also be called to provide incremental tree updates whenever
     /// the UI is updated.
+    ///
+    /// On X11 and Wayland, the adapter is created once AT-SPI reports that assistive technology
+    /// is running, or when the window next gains focus after that.
     #[cfg(feature = "accesskit")]
     fn accesskit_tree(&mut self) -> accesskit::TreeUpdate;
 
@@ -652,6 +1926,10 @@ pub trait WinHandler {
     /// Called on a key down event.
     ///
     /// Return `true` if the event is handled.
+    ///
+    /// On macOS, keys which match the shortcut of an item in the menu bar activate the item
+    /// instead, and aren't sent here. Other keys pressed with Command or Control are sent here
+    /// before AppKit gets to use them, and AppKit only uses them if this returns `false`.
     #[allow(unused_variables)]
     fn key_down(&mut self, event: KeyEvent) -> bool {
         false
@@ -770,6 +2048,22 @@ pub trait WinHandler {
     #[allow(unused_variables)]
     fn lost_focus(&mut self) {}
 
+    /// Called when the system would move keyboard focus out of this window's content, in
+    /// `direction`.
+    ///
+    /// Return `true` to let focus move on, as [`WindowHandle::focus_next_window`] would. By
+    /// default this returns `false`, and the app handles focus itself.
+    ///
+    /// This is called on macOS, when Tab or Shift+Tab isn't handled by [`key_down`], so that
+    /// the window's key view loop and screen reader navigation can reach native views next to
+    /// this one. When this returns `false`, the key goes on to the text input system.
+    ///
+    /// [`key_down`]: WinHandler::key_down
+    #[allow(unused_variables)]
+    fn wants_focus_change(&mut self, direction: FocusDirection) -> bool {
+        false
+    }
+
     /// Called when the shell requests to close the window, for example because the user clicked
     /// the little "X" in the titlebar.
     ///
@@ -778,9 +2072,21 @@ pub trait WinHandler {
     /// have no effect.
     fn request_close(&mut self) {}
 
+    /// Called when a popup created with [`WindowBuilder::set_popup`] is dismissed by the
+    /// platform, for example because the user clicked outside of it while it had a grab.
+    ///
+    /// The popup is no longer shown, and should be closed with [`WindowHandle::close`].
+    fn popup_dismissed(&mut self) {}
+
     /// Called when the window is being destroyed. Note that this happens
     /// earlier in the sequence than drop (at `WM_DESTROY`, while the latter is
     /// `WM_NCDESTROY`).
+    ///
+    /// It is called exactly once for every handler which was connected, on the main thread,
+    /// before the platform window is released. This holds whether the window was closed, the
+    /// application quit, the connection to the display server was lost, or
+    /// [`Application::run`] returned with windows still open. No other method is called after
+    /// it, and in debug builds a backend which does so panics.
     #[allow(unused_variables)]
     fn destroy(&mut self) {}
 
@@ -798,6 +2104,343 @@ pub trait WinHandler {
     fn as_any(&mut self) -> &mut dyn Any;
 }
 
+thread_local! {
+    /// Windows whose handlers panicked while [closing them] is turned on, with the payloads
+    /// of their panics, waiting to be passed to [`AppHandler::handler_panicked`].
+    ///
+    /// [closing them]: Application::set_close_windows_on_panic
+    /// [`AppHandler::handler_panicked`]: crate::AppHandler::handler_panicked
+    static PANICKED: RefCell<Vec<(WindowHandle, Box<dyn Any + Send>)>> = RefCell::new(Vec::new());
+}
+
+/// Wraps the handler of every window, so that its panics don't unwind into the platform's
+/// callbacks, and so that debug builds catch backends which call it after
+/// [`WinHandler::destroy`].
+struct GuardedHandler {
+    inner: Box<dyn WinHandler>,
+    handle: WindowHandle,
+    /// Identifies the window in [`common_util::callback_span`].
+    id: u64,
+    /// Set once the handler has panicked, after which only `destroy` is passed on.
+    poisoned: bool,
+    #[cfg(debug_assertions)]
+    destroyed: bool,
+}
+
+impl GuardedHandler {
+    fn new(inner: Box<dyn WinHandler>) -> GuardedHandler {
+        static WINDOW_COUNTER: Counter = Counter::new();
+        GuardedHandler {
+            inner,
+            handle: WindowHandle::default(),
+            id: WINDOW_COUNTER.next(),
+            poisoned: false,
+            #[cfg(debug_assertions)]
+            destroyed: false,
+        }
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn check(&self, callback: &str) {
+        #[cfg(debug_assertions)]
+        if self.destroyed {
+            panic!("WinHandler::{callback} was called after destroy");
+        }
+    }
+
+    /// Pass a callback on to the handler, unless it has panicked before.
+    fn call<R>(&mut self, callback: &str, f: impl FnOnce(&mut dyn WinHandler) -> R) -> Option<R> {
+        self.check(callback);
+        if self.poisoned {
+            return None;
+        }
+        let _span = common_util::callback_span("WinHandler", callback, Some(self.id));
+        self.catch(callback, f)
+    }
+
+    fn catch<R>(&mut self, callback: &str, f: impl FnOnce(&mut dyn WinHandler) -> R) -> Option<R> {
+        let inner = &mut *self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(inner))) {
+            Ok(result) => Some(result),
+            Err(payload) => {
+                self.panicked(callback, payload);
+                None
+            }
+        }
+    }
+
+    fn panicked(&mut self, callback: &str, payload: Box<dyn Any + Send>) {
+        let message = common_util::panic_message(&*payload);
+        if !crate::application::closes_windows_on_panic() {
+            error!("WinHandler::{callback} panicked, aborting: {message}");
+            std::process::abort();
+        }
+        error!("WinHandler::{callback} panicked, closing the window: {message}");
+        self.poisoned = true;
+        PANICKED.with(|panicked| panicked.borrow_mut().push((self.handle.clone(), payload)));
+        // The window is closed once the platform has returned from this callback, as closing
+        // it from inside can destroy it right away, and the platform would then destroy it
+        // again when the callback was `destroy`.
+        if let Some(app) = Application::try_global().and_then(|app| app.get_handle()) {
+            app.run_on_main(|mut handler| {
+                for (window, payload) in PANICKED.with(|panicked| panicked.take()) {
+                    if window.is_alive() {
+                        window.close();
+                    }
+                    if let Some(handler) = &mut handler {
+                        handler.handler_panicked(&window, payload);
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl WinHandler for GuardedHandler {
+    fn connect(&mut self, handle: &WindowHandle) {
+        self.handle = handle.clone();
+        self.call("connect", |h| h.connect(handle));
+    }
+
+    fn size(&mut self, size: Size) {
+        self.call("size", |h| h.size(size));
+    }
+
+    fn scale(&mut self, scale: Scale) {
+        self.call("scale", |h| h.scale(scale));
+    }
+
+    fn visibility_changed(&mut self, visibility: Visibility) {
+        self.call("visibility_changed", |h| h.visibility_changed(visibility));
+    }
+
+    fn decoration_mode_changed(&mut self, mode: DecorationMode) {
+        self.call("decoration_mode_changed", |h| {
+            h.decoration_mode_changed(mode)
+        });
+    }
+
+    fn frame_presented(&mut self, info: PresentInfo) {
+        self.call("frame_presented", |h| h.frame_presented(info));
+    }
+
+    fn surface_resized(&mut self, size: Size) {
+        self.call("surface_resized", |h| h.surface_resized(size));
+    }
+
+    fn interactive_resize_began(&mut self) {
+        self.call("interactive_resize_began", |h| h.interactive_resize_began());
+    }
+
+    fn interactive_resize_ended(&mut self) {
+        self.call("interactive_resize_ended", |h| h.interactive_resize_ended());
+    }
+
+    fn prepare_paint(&mut self) {
+        self.call("prepare_paint", |h| h.prepare_paint());
+    }
+
+    fn paint(&mut self, invalid: &Region) {
+        self.call("paint", |h| {
+            common_util::record_damage(invalid);
+            h.paint(invalid)
+        });
+    }
+
+    #[cfg(feature = "accesskit")]
+    fn accesskit_tree(&mut self) -> accesskit::TreeUpdate {
+        // There is no tree to fall back to, so the panic carries on.
+        self.check("accesskit_tree");
+        self.inner.accesskit_tree()
+    }
+
+    fn rebuild_resources(&mut self) {
+        self.call("rebuild_resources", |h| h.rebuild_resources());
+    }
+
+    fn command(&mut self, id: u32) {
+        self.call("command", |h| h.command(id));
+    }
+
+    fn save_as(&mut self, token: FileDialogToken, file: Option<FileInfo>) {
+        self.call("save_as", |h| h.save_as(token, file));
+    }
+
+    fn open_file(&mut self, token: FileDialogToken, file: Option<FileInfo>) {
+        self.call("open_file", |h| h.open_file(token, file));
+    }
+
+    fn open_files(&mut self, token: FileDialogToken, files: Vec<FileInfo>) {
+        self.call("open_files", |h| h.open_files(token, files));
+    }
+
+    fn key_down(&mut self, event: KeyEvent) -> bool {
+        self.call("key_down", |h| h.key_down(event))
+            .unwrap_or(false)
+    }
+
+    fn key_up(&mut self, event: KeyEvent) {
+        self.call("key_up", |h| h.key_up(event));
+    }
+
+    fn acquire_input_lock(
+        &mut self,
+        token: TextFieldToken,
+        mutable: bool,
+    ) -> Box<dyn InputHandler> {
+        self.call("acquire_input_lock", |h| {
+            h.acquire_input_lock(token, mutable)
+        })
+        .unwrap_or_else(|| Box::new(NoInput))
+    }
+
+    fn release_input_lock(&mut self, token: TextFieldToken) {
+        self.call("release_input_lock", |h| h.release_input_lock(token));
+    }
+
+    fn zoom(&mut self, delta: f64) {
+        self.call("zoom", |h| h.zoom(delta));
+    }
+
+    fn mouse_wheel(&mut self, event: &MouseEvent) {
+        self.call("mouse_wheel", |h| h.mouse_wheel(event));
+    }
+
+    fn mouse_move(&mut self, event: &MouseEvent) {
+        self.call("mouse_move", |h| h.mouse_move(event));
+    }
+
+    fn mouse_down(&mut self, event: &MouseEvent) {
+        self.call("mouse_down", |h| h.mouse_down(event));
+    }
+
+    fn mouse_up(&mut self, event: &MouseEvent) {
+        self.call("mouse_up", |h| h.mouse_up(event));
+    }
+
+    fn mouse_leave(&mut self) {
+        self.call("mouse_leave", |h| h.mouse_leave());
+    }
+
+    fn wheel(&mut self, event: &PointerEvent) {
+        self.call("wheel", |h| h.wheel(event));
+    }
+
+    fn pointer_move(&mut self, event: &PointerEvent) {
+        self.call("pointer_move", |h| h.pointer_move(event));
+    }
+
+    fn pointer_down(&mut self, event: &PointerEvent) {
+        self.call("pointer_down", |h| h.pointer_down(event));
+    }
+
+    fn pointer_up(&mut self, event: &PointerEvent) {
+        self.call("pointer_up", |h| h.pointer_up(event));
+    }
+
+    fn pointer_leave(&mut self) {
+        self.call("pointer_leave", |h| h.pointer_leave());
+    }
+
+    fn timer(&mut self, token: TimerToken) {
+        self.call("timer", |h| h.timer(token));
+    }
+
+    fn got_focus(&mut self) {
+        self.call("got_focus", |h| h.got_focus());
+    }
+
+    fn lost_focus(&mut self) {
+        self.call("lost_focus", |h| h.lost_focus());
+    }
+
+    fn wants_focus_change(&mut self, direction: FocusDirection) -> bool {
+        self.call("wants_focus_change", |h| h.wants_focus_change(direction))
+            .unwrap_or(false)
+    }
+
+    fn request_close(&mut self) {
+        self.call("request_close", |h| h.request_close());
+    }
+
+    fn popup_dismissed(&mut self) {
+        self.call("popup_dismissed", |h| h.popup_dismissed());
+    }
+
+    fn destroy(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.check("destroy");
+            self.destroyed = true;
+        }
+        // Even a poisoned handler gets to release what it holds.
+        let _span = common_util::callback_span("WinHandler", "destroy", Some(self.id));
+        self.catch("destroy", |h| h.destroy());
+    }
+
+    fn idle(&mut self, token: IdleToken) {
+        self.call("idle", |h| h.idle(token));
+    }
+
+    #[cfg(feature = "accesskit")]
+    fn accesskit_action(&mut self, request: accesskit::ActionRequest) {
+        self.call("accesskit_action", |h| h.accesskit_action(request));
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self.inner.as_any()
+    }
+}
+
+/// The text field of a window whose handler panicked, which is empty and ignores any edits.
+struct NoInput;
+
+impl InputHandler for NoInput {
+    fn selection(&self) -> Selection {
+        Selection::caret(0)
+    }
+
+    fn set_selection(&mut self, _: Selection) {}
+
+    fn composition_range(&self) -> Option<std::ops::Range<usize>> {
+        None
+    }
+
+    fn set_composition_range(&mut self, _: Option<std::ops::Range<usize>>) {}
+
+    fn is_char_boundary(&self, i: usize) -> bool {
+        i == 0
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+
+    fn slice(&self, _: std::ops::Range<usize>) -> Cow<str> {
+        Cow::Borrowed("")
+    }
+
+    fn replace_range(&mut self, _: std::ops::Range<usize>, _: &str) {}
+
+    fn hit_test_point(&self, _: Point) -> HitTestPoint {
+        HitTestPoint::default()
+    }
+
+    fn line_range(&self, _: usize, _: Affinity) -> std::ops::Range<usize> {
+        0..0
+    }
+
+    fn bounding_box(&self) -> Option<Rect> {
+        None
+    }
+
+    fn slice_bounding_box(&self, _: std::ops::Range<usize>) -> Option<Rect> {
+        None
+    }
+
+    fn handle_action(&mut self, _: Action) {}
+}
+
 impl From<backend::WindowHandle> for WindowHandle {
     fn from(src: backend::WindowHandle) -> WindowHandle {
         WindowHandle(src)
@@ -811,5 +2454,142 @@ mod test {
     use static_assertions as sa;
 
     sa::assert_not_impl_any!(WindowHandle: Send, Sync);
+    sa::assert_not_impl_any!(WeakWindowHandle: Send, Sync);
     sa::assert_impl_all!(IdleHandle: Send);
+
+    #[test]
+    fn default_handle_is_not_alive() {
+        assert!(!WindowHandle::default().is_alive());
+        assert!(WindowHandle::default().downgrade().upgrade().is_none());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "WinHandler::paint was called after destroy")]
+    fn no_callbacks_after_destroy() {
+        struct Handler;
+        impl WinHandler for Handler {
+            fn connect(&mut self, _: &WindowHandle) {}
+            fn prepare_paint(&mut self) {}
+            fn paint(&mut self, _: &Region) {}
+            #[cfg(feature = "accesskit")]
+            fn accesskit_tree(&mut self) -> accesskit::TreeUpdate {
+                unreachable!()
+            }
+            fn as_any(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        let mut handler = GuardedHandler::new(Box::new(Handler));
+        handler.connect(&WindowHandle::default());
+        handler.destroy();
+        handler.paint(&Region::EMPTY);
+    }
+
+    #[test]
+    fn panicking_handler_is_poisoned() {
+        #[derive(Default)]
+        struct Handler {
+            painted: usize,
+            destroyed: bool,
+        }
+        impl WinHandler for Handler {
+            fn connect(&mut self, _: &WindowHandle) {}
+            fn prepare_paint(&mut self) {}
+            fn paint(&mut self, _: &Region) {
+                self.painted += 1;
+                panic!("deliberately");
+            }
+            fn key_down(&mut self, _: KeyEvent) -> bool {
+                true
+            }
+            fn destroy(&mut self) {
+                self.destroyed = true;
+            }
+            #[cfg(feature = "accesskit")]
+            fn accesskit_tree(&mut self) -> accesskit::TreeUpdate {
+                unreachable!()
+            }
+            fn as_any(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+
+        /// Turns closing windows on panic back off when the test ends, however it ends.
+        struct ClosesOnPanic;
+        impl Drop for ClosesOnPanic {
+            fn drop(&mut self) {
+                crate::application::CLOSE_WINDOWS_ON_PANIC.store(false, Ordering::Relaxed);
+            }
+        }
+
+        use std::sync::atomic::Ordering;
+        // Nothing else may depend on the flag, or claim the main thread, while it is set
+        let _lock = crate::application::test::lock_app();
+        crate::application::CLOSE_WINDOWS_ON_PANIC.store(true, Ordering::Relaxed);
+        let _closes_on_panic = ClosesOnPanic;
+        let mut handler = GuardedHandler::new(Box::<Handler>::default());
+        handler.connect(&WindowHandle::default());
+        handler.paint(&Region::EMPTY);
+        // Only destroy gets through once it has panicked.
+        handler.paint(&Region::EMPTY);
+        assert!(!handler.key_down(KeyEvent::default()));
+        handler.destroy();
+        let inner = handler.as_any().downcast_mut::<Handler>().unwrap();
+        assert_eq!(inner.painted, 1);
+        assert!(inner.destroyed);
+    }
+
+    #[test]
+    fn restore_placement() {
+        let primary = Monitor::new(
+            true,
+            Rect::new(0.0, 0.0, 1920.0, 1080.0),
+            Rect::new(0.0, 0.0, 1920.0, 1040.0),
+        );
+        let secondary = Monitor::new(
+            false,
+            Rect::new(1920.0, 0.0, 3200.0, 1024.0),
+            Rect::new(1920.0, 30.0, 3200.0, 1024.0),
+        );
+        let placement = WindowPlacement {
+            monitor: secondary.virtual_rect(),
+            rect: Rect::new(100.0, 100.0, 900.0, 700.0),
+            state: WindowState::Restored,
+        };
+        assert_eq!(
+            placement.restored_rect(&[primary.clone(), secondary]),
+            Some(Rect::new(2020.0, 130.0, 2820.0, 730.0))
+        );
+        // The monitor is gone, so the window is centered on the primary one.
+        assert_eq!(
+            placement.restored_rect(&[primary.clone()]),
+            Some(Rect::new(560.0, 220.0, 1360.0, 820.0))
+        );
+        // It is kept within the work area.
+        let placement = WindowPlacement {
+            monitor: primary.virtual_rect(),
+            rect: Rect::new(1500.0, -50.0, 2300.0, 1150.0),
+            ..placement
+        };
+        assert_eq!(
+            placement.restored_rect(&[primary]),
+            Some(Rect::new(1120.0, 0.0, 1920.0, 1040.0))
+        );
+        assert_eq!(placement.restored_rect(&[]), None);
+    }
+
+    #[test]
+    fn pixel_format_to_bgra() {
+        let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            &*PixelFormat::Rgba8.to_bgra(&rgba),
+            &[3, 2, 1, 4, 7, 6, 5, 8]
+        );
+        assert!(matches!(
+            PixelFormat::Bgra8.to_bgra(&rgba),
+            Cow::Borrowed(pixels) if pixels == rgba
+        ));
+    }
 }